@@ -0,0 +1,144 @@
+//! Serialization cost of the two heaviest webview payloads, `node_avg` and `gh_avg`.
+//! - `serde_json`: the bare struct, to JSON text and to a `Value`.
+//! - `emit`: the whole presenter path (display prefs, rounding, metering and cap check) into a
+//!   sink that drops the text, which is what one window close costs per node / greenhouse.
+//!
+//! Run with `cargo bench --bench payloads`; compare against a saved baseline to catch regressions.
+
+use std::{collections::HashMap, hint::black_box, sync::{Arc, RwLock}};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use serde_json::Value;
+
+use apptest_v05_lib::services::mqtt::greenhouse_sensor::{
+    aggregator::{Extremes, NodeAvgUi, SoilAvg, Spread},
+    greenhouse_aggregator::{Confidence, GhAvg},
+};
+use apptest_v05_lib::services::presenter::{
+    emitter::{EventSink, UiEmitter},
+    metered::MeteredSink,
+};
+
+/// Drops every event; keeps the text alive so the serialization is not optimized out.
+struct NullSink;
+
+impl EventSink for NullSink {
+    fn emit_json(&self, _event: &str, payload: Value) { black_box(payload); }
+    fn emit_str(&self, _event: &str, payload: String) { black_box(payload); }
+}
+
+fn extremes() -> Extremes {
+    Extremes {
+        air_temp_c_min: Some(21.37), air_temp_c_max: Some(24.81),
+        air_rh_pct_min: Some(61.2),  air_rh_pct_max: Some(72.9),
+        vpd_kpa_min: Some(0.81),     vpd_kpa_max: Some(1.27),
+        par_value_min: Some(412.0),  par_value_max: Some(655.5),
+        weight_g_min: Some(10_412.3), weight_g_max: Some(10_498.7),
+    }
+}
+
+/// A standard node with every sensor present and a soil probe: the largest `node_avg`.
+fn node_avg() -> NodeAvgUi {
+    NodeAvgUi {
+        ts_ms: 1_760_000_000_000,
+        greenhouse_id: 1,
+        node_id: 7,
+        window_sec: 60,
+        outdoor: false,
+        air_temp_c: Some(23.418),
+        leaf_temp_c: Some(22.907),
+        bag_temp_c: Some(21.553),
+        air_rh_pct: Some(66.231),
+        bag_rh1_pct: Some(48.12),
+        bag_rh2_pct: Some(51.77),
+        bag_rh3_pct: Some(47.09),
+        bag_rh4_pct: Some(50.64),
+        bag_rh_avg_pct: Some(49.405),
+        par_value: Some(538.25),
+        weight_g: Some(10_455.81),
+        ea_air_kpa: Some(1.9116),
+        ea_leaf_kpa: Some(2.7902),
+        es_kpa: Some(2.8864),
+        vpd_kpa: Some(0.9748),
+        leaf_air_dt_c: Some(-0.511),
+        transpiration_g_min: Some(1.874),
+        extremes: extremes(),
+        spread: Spread { air_temp_sd: Some(0.412), air_rh_sd: Some(1.93), vpd_sd: Some(0.061) },
+        samples: Default::default(),
+        derived: Default::default(),
+        maintenance: false,
+        received_packets: 12,
+        lost_packets: Some(0),
+        battery_v: Some(3.912),
+        rssi_dbm: Some(-71.0),
+        soil: Some(SoilAvg {
+            vwc1_pct: Some(38.41), vwc2_pct: Some(40.02), vwc3_pct: Some(37.77), vwc4_pct: Some(39.18),
+            ec_ms_cm: Some(2.314),
+        }),
+        wind_ms: None,
+        wind_gust_ms: None,
+        rain_mm: None,
+    }
+}
+
+/// A fully covered greenhouse of eight nodes.
+fn gh_avg() -> GhAvg {
+    GhAvg {
+        ts_ms: 1_760_000_000_000,
+        window_start_ms: 1_760_000_000_000 - 60_000,
+        window_sec: 60,
+        window_seq: 4_211,
+        greenhouse_id: 1,
+        air_temp_c: Some(23.177),
+        leaf_temp_c: Some(22.664),
+        bag_temp_c: Some(21.402),
+        air_rh_pct: Some(65.918),
+        bag_rh1_pct: Some(48.55),
+        bag_rh2_pct: Some(50.91),
+        bag_rh3_pct: Some(47.63),
+        bag_rh4_pct: Some(50.02),
+        bag_rh_avg_pct: Some(49.278),
+        par_value: Some(541.9),
+        weight_g: Some(10_438.2),
+        ea_air_kpa: Some(1.8712),
+        ea_leaf_kpa: Some(2.7451),
+        es_kpa: Some(2.8388),
+        vpd_kpa: Some(0.9676),
+        leaf_air_dt_c: Some(-0.513),
+        transpiration_g_min: Some(1.802),
+        extremes: extremes(),
+        derived: Default::default(),
+        nodes: 8,
+        outdoor: None,
+        roster: 8,
+        coverage: 1.0,
+        confidence: Confidence::High,
+        field_nodes: [8; 16],
+        transpiration_nodes: 8,
+    }
+}
+
+fn serde_json(c: &mut Criterion) {
+    let na = node_avg();
+    let ga = gh_avg();
+    let mut g = c.benchmark_group("serde_json");
+    g.bench_function("node_avg/to_string", |b| b.iter(|| serde_json::to_string(black_box(&na)).unwrap()));
+    g.bench_function("node_avg/to_value", |b| b.iter(|| serde_json::to_value(black_box(&na)).unwrap()));
+    g.bench_function("gh_avg/to_string", |b| b.iter(|| serde_json::to_string(black_box(&ga)).unwrap()));
+    g.bench_function("gh_avg/to_value", |b| b.iter(|| serde_json::to_value(black_box(&ga)).unwrap()));
+    g.finish();
+}
+
+fn emit(c: &mut Criterion) {
+    let na = node_avg();
+    let ga = gh_avg();
+    let sink = MeteredSink::new(NullSink, Arc::new(RwLock::new(Default::default())));
+    let ui = UiEmitter::new(sink, Arc::new(RwLock::new(HashMap::new())), Arc::new(RwLock::new(HashMap::new())));
+    let mut g = c.benchmark_group("emit");
+    g.bench_function("node_avg", |b| b.iter(|| ui.node_avg(black_box(&na))));
+    g.bench_function("gh_avg", |b| b.iter(|| ui.gh_avg(black_box(&ga))));
+    g.finish();
+}
+
+criterion_group!(benches, serde_json, emit);
+criterion_main!(benches);
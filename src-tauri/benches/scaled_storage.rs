@@ -0,0 +1,117 @@
+//! Plain REAL vs scaled-integer value storage (scaled.rs) on a season of synthetic data.
+//! - One greenhouse, 8 nodes, 6 sensors, 5-minute windows for 120 days: ~1.7M node rows and
+//!   ~0.2M greenhouse rows, seeded once into a plain database, then copied and converted with
+//!   `convert_scaled_values`. Both files are VACUUMed and their sizes printed before timing.
+//! - Timed: the history commands' range scans (`load_gh_history`, `load_node_history`) over one
+//!   week in the middle of the season, on each database.
+//!
+//! Run with `cargo bench --bench scaled_storage`; seeding takes a few seconds per run.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rusqlite::{params, Connection};
+
+use apptest_v05_lib::services::storage::{
+    history::{load_gh_history, load_node_history, HistoryQuery},
+    scaled::convert_scaled_values,
+    sqlite::{db_size_bytes, open_db},
+};
+
+const SEASON_DAYS: i64 = 120;
+const WINDOW_MS: i64 = 300_000;
+const NODES: u16 = 8;
+const SEASON_START_MS: i64 = 1_740_787_200_000; // 2025-03-01 00:00 UTC
+const DAY_MS: i64 = 86_400_000;
+
+/// (key, unit, base value, daily swing)
+const SENSORS: [(&str, &str, f64, f64); 6] = [
+    ("air_temp_c", "C", 22.0, 6.0),
+    ("leaf_temp_c", "C", 21.5, 5.0),
+    ("air_rh_pct", "%", 70.0, 15.0),
+    ("vpd_kpa", "kPa", 0.9, 0.5),
+    ("par_value", "", 300.0, 300.0),
+    ("weight_g", "", 10_450.0, 60.0),
+];
+
+/// A 2-decimal reading with a daily cycle and a per-node offset, like the aggregated windows.
+fn reading(base: f64, swing: f64, ts_ms: i64, node: u16) -> f64 {
+    let day = (ts_ms % DAY_MS) as f64 / DAY_MS as f64;
+    let v = base + swing * (day * std::f64::consts::TAU).sin() + node as f64 * 0.13;
+    (v * 100.0).round() / 100.0
+}
+
+fn seed(db_path: &str) -> rusqlite::Result<()> {
+    let mut conn = open_db(db_path)?;
+    let tx = conn.transaction()?;
+    tx.execute("INSERT INTO greenhouse_id(id) VALUES (1)", [])?;
+    for node in 1..=NODES {
+        tx.execute("INSERT INTO node_name(greenhouse_id, node_id, label) VALUES (1, ?1, ?2)", params![node, format!("Node {node}")])?;
+    }
+    for (key, unit, ..) in SENSORS {
+        tx.execute("INSERT INTO sensor_type(key, unit) VALUES (?1, ?2)", params![key, unit])?;
+    }
+    {
+        let mut nv = tx.prepare(
+            "INSERT INTO node_values (ts_ms, node_id, sensor_type_id, value, agg, window_sec, window_start_ms, window_seq, samples)
+             VALUES (?1, (SELECT id FROM node_name WHERE node_id = ?2), ?3, ?4, 'avg', 300, ?5, ?6, 12)",
+        )?;
+        let mut ga = tx.prepare(
+            "INSERT INTO greenhouse_average
+             (ts_ms, greenhouse_id, sensor_type_id, value, nodes, agg, window_sec, window_start_ms, window_seq, coverage, confidence)
+             VALUES (?1, 1, ?2, ?3, ?4, 'avg', 300, ?5, ?6, 1.0, 'high')",
+        )?;
+        let windows = SEASON_DAYS * DAY_MS / WINDOW_MS;
+        for seq in 0..windows {
+            let start = SEASON_START_MS + seq * WINDOW_MS;
+            let ts = start + WINDOW_MS;
+            for (st_id, (_, _, base, swing)) in (1i64..).zip(SENSORS) {
+                for node in 1..=NODES {
+                    nv.execute(params![ts, node, st_id, reading(base, swing, ts, node), start, seq])?;
+                }
+                ga.execute(params![ts, st_id, reading(base, swing, ts, 4), NODES, start, seq])?;
+            }
+        }
+    }
+    tx.commit()
+}
+
+fn vacuum(db_path: &str) -> rusqlite::Result<()> {
+    Connection::open(db_path)?.execute_batch("PRAGMA wal_checkpoint(TRUNCATE); VACUUM;")
+}
+
+fn scaled_storage(c: &mut Criterion) {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let plain = dir.path().join("plain.db").to_string_lossy().into_owned();
+    let scaled = dir.path().join("scaled.db").to_string_lossy().into_owned();
+    seed(&plain).expect("seed");
+    vacuum(&plain).expect("vacuum plain");
+    std::fs::copy(&plain, &scaled).expect("copy");
+    let report = convert_scaled_values(&scaled, None, None).expect("convert");
+    assert!(report.complete, "conversion stopped early");
+    vacuum(&scaled).expect("vacuum scaled");
+
+    let (plain_bytes, scaled_bytes) = (db_size_bytes(&plain), db_size_bytes(&scaled));
+    println!(
+        "season of {SEASON_DAYS} days: plain {:.1} MB, scaled {:.1} MB ({:+.1}%)",
+        plain_bytes as f64 / 1e6,
+        scaled_bytes as f64 / 1e6,
+        (scaled_bytes as f64 / plain_bytes as f64 - 1.0) * 100.0,
+    );
+
+    let from = SEASON_START_MS + SEASON_DAYS / 2 * DAY_MS;
+    let to = from + 7 * DAY_MS;
+    let q = HistoryQuery::default();
+    let mut g = c.benchmark_group("history_week");
+    g.sample_size(20);
+    for (name, path) in [("plain", &plain), ("scaled", &scaled)] {
+        g.bench_function(format!("gh/{name}"), |b| b.iter(|| load_gh_history(black_box(path), 1, from, to, &q).unwrap()));
+        g.bench_function(format!("node/{name}"), |b| {
+            b.iter(|| load_node_history(black_box(path), 1, Some(3), from, to, &q).unwrap())
+        });
+    }
+    g.finish();
+}
+
+criterion_group!(benches, scaled_storage);
+criterion_main!(benches);
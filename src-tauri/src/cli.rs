@@ -0,0 +1,128 @@
+//! Headless subcommands of the app binary (no window, no broker, no database).
+//! - `decode-replay <file> [--out <csv>]`: decode a replay file (replay.rs) and write one CSV row per
+//!   record (per sample for batch records) to `--out` or stdout; a summary goes to stderr. Exit code 1 when the file cannot be read.
+//! - `encode-frame <json> [--crc]`: turn a test-rig JSON frame (decoder.rs) into the binary frame a node
+//!   would publish, printed as hex (`xxd -r -p` turns it into a file for `mosquitto_pub -f`). Exit code 1
+//!   when the JSON does not decode. With the `proto` feature, `--proto` prints the protobuf frame instead.
+//!   `--mac <hex key>` appends the HMAC trailer of a keyed greenhouse (frame_mac.rs), after any CRC.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::services::mqtt::greenhouse_sensor::decoder::{decode_payload, encode_payload, parse_hex, with_crc};
+use crate::services::mqtt::greenhouse_sensor::frame_mac::with_mac;
+#[cfg(feature = "proto")]
+use crate::services::mqtt::greenhouse_sensor::proto::encode_proto;
+use crate::services::mqtt::replay::{decode_replay, load_replay, ReplayOutcome};
+
+/// Reading columns, in frame order (outdoor frames leave the standard-only ones empty, v1 frames the v2 ones,
+/// soil frames everything but the soil ones).
+const FIELDS: [&str; 25] = [
+    "air_temp_c", "leaf_temp_c", "bag_temp_c", "air_rh_pct",
+    "bag_rh1_pct", "bag_rh2_pct", "bag_rh3_pct", "bag_rh4_pct", "bag_rh_avg_pct",
+    "par_value", "weight_g", "ea_air_kpa", "ea_leaf_kpa", "es_kpa", "vpd_kpa",
+    "battery_v", "rssi_dbm",
+    "vwc1_pct", "vwc2_pct", "vwc3_pct", "vwc4_pct", "ec_ms_cm",
+    "wind_ms", "wind_gust_ms", "rain_tips",
+];
+
+const USAGE: &str = "usage: apptest_v05 decode-replay <file.jsonl> [--out <file.csv>]
+       apptest_v05 encode-frame '<json frame>' [--crc | --proto] [--mac <hex key>]";
+
+/// Run the subcommand named in `args` (program name excluded); None when it is not a subcommand.
+pub fn run(args: &[String]) -> Option<i32> {
+    match args.first().map(String::as_str) {
+        Some("decode-replay") => Some(decode_replay_cmd(&args[1..])),
+        Some("encode-frame") => Some(encode_frame_cmd(&args[1..])),
+        _ => None,
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) { format!("\"{}\"", s.replace('"', "\"\"")) } else { s.to_string() }
+}
+
+fn csv_row(o: &ReplayOutcome) -> String {
+    let decoded = o.decoded.as_ref().and_then(|d| serde_json::to_value(d).ok());
+    let kind = decoded.as_ref().and_then(|v| v["kind"].as_str()).unwrap_or("");
+    let mut row = vec![
+        o.line.to_string(),
+        o.ts.map(|t| t.to_string()).unwrap_or_default(),
+        csv_field(&o.topic),
+        csv_field(&o.origin),
+        o.ids.map(|i| i.0.to_string()).unwrap_or_default(),
+        o.ids.map(|i| i.1.to_string()).unwrap_or_default(),
+        kind.to_string(),
+    ];
+    // readings are f32 on the wire: print them as f32, not widened
+    row.extend(FIELDS.iter().map(|f| decoded.as_ref()
+        .and_then(|v| v[*f].as_f64()).map(|x| (x as f32).to_string()).unwrap_or_default()));
+    row.push(o.out_of_range.join("|"));
+    row.push(o.slew_rejected.join("|"));
+    row.push(csv_field(o.error.as_deref().unwrap_or("")));
+    row.join(",")
+}
+
+fn decode_replay_cmd(args: &[String]) -> i32 {
+    let (file, out) = match args {
+        [file] => (file, None),
+        [file, flag, out] if flag == "--out" => (file, Some(out)),
+        _ => { eprintln!("{USAGE}"); return 2; }
+    };
+    let records = match load_replay(Path::new(file)) {
+        Ok(r) => r,
+        Err(e) => { eprintln!("{e}"); return 1; }
+    };
+    let outcomes = decode_replay(records);
+
+    let mut w: Box<dyn Write> = match out {
+        Some(p) => match std::fs::File::create(p) {
+            Ok(f) => Box::new(io::BufWriter::new(f)),
+            Err(e) => { eprintln!("{p}: {e}"); return 1; }
+        },
+        None => Box::new(io::stdout().lock()),
+    };
+    let header = format!("line,ts,topic,origin,greenhouse_id,node_id,kind,{},out_of_range,slew_rejected,error", FIELDS.join(","));
+    let written = std::iter::once(header).chain(outcomes.iter().map(csv_row))
+        .try_for_each(|line| writeln!(w, "{line}"))
+        .and_then(|_| w.flush());
+    if let Err(e) = written { eprintln!("write failed: {e}"); return 1; }
+
+    let decoded = outcomes.iter().filter(|o| o.decoded.is_some()).count();
+    let scrubbed: usize = outcomes.iter().map(|o| o.out_of_range.len()).sum();
+    let rejected: usize = outcomes.iter().map(|o| o.slew_rejected.len()).sum();
+    eprintln!("{} record(s): {decoded} decoded, {} error(s), {scrubbed} reading(s) out of range, {rejected} rejected by the slew guard",
+        outcomes.len(), outcomes.len() - decoded);
+    0
+}
+
+fn encode_frame_cmd(args: &[String]) -> i32 {
+    let (args, key) = match args {
+        [rest @ .., opt, key] if opt == "--mac" => match parse_hex(key) {
+            Some(k) if !k.is_empty() => (rest, Some(k)),
+            _ => { eprintln!("--mac needs a hex key"); return 2; }
+        },
+        _ => (args, None),
+    };
+    let (json, flag) = match args {
+        [json] => (json, None),
+        [json, flag] if flag == "--crc" || (cfg!(feature = "proto") && flag == "--proto") => (json, Some(flag.as_str())),
+        _ => { eprintln!("{USAGE}"); return 2; }
+    };
+    let decoded = match decode_payload(json.as_bytes()) {
+        Ok(d) => d,
+        Err(e) => { eprintln!("not a frame: {e}"); return 1; }
+    };
+    let frame = match flag {
+        Some("--crc") => with_crc(encode_payload(&decoded)),
+        #[cfg(feature = "proto")]
+        Some("--proto") => encode_proto(&decoded),
+        _ => encode_payload(&decoded),
+    };
+    let frame = match key {
+        Some(k) => with_mac(frame, &k),
+        None => frame,
+    };
+    println!("{}", frame.iter().map(|b| format!("{b:02x}")).collect::<String>());
+    0
+}
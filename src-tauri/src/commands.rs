@@ -0,0 +1,577 @@
+//! Tauri commands invoked from the frontend.
+//! - Read-only views over shared pipeline state; no command blocks the hot path.
+//! - DB work runs on the blocking pool and reports failures as `Err(String)`.
+
+use tauri::{AppHandle, State};
+
+use crate::services::mqtt::greenhouse_sensor::ack::{self, AckShared, AckWindow};
+use crate::services::mqtt::greenhouse_sensor::origin::OriginShared;
+use crate::services::mqtt::greenhouse_sensor::calibration::{replace_calibrations, CalibrationShared};
+use crate::services::mqtt::schema::SchemaShared;
+use crate::services::mqtt::publisher::{self, PublishPayload, PublisherShared};
+use crate::services::mqtt::auth::{mqtt_auth, set_mqtt_password as store_mqtt_password};
+use crate::services::mqtt::core::{test_connection, ConnectionTest};
+use crate::services::mqtt::greenhouse_sensor::discovery::{self, DiscoveryReport, DiscoveryShared};
+use crate::services::mqtt::greenhouse_sensor::decoder::{decode_payload, parse_hex, Decoded, FrameLayout};
+use crate::services::mqtt::greenhouse_sensor::subscriber::{DecodeErrorsShared, SubscriptionInfo, SubscriptionsShared};
+use crate::services::mqtt::greenhouse_sensor::decoder_stats::{DecoderStatsShared, DecoderStatsSnapshot};
+use crate::services::mqtt::greenhouse_sensor::node_rates::{NodeRatesReport, NodeRatesShared};
+use crate::services::mqtt::greenhouse_sensor::conn_status::{MqttStatusShared, MqttStatusSnapshot, StatusReporter};
+use crate::services::mqtt::greenhouse_sensor::ingest_pause::{IngestPauseShared, IngestPauseState};
+use crate::services::mqtt::greenhouse_sensor::availability::{AvailabilityShared, NodeAvailability};
+use crate::services::mqtt::greenhouse_sensor::raw_capture::{RawCaptureShared, RawDump};
+use crate::services::channels::{channel_config, lane_stats, ChannelConfig, LaneStat, LanesShared, QueueCountersShared, QueueStat};
+use crate::services::clock::ClockAdjustment;
+use crate::services::node_maintenance::{end_in_memory, now_ms, publish, start_in_memory, MaintenanceShared};
+use crate::services::node_health::NodeHealthShared;
+use crate::services::log_tail::{self, LogEvent};
+use crate::services::instance_lock::{live_holder, send_request, InstanceStatus, LockRequest};
+use crate::services::mqtt::greenhouse_sensor::greenhouse_aggregator::{replace_roster, LatestGhShared, RosterShared};
+use crate::services::mqtt::greenhouse_sensor::hourly_aggregator::{GhHourly, HourlyShared};
+use crate::services::presenter::emitter::{replace_display_prefs, DisplayPrefsShared, EventSink};
+use crate::services::presenter::metered::{EmitStatsShared, EventStat, MeteredSink};
+use crate::services::presenter::overview::{site_overview, SiteOverview};
+use crate::services::pipeline::{Pipeline, RestartReport};
+use crate::services::shutdown::Shutdown;
+use crate::services::report::shift::{render_html, shift_report, ShiftReport};
+use crate::services::report::vpd_kpi::{vpd_kpi, VpdKpi};
+use crate::services::report::drainage::{drainage_analysis, DrainageDay};
+use crate::services::storage::dry_run::{dry_run_requested, DryRunReport, DryRunShared};
+use crate::services::storage::export::{load_export_history, ExportRecord};
+use crate::services::storage::health::{load_health_days, HealthDay};
+use crate::services::storage::history::{load_gh_history, load_node_history, HistoryQuery, HistoryRow, NodeHistoryRow};
+use crate::services::storage::commissioning::{self, ImportReport, NodeAction, NodeDiff};
+use crate::services::storage::integrity::{self, AuditReport};
+use crate::services::storage::maint_window::{end_maintenance, load_windows, start_maintenance, MaintenanceWindow};
+use crate::services::storage::maintenance::{self, DeactivateReport, MergeReport};
+use crate::services::storage::scaled::{self, ScaledReport};
+use crate::services::storage::rebuild::{self, RebuildCancel, RebuildOptions, RebuildReport};
+use crate::services::storage::origin_map::{load_origin_map, set_origin_mapping, OriginMapping};
+use crate::services::storage::node_schema::{self, NodeSchemaEntry};
+use crate::services::storage::sqlite::open_db;
+use crate::services::storage::node_meta::{self, NodeDisplayPrefs, NodeInfo};
+use crate::services::storage::sqlite::{absolute_path, load_clock_adjustments, StorageStats, StorageStatsShared};
+use crate::DB_PATH;
+
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T, String> + Send + 'static) -> Result<T, String> {
+    tokio::task::spawn_blocking(f).await.map_err(|e| format!("join error: {e}"))?
+}
+
+/// Reload the greenhouse aggregator's roster after `node_name` changed.
+async fn reload_roster(rosters: &RosterShared) -> Result<(), String> {
+    let roster = blocking(|| commissioning::load_roster(DB_PATH).map_err(|e| e.to_string())).await?;
+    replace_roster(rosters, roster);
+    Ok(())
+}
+
+/// Last `hours` closed local hours for a greenhouse (oldest first), for the 24h strip.
+#[tauri::command]
+pub fn get_recent_hourly(store: State<'_, HourlyShared>, gh_id: u16, hours: usize) -> Vec<GhHourly> {
+    store.read().map(|s| s.recent(gh_id, hours)).unwrap_or_default()
+}
+
+/// Wall-clock jumps since `since_ms`; aggregates stamped inside `from_ms..to_ms` are untrustworthy.
+#[tauri::command]
+pub async fn get_clock_adjustments(since_ms: i64) -> Result<Vec<ClockAdjustment>, String> {
+    blocking(move || load_clock_adjustments(DB_PATH, since_ms).map_err(|e| e.to_string())).await
+}
+
+/// All greenhouses side by side (same payload as the `site_overview` event).
+#[tauri::command]
+pub fn get_site_overview(latest: State<'_, LatestGhShared>, hourly: State<'_, HourlyShared>, origins: State<'_, OriginShared>) -> SiteOverview {
+    site_overview(&latest, &hourly, &origins)
+}
+
+#[derive(serde::Serialize)]
+pub struct PipelineStats {
+    pub channels: ChannelConfig,
+    pub lanes: Vec<LaneStat>,
+    /// Subscriber -> node aggregator queue: full policy, sent / dropped / blocked.
+    pub decoded: QueueStat,
+    pub events: std::collections::BTreeMap<String, EventStat>,
+    pub storage: StorageStats,
+    /// Frames the subscriber dropped, by reason: decode failures, `TopicIdMismatch` (topic / payload ids disagree)
+    /// and `Duplicate` (same frame twice within the dedup window).
+    pub decode_errors: std::collections::BTreeMap<&'static str, u64>,
+}
+
+/// Configured channel capacities, per-lane sent / dropped / waited / timed-out counts, decoded queue drops, per-event payload sizes / rates,
+/// DB flush timing and decode failures since startup.
+#[tauri::command]
+pub fn get_pipeline_stats(
+    stats: State<'_, EmitStatsShared>,
+    storage: State<'_, StorageStatsShared>,
+    lanes: State<'_, LanesShared>,
+    decoded: State<'_, QueueCountersShared>,
+    decode_errors: State<'_, DecodeErrorsShared>,
+) -> PipelineStats {
+    PipelineStats {
+        channels: channel_config(),
+        lanes: lane_stats(&lanes),
+        decoded: decoded.stat(),
+        events: stats.read().map(|m| m.clone()).unwrap_or_default(),
+        storage: storage.read().map(|s| s.clone()).unwrap_or_default(),
+        decode_errors: decode_errors.read().map(|m| m.clone()).unwrap_or_default(),
+    }
+}
+
+/// Decoded frames per kind and decode failures since startup or the last reset (same payload as the
+/// `decoder_stats` event).
+#[tauri::command]
+pub fn get_decoder_stats(stats: State<'_, DecoderStatsShared>) -> DecoderStatsSnapshot {
+    stats.snapshot()
+}
+
+/// Zero the decoder counters; returns the totals they had.
+#[tauri::command]
+pub fn reset_decoder_stats(stats: State<'_, DecoderStatsShared>) -> DecoderStatsSnapshot {
+    let before = stats.snapshot();
+    stats.reset();
+    before
+}
+
+/// Shift report for one greenhouse over `[from_ms, to_ms)`; with `html_path`, also writes a printable page there.
+#[tauri::command]
+pub async fn generate_shift_report(gh_id: u16, from_ms: i64, to_ms: i64, html_path: Option<String>) -> Result<ShiftReport, String> {
+    blocking(move || {
+        let report = shift_report(DB_PATH, gh_id, from_ms, to_ms)?;
+        if let Some(path) = html_path {
+            std::fs::write(&path, render_html(&report)).map_err(|e| format!("could not write {path}: {e}"))?;
+        }
+        Ok(report)
+    }).await
+}
+
+/// Daylight minutes in / out of the VPD target band for the local days starting in `[from_ms, to_ms)`.
+#[tauri::command]
+pub async fn get_vpd_kpi(gh_id: u16, from_ms: i64, to_ms: i64) -> Result<VpdKpi, String> {
+    blocking(move || vpd_kpi(DB_PATH, gh_id, from_ms, to_ms)).await
+}
+
+/// Drainage analyses of `node_id` for the irrigation events on local `date` (YYYY-MM-DD).
+#[tauri::command]
+pub async fn get_drainage_analysis(node_id: u16, date: String) -> Result<DrainageDay, String> {
+    blocking(move || drainage_analysis(DB_PATH, node_id, &date)).await
+}
+
+/// Latest scheduled export outcomes (newest first), default 50.
+#[tauri::command]
+pub async fn get_export_history(limit: Option<u32>) -> Result<Vec<ExportRecord>, String> {
+    blocking(move || load_export_history(DB_PATH, limit.unwrap_or(50)).map_err(|e| e.to_string())).await
+}
+
+/// Stored greenhouse rows in `[from_ms, to_ms)` with provenance (source, revision, revised_ms).
+/// `query.revisions`: "latest" (default), "original" or "revised"; `query.source` narrows to one write path.
+#[tauri::command]
+pub async fn get_gh_history(gh_id: u16, from_ms: i64, to_ms: i64, query: Option<HistoryQuery>) -> Result<Vec<HistoryRow>, String> {
+    blocking(move || load_gh_history(DB_PATH, gh_id, from_ms, to_ms, &query.unwrap_or_default()).map_err(|e| e.to_string())).await
+}
+
+/// Stored node rows in `[from_ms, to_ms)` (one node, or every node of the greenhouse); windows skipped
+/// by compact storage come back forward-filled with `filled: true`.
+#[tauri::command]
+pub async fn get_node_history(
+    gh_id: u16, node_id: Option<u16>, from_ms: i64, to_ms: i64, query: Option<HistoryQuery>,
+) -> Result<Vec<NodeHistoryRow>, String> {
+    blocking(move || load_node_history(DB_PATH, gh_id, node_id, from_ms, to_ms, &query.unwrap_or_default()).map_err(|e| e.to_string())).await
+}
+
+/// Full database sanity audit; `fix` repairs the safe subset (unit backfill, missing indexes).
+#[tauri::command]
+pub async fn audit_database(fix: Option<bool>) -> Result<AuditReport, String> {
+    blocking(move || integrity::audit_database(DB_PATH, false, fix.unwrap_or(false)).map_err(|e| e.to_string())).await
+}
+
+/// Switch the database to scaled-integer values and convert older rows in chunks (resumable:
+/// `max_chunks` stops early, rerunning continues where it stopped).
+#[tauri::command]
+pub async fn convert_scaled_values(chunk_rows: Option<i64>, max_chunks: Option<usize>) -> Result<ScaledReport, String> {
+    blocking(move || scaled::convert_scaled_values(DB_PATH, chunk_rows, max_chunks).map_err(|e| e.to_string())).await
+}
+
+/// Store frames from `origin` ("" = local broker) with greenhouse id `gh_id` under `effective_gh_id`
+/// (None removes the mapping). Takes effect on the next frame; returns every mapping.
+#[tauri::command]
+pub async fn set_gh_origin_map(
+    origins: State<'_, OriginShared>,
+    origin: String,
+    gh_id: u16,
+    effective_gh_id: Option<u16>,
+) -> Result<Vec<OriginMapping>, String> {
+    let list = blocking(move || set_origin_mapping(DB_PATH, &origin, gh_id, effective_gh_id, now_ms()).map_err(|e| e.to_string())).await?;
+    if let Ok(mut o) = origins.write() { o.replace_map(&list); }
+    Ok(list)
+}
+
+/// Every origin mapping (conflicts themselves are in the site overview).
+#[tauri::command]
+pub async fn get_gh_origin_map() -> Result<Vec<OriginMapping>, String> {
+    blocking(|| load_origin_map(DB_PATH).map_err(|e| e.to_string())).await
+}
+
+/// Declare (`layout` Some) or remove the frame layout of nodes `node_from..=node_to` in `gh_id`
+/// (None = every greenhouse). Takes effect on the next frame; returns every declared range.
+#[tauri::command]
+pub async fn set_node_schema(
+    schemas: State<'_, SchemaShared>,
+    gh_id: Option<u16>,
+    node_from: u16,
+    node_to: u16,
+    layout: Option<FrameLayout>,
+) -> Result<Vec<NodeSchemaEntry>, String> {
+    if node_from > node_to { return Err(format!("empty node range {node_from}..={node_to}")); }
+    let list = blocking(move || node_schema::set_node_schema(DB_PATH, gh_id, node_from, node_to, layout, now_ms()).map_err(|e| e.to_string())).await?;
+    if let Ok(mut s) = schemas.write() { s.replace(list.clone()); }
+    Ok(list)
+}
+
+/// Every declared node layout range; nodes outside them are decoded by payload length.
+#[tauri::command]
+pub async fn get_node_schema() -> Result<Vec<NodeSchemaEntry>, String> {
+    blocking(|| node_schema::load_node_schema(DB_PATH).map_err(|e| e.to_string())).await
+}
+
+/// Sensor topic filters with the QoS requested and the QoS the broker granted on this connection.
+#[tauri::command]
+pub fn get_subscription_info(subscriptions: State<'_, SubscriptionsShared>) -> Vec<SubscriptionInfo> {
+    subscriptions.read().map(|s| s.clone()).unwrap_or_default()
+}
+
+/// Publishes per node in the last full minute, silent nodes at 0 until aged out; `node_rates` events
+/// carry the same report every minute.
+#[tauri::command]
+pub fn get_node_rates(rates: State<'_, NodeRatesShared>) -> NodeRatesReport {
+    rates.report()
+}
+
+/// Broker connection state of the sensor subscriber; `mqtt_status` events carry the transitions.
+#[tauri::command]
+pub fn get_mqtt_status(status: State<'_, MqttStatusShared>) -> MqttStatusSnapshot {
+    status.read().map(|s| s.clone()).unwrap_or_default()
+}
+
+/// Store the broker password of the configured login in the OS credential store; every client uses it
+/// from its next connect on. The password is never logged or returned.
+#[tauri::command]
+pub async fn set_mqtt_password(password: String) -> Result<(), String> {
+    blocking(move || store_mqtt_password(&password)).await
+}
+
+/// Connect once with the current settings and stored password, without touching the running clients.
+#[tauri::command]
+pub async fn test_mqtt_connection() -> Result<ConnectionTest, String> {
+    test_connection(&mqtt_auth(), std::time::Duration::from_secs(10)).await
+}
+
+/// Publish a command, e.g. `greenhouse/1/node/3/cmd` with `{ text: "tare" }` or `{ base64: "..." }`; only
+/// under `control_publish().allowed_prefixes`. Errors when the publisher is not connected.
+#[tauri::command]
+pub fn publish_mqtt(
+    publisher: State<'_, PublisherShared>,
+    topic: String,
+    payload: PublishPayload,
+    qos: u8,
+    retain: bool,
+) -> Result<(), String> {
+    publisher::publish(&publisher, &topic, payload, qos, retain)
+}
+
+/// Recent raw publishes (hex) with their decode outcome, oldest first; only `node_id` (optionally in
+/// `gh_id`) when given.
+#[tauri::command]
+pub fn dump_raw_payloads(raw: State<'_, RawCaptureShared>, gh_id: Option<u16>, node_id: Option<u16>) -> RawDump {
+    raw.dump(gh_id, node_id)
+}
+
+/// `gh_id` + `node_id` for one node, neither for everything.
+fn pause_target(gh_id: Option<u16>, node_id: Option<u16>) -> Result<Option<(u16, u16)>, String> {
+    match (gh_id, node_id) {
+        (Some(gh), Some(node)) => Ok(Some((gh, node))),
+        (None, None) => Ok(None),
+        _ => Err("give both ghId and nodeId to pause a single node, or neither".to_string()),
+    }
+}
+
+/// Stop forwarding samples to the aggregator, of one node or of all; frames are still decoded and
+/// counted. Until `resume_ingestion` or a restart.
+#[tauri::command]
+pub fn pause_ingestion(
+    pause: State<'_, IngestPauseShared>,
+    status: State<'_, StatusReporter>,
+    gh_id: Option<u16>,
+    node_id: Option<u16>,
+) -> Result<IngestPauseState, String> {
+    pause.pause(pause_target(gh_id, node_id)?);
+    status.report_pause();
+    Ok(pause.state())
+}
+
+/// Forward samples again, of one node or of all (single-node pauses included).
+#[tauri::command]
+pub fn resume_ingestion(
+    pause: State<'_, IngestPauseShared>,
+    status: State<'_, StatusReporter>,
+    gh_id: Option<u16>,
+    node_id: Option<u16>,
+) -> Result<IngestPauseState, String> {
+    pause.resume(pause_target(gh_id, node_id)?);
+    status.report_pause();
+    Ok(pause.state())
+}
+
+/// Last reported online / offline state of every node that published a status, by greenhouse then node.
+#[tauri::command]
+pub fn get_node_availability(availability: State<'_, AvailabilityShared>) -> Vec<NodeAvailability> {
+    availability.read().map(|m| m.values().cloned().collect()).unwrap_or_default()
+}
+
+/// Re-derive `gh_id`'s stored 60s rows in `[from_ms, to_ms)` from its node rows under current rules;
+/// emits `gh_rebuild_progress` per chunk. `cancel_gh_rebuild` stops it between chunks.
+#[tauri::command]
+pub async fn rebuild_gh_averages(
+    sink: State<'_, MeteredSink<AppHandle>>,
+    cancel: State<'_, RebuildCancel>,
+    gh_id: u16,
+    from_ms: i64,
+    to_ms: i64,
+    options: Option<RebuildOptions>,
+) -> Result<RebuildReport, String> {
+    let (sink, cancel) = (sink.inner().clone(), cancel.inner().clone());
+    cancel.store(false, std::sync::atomic::Ordering::Relaxed);
+    blocking(move || rebuild::rebuild_gh_averages(DB_PATH, gh_id, from_ms, to_ms, options.unwrap_or_default(), &cancel, &|p| {
+        if let Ok(v) = serde_json::to_value(p) { sink.emit_json("gh_rebuild_progress", v); }
+    })).await
+}
+
+/// Ask a running `rebuild_gh_averages` to stop after the chunk in progress.
+#[tauri::command]
+pub fn cancel_gh_rebuild(cancel: State<'_, RebuildCancel>) {
+    cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// Move a duplicated node's history onto another node (`dry_run` previews without writing).
+#[tauri::command]
+pub async fn merge_nodes(
+    rosters: State<'_, RosterShared>,
+    gh_id: u16,
+    from_node_id: u16,
+    into_node_id: u16,
+    dry_run: bool,
+) -> Result<MergeReport, String> {
+    let report = blocking(move || maintenance::merge_nodes(DB_PATH, gh_id, from_node_id, into_node_id, dry_run)).await?;
+    if !dry_run { reload_roster(&rosters).await?; }
+    Ok(report)
+}
+
+/// Hide a node while keeping its history (`dry_run` previews without writing).
+#[tauri::command]
+pub async fn deactivate_node(rosters: State<'_, RosterShared>, gh_id: u16, node_id: u16, dry_run: bool) -> Result<DeactivateReport, String> {
+    let report = blocking(move || maintenance::deactivate_node(DB_PATH, gh_id, node_id, dry_run)).await?;
+    if !dry_run { reload_roster(&rosters).await?; }
+    Ok(report)
+}
+
+/// Start (`on`) or end maintenance on a node; its averages stay stored but leave the greenhouse figures.
+/// Emits `node_maintenance`; ending when nothing is open is an error. In dry-run the window is kept in memory only.
+#[tauri::command]
+pub async fn set_node_maintenance(
+    sink: State<'_, MeteredSink<AppHandle>>,
+    windows: State<'_, MaintenanceShared>,
+    gh_id: u16,
+    node_id: u16,
+    on: bool,
+    note: Option<String>,
+) -> Result<MaintenanceWindow, String> {
+    let note = note.unwrap_or_default();
+    let now = now_ms();
+    let w = if dry_run_requested() {
+        if on {
+            start_in_memory(&windows, gh_id, node_id, note.trim(), now)
+        } else {
+            end_in_memory(&windows, gh_id, node_id, note.trim(), now, false)
+                .ok_or_else(|| format!("GH {gh_id} node {node_id} is not in maintenance"))?
+        }
+    } else {
+        blocking(move || {
+            if on {
+                start_maintenance(DB_PATH, gh_id, node_id, note.trim(), now).map_err(|e| e.to_string())
+            } else {
+                end_maintenance(DB_PATH, gh_id, node_id, note.trim(), now, false).map_err(|e| e.to_string())?
+                    .ok_or_else(|| format!("GH {gh_id} node {node_id} is not in maintenance"))
+            }
+        }).await?
+    };
+    publish(&windows, sink.inner(), &w);
+    Ok(w)
+}
+
+/// Maintenance windows of one greenhouse overlapping `[from_ms, to_ms)`, for overlaying on history charts
+/// (in dry-run, the windows of this session still in memory).
+#[tauri::command]
+pub async fn get_node_maintenance(
+    windows: State<'_, MaintenanceShared>, gh_id: u16, from_ms: i64, to_ms: i64,
+) -> Result<Vec<MaintenanceWindow>, String> {
+    if dry_run_requested() {
+        let m = windows.read().map_err(|e| e.to_string())?;
+        let mut list: Vec<_> = m.values()
+            .filter(|w| w.greenhouse_id == gh_id && w.started_ms < to_ms && w.ended_ms.is_none_or(|end| end > from_ms))
+            .cloned().collect();
+        list.sort_by_key(|w| w.started_ms);
+        return Ok(list);
+    }
+    blocking(move || {
+        let conn = open_db(DB_PATH).map_err(|e| e.to_string())?;
+        load_windows(&conn, gh_id, from_ms, to_ms).map_err(|e| e.to_string())
+    }).await
+}
+
+/// Validate a commissioning sheet (CSV) and diff it against the node roster; applies it unless `dry_run`.
+#[tauri::command]
+pub async fn import_commissioning_sheet(
+    calibrations: State<'_, CalibrationShared>,
+    rosters: State<'_, RosterShared>,
+    path: String,
+    dry_run: bool,
+) -> Result<ImportReport, String> {
+    let report = blocking(move || commissioning::import_commissioning_sheet(DB_PATH, std::path::Path::new(&path), dry_run)).await?;
+    if report.applied {
+        let offsets = blocking(|| commissioning::load_calibrations(DB_PATH).map_err(|e| e.to_string())).await?;
+        replace_calibrations(&calibrations, offsets);
+        reload_roster(&rosters).await?;
+    }
+    Ok(report)
+}
+
+/// Listen to everything on the broker for `duration_secs` (default and cap 300) without aggregating or
+/// storing it; reports every topic heard, with nodes missing from the roster marked `known: false`.
+#[tauri::command]
+pub async fn discover_nodes(state: State<'_, DiscoveryShared>, duration_secs: Option<u64>) -> Result<DiscoveryReport, String> {
+    let mut report = discovery::discover(&state, duration_secs).await?;
+    let roster = blocking(|| node_meta::list_nodes(DB_PATH).map_err(|e| e.to_string())).await?;
+    report.mark_known(&roster.iter().map(|n| (n.greenhouse_id, n.node_id)).collect());
+    Ok(report)
+}
+
+/// Stop a running `discover_nodes` scan; it returns what it saw so far. False when none is running.
+#[tauri::command]
+pub fn cancel_node_discovery(state: State<'_, DiscoveryShared>) -> bool {
+    state.cancel()
+}
+
+/// Add a node found by `discover_nodes` (and its greenhouse) to the roster; `label` defaults to `nodeNN`.
+#[tauri::command]
+pub async fn adopt_node(rosters: State<'_, RosterShared>, gh_id: u16, node_id: u16, label: Option<String>) -> Result<NodeDiff, String> {
+    let diff = blocking(move || commissioning::adopt_node(DB_PATH, gh_id, node_id, label)).await?;
+    if diff.action == NodeAction::Insert { reload_roster(&rosters).await?; }
+    Ok(diff)
+}
+
+/// Every known node with status, display prefs and latest health score.
+#[tauri::command]
+pub async fn list_nodes(health: State<'_, NodeHealthShared>) -> Result<Vec<NodeInfo>, String> {
+    let mut nodes = blocking(|| node_meta::list_nodes(DB_PATH).map_err(|e| e.to_string())).await?;
+    if let Ok(scores) = health.read() {
+        for n in &mut nodes { n.health = scores.get(&(n.greenhouse_id, n.node_id)).cloned(); }
+    }
+    Ok(nodes)
+}
+
+/// Daily health score snapshots of one node for days starting in `[from_ms, to_ms)`.
+#[tauri::command]
+pub async fn get_node_health_history(gh_id: u16, node_id: u16, from_ms: i64, to_ms: i64) -> Result<Vec<HealthDay>, String> {
+    blocking(move || load_health_days(DB_PATH, gh_id, node_id, from_ms, to_ms).map_err(|e| e.to_string())).await
+}
+
+/// Display prefs (color, order, icon, hidden) for every node.
+#[tauri::command]
+pub async fn get_node_display_prefs() -> Result<Vec<NodeDisplayPrefs>, String> {
+    blocking(|| node_meta::get_display_prefs(DB_PATH).map_err(|e| e.to_string())).await
+}
+
+/// Store one node's display prefs and broadcast `display_prefs_changed` to every window.
+#[tauri::command]
+pub async fn set_node_display_prefs(
+    sink: State<'_, MeteredSink<AppHandle>>,
+    cache: State<'_, DisplayPrefsShared>,
+    prefs: NodeDisplayPrefs,
+) -> Result<Vec<NodeDisplayPrefs>, String> {
+    let list = blocking(move || node_meta::set_display_prefs(DB_PATH, &prefs)).await?;
+    replace_display_prefs(&cache, list.clone());
+    sink.emit_json("display_prefs_changed", serde_json::to_value(&list).map_err(|e| e.to_string())?);
+    Ok(list)
+}
+
+/// Ack mode for one node (firmware bring-up): an ack / nack per frame on `greenhouse/{gh}/node/{id}/ack`.
+/// Turns itself off after `minutes` (capped); returns the active window, or None once off.
+#[tauri::command]
+pub fn set_node_ack(acks: State<'_, AckShared>, gh_id: u16, node_id: u16, on: bool, minutes: Option<u64>) -> Option<AckWindow> {
+    if on {
+        Some(ack::enable(&acks, gh_id, node_id, minutes.map(|m| m * 60), now_ms()))
+    } else {
+        ack::disable(&acks, gh_id, node_id);
+        None
+    }
+}
+
+/// Decode a captured frame pasted as hex so a field tech can check what a node actually sent.
+#[tauri::command]
+pub fn verify_payload(hex: String) -> Result<Decoded, String> {
+    let bytes = parse_hex(&hex).ok_or("not a valid hex string")?;
+    decode_payload(&bytes).map_err(|e| format!("{} bytes did not decode as a known frame layout ({e})", bytes.len()))
+}
+
+/// Most recent buffered log events at or above `level` (default info), oldest first; `limit` default 200.
+#[tauri::command]
+pub fn get_log_tail(level: Option<String>, limit: Option<usize>) -> Result<Vec<LogEvent>, String> {
+    let level = log_tail::parse_level(level.as_deref().unwrap_or("info"))?;
+    Ok(log_tail::tail(level, limit.unwrap_or(200)))
+}
+
+/// Lowest level sent live as `log_event` (error, warn or info).
+#[tauri::command]
+pub fn set_log_stream_level(level: String) -> Result<(), String> {
+    log_tail::set_stream_level(log_tail::parse_level(&level)?);
+    Ok(())
+}
+
+/// Would-be DB writes since startup; `enabled` drives the DRY RUN banner.
+#[tauri::command]
+pub fn get_dry_run_report(report: State<'_, DryRunShared>) -> DryRunReport {
+    report.read().map(|r| r.clone()).unwrap_or_default()
+}
+
+/// Whether this window runs the pipeline or lost the instance lock to another copy.
+#[tauri::command]
+pub fn get_instance_status(status: State<'_, InstanceStatus>) -> InstanceStatus {
+    status.inner().clone()
+}
+
+/// Second instance: bring the running copy's window forward and quit.
+#[tauri::command]
+pub fn focus_existing_instance(app: AppHandle) -> Result<(), String> {
+    send_request(&absolute_path(DB_PATH), LockRequest::Focus)?;
+    app.exit(0);
+    Ok(())
+}
+
+/// Second instance: ask the running copy to release the lock and exit, then restart as the owner.
+#[tauri::command]
+pub async fn take_over_instance(app: AppHandle) -> Result<(), String> {
+    let db_abs = absolute_path(DB_PATH);
+    send_request(&db_abs, LockRequest::TakeOver)?;
+    for _ in 0..120 {
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        if live_holder(&db_abs).is_none() { app.restart(); }
+    }
+    Err("the running instance did not release the lock within 30s".into())
+}
+
+/// Stop the data path (source and both aggregators, flushing their windows) and start it again with
+/// mqtt.toml read anew, without closing the window; emits `pipeline_restart` per step.
+#[tauri::command]
+pub async fn restart_pipeline(
+    pipeline: State<'_, Pipeline<MeteredSink<AppHandle>>>,
+    shutdown: State<'_, Shutdown>,
+) -> Result<RestartReport, String> {
+    pipeline.restart(&shutdown).await
+}
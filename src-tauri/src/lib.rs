@@ -1 +1,24 @@
-// for future code
\ No newline at end of file
+//! Pipeline services, Tauri commands and the CLI; main.rs wires them into the app. A library so the
+//! benchmarks (benches/) can reach the payload types.
+
+pub mod services {
+    pub mod mqtt;
+    pub mod storage;
+    pub mod presenter;
+    pub mod scheduler;
+    pub mod channels;
+    pub mod instance_lock;
+    pub mod clock;
+    pub mod report;
+    pub mod node_maintenance;
+    pub mod node_health;
+    pub mod math;
+    pub mod log_tail;
+    pub mod shutdown;
+    pub mod supervisor;
+    pub mod pipeline;
+}
+pub mod commands;
+pub mod cli;
+
+pub const DB_PATH: &str = "../data/app.db"; // keep DB outside src-tauri
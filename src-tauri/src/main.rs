@@ -1,82 +1,495 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod services {
-    pub mod mqtt;
-    pub mod storage;
-}
+use apptest_v05_lib::{cli, commands, services, DB_PATH};
 
 use services::mqtt::greenhouse_sensor::{
-    subscriber::run_debug_subscriber,
-    aggregator::{run_rolling_avg, NodeAvg, NodeAvgUi},
-    greenhouse_aggregator::{run_greenhouse_avg, GhAvg},
+    subscriber::{DecodeErrorsShared, SubscriberShared, SubscriptionsShared},
+    decoder_stats::{run_decoder_stats, DecoderStatsShared},
+    node_rates::{run_node_rates, NodeRatesShared},
+    conn_status::{MqttStatusEvent, MqttStatusShared, StatusReporter},
+    raw_capture::RawCaptureShared,
+    ingest_pause::IngestPauseShared,
+    availability::{run_availability, AvailabilityShared, AvailabilityTracker, NodeAvailability},
+    liveness::{run_liveness, NodeStatus},
+    aggregator::{aggregation_window, CatchupReport, LatestNodeShared, NodeAvg, NodeAvgUi, NodeLive},
+    calibration::{replace_calibrations, CalibrationShared},
+    greenhouse_aggregator::{replace_roster, GhAvg, GhAvgOutputs, LatestGhShared, RosterShared},
+    hourly_aggregator::{run_hourly_avg, GhHourly, HourlyShared},
+    derived,
+    ack::AckShared,
+    origin::{run_origin_watch, OriginShared},
+    discovery::DiscoveryShared,
 };
-use services::storage::sqlite::run_storage;
+use services::mqtt::auth::init_mqtt_auth;
+use services::mqtt::heartbeat::run_heartbeat;
+use services::mqtt::query::{run_query_responder, QuerySources};
+use services::mqtt::publisher::{run_publisher, PublisherShared};
+use services::mqtt::recording::{recording_mode, RecordingMode};
+use services::mqtt::site_summary::run_site_summary;
+use services::mqtt::remote_cmd::run_remote_commands;
+use services::mqtt::schema::{load_schema_registry, SchemaShared};
+use services::presenter::emitter::{replace_display_prefs, DisplayPrefsShared, EventSink, UiEmitter};
+use services::presenter::overview::run_site_overview;
+use services::presenter::kiosk::run_kiosk_snapshot;
+use services::presenter::metered::{EmitStatsShared, MeteredSink};
+use services::storage::integrity::{audit_database, Severity};
+use services::storage::dry_run::{dry_run_requested, new_report};
+use services::storage::node_meta::get_display_prefs;
+use services::storage::commissioning::{load_calibrations, load_roster};
+use services::storage::rebuild::RebuildCancel;
+use services::storage::sqlite::{absolute_path, load_recent_hourly, run_storage, StorageInputs, StorageStatsShared, BATCH_SIZE, FLUSH_EVERY};
+use services::report::shift::run_shift_reports;
+use services::report::export::run_scheduled_exports;
+use services::report::vpd_kpi::{run_vpd_kpi, VpdKpiShared};
+use services::report::drainage::run_drainage_analysis;
+use services::node_health::{run_node_health, HealthCountersShared, NodeHealthShared};
+use services::clock::{run_clock_watch, ClockAdjustment};
+use services::instance_lock::{run_instance_heartbeat, InstanceLock, InstanceStatus};
+use services::node_maintenance::{run_maintenance_watch, MaintenanceShared};
+use services::channels::{channel_config, decoded_policy, lane, log_sizing_report, queue_counters, run_queue_watch, LanesShared, Priority};
+use services::log_tail;
+use services::shutdown::{Shutdown, SHUTDOWN_TIMEOUT};
+use services::supervisor::{supervise, Slot};
+use services::pipeline::{DataPath, NodeOutputs, Pipeline, Source};
 
+use tauri::{AppHandle, Manager};
 use tokio::sync::mpsc;
+use tracing::{error, info, warn};
 
-const DB_PATH: &str = "../data/app.db"; // keep DB outside src-tauri
+/// Unix ms `hours` ago, used as the lower bound when refilling hourly history.
+fn unix_ms_hours_ago(hours: i64) -> i64 {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as i64;
+    now - hours * 3_600_000
+}
 
 #[tokio::main]
 async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) { std::process::exit(code); }
+    log_tail::install();
     tauri::Builder::default()
         .setup(|app| {
-            // Stage 1: decoded samples from MQTT subscriber
-            let (tx_decoded, rx_decoded) = mpsc::channel(256);
+            // One pipeline per database: a second instance only shows the conflict banner
+            let db_abs = absolute_path(DB_PATH);
+            match InstanceLock::acquire(&db_abs) {
+                Ok(lock) => {
+                    app.manage(InstanceStatus { pid: std::process::id(), conflict: None });
+                    app.manage(lock.clone());
+                    tauri::async_runtime::spawn(run_instance_heartbeat(lock, db_abs, app.handle().clone()));
+                }
+                Err(held) => {
+                    warn!(target: "LOCK", "pid {} already runs the pipeline on this database; not starting another", held.pid);
+                    app.manage(InstanceStatus { pid: std::process::id(), conflict: Some(held) });
+                    return Ok(());
+                }
+            }
+
+            // Configured derived metrics; a bad expression, unknown key or cycle stops the app here
+            derived::load_registry().map_err(|e| {
+                error!(target: "PIPE", "not starting: {e}");
+                e
+            })?;
+
+            // One window length for both aggregators and their rows; a bad one stops the app here
+            let window = aggregation_window().map_err(|e| {
+                error!(target: "PIPE", "not starting: {e}");
+                e
+            })?;
+            info!(target: "PIPE", "aggregation window {}s (rows stored as rolling_{}s)", window.as_secs(), window.as_secs());
 
-            // Stage 2 outputs: per-node 60s averages
-            let (tx_nodeavg_for_gh, rx_nodeavg_for_gh) = mpsc::channel::<NodeAvg>(128);
-            let (tx_nodeavg_for_db, rx_nodeavg_for_db) = mpsc::channel::<NodeAvg>(128);
-            let (tx_nodeavg_for_ui, mut rx_nodeavg_for_ui) = mpsc::channel::<NodeAvgUi>(128);
+            // Broker address / credentials: mqtt.toml in the app config dir, APP_MQTT_* env, defaults
+            init_mqtt_auth(app.path().app_config_dir().ok().as_deref());
+
+            // Subscriber, aggregators and DB writer finish their work on exit (see shutdown.rs), and are
+            // restarted if they die before it (supervisor.rs); the data path also on restart_pipeline (pipeline.rs)
+            let shutdown = Shutdown::default();
+            let stop = shutdown.token();
+
+            let caps = channel_config();
+            log_sizing_report(&caps, FLUSH_EVERY, BATCH_SIZE);
+
+            // Stage 1: decoded samples from MQTT subscriber (the queue itself is built per data path run)
+            let decoded_queue = queue_counters("decoded", caps.decoded, decoded_policy());
+            app.manage(decoded_queue.clone());
+
+            // Stage 2 and 3 outputs are lanes: NodeAvg and live UI droppable, GhAvg to DB / hourly / KPI
+            // critical (never dropped; see channels.rs for the ordering guarantee)
+            let lanes = LanesShared::default();
+            app.manage(lanes.clone());
+
+            // Stage 2 outputs: per-node 60s averages (the lane to the greenhouse aggregator is built per run)
+            let (tx_nodeavg_for_db, rx_nodeavg_for_db) = lane::<NodeAvg>(&lanes, "nodeavg_db", Priority::Droppable, caps.nodeavg);
+            let (tx_catchup_for_db, rx_catchup_for_db) = lane::<NodeAvg>(&lanes, "nodeavg_catchup", Priority::Critical, caps.nodeavg);
+            let (tx_catchup_done, mut rx_catchup_done) = lane::<CatchupReport>(&lanes, "catchup_done", Priority::Droppable, 4);
+            let (tx_nodeavg_for_ui, rx_nodeavg_for_ui) = lane::<NodeAvgUi>(&lanes, "nodeavg_ui", Priority::Droppable, caps.nodeavg);
+            let (tx_nodelive_for_ui, rx_nodelive_for_ui) = lane::<NodeLive>(&lanes, "nodelive_ui", Priority::Droppable, caps.nodeavg);
+            let (tx_node_status, rx_node_status) = lane::<NodeStatus>(&lanes, "node_status", Priority::Droppable, caps.node_status);
 
             // Stage 3 outputs: greenhouse 60s averages
-            let (tx_ghavg_for_db, rx_ghavg_for_db) = mpsc::channel::<GhAvg>(64);
-            let (tx_ghavg_for_ui, mut rx_ghavg_for_ui) = mpsc::channel::<GhAvg>(64);
+            let (tx_ghavg_for_db, rx_ghavg_for_db) = lane::<GhAvg>(&lanes, "ghavg_db", Priority::Critical, caps.ghavg);
+            let (tx_ghavg_for_ui, rx_ghavg_for_ui) = lane::<GhAvg>(&lanes, "ghavg_ui", Priority::Droppable, caps.ghavg);
+            let (tx_ghavg_for_hourly, rx_ghavg_for_hourly) = lane::<GhAvg>(&lanes, "ghavg_hourly", Priority::Critical, caps.ghavg);
+            let (tx_ghavg_for_kpi, rx_ghavg_for_kpi) = lane::<GhAvg>(&lanes, "ghavg_kpi", Priority::Critical, caps.ghavg);
 
-            // DB writer task
-            tauri::async_runtime::spawn(async move {
-                run_storage(DB_PATH, rx_nodeavg_for_db, rx_ghavg_for_db).await;
-            });
+            // Stage 4 outputs: greenhouse hourly aggregates
+            let (tx_hourly_for_db, rx_hourly_for_db) = mpsc::channel::<GhHourly>(caps.hourly);
+            let (tx_hourly_for_ui, rx_hourly_for_ui) = mpsc::channel::<GhHourly>(caps.hourly);
+
+            // Clock watch outputs: detected wall-clock jumps
+            let (tx_clock_for_db, rx_clock_for_db) = mpsc::channel::<ClockAdjustment>(caps.clock);
+            let (tx_clock_for_ui, rx_clock_for_ui) = mpsc::channel::<ClockAdjustment>(caps.clock);
+
+            // Shared state read by Tauri commands
+            let hourly_store = HourlyShared::default();
+            app.manage(hourly_store.clone());
+            let latest_gh = LatestGhShared::default();
+            app.manage(latest_gh.clone());
+            let display_prefs = DisplayPrefsShared::default();
+            app.manage(display_prefs.clone());
+            let vpd_kpi_today = VpdKpiShared::default();
+            let maintenance_windows = MaintenanceShared::default();
+            app.manage(maintenance_windows.clone());
+            let calibrations = CalibrationShared::default();
+            app.manage(calibrations.clone());
+            let dry_run_enabled = dry_run_requested();
+            let dry_run_report = new_report(dry_run_enabled);
+            app.manage(dry_run_report.clone());
+            app.manage(RebuildCancel::default());
+            app.manage(DiscoveryShared::default());
+            let health_counters = HealthCountersShared::default();
+            let node_health = NodeHealthShared::default();
+            app.manage(node_health.clone());
+
+            // Every webview event goes through one metered, size-capped sink
+            let emit_stats = EmitStatsShared::default();
+            app.manage(emit_stats.clone());
+            let ui_sink = MeteredSink::new(app.handle().clone(), emit_stats);
+            app.manage(ui_sink.clone());
+
+            // Decoded queue drops (full policy) -> warning & "decoded_backpressure" every 30 s while growing
+            tauri::async_runtime::spawn(run_queue_watch(ui_sink.clone(), decoded_queue.clone()));
+
+            // Log tail: buffered events -> live "log_event" stream for the diagnostics screen
+            if let Some(mut rx_log) = log_tail::take_stream() {
+                let log_sink = ui_sink.clone();
+                tauri::async_runtime::spawn(async move {
+                    while let Some(ev) = rx_log.recv().await {
+                        if let Ok(v) = serde_json::to_value(&ev) { log_sink.emit_json("log_event", v); }
+                    }
+                });
+            }
+
+            // Quick DB audit (schema, units, indexes); problems -> "db_audit_warning" (not in dry-run: the DB stays closed)
+            let audit_sink = ui_sink.clone();
+            if !dry_run_enabled { tauri::async_runtime::spawn(async move {
+                match tokio::task::spawn_blocking(|| audit_database(DB_PATH, true, false)).await {
+                    Ok(Ok(report)) if report.worst() >= Some(Severity::Warning) => {
+                        for f in &report.findings { warn!(target: "DB", "audit {:?}: {}", f.severity, f.detail); }
+                        if let Ok(v) = serde_json::to_value(&report) { audit_sink.emit_json("db_audit_warning", v); }
+                    }
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => warn!(target: "DB", "startup audit failed: {e}"),
+                    Err(e) => error!(target: "DB", "startup audit join error: {e}"),
+                }
+            }); }
+
+            // Greenhouse ids arriving from several sites: mappings + conflict watch -> "gh_origin_conflict"
+            let gh_origins = OriginShared::default();
+            app.manage(gh_origins.clone());
+            tauri::async_runtime::spawn(run_origin_watch(ui_sink.clone(), gh_origins.clone(), DB_PATH, dry_run_enabled));
+
+            // Site overview: latest GhAvg + hourly history -> "site_overview" every minute
+            tauri::async_runtime::spawn(run_site_overview(ui_sink.clone(), latest_gh.clone(), hourly_store.clone(), gh_origins.clone()));
+
+            // Kiosk snapshot: same shared state -> atomic JSON file for the lobby screen
+            tauri::async_runtime::spawn(run_kiosk_snapshot(latest_gh.clone(), hourly_store.clone()));
 
-            // Greenhouse aggregator (NodeAvg -> GhAvg -> DB & UI)
-            let tx_ghavg_for_db_clone = tx_ghavg_for_db.clone();
-            let tx_ghavg_for_ui_clone = tx_ghavg_for_ui.clone();
+            // DB writer task (counts only in dry-run; fixed until restart)
+            let dry_run = dry_run_enabled.then_some(dry_run_report);
+            let storage_stats = StorageStatsShared::default();
+            app.manage(storage_stats.clone());
+            let storage_stats_clone = storage_stats.clone();
+            let storage_inputs = Slot::new(StorageInputs { nodeavg: rx_nodeavg_for_db, catchup: rx_catchup_for_db, ghavg: rx_ghavg_for_db, hourly: rx_hourly_for_db, clock: rx_clock_for_db });
+            let storage_stop = stop.clone();
+            shutdown.track("storage", tauri::async_runtime::spawn(supervise("storage", ui_sink.clone(), stop.clone(), move || {
+                let (inputs, dry_run, stats, stop) = (storage_inputs.lease(), dry_run.clone(), storage_stats.clone(), storage_stop.clone());
+                async move {
+                    let Some(inputs) = inputs else { return };
+                    run_storage(DB_PATH, inputs, dry_run, stats, stop).await;
+                }
+            })));
+
+            // Hourly aggregator (GhAvg -> GhHourly -> DB & UI), refilled from DB first (starts empty in dry-run)
+            let rx_ghavg_for_hourly = Slot::new(rx_ghavg_for_hourly);
+            let hourly_sink = ui_sink.clone();
+            let hourly_stop = stop.clone();
             tauri::async_runtime::spawn(async move {
-                run_greenhouse_avg(rx_nodeavg_for_gh, tx_ghavg_for_db_clone, tx_ghavg_for_ui_clone).await;
+                let since_ms = unix_ms_hours_ago(48);
+                if !dry_run_enabled {
+                    match tokio::task::spawn_blocking(move || load_recent_hourly(DB_PATH, since_ms)).await {
+                        Ok(Ok(rows)) => {
+                            if let Ok(mut s) = hourly_store.write() { rows.into_iter().for_each(|h| s.push(h)); }
+                        }
+                        Ok(Err(e)) => warn!(target: "DB", "hourly refill failed: {e}"),
+                        Err(e) => error!(target: "DB", "hourly refill join error: {e}"),
+                    }
+                }
+                supervise("hourly_avg", hourly_sink, hourly_stop, move || {
+                    let (rx, tx_db, tx_ui, store) = (rx_ghavg_for_hourly.lease(), tx_hourly_for_db.clone(), tx_hourly_for_ui.clone(), hourly_store.clone());
+                    async move {
+                        let Some(rx) = rx else { return };
+                        run_hourly_avg(rx, tx_db, tx_ui, store).await;
+                    }
+                }).await;
             });
 
-            // Node rolling averages (Decoded -> NodeAvg for GH & DB & UI)
-            let tx_nodeavg_for_gh_clone = tx_nodeavg_for_gh.clone();
-            let tx_nodeavg_for_db_clone = tx_nodeavg_for_db.clone();
-            let tx_nodeavg_for_ui_clone = tx_nodeavg_for_ui.clone();
+            // Greenhouse aggregator outputs (GhAvg -> DB & UI & hourly & KPI); coverage counts the roster
+            let gh_outputs = GhAvgOutputs { db: tx_ghavg_for_db, ui: tx_ghavg_for_ui, hourly: tx_ghavg_for_hourly, kpi: tx_ghavg_for_kpi, latest: latest_gh.clone() };
+            let rosters = RosterShared::default();
+            app.manage(rosters.clone());
+
+            // VPD KPI (GhAvg -> today's in-band daylight minutes -> greenhouse_daily & gh_avg events)
+            tauri::async_runtime::spawn(run_vpd_kpi(rx_ghavg_for_kpi, vpd_kpi_today.clone(), DB_PATH, dry_run_enabled));
+
+            // Slab drainage after irrigation (stored node weights, hourly) -> drainage_event & "drainage_alert"
+            tauri::async_runtime::spawn(run_drainage_analysis(ui_sink.clone(), DB_PATH, dry_run_enabled));
+
+            // Node health scores (frame counters from subscriber & aggregator, every 5 min) -> "node_health" & node_health_daily
+            tauri::async_runtime::spawn(run_node_health(ui_sink.clone(), health_counters.clone(), node_health, DB_PATH, dry_run_enabled));
+
+            // Latest window of every node (node aggregator -> remote commands, queries, site summary)
+            let latest_nodes = LatestNodeShared::default();
+
+            // Remote integrator commands (opt-in; HMAC-signed, allow-listed, audited)
+            tauri::async_runtime::spawn(run_remote_commands(ui_sink.clone(), latest_gh.clone(), latest_nodes.clone(), DB_PATH));
+
+            // Headless queries for the latest averages (apps/.../query -> .../query/response)
+            tauri::async_runtime::spawn(run_query_responder(QuerySources { latest_gh: latest_gh.clone(), latest_nodes: latest_nodes.clone() }));
+
+            // Site summary publisher (latest GhAvg and node windows -> retained MQTT heartbeat)
+            let latest_nodes_clone = latest_nodes.clone();
             tauri::async_runtime::spawn(async move {
-                run_rolling_avg(rx_decoded, tx_nodeavg_for_db_clone, tx_nodeavg_for_gh_clone, tx_nodeavg_for_ui_clone).await;
+                run_site_summary(latest_gh, latest_nodes_clone, DB_PATH).await;
             });
 
-            // MQTT subscriber (hot path)
+            // Decoder counters (subscriber frames, node aggregator slew rejections) -> "decoder_stats"
+            let decoder_stats = DecoderStatsShared::default();
+            app.manage(decoder_stats.clone());
+            tauri::async_runtime::spawn(run_decoder_stats(ui_sink.clone(), decoder_stats.clone()));
+
+            // Node rolling averages outputs (NodeAvg to DB & UI, liveness, catch-up)
+            let node_outputs = NodeOutputs { db: tx_nodeavg_for_db, ui: tx_nodeavg_for_ui, live: tx_nodelive_for_ui, status: tx_node_status, latest: latest_nodes, catchup: tx_catchup_for_db, catchup_done: tx_catchup_done };
+            let maintenance_clone = maintenance_windows.clone();
+            let health_counters_clone = health_counters.clone();
+            let decoder_stats_clone = decoder_stats.clone();
+
+            // Node maintenance watchdog (reload open windows, reminder after an hour, auto-close; in memory in dry-run)
+            tauri::async_runtime::spawn(run_maintenance_watch(ui_sink.clone(), maintenance_windows, DB_PATH, dry_run_enabled));
+
+            // Wall-clock jump detection (quarantine intervals -> DB, "clock_adjusted" -> UI)
+            tauri::async_runtime::spawn(run_clock_watch(tx_clock_for_db, tx_clock_for_ui));
+
+            // Night-shift report at every local shift change -> "shift_report_ready" (from stored rows: none in dry-run)
+            if !dry_run_enabled { tauri::async_runtime::spawn(run_shift_reports(ui_sink.clone(), DB_PATH)); }
+
+            // Daily CSV exports of yesterday per configured greenhouse (no-op without jobs)
+            tauri::async_runtime::spawn(run_scheduled_exports(DB_PATH, dry_run_enabled));
+
+            // MQTT subscriber (hot path; also publishes per-node acks while ack mode is on)
+            let node_acks = AckShared::default();
+            app.manage(node_acks.clone());
+            let decode_errors = DecodeErrorsShared::default();
+            app.manage(decode_errors.clone());
+            // Publishes per node and minute -> "node_rates" & slow-node warnings
+            let node_rates = NodeRatesShared::default();
+            app.manage(node_rates.clone());
+            tauri::async_runtime::spawn(run_node_rates(ui_sink.clone(), node_rates.clone()));
+            // Declared frame layouts per node (node_schema); unknown nodes keep the length heuristic
+            let node_schemas = SchemaShared::default();
+            app.manage(node_schemas.clone());
+            if !dry_run_enabled { tauri::async_runtime::spawn(load_schema_registry(node_schemas.clone(), DB_PATH)); }
+            let subscriptions = SubscriptionsShared::default();
+            app.manage(subscriptions.clone());
+            // Connection transitions -> "mqtt_status"; latest state for get_mqtt_status
+            let mqtt_status = MqttStatusShared::default();
+            app.manage(mqtt_status.clone());
+            let (tx_status, mut rx_status) = mpsc::channel::<MqttStatusEvent>(caps.mqtt_status);
+            // pause_ingestion / resume_ingestion (never persisted); the state rides on mqtt_status
+            let ingest_pause = IngestPauseShared::default();
+            app.manage(ingest_pause.clone());
+            let status_reporter = StatusReporter::new(tx_status, mqtt_status, ingest_pause.clone());
+            app.manage(status_reporter.clone());
+            let status_sink = ui_sink.clone();
             tauri::async_runtime::spawn(async move {
-                run_debug_subscriber(tx_decoded).await;
+                while let Some(ev) = rx_status.recv().await {
+                    if let Ok(v) = serde_json::to_value(&ev) { status_sink.emit_json("mqtt_status", v); }
+                }
             });
-
-            // UI emitter: forward full GhAvg to frontend ("gh_avg" events)
-            let app_handle = app.handle().clone();
+            let catchup_sink = ui_sink.clone();
             tauri::async_runtime::spawn(async move {
-                use tauri::Emitter;
-                while let Some(ga) = rx_ghavg_for_ui.recv().await {
-                    let _ = app_handle.emit("gh_avg", ga);
+                while let Some(report) = rx_catchup_done.recv().await {
+                    if let Ok(v) = serde_json::to_value(&report) { catchup_sink.emit_json("catchup_complete", v); }
                 }
             });
+            // Dashboard -> node commands (publish_mqtt) over their own client
+            let publisher = PublisherShared::default();
+            app.manage(publisher.clone());
+            tauri::async_runtime::spawn(run_publisher(publisher.clone()));
+            // App heartbeat for ops (retained, Last Will offline) on the publisher's client
+            shutdown.track("heartbeat", tauri::async_runtime::spawn(run_heartbeat(publisher, decoder_stats.clone(), storage_stats_clone, stop.clone())));
+
+            // Node online / offline (Last Will status topics) -> "node_status" & node_status_log
+            let node_availability = AvailabilityShared::default();
+            app.manage(node_availability.clone());
+            let (tx_availability, rx_availability) = mpsc::channel::<NodeAvailability>(caps.node_status);
+            tauri::async_runtime::spawn(run_availability(ui_sink.clone(), rx_availability, DB_PATH, dry_run_enabled));
+            // Node online / late / offline from the samples (node aggregator) -> "node_status" & node_status_log
+            tauri::async_runtime::spawn(run_liveness(ui_sink.clone(), rx_node_status, DB_PATH, dry_run_enabled));
+            // Last raw publishes for dump_raw_payloads
+            let raw_capture = RawCaptureShared::default();
+            app.manage(raw_capture.clone());
+            // Live broker traffic, or a traffic recording played back in its place (--replay)
+            let mode = recording_mode();
+            let replay_schemas = node_schemas.clone();
+            let shared = SubscriberShared {
+                acks: node_acks,
+                origins: gh_origins,
+                health: health_counters,
+                decode_errors,
+                decoder_stats,
+                schemas: node_schemas,
+                subscriptions,
+                status: status_reporter,
+                availability: AvailabilityTracker::new(node_availability, tx_availability),
+                raw: raw_capture,
+                pause: ingest_pause,
+                rates: node_rates,
+                record_to: match &mode { Some(RecordingMode::Record(path)) => Some(path.clone()), _ => None },
+            };
+            let source = match mode {
+                Some(RecordingMode::Replay { path, speed }) => Source::Replay { path, speed, schemas: replay_schemas },
+                _ => Source::Mqtt(shared),
+            };
 
-            // UI emitter: forward NodeAvg to frontend ("node_avg" events)
-            let app_handle2 = app.handle().clone();
+            // Data path (source -> node aggregator -> greenhouse aggregator), restarted as a whole by
+            // restart_pipeline (pipeline.rs)
+            let pipeline = Pipeline::new(DataPath {
+                source,
+                lanes: lanes.clone(),
+                decoded: decoded_queue,
+                nodes: node_outputs,
+                greenhouses: gh_outputs,
+                rosters: rosters.clone(),
+                calibrations: calibrations.clone(),
+                maintenance: maintenance_clone,
+                health: health_counters_clone,
+                decoder_stats: decoder_stats_clone,
+            }, ui_sink.clone(), &shutdown);
+            app.manage(pipeline);
+            app.manage(shutdown);
+            let handle = app.handle().clone();
             tauri::async_runtime::spawn(async move {
-                use tauri::Emitter;
-                while let Some(na) = rx_nodeavg_for_ui.recv().await {
-                    let _ = app_handle2.emit("node_avg", na);
+                // calibration offsets and the stored roster first, so no frame is averaged uncorrected and
+                // coverage counts nodes that have not reported yet (none in dry-run)
+                if !dry_run_enabled {
+                    match tokio::task::spawn_blocking(|| load_calibrations(DB_PATH)).await {
+                        Ok(Ok(offsets)) => replace_calibrations(&calibrations, offsets),
+                        Ok(Err(e)) => warn!(target: "DB", "calibration load failed: {e}"),
+                        Err(e) => error!(target: "DB", "calibration load join error: {e}"),
+                    }
+                    match tokio::task::spawn_blocking(|| load_roster(DB_PATH)).await {
+                        Ok(Ok(roster)) => replace_roster(&rosters, roster),
+                        Ok(Err(e)) => warn!(target: "DB", "roster load failed: {e}"),
+                        Err(e) => error!(target: "DB", "roster load join error: {e}"),
+                    }
                 }
+                let (pipeline, shutdown) = (handle.state::<Pipeline<MeteredSink<AppHandle>>>(), handle.state::<Shutdown>());
+                if let Err(e) = pipeline.start(&shutdown).await { error!(target: "PIPE", "data path not started: {e}"); }
             });
 
+            // UI emitter: NodeAvgUi / NodeLive / GhAvg / GhHourly -> "node_avg" / "node_live" / "gh_avg" / "gh_hourly" events
+            let prefs_cache = display_prefs.clone();
+            if !dry_run_enabled { tauri::async_runtime::spawn(async move {
+                match tokio::task::spawn_blocking(|| get_display_prefs(DB_PATH)).await {
+                    Ok(Ok(list)) => replace_display_prefs(&prefs_cache, list),
+                    Ok(Err(e)) => warn!(target: "DB", "display prefs load failed: {e}"),
+                    Err(e) => error!(target: "DB", "display prefs join error: {e}"),
+                }
+            }); }
+            let ui_inputs = (Slot::new(rx_nodeavg_for_ui), Slot::new(rx_nodelive_for_ui), Slot::new(rx_ghavg_for_ui),
+                             Slot::new(rx_hourly_for_ui), Slot::new(rx_clock_for_ui));
+            tauri::async_runtime::spawn(supervise("ui_emitter", ui_sink.clone(), stop, move || {
+                let ui = UiEmitter::new(ui_sink.clone(), display_prefs.clone(), vpd_kpi_today.clone());
+                let (node, live, gh) = (ui_inputs.0.lease(), ui_inputs.1.lease(), ui_inputs.2.lease());
+                let (hourly, clock) = (ui_inputs.3.lease(), ui_inputs.4.lease());
+                async move {
+                    let (Some(node), Some(live), Some(gh), Some(hourly), Some(clock)) = (node, live, gh, hourly, clock) else { return };
+                    ui.run(node, live, gh, hourly, clock).await;
+                }
+            }));
+
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running Tauri application");
+        .invoke_handler(tauri::generate_handler![
+            commands::get_recent_hourly,
+            commands::get_clock_adjustments,
+            commands::get_site_overview,
+            commands::get_pipeline_stats,
+            commands::get_decoder_stats,
+            commands::get_node_rates,
+            commands::reset_decoder_stats,
+            commands::generate_shift_report,
+            commands::get_vpd_kpi,
+            commands::get_drainage_analysis,
+            commands::get_export_history,
+            commands::get_gh_history,
+            commands::get_node_history,
+            commands::audit_database,
+            commands::convert_scaled_values,
+            commands::set_gh_origin_map,
+            commands::rebuild_gh_averages,
+            commands::cancel_gh_rebuild,
+            commands::get_gh_origin_map,
+            commands::set_node_schema,
+            commands::get_node_schema,
+            commands::get_subscription_info,
+            commands::get_mqtt_status,
+            commands::get_node_availability,
+            commands::publish_mqtt,
+            commands::set_mqtt_password,
+            commands::test_mqtt_connection,
+            commands::dump_raw_payloads,
+            commands::pause_ingestion,
+            commands::resume_ingestion,
+            commands::merge_nodes,
+            commands::deactivate_node,
+            commands::set_node_maintenance,
+            commands::get_node_maintenance,
+            commands::import_commissioning_sheet,
+            commands::discover_nodes,
+            commands::cancel_node_discovery,
+            commands::adopt_node,
+            commands::list_nodes,
+            commands::get_node_health_history,
+            commands::get_node_display_prefs,
+            commands::set_node_display_prefs,
+            commands::set_node_ack,
+            commands::verify_payload,
+            commands::get_dry_run_report,
+            commands::get_log_tail,
+            commands::set_log_stream_level,
+            commands::get_instance_status,
+            commands::focus_existing_instance,
+            commands::take_over_instance,
+            commands::restart_pipeline,
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while running Tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                if let Some(pipeline) = app.try_state::<Pipeline<MeteredSink<AppHandle>>>() { pipeline.close(); }
+                if let Some(shutdown) = app.try_state::<Shutdown>() { shutdown.stop(SHUTDOWN_TIMEOUT); }
+                if let Some(lock) = app.try_state::<InstanceLock>() { lock.release(); }
+            }
+        });
 }
@@ -0,0 +1,609 @@
+//! Pipeline channel capacities, sized from the expected site size.
+//! - Defaults scale with `EXPECTED_NODES` / `EXPECTED_GREENHOUSES`; the old fixed sizes are the floors.
+//! - Decoded must absorb a reconnect burst (one window of samples from every node at once);
+//!   per-window stages must hold one message per node/greenhouse per tick.
+//! - `log_sizing_report` prints rates vs capacities at startup and warns on obvious mismatches.
+//! - Stage outputs are `Lane`s with a drop policy. Ordering guarantee under load:
+//!     * `Critical` (GhAvg -> DB / hourly / KPI): not dropped under load; a full lane makes the greenhouse
+//!       aggregator wait for room, so the backlog moves upstream to NodeAvg. The wait is bounded
+//!       (`CRITICAL_SEND_TIMEOUT`): a consumer stalled that long costs the message (counted as
+//!       `timed_out`) rather than freezing the greenhouse aggregator and every lane it feeds.
+//!     * `Droppable` (NodeAvg, live UI): dropped when full, counted per lane (`get_pipeline_stats`).
+//!
+//!   A stalled consumer can therefore cost node averages but never a greenhouse average.
+//! - Decoded samples (subscriber -> node aggregator) go through a `BoundedQueue` with a `FullPolicy`
+//!   (`channel_config().decoded_policy`, or `APPTEST_DECODED_POLICY` = `drop_newest` / `drop_oldest` /
+//!   `block:<ms>` per deployment): drop the new sample (default), drop the oldest queued one, or
+//!   wait up to `timeout_ms` for room and then drop the new one. Waiting stalls the MQTT loop, so it
+//!   suits storage-critical sites with a broker-side buffer. Drops are counted (`get_pipeline_stats`)
+//!   and reported every 30 s while they grow (`run_queue_watch`, `decoded_backpressure` event).
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc::{self, error::TrySendError}, Notify};
+use tokio::time::{interval, timeout};
+use tracing::{info, warn};
+
+use super::presenter::emitter::EventSink;
+
+const EXPECTED_NODES: usize = 64;       // 50+ planned, incl. outdoor stations
+const EXPECTED_GREENHOUSES: usize = 8;
+const SAMPLES_PER_WINDOW: usize = 6;    // per node per 60s window (~10s publish period)
+const WINDOW_SECS: u64 = 60;
+/// A critical send waiting longer than this is logged (the consumer is falling behind).
+const SLOW_CRITICAL_SEND: Duration = Duration::from_secs(1);
+/// A critical send gives up after this long (the consumer is stalled, not slow); well under a window.
+const CRITICAL_SEND_TIMEOUT: Duration = Duration::from_secs(10);
+const QUEUE_WATCH_EVERY: Duration = Duration::from_secs(30);
+
+/// What a full `BoundedQueue` does with a new message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum FullPolicy {
+    /// Drop the new message (the queue keeps the older backlog).
+    DropNewest,
+    /// Drop the oldest queued message to make room (the queue keeps the freshest data).
+    DropOldest,
+    /// Wait up to `timeout_ms` for room, then drop the new message.
+    Block { timeout_ms: u64 },
+}
+
+impl FullPolicy {
+    /// `drop_newest`, `drop_oldest` or `block:<timeout ms>`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "drop_newest" => Some(FullPolicy::DropNewest),
+            "drop_oldest" => Some(FullPolicy::DropOldest),
+            other => other.strip_prefix("block:")
+                .and_then(|ms| ms.trim().parse().ok())
+                .map(|timeout_ms| FullPolicy::Block { timeout_ms }),
+        }
+    }
+}
+
+/// Full policy of the decoded queue: `APPTEST_DECODED_POLICY` when set and valid, else `channel_config()`.
+pub fn decoded_policy() -> FullPolicy {
+    let default = channel_config().decoded_policy;
+    match std::env::var("APPTEST_DECODED_POLICY") {
+        Ok(v) => FullPolicy::parse(&v).unwrap_or_else(|| {
+            warn!(target: "PIPE", "APPTEST_DECODED_POLICY '{v}' is not valid (drop_newest, drop_oldest, block:<ms>); using {default:?}");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct ChannelConfig {
+    pub expected_nodes: usize,
+    pub expected_greenhouses: usize,
+    pub samples_per_window: usize,
+    /// subscriber -> node aggregator
+    pub decoded: usize,
+    /// what the subscriber does when `decoded` is full
+    pub decoded_policy: FullPolicy,
+    /// node aggregator -> greenhouse aggregator / DB / UI (each)
+    pub nodeavg: usize,
+    /// greenhouse aggregator -> DB / UI / hourly (each)
+    pub ghavg: usize,
+    /// hourly aggregator -> DB / UI (each)
+    pub hourly: usize,
+    /// clock watch -> DB / UI (each); rare events
+    pub clock: usize,
+    /// subscriber connection status -> UI; rare events
+    pub mqtt_status: usize,
+    /// subscriber node availability changes -> UI / DB; a burst of retained statuses on connect
+    pub node_status: usize,
+}
+
+const fn at_least(floor: usize, v: usize) -> usize { if v > floor { v } else { floor } }
+
+pub const fn channel_config() -> ChannelConfig {
+    ChannelConfig {
+        expected_nodes: EXPECTED_NODES,
+        expected_greenhouses: EXPECTED_GREENHOUSES,
+        samples_per_window: SAMPLES_PER_WINDOW,
+        decoded: at_least(256, EXPECTED_NODES * SAMPLES_PER_WINDOW * 2),
+        decoded_policy: FullPolicy::DropNewest,
+        nodeavg: at_least(128, EXPECTED_NODES * 2),
+        ghavg: at_least(64, EXPECTED_GREENHOUSES * 4),
+        hourly: at_least(16, EXPECTED_GREENHOUSES * 2),
+        clock: 8,
+        mqtt_status: 16,
+        node_status: at_least(64, EXPECTED_NODES * 2),
+    }
+}
+
+/// Startup report: expected message rates vs capacities vs DB flush cadence.
+pub fn log_sizing_report(c: &ChannelConfig, flush_every: Duration, batch_size: usize) {
+    let burst = c.expected_nodes * c.samples_per_window;
+    info!(
+        target: "PIPE",
+        "sizing: nodes={} greenhouses={} | decoded {:.1}/s cap {} (burst {}) | nodeavg {}/{}s cap {} | ghavg {}/{}s cap {} | hourly cap {} | DB flush every {:?} or {} rows",
+        c.expected_nodes, c.expected_greenhouses,
+        burst as f64 / WINDOW_SECS as f64, c.decoded, burst,
+        c.expected_nodes, WINDOW_SECS, c.nodeavg,
+        c.expected_greenhouses, WINDOW_SECS, c.ghavg,
+        c.hourly, flush_every, batch_size,
+    );
+    let checks = [
+        (c.decoded < burst, format!("decoded capacity {} < nodes x samples per window ({burst}); reconnect bursts will drop", c.decoded)),
+        (c.nodeavg < c.expected_nodes, format!("nodeavg capacity {} < nodes ({}); a window tick can overflow it", c.nodeavg, c.expected_nodes)),
+        (c.ghavg < c.expected_greenhouses, format!("ghavg capacity {} < greenhouses ({})", c.ghavg, c.expected_greenhouses)),
+        (c.hourly < c.expected_greenhouses, format!("hourly capacity {} < greenhouses ({})", c.hourly, c.expected_greenhouses)),
+        (batch_size < c.expected_nodes + c.expected_greenhouses,
+         format!("DB batch size {batch_size} < one window of node+greenhouse rows; each window is split across several flushes")),
+    ];
+    for (bad, msg) in checks {
+        if bad { warn!(target: "PIPE", "WARN sizing: {msg}"); }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Dropped (and counted) when the lane is full.
+    Droppable,
+    /// Not dropped when full: the sender waits for room, up to `CRITICAL_SEND_TIMEOUT`.
+    Critical,
+}
+
+/// Per-lane counters since startup.
+#[derive(Debug)]
+pub struct LaneCounters {
+    pub name: &'static str,
+    pub priority: Priority,
+    pub capacity: usize,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    waited: AtomicU64,
+    timed_out: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LaneStat {
+    pub name: &'static str,
+    pub priority: Priority,
+    pub capacity: usize,
+    pub sent: u64,
+    /// Messages lost: full droppable lane, or consumer gone.
+    pub dropped: u64,
+    /// Critical sends that found the lane full and had to wait.
+    pub waited: u64,
+    /// Critical sends given up after `CRITICAL_SEND_TIMEOUT` (also in `dropped`).
+    pub timed_out: u64,
+}
+
+/// Every lane built by `lane`, for `get_pipeline_stats`.
+pub type LanesShared = Arc<RwLock<Vec<Arc<LaneCounters>>>>;
+
+pub fn lane_stats(lanes: &LanesShared) -> Vec<LaneStat> {
+    lanes.read().map(|v| v.iter().map(|c| LaneStat {
+        name: c.name, priority: c.priority, capacity: c.capacity,
+        sent: c.sent.load(Ordering::Relaxed),
+        dropped: c.dropped.load(Ordering::Relaxed),
+        waited: c.waited.load(Ordering::Relaxed),
+        timed_out: c.timed_out.load(Ordering::Relaxed),
+    }).collect()).unwrap_or_default()
+}
+
+/// Sending half of a pipeline channel with its drop policy.
+#[derive(Debug)]
+pub struct Lane<T> {
+    tx: mpsc::Sender<T>,
+    counters: Arc<LaneCounters>,
+    critical_timeout: Duration,
+}
+
+impl<T> Clone for Lane<T> {
+    fn clone(&self) -> Self { Lane { tx: self.tx.clone(), counters: self.counters.clone(), critical_timeout: self.critical_timeout } }
+}
+
+/// Build a lane of `capacity` and register its counters in `lanes`. A lane built again under a name
+/// already registered (the data path after `restart_pipeline`) keeps counting on the same counters.
+pub fn lane<T>(lanes: &LanesShared, name: &'static str, priority: Priority, capacity: usize) -> (Lane<T>, mpsc::Receiver<T>) {
+    let (tx, rx) = mpsc::channel(capacity);
+    let fresh = || Arc::new(LaneCounters {
+        name, priority, capacity,
+        sent: AtomicU64::new(0), dropped: AtomicU64::new(0), waited: AtomicU64::new(0), timed_out: AtomicU64::new(0),
+    });
+    let counters = match lanes.write() {
+        Ok(mut v) => match v.iter().find(|c| c.name == name) {
+            Some(c) => c.clone(),
+            None => {
+                v.push(fresh());
+                v[v.len() - 1].clone()
+            }
+        },
+        Err(_) => fresh(),
+    };
+    (Lane { tx, counters, critical_timeout: CRITICAL_SEND_TIMEOUT }, rx)
+}
+
+impl<T> Lane<T> {
+    /// Send per the lane's policy; only a full `Critical` lane awaits, at most `CRITICAL_SEND_TIMEOUT`.
+    pub async fn send(&self, msg: T) {
+        let c = &self.counters;
+        match self.tx.try_send(msg) {
+            Ok(()) => { c.sent.fetch_add(1, Ordering::Relaxed); }
+            Err(TrySendError::Closed(_)) => { c.dropped.fetch_add(1, Ordering::Relaxed); }
+            Err(TrySendError::Full(_)) if c.priority == Priority::Droppable => { c.dropped.fetch_add(1, Ordering::Relaxed); }
+            Err(TrySendError::Full(msg)) => {
+                c.waited.fetch_add(1, Ordering::Relaxed);
+                let started = Instant::now();
+                let counter = match timeout(self.critical_timeout, self.tx.send(msg)).await {
+                    Ok(Ok(())) => &c.sent,
+                    Ok(Err(_)) => &c.dropped,
+                    Err(_) => {
+                        c.timed_out.fetch_add(1, Ordering::Relaxed);
+                        warn!(target: "PIPE", "critical lane '{}' full for {:?}; message dropped, consumer is stalled", c.name, self.critical_timeout);
+                        &c.dropped
+                    }
+                };
+                counter.fetch_add(1, Ordering::Relaxed);
+                let waited = started.elapsed();
+                if waited > SLOW_CRITICAL_SEND && waited < self.critical_timeout {
+                    warn!(target: "PIPE", "critical lane '{}' full for {waited:?}; consumer is falling behind", c.name);
+                }
+            }
+        }
+    }
+}
+
+/// Counters of a `BoundedQueue` since startup.
+#[derive(Debug)]
+pub struct QueueCounters {
+    pub name: &'static str,
+    pub policy: FullPolicy,
+    pub capacity: usize,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    blocked: AtomicU64,
+}
+
+pub type QueueCountersShared = Arc<QueueCounters>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueStat {
+    pub name: &'static str,
+    pub policy: FullPolicy,
+    pub capacity: usize,
+    /// Messages that entered the queue.
+    pub sent: u64,
+    /// Messages lost: the new one (`drop_newest`, `block` timeout, consumer gone) or the oldest queued one
+    /// (`drop_oldest`).
+    pub dropped: u64,
+    /// `block` sends that found the queue full and had to wait.
+    pub blocked: u64,
+}
+
+impl QueueCounters {
+    pub fn stat(&self) -> QueueStat {
+        QueueStat {
+            name: self.name, policy: self.policy, capacity: self.capacity,
+            sent: self.sent.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            blocked: self.blocked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct QueueInner<T> {
+    buf: Mutex<VecDeque<T>>,
+    /// Signalled after a push.
+    items: Notify,
+    /// Signalled after a pop.
+    space: Notify,
+    sender_gone: AtomicBool,
+    receiver_gone: AtomicBool,
+    counters: QueueCountersShared,
+}
+
+/// Sending half of a `BoundedQueue`; one producer.
+pub struct QueueSender<T> {
+    inner: Arc<QueueInner<T>>,
+}
+
+/// Receiving half of a `BoundedQueue`.
+pub struct QueueReceiver<T> {
+    inner: Arc<QueueInner<T>>,
+}
+
+/// Bounded single-producer queue whose full behaviour is `policy` (an mpsc channel cannot drop its
+/// oldest message from the sending side).
+pub fn bounded_queue<T>(name: &'static str, capacity: usize, policy: FullPolicy) -> (QueueSender<T>, QueueReceiver<T>) {
+    requeue(&queue_counters(name, capacity, policy))
+}
+
+/// Zeroed counters for queues built with `requeue`.
+pub fn queue_counters(name: &'static str, capacity: usize, policy: FullPolicy) -> QueueCountersShared {
+    Arc::new(QueueCounters {
+        name, policy, capacity,
+        sent: AtomicU64::new(0), dropped: AtomicU64::new(0), blocked: AtomicU64::new(0),
+    })
+}
+
+/// A new, empty queue with the name, capacity and policy of `counters`, counting on them (the decoded
+/// queue after `restart_pipeline`).
+pub fn requeue<T>(counters: &QueueCountersShared) -> (QueueSender<T>, QueueReceiver<T>) {
+    let inner = Arc::new(QueueInner {
+        buf: Mutex::new(VecDeque::with_capacity(counters.capacity)),
+        items: Notify::new(),
+        space: Notify::new(),
+        sender_gone: AtomicBool::new(false),
+        receiver_gone: AtomicBool::new(false),
+        counters: counters.clone(),
+    });
+    (QueueSender { inner: inner.clone() }, QueueReceiver { inner })
+}
+
+impl<T> QueueInner<T> {
+    /// Push when there is room (or make room under `DropOldest`); gives `msg` back when full.
+    fn try_push(&self, msg: T) -> Result<(), T> {
+        let Ok(mut buf) = self.buf.lock() else { return Err(msg) };
+        if buf.len() >= self.counters.capacity {
+            if self.counters.policy != FullPolicy::DropOldest { return Err(msg); }
+            buf.pop_front();
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        buf.push_back(msg);
+        drop(buf);
+        self.counters.sent.fetch_add(1, Ordering::Relaxed);
+        self.items.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> QueueSender<T> {
+    /// Queue `msg` per the policy; awaits only under `Block`, and at most `timeout_ms`.
+    pub async fn send(&self, msg: T) {
+        let c = &self.inner.counters;
+        if self.inner.receiver_gone.load(Ordering::Relaxed) {
+            c.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let Err(msg) = self.inner.try_push(msg) else { return };
+        let FullPolicy::Block { timeout_ms } = c.policy else {
+            c.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        c.blocked.fetch_add(1, Ordering::Relaxed);
+        let mut pending = Some(msg);
+        let wait = async {
+            while let Some(m) = pending.take() {
+                if let Err(m) = self.inner.try_push(m) {
+                    pending = Some(m);
+                    self.inner.space.notified().await;
+                }
+            }
+        };
+        if timeout(Duration::from_millis(timeout_ms), wait).await.is_err() {
+            c.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn counters(&self) -> QueueCountersShared {
+        self.inner.counters.clone()
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        self.inner.sender_gone.store(true, Ordering::Relaxed);
+        self.inner.items.notify_one();
+    }
+}
+
+impl<T> QueueReceiver<T> {
+    /// Next message, oldest first; None once the sender is gone and the queue is empty.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            let popped = self.inner.buf.lock().ok().and_then(|mut b| b.pop_front());
+            if let Some(msg) = popped {
+                self.inner.space.notify_one();
+                return Some(msg);
+            }
+            if self.inner.sender_gone.load(Ordering::Relaxed) { return None; }
+            self.inner.items.notified().await;
+        }
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    fn drop(&mut self) {
+        self.inner.receiver_gone.store(true, Ordering::Relaxed);
+        self.inner.space.notify_one();
+    }
+}
+
+/// Every 30 s while the queue keeps dropping: a warning and a `{name}_backpressure` event with its stat.
+pub async fn run_queue_watch<S: EventSink>(sink: S, counters: QueueCountersShared) {
+    let mut tick = interval(QUEUE_WATCH_EVERY);
+    let mut last_dropped = 0;
+    loop {
+        tick.tick().await;
+        let stat = counters.stat();
+        if stat.dropped == last_dropped { continue; }
+        warn!(
+            target: "PIPE", "queue '{}' ({:?}, cap {}) dropped {} message(s) in the last {}s, {} since startup",
+            stat.name, stat.policy, stat.capacity, stat.dropped - last_dropped, QUEUE_WATCH_EVERY.as_secs(), stat.dropped
+        );
+        last_dropped = stat.dropped;
+        if let Ok(v) = serde_json::to_value(&stat) { sink.emit_json(&format!("{}_backpressure", stat.name), v); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(lanes: &LanesShared, name: &str) -> LaneStat {
+        lane_stats(lanes).into_iter().find(|s| s.name == name).unwrap()
+    }
+
+    #[tokio::test]
+    async fn flood_drops_node_averages_and_no_greenhouse_average() {
+        let lanes = LanesShared::default();
+        let (nodes, mut node_rx) = lane::<u32>(&lanes, "nodeavg", Priority::Droppable, 8);
+        let (ghs, mut gh_rx) = lane::<u32>(&lanes, "ghavg", Priority::Critical, 4);
+        // a slow consumer of both lanes, like the DB writer behind a slow disk
+        let consumer = tokio::spawn(async move {
+            let (mut node_n, mut gh_n) = (0, 0);
+            loop {
+                tokio::select! {
+                    Some(_) = node_rx.recv() => node_n += 1,
+                    Some(_) = gh_rx.recv() => gh_n += 1,
+                    else => break,
+                }
+                tokio::time::sleep(Duration::from_micros(200)).await;
+            }
+            (node_n, gh_n)
+        });
+        // 200 windows of 13 nodes and their greenhouse
+        for w in 0..200 {
+            for n in 0..13 { nodes.send(w * 13 + n).await; }
+            ghs.send(w).await;
+        }
+        drop((nodes, ghs));
+        let (node_n, gh_n) = consumer.await.unwrap();
+
+        let gh = stat(&lanes, "ghavg");
+        assert_eq!((gh_n, gh.sent, gh.dropped, gh.timed_out), (200, 200, 0, 0));
+        assert!(gh.waited > 0, "the flood never filled the greenhouse lane");
+        let node = stat(&lanes, "nodeavg");
+        assert!(node.dropped > 0, "{node:?}");
+        assert_eq!((node.sent + node.dropped, node.sent), (2600, node_n));
+    }
+
+    #[tokio::test]
+    async fn critical_send_gives_up_on_a_stalled_consumer() {
+        let lanes = LanesShared::default();
+        let (mut ghs, mut rx) = lane::<u32>(&lanes, "ghavg", Priority::Critical, 1);
+        ghs.critical_timeout = Duration::from_millis(50);
+        ghs.send(1).await;
+        let started = Instant::now();
+        ghs.send(2).await;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        let s = stat(&lanes, "ghavg");
+        assert_eq!((s.sent, s.waited, s.timed_out, s.dropped), (1, 1, 1, 1));
+
+        // a consumer that catches up within the timeout loses nothing
+        let reader = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let mut got = Vec::new();
+            while let Some(v) = rx.recv().await { got.push(v); }
+            got
+        });
+        ghs.send(3).await;
+        drop(ghs);
+        assert_eq!(reader.await.unwrap(), [1, 3]);
+        let s = stat(&lanes, "ghavg");
+        assert_eq!((s.sent, s.waited, s.timed_out), (2, 2, 1));
+    }
+
+    async fn filled(policy: FullPolicy) -> (QueueSender<u32>, QueueReceiver<u32>) {
+        let (tx, rx) = bounded_queue("decoded", 4, policy);
+        for v in 0..4 { tx.send(v).await; }
+        (tx, rx)
+    }
+
+    /// Everything queued once the sender is gone.
+    async fn rest(tx: QueueSender<u32>, mut rx: QueueReceiver<u32>) -> Vec<u32> {
+        drop(tx);
+        let mut got = Vec::new();
+        while let Some(v) = rx.recv().await { got.push(v); }
+        got
+    }
+
+    fn counts(tx: &QueueSender<u32>) -> (u64, u64, u64) {
+        let s = tx.counters().stat();
+        (s.sent, s.dropped, s.blocked)
+    }
+
+    #[tokio::test]
+    async fn drop_newest_keeps_the_backlog() {
+        let (tx, rx) = filled(FullPolicy::DropNewest).await;
+        for v in 4..7 { tx.send(v).await; }
+        assert_eq!(counts(&tx), (4, 3, 0));
+        assert_eq!(rest(tx, rx).await, [0, 1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_keeps_the_freshest() {
+        let (tx, rx) = filled(FullPolicy::DropOldest).await;
+        for v in 4..7 { tx.send(v).await; }
+        assert_eq!(counts(&tx), (7, 3, 0));
+        assert_eq!(rest(tx, rx).await, [3, 4, 5, 6]);
+    }
+
+    #[tokio::test]
+    async fn block_gives_up_after_its_timeout() {
+        let (tx, rx) = filled(FullPolicy::Block { timeout_ms: 50 }).await;
+        let started = Instant::now();
+        tx.send(4).await;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(counts(&tx), (4, 1, 1));
+        assert_eq!(rest(tx, rx).await, [0, 1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn block_loses_nothing_when_the_reader_frees_room_in_time() {
+        let (tx, mut rx) = filled(FullPolicy::Block { timeout_ms: 1_000 }).await;
+        let reader = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let mut got = Vec::new();
+            while let Some(v) = rx.recv().await { got.push(v); }
+            got
+        });
+        for v in 4..8 { tx.send(v).await; }
+        assert_eq!(counts(&tx).1, 0);
+        assert!(counts(&tx).2 >= 1, "the queue was never full");
+        drop(tx);
+        assert_eq!(reader.await.unwrap(), (0..8).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn sends_after_the_receiver_is_gone_are_dropped_without_waiting() {
+        let (tx, rx) = filled(FullPolicy::Block { timeout_ms: 60_000 }).await;
+        drop(rx);
+        let started = Instant::now();
+        tx.send(4).await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(counts(&tx), (4, 1, 0));
+    }
+
+    #[tokio::test]
+    async fn rebuilt_channels_keep_counting_on_the_old_counters() {
+        let lanes = LanesShared::default();
+        let (first, rx) = lane::<u32>(&lanes, "nodeavg_gh", Priority::Droppable, 1);
+        first.send(1).await;
+        first.send(2).await;
+        drop((first, rx));
+        let (second, _rx) = lane::<u32>(&lanes, "nodeavg_gh", Priority::Droppable, 1);
+        second.send(3).await;
+        assert_eq!(lane_stats(&lanes).len(), 1);
+        let s = stat(&lanes, "nodeavg_gh");
+        assert_eq!((s.sent, s.dropped), (2, 1));
+
+        let (tx, rx) = filled(FullPolicy::DropNewest).await;
+        let counters = tx.counters();
+        drop(rx);
+        let (tx, rx) = requeue::<u32>(&counters);
+        tx.send(9).await;
+        assert_eq!(counts(&tx), (5, 0, 0));
+        assert_eq!(rest(tx, rx).await, [9], "the old backlog stays with the old queue");
+    }
+
+    #[test]
+    fn policy_parses_from_the_environment_form() {
+        assert_eq!(FullPolicy::parse("drop_newest"), Some(FullPolicy::DropNewest));
+        assert_eq!(FullPolicy::parse(" Drop_Oldest "), Some(FullPolicy::DropOldest));
+        assert_eq!(FullPolicy::parse("block:100"), Some(FullPolicy::Block { timeout_ms: 100 }));
+        assert_eq!(FullPolicy::parse("block: 250"), Some(FullPolicy::Block { timeout_ms: 250 }));
+        for bad in ["", "block", "block:-1", "block:soon", "drop"] {
+            assert_eq!(FullPolicy::parse(bad), None, "{bad}");
+        }
+    }
+}
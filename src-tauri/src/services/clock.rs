@@ -0,0 +1,140 @@
+//! Wall-clock discontinuity detection (NTP corrections, a wrong clock at boot).
+//! - Every `CHECK_EVERY` compares wall-clock elapsed with elapsed time on a clock that keeps counting
+//!   through suspend (`boot_ms`); a difference above `JUMP_THRESHOLD_MS` is a clock adjustment, while
+//!   a laptop waking up moves both by the same amount and is not.
+//! - Aggregates stamped in the ambiguous interval mixed samples from both clocks. Two spans are
+//!   quarantined: on the old clock, from the last good check to the jump (`before_from_ms..before_to_ms`),
+//!   and on the new clock from just before detection to one full window after it (`from_ms..to_ms`).
+//!   Storage records both and flags every aggregate stamped inside them (`quarantined`), so history
+//!   and gap views need not trust their timestamps.
+//! - Windows run on the monotonic clock, so they are unaffected; hour buckets only move forward, so
+//!   a clock set back never reopens an hour that was already closed.
+
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::time::interval;
+use tracing::warn;
+
+use crate::services::mqtt::greenhouse_sensor::aggregator::current_window;
+use crate::services::mqtt::greenhouse_sensor::greenhouse_aggregator::stale_grace;
+
+const CHECK_EVERY: Duration = Duration::from_secs(5);
+const JUMP_THRESHOLD_MS: i64 = 5_000;
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+/// Milliseconds on a clock that is never set and keeps running while the machine sleeps
+/// (CLOCK_BOOTTIME on Linux; CLOCK_MONOTONIC counts sleep on macOS; elsewhere the std monotonic clock).
+fn boot_ms() -> i64 {
+    #[cfg(unix)]
+    {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        const CLOCK: libc::clockid_t = libc::CLOCK_BOOTTIME;
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        const CLOCK: libc::clockid_t = libc::CLOCK_MONOTONIC;
+        let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        // SAFETY: `ts` is a valid, writable timespec for the duration of the call
+        if unsafe { libc::clock_gettime(CLOCK, &mut ts) } == 0 {
+            #[allow(clippy::unnecessary_cast)] // time_t / c_long are 32-bit on some targets
+            return ts.tv_sec as i64 * 1000 + ts.tv_nsec as i64 / 1_000_000;
+        }
+    }
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START.get_or_init(std::time::Instant::now).elapsed().as_millis() as i64
+}
+
+/// One detected jump. `from_ms..to_ms` (new clock) and `before_from_ms..before_to_ms` (old clock)
+/// are the quarantined spans.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ClockAdjustment {
+    pub detected_ms: i64,
+    pub delta_ms: i64, // positive = clock moved forward
+    pub from_ms: i64,
+    pub to_ms: i64,
+    pub before_from_ms: i64,
+    pub before_to_ms: i64,
+}
+
+/// The adjustment between two (wall, boot) readings, if the wall clock moved apart from elapsed time.
+/// `window_ms`: how long after the jump aggregates can still hold samples from before it.
+fn detect(last: (i64, i64), now: (i64, i64), window_ms: i64) -> Option<ClockAdjustment> {
+    let ((last_wall, last_boot), (wall, boot)) = (last, now);
+    let elapsed = boot - last_boot;
+    let delta_ms = (wall - last_wall) - elapsed;
+    (delta_ms.abs() >= JUMP_THRESHOLD_MS).then_some(ClockAdjustment {
+        detected_ms: wall,
+        delta_ms,
+        from_ms: wall - elapsed,
+        to_ms: wall + window_ms,
+        before_from_ms: last_wall,
+        before_to_ms: last_wall + elapsed,
+    })
+}
+
+/// Public task:
+/// - tx_db: adjustments to persist (storage keeps the quarantine intervals and flags their rows)
+/// - tx_ui: `clock_adjusted` events
+pub async fn run_clock_watch(tx_db: mpsc::Sender<ClockAdjustment>, tx_ui: mpsc::Sender<ClockAdjustment>) {
+    let window = current_window();
+    let window_ms = (window + stale_grace(window)).as_millis() as i64; // one aggregation window plus the greenhouse stale grace
+    let mut tick = interval(CHECK_EVERY);
+    let mut last = (now_ms(), boot_ms());
+
+    loop {
+        tick.tick().await;
+        let now = (now_ms(), boot_ms());
+        let jump = detect(last, now, window_ms);
+        last = now;
+        let Some(adj) = jump else { continue };
+
+        warn!(
+            target: "CLOCK",
+            "wall clock jumped {:+.1}s; aggregates stamped {}..{} (old clock) and {}..{} are quarantined",
+            adj.delta_ms as f64 / 1000.0, adj.before_from_ms, adj.before_to_ms, adj.from_ms, adj.to_ms
+        );
+        let _ = tx_db.try_send(adj);
+        let _ = tx_ui.try_send(adj);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW_MS: i64 = 62_500;
+
+    #[test]
+    fn forward_jump_quarantines_both_sides() {
+        let last = (1_000_000, 50_000);
+        let adj = detect(last, (1_000_000 + 5_000 + 6 * 3_600_000, 55_000), WINDOW_MS).unwrap();
+        assert_eq!(adj.delta_ms, 6 * 3_600_000);
+        assert_eq!((adj.before_from_ms, adj.before_to_ms), (1_000_000, 1_005_000));
+        assert_eq!(adj.from_ms, adj.detected_ms - 5_000);
+        assert_eq!(adj.to_ms, adj.detected_ms + WINDOW_MS);
+    }
+
+    #[test]
+    fn backward_jump_is_negative() {
+        let adj = detect((10_000_000, 0), (10_000_000 + 5_000 - 600_000, 5_000), WINDOW_MS).unwrap();
+        assert_eq!(adj.delta_ms, -600_000);
+        assert!(adj.from_ms < adj.before_from_ms);
+    }
+
+    #[test]
+    fn suspend_and_drift_are_not_jumps() {
+        // eight hours asleep: wall and boot clocks both moved on
+        assert!(detect((1_000_000, 50_000), (1_000_000 + 8 * 3_600_000, 50_000 + 8 * 3_600_000), WINDOW_MS).is_none());
+        // NTP slewing a few hundred ms
+        assert!(detect((1_000_000, 50_000), (1_005_300, 55_000), WINDOW_MS).is_none());
+    }
+
+    #[test]
+    fn boot_clock_moves_forward() {
+        let a = boot_ms();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(boot_ms() - a >= 15);
+    }
+}
@@ -0,0 +1,259 @@
+//! Single-instance guard for the pipeline (one writer per database).
+//! - `<db>.lock` is created exclusively at startup and holds {pid, nonce, started_ms, heartbeat_ms};
+//!   the random nonce tells this run's lock from an older one with a reused pid.
+//! - The holder refreshes the heartbeat every `HEARTBEAT`, after re-reading the file: when it no longer
+//!   holds its pid and nonce (broken while the holder was suspended or hung) the holder steps down and
+//!   exits rather than keep a second pipeline writing.
+//! - A lock is broken when its pid is no longer running, or when its heartbeat is older than
+//!   `STALE_AFTER_MS` and the pid cannot be checked; a running pid keeps it for `HUNG_AFTER_MS`
+//!   (hung holder, or the pid reused by another program).
+//! - A second instance never starts its pipeline. It can ask the holder, through `<db>.lock.request`,
+//!   to focus its window or to release the lock and exit (take over).
+
+use serde::{Deserialize, Serialize};
+use std::{fs, io::Write, path::{Path, PathBuf}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use tauri::{AppHandle, Manager};
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+const HEARTBEAT: Duration = Duration::from_secs(5);
+const STALE_AFTER_MS: i64 = 20_000;
+const HUNG_AFTER_MS: i64 = 120_000;
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockInfo {
+    pub pid: u32,
+    /// Random per run; 0 in locks written before it existed.
+    #[serde(default)]
+    pub nonce: u64,
+    pub started_ms: i64,
+    pub heartbeat_ms: i64,
+}
+
+/// What a second instance asks the lock holder to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockRequest {
+    Focus,
+    TakeOver,
+}
+
+/// Startup outcome, read by the frontend through `get_instance_status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct InstanceStatus {
+    pub pid: u32,
+    /// Some when another live instance holds the lock (this one is not running a pipeline).
+    pub conflict: Option<LockInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct InstanceLock {
+    path: PathBuf,
+    info: LockInfo,
+}
+
+fn lock_path(db_abs: &Path) -> PathBuf { PathBuf::from(format!("{}.lock", db_abs.display())) }
+fn request_path(db_abs: &Path) -> PathBuf { PathBuf::from(format!("{}.lock.request", db_abs.display())) }
+
+fn read_info(path: &Path) -> Option<LockInfo> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+/// Whether `pid` is a running process; None where that cannot be checked.
+fn pid_alive(pid: u32) -> Option<bool> {
+    #[cfg(target_os = "linux")]
+    { Some(Path::new("/proc").join(pid.to_string()).exists()) }
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        std::process::Command::new("ps").args(["-p", &pid.to_string()])
+            .stdout(std::process::Stdio::null()).stderr(std::process::Stdio::null())
+            .status().ok().map(|s| s.success())
+    }
+    #[cfg(windows)]
+    {
+        let out = std::process::Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {pid}"), "/NH", "/FO", "CSV"]).output().ok()?;
+        Some(String::from_utf8_lossy(&out.stdout).contains(&format!("\"{pid}\"")))
+    }
+    #[cfg(not(any(unix, windows)))]
+    { let _ = pid; None }
+}
+
+/// Whether the lock `held` still belongs to a running instance at `now`.
+fn holder_alive(held: &LockInfo, now: i64) -> bool {
+    let silent = now - held.heartbeat_ms;
+    if held.pid == std::process::id() { return false; } // not ours yet: a previous run's pid, reused
+    match pid_alive(held.pid) {
+        Some(false) => false,
+        Some(true) => silent <= HUNG_AFTER_MS,
+        None => silent <= STALE_AFTER_MS,
+    }
+}
+
+/// Current holder of the lock next to `db_abs`, if it is alive.
+pub fn live_holder(db_abs: &Path) -> Option<LockInfo> {
+    read_info(&lock_path(db_abs)).filter(|i| holder_alive(i, now_ms()))
+}
+
+impl InstanceLock {
+    /// Take the lock, breaking it if its holder stopped heartbeating. Err carries the live holder.
+    pub fn acquire(db_abs: &Path) -> Result<Self, LockInfo> {
+        let path = lock_path(db_abs);
+        let now = now_ms();
+        let info = LockInfo { pid: std::process::id(), nonce: fastrand::u64(1..), started_ms: now, heartbeat_ms: now };
+        for _ in 0..2 {
+            match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut f) => {
+                    let _ = f.write_all(serde_json::to_string(&info).unwrap_or_default().as_bytes());
+                    let _ = fs::remove_file(request_path(db_abs)); // leftover from an earlier conflict
+                    info!(target: "LOCK", "acquired {} (pid {})", path.display(), info.pid);
+                    return Ok(Self { path, info });
+                }
+                Err(_) => match read_info(&path) {
+                    Some(held) if holder_alive(&held, now) => return Err(held),
+                    stale => {
+                        warn!(target: "LOCK", "breaking stale lock {} (pid {:?})", path.display(), stale.map(|i| i.pid));
+                        let _ = fs::remove_file(&path);
+                    }
+                },
+            }
+        }
+        // Lost a race with another instance breaking the same stale lock.
+        Err(read_info(&path).unwrap_or(info))
+    }
+
+    fn is_ours(&self, held: &LockInfo) -> bool {
+        held.pid == self.info.pid && held.nonce == self.info.nonce
+    }
+
+    /// Refresh the heartbeat; false once the lock file is no longer ours (the holder must step down).
+    fn heartbeat(&mut self) -> bool {
+        match read_info(&self.path) {
+            Some(held) if self.is_ours(&held) => {}
+            other => {
+                error!(target: "LOCK", "{} is no longer ours (now pid {:?}); stepping down", self.path.display(), other.map(|i| i.pid));
+                return false;
+            }
+        }
+        self.info.heartbeat_ms = now_ms();
+        let tmp = PathBuf::from(format!("{}.tmp", self.path.display()));
+        let ok = fs::write(&tmp, serde_json::to_string(&self.info).unwrap_or_default()).is_ok()
+            && fs::rename(&tmp, &self.path).is_ok();
+        if !ok { warn!(target: "LOCK", "heartbeat write failed for {}", self.path.display()); }
+        true
+    }
+
+    /// Remove the lock file if it is still ours.
+    pub fn release(&self) {
+        if read_info(&self.path).is_some_and(|i| self.is_ours(&i)) {
+            let _ = fs::remove_file(&self.path);
+            info!(target: "LOCK", "released {}", self.path.display());
+        }
+    }
+}
+
+/// Second instance: leave a request for the lock holder.
+pub fn send_request(db_abs: &Path, req: LockRequest) -> Result<(), String> {
+    let body = serde_json::to_string(&req).map_err(|e| e.to_string())?;
+    fs::write(request_path(db_abs), body).map_err(|e| format!("could not reach running instance: {e}"))
+}
+
+fn take_request(db_abs: &Path) -> Option<LockRequest> {
+    let path = request_path(db_abs);
+    let req = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok();
+    let _ = fs::remove_file(&path);
+    req
+}
+
+/// Lock holder task: heartbeat and answer requests from a second instance.
+pub async fn run_instance_heartbeat(mut lock: InstanceLock, db_abs: PathBuf, app: AppHandle) {
+    let mut tick = interval(HEARTBEAT);
+    loop {
+        tick.tick().await;
+        if !lock.heartbeat() {
+            app.exit(0);
+            return;
+        }
+        match take_request(&db_abs) {
+            Some(LockRequest::Focus) => {
+                if let Some(w) = app.get_webview_window("main") {
+                    let _ = w.unminimize();
+                    let _ = w.show();
+                    let _ = w.set_focus();
+                }
+            }
+            Some(LockRequest::TakeOver) => {
+                info!(target: "LOCK", "another instance is taking over; releasing and exiting");
+                lock.release();
+                app.exit(0);
+                return;
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_lock(db: &Path, pid: u32, age_ms: i64) {
+        let at = now_ms() - age_ms;
+        let info = LockInfo { pid, nonce: 7, started_ms: at, heartbeat_ms: at };
+        fs::write(lock_path(db), serde_json::to_string(&info).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn second_acquire_sees_the_holder() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let mut lock = InstanceLock::acquire(&db).unwrap();
+        assert!(lock.heartbeat());
+        let held = read_info(&lock_path(&db)).unwrap();
+        assert_eq!((held.pid, held.nonce), (lock.info.pid, lock.info.nonce));
+        lock.release();
+        assert!(!lock_path(&db).exists());
+    }
+
+    #[test]
+    fn holder_steps_down_when_the_lock_is_taken() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let mut lock = InstanceLock::acquire(&db).unwrap();
+        // broken and re-taken by another instance while this one was suspended
+        write_lock(&db, lock.info.pid, 0);
+        assert!(!lock.heartbeat());
+        lock.release();
+        assert_eq!(read_info(&lock_path(&db)).unwrap().nonce, 7, "someone else's lock is left alone");
+        fs::remove_file(lock_path(&db)).unwrap();
+        assert!(!lock.heartbeat());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn stale_detection_checks_the_pid() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let dead = u32::MAX - 1;
+        assert_eq!(pid_alive(dead), Some(false));
+        write_lock(&db, dead, 0);
+        assert!(live_holder(&db).is_none());
+        InstanceLock::acquire(&db).unwrap().release();
+
+        // pid 1 always runs: a silent holder keeps the lock until it counts as hung
+        write_lock(&db, 1, STALE_AFTER_MS * 2);
+        assert_eq!(InstanceLock::acquire(&db).unwrap_err().pid, 1);
+        write_lock(&db, 1, HUNG_AFTER_MS + 1_000);
+        let lock = InstanceLock::acquire(&db).unwrap();
+        assert_eq!(read_info(&lock_path(&db)).unwrap().pid, std::process::id());
+        lock.release();
+
+        // a lock left with our own pid is from an earlier run
+        write_lock(&db, std::process::id(), 0);
+        InstanceLock::acquire(&db).unwrap().release();
+    }
+}
@@ -0,0 +1,301 @@
+//! In-app log tail for technicians without console access on the kiosk PC.
+//! - `LogTail` is a `tracing_subscriber` layer on the global registry, under `log_tail_config().level`:
+//!   every event is printed as `[TARGET] message` on stdout (stderr for warn / error), like the println
+//!   lines it replaced, and kept in a bounded ring. The registry keeps real span ids for any other layer.
+//! - Secrets are replaced with `***` before the event is printed, buffered or streamed: fields with a
+//!   secret-looking name, and `name=value` / `name: value` / `"name": "value"` pairs in the message. Names
+//!   are matched per word (`db_password`, `authToken`), so `author` or `tokens_left` stay readable.
+//! - `get_log_tail(level, limit)` reads the ring; new events at or above the stream level
+//!   (`set_log_stream_level`, default info) go out live as `log_event`.
+
+use serde::Serialize;
+use std::{collections::{BTreeMap, VecDeque}, fmt, sync::{atomic::{AtomicU64, AtomicU8, Ordering}, Mutex, OnceLock}};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tracing::{field::{Field, Visit}, level_filters::LevelFilter, Event, Level, Subscriber};
+use tracing_subscriber::{layer::{Context, SubscriberExt}, Layer};
+
+#[derive(Debug, Clone, Copy)]
+pub struct LogTailConfig {
+    /// Most verbose level recorded at all (printed, buffered, streamable).
+    pub level: Level,
+    /// Events kept for `get_log_tail` (clamped to 100..=20_000).
+    pub capacity: usize,
+    /// Live events queued for the webview; extra events are dropped from the stream, not the ring.
+    pub stream_queue: usize,
+}
+
+pub const fn log_tail_config() -> LogTailConfig {
+    LogTailConfig {
+        level: Level::INFO,
+        capacity: 2_000,
+        stream_queue: 256,
+    }
+}
+
+/// Words of field / parameter names whose values never leave the process.
+const SECRET_WORDS: &[&str] = &[
+    "password", "passwd", "pwd", "secret", "token", "auth", "authorization", "apikey", "hmac", "credential", "credentials",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEvent {
+    pub seq: u64,
+    pub ts_ms: i64,
+    pub level: &'static str,
+    pub target: String,
+    pub message: String,
+    pub fields: BTreeMap<String, String>,
+    #[serde(skip)]
+    rank: u8,
+}
+
+struct Ring {
+    events: VecDeque<LogEvent>,
+    capacity: usize,
+}
+
+static RING: OnceLock<Mutex<Ring>> = OnceLock::new();
+static STREAM: OnceLock<mpsc::Sender<LogEvent>> = OnceLock::new();
+static STREAM_LEVEL: AtomicU8 = AtomicU8::new(3); // rank of INFO
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// 1 = error … 5 = trace (lower is more severe).
+fn rank(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 1,
+        Level::WARN => 2,
+        Level::INFO => 3,
+        Level::DEBUG => 4,
+        Level::TRACE => 5,
+    }
+}
+
+fn level_name(level: &Level) -> &'static str {
+    match *level {
+        Level::ERROR => "error",
+        Level::WARN => "warn",
+        Level::INFO => "info",
+        Level::DEBUG => "debug",
+        Level::TRACE => "trace",
+    }
+}
+
+/// Level from "error" / "warn" / "info" / "debug" / "trace" (any case).
+pub fn parse_level(s: &str) -> Result<Level, String> {
+    s.parse::<Level>().map_err(|_| format!("unknown log level '{s}' (error, warn, info, debug, trace)"))
+}
+
+/// Whether a name has a secret word in it: split on `_` `-` `.` and camel case, so `db_password`,
+/// `x-auth-token` and `apiKey` match but `author` does not.
+fn is_secret(name: &str) -> bool {
+    let mut words: Vec<String> = vec![String::new()];
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if matches!(c, '_' | '-' | '.') {
+            words.push(String::new());
+        } else {
+            if c.is_uppercase() && prev_lower { words.push(String::new()); }
+            words.last_mut().unwrap().extend(c.to_lowercase());
+        }
+        prev_lower = c.is_lowercase() || c.is_ascii_digit();
+    }
+    let joined = words.concat();
+    let secret = words.iter().chain([&joined]).any(|w| SECRET_WORDS.contains(&w.as_str()));
+    secret
+}
+
+fn is_name_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.')
+}
+
+/// Mask the value after every secret name followed by `=` or `:` (optionally quoted, as in JSON).
+/// Quoted values are masked up to the closing quote, bare ones up to a space or punctuation;
+/// `Bearer` / `Basic` schemes take the credential after them along.
+fn redact_message(msg: &str) -> String {
+    let b = msg.as_bytes();
+    let mut out = String::with_capacity(msg.len());
+    let (mut copied, mut pos) = (0, 0);
+    while pos < b.len() {
+        if !is_name_byte(b[pos]) || (pos > 0 && is_name_byte(b[pos - 1])) { pos += 1; continue; }
+        let mut end = pos;
+        while end < b.len() && is_name_byte(b[end]) { end += 1; }
+        let name = &msg[pos..end];
+        pos = end;
+        if !is_secret(name) { continue; }
+
+        let mut j = end;
+        if j < b.len() && matches!(b[j], b'"' | b'\'') { j += 1; }
+        while j < b.len() && b[j] == b' ' { j += 1; }
+        if j >= b.len() || !matches!(b[j], b'=' | b':') { continue; }
+        j += 1;
+        while j < b.len() && b[j] == b' ' { j += 1; }
+        let (start, stop) = match b.get(j) {
+            Some(&q @ (b'"' | b'\'')) => (j + 1, b[j + 1..].iter().position(|&c| c == q).map_or(b.len(), |k| j + 1 + k)),
+            Some(_) => {
+                let bare = |from: usize| b[from..].iter().position(|&c| c.is_ascii_whitespace() || b",;&)]}".contains(&c))
+                    .map_or(b.len(), |k| from + k);
+                let mut stop = bare(j);
+                let scheme = &msg[j..stop];
+                if (scheme.eq_ignore_ascii_case("bearer") || scheme.eq_ignore_ascii_case("basic")) && b.get(stop) == Some(&b' ') {
+                    stop = bare(stop + 1);
+                }
+                (j, stop)
+            }
+            None => continue,
+        };
+        if stop == start { continue; }
+        out.push_str(&msg[copied..start]);
+        out.push_str("***");
+        copied = stop;
+        pos = stop;
+    }
+    out.push_str(&msg[copied..]);
+    out
+}
+
+#[derive(Default)]
+struct Fields {
+    message: String,
+    fields: BTreeMap<String, String>,
+}
+
+impl Fields {
+    fn put(&mut self, field: &Field, value: String) {
+        match field.name() {
+            "message" => self.message = value,
+            name if is_secret(name) => { self.fields.insert(name.into(), "***".into()); }
+            name => { self.fields.insert(name.into(), value); }
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.put(field, value.to_string());
+    }
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.put(field, format!("{value:?}"));
+    }
+}
+
+/// The ring / stream layer on the global registry (`install`).
+pub struct LogTail;
+
+impl<S: Subscriber> Layer<S> for LogTail {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let meta = event.metadata();
+        let mut f = Fields::default();
+        event.record(&mut f);
+        let message = redact_message(&f.message);
+
+        let mut line = format!("[{}] {message}", meta.target());
+        for (k, v) in &f.fields { line.push_str(&format!(" {k}={v}")); }
+        if *meta.level() <= Level::WARN { eprintln!("{line}"); } else { println!("{line}"); }
+
+        let ev = LogEvent {
+            seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+            ts_ms: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0),
+            level: level_name(meta.level()),
+            target: meta.target().to_string(),
+            message,
+            fields: f.fields,
+            rank: rank(meta.level()),
+        };
+        if ev.rank <= STREAM_LEVEL.load(Ordering::Relaxed) {
+            if let Some(tx) = STREAM.get() { let _ = tx.try_send(ev.clone()); }
+        }
+        if let Some(ring) = RING.get() {
+            if let Ok(mut r) = ring.lock() {
+                if r.events.len() >= r.capacity { r.events.pop_front(); }
+                r.events.push_back(ev);
+            }
+        }
+    }
+}
+
+fn init_ring() {
+    let capacity = log_tail_config().capacity.clamp(100, 20_000);
+    let _ = RING.set(Mutex::new(Ring { events: VecDeque::with_capacity(capacity), capacity }));
+}
+
+/// Install the registry with `LogTail` as the global subscriber; call once, first thing in `main`.
+pub fn install() {
+    init_ring();
+    let subscriber = tracing_subscriber::registry().with(LevelFilter::from_level(log_tail_config().level)).with(LogTail);
+    if tracing::subscriber::set_global_default(subscriber).is_err() {
+        eprintln!("[LOG] a tracing subscriber was already installed; log tail disabled");
+    }
+}
+
+/// Receiver for the live `log_event` stream (first call only; None afterwards).
+pub fn take_stream() -> Option<mpsc::Receiver<LogEvent>> {
+    let (tx, rx) = mpsc::channel(log_tail_config().stream_queue.max(1));
+    STREAM.set(tx).ok().map(|_| rx)
+}
+
+pub fn set_stream_level(level: Level) {
+    STREAM_LEVEL.store(rank(&level), Ordering::Relaxed);
+}
+
+/// Up to `limit` most recent buffered events at or above `level`, oldest first.
+pub fn tail(level: Level, limit: usize) -> Vec<LogEvent> {
+    let Some(Ok(ring)) = RING.get().map(|r| r.lock()) else { return Vec::new() };
+    let max = rank(&level);
+    let mut out: Vec<LogEvent> = ring.events.iter().rev().filter(|e| e.rank <= max).take(limit).cloned().collect();
+    out.reverse();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::registry;
+
+    #[test]
+    fn secret_names_match_whole_words() {
+        for name in ["password", "db_password", "MQTT_PASSWORD", "x-auth-token", "authToken", "apiKey", "api_key", "hmac", "Authorization"] {
+            assert!(is_secret(name), "{name}");
+        }
+        for name in ["author", "authority", "tokens_left", "passage", "user"] {
+            assert!(!is_secret(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn message_values_are_masked_in_every_form() {
+        let cases = [
+            ("login user=bob password=hunter2 ok", "login user=bob password=*** ok"),
+            ("password: hunter2", "password: ***"),
+            ("token = abc123, retry", "token = ***, retry"),
+            (r#"cfg {"password": "p w", "user": "bob"}"#, r#"cfg {"password": "***", "user": "bob"}"#),
+            ("secret='a b c' kept", "secret='***' kept"),
+            ("Authorization: Bearer eyJhbGci.x.y next", "Authorization: *** next"),
+            ("url?api_key=k1&x=2", "url?api_key=***&x=2"),
+            ("author=alice auth=xyz", "author=alice auth=***"),
+            ("password for 'bob' updated", "password for 'bob' updated"),
+            ("token:", "token:"),
+        ];
+        for (msg, want) in cases {
+            assert_eq!(redact_message(msg), want, "{msg}");
+        }
+    }
+
+    #[test]
+    fn layer_redacts_before_buffering_and_leaves_spans_to_the_registry() {
+        init_ring();
+        let subscriber = registry().with(LevelFilter::DEBUG).with(LogTail);
+        tracing::subscriber::with_default(subscriber, || {
+            let outer = tracing::info_span!("outer");
+            let inner = tracing::info_span!("inner");
+            assert_ne!(outer.id(), inner.id());
+            let _g = outer.enter();
+            tracing::debug!(target: "TAIL-TEST", password = "hunter2", author = "alice", "login token=abc");
+        });
+        let ev = tail(Level::DEBUG, usize::MAX).into_iter().rev().find(|e| e.target == "TAIL-TEST").unwrap();
+        assert_eq!(ev.message, "login token=***");
+        assert_eq!(ev.fields["password"], "***");
+        assert_eq!(ev.fields["author"], "alice");
+        assert_eq!(ev.level, "debug");
+    }
+}
@@ -0,0 +1,307 @@
+//! Shared numeric helpers for the node and greenhouse aggregators and the DB writer.
+//! - One implementation of running sums, means (with min / max / standard deviation, `Stats`) and
+//!   2-decimal rounding, so the stages cannot drift apart.
+//! - Non-finite inputs never enter a sum; an empty sum has no mean (None), never 0.
+//! - No psychrometric (Magnus) or circular-mean helpers: dew point is a configured derived metric
+//!   (derived.rs), VPD arrives computed by the nodes, and no frame carries a wind direction.
+
+/// Mean of `cnt` accumulated values; None when nothing was accumulated.
+#[inline] pub fn mean(sum: f64, cnt: u32) -> Option<f32> {
+    if cnt == 0 { None } else { Some((sum / (cnt as f64)) as f32) }
+}
+
+/// Add `v` to a running sum / count unless it is NaN or infinite.
+#[inline] pub fn acc(v: f32, sum: &mut f64, cnt: &mut u32) {
+    let x = v as f64; if x.is_finite() { *sum += x; *cnt += 1; }
+}
+
+/// `acc` for optional values (missing values are skipped).
+#[inline] pub fn acc_opt(v: Option<f32>, sum: &mut f64, cnt: &mut u32) {
+    if let Some(x) = v { acc(x, sum, cnt); }
+}
+
+/// Running sum / count / min / max of one field, so a mean comes with its extremes and standard
+/// deviation (Welford) in the same pass; like `acc`, non-finite inputs are skipped, and nothing
+/// accumulated gives None for all of them.
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    sum: f64,
+    cnt: u32,
+    min: f32,
+    max: f32,
+    run_mean: f64, // Welford running mean and sum of squared deviations
+    m2: f64,
+}
+
+impl Default for Stats {
+    fn default() -> Self { Stats::new() }
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Stats { sum: 0.0, cnt: 0, min: f32::INFINITY, max: f32::NEG_INFINITY, run_mean: 0.0, m2: 0.0 }
+    }
+
+    pub fn add(&mut self, v: f32) {
+        if !v.is_finite() { return; }
+        acc(v, &mut self.sum, &mut self.cnt);
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+        let x = v as f64;
+        let d = x - self.run_mean;
+        self.run_mean += d / self.cnt as f64;
+        self.m2 += d * (x - self.run_mean);
+    }
+
+    /// Finite samples added.
+    pub fn count(&self) -> u32 { self.cnt }
+    pub fn mean(&self) -> Option<f32> { mean(self.sum, self.cnt) }
+    pub fn min(&self) -> Option<f32> { (self.cnt > 0).then_some(self.min) }
+    pub fn max(&self) -> Option<f32> { (self.cnt > 0).then_some(self.max) }
+
+    /// Sample standard deviation (n - 1); None below two samples, where 0 would read as "perfectly stable".
+    pub fn sd(&self) -> Option<f32> {
+        (self.cnt > 1).then(|| (self.m2 / (self.cnt - 1) as f64).sqrt() as f32)
+    }
+}
+
+/// How a window combines the samples of one field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggKind {
+    Mean,
+    Max,
+    Sum,
+}
+
+/// Running window aggregate of one field; like `acc`, non-finite inputs are skipped, and a field
+/// with no finite sample has no value (None) whatever its kind.
+#[derive(Debug, Clone, Copy)]
+pub struct Agg {
+    kind: AggKind,
+    val: f64,
+    cnt: u32,
+}
+
+impl Agg {
+    pub const fn new(kind: AggKind) -> Self { Agg { kind, val: 0.0, cnt: 0 } }
+
+    pub fn add(&mut self, v: f32) {
+        let x = v as f64;
+        if !x.is_finite() { return; }
+        self.val = match self.kind {
+            AggKind::Mean | AggKind::Sum => self.val + x,
+            AggKind::Max if self.cnt == 0 => x,
+            AggKind::Max => self.val.max(x),
+        };
+        self.cnt += 1;
+    }
+
+    pub fn get(&self) -> Option<f32> {
+        match self.kind {
+            AggKind::Mean => mean(self.val, self.cnt),
+            AggKind::Max | AggKind::Sum => (self.cnt > 0).then_some(self.val as f32),
+        }
+    }
+}
+
+/// Median of `vals` (sorted in place); the mean of the two middle values for an even count.
+pub fn median(vals: &mut [f32]) -> Option<f32> {
+    vals.sort_by(f32::total_cmp);
+    let n = vals.len();
+    if n == 0 { return None; }
+    Some(if n % 2 == 1 { vals[n / 2] } else { ((vals[n / 2 - 1] as f64 + vals[n / 2] as f64) / 2.0) as f32 })
+}
+
+/// Mean of `vals` (sorted in place) without `pct` % of them at each end, rounded down; at least the
+/// middle one or two always remain.
+pub fn trimmed_mean(vals: &mut [f32], pct: u8) -> Option<f32> {
+    vals.sort_by(f32::total_cmp);
+    let n = vals.len();
+    let k = (n * usize::from(pct) / 100).min(n.saturating_sub(1) / 2);
+    let (mut sum, mut cnt) = (0.0, 0);
+    for &v in &vals[k..n - k] { acc(v, &mut sum, &mut cnt); }
+    mean(sum, cnt)
+}
+
+/// Keep the samples within `k` median absolute deviations of their median (order not kept); returns
+/// how many were dropped. A MAD of 0 (most samples equal) drops every sample off the median.
+pub fn reject_outliers(vals: &mut Vec<f32>, k: f32) -> usize {
+    let Some(med) = median(vals) else { return 0 };
+    let mut dev: Vec<f32> = vals.iter().map(|v| (v - med).abs()).collect();
+    let limit = k * median(&mut dev).unwrap_or(0.0);
+    let before = vals.len();
+    vals.retain(|v| (v - med).abs() <= limit);
+    before - vals.len()
+}
+
+/// 2-decimal rounding for logs, in f64 like `r2` (in f32, `v * 100` can land on the wrong side of .5
+/// and a rounded value round again to its neighbour).
+#[inline] pub fn round2(v: f32) -> f32 { ((v as f64 * 100.0).round() / 100.0) as f32 }
+
+/// Least-squares slope of y over x; None below two points or when they all share one x.
+pub fn ls_slope(points: &[(f64, f64)]) -> Option<f64> {
+    if points.len() < 2 { return None; }
+    let n = points.len() as f64;
+    let (mx, my) = points.iter().fold((0.0, 0.0), |(sx, sy), &(x, y)| (sx + x, sy + y));
+    let (mx, my) = (mx / n, my / n);
+    let (sxy, sxx) = points.iter().fold((0.0, 0.0), |(sxy, sxx), &(x, y)| (sxy + (x - mx) * (y - my), sxx + (x - mx) * (x - mx)));
+    (sxx > 0.0).then(|| sxy / sxx)
+}
+
+/// 2-decimal rounding as stored (REAL columns), computed in f64.
+#[inline] pub fn r2(v: Option<f32>) -> Option<f64> { v.map(|x| ((x as f64) * 100.0).round() / 100.0) }
+
+/// Leaf minus air temperature for a window; None when either mean is missing (e.g. outdoor nodes).
+#[inline] pub fn leaf_air_dt(leaf: Option<f32>, air: Option<f32>) -> Option<f32> {
+    Some(leaf? - air?)
+}
+
+/// Log formatting used by the 60s lines: `Some(1.23)unit` or `None`.
+pub fn fmt_opt2(v: Option<f32>, unit: &str) -> String {
+    match v.map(round2) {
+        Some(x) if x.is_finite() => {
+            if unit.is_empty() { format!("Some({:.2})", x) }
+            else { format!("Some({:.2}){}", x, unit) }
+        }
+        _ => "None".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn mean_of_nothing_is_none() {
+        assert_eq!(mean(0.0, 0), None);
+        assert_eq!(Stats::new().mean(), None);
+        assert_eq!(median(&mut []), None);
+        assert_eq!(trimmed_mean(&mut [], 10), None);
+        assert_eq!(ls_slope(&[]), None);
+    }
+
+    #[test]
+    fn non_finite_inputs_are_skipped() {
+        let (mut sum, mut cnt) = (0.0, 0);
+        for v in [1.0, f32::NAN, 3.0, f32::INFINITY, f32::NEG_INFINITY] { acc(v, &mut sum, &mut cnt); }
+        assert_eq!((sum, cnt), (4.0, 2));
+        let mut st = Stats::new();
+        for v in [f32::NAN, 2.0, 4.0] { st.add(v); }
+        assert_eq!((st.count(), st.mean(), st.min(), st.max()), (2, Some(3.0), Some(2.0), Some(4.0)));
+        assert_eq!(trimmed_mean(&mut [f32::NAN, 1.0, 2.0], 0), Some(1.5));
+    }
+
+    #[test]
+    fn stats_sd_needs_two_samples() {
+        let mut st = Stats::new();
+        st.add(5.0);
+        assert_eq!(st.sd(), None);
+        for v in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] { st.add(v); }
+        let mut ref_st = Stats::new();
+        for v in [5.0, 2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] { ref_st.add(v); }
+        assert!((st.sd().unwrap() - ref_st.sd().unwrap()).abs() < 1e-6);
+        let mut eight = Stats::new();
+        for v in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] { eight.add(v); }
+        assert!((eight.sd().unwrap() - (32.0f32 / 7.0).sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn stats_sd_stays_exact_far_from_zero() {
+        // a scale reading ~10 kg moving by grams: a naive sum of squares loses these digits in f32
+        let mut st = Stats::new();
+        for v in [10_000.1, 10_000.3, 10_000.5, 10_000.7] { st.add(v); }
+        let sd = st.sd().unwrap();
+        assert!((sd - 0.258_199).abs() < 2e-3, "{sd}");
+        let mut two = Stats::new();
+        for _ in 0..2 { two.add(1_000.25); }
+        assert_eq!(two.sd(), Some(0.0));
+    }
+
+    #[test]
+    fn median_odd_and_even() {
+        assert_eq!(median(&mut [3.0, 1.0, 2.0]), Some(2.0));
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), Some(2.5));
+        assert_eq!(median(&mut [7.0]), Some(7.0));
+    }
+
+    #[test]
+    fn trimmed_mean_limits() {
+        let mut v = [1.0, 2.0, 3.0, 4.0, 100.0, 5.0, 6.0, 7.0, 8.0, 9.0];
+        assert_eq!(trimmed_mean(&mut v.clone(), 0), Some(14.5));
+        assert_eq!(trimmed_mean(&mut v.clone(), 10), Some(5.5)); // one dropped at each end
+        // never trims past the middle: 49 % of 10 rounds to 4, leaving the middle two
+        assert_eq!(trimmed_mean(&mut v, 49), Some(5.5));
+        assert_eq!(trimmed_mean(&mut [1.0, 50.0, 2.0], 49), Some(2.0));
+        assert_eq!(trimmed_mean(&mut [1.0, 3.0], 49), Some(2.0));
+    }
+
+    #[test]
+    fn outliers_by_mad() {
+        let mut v = vec![24.0, 24.1, 23.9, 24.2, 0.0, 24.0];
+        assert_eq!(reject_outliers(&mut v, 5.0), 1);
+        assert!(!v.contains(&0.0));
+        let mut flat = vec![20.0, 20.0, 20.0, 20.0, 21.0];
+        assert_eq!(reject_outliers(&mut flat, 5.0), 1); // MAD 0: anything off the median goes
+        assert_eq!(reject_outliers(&mut Vec::new(), 5.0), 0);
+    }
+
+    #[test]
+    fn slope_of_collinear_points() {
+        let pts: Vec<(f64, f64)> = (0..5).map(|i| (i as f64, 3.0 - 2.0 * i as f64)).collect();
+        assert!((ls_slope(&pts).unwrap() + 2.0).abs() < 1e-12);
+        assert_eq!(ls_slope(&[(1.0, 1.0)]), None);
+        assert_eq!(ls_slope(&[(2.0, 1.0), (2.0, 5.0)]), None); // vertical: no slope
+    }
+
+    #[test]
+    fn agg_kinds() {
+        let mut gust = Agg::new(AggKind::Max);
+        let mut rain = Agg::new(AggKind::Sum);
+        for v in [-3.0, f32::NAN, -1.0] { gust.add(v); rain.add(v); }
+        assert_eq!(gust.get(), Some(-1.0));
+        assert_eq!(rain.get(), Some(-4.0));
+        assert_eq!(Agg::new(AggKind::Sum).get(), None);
+    }
+
+    #[test]
+    fn rounding_and_formatting() {
+        assert_eq!(r2(Some(1.005)), Some(1.0)); // 1.005f32 is just below 1.005
+        assert_eq!(r2(Some(-2.346)), Some(-2.35));
+        assert_eq!(r2(None), None);
+        assert_eq!(fmt_opt2(Some(1.234), "C"), "Some(1.23)C");
+        assert_eq!(fmt_opt2(Some(f32::NAN), "C"), "None");
+        assert_eq!(leaf_air_dt(Some(21.0), None), None);
+    }
+
+    proptest! {
+        #[test]
+        fn mean_within_min_and_max(vals in prop::collection::vec(-1.0e6f32..1.0e6, 1..64)) {
+            let mut st = Stats::new();
+            for &v in &vals { st.add(v); }
+            let (m, lo, hi) = (st.mean().unwrap(), st.min().unwrap(), st.max().unwrap());
+            let tol = 1e-3 * hi.abs().max(lo.abs()).max(1.0);
+            prop_assert!(m >= lo - tol && m <= hi + tol);
+        }
+
+        #[test]
+        fn round2_is_idempotent(v in -1.0e5f32..1.0e5) {
+            prop_assert_eq!(round2(round2(v)), round2(v));
+        }
+
+        #[test]
+        fn median_and_trim_within_range(vals in prop::collection::vec(-1.0e3f32..1.0e3, 1..40), pct in 0u8..50) {
+            let (lo, hi) = vals.iter().fold((f32::MAX, f32::MIN), |(a, b), &v| (a.min(v), b.max(v)));
+            let med = median(&mut vals.clone()).unwrap();
+            let trim = trimmed_mean(&mut vals.clone(), pct).unwrap();
+            prop_assert!(med >= lo && med <= hi);
+            prop_assert!(trim >= lo - 1e-3 && trim <= hi + 1e-3);
+        }
+
+        #[test]
+        fn slope_recovers_a_line(a in -100.0f64..100.0, b in -50.0f64..50.0, n in 2usize..30) {
+            let pts: Vec<(f64, f64)> = (0..n).map(|i| (i as f64 * 0.5, a + b * i as f64 * 0.5)).collect();
+            prop_assert!((ls_slope(&pts).unwrap() - b).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,616 @@
+//! Broker address, credentials and sensor subscriptions, read at startup instead of built into the binary.
+//! - `mqtt.toml` in the app config directory: `host`, `port`, `transport`, `path`, `username`, `password`,
+//!   `client_id_prefix`, `machine_id`, `keep_alive` (seconds), `clean_session`, `max_inflight`,
+//!   `eventloop_capacity`, `max_packet_size` (bytes, both directions), and `[[subscribe]]` tables (`filter`, `qos` 0..2,
+//!   default 1) for the sensor subscriber, and `[[broker]]` tables (`host`, `port` default per transport); every key
+//!   optional.
+//! - `[[broker]]` lists brokers in order of preference for the subscriber's failover (failover.rs); the
+//!   first takes the place of `host` / `port` and is the one every other client uses. The broker of the
+//!   last working connection is kept in `LAST_BROKER_FILE` and tried first on the next start. One login
+//!   (and keyring entry, under the first broker) for all of them.
+//! - `transport` is `tcp` (default), `tls`, `ws` or `wss`; the port defaults to 1883, 8883, 80 or 443 to
+//!   match. `ws` / `wss` connect to `{scheme}://{host}:{port}{path}` (`path` default `/mqtt`) and need a
+//!   build with the `websocket` feature; without it they are refused at startup and tcp is used. TLS
+//!   checks the broker against the OS certificate store. The transport applies to every broker.
+//! - Without `[[subscribe]]` the subscriber listens to `greenhouse/+/node/+/data` plus
+//!   `gh_origin().bridged_topic`, both at QoS 1; with it, exactly to the listed filters. Every filter
+//!   ending in `/data` also gets its `/status` twin (node availability) at the same QoS.
+//! - Environment variables override the file: `APP_MQTT_HOST`, `APP_MQTT_PORT`, `APP_MQTT_USERNAME`,
+//!   `APP_MQTT_PASSWORD`, `APP_MQTT_CLIENT_ID_PREFIX`, `APP_MQTT_MACHINE_ID`, `APP_MQTT_KEEP_ALIVE`,
+//!   `APP_MQTT_TRANSPORT`, `APP_MQTT_PATH`, `APP_MQTT_CLEAN_SESSION`, `APP_MQTT_MAX_INFLIGHT`,
+//!   `APP_MQTT_EVENTLOOP_CAPACITY`, `APP_MQTT_MAX_PACKET_SIZE`. A value
+//!   that does not parse is logged and ignored.
+//! - Unset settings fall back to `DEFAULTS` (local broker, no login). The session and queue settings are
+//!   logged at startup: an eventloop queue too small for a burst from every node shows up as
+//!   disconnects under load, a packet limit too small for a batch frame as a dropped connection.
+//! - The password is taken from the OS credential store (credentials.rs) before the file; a file password
+//!   without a stored one is copied into the store on the first start. `APP_MQTT_PASSWORD` still wins.
+//! - Client ids are `{client_id_prefix}-{client}-{machine_id}` so two installations on one broker do not
+//!   take over each other's sessions. The default machine id is the host name plus a random id created
+//!   once and kept in `MACHINE_ID_FILE` in the config directory; set `machine_id` for a fixed one, or
+//!   to "" for the old ids without it.
+//! - `remote_cmd_secret` (or `APP_MQTT_REMOTE_CMD_SECRET`) is the HMAC key of the integrator's remote
+//!   commands (remote_cmd.rs); without one the command listener does not start. Logged as set or not only.
+//! - Resolved once (`init_mqtt_auth` in setup) and shared by every client. The password can change later
+//!   (`set_mqtt_password`), and `restart_pipeline` reads the file and environment again
+//!   (`reload_mqtt_auth`); either takes effect on each client's next connect. The source of each setting
+//!   (file / env / keyring / default) is logged; the password only as set or not, never its value.
+
+use serde::{Deserialize, Serialize};
+use std::{fs, path::{Path, PathBuf}, str::FromStr, sync::{OnceLock, PoisonError, RwLock}};
+use tracing::{error, info, warn};
+
+use super::config::gh_origin;
+use super::credentials::{load_password, store_password};
+
+pub const FILE_NAME: &str = "mqtt.toml";
+pub const MACHINE_ID_FILE: &str = "mqtt_machine_id";
+pub const LAST_BROKER_FILE: &str = "mqtt_last_broker";
+const SENSOR_TOPIC: &str = "greenhouse/+/node/+/data";
+
+/// How the clients reach the broker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MqttTransport {
+    Tcp,
+    Tls,
+    Ws,
+    Wss,
+}
+
+impl MqttTransport {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MqttTransport::Tcp => "tcp",
+            MqttTransport::Tls => "tls",
+            MqttTransport::Ws => "ws",
+            MqttTransport::Wss => "wss",
+        }
+    }
+
+    pub fn default_port(self) -> u16 {
+        match self {
+            MqttTransport::Tcp => 1883,
+            MqttTransport::Tls => 8883,
+            MqttTransport::Ws => 80,
+            MqttTransport::Wss => 443,
+        }
+    }
+}
+
+impl FromStr for MqttTransport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let t = match s.trim().to_ascii_lowercase().as_str() {
+            "tcp" => MqttTransport::Tcp,
+            "tls" => MqttTransport::Tls,
+            "ws" => MqttTransport::Ws,
+            "wss" => MqttTransport::Wss,
+            other => return Err(format!("unknown transport '{other}' (tcp, tls, ws, wss)")),
+        };
+        if matches!(t, MqttTransport::Ws | MqttTransport::Wss) && !cfg!(feature = "websocket") {
+            return Err(format!("transport '{}' needs a build with the websocket feature", t.as_str()));
+        }
+        Ok(t)
+    }
+}
+
+#[derive(Clone)]
+pub struct MqttAuth {
+    pub host: String,
+    pub port: u16,
+    pub transport: MqttTransport,
+    /// URL path of `ws` / `wss`, starting with `/`.
+    pub path: String,
+    pub client_id_prefix: String,
+    /// Last part of every client id; empty = none.
+    pub machine_id: String,
+    /// Empty = connect without credentials.
+    pub username: String,
+    pub password: String,
+    pub keep_alive_secs: u16,
+    /// false = persistent session: the broker keeps subscriptions and QoS 1/2 messages across reconnects.
+    pub clean_session: bool,
+    /// Outgoing QoS 1/2 publishes awaiting their ack.
+    pub max_inflight: u16,
+    /// Requests (publish, subscribe) queued between a client and its event loop.
+    pub eventloop_capacity: usize,
+    /// Largest packet accepted from or sent to the broker.
+    pub max_packet_size: usize,
+}
+
+struct Defaults {
+    host: &'static str,
+    transport: MqttTransport,
+    path: &'static str,
+    client_id_prefix: &'static str,
+    keep_alive_secs: u16,
+    clean_session: bool,
+    max_inflight: u16,
+    eventloop_capacity: usize,
+    max_packet_size: usize,
+}
+
+const DEFAULTS: Defaults = Defaults {
+    host: "localhost",
+    transport: MqttTransport::Tcp,
+    path: "/mqtt",
+    client_id_prefix: "tauri-greenhouse",
+    keep_alive_secs: 30,
+    clean_session: true,
+    max_inflight: 100,
+    eventloop_capacity: 100,
+    max_packet_size: 10 * 1024,
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MqttFile {
+    host: Option<String>,
+    port: Option<u16>,
+    transport: Option<String>,
+    path: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    client_id_prefix: Option<String>,
+    machine_id: Option<String>,
+    keep_alive: Option<u16>,
+    clean_session: Option<bool>,
+    max_inflight: Option<u16>,
+    eventloop_capacity: Option<usize>,
+    max_packet_size: Option<usize>,
+    remote_cmd_secret: Option<String>,
+    subscribe: Option<Vec<SubscribeFile>>,
+    broker: Option<Vec<BrokerFile>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubscribeFile {
+    filter: String,
+    qos: Option<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BrokerFile {
+    host: String,
+    port: Option<u16>,
+}
+
+/// One broker of the failover list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BrokerEndpoint {
+    pub host: String,
+    pub port: u16,
+}
+
+/// One sensor topic filter and the QoS requested for it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Subscription {
+    pub filter: String,
+    pub qos: u8,
+}
+
+struct MqttSettings {
+    auth: RwLock<MqttAuth>,
+    subscriptions: Vec<Subscription>,
+    /// Failover order; the first is `auth.host` / `auth.port`.
+    brokers: Vec<BrokerEndpoint>,
+    /// Index into `brokers` of the last working connection.
+    last_broker: usize,
+    /// Key of the signed remote commands; empty = listener off.
+    remote_cmd_secret: String,
+    dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    File,
+    Env,
+    Keyring,
+    Default,
+}
+
+impl Source {
+    fn as_str(self) -> &'static str {
+        match self {
+            Source::File => "file",
+            Source::Env => "env",
+            Source::Keyring => "keyring",
+            Source::Default => "default",
+        }
+    }
+}
+
+/// Where each setting came from, in `MqttAuth` field order.
+pub struct Sources {
+    pub host: Source,
+    pub port: Source,
+    pub transport: Source,
+    pub path: Source,
+    pub client_id_prefix: Source,
+    pub machine_id: Source,
+    pub username: Source,
+    pub password: Source,
+    pub keep_alive_secs: Source,
+    pub clean_session: Source,
+    pub max_inflight: Source,
+    pub eventloop_capacity: Source,
+    pub max_packet_size: Source,
+}
+
+/// Env value if set and parseable, else the file value, else the default.
+fn pick<T: FromStr>(env: &dyn Fn(&str) -> Option<String>, var: &str, file: Option<T>, default: T) -> (T, Source) {
+    if let Some(raw) = env(var) {
+        match raw.trim().parse() {
+            Ok(v) => return (v, Source::Env),
+            Err(_) => warn!(target: "MQTT", "{var} is not valid, ignored"),
+        }
+    }
+    match file {
+        Some(v) => (v, Source::File),
+        None => (default, Source::Default),
+    }
+}
+
+/// `picked`, or `default` (logged) when it is zero.
+fn nonzero<T: PartialEq + Default + std::fmt::Display>(name: &str, picked: (T, Source), default: T) -> (T, Source) {
+    if picked.0 != T::default() { return picked; }
+    warn!(target: "MQTT", "{name} 0 is not valid, using {default}");
+    (default, Source::Default)
+}
+
+/// Settings from the parsed file and the environment (`env` looks a variable up).
+fn resolve(file: MqttFile, env: &dyn Fn(&str) -> Option<String>) -> (MqttAuth, Sources) {
+    let (host, host_src) = pick(env, "APP_MQTT_HOST", file.host, DEFAULTS.host.to_string());
+    // parsed here rather than by `pick` so a refused ws / wss says why
+    let transport_of = |raw: String, from: &str| raw.parse::<MqttTransport>().map_err(|e| warn!(target: "MQTT", "{from}: {e}; ignored")).ok();
+    let (transport, transport_src) = match (env("APP_MQTT_TRANSPORT").and_then(|t| transport_of(t, "APP_MQTT_TRANSPORT")), file.transport.and_then(|t| transport_of(t, FILE_NAME))) {
+        (Some(t), _) => (t, Source::Env),
+        (None, Some(t)) => (t, Source::File),
+        (None, None) => (DEFAULTS.transport, Source::Default),
+    };
+    let (port, port_src) = pick(env, "APP_MQTT_PORT", file.port, transport.default_port());
+    let (path, path_src) = pick(env, "APP_MQTT_PATH", file.path, DEFAULTS.path.to_string());
+    let path = if path.starts_with('/') { path } else { format!("/{path}") };
+    let (client_id_prefix, prefix_src) = pick(env, "APP_MQTT_CLIENT_ID_PREFIX", file.client_id_prefix, DEFAULTS.client_id_prefix.to_string());
+    // a default machine id is generated (and persisted) by `load`
+    let (machine_id, machine_src) = pick(env, "APP_MQTT_MACHINE_ID", file.machine_id, String::new());
+    let (username, user_src) = pick(env, "APP_MQTT_USERNAME", file.username, String::new());
+    let (password, pass_src) = pick(env, "APP_MQTT_PASSWORD", file.password, String::new());
+    let (keep_alive_secs, keep_src) = pick(env, "APP_MQTT_KEEP_ALIVE", file.keep_alive, DEFAULTS.keep_alive_secs);
+    let (clean_session, clean_src) = pick(env, "APP_MQTT_CLEAN_SESSION", file.clean_session, DEFAULTS.clean_session);
+    // zero would stall the client (or panic in rumqttc for inflight): use the default instead
+    let (max_inflight, inflight_src) = nonzero("max_inflight", pick(env, "APP_MQTT_MAX_INFLIGHT", file.max_inflight, DEFAULTS.max_inflight), DEFAULTS.max_inflight);
+    let (eventloop_capacity, capacity_src) = nonzero("eventloop_capacity", pick(env, "APP_MQTT_EVENTLOOP_CAPACITY", file.eventloop_capacity, DEFAULTS.eventloop_capacity), DEFAULTS.eventloop_capacity);
+    let (max_packet_size, packet_src) = nonzero("max_packet_size", pick(env, "APP_MQTT_MAX_PACKET_SIZE", file.max_packet_size, DEFAULTS.max_packet_size), DEFAULTS.max_packet_size);
+    (
+        MqttAuth {
+            host, port, transport, path, client_id_prefix, machine_id: machine_id.trim().to_string(), username, password, keep_alive_secs,
+            clean_session, max_inflight, eventloop_capacity, max_packet_size,
+        },
+        Sources {
+            host: host_src, port: port_src, transport: transport_src, path: path_src, client_id_prefix: prefix_src, machine_id: machine_src,
+            username: user_src, password: pass_src, keep_alive_secs: keep_src,
+            clean_session: clean_src, max_inflight: inflight_src, eventloop_capacity: capacity_src, max_packet_size: packet_src,
+        },
+    )
+}
+
+/// `…/status` for every `…/data` filter not already listed, at the data filter's QoS.
+fn with_status_filters(mut subs: Vec<Subscription>) -> Vec<Subscription> {
+    let status: Vec<Subscription> = subs.iter()
+        .filter_map(|s| s.filter.strip_suffix("/data").map(|p| Subscription { filter: format!("{p}/status"), qos: s.qos }))
+        .collect();
+    for s in status {
+        if !subs.iter().any(|x| x.filter == s.filter) { subs.push(s); }
+    }
+    subs
+}
+
+/// Configured filters, or the built-in ones when the file lists none, plus their status filters; bad
+/// entries are logged and skipped.
+fn subscriptions(file: Option<Vec<SubscribeFile>>) -> Vec<Subscription> {
+    let Some(list) = file else {
+        return with_status_filters(std::iter::once(SENSOR_TOPIC).chain(gh_origin().bridged_topic)
+            .map(|f| Subscription { filter: f.to_string(), qos: 1 })
+            .collect());
+    };
+    with_status_filters(list.into_iter().filter_map(|s| {
+        let qos = s.qos.unwrap_or(1);
+        let filter = s.filter.trim();
+        if filter.is_empty() || qos > 2 {
+            warn!(target: "MQTT", "subscription '{filter}' (qos {qos}) is not valid, skipped");
+            return None;
+        }
+        Some(Subscription { filter: filter.to_string(), qos })
+    }).collect())
+}
+
+/// `mqtt.toml` in `dir`; empty when there is no directory or file, or it cannot be used (logged).
+fn read_file(dir: Option<&Path>) -> MqttFile {
+    parse_file(dir).unwrap_or_else(|e| {
+        error!(target: "MQTT", "{e}; using environment and defaults");
+        MqttFile::default()
+    })
+}
+
+/// `FILE_NAME` in `dir`, empty when there is none; Err when it cannot be read or parsed.
+fn parse_file(dir: Option<&Path>) -> Result<MqttFile, String> {
+    let Some(path) = dir.map(|d| d.join(FILE_NAME)) else { return Ok(MqttFile::default()) };
+    let text = match fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            info!(target: "MQTT", "no {}; using environment and defaults", path.display());
+            return Ok(MqttFile::default());
+        }
+        Err(e) => return Err(format!("could not read {}: {e}", path.display())),
+    };
+    toml::from_str(&text).map_err(|e| format!("{} is not valid: {e}", path.display()))
+}
+
+/// Host name as a client id part: ASCII letters, digits and `-`, lowercased, at most 24 characters.
+fn host_name() -> Option<String> {
+    let raw = std::env::var("COMPUTERNAME").ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())?;
+    let name: String = raw.trim().chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .take(24)
+        .collect();
+    let name = name.trim_matches('-').to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+/// `{host}-{random}`: the random part is read from `MACHINE_ID_FILE` in `dir`, or created and saved there.
+/// Without a usable directory it is new on every start (logged).
+fn machine_id(dir: Option<&Path>) -> String {
+    let path = dir.map(|d| d.join(MACHINE_ID_FILE));
+    let saved = path.as_ref().and_then(|p| fs::read_to_string(p).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric()));
+    let random = saved.unwrap_or_else(|| {
+        let id = format!("{:08x}", fastrand::u32(..));
+        let stored = path.as_ref().is_some_and(|p| {
+            p.parent().is_some_and(|d| fs::create_dir_all(d).is_ok()) && fs::write(p, &id).is_ok()
+        });
+        if !stored { warn!(target: "MQTT", "could not store the machine id; client ids change on every start"); }
+        id
+    });
+    match host_name() {
+        Some(host) => format!("{host}-{random}"),
+        None => random,
+    }
+}
+
+/// Valid `[[broker]]` entries (bad ones logged and skipped); the first one becomes the file's `host` / `port`.
+fn take_brokers(file: &mut MqttFile) -> Vec<BrokerFile> {
+    let list: Vec<BrokerFile> = file.broker.take().unwrap_or_default().into_iter().filter_map(|b| {
+        let host = b.host.trim();
+        if host.is_empty() || b.port == Some(0) {
+            warn!(target: "MQTT", "[[broker]] '{host}' is not valid (empty host or port 0), skipped");
+            return None;
+        }
+        Some(BrokerFile { host: host.to_string(), port: b.port })
+    }).collect();
+    if let Some(first) = list.first() {
+        if file.host.is_some() || file.port.is_some() {
+            warn!(target: "MQTT", "{FILE_NAME} has both host / port and [[broker]]; using [[broker]]");
+        }
+        file.host = Some(first.host.clone());
+        file.port = first.port;
+    }
+    list
+}
+
+/// The resolved primary (environment overrides included), then the other listed brokers without repeats;
+/// a listed broker without a port uses the transport's default.
+fn broker_list(auth: &MqttAuth, listed: Vec<BrokerFile>) -> Vec<BrokerEndpoint> {
+    let mut brokers = vec![BrokerEndpoint { host: auth.host.clone(), port: auth.port }];
+    for b in listed.into_iter().skip(1) {
+        let b = BrokerEndpoint { host: b.host, port: b.port.unwrap_or(auth.transport.default_port()) };
+        if !brokers.contains(&b) { brokers.push(b); }
+    }
+    brokers
+}
+
+/// Index of the broker saved in `LAST_BROKER_FILE` (`host:port`); 0 when none or no longer listed.
+fn read_last_broker(dir: Option<&Path>, brokers: &[BrokerEndpoint]) -> usize {
+    let Some(saved) = dir.and_then(|d| fs::read_to_string(d.join(LAST_BROKER_FILE)).ok()) else { return 0 };
+    brokers.iter().position(|b| format!("{}:{}", b.host, b.port) == saved.trim()).unwrap_or(0)
+}
+
+fn load(dir: Option<&Path>) -> MqttSettings {
+    load_from(dir, read_file(dir))
+}
+
+/// Settings from an already read `file` (`dir` for the keyring copy, machine id and last broker).
+fn load_from(dir: Option<&Path>, mut file: MqttFile) -> MqttSettings {
+    let subscriptions = subscriptions(file.subscribe.take());
+    let (remote_cmd_secret, secret_src) = pick(&|var| std::env::var(var).ok(), "APP_MQTT_REMOTE_CMD_SECRET", file.remote_cmd_secret.take(), String::new());
+    let listed = take_brokers(&mut file);
+    let (mut auth, mut src) = resolve(file, &|var| std::env::var(var).ok());
+    let brokers = broker_list(&auth, listed);
+    let last_broker = read_last_broker(dir, &brokers);
+    if src.machine_id == Source::Default { auth.machine_id = machine_id(dir); }
+    if !auth.username.is_empty() && src.password != Source::Env {
+        match load_password(&auth.username, &auth.host, auth.port) {
+            Ok(Some(p)) => {
+                auth.password = p;
+                src.password = Source::Keyring;
+            }
+            Ok(None) if src.password == Source::File => match store_password(&auth.username, &auth.host, auth.port, &auth.password) {
+                Ok(()) => warn!(target: "MQTT", "password copied from {FILE_NAME} into the credential store; remove it from the file"),
+                Err(e) => warn!(target: "MQTT", "{e}; keeping the password from {FILE_NAME}"),
+            },
+            Ok(None) => {}
+            Err(e) => warn!(target: "MQTT", "{e}; using the password from {FILE_NAME} or the environment"),
+        }
+    }
+    info!(
+        target: "MQTT",
+        "broker {}:{} (host {}, port {}), transport {} ({}), path {} ({}), client id prefix '{}' ({}), machine id '{}' ({}), user '{}' ({}), password {} ({}), keep-alive {}s ({})",
+        auth.host, auth.port, src.host.as_str(), src.port.as_str(),
+        auth.transport.as_str(), src.transport.as_str(), auth.path, src.path.as_str(),
+        auth.client_id_prefix, src.client_id_prefix.as_str(),
+        auth.machine_id, src.machine_id.as_str(),
+        auth.username, src.username.as_str(),
+        if auth.password.is_empty() { "not set" } else { "set" }, src.password.as_str(),
+        auth.keep_alive_secs, src.keep_alive_secs.as_str(),
+    );
+    info!(
+        target: "MQTT",
+        "session: clean {} ({}), max inflight {} ({}), eventloop capacity {} ({}), max packet {} bytes ({})",
+        auth.clean_session, src.clean_session.as_str(),
+        auth.max_inflight, src.max_inflight.as_str(),
+        auth.eventloop_capacity, src.eventloop_capacity.as_str(),
+        auth.max_packet_size, src.max_packet_size.as_str(),
+    );
+    let subs: Vec<String> = subscriptions.iter().map(|s| format!("{} (qos {})", s.filter, s.qos)).collect();
+    info!(target: "MQTT", "sensor subscriptions: {}", if subs.is_empty() { "none".to_string() } else { subs.join(", ") });
+    info!(target: "MQTT", "remote command secret {} ({})", if remote_cmd_secret.is_empty() { "not set" } else { "set" }, secret_src.as_str());
+    if brokers.len() > 1 {
+        let list: Vec<String> = brokers.iter().map(|b| format!("{}:{}", b.host, b.port)).collect();
+        info!(target: "MQTT", "failover brokers: {}; last working: {}", list.join(", "), list[last_broker]);
+    }
+    MqttSettings { auth: RwLock::new(auth), subscriptions, brokers, last_broker, remote_cmd_secret, dir: dir.map(Path::to_path_buf) }
+}
+
+/// The settings in use. A reload leaks the ones it replaces: a running client may still hold the
+/// `&'static` subscriptions or brokers handed out before it, and reloads are rare manual steps.
+static MQTT: OnceLock<RwLock<&'static MqttSettings>> = OnceLock::new();
+
+fn settings() -> &'static MqttSettings {
+    let current = MQTT.get_or_init(|| RwLock::new(Box::leak(Box::new(load(None)))));
+    *current.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Resolve the settings from `config_dir` (the app config directory) and the environment; call once in
+/// setup before any client connects. Later calls keep the first result.
+pub fn init_mqtt_auth(config_dir: Option<&Path>) {
+    MQTT.get_or_init(|| RwLock::new(Box::leak(Box::new(load(config_dir)))));
+}
+
+/// Resolve the settings again from the same config directory and the environment (`restart_pipeline`);
+/// each client uses them from its next connect on. A file that cannot be read or parsed keeps the
+/// current settings and is returned as the error.
+pub fn reload_mqtt_auth() -> Result<(), String> {
+    let dir = settings().dir.clone();
+    let file = parse_file(dir.as_deref())?;
+    let fresh: &'static MqttSettings = Box::leak(Box::new(load_from(dir.as_deref(), file)));
+    if let Some(current) = MQTT.get() { *current.write().unwrap_or_else(PoisonError::into_inner) = fresh; }
+    Ok(())
+}
+
+/// Broker settings for `new_client`, current password included; environment and defaults only if
+/// `init_mqtt_auth` has not run.
+pub fn mqtt_auth() -> MqttAuth {
+    settings().auth.read().map(|a| a.clone()).unwrap_or_else(|p| p.into_inner().clone())
+}
+
+/// Store a new password for the configured login in the credential store and use it from each client's
+/// next connect on.
+pub fn set_mqtt_password(password: &str) -> Result<(), String> {
+    let auth = mqtt_auth();
+    if auth.username.is_empty() {
+        return Err("no MQTT username configured; a password is only used with one".to_string());
+    }
+    store_password(&auth.username, &auth.host, auth.port, password)?;
+    if let Ok(mut a) = settings().auth.write() { a.password = password.to_string(); }
+    info!(target: "MQTT", "password for '{}' updated in the credential store", auth.username);
+    Ok(())
+}
+
+/// Topic filters of the sensor subscriber, in subscribe order.
+pub fn sensor_subscriptions() -> &'static [Subscription] {
+    &settings().subscriptions
+}
+
+/// HMAC key of remote commands (remote_cmd.rs); empty when none is configured.
+pub fn remote_cmd_secret() -> &'static str {
+    &settings().remote_cmd_secret
+}
+
+/// Brokers of the subscriber's failover, primary first; never empty.
+pub fn mqtt_brokers() -> &'static [BrokerEndpoint] {
+    &settings().brokers
+}
+
+/// Index into `mqtt_brokers` of the broker that last worked (previous run included).
+pub fn last_broker() -> usize {
+    settings().last_broker
+}
+
+/// Keep `broker` for the next start; a failed write is logged and the next start begins at the primary.
+pub fn remember_broker(broker: &BrokerEndpoint) {
+    let Some(path) = settings().dir.as_ref().map(|d| d.join(LAST_BROKER_FILE)) else { return };
+    if let Err(e) = fs::write(&path, format!("{}:{}", broker.host, broker.port)) {
+        warn!(target: "MQTT", "could not store the last broker in {}: {e}", path.display());
+    }
+}
+
+#[cfg(test)]
+impl MqttAuth {
+    /// Defaults for a tcp broker at `host:port`, no login.
+    pub(crate) fn sample(host: &str, port: u16) -> Self {
+        MqttAuth {
+            host: host.to_string(), port, transport: DEFAULTS.transport, path: DEFAULTS.path.to_string(),
+            client_id_prefix: DEFAULTS.client_id_prefix.to_string(), machine_id: String::new(),
+            username: String::new(), password: String::new(), keep_alive_secs: DEFAULTS.keep_alive_secs,
+            clean_session: DEFAULTS.clean_session, max_inflight: DEFAULTS.max_inflight,
+            eventloop_capacity: DEFAULTS.eventloop_capacity, max_packet_size: DEFAULTS.max_packet_size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(host: &str, port: u16) -> BrokerEndpoint {
+        BrokerEndpoint { host: host.into(), port }
+    }
+
+    #[test]
+    fn the_last_working_broker_is_preferred_while_it_is_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        let brokers = [endpoint("gateway", 1883), endpoint("office", 1883), endpoint("office", 8883)];
+        assert_eq!(read_last_broker(Some(dir.path()), &brokers), 0, "nothing saved yet");
+
+        fs::write(dir.path().join(LAST_BROKER_FILE), "office:8883\n").unwrap();
+        assert_eq!(read_last_broker(Some(dir.path()), &brokers), 2);
+        assert_eq!(read_last_broker(Some(dir.path()), &brokers[..2]), 0, "no longer listed");
+        assert_eq!(read_last_broker(None, &brokers), 0);
+    }
+
+    #[test]
+    fn broker_list_starts_at_the_configured_broker_and_skips_repeats() {
+        let (auth, _) = resolve(MqttFile { host: Some("gateway".into()), ..Default::default() }, &|_| None);
+        let listed = ["gateway", "office", "office"].map(|h| BrokerFile { host: h.into(), port: None });
+        assert_eq!(broker_list(&auth, listed.into()), [endpoint("gateway", 1883), endpoint("office", 1883)]);
+    }
+
+    #[test]
+    fn session_settings_come_from_env_then_file_then_defaults() {
+        let file = MqttFile { clean_session: Some(false), max_inflight: Some(20), eventloop_capacity: Some(0), ..Default::default() };
+        let env = |var: &str| (var == "APP_MQTT_MAX_PACKET_SIZE").then(|| "65536".to_string());
+        let (auth, _) = resolve(file, &env);
+        assert!(!auth.clean_session);
+        assert_eq!(auth.max_inflight, 20);
+        // zero would stall the client: the default instead
+        assert_eq!(auth.eventloop_capacity, DEFAULTS.eventloop_capacity);
+        assert_eq!(auth.max_packet_size, 65_536);
+    }
+
+    #[test]
+    fn a_broken_file_is_an_error_for_a_reload_and_no_file_is_not() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(parse_file(Some(dir.path())).unwrap().host.is_none());
+        fs::write(dir.path().join(FILE_NAME), "host = \"gateway\"\n").unwrap();
+        assert_eq!(parse_file(Some(dir.path())).unwrap().host.as_deref(), Some("gateway"));
+        fs::write(dir.path().join(FILE_NAME), "host = gateway\n").unwrap();
+        assert!(parse_file(Some(dir.path())).unwrap_err().contains("is not valid"));
+        assert!(read_file(Some(dir.path())).host.is_none(), "startup falls back to the defaults");
+    }
+}
@@ -0,0 +1,163 @@
+//! Reconnect backoff of an MQTT client loop.
+//! - Delays double from `base_ms` to `max_ms`, each one spread by ±`jitter_pct` so machines that lost
+//!   the broker together (power blip) do not retry in lockstep.
+//! - A CONNACK alone does not reset it: the connection must stay up for `healthy_after_secs`, so a
+//!   broker that accepts and then drops us keeps backing off instead of being hammered.
+//! - Downtime runs from the first failure until the connection is healthy again; every
+//!   `loud_every_secs` of it the caller gets `loud` to log an error instead of another quiet retry.
+
+use std::time::{Duration, Instant};
+
+use super::config::ReconnectConfig;
+
+/// One scheduled retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+    /// Failed tries since the connection was last healthy (1 = first retry).
+    pub attempt: u32,
+    pub delay_ms: u64,
+    pub downtime_ms: u64,
+    /// Another `loud_every_secs` of continuous failure has passed.
+    pub loud: bool,
+}
+
+#[derive(Debug)]
+pub struct Backoff {
+    cfg: ReconnectConfig,
+    attempt: u32,
+    delay_ms: u64,
+    down_since: Option<Instant>,
+    connected_at: Option<Instant>,
+    loud_after_ms: u64,
+}
+
+impl Backoff {
+    pub fn new(cfg: ReconnectConfig) -> Self {
+        Backoff { cfg, attempt: 0, delay_ms: cfg.base_ms, down_since: None, connected_at: None, loud_after_ms: cfg.loud_every_secs * 1000 }
+    }
+
+    /// CONNACK received; the backoff resets once the connection has lasted `healthy_after_secs`.
+    pub fn on_connected(&mut self, now: Instant) {
+        self.connected_at = Some(now);
+        self.on_alive(now);
+    }
+
+    /// Any traffic on an open connection; resets the backoff when it has been up long enough.
+    pub fn on_alive(&mut self, now: Instant) {
+        let Some(at) = self.connected_at else { return };
+        if now.duration_since(at) >= Duration::from_secs(self.cfg.healthy_after_secs) {
+            self.attempt = 0;
+            self.delay_ms = self.cfg.base_ms;
+            self.down_since = None;
+            self.loud_after_ms = self.cfg.loud_every_secs * 1000;
+        }
+    }
+
+    /// Connection lost or never made; `jitter` in -1..=1 scales the ±`jitter_pct` spread.
+    pub fn on_failure_with(&mut self, now: Instant, jitter: f64) -> Retry {
+        self.connected_at = None;
+        let down_since = *self.down_since.get_or_insert(now);
+        let downtime_ms = now.duration_since(down_since).as_millis() as u64;
+        let loud = downtime_ms >= self.loud_after_ms;
+        if loud {
+            let every = (self.cfg.loud_every_secs * 1000).max(1);
+            self.loud_after_ms = (downtime_ms / every + 1) * every;
+        }
+        self.attempt += 1;
+        let spread = self.delay_ms as f64 * self.cfg.jitter_pct as f64 / 100.0 * jitter.clamp(-1.0, 1.0);
+        let delay_ms = (self.delay_ms as f64 + spread).round().max(0.0) as u64;
+        self.delay_ms = (self.delay_ms * 2).min(self.cfg.max_ms);
+        Retry { attempt: self.attempt, delay_ms, downtime_ms, loud }
+    }
+
+    /// `on_failure_with` a random jitter.
+    pub fn on_failure(&mut self, now: Instant) -> Retry {
+        self.on_failure_with(now, fastrand::f64() * 2.0 - 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mqtt::config::reconnect;
+
+    fn cfg() -> ReconnectConfig {
+        ReconnectConfig { base_ms: 250, max_ms: 10_000, jitter_pct: 25, healthy_after_secs: 30, loud_every_secs: 60 }
+    }
+
+    fn delays(b: &mut Backoff, start: Instant, n: usize) -> Vec<u64> {
+        (0..n).map(|_| b.on_failure_with(start, 0.0).delay_ms).collect()
+    }
+
+    #[test]
+    fn delays_double_up_to_the_cap() {
+        let mut b = Backoff::new(cfg());
+        let t0 = Instant::now();
+        assert_eq!(delays(&mut b, t0, 8), [250, 500, 1_000, 2_000, 4_000, 8_000, 10_000, 10_000]);
+        assert_eq!(b.on_failure_with(t0, 0.0).attempt, 9);
+    }
+
+    #[test]
+    fn jitter_spreads_each_delay_by_at_most_its_percentage() {
+        let t0 = Instant::now();
+        let at = |jitter| Backoff::new(cfg()).on_failure_with(t0, jitter).delay_ms;
+        assert_eq!((at(-1.0), at(1.0), at(0.5)), (188, 313, 281));
+        assert_eq!(at(7.0), at(1.0), "jitter is clamped");
+        let mut b = Backoff::new(cfg());
+        for _ in 0..50 {
+            let r = b.on_failure(t0);
+            let nominal = (250u64 << (r.attempt - 1).min(6)).min(10_000) as f64;
+            assert!((r.delay_ms as f64 - nominal).abs() <= nominal * 0.25 + 1.0, "{r:?}");
+        }
+    }
+
+    #[test]
+    fn a_connection_that_drops_at_once_keeps_backing_off() {
+        let mut b = Backoff::new(cfg());
+        let t0 = Instant::now();
+        delays(&mut b, t0, 4);
+        // CONNACK, some traffic, then an error 5 s later: no reset
+        let up = t0 + Duration::from_secs(10);
+        b.on_connected(up);
+        b.on_alive(up + Duration::from_secs(5));
+        let r = b.on_failure_with(up + Duration::from_secs(5), 0.0);
+        assert_eq!((r.attempt, r.delay_ms, r.downtime_ms), (5, 4_000, 15_000));
+    }
+
+    #[test]
+    fn a_healthy_connection_resets_attempts_and_downtime() {
+        let mut b = Backoff::new(cfg());
+        let t0 = Instant::now();
+        delays(&mut b, t0, 4);
+        let up = t0 + Duration::from_secs(10);
+        b.on_connected(up);
+        b.on_alive(up + Duration::from_secs(31));
+        let down = up + Duration::from_secs(40);
+        let r = b.on_failure_with(down, 0.0);
+        assert_eq!((r.attempt, r.delay_ms, r.downtime_ms, r.loud), (1, 250, 0, false));
+        // the CONNACK alone would have been enough had the link stayed up as long
+        let mut b = Backoff::new(cfg());
+        b.on_failure_with(t0, 0.0);
+        b.on_connected(t0);
+        b.on_alive(t0 + Duration::from_secs(29));
+        assert_eq!(b.on_failure_with(t0 + Duration::from_secs(29), 0.0).attempt, 2);
+    }
+
+    #[test]
+    fn continuous_failure_is_loud_once_per_minute() {
+        let mut b = Backoff::new(cfg());
+        let t0 = Instant::now();
+        let loud: Vec<u64> = (0..=200).step_by(10)
+            .map(|s| (s, b.on_failure_with(t0 + Duration::from_secs(s), 0.0)))
+            .filter(|(_, r)| r.loud)
+            .map(|(s, r)| { assert_eq!(r.downtime_ms, s * 1000); s })
+            .collect();
+        assert_eq!(loud, [60, 120, 180]);
+    }
+
+    #[test]
+    fn shipped_config_matches_the_documented_policy() {
+        let c = reconnect();
+        assert_eq!((c.base_ms, c.max_ms, c.jitter_pct, c.healthy_after_secs, c.loud_every_secs), (250, 10_000, 25, 30, 60));
+    }
+}
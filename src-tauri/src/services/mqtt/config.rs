@@ -1,20 +1,434 @@
 #[derive(Clone, Copy)]
-pub struct MqttAuth<'a> {
-    pub host: &'a str,
-    pub port: u16,
-    pub client_id_prefix: &'a str,
-    pub username: &'a str,
-    pub password: &'a str,
-    pub keep_alive_secs: u16,
-}
-
-pub const fn mqtt_auth() -> MqttAuth<'static> {
-    MqttAuth {
-        host: "192.168.20.1",
-        port: 1883,
-        client_id_prefix: "tauri-greenhouse",
-        username: "cresla",
-        password: "cresla123.",
-        keep_alive_secs: 30,
+pub struct SiteSummaryConfig<'a> {
+    pub site_id: &'a str,
+    pub every_secs: u64,
+}
+
+/// Retained heartbeat for head office on `greenhouse/site/{site_id}/summary`.
+pub const fn site_summary() -> SiteSummaryConfig<'static> {
+    SiteSummaryConfig {
+        site_id: "site-01",
+        every_secs: 300,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct HeartbeatConfig<'a> {
+    /// Topic is `{topic_prefix}/{client_id}/status`; "" turns the heartbeat and its Last Will off.
+    pub topic_prefix: &'a str,
+    pub every_secs: u64,
+}
+
+/// Retained app heartbeat for ops on the broker side (heartbeat.rs).
+pub const fn heartbeat() -> HeartbeatConfig<'static> {
+    HeartbeatConfig {
+        topic_prefix: "apps/greenhouse-dashboard",
+        every_secs: 30,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RemoteQueryConfig {
+    pub enabled: bool,
+    /// Larger answers are replaced by an error asking for a narrower query.
+    pub max_reply_bytes: usize,
+    /// Queries beyond this many per minute are dropped unanswered.
+    pub max_per_min: u32,
+}
+
+/// Read-only queries on `{heartbeat().topic_prefix}/{client_id}/query` (query.rs).
+pub const fn remote_query() -> RemoteQueryConfig {
+    RemoteQueryConfig {
+        enabled: true,
+        max_reply_bytes: 16 * 1024,
+        max_per_min: 30,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct AggregationConfig {
+    /// Expected publish interval of outdoor stations (they report about once a minute).
+    pub outdoor_expected_interval_secs: u64,
+    /// Node and greenhouse window length, the tick of both aggregators (1..=900);
+    /// `APPTEST_WINDOW_SECS` overrides it per deployment.
+    pub window_secs: u64,
+    /// Outdoor window length; a multiple of the window tick, several expected intervals long.
+    pub outdoor_window_secs: u64,
+    /// Samples are windowed by the node clock (`device_ts`) unless it is further than this ahead of
+    /// local time; such a clock is not trusted and the arrival time is used instead.
+    pub max_device_skew_secs: u64,
+    /// How far behind local time a node clock may be: older samples (a burst after a dropout) are
+    /// back-filled into the windows they were measured in; a clock further behind is wrong and gets
+    /// the arrival time, like one too far ahead.
+    pub max_backfill_secs: u64,
+    /// How node windows reduce sensor readings; stored in the rows' `agg` (aggregator.rs, `node_agg`).
+    /// `APPTEST_AGG_MODE` (`mean`, `median`, `trim:<pct>`) overrides it per deployment.
+    pub mode: AggMode,
+    /// Per sensor key overrides of `mode`, e.g. `("par_value", AggMode::Median)` for spiky PAR sensors.
+    pub field_modes: &'static [(&'static str, AggMode)],
+    /// Drop samples further than `outlier_mad_k` median absolute deviations from the window median
+    /// before reducing a reading (windows of 5+ samples only).
+    pub reject_outliers: bool,
+    pub outlier_mad_k: f32,
+    /// A weight rise larger than this between consecutive samples is an irrigation shot: the
+    /// transpiration fit stops before it (aggregator.rs, `transpiration_g_min`).
+    pub irrigation_jump_g: f32,
+}
+
+/// Reduction of one field's window samples to its value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AggMode {
+    Mean,
+    /// Middle sample; the mean of the two middle ones for an even count.
+    Median,
+    /// Mean without the lowest and highest `pct` % of the samples (rounded down; the middle stays).
+    TrimmedMean { pct: u8 },
+}
+
+impl AggMode {
+    /// `mean`, `median` or `trim:<pct>` (0..=49).
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "mean" => Some(AggMode::Mean),
+            "median" => Some(AggMode::Median),
+            other => other.strip_prefix("trim:")?.trim().parse::<u8>().ok()
+                .filter(|pct| *pct < 50)
+                .map(|pct| AggMode::TrimmedMean { pct }),
+        }
+    }
+}
+
+pub const fn aggregation() -> AggregationConfig {
+    AggregationConfig {
+        outdoor_expected_interval_secs: 60,
+        window_secs: 60,
+        outdoor_window_secs: 300,
+        max_device_skew_secs: 600,
+        max_backfill_secs: 3 * 24 * 3600,
+        mode: AggMode::Mean,
+        field_modes: &[],
+        reject_outliers: false,
+        outlier_mad_k: 5.0,
+        irrigation_jump_g: 20.0,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SlewGuardConfig {
+    /// Off: every reading passes unguarded (the range check still applies).
+    pub enabled: bool,
+    /// (field, max change, per seconds). The allowed change grows with the time since the last
+    /// accepted value, but never drops below one period's worth. Fields not listed (PAR, weight)
+    /// jump by nature and are never slew-guarded.
+    pub limits: &'static [(&'static str, f32, u64)],
+}
+
+pub const fn slew_guard() -> SlewGuardConfig {
+    SlewGuardConfig {
+        enabled: true,
+        limits: &[
+            ("air_temp_c",     5.0,  10),
+            ("leaf_temp_c",    5.0,  10),
+            ("bag_temp_c",     5.0,  10),
+            ("air_rh_pct",     20.0, 10),
+            ("bag_rh1_pct",    20.0, 10),
+            ("bag_rh2_pct",    20.0, 10),
+            ("bag_rh3_pct",    20.0, 10),
+            ("bag_rh4_pct",    20.0, 10),
+            ("bag_rh_avg_pct", 20.0, 10),
+            ("ea_air_kpa",     1.0,  10),
+            ("ea_leaf_kpa",    1.0,  10),
+            ("es_kpa",         1.0,  10),
+            ("vpd_kpa",        1.0,  10),
+        ],
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutdoorPolicy {
+    /// Highest-priority fresh station only; the next one takes over while it is stale.
+    PreferPrimary,
+    /// Average the two highest-priority fresh stations when they agree within tolerance.
+    BlendWhenAgree,
+}
+
+#[derive(Clone, Copy)]
+pub struct OutdoorConfig {
+    /// Outdoor station ids (22-byte frames) in priority order; unlisted stations rank after these by id.
+    pub station_priority: &'static [u16],
+    pub policy: OutdoorPolicy,
+    /// Two fresh stations further apart than this disagree (no blend, `disagree` on the snapshot).
+    pub agree_air_temp_c: f32,
+    pub agree_air_rh_pct: f32,
+    /// Rain gauge calibration: millimetres of rain per bucket tip (weather frames, `rain_mm`).
+    pub rain_mm_per_tip: f32,
+}
+
+/// Greenhouse outdoor reference built from one or more outdoor stations (see greenhouse_aggregator.rs).
+pub const fn outdoor() -> OutdoorConfig {
+    OutdoorConfig {
+        station_priority: &[65001, 65002],
+        policy: OutdoorPolicy::PreferPrimary,
+        agree_air_temp_c: 1.5,
+        agree_air_rh_pct: 8.0,
+        rain_mm_per_tip: 0.2,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ConfidenceConfig {
+    /// Fraction of roster nodes that must contribute for High / Medium; below Medium is Low.
+    pub high_min_coverage: f32,
+    pub medium_min_coverage: f32,
+    /// Below this coverage no greenhouse average is published (`gh_insufficient_data` instead); None publishes always.
+    pub suppress_below: Option<f32>,
+    /// Expected indoor nodes per greenhouse; the roster is the larger of this and the stored / reporting indoor nodes.
+    pub expected_nodes: &'static [(u16, usize)],
+}
+
+/// Greenhouse average confidence from roster coverage (see greenhouse_aggregator.rs).
+pub const fn gh_confidence() -> ConfidenceConfig {
+    ConfidenceConfig {
+        high_min_coverage: 0.75,
+        medium_min_coverage: 0.5,
+        suppress_below: None,
+        expected_nodes: &[],
     }
 }
+
+#[derive(Clone, Copy)]
+pub struct NodeAckConfig {
+    /// Ack mode switches itself off after this long (also the default when the command gives none).
+    pub max_duration_secs: u64,
+    /// At most one ack / nack per node per interval; frames in between are counted, not acked.
+    pub min_interval_ms: u64,
+}
+
+/// Per-node decode acknowledgements on `greenhouse/{gh}/node/{id}/ack` (see ack.rs).
+pub const fn node_ack() -> NodeAckConfig {
+    NodeAckConfig {
+        max_duration_secs: 30 * 60,
+        min_interval_ms: 1_000,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectConfig {
+    pub base_ms: u64,
+    pub max_ms: u64,
+    /// Each delay is spread by up to ± this percentage.
+    pub jitter_pct: u64,
+    /// The backoff resets only after a connection has stayed up this long.
+    pub healthy_after_secs: u64,
+    /// Continuous failure is logged as an error this often.
+    pub loud_every_secs: u64,
+}
+
+/// Reconnect backoff of the sensor subscriber and the publisher (backoff.rs).
+pub const fn reconnect() -> ReconnectConfig {
+    ReconnectConfig {
+        base_ms: 250,
+        max_ms: 10_000,
+        jitter_pct: 25,
+        healthy_after_secs: 30,
+        loud_every_secs: 60,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TopicIdPolicy {
+    /// Drop frames whose payload ids differ from the topic's (misflashed node).
+    Drop,
+    /// Log the mismatch but keep the frame under its payload ids.
+    Warn,
+}
+
+/// What the subscriber does with data frames the broker delivers as retained (its last stored frame
+/// per topic, possibly minutes old).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RetainedPolicy {
+    /// Treat them like live frames (frames with `device_ts` are still windowed by their own time).
+    Keep,
+    /// Keep them on the first connection after startup (instant UI fill), drop them after a reconnect.
+    FirstConnect,
+    /// Always drop them.
+    Drop,
+}
+
+#[derive(Clone, Copy)]
+pub struct SubscriberConfig {
+    /// What to do when `greenhouse/{gh}/node/{id}/data` disagrees with the ids inside the frame.
+    pub topic_id_mismatch: TopicIdPolicy,
+    /// An identical payload from the same node within this window is a duplicate delivery (dedup.rs).
+    pub duplicate_window_ms: u64,
+    /// Retained data frames (status topics are always read, see availability.rs).
+    pub retained: RetainedPolicy,
+}
+
+/// Checks on incoming node frames (see subscriber.rs).
+pub const fn subscriber() -> SubscriberConfig {
+    SubscriberConfig {
+        topic_id_mismatch: TopicIdPolicy::Drop,
+        duplicate_window_ms: 2_000,
+        retained: RetainedPolicy::FirstConnect,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct FrameMacConfig<'a> {
+    /// JSON object of greenhouse id -> hex HMAC-SHA256 key, e.g. `{"1": "9f2c..."}`; kept out of the
+    /// build and the repo. A missing file means no greenhouse is keyed.
+    pub keys_file: &'a str,
+}
+
+/// Signed node frames (see frame_mac.rs).
+pub const fn frame_mac() -> FrameMacConfig<'static> {
+    FrameMacConfig {
+        keys_file: "../data/frame_keys.json",
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct GhOriginConfig<'a> {
+    /// Extra subscription for frames bridged in from other sites (`{origin}/greenhouse/...`); None = local only.
+    pub bridged_topic: Option<&'a str>,
+    /// An origin not heard from for this long no longer counts towards a duplicate-id conflict.
+    pub forget_after_secs: u64,
+}
+
+/// Duplicate greenhouse ids across sites sharing one broker (see origin.rs).
+pub const fn gh_origin() -> GhOriginConfig<'static> {
+    GhOriginConfig {
+        bridged_topic: Some("+/greenhouse/+/node/+/data"),
+        forget_after_secs: 3_600,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct NodeDiscoveryConfig<'a> {
+    /// Subscription of the scan's own connection (everything on the broker by default).
+    pub topic_filter: &'a str,
+    /// Longest scan `discover_nodes` accepts (also the default).
+    pub max_duration_secs: u64,
+    /// Distinct topics kept per scan; later topics are counted in `dropped_topics` only.
+    pub max_topics: usize,
+}
+
+/// On-site node discovery scan (see discovery.rs).
+pub const fn node_discovery() -> NodeDiscoveryConfig<'static> {
+    NodeDiscoveryConfig {
+        topic_filter: "#",
+        max_duration_secs: 300,
+        max_topics: 1_000,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RemoteCmdConfig<'a> {
+    /// Topics are `greenhouse/app/{client_id}/cmd` and `.../resp`. The HMAC key shared with the
+    /// integrator is `remote_cmd_secret` in mqtt.toml (auth.rs); the listener runs only with one.
+    pub client_id: &'a str,
+    /// Commands whose `ts_ms` is further than this from our clock are rejected (replay window).
+    pub max_skew_secs: i64,
+    /// Accepted command ids remembered for replay checks (those within the skew window); a full
+    /// cache rejects further commands until ids age out.
+    pub max_seen_ids: usize,
+}
+
+/// Remote configuration listener for the site integrator (see remote_cmd.rs).
+pub const fn remote_cmd() -> RemoteCmdConfig<'static> {
+    RemoteCmdConfig {
+        client_id: "site-01",
+        max_skew_secs: 300,
+        max_seen_ids: 256,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RawCaptureConfig {
+    /// Publishes kept; 0 turns capturing off.
+    pub entries: usize,
+    /// Longer payloads keep only their head.
+    pub max_payload_bytes: usize,
+}
+
+/// Ring of recent raw publishes for `dump_raw_payloads` (raw_capture.rs); about 1 MB at most.
+pub const fn raw_capture() -> RawCaptureConfig {
+    RawCaptureConfig {
+        entries: 500,
+        max_payload_bytes: 2048,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct NodeRatesConfig {
+    /// A node that published at least this many messages in one minute and fewer in the next is
+    /// logged as slow (nodes publish every ~10 s, i.e. 6 / min).
+    pub slow_below_per_min: u32,
+    /// Minutes a silent node stays in the report (rate 0) before it is dropped.
+    pub keep_silent_minutes: u32,
+}
+
+/// Publishes per node and minute (node_rates.rs).
+pub const fn node_rates() -> NodeRatesConfig {
+    NodeRatesConfig {
+        slow_below_per_min: 3,
+        keep_silent_minutes: 10,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct NodeLivenessConfig {
+    /// A node whose last sample is this many of its windows old is `late`,
+    pub late_after_windows: u32,
+    /// and `offline` at this many.
+    pub offline_after_windows: u32,
+}
+
+/// Node state from its samples, checked on every window tick (liveness.rs).
+pub const fn node_liveness() -> NodeLivenessConfig {
+    NodeLivenessConfig {
+        late_after_windows: 2,
+        offline_after_windows: 5,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ControlPublishConfig<'a> {
+    /// Topics `publish_mqtt` may publish to: a topic must start with one of these, `+` matching one level.
+    pub allowed_prefixes: &'a [&'a str],
+    pub max_payload_bytes: usize,
+}
+
+/// Commands from the dashboard to nodes (publisher.rs).
+pub const fn control_publish() -> ControlPublishConfig<'static> {
+    ControlPublishConfig {
+        allowed_prefixes: &["greenhouse/+/node/+/cmd"],
+        max_payload_bytes: 4096,
+    }
+}
+
+/// One configured derived metric: `key = expr` over per-window means (see derived.rs).
+#[derive(Clone, Copy)]
+pub struct DerivedDef<'a> {
+    pub key: &'a str,
+    pub unit: &'a str,
+    /// Decimals kept when the value is computed (storage and UI keep at most two).
+    pub precision: u32,
+    /// Arithmetic over sensor keys and earlier-or-later derived keys: + - * / ^, parentheses,
+    /// abs() sqrt() exp() ln() min(,) max(,).
+    pub expr: &'a str,
+}
+
+pub const fn derived_metrics() -> &'static [DerivedDef<'static>] {
+    &[
+        // Magnus formula (Sonntag constants); condensation risk when leaf temp nears it
+        DerivedDef {
+            key: "dew_point_c",
+            unit: "C",
+            precision: 2,
+            expr: "243.12 * (ln(air_rh_pct / 100) + 17.62 * air_temp_c / (243.12 + air_temp_c))
+                   / (17.62 - (ln(air_rh_pct / 100) + 17.62 * air_temp_c / (243.12 + air_temp_c)))",
+        },
+    ]
+}
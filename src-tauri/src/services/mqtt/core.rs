@@ -1,15 +1,155 @@
-use rumqttc::{AsyncClient, EventLoop, MqttOptions};
-use std::time::Duration;
-use super::config::MqttAuth;
-
-/// Very small internal queues to avoid memory bloat.
-pub fn new_client(client_id_suffix: &str, auth: MqttAuth) -> (AsyncClient, EventLoop) {
-    let mut opts = MqttOptions::new(
-        format!("{}-{}", auth.client_id_prefix, client_id_suffix),
-        auth.host,
-        auth.port,
-    );
-    opts.set_credentials(auth.username, auth.password);
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, Transport};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+use tracing::info;
+use super::auth::{MqttAuth, MqttTransport};
+
+/// `{prefix}-{client}-{machine id}`; unique per installation so two PCs do not take over each other's session.
+pub fn client_id(client_id_suffix: &str, auth: &MqttAuth) -> String {
+    if auth.machine_id.is_empty() {
+        format!("{}-{}", auth.client_id_prefix, client_id_suffix)
+    } else {
+        format!("{}-{}-{}", auth.client_id_prefix, client_id_suffix, auth.machine_id)
+    }
+}
+
+/// Where `auth` connects to, e.g. `wss://cloud.example.com:443/mqtt`; never carries the login.
+pub fn broker_url(auth: &MqttAuth) -> String {
+    match auth.transport {
+        MqttTransport::Tcp => format!("mqtt://{}:{}", auth.host, auth.port),
+        MqttTransport::Tls => format!("mqtts://{}:{}", auth.host, auth.port),
+        MqttTransport::Ws => format!("ws://{}:{}{}", auth.host, auth.port, auth.path),
+        MqttTransport::Wss => format!("wss://{}:{}{}", auth.host, auth.port, auth.path),
+    }
+}
+
+/// Options for `auth`: transport (WebSocket clients take the whole URL as their address), keep-alive,
+/// login, session and packet limits.
+pub fn options(id: String, auth: &MqttAuth) -> MqttOptions {
+    let mut opts = transport_options(id, auth);
+    if !auth.username.is_empty() { opts.set_credentials(auth.username.as_str(), auth.password.as_str()); }
     opts.set_keep_alive(Duration::from_secs(auth.keep_alive_secs as u64));
-    AsyncClient::new(opts, 10)
+    opts.set_clean_session(auth.clean_session);
+    opts.set_inflight(auth.max_inflight);
+    opts.set_max_packet_size(auth.max_packet_size, auth.max_packet_size);
+    opts
+}
+
+fn transport_options(id: String, auth: &MqttAuth) -> MqttOptions {
+    match auth.transport {
+        MqttTransport::Tcp => MqttOptions::new(id, auth.host.as_str(), auth.port),
+        MqttTransport::Tls => {
+            let mut opts = MqttOptions::new(id, auth.host.as_str(), auth.port);
+            opts.set_transport(Transport::tls_with_default_config());
+            opts
+        }
+        #[cfg(feature = "websocket")]
+        MqttTransport::Ws => {
+            let mut opts = MqttOptions::new(id, broker_url(auth), auth.port);
+            opts.set_transport(Transport::ws());
+            opts
+        }
+        #[cfg(feature = "websocket")]
+        MqttTransport::Wss => {
+            let mut opts = MqttOptions::new(id, broker_url(auth), auth.port);
+            opts.set_transport(Transport::wss_with_default_config());
+            opts
+        }
+        // refused when the settings are read (auth.rs)
+        #[cfg(not(feature = "websocket"))]
+        MqttTransport::Ws | MqttTransport::Wss => MqttOptions::new(id, auth.host.as_str(), auth.port),
+    }
+}
+
+/// Request queue of `auth.eventloop_capacity`. Keep-alive and login are the same on every transport.
+pub fn new_client(client_id_suffix: &str, auth: &MqttAuth) -> (AsyncClient, EventLoop) {
+    new_client_with(client_id_suffix, auth, None)
+}
+
+/// `new_client` with an optional Last Will, which the broker publishes when the connection dies
+/// without a DISCONNECT.
+pub fn new_client_with(client_id_suffix: &str, auth: &MqttAuth, last_will: Option<LastWill>) -> (AsyncClient, EventLoop) {
+    let id = client_id(client_id_suffix, auth);
+    info!(target: "MQTT", "connecting to {} as '{id}'", broker_url(auth));
+    let mut opts = options(id, auth);
+    if let Some(will) = last_will { opts.set_last_will(will); }
+    AsyncClient::new(opts, auth.eventloop_capacity)
+}
+
+/// Outcome of a successful `test_connection`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionTest {
+    pub url: String,
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub connect_ms: u64,
+}
+
+/// Connect once with `auth` on a throwaway client and disconnect again; the error is the broker's or
+/// the network's answer (a refused login reads e.g. `BadUserNamePassword`).
+pub async fn test_connection(auth: &MqttAuth, wait: Duration) -> Result<ConnectionTest, String> {
+    let started = Instant::now();
+    let (client, mut eventloop) = new_client("connection-test", auth);
+    let connected = timeout(wait, async {
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => return Ok(()),
+                Ok(_) => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }).await;
+    let connect_ms = started.elapsed().as_millis() as u64;
+    match connected {
+        Ok(Ok(())) => {
+            let _ = client.try_disconnect();
+            let _ = timeout(Duration::from_millis(500), eventloop.poll()).await;
+            Ok(ConnectionTest { url: broker_url(auth), host: auth.host.clone(), port: auth.port, username: auth.username.clone(), connect_ms })
+        }
+        Ok(Err(e)) => Err(format!("connection to {} failed: {e}", broker_url(auth))),
+        Err(_) => Err(format!("no answer from {} within {}s", broker_url(auth), wait.as_secs())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rumqttc::QoS;
+
+    #[test]
+    fn options_reflect_the_session_settings() {
+        let mut auth = MqttAuth::sample("gateway", 1884);
+        (auth.username, auth.password) = ("grower".into(), "secret".into());
+        (auth.keep_alive_secs, auth.clean_session, auth.max_inflight, auth.max_packet_size) = (45, false, 7, 65_536);
+        auth.machine_id = "pc1".into();
+        let opts = options(client_id("sensor-subscriber", &auth), &auth);
+
+        assert_eq!(opts.broker_address(), ("gateway".to_string(), 1884));
+        assert_eq!(opts.client_id(), "tauri-greenhouse-sensor-subscriber-pc1");
+        assert_eq!(opts.credentials(), Some(("grower".to_string(), "secret".to_string())));
+        assert_eq!(opts.keep_alive(), Duration::from_secs(45));
+        assert!(!opts.clean_session());
+        assert_eq!(opts.inflight(), 7);
+        assert_eq!(opts.max_packet_size(), 65_536);
+    }
+
+    #[test]
+    fn no_username_means_no_credentials() {
+        let opts = options("id".into(), &MqttAuth::sample("gateway", 1883));
+        assert_eq!(opts.credentials(), None);
+        assert!(opts.clean_session());
+    }
+
+    #[test]
+    fn request_queue_holds_eventloop_capacity() {
+        let mut auth = MqttAuth::sample("gateway", 1883);
+        auth.eventloop_capacity = 3;
+        let (client, _eventloop) = new_client("queue-test", &auth);
+        for i in 0..3 {
+            assert!(client.try_publish("t", QoS::AtMostOnce, false, vec![i]).is_ok(), "request {i}");
+        }
+        assert!(client.try_publish("t", QoS::AtMostOnce, false, vec![3]).is_err(), "queue is full");
+    }
 }
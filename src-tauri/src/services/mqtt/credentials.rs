@@ -0,0 +1,30 @@
+//! MQTT password in the OS credential store (Windows Credential Manager, macOS Keychain, Secret Service /
+//! libsecret on Linux) instead of plaintext in `mqtt.toml` on shared greenhouse PCs.
+//! - One entry per login: service `SERVICE`, user `{username}@{host}:{port}`.
+//! - Read by the settings loader (auth.rs) before the file's `password`; on the first start with a file
+//!   password and no entry, the password is copied into the store so the file line can be removed.
+//! - `set_mqtt_password` stores a new one; it is used from the next connect on (running clients pick it
+//!   up when they reconnect), and `test_mqtt_connection` tries it without a restart.
+//! - Errors carry the store's message only; the password itself is never logged or returned.
+
+use keyring::{Entry, Error};
+
+pub const SERVICE: &str = "apptest-greenhouse-mqtt";
+
+fn entry(username: &str, host: &str, port: u16) -> Result<Entry, String> {
+    Entry::new(SERVICE, &format!("{username}@{host}:{port}")).map_err(|e| format!("credential store unavailable: {e}"))
+}
+
+/// Stored password of the login; None when there is no entry.
+pub fn load_password(username: &str, host: &str, port: u16) -> Result<Option<String>, String> {
+    match entry(username, host, port)?.get_password() {
+        Ok(p) => Ok(Some(p)),
+        Err(Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("credential store read failed: {e}")),
+    }
+}
+
+/// Store (or replace) the password of the login.
+pub fn store_password(username: &str, host: &str, port: u16, password: &str) -> Result<(), String> {
+    entry(username, host, port)?.set_password(password).map_err(|e| format!("credential store write failed: {e}"))
+}
@@ -0,0 +1,196 @@
+//! Broker failover of the sensor subscriber: `[[broker]]` in mqtt.toml lists brokers in order of
+//! preference (e.g. the gateway, then the office backup); without it there is one.
+//! - Every reconnect attempt goes to the next broker, round-robin. Each broker has its own backoff
+//!   (backoff.rs), so a dead primary only delays retries of the primary: the backup is tried at once.
+//! - Attempt count, downtime and the once-a-minute error log run across all brokers until any
+//!   connection has been healthy for a while, as with a single broker.
+//! - The broker of the last CONNACK is kept in `LAST_BROKER_FILE` (auth.rs) and tried first on the
+//!   next start. There is no automatic fail-back: the subscriber stays on a working backup.
+//! - The broker in use rides on every `mqtt_status` event (`broker`, conn_status.rs).
+
+use std::time::{Duration, Instant};
+
+use super::auth::BrokerEndpoint;
+use super::backoff::Backoff;
+use super::config::ReconnectConfig;
+
+/// Next try after a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailoverRetry {
+    /// Failed tries on any broker since a connection was last healthy (1 = first retry).
+    pub attempt: u32,
+    /// Until the next broker's own backoff has run out; 0 when it can be tried at once.
+    pub delay_ms: u64,
+    pub downtime_ms: u64,
+    /// Another `loud_every_secs` of continuous failure has passed.
+    pub loud: bool,
+    /// Index of the broker tried next.
+    pub next: usize,
+}
+
+#[derive(Debug)]
+pub struct Failover {
+    brokers: Vec<BrokerEndpoint>,
+    /// Per broker: the retry delay.
+    backoffs: Vec<Backoff>,
+    /// Per broker: not to be tried before.
+    due: Vec<Instant>,
+    /// Across brokers: attempt, downtime, loud.
+    overall: Backoff,
+    current: usize,
+}
+
+impl Failover {
+    /// Start at `preferred` (falls back to the first broker when out of range); `brokers` must not be empty.
+    pub fn new(brokers: Vec<BrokerEndpoint>, preferred: usize, cfg: ReconnectConfig, now: Instant) -> Self {
+        let n = brokers.len();
+        Failover {
+            backoffs: (0..n).map(|_| Backoff::new(cfg)).collect(),
+            due: vec![now; n],
+            overall: Backoff::new(cfg),
+            current: if preferred < n { preferred } else { 0 },
+            brokers,
+        }
+    }
+
+    /// Index and address of the broker to connect to.
+    pub fn current(&self) -> (usize, &BrokerEndpoint) {
+        (self.current, &self.brokers[self.current])
+    }
+
+    /// CONNACK from the current broker.
+    pub fn on_connected(&mut self, now: Instant) {
+        self.backoffs[self.current].on_connected(now);
+        self.overall.on_connected(now);
+    }
+
+    /// Traffic on the current connection.
+    pub fn on_alive(&mut self, now: Instant) {
+        self.backoffs[self.current].on_alive(now);
+        self.overall.on_alive(now);
+    }
+
+    /// The current broker failed; moves on to the next one. `jitter` in -1..=1 as in `Backoff`.
+    pub fn on_failure_with(&mut self, now: Instant, jitter: f64) -> FailoverRetry {
+        let failed = self.backoffs[self.current].on_failure_with(now, jitter);
+        self.due[self.current] = now + Duration::from_millis(failed.delay_ms);
+        let overall = self.overall.on_failure_with(now, jitter);
+        self.current = (self.current + 1) % self.brokers.len();
+        let delay_ms = self.due[self.current].saturating_duration_since(now).as_millis() as u64;
+        FailoverRetry { attempt: overall.attempt, delay_ms, downtime_ms: overall.downtime_ms, loud: overall.loud, next: self.current }
+    }
+
+    /// `on_failure_with` a random jitter.
+    pub fn on_failure(&mut self, now: Instant) -> FailoverRetry {
+        self.on_failure_with(now, fastrand::f64() * 2.0 - 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use rumqttc::{Event, Packet};
+    use crate::services::mqtt::auth::MqttAuth;
+    use crate::services::mqtt::config::reconnect;
+    use crate::services::mqtt::core::new_client;
+
+    fn cfg() -> ReconnectConfig {
+        ReconnectConfig { base_ms: 250, max_ms: 10_000, jitter_pct: 25, healthy_after_secs: 30, loud_every_secs: 60 }
+    }
+
+    fn brokers(n: u16) -> Vec<BrokerEndpoint> {
+        (0..n).map(|i| BrokerEndpoint { host: format!("broker{i}"), port: 1883 }).collect()
+    }
+
+    #[test]
+    fn a_dead_primary_does_not_delay_the_backups() {
+        let t0 = Instant::now();
+        let mut f = Failover::new(brokers(3), 0, cfg(), t0);
+        let tries: Vec<_> = (0..3).map(|_| f.on_failure_with(t0, 0.0)).map(|r| (r.next, r.delay_ms, r.attempt)).collect();
+        // each backup is tried at once; back at the primary its own 250 ms are still running
+        assert_eq!(tries, [(1, 0, 1), (2, 0, 2), (0, 250, 3)]);
+
+        // second round: the backups' first delays ran out meanwhile, the primary now waits its doubled 500 ms
+        let t1 = t0 + Duration::from_millis(250);
+        let tries: Vec<_> = (0..3).map(|_| f.on_failure_with(t1, 0.0)).map(|r| (r.next, r.delay_ms, r.attempt)).collect();
+        assert_eq!(tries, [(1, 0, 4), (2, 0, 5), (0, 500, 6)]);
+    }
+
+    #[test]
+    fn starts_at_the_remembered_broker() {
+        let t0 = Instant::now();
+        let f = Failover::new(brokers(3), 2, cfg(), t0);
+        assert_eq!(f.current(), (2, &brokers(3)[2]));
+        // a broker dropped from the list since: back to the primary
+        let f = Failover::new(brokers(2), 2, cfg(), t0);
+        assert_eq!(f.current().0, 0);
+    }
+
+    #[test]
+    fn a_healthy_connection_resets_the_count() {
+        let t0 = Instant::now();
+        let mut f = Failover::new(brokers(2), 0, cfg(), t0);
+        for _ in 0..5 { f.on_failure_with(t0, 0.0); }
+        let t1 = t0 + Duration::from_secs(1);
+        f.on_connected(t1);
+        f.on_alive(t1 + Duration::from_secs(31));
+        let r = f.on_failure_with(t1 + Duration::from_secs(32), 0.0);
+        assert_eq!((r.attempt, r.delay_ms, r.downtime_ms), (1, 0, 0));
+    }
+
+    #[test]
+    fn a_single_broker_backs_off_as_before() {
+        let t0 = Instant::now();
+        let mut f = Failover::new(brokers(1), 0, cfg(), t0);
+        let delays: Vec<_> = (0..4).map(|_| f.on_failure_with(t0, 0.0)).map(|r| (r.next, r.delay_ms)).collect();
+        assert_eq!(delays, [(0, 250), (0, 500), (0, 1_000), (0, 2_000)]);
+    }
+
+    /// A port nothing listens on: connecting is refused at once.
+    fn closed_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    /// Accepts one client, answers its CONNECT with a CONNACK and keeps the connection open.
+    fn fake_broker() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 256];
+            let _ = s.read(&mut buf);
+            s.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+            let _ = s.read(&mut buf);
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn switches_over_past_unreachable_brokers() {
+        let local = |port| BrokerEndpoint { host: "127.0.0.1".into(), port };
+        let list = vec![local(closed_port()), local(closed_port()), local(fake_broker())];
+        let mut failover = Failover::new(list, 0, reconnect(), Instant::now());
+        let started = Instant::now();
+        let mut tried = Vec::new();
+        let connected = loop {
+            let (index, broker) = failover.current();
+            tried.push(index);
+            assert!(tried.len() <= 3, "no switchover: {tried:?}");
+            let (_client, mut eventloop) = new_client("failover-test", &MqttAuth::sample(&broker.host, broker.port));
+            match tokio::time::timeout(Duration::from_secs(5), eventloop.poll()).await.expect("connect timed out") {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => break index,
+                Ok(other) => panic!("unexpected {other:?}"),
+                Err(_) => {
+                    let r = failover.on_failure(Instant::now());
+                    tokio::time::sleep(Duration::from_millis(r.delay_ms)).await;
+                }
+            }
+        };
+        failover.on_connected(Instant::now());
+        assert_eq!((connected, tried), (2, vec![0, 1, 2]));
+        // no backoff on the way to the healthy backup
+        assert!(started.elapsed() < Duration::from_millis(reconnect().base_ms), "{:?}", started.elapsed());
+    }
+}
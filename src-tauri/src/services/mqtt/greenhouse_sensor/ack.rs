@@ -0,0 +1,109 @@
+//! Opt-in per-node decode acknowledgements for firmware bring-up.
+//! - `set_node_ack` turns ack mode on for one node; it switches itself off after at most
+//!   `node_ack().max_duration_secs`, so it cannot be left on by accident.
+//! - While on, every frame from the node gets an ack (decoded) or a nack (DecodeError name) on
+//!   `greenhouse/{gh}/node/{id}/ack`, published QoS 0 through the subscriber's own connection with
+//!   `try_publish`, so the hot path never waits on it.
+//! - Rate-limited per node (`min_interval_ms`); frames in between are counted in `skipped`.
+//! - Nodes send no sequence number or device time, so acks carry the app's per-node frame count and
+//!   receive time instead. Acks are diagnostics, not commands: nothing here touches `command_audit`.
+
+use serde::Serialize;
+use std::{collections::HashMap, sync::{Arc, RwLock}, time::{Duration, Instant}};
+use tracing::info;
+
+use super::decoder::{DecodeError, Decoded};
+use crate::services::mqtt::config::node_ack;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct AckWindow {
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+    pub until_ms: i64,
+}
+
+/// Nodes with ack mode on, by (greenhouse_id, node_id).
+pub type AckShared = Arc<RwLock<HashMap<(u16, u16), AckWindow>>>;
+
+/// Turn ack mode on for `secs` (default and cap: `max_duration_secs`).
+pub fn enable(shared: &AckShared, gh_id: u16, node_id: u16, secs: Option<u64>, now_ms: i64) -> AckWindow {
+    let max = node_ack().max_duration_secs;
+    let secs = secs.unwrap_or(max).clamp(1, max);
+    let w = AckWindow { greenhouse_id: gh_id, node_id, until_ms: now_ms + secs as i64 * 1000 };
+    if let Ok(mut m) = shared.write() { m.insert((gh_id, node_id), w); }
+    info!(target: "ACK", "GH:{gh_id} Node:{node_id} ack mode on for {secs}s");
+    w
+}
+
+/// Turn ack mode off; false when it was not on.
+pub fn disable(shared: &AckShared, gh_id: u16, node_id: u16) -> bool {
+    shared.write().map(|mut m| m.remove(&(gh_id, node_id)).is_some()).unwrap_or(false)
+}
+
+/// (greenhouse_id, node_id) from `greenhouse/{gh}/node/{id}/data`, for frames that did not decode.
+pub fn ids_from_topic(topic: &str) -> Option<(u16, u16)> {
+    let mut parts = topic.split('/');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("greenhouse"), Some(gh), Some("node"), Some(id)) => Some((gh.parse().ok()?, id.parse().ok()?)),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+struct NodeAckState {
+    last_sent: Option<Instant>,
+    frames: u64,
+    skipped: u64,
+}
+
+/// Subscriber-side ack bookkeeping (one per subscriber task).
+#[derive(Debug, Default)]
+pub struct Acker {
+    nodes: HashMap<(u16, u16), NodeAckState>,
+}
+
+impl Acker {
+    /// (topic, payload) to publish for this frame, if the node is in ack mode and not rate-limited.
+    pub fn on_frame(
+        &mut self,
+        shared: &AckShared,
+        ids: (u16, u16),
+        outcome: Result<&Decoded, DecodeError>,
+        bytes: usize,
+        received_ms: i64,
+        decode_us: u64,
+    ) -> Option<(String, Vec<u8>)> {
+        let until_ms = shared.read().ok()?.get(&ids)?.until_ms;
+        if received_ms >= until_ms {
+            disable(shared, ids.0, ids.1);
+            self.nodes.remove(&ids);
+            info!(target: "ACK", "GH:{} Node:{} ack mode expired", ids.0, ids.1);
+            return None;
+        }
+        let st = self.nodes.entry(ids).or_insert(NodeAckState { last_sent: None, frames: 0, skipped: 0 });
+        st.frames += 1;
+        let now = Instant::now();
+        if st.last_sent.is_some_and(|t| now.duration_since(t) < Duration::from_millis(node_ack().min_interval_ms)) {
+            st.skipped += 1;
+            return None;
+        }
+        st.last_sent = Some(now);
+        let mut payload = serde_json::json!({
+            "ok": outcome.is_ok(),
+            "frame": st.frames,
+            "skipped": st.skipped,
+            "received_ms": received_ms,
+            "decode_us": decode_us,
+            "bytes": bytes,
+            "ack_until_ms": until_ms,
+        });
+        st.skipped = 0;
+        match outcome {
+            Ok(Decoded::Standard { .. }) => payload["kind"] = "standard".into(),
+            Ok(Decoded::Outdoor { .. }) => payload["kind"] = "outdoor".into(),
+            Ok(Decoded::Soil { .. }) => payload["kind"] = "soil".into(),
+            Err(e) => payload["error"] = e.name().into(),
+        }
+        Some((format!("greenhouse/{}/node/{}/ack", ids.0, ids.1), payload.to_string().into_bytes()))
+    }
+}
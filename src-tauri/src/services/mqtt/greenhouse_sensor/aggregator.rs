@@ -1,33 +1,337 @@
-//! Per-node 60s rolling averages (time-based, resilient to dropouts).
-//! - Non-blocking: decoded samples come in via mpsc::Receiver<Decoded>.
-//! - Every 60s we compute means for the last 60s window and:
+//! Per-node rolling averages over the configured window (time-based, resilient to dropouts).
+//! - Non-blocking: decoded samples come in via the subscriber's bounded queue (channels.rs).
+//! - Samples pass the slew-rate guard (sanitize.rs) before entering a window.
+//! - The window is `aggregation_window()` (60s by default, `aggregation().window_secs` or
+//!   `APPTEST_WINDOW_SECS`), resolved once in main.rs and shared with the greenhouse aggregator; rows
+//!   are stored as `rolling_{window_sec}s`. An invalid value stops the app at startup.
+//! - Every window we compute means for the last window (plus derived `leaf_air_dt_c`,
+//!   mean leaf minus mean air temperature, our main plant stress indicator, and the
+//!   configured metrics from derived.rs) and:
 //!     * Print one compact line per node with **two decimals** everywhere.
-//!     * Emit NodeAvg to BOTH: DB writer and greenhouse aggregator.
+//!     * Emit NodeAvg to BOTH: DB writer and greenhouse aggregator (droppable lanes, see channels.rs).
+//! - Outdoor stations publish about once a minute, so they get their own, longer window
+//!   (`aggregation().outdoor_window_secs`) and emit on that cadence; `window_sec` says which.
+//! - Node telemetry (`battery_v`, `rssi_dbm`; standard v2+ and outdoor telemetry frames) is averaged
+//!   like a reading and stored as node rows when present; nodes on older firmware simply have none.
+//! - Outdoor weather frames add wind and rain, which are not means: mean wind, max gust and summed rain
+//!   tips (as mm) per window, via the per-field `Agg` accumulator (math.rs). Stored only when present.
+//! - Soil nodes (substrate VWC / EC) use the indoor window and carry their means in `soil` only; the
+//!   greenhouse aggregator ignores them for now.
+//! - Windows overlapping a node maintenance window are flagged (`maintenance`): still stored and
+//!   shown, but left out of greenhouse averages (see node_maintenance.rs).
+//! - Frames with a sequence counter (v3 / JSON `seq`) give packet loss per window: gaps between
+//!   consecutive numbers, modulo 65536. A backwards jump larger than `MAX_SEQ_GAP` is a node reboot
+//!   (the counter restarted at 0), not loss; a smaller one is a reordered frame, received late and no
+//!   longer lost. Stored as `received_packets` / `lost_packets` node rows.
+//! - Frames with a node clock (v4 / JSON `ts`) are windowed by measurement time, not arrival time, so
+//!   a burst published after a Wi-Fi dropout lands in the minute it was measured. Samples from before
+//!   the node's open window are back-filled: collected per past window (on the grid of the node's
+//!   window), closed once a tick passes without a new one and sent as `catchup` windows to the DB
+//!   writer only (stored with source 'catchup', see history.rs). They skip the slew guard and the live
+//!   snapshot. A late sample whose window is already stored (or overlaps the open one) is dropped,
+//!   counted (`late_dropped`, decoder_stats.rs) and logged per node and tick. A clock more than
+//!   `aggregation().max_device_skew_secs` ahead of local time, or more than `max_backfill_secs` behind
+//!   it, is wrong and ignored (arrival time, as for old frames). `measured_ms` carries the newest
+//!   measurement time to the DB writer.
+//! - Startup catch-up: with a persistent session (`clean_session = false` in mqtt.toml, QoS 1
+//!   subscriptions) the broker delivers what it queued while the app was closed right after connecting.
+//!   Those v4 samples take the back-fill path above, so the live windows only see current ones. Once a
+//!   current sample has arrived and every past window is closed, a `CatchupReport` (windows rebuilt,
+//!   their samples, late samples dropped, nodes, time span) goes out as `catchup_complete`, once per run.
+//!   No greenhouse averages are written for the rebuilt windows.
+//! - The key fields (air temperature, RH, VPD, PAR, weight) also carry their window min / max
+//!   (`Extremes`), accumulated with the means in one pass (`Stats`, math.rs) and stored as
+//!   `min_{window_sec}s` / `max_{window_sec}s` rows next to the rolling ones. Air temperature, RH and
+//!   VPD add their within-window standard deviation (`Spread`, `sd_{window_sec}s` rows) for uniformity
+//!   analysis; None below two samples.
+//! - Sensor readings are reduced per `aggregation().mode` (or `APPTEST_AGG_MODE`) / `field_modes`:
+//!   the mean (default), the median or a trimmed mean, e.g. median PAR against single-sample spikes.
+//!   Other modes keep the window's samples per field (bounded by the sample cap) and are named in the
+//!   row's `agg` (`node_agg`: `rolling_median_60s`, `rolling_trim10_60s`). Derived metrics use the
+//!   reduced values.
+//! - Every reading carries the number of finite samples behind it (`SampleCounts`, after outlier
+//!   rejection): shown in `node_avg` as `samples` and stored in the `samples` column of its rows, so a
+//!   mean of one reading is told apart from a mean of six.
+//! - Windows close on the wall-clock grid (multiples of the window since the epoch, `align_tick`) and
+//!   `ts_ms` is that close, so the DB writer stamps rows with it rather than the flush time.
+//! - Between windows, every `live_snapshot().every_secs` (5s) the latest sample of each node goes to
+//!   the UI as `NodeLive` (`node_live`), for nodes heard from in the last `max_age_secs` (15s). Never
+//!   stored, and kept apart from the window buffers.
+//! - Standard nodes add `transpiration_g_min`: the least-squares weight slope over the window, as a loss
+//!   rate, fitted up to the first rise above `aggregation().irrigation_jump_g` (an irrigation shot).
+//! - Every tick also rates each node online / late / offline by its last sample (liveness.rs) and sends
+//!   the changes as `NodeStatus`.
+//! - Optional outlier rejection (`aggregation().reject_outliers`, off by default): before a reading is
+//!   reduced, samples further than `outlier_mad_k` (5) median absolute deviations from the window
+//!   median are dropped, e.g. one frozen 0.0 °C read among 24 °C ones. Windows with fewer than
+//!   `MIN_OUTLIER_SAMPLES` samples are left alone. Extremes and deviations describe the kept samples;
+//!   rejections are logged and counted per field in the node health (`outliers`).
+//! - Every window sent to the UI is also kept as the node's latest (`LatestNodeShared`) for remote
+//!   queries (query.rs).
+//! - When the decoded queue closes (app shutdown, shutdown.rs) every node with samples gets one last,
+//!   partial window and the task returns, closing the node lanes behind it.
 //! - RAM-only buffers, bounded, no panics.
 
-use std::{collections::{HashMap, VecDeque}, time::{Duration, SystemTime}};
-use tokio::sync::mpsc;
-use tokio::time::{Instant, interval};
+use std::{collections::{BTreeMap, HashMap, HashSet, VecDeque}, sync::{Arc, OnceLock, RwLock}, time::{Duration, SystemTime}};
+use tokio::time::{Instant, Interval, interval};
+use tracing::{info, warn};
 
-use super::decoder::Decoded;
+use super::calibration::{self, CalibrationShared};
+use super::decoder::{u16_reading, Decoded};
+use super::decoder_stats::DecoderStatsShared;
+use super::derived::{evaluate, DerivedValues};
+use super::liveness::{Liveness, NodeStatus};
+use super::sanitize::SlewGuard;
+use crate::services::channels::{Lane, QueueReceiver};
+use crate::services::math::{acc_opt, fmt_opt2, leaf_air_dt, ls_slope, mean, median, reject_outliers, trimmed_mean, Agg, AggKind, Stats};
+use crate::services::mqtt::config::{aggregation, outdoor, AggMode};
+use crate::services::node_health::{count_outliers, count_readings, HealthCountersShared};
+use crate::services::node_maintenance::{in_maintenance, MaintenanceShared};
+use crate::services::presenter::config::live_snapshot;
+use crate::services::shutdown::tick_or_closed;
+use crate::services::supervisor::Lease;
 
-// 60-second window
-const WINDOW: Duration = Duration::from_secs(60);
-const MAX_SAMPLES_PER_NODE: usize = 64; // ~6 samples/min, headroom
+const MAX_WINDOW_SECS: u64 = 900; // several windows per hour for the hourly stage
+const MAX_SAMPLES_PER_NODE: usize = 64; // ~6 samples/min, headroom; longer windows get one per 5 s
+const MAX_SEQ_GAP: u16 = 1000;          // larger jumps are reboots (~2.8 h of frames at 10s)
+const MIN_OUTLIER_SAMPLES: usize = 5;   // too few for a median / MAD to say which sample is off
+
+/// Window length of both aggregators: `APPTEST_WINDOW_SECS` when set, else `aggregation().window_secs`;
+/// 1..=900 s.
+pub fn aggregation_window() -> Result<Duration, String> {
+    window_from(std::env::var("APPTEST_WINDOW_SECS").ok(), aggregation().window_secs)
+}
+
+/// `aggregation_window` from the variable's value, if set, and the configured seconds.
+fn window_from(env: Option<String>, configured_secs: u64) -> Result<Duration, String> {
+    let (secs, from) = match env {
+        Some(v) => (v.trim().parse::<u64>().map_err(|_| format!("APPTEST_WINDOW_SECS '{v}' is not a number of seconds"))?, "APPTEST_WINDOW_SECS"),
+        None => (configured_secs, "aggregation().window_secs"),
+    };
+    if !(1..=MAX_WINDOW_SECS).contains(&secs) {
+        return Err(format!("{from} = {secs}; the aggregation window must be 1..={MAX_WINDOW_SECS} seconds"));
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// The window for code outside the pipeline (reports, clock watch); the pipeline refuses to start on
+/// an invalid one, so the fallback is never what was stored.
+pub fn current_window() -> Duration {
+    aggregation_window().unwrap_or(Duration::from_secs(aggregation().window_secs))
+}
+
+/// Wall-clock close of the window ticking at `wall_ms`: the nearest multiple of `window` since the epoch.
+pub fn window_close_ms(wall_ms: i64, window: Duration) -> i64 {
+    let w = (window.as_millis() as i64).max(1);
+    (wall_ms + w / 2).div_euclid(w) * w
+}
+
+/// Re-arms `tick` for `offset` past the wall-clock window close after the current one and returns that
+/// close. Called after every tick, so both aggregators close their windows on the same grid however the
+/// monotonic clock drifts from the wall clock; the first window runs half to one and a half windows.
+pub fn align_tick(tick: &mut Interval, window: Duration, offset: Duration) -> i64 {
+    let w = (window.as_millis() as i64).max(1);
+    let now = now_ms();
+    let next = window_close_ms(now, window) + w;
+    tick.reset_at(Instant::now() + Duration::from_millis((next - now) as u64) + offset);
+    next
+}
+
+/// `agg` of rolling rows with a `window_sec` window.
+pub fn rolling_agg(window_sec: u64) -> String {
+    format!("rolling_{window_sec}s")
+}
+
+/// Readings reduced per `aggregation().mode` (standard frame order); derived metrics, telemetry, wind
+/// and packet counts are always means, sums or maxima under `rolling_agg`.
+const STANDARD_READINGS: [&str; 15] = [
+    "air_temp_c", "leaf_temp_c", "bag_temp_c", "air_rh_pct", "bag_rh1_pct", "bag_rh2_pct", "bag_rh3_pct",
+    "bag_rh4_pct", "bag_rh_avg_pct", "par_value", "weight_g", "ea_air_kpa", "ea_leaf_kpa", "es_kpa", "vpd_kpa",
+];
+const OUTDOOR_READINGS: [&str; 5] = ["air_temp_c", "air_rh_pct", "par_value", "ea_air_kpa", "es_kpa"];
+const SOIL_READINGS: [&str; 5] = ["vwc1_pct", "vwc2_pct", "vwc3_pct", "vwc4_pct", "ec_ms_cm"];
+
+static GLOBAL_MODE: OnceLock<AggMode> = OnceLock::new();
+
+/// Mode of readings without a `field_modes` entry: `APPTEST_AGG_MODE` when set and valid, else
+/// `aggregation().mode`; read once.
+fn global_mode() -> AggMode {
+    *GLOBAL_MODE.get_or_init(|| {
+        let default = aggregation().mode;
+        match std::env::var("APPTEST_AGG_MODE") {
+            Ok(v) => AggMode::parse(&v).unwrap_or_else(|| {
+                warn!(target: "AVG", "APPTEST_AGG_MODE '{v}' is not valid (mean, median, trim:<pct>); using {default:?}");
+                default
+            }),
+            Err(_) => default,
+        }
+    })
+}
+
+/// How node windows reduce `key`: its `field_modes` entry, else the global mode for readings.
+pub fn agg_mode(key: &str) -> AggMode {
+    if !STANDARD_READINGS.contains(&key) && !SOIL_READINGS.contains(&key) { return AggMode::Mean; }
+    aggregation().field_modes.iter().find(|(k, _)| *k == key).map_or_else(global_mode, |m| m.1)
+}
+
+/// `agg` of a node row of `key`: `rolling_agg` for means, with the mode named otherwise.
+pub fn node_agg(key: &str, window_sec: u64) -> String {
+    mode_agg(agg_mode(key), window_sec)
+}
+
+fn mode_agg(mode: AggMode, window_sec: u64) -> String {
+    match mode {
+        AggMode::Mean => rolling_agg(window_sec),
+        AggMode::Median => format!("rolling_median_{window_sec}s"),
+        AggMode::TrimmedMean { pct } => format!("rolling_trim{pct}_{window_sec}s"),
+    }
+}
+
+/// `agg` of the `Extremes` and `Spread` rows of a `window_sec` window: `min_60s`, `max_60s`, `sd_60s`
+/// at the default.
+pub fn stat_agg(kind: &str, window_sec: u64) -> String {
+    format!("{kind}_{window_sec}s")
+}
+
+/// One reading over a window: `Stats`, plus the samples themselves when its mode is not the mean or
+/// outliers are rejected. `close` before reading `stats` or `value`.
+#[derive(Debug)]
+struct FieldAgg {
+    key: &'static str,
+    stats: Stats,
+    mode: AggMode,
+    keep: bool,
+    /// `outlier_mad_k` when outliers are rejected.
+    reject_k: Option<f32>,
+    vals: Vec<f32>,
+    rejected: usize,
+}
+
+impl FieldAgg {
+    fn new(key: &'static str) -> Self {
+        let cfg = aggregation();
+        Self::with(key, agg_mode(key), cfg.reject_outliers.then_some(cfg.outlier_mad_k))
+    }
+    fn with(key: &'static str, mode: AggMode, reject_k: Option<f32>) -> Self {
+        let keep = mode != AggMode::Mean || reject_k.is_some();
+        FieldAgg { key, stats: Stats::new(), mode, keep, reject_k, vals: Vec::new(), rejected: 0 }
+    }
+    fn add(&mut self, v: f32) {
+        if !v.is_finite() { return; }
+        if self.keep { self.vals.push(v); } else { self.stats.add(v); }
+    }
+    /// End of window: drop outliers (when enabled, from `MIN_OUTLIER_SAMPLES` on), then `stats`
+    /// describe the samples kept.
+    fn close(&mut self) {
+        if !self.keep { return; }
+        if let Some(k) = self.reject_k.filter(|_| self.vals.len() >= MIN_OUTLIER_SAMPLES) {
+            self.rejected = reject_outliers(&mut self.vals, k);
+        }
+        for &v in &self.vals { self.stats.add(v); }
+    }
+    /// The window value in this field's mode.
+    fn value(&mut self) -> Option<f32> {
+        match self.mode {
+            AggMode::Mean => self.stats.mean(),
+            AggMode::Median => median(&mut self.vals),
+            AggMode::TrimmedMean { pct } => trimmed_mean(&mut self.vals, pct),
+        }
+    }
+}
+
+/// Close every field of a window; fields that rejected outliers are logged and counted for the
+/// node's health.
+fn close_fields(fields: &mut [FieldAgg], ids: (u16, u16), health: &HealthCountersShared) {
+    fields.iter_mut().for_each(FieldAgg::close);
+    let rejected: Vec<(&'static str, usize)> = fields.iter().filter(|f| f.rejected > 0).map(|f| (f.key, f.rejected)).collect();
+    if rejected.is_empty() { return; }
+    info!(target: "AVG", "GH:{} Node:{} | outliers rejected: {}", ids.0, ids.1,
+          rejected.iter().map(|(k, n)| format!("{k}={n}")).collect::<Vec<_>>().join(" "));
+    count_outliers(health, ids, &rejected);
+}
 
 #[derive(Debug)]
 struct TimedSample {
     at: Instant,
+    device_ms: Option<i64>, // trusted node clock, wall ms
     data: Decoded,
 }
 
+impl TimedSample {
+    /// Minutes since `first`: by node clock when both carry one (back-filled windows), else by `at`.
+    fn minutes_since(&self, first: &TimedSample) -> f64 {
+        match (self.device_ms, first.device_ms) {
+            (Some(t), Some(t0)) => (t - t0) as f64 / 60_000.0,
+            _ => self.at.duration_since(first.at).as_secs_f64() / 60.0,
+        }
+    }
+}
+
 #[inline] fn now_ms() -> i64 { SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as i64 }
 
+/// Window time of a frame: its node clock mapped onto `now` when plausible, else arrival time.
+/// A node clock slightly ahead of ours counts as now; one behind (up to `max_backfill_secs`) dates a
+/// late sample, which may belong to a window already closed.
+fn sample_time(now: Instant, wall_ms: i64, device_ts: Option<u32>) -> (Instant, Option<i64>) {
+    let Some(ts) = device_ts else { return (now, None) };
+    let device_ms = i64::from(ts) * 1000;
+    let cfg = aggregation();
+    let age_ms = wall_ms - device_ms;
+    if age_ms < -(cfg.max_device_skew_secs as i64 * 1000) || age_ms > cfg.max_backfill_secs as i64 * 1000 {
+        warn!(target: "AVG", "node clock {}s off local time (ts={ts}), using arrival time", age_ms / 1000);
+        return (now, None);
+    }
+    let age = Duration::from_millis(age_ms.max(0) as u64);
+    (now.checked_sub(age).unwrap_or(now), Some(device_ms.min(wall_ms)))
+}
+
+/// Weight loss rate of a window in g/min, positive while the plant transpires: the least-squares
+/// slope of the (minutes, grams) samples in time order, up to the first rise of more than `jump_g`
+/// from one finite sample to the next. What follows such a rise is irrigation filling the substrate.
+pub fn transpiration_g_min(weights: &[(f64, f32)], jump_g: f32) -> Option<f32> {
+    let mut fit: Vec<(f64, f64)> = Vec::with_capacity(weights.len());
+    for &(min, w) in weights.iter().filter(|(_, w)| w.is_finite()) {
+        if fit.last().is_some_and(|&(_, prev)| f64::from(w) - prev > f64::from(jump_g)) { break; }
+        fit.push((min, f64::from(w)));
+    }
+    ls_slope(&fit).map(|slope| -slope as f32)
+}
+
+/// `node_live` event payload: a node's latest sample (after the slew guard) between windows, UI only.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NodeLive {
+    pub ts_ms: i64,       // node-clock time when trusted, else arrival
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+    pub outdoor: bool,
+    pub values: BTreeMap<&'static str, f32>, // finite readings only
+}
+
+impl NodeLive {
+    fn new(msg: &mut Decoded, ts_ms: i64) -> Self {
+        let (greenhouse_id, node_id) = msg.ids();
+        let outdoor = matches!(msg, Decoded::Outdoor { .. });
+        let par = match *msg {
+            Decoded::Standard { par_value, .. } | Decoded::Outdoor { par_value, .. } => u16_reading(par_value),
+            Decoded::Soil { .. } => f32::NAN,
+        };
+        let values = msg.f32_fields_mut().into_iter().map(|(k, v)| (k, *v))
+            .chain(std::iter::once(("par_value", par)))
+            .filter(|(_, v)| v.is_finite())
+            .collect();
+        NodeLive { ts_ms, greenhouse_id, node_id, outdoor, values }
+    }
+}
+
+/// `node_avg` event payload: a `NodeAvg` with its wall-clock window close instead of the `Instant`; the
+/// field names are the frontend's contract (snake_case, soil fields flattened, `null` when missing).
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct NodeAvgUi {
     pub ts_ms: i64,
     pub greenhouse_id: u16,
     pub node_id: u16,
+    pub window_sec: u32,
+    pub outdoor: bool,
     pub air_temp_c: Option<f32>,
     pub leaf_temp_c: Option<f32>,
     pub bag_temp_c: Option<f32>,
@@ -43,40 +347,590 @@ pub struct NodeAvgUi {
     pub ea_leaf_kpa: Option<f32>,
     pub es_kpa: Option<f32>,
     pub vpd_kpa: Option<f32>,
+    pub leaf_air_dt_c: Option<f32>,
+    pub transpiration_g_min: Option<f32>,
+    #[serde(flatten)]
+    pub extremes: Extremes,
+    #[serde(flatten)]
+    pub spread: Spread,
+    /// Samples behind each reading, by sensor key.
+    pub samples: SampleCounts,
+    #[serde(flatten)]
+    pub derived: DerivedValues,
+    pub maintenance: bool,
+    pub received_packets: u32,
+    pub lost_packets: Option<u32>,
+    pub battery_v: Option<f32>,
+    pub rssi_dbm: Option<f32>,
+    #[serde(flatten)]
+    pub soil: Option<SoilAvg>,
+    pub wind_ms: Option<f32>,
+    pub wind_gust_ms: Option<f32>,
+    pub rain_mm: Option<f32>,
+}
+
+impl NodeAvgUi {
+    pub fn new(na: &NodeAvg) -> Self {
+        NodeAvgUi {
+            ts_ms: na.ts_ms,
+            greenhouse_id: na.greenhouse_id,
+            node_id: na.node_id,
+            window_sec: na.window_sec,
+            outdoor: na.outdoor,
+            air_temp_c: na.air_temp_c,
+            leaf_temp_c: na.leaf_temp_c,
+            bag_temp_c: na.bag_temp_c,
+            air_rh_pct: na.air_rh_pct,
+            bag_rh1_pct: na.bag_rh1_pct,
+            bag_rh2_pct: na.bag_rh2_pct,
+            bag_rh3_pct: na.bag_rh3_pct,
+            bag_rh4_pct: na.bag_rh4_pct,
+            bag_rh_avg_pct: na.bag_rh_avg_pct,
+            par_value: na.par_value,
+            weight_g: na.weight_g,
+            ea_air_kpa: na.ea_air_kpa,
+            ea_leaf_kpa: na.ea_leaf_kpa,
+            es_kpa: na.es_kpa,
+            vpd_kpa: na.vpd_kpa,
+            leaf_air_dt_c: na.leaf_air_dt_c,
+            transpiration_g_min: na.transpiration_g_min,
+            extremes: na.extremes,
+            spread: na.spread,
+            samples: na.samples,
+            derived: na.derived,
+            maintenance: na.maintenance,
+            received_packets: na.received_packets,
+            lost_packets: na.lost_packets,
+            battery_v: na.battery_v,
+            rssi_dbm: na.rssi_dbm,
+            soil: na.soil,
+            wind_ms: na.wind_ms,
+            wind_gust_ms: na.wind_gust_ms,
+            rain_mm: na.rain_mm,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 enum NodeKind {
     Standard,
     Outdoor,
+    Soil,
+}
+
+impl NodeKind {
+    /// Window length for this kind; always a whole number of `tick`s.
+    fn span(self, tick: Duration) -> Duration {
+        match self {
+            NodeKind::Standard | NodeKind::Soil => tick,
+            NodeKind::Outdoor => {
+                let cfg = aggregation();
+                let secs = cfg.outdoor_window_secs.max(cfg.outdoor_expected_interval_secs * 2);
+                tick * secs.div_ceil(tick.as_secs()).max(1) as u32
+            }
+        }
+    }
+}
+
+/// Frames received and lost (sequence gaps) since the last emit.
+#[derive(Debug, Default)]
+struct PacketCount {
+    last_seq: Option<u16>,
+    received: u32,
+    lost: u32,
+    with_seq: bool,
+}
+
+impl PacketCount {
+    fn on_frame(&mut self, seq: Option<u16>) {
+        let Some(seq) = seq else { self.received += 1; return };
+        if let Some(prev) = self.last_seq {
+            let gap = seq.wrapping_sub(prev);
+            if gap == 0 { return; } // redelivered frame
+            if prev.wrapping_sub(seq) <= MAX_SEQ_GAP {
+                // reordered: counted lost by the gap it left, now received; the newest number stays
+                self.received += 1;
+                self.lost = self.lost.saturating_sub(1);
+                self.with_seq = true;
+                return;
+            }
+            self.lost += if gap <= MAX_SEQ_GAP {
+                u32::from(gap - 1)
+            } else if seq <= MAX_SEQ_GAP {
+                u32::from(seq) // reboot: frames 0..seq went missing
+            } else {
+                0
+            };
+        }
+        self.last_seq = Some(seq);
+        self.received += 1;
+        self.with_seq = true;
+    }
+    /// (received, lost) for the window just closed; lost is None without sequence numbers.
+    fn take(&mut self) -> (u32, Option<u32>) {
+        let out = (self.received, self.with_seq.then_some(self.lost));
+        (self.received, self.lost, self.with_seq) = (0, 0, false);
+        out
+    }
+}
+
+/// The window a `NodeWindow::close` computes, besides its samples.
+struct WindowMeta {
+    at: Instant,
+    ts_ms: i64,
+    window_start_ms: i64,
+    window_seq: u64,
+    packets: (u32, Option<u32>),
+    catchup: bool,
+}
+
+/// Wall-clock ranges [start, end) of a node's stored windows, merged where they touch.
+#[derive(Debug, Default)]
+struct Spans(BTreeMap<i64, i64>);
+
+impl Spans {
+    fn overlaps(&self, start: i64, end: i64) -> bool {
+        self.0.range(..end).next_back().is_some_and(|(_, &e)| e > start)
+    }
+    fn insert(&mut self, mut start: i64, mut end: i64) {
+        if let Some((&s, &e)) = self.0.range(..=start).next_back() {
+            if e >= start { (start, end) = (s, end.max(e)); }
+        }
+        while let Some((&s, &e)) = self.0.range(start..=end).next() {
+            self.0.remove(&s);
+            end = end.max(e);
+        }
+        self.0.insert(start, end);
+    }
+    /// Forget the ranges over before `ms`; no late sample that old is accepted.
+    fn forget_before(&mut self, ms: i64) {
+        self.0.retain(|_, e| *e > ms);
+    }
+}
+
+/// A past window of a node, filled by late samples.
+#[derive(Debug, Default)]
+struct PastWindow {
+    buf: VecDeque<TimedSample>, // in node-clock order
+    packets: PacketCount,
+    fresh: bool,                // got a sample since the last tick
+}
+
+/// Where a sample goes, by its node clock.
+#[derive(Debug, PartialEq)]
+enum Place {
+    /// The open window (also every sample without a trusted clock).
+    Open,
+    /// The past window starting at this wall ms.
+    Past(i64),
+    /// A window already stored, or overlapping the open one: dropped.
+    Covered,
 }
 
 #[derive(Debug)]
 struct NodeWindow {
     kind: NodeKind,
     ids: (u16, u16), // (greenhouse_id, node_id)
+    span: Duration,
+    tick: Duration,
+    ticks: u32,      // ticks since the last emit
+    max_samples: usize,
     buf: VecDeque<TimedSample>,
+    packets: PacketCount,
+    open_start_ms: i64,                // wall-clock start of the open window
+    stored: Spans,                     // windows sent to the DB writer, for `max_backfill_secs`
+    past: BTreeMap<i64, PastWindow>,   // back-filled windows by start
+    late_dropped: u32,                 // `Place::Covered` samples since the last tick
 }
 
 impl NodeWindow {
-    fn new(kind: NodeKind, ids: (u16,u16)) -> Self {
-        Self { kind, ids, buf: VecDeque::with_capacity(8) }
+    fn new(kind: NodeKind, ids: (u16,u16), tick: Duration, open_start_ms: i64) -> Self {
+        let span = kind.span(tick);
+        let max_samples = MAX_SAMPLES_PER_NODE.max(span.as_secs() as usize / 5);
+        Self {
+            kind, ids, span, tick, ticks: 0, max_samples, buf: VecDeque::with_capacity(8), packets: PacketCount::default(),
+            open_start_ms, stored: Spans::default(), past: BTreeMap::new(), late_dropped: 0,
+        }
+    }
+    fn span_ms(&self) -> i64 {
+        self.span.as_millis() as i64
+    }
+    /// Where a sample measured at `device_ms` belongs: past windows sit on the grid of `span`.
+    fn place(&self, device_ms: Option<i64>) -> Place {
+        let Some(ms) = device_ms.filter(|&ms| ms < self.open_start_ms) else { return Place::Open };
+        let span = self.span_ms();
+        let start = ms.div_euclid(span) * span;
+        if start + span > self.open_start_ms || self.stored.overlaps(start, start + span) { Place::Covered } else { Place::Past(start) }
     }
-    fn push_and_prune(&mut self, now: Instant, data: Decoded) {
-        self.buf.push_back(TimedSample { at: now, data });
+    /// Keep a late sample for the past window at `start`, in node-clock order.
+    fn backfill(&mut self, start: i64, sample: TimedSample) {
+        let w = self.past.entry(start).or_default();
+        w.packets.on_frame(sample.data.seq());
+        let i = w.buf.partition_point(|s| s.device_ms <= sample.device_ms);
+        w.buf.insert(i, sample);
+        w.fresh = true;
+        while w.buf.len() > self.max_samples { w.buf.pop_front(); }
+    }
+    /// Past windows to close: those without a sample since the last tick (a burst is over), or all.
+    fn past_due(&mut self, all: bool) -> Vec<(i64, PastWindow)> {
+        let due: Vec<i64> = self.past.iter_mut()
+            .filter_map(|(&start, w)| (!std::mem::take(&mut w.fresh) || all).then_some(start))
+            .collect();
+        due.into_iter().filter_map(|start| self.past.remove(&start).map(|w| (start, w))).collect()
+    }
+    fn prune(&mut self, now: Instant) {
         while let Some(front) = self.buf.front() {
-            if now.duration_since(front.at) > WINDOW { self.buf.pop_front(); } else { break; }
+            if now.duration_since(front.at) > self.span { self.buf.pop_front(); } else { break; }
+        }
+    }
+    /// Insert in time order (late frames from a buffered burst may be older than the tail).
+    fn push_and_prune(&mut self, now: Instant, at: Instant, device_ms: Option<i64>, data: Decoded) {
+        self.packets.on_frame(data.seq());
+        let i = self.buf.partition_point(|s| s.at <= at);
+        self.buf.insert(i, TimedSample { at, device_ms, data });
+        self.prune(now);
+        while self.buf.len() > self.max_samples { self.buf.pop_front(); }
+    }
+    /// Count a tick; true when this node's window is due.
+    fn due(&mut self) -> bool {
+        self.ticks += 1;
+        if self.tick * self.ticks < self.span { return false; }
+        self.ticks = 0;
+        true
+    }
+    /// Reduce one window of samples (the open buffer or a past window's, not empty) to its `NodeAvg`,
+    /// logged in one line.
+    fn close(&self, buf: &VecDeque<TimedSample>, meta: WindowMeta, maintenance_windows: &MaintenanceShared, health: &HealthCountersShared) -> NodeAvg {
+        let WindowMeta { at, ts_ms, window_start_ms, window_seq, packets: (received_packets, lost_packets), catchup } = meta;
+        let window_sec = self.span.as_secs() as u32;
+        let samples = buf.len();
+        let measured_ms = buf.iter().filter_map(|s| s.device_ms).max().map(|m| m.max(window_start_ms));
+        let (mut batt_s, mut batt_c, mut rssi_s, mut rssi_c) = (0.0, 0, 0.0, 0);
+        for s in buf.iter() {
+            let (battery_v, rssi_dbm) = s.data.telemetry();
+            acc_opt(battery_v, &mut batt_s, &mut batt_c);
+            acc_opt(rssi_dbm, &mut rssi_s, &mut rssi_c);
         }
-        while self.buf.len() > MAX_SAMPLES_PER_NODE { self.buf.pop_front(); }
+        let (battery_v, rssi_dbm) = (mean(batt_s, batt_c), mean(rssi_s, rssi_c));
+        let maintenance = in_maintenance(maintenance_windows, self.ids, window_start_ms);
+        let flags = format!("{}{}", if maintenance { " [MAINT]" } else { "" }, if catchup { " [CATCHUP]" } else { "" });
+
+        match self.kind {
+            NodeKind::Standard => {
+                let mut fields = STANDARD_READINGS.map(FieldAgg::new);
+                let first = buf.front();
+                let mut weights: Vec<(f64, f32)> = Vec::with_capacity(buf.len());
+                for s in buf.iter() {
+                    if let Decoded::Standard {
+                        air_temp_c, leaf_temp_c, bag_temp_c, air_rh_pct,
+                        bag_rh1_pct, bag_rh2_pct, bag_rh3_pct, bag_rh4_pct, bag_rh_avg_pct,
+                        par_value, weight_g, ea_air_kpa, ea_leaf_kpa, es_kpa, vpd_kpa, ..
+                    } = s.data {
+                        let vals = [
+                            air_temp_c, leaf_temp_c, bag_temp_c, air_rh_pct,
+                            bag_rh1_pct, bag_rh2_pct, bag_rh3_pct, bag_rh4_pct, bag_rh_avg_pct,
+                            u16_reading(par_value), weight_g, ea_air_kpa, ea_leaf_kpa, es_kpa, vpd_kpa,
+                        ];
+                        for (f, v) in fields.iter_mut().zip(vals) { f.add(v); }
+                        weights.push((first.map_or(0.0, |f| s.minutes_since(f)), weight_g));
+                    }
+                }
+                close_fields(&mut fields, self.ids, health);
+                let counts = SampleCounts::new(&STANDARD_READINGS, &fields);
+                let [mut air_t, mut leaf_t, mut bag_t, mut air_rh, mut brh1, mut brh2, mut brh3, mut brh4,
+                     mut brh_avg, mut par, mut weight, mut ea_air, mut ea_leaf, mut es, mut vpd] = fields;
+
+                let (air_temp_c, leaf_temp_c) = (air_t.value(), leaf_t.value());
+                let na = NodeAvg {
+                    greenhouse_id: self.ids.0, node_id: self.ids.1, at, ts_ms,
+                    window_start_ms, window_seq, window_sec, maintenance, outdoor: false,
+                    air_temp_c,                           leaf_temp_c,
+                    bag_temp_c: bag_t.value(),            air_rh_pct:  air_rh.value(),
+                    bag_rh1_pct: brh1.value(),            bag_rh2_pct: brh2.value(),
+                    bag_rh3_pct: brh3.value(),            bag_rh4_pct: brh4.value(),
+                    bag_rh_avg_pct: brh_avg.value(),
+                    par_value: par.value(),               weight_g:  weight.value(),
+                    ea_air_kpa: ea_air.value(),           ea_leaf_kpa: ea_leaf.value(),
+                    es_kpa: es.value(),                   vpd_kpa: vpd.value(),
+                    leaf_air_dt_c: leaf_air_dt(leaf_temp_c, air_temp_c),
+                    transpiration_g_min: transpiration_g_min(&weights, aggregation().irrigation_jump_g),
+                    extremes: Extremes::from_stats(&air_t.stats, &air_rh.stats, &vpd.stats, &par.stats, &weight.stats),
+                    spread: Spread { air_temp_sd: air_t.stats.sd(), air_rh_sd: air_rh.stats.sd(), vpd_sd: vpd.stats.sd() },
+                    samples: counts,
+                    derived: DerivedValues::default(),
+                    received_packets, lost_packets, measured_ms, catchup, soil: None, battery_v, rssi_dbm,
+                    wind_ms: None, wind_gust_ms: None, rain_mm: None,
+                };
+                let na = NodeAvg { derived: evaluate(&na.base_fields()), ..na };
+
+                info!(
+                  target: "AVG-60s",
+                  "GH:{} Node:{}{} | Samples:{} | Air:{} | Leaf:{} | Bag:{} | RH:{} | BRH1:{} | BRH2:{} | BRH3:{} | BRH4:{} | BRH_avg:{} | PAR:{} | W:{} | Ea_air:{} | Ea_leaf:{} | Es:{} | VPD:{} | dT_leaf-air:{}",
+                  self.ids.0, self.ids.1, flags, samples,
+                  fmt_opt2(na.air_temp_c, "C"),
+                  fmt_opt2(na.leaf_temp_c, "C"),
+                  fmt_opt2(na.bag_temp_c, "C"),
+                  fmt_opt2(na.air_rh_pct, "%"),
+                  fmt_opt2(na.bag_rh1_pct, "%"),
+                  fmt_opt2(na.bag_rh2_pct, "%"),
+                  fmt_opt2(na.bag_rh3_pct, "%"),
+                  fmt_opt2(na.bag_rh4_pct, "%"),
+                  fmt_opt2(na.bag_rh_avg_pct, "%"),
+                  fmt_opt2(na.par_value, ""),
+                  fmt_opt2(na.weight_g, ""),
+                  fmt_opt2(na.ea_air_kpa, "kPa"),
+                  fmt_opt2(na.ea_leaf_kpa, "kPa"),
+                  fmt_opt2(na.es_kpa, "kPa"),
+                  fmt_opt2(na.vpd_kpa, "kPa"),
+                  fmt_opt2(na.leaf_air_dt_c, "C"),
+                );
+
+                na
+            }
+            NodeKind::Outdoor => {
+                // mean readings, but the strongest gust and the rain total of the window
+                let mut fields = OUTDOOR_READINGS.map(FieldAgg::new);
+                let mut wind = Agg::new(AggKind::Mean);
+                let mut gust = Agg::new(AggKind::Max);    let mut rain = Agg::new(AggKind::Sum);
+
+                for s in buf.iter() {
+                    if let Decoded::Outdoor {
+                        air_temp_c, air_rh_pct, par_value, ea_air_kpa, es_kpa, wind_ms, wind_gust_ms, rain_tips, ..
+                    } = s.data {
+                        let vals = [air_temp_c, air_rh_pct, u16_reading(par_value), ea_air_kpa, es_kpa];
+                        for (f, v) in fields.iter_mut().zip(vals) { f.add(v); }
+                        wind.add(wind_ms);
+                        gust.add(wind_gust_ms);
+                        rain.add(u16_reading(rain_tips));
+                    }
+                }
+                close_fields(&mut fields, self.ids, health);
+                let counts = SampleCounts::new(&OUTDOOR_READINGS, &fields);
+                let [mut air_t, mut air_rh, mut par, mut ea_air, mut es] = fields;
+
+                let na = NodeAvg {
+                    greenhouse_id: self.ids.0, node_id: self.ids.1, at, ts_ms,
+                    window_start_ms, window_seq, window_sec, maintenance, outdoor: true,
+                    air_temp_c: air_t.value(),           leaf_temp_c: None,
+                    bag_temp_c: None,                    air_rh_pct: air_rh.value(),
+                    bag_rh1_pct: None, bag_rh2_pct: None, bag_rh3_pct: None, bag_rh4_pct: None,
+                    bag_rh_avg_pct: None,
+                    par_value: par.value(),              weight_g: None,
+                    ea_air_kpa: ea_air.value(),          ea_leaf_kpa: None,
+                    es_kpa: es.value(),                  vpd_kpa: None,
+                    leaf_air_dt_c: None,                 transpiration_g_min: None,
+                    extremes: Extremes::from_stats(&air_t.stats, &air_rh.stats, &Stats::new(), &par.stats, &Stats::new()),
+                    spread: Spread { air_temp_sd: air_t.stats.sd(), air_rh_sd: air_rh.stats.sd(), vpd_sd: None },
+                    samples: counts,
+                    derived: DerivedValues::default(),
+                    received_packets, lost_packets, measured_ms, catchup, soil: None, battery_v, rssi_dbm,
+                    wind_ms: wind.get(), wind_gust_ms: gust.get(),
+                    rain_mm: rain.get().map(|tips| tips * outdoor().rain_mm_per_tip),
+                };
+                let na = NodeAvg { derived: evaluate(&na.base_fields()), ..na };
+
+                info!(
+                    target: "AVG",
+                    "{}s GH:{} Node:{}{} | Samples:{} | Air:{} | RH:{} | PAR:{} | Ea_air:{} | Es:{} | Wind:{} | Gust:{} | Rain:{}",
+                    window_sec, self.ids.0, self.ids.1, flags, samples,
+                    fmt_opt2(na.air_temp_c, "C"),
+                    fmt_opt2(na.air_rh_pct, "%"),
+                    fmt_opt2(na.par_value, ""),
+                    fmt_opt2(na.ea_air_kpa, "kPa"),
+                    fmt_opt2(na.es_kpa, "kPa"),
+                    fmt_opt2(na.wind_ms, "m/s"),
+                    fmt_opt2(na.wind_gust_ms, "m/s"),
+                    fmt_opt2(na.rain_mm, "mm"),
+                );
+
+                na
+            }
+            NodeKind::Soil => {
+                let mut fields = SOIL_READINGS.map(FieldAgg::new);
+                for s in buf.iter() {
+                    if let Decoded::Soil { vwc1_pct, vwc2_pct, vwc3_pct, vwc4_pct, ec_ms_cm, .. } = s.data {
+                        for (f, v) in fields.iter_mut().zip([vwc1_pct, vwc2_pct, vwc3_pct, vwc4_pct, ec_ms_cm]) { f.add(v); }
+                    }
+                }
+                close_fields(&mut fields, self.ids, health);
+                let counts = SampleCounts::new(&SOIL_READINGS, &fields);
+                let [mut v1, mut v2, mut v3, mut v4, mut ec] = fields;
+
+                let soil = SoilAvg {
+                    vwc1_pct: v1.value(), vwc2_pct: v2.value(),
+                    vwc3_pct: v3.value(), vwc4_pct: v4.value(),
+                    ec_ms_cm: ec.value(),
+                };
+                let na = NodeAvg {
+                    greenhouse_id: self.ids.0, node_id: self.ids.1, at, ts_ms,
+                    window_start_ms, window_seq, window_sec, maintenance, outdoor: false,
+                    air_temp_c: None, leaf_temp_c: None, bag_temp_c: None, air_rh_pct: None,
+                    bag_rh1_pct: None, bag_rh2_pct: None, bag_rh3_pct: None, bag_rh4_pct: None,
+                    bag_rh_avg_pct: None, par_value: None, weight_g: None,
+                    ea_air_kpa: None, ea_leaf_kpa: None, es_kpa: None, vpd_kpa: None,
+                    leaf_air_dt_c: None, transpiration_g_min: None,
+                    extremes: Extremes::default(), spread: Spread::default(), samples: counts,
+                    derived: DerivedValues::default(),
+                    received_packets, lost_packets, measured_ms, catchup, soil: Some(soil), battery_v, rssi_dbm,
+                    wind_ms: None, wind_gust_ms: None, rain_mm: None,
+                };
+
+                info!(
+                    target: "AVG-60s",
+                    "GH:{} Node:{}{} | Samples:{} | VWC1:{} | VWC2:{} | VWC3:{} | VWC4:{} | EC:{}",
+                    self.ids.0, self.ids.1, flags, samples,
+                    fmt_opt2(soil.vwc1_pct, "%"),
+                    fmt_opt2(soil.vwc2_pct, "%"),
+                    fmt_opt2(soil.vwc3_pct, "%"),
+                    fmt_opt2(soil.vwc4_pct, "%"),
+                    fmt_opt2(soil.ec_ms_cm, "mS/cm"),
+                );
+
+                na
+            }
+        }
+    }
+}
+
+/// Soil node means (substrate sensors); the air fields of such a `NodeAvg` are all None.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct SoilAvg {
+    pub vwc1_pct: Option<f32>,
+    pub vwc2_pct: Option<f32>,
+    pub vwc3_pct: Option<f32>,
+    pub vwc4_pct: Option<f32>,
+    pub ec_ms_cm: Option<f32>,
+}
+
+impl SoilAvg {
+    fn fields(&self) -> [(&'static str, &'static str, Option<f32>); 5] {
+        [
+            ("vwc1_pct", "%", self.vwc1_pct),
+            ("vwc2_pct", "%", self.vwc2_pct),
+            ("vwc3_pct", "%", self.vwc3_pct),
+            ("vwc4_pct", "%", self.vwc4_pct),
+            ("ec_ms_cm", "mS/cm", self.ec_ms_cm),
+        ]
+    }
+}
+
+/// Window min / max of the key fields, serialized flat (`air_temp_c_min`, `air_temp_c_max`, ...);
+/// None where the field had no sample (outdoor stations have no VPD or weight).
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct Extremes {
+    pub air_temp_c_min: Option<f32>,
+    pub air_temp_c_max: Option<f32>,
+    pub air_rh_pct_min: Option<f32>,
+    pub air_rh_pct_max: Option<f32>,
+    pub vpd_kpa_min: Option<f32>,
+    pub vpd_kpa_max: Option<f32>,
+    pub par_value_min: Option<f32>,
+    pub par_value_max: Option<f32>,
+    pub weight_g_min: Option<f32>,
+    pub weight_g_max: Option<f32>,
+}
+
+impl Extremes {
+    fn from_stats(air_t: &Stats, air_rh: &Stats, vpd: &Stats, par: &Stats, weight: &Stats) -> Self {
+        Extremes {
+            air_temp_c_min: air_t.min(), air_temp_c_max: air_t.max(),
+            air_rh_pct_min: air_rh.min(), air_rh_pct_max: air_rh.max(),
+            vpd_kpa_min: vpd.min(),       vpd_kpa_max: vpd.max(),
+            par_value_min: par.min(),     par_value_max: par.max(),
+            weight_g_min: weight.min(),   weight_g_max: weight.max(),
+        }
+    }
+
+    /// Lowest min and highest max of both (greenhouse extremes from node extremes).
+    pub fn merge(self, o: &Extremes) -> Self {
+        let lo = |a: Option<f32>, b: Option<f32>| match (a, b) { (Some(x), Some(y)) => Some(x.min(y)), (x, y) => x.or(y) };
+        let hi = |a: Option<f32>, b: Option<f32>| match (a, b) { (Some(x), Some(y)) => Some(x.max(y)), (x, y) => x.or(y) };
+        Extremes {
+            air_temp_c_min: lo(self.air_temp_c_min, o.air_temp_c_min), air_temp_c_max: hi(self.air_temp_c_max, o.air_temp_c_max),
+            air_rh_pct_min: lo(self.air_rh_pct_min, o.air_rh_pct_min), air_rh_pct_max: hi(self.air_rh_pct_max, o.air_rh_pct_max),
+            vpd_kpa_min: lo(self.vpd_kpa_min, o.vpd_kpa_min),          vpd_kpa_max: hi(self.vpd_kpa_max, o.vpd_kpa_max),
+            par_value_min: lo(self.par_value_min, o.par_value_min),    par_value_max: hi(self.par_value_max, o.par_value_max),
+            weight_g_min: lo(self.weight_g_min, o.weight_g_min),       weight_g_max: hi(self.weight_g_max, o.weight_g_max),
+        }
+    }
+
+    /// ("min" | "max", sensor key, unit, value) for every extreme present, in storage order.
+    pub fn rows(&self) -> Vec<(&'static str, &'static str, &'static str, f32)> {
+        let fields = [
+            ("air_temp_c", "C", self.air_temp_c_min, self.air_temp_c_max),
+            ("air_rh_pct", "%", self.air_rh_pct_min, self.air_rh_pct_max),
+            ("vpd_kpa", "kPa", self.vpd_kpa_min, self.vpd_kpa_max),
+            ("par_value", "", self.par_value_min, self.par_value_max),
+            ("weight_g", "", self.weight_g_min, self.weight_g_max),
+        ];
+        let mut out = Vec::new();
+        for (key, unit, min, max) in fields {
+            if let Some(v) = min { out.push(("min", key, unit, v)); }
+            if let Some(v) = max { out.push(("max", key, unit, v)); }
+        }
+        out
+    }
+}
+
+/// Within-window sample standard deviation of air temperature, RH and VPD; None with fewer than two
+/// samples, so a single reading is not mistaken for a perfectly stable one.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct Spread {
+    pub air_temp_sd: Option<f32>,
+    pub air_rh_sd: Option<f32>,
+    pub vpd_sd: Option<f32>,
+}
+
+impl Spread {
+    /// ("sd", sensor key, unit, value) for every deviation present; same shape as `Extremes::rows`.
+    pub fn rows(&self) -> Vec<(&'static str, &'static str, &'static str, f32)> {
+        [("air_temp_c", "C", self.air_temp_sd), ("air_rh_pct", "%", self.air_rh_sd), ("vpd_kpa", "kPa", self.vpd_sd)]
+            .into_iter()
+            .filter_map(|(key, unit, v)| Some(("sd", key, unit, v?)))
+            .collect()
+    }
+}
+
+/// Finite samples behind each reading of a window (kept ones when outliers are rejected), by sensor
+/// key; serialized as `{ "air_temp_c": 6, ... }`. Derived metrics, telemetry and wind have none.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SampleCounts {
+    keys: &'static [&'static str],
+    counts: [u16; 15],
+}
+
+impl SampleCounts {
+    /// From closed `fields`, one per entry of `keys`.
+    fn new(keys: &'static [&'static str], fields: &[FieldAgg]) -> Self {
+        let mut counts = [0u16; 15];
+        for (c, f) in counts.iter_mut().zip(fields) { *c = f.stats.count().min(u16::MAX as u32) as u16; }
+        SampleCounts { keys, counts }
+    }
+
+    pub fn get(&self, key: &str) -> Option<u16> {
+        self.keys.iter().position(|k| *k == key).map(|i| self.counts[i])
     }
 }
 
-/// Per-node 60s snapshot (all fields optional to reflect missing data).
+impl serde::Serialize for SampleCounts {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_map(self.keys.iter().zip(self.counts))
+    }
+}
+
+/// Per-node window snapshot (all fields optional to reflect missing data).
 #[derive(Debug, Clone, Copy)]
 pub struct NodeAvg {
     pub greenhouse_id: u16,
     pub node_id: u16,
     pub at: Instant,
+    pub ts_ms: i64,           // wall clock at window close, shared with the greenhouse window of the tick
+    pub window_start_ms: i64, // wall clock at window start
+    pub window_seq: u64,      // per-task window counter; (start, seq) identifies the window in storage
+    pub window_sec: u32,      // the window indoor (60 by default), longer for outdoor stations
+    pub maintenance: bool,    // overlaps a maintenance window: stored, excluded from greenhouse averages
+    pub outdoor: bool,        // outdoor station: feeds the greenhouse outdoor reference, not the indoor means
+    pub catchup: bool,        // back-filled from late samples: DB writer only, stored with source 'catchup'
 
     pub air_temp_c: Option<f32>,
     pub leaf_temp_c: Option<f32>,
@@ -93,216 +947,933 @@ pub struct NodeAvg {
     pub ea_leaf_kpa: Option<f32>,
     pub es_kpa: Option<f32>,
     pub vpd_kpa: Option<f32>,
+    pub leaf_air_dt_c: Option<f32>,
+    pub transpiration_g_min: Option<f32>, // weight loss rate; standard nodes only
+    pub extremes: Extremes,       // window min / max of the key fields
+    pub spread: Spread,           // window standard deviation of air temperature, RH and VPD
+    pub samples: SampleCounts,    // finite samples behind each reading
+    pub derived: DerivedValues,
+    pub received_packets: u32,    // frames received in the window
+    pub lost_packets: Option<u32>, // sequence gaps in the window; None when frames carry no sequence
+    pub measured_ms: Option<i64>,  // newest node-clock time in the window; None when no frame carries one
+    pub soil: Option<SoilAvg>,     // soil nodes only
+    pub battery_v: Option<f32>,    // telemetry means; None for frames without telemetry
+    pub rssi_dbm: Option<f32>,
+    pub wind_ms: Option<f32>,      // outdoor weather frames only: mean wind,
+    pub wind_gust_ms: Option<f32>, // strongest gust,
+    pub rain_mm: Option<f32>,      // and rain over the window (tips summed, `outdoor().rain_mm_per_tip`)
 }
 
-#[inline] fn mean(sum: f64, cnt: u32) -> Option<f32> {
-    if cnt == 0 { None } else { Some((sum / (cnt as f64)) as f32) }
+impl NodeAvg {
+    /// Sensor fields plus configured derived metrics (and telemetry / packet counts when frames carry them), in storage order.
+    /// Soil nodes store their soil fields only.
+    pub fn fields(&self) -> Vec<(&'static str, &'static str, Option<f32>)> {
+        let mut f = match &self.soil {
+            Some(soil) => soil.fields().to_vec(),
+            None => {
+                let mut f = self.base_fields().to_vec();
+                f.extend(self.derived.fields());
+                f
+            }
+        };
+        if let Some(v) = self.transpiration_g_min { f.push(("transpiration_g_min", "g/min", Some(v))); }
+        if let Some(v) = self.battery_v { f.push(("battery_v", "V", Some(v))); }
+        if let Some(r) = self.rssi_dbm { f.push(("rssi_dbm", "dBm", Some(r))); }
+        if let Some(v) = self.wind_ms { f.push(("wind_ms", "m/s", Some(v))); }
+        if let Some(v) = self.wind_gust_ms { f.push(("wind_gust_ms", "m/s", Some(v))); }
+        if let Some(v) = self.rain_mm { f.push(("rain_mm", "mm", Some(v))); }
+        if let Some(lost) = self.lost_packets {
+            f.push(("received_packets", "", Some(self.received_packets as f32)));
+            f.push(("lost_packets", "", Some(lost as f32)));
+        }
+        f
+    }
+
+    /// (sensor key, unit, value) for every averaged sensor field.
+    fn base_fields(&self) -> [(&'static str, &'static str, Option<f32>); 16] {
+        [
+            ("air_temp_c", "C", self.air_temp_c),
+            ("leaf_temp_c", "C", self.leaf_temp_c),
+            ("bag_temp_c", "C", self.bag_temp_c),
+            ("air_rh_pct", "%", self.air_rh_pct),
+            ("bag_rh1_pct", "%", self.bag_rh1_pct),
+            ("bag_rh2_pct", "%", self.bag_rh2_pct),
+            ("bag_rh3_pct", "%", self.bag_rh3_pct),
+            ("bag_rh4_pct", "%", self.bag_rh4_pct),
+            ("bag_rh_avg_pct", "%", self.bag_rh_avg_pct),
+            ("par_value", "", self.par_value),
+            ("weight_g", "", self.weight_g),
+            ("ea_air_kpa", "kPa", self.ea_air_kpa),
+            ("ea_leaf_kpa", "kPa", self.ea_leaf_kpa),
+            ("es_kpa", "kPa", self.es_kpa),
+            ("vpd_kpa", "kPa", self.vpd_kpa),
+            ("leaf_air_dt_c", "C", self.leaf_air_dt_c),
+        ]
+    }
 }
-#[inline] fn acc(v: f32, sum: &mut f64, cnt: &mut u32) {
-    let x = v as f64; if x.is_finite() { *sum += x; *cnt += 1; }
+
+
+/// Latest NodeAvgUi per (greenhouse, node) (written by the aggregator, read by remote queries).
+pub type LatestNodeShared = Arc<RwLock<HashMap<(u16, u16), NodeAvgUi>>>;
+
+/// Keep `ui` as its node's latest and send it to the UI lane.
+async fn emit_ui(tx: &Lane<NodeAvgUi>, latest: &LatestNodeShared, ui: NodeAvgUi) {
+    if let Ok(mut l) = latest.write() { l.insert((ui.greenhouse_id, ui.node_id), ui.clone()); }
+    tx.send(ui).await;
 }
-#[inline] fn round2(v: f32) -> f32 { (v * 100.0).round() / 100.0 }
-fn fmt_opt2(v: Option<f32>, unit: &str) -> String {
-    match v.map(round2) {
-        Some(x) if x.is_finite() => {
-            if unit.is_empty() { format!("Some({:.2})", x) }
-            else { format!("Some({:.2}){}", x, unit) }
-        }
-        _ => "None".to_string(),
+
+/// `catchup_complete` event payload: what the node aggregator back-filled from the backlog delivered
+/// at startup.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct CatchupReport {
+    pub ts_ms: i64,
+    /// Past windows rebuilt and sent to the DB writer.
+    pub windows: u32,
+    /// Late samples in those windows.
+    pub samples: u64,
+    /// Late samples whose window was already stored.
+    pub dropped: u64,
+    /// Nodes with at least one rebuilt window.
+    pub nodes: u32,
+    /// Start of the oldest and end of the newest rebuilt window; None when there was no backlog.
+    pub from_ms: Option<i64>,
+    pub to_ms: Option<i64>,
+}
+
+/// The startup catch-up of one run, until its report goes out.
+#[derive(Debug, Default)]
+struct Catchup {
+    report: CatchupReport,
+    nodes: HashSet<(u16, u16)>,
+    current: bool, // a sample for an open window has arrived: the broker's backlog is behind us
+}
+
+impl Catchup {
+    fn window(&mut self, na: &NodeAvg, samples: usize) {
+        let r = &mut self.report;
+        r.windows += 1;
+        r.samples += samples as u64;
+        r.from_ms = Some(r.from_ms.map_or(na.window_start_ms, |m| m.min(na.window_start_ms)));
+        r.to_ms = Some(r.to_ms.map_or(na.ts_ms, |m| m.max(na.ts_ms)));
+        self.nodes.insert((na.greenhouse_id, na.node_id));
+    }
+    fn finish(mut self, ts_ms: i64) -> CatchupReport {
+        self.report.ts_ms = ts_ms;
+        self.report.nodes = self.nodes.len() as u32;
+        self.report
     }
 }
 
+/// Where the node aggregator's windows go.
+#[derive(Clone)]
+pub struct NodeAvgOutputs {
+    /// NodeAvg stream to DB writer
+    pub db: Lane<NodeAvg>,
+    /// NodeAvg stream to greenhouse aggregator
+    pub gh: Lane<NodeAvg>,
+    /// NodeAvgUi stream to the UI emitter
+    pub ui: Lane<NodeAvgUi>,
+    /// NodeLive snapshots to the UI emitter, every `live_snapshot().every_secs`
+    pub live: Lane<NodeLive>,
+    /// online / late / offline changes (liveness.rs)
+    pub status: Lane<NodeStatus>,
+    /// every node's last UI window
+    pub latest: LatestNodeShared,
+    /// back-filled windows of late samples to the DB writer (critical: a burst is written whole)
+    pub catchup: Lane<NodeAvg>,
+    /// the startup catch-up report, once per run
+    pub catchup_done: Lane<CatchupReport>,
+}
+
 /// Public task:
 /// - rx_decoded: incoming Decoded samples from subscriber
-/// - tx_nodeavg_db: NodeAvg stream to DB writer
-/// - tx_nodeavg_gh: NodeAvg stream to greenhouse aggregator
+/// - window: `aggregation_window()`, the tick and the indoor window
+/// - out: NodeAvg to DB and greenhouse aggregator, NodeAvgUi and NodeLive to the UI, NodeStatus changes,
+///   back-filled windows to the DB and the startup catch-up report (`NodeAvgOutputs`)
+/// - maintenance: open node maintenance windows, used to flag overlapping averages
+/// - health: per-node reading counters for the health score (readings kept vs rejected by the slew guard)
+/// - decoder_stats: total slew guard rejections and late samples
 pub async fn run_rolling_avg(
-    mut rx_decoded: mpsc::Receiver<Decoded>,
-    tx_nodeavg_db: mpsc::Sender<NodeAvg>,
-    tx_nodeavg_gh: mpsc::Sender<NodeAvg>,
-    tx_nodeavg_ui: mpsc::Sender<NodeAvgUi>,
+    mut rx_decoded: Lease<QueueReceiver<Decoded>>,
+    window: Duration,
+    out: NodeAvgOutputs,
+    maintenance_windows: MaintenanceShared,
+    health: HealthCountersShared,
+    decoder_stats: DecoderStatsShared,
+    calibrations: CalibrationShared,
 ) {
+    let NodeAvgOutputs {
+        db: tx_nodeavg_db, gh: tx_nodeavg_gh, ui: tx_nodeavg_ui, live: tx_live, status: tx_status, latest: latest_nodes,
+        catchup: tx_catchup, catchup_done: tx_catchup_done,
+    } = out;
     let mut nodes: HashMap<(u16, u16), NodeWindow> = HashMap::new();
-    let mut tick = interval(WINDOW);
-    tick.tick().await; // align first output to +60s
+    let mut slew = SlewGuard::default();
+    let mut tick = interval(window);
+    let mut next_close_ms = align_tick(&mut tick, window, Duration::ZERO);
+    let window_ms = window.as_millis() as i64;
+    let backfill_ms = aggregation().max_backfill_secs as i64 * 1000;
+    let mut window_seq: u64 = 0;
+    let mut closed = false; // the subscriber is gone: emit what is buffered once more and stop
+    // latest sample per node for `node_live`; separate from the windows, which it never touches
+    let live_cfg = live_snapshot();
+    let mut live_tick = interval(Duration::from_secs(live_cfg.every_secs.max(1)));
+    let mut live: HashMap<(u16, u16), (Instant, NodeLive)> = HashMap::new();
+    let mut liveness = Liveness::default();
+    let mut catchup = Some(Catchup::default());
 
     loop {
         tokio::select! {
-            maybe_msg = rx_decoded.recv() => {
-                if let Some(msg) = maybe_msg {
+            maybe_msg = rx_decoded.recv(), if !closed => {
+                if maybe_msg.is_none() { closed = true; }
+                if let Some(mut msg) = maybe_msg {
+                    calibration::apply(&calibrations, &mut msg);
                     let now = Instant::now();
+                    let wall_ms = now_ms();
+                    let (at, device_ms) = sample_time(now, wall_ms, msg.device_ts());
                     let (key, kind) = match msg {
                         Decoded::Standard { greenhouse_id, node_id, .. } =>
                             ((greenhouse_id, node_id), NodeKind::Standard),
                         Decoded::Outdoor  { greenhouse_id, node_id, .. } =>
                             ((greenhouse_id, node_id), NodeKind::Outdoor),
+                        Decoded::Soil     { greenhouse_id, node_id, .. } =>
+                            ((greenhouse_id, node_id), NodeKind::Soil),
                     };
-                    nodes.entry(key).or_insert_with(|| NodeWindow::new(kind, key))
-                         .push_and_prune(now, msg);
+                    // a node heard first fills the window closing at the next tick
+                    let win = nodes.entry(key).or_insert_with(|| NodeWindow::new(kind, key, window, next_close_ms - window_ms));
+                    liveness.seen(key, now, wall_ms, win.span);
+                    let readings = msg.f32_fields_mut().into_iter().filter(|(_, v)| v.is_finite()).count();
+                    match win.place(device_ms) {
+                        Place::Open => {
+                            if let Some(c) = catchup.as_mut() { c.current = true; }
+                            decoder_stats.count_slew_rejected(slew.check(at, &mut msg).len());
+                            let kept = msg.f32_fields_mut().into_iter().filter(|(_, v)| v.is_finite()).count();
+                            count_readings(&health, key, readings, readings.saturating_sub(kept));
+                            if live_cfg.every_secs > 0 {
+                                live.insert(key, (now, NodeLive::new(&mut msg, device_ms.unwrap_or(wall_ms))));
+                            }
+                            win.push_and_prune(now, at, device_ms, msg);
+                        }
+                        // late: past the slew guard, whose last sample is newer, and never live
+                        Place::Past(start) => {
+                            decoder_stats.count_late(true);
+                            count_readings(&health, key, readings, 0);
+                            win.backfill(start, TimedSample { at, device_ms, data: msg });
+                        }
+                        Place::Covered => {
+                            if let Some(c) = catchup.as_mut() { c.report.dropped += 1; }
+                            decoder_stats.count_late(false);
+                            win.late_dropped += 1;
+                        }
+                    }
+                }
+            }
+            _ = live_tick.tick(), if live_cfg.every_secs > 0 && !closed => {
+                let now = Instant::now();
+                live.retain(|_, (seen, _)| now.duration_since(*seen) <= Duration::from_secs(live_cfg.max_age_secs));
+                for (_, nl) in live.values() {
+                    tx_live.send(nl.clone()).await;
                 }
             }
-            _ = tick.tick() => {
+            last = tick_or_closed(&mut tick, closed) => {
                 let now = Instant::now();
-                for (_key, win) in nodes.iter_mut() {
-                    while let Some(front) = win.buf.front() {
-                        if now.duration_since(front.at) > WINDOW { win.buf.pop_front(); } else { break; }
+                // windows close on the wall-clock grid; the last, partial one when it actually closes
+                let tick_ms = if last { now_ms() } else { window_close_ms(now_ms(), window) };
+                if !last {
+                    next_close_ms = align_tick(&mut tick, window, Duration::ZERO);
+                    for change in liveness.check(now, tick_ms) { tx_status.send(change).await; }
+                }
+                window_seq += 1;
+                for win in nodes.values_mut() {
+                    let span_ms = win.span_ms();
+                    for (start, mut past) in win.past_due(last) {
+                        let meta = WindowMeta {
+                            at: now, ts_ms: start + span_ms, window_start_ms: start, window_seq,
+                            packets: past.packets.take(), catchup: true,
+                        };
+                        let na = win.close(&past.buf, meta, &maintenance_windows, &health);
+                        if let Some(c) = catchup.as_mut() { c.window(&na, past.buf.len()); }
+                        tx_catchup.send(na).await;
+                        win.stored.insert(start, start + span_ms);
                     }
-                    let samples = win.buf.len();
-                    if samples == 0 { continue; }
-
-                    match win.kind {
-                        NodeKind::Standard => {
-                            // sums + counts
-                            let (mut air_t_s,mut air_t_c)=(0.0,0); let (mut leaf_t_s,mut leaf_t_c)=(0.0,0);
-                            let (mut bag_t_s,mut bag_t_c)=(0.0,0); let (mut air_rh_s,mut air_rh_c)=(0.0,0);
-                            let (mut brh1_s,mut brh1_c)=(0.0,0); let (mut brh2_s,mut brh2_c)=(0.0,0);
-                            let (mut brh3_s,mut brh3_c)=(0.0,0); let (mut brh4_s,mut brh4_c)=(0.0,0);
-                            let (mut brh_avg_s,mut brh_avg_c)=(0.0,0); let (mut par_s,mut par_c)=(0.0,0);
-                            let (mut weight_s,mut weight_c)=(0.0,0); let (mut ea_air_s,mut ea_air_c)=(0.0,0);
-                            let (mut ea_leaf_s,mut ea_leaf_c)=(0.0,0); let (mut es_s,mut es_c)=(0.0,0);
-                            let (mut vpd_s,mut vpd_c)=(0.0,0);
-
-                            for s in win.buf.iter() {
-                                if let Decoded::Standard {
-                                    air_temp_c, leaf_temp_c, bag_temp_c, air_rh_pct,
-                                    bag_rh1_pct, bag_rh2_pct, bag_rh3_pct, bag_rh4_pct, bag_rh_avg_pct,
-                                    par_value, weight_g, ea_air_kpa, ea_leaf_kpa, es_kpa, vpd_kpa, ..
-                                } = s.data {
-                                    acc(air_temp_c, &mut air_t_s, &mut air_t_c);
-                                    acc(leaf_temp_c,&mut leaf_t_s,&mut leaf_t_c);
-                                    acc(bag_temp_c, &mut bag_t_s, &mut bag_t_c);
-                                    acc(air_rh_pct, &mut air_rh_s, &mut air_rh_c);
-                                    acc(bag_rh1_pct,&mut brh1_s,  &mut brh1_c);
-                                    acc(bag_rh2_pct,&mut brh2_s,  &mut brh2_c);
-                                    acc(bag_rh3_pct,&mut brh3_s,  &mut brh3_c);
-                                    acc(bag_rh4_pct,&mut brh4_s,  &mut brh4_c);
-                                    acc(bag_rh_avg_pct,&mut brh_avg_s,&mut brh_avg_c);
-                                    acc(par_value as f32,&mut par_s,&mut par_c);
-                                    acc(weight_g as f32,&mut weight_s,&mut weight_c);
-                                    acc(ea_air_kpa,  &mut ea_air_s,  &mut ea_air_c);
-                                    acc(ea_leaf_kpa, &mut ea_leaf_s, &mut ea_leaf_c);
-                                    acc(es_kpa,      &mut es_s,      &mut es_c);
-                                    acc(vpd_kpa,     &mut vpd_s,     &mut vpd_c);
-                                }
-                            }
-
-                            let na = NodeAvg {
-                                greenhouse_id: win.ids.0, node_id: win.ids.1, at: now,
-                                air_temp_c: mean(air_t_s, air_t_c),   leaf_temp_c: mean(leaf_t_s, leaf_t_c),
-                                bag_temp_c: mean(bag_t_s, bag_t_c),   air_rh_pct:  mean(air_rh_s, air_rh_c),
-                                bag_rh1_pct: mean(brh1_s, brh1_c),    bag_rh2_pct: mean(brh2_s, brh2_c),
-                                bag_rh3_pct: mean(brh3_s, brh3_c),    bag_rh4_pct: mean(brh4_s, brh4_c),
-                                bag_rh_avg_pct: mean(brh_avg_s, brh_avg_c),
-                                par_value: mean(par_s, par_c),        weight_g:  mean(weight_s, weight_c),
-                                ea_air_kpa: mean(ea_air_s, ea_air_c), ea_leaf_kpa: mean(ea_leaf_s, ea_leaf_c),
-                                es_kpa: mean(es_s, es_c),             vpd_kpa: mean(vpd_s, vpd_c),
-                            };
-
-                            println!(
-                              "[AVG-60s] GH:{} Node:{} | Samples:{} | Air:{} | Leaf:{} | Bag:{} | RH:{} | BRH1:{} | BRH2:{} | BRH3:{} | BRH4:{} | BRH_avg:{} | PAR:{} | W:{} | Ea_air:{} | Ea_leaf:{} | Es:{} | VPD:{}",
-                              win.ids.0, win.ids.1, samples,
-                              fmt_opt2(na.air_temp_c, "C"),
-                              fmt_opt2(na.leaf_temp_c, "C"),
-                              fmt_opt2(na.bag_temp_c, "C"),
-                              fmt_opt2(na.air_rh_pct, "%"),
-                              fmt_opt2(na.bag_rh1_pct, "%"),
-                              fmt_opt2(na.bag_rh2_pct, "%"),
-                              fmt_opt2(na.bag_rh3_pct, "%"),
-                              fmt_opt2(na.bag_rh4_pct, "%"),
-                              fmt_opt2(na.bag_rh_avg_pct, "%"),
-                              fmt_opt2(na.par_value, ""),
-                              fmt_opt2(na.weight_g, ""),
-                              fmt_opt2(na.ea_air_kpa, "kPa"),
-                              fmt_opt2(na.ea_leaf_kpa, "kPa"),
-                              fmt_opt2(na.es_kpa, "kPa"),
-                              fmt_opt2(na.vpd_kpa, "kPa"),
-                            );
-
-                            let _ = tx_nodeavg_db.try_send(na);
-                            let _ = tx_nodeavg_gh.try_send(na);
-                            let _ = tx_nodeavg_ui.try_send(NodeAvgUi {
-                                ts_ms: now_ms(),
-                                greenhouse_id: win.ids.0,
-                                node_id: win.ids.1,
-                                air_temp_c: na.air_temp_c,
-                                leaf_temp_c: na.leaf_temp_c,
-                                bag_temp_c: na.bag_temp_c,
-                                air_rh_pct: na.air_rh_pct,
-                                bag_rh1_pct: na.bag_rh1_pct,
-                                bag_rh2_pct: na.bag_rh2_pct,
-                                bag_rh3_pct: na.bag_rh3_pct,
-                                bag_rh4_pct: na.bag_rh4_pct,
-                                bag_rh_avg_pct: na.bag_rh_avg_pct,
-                                par_value: na.par_value,
-                                weight_g: na.weight_g,
-                                ea_air_kpa: na.ea_air_kpa,
-                                ea_leaf_kpa: na.ea_leaf_kpa,
-                                es_kpa: na.es_kpa,
-                                vpd_kpa: na.vpd_kpa,
-                            });
-                        }
-                        NodeKind::Outdoor => {
-                            let (mut air_t_s,mut air_t_c)=(0.0,0); let (mut air_rh_s,mut air_rh_c)=(0.0,0);
-                            let (mut par_s,mut par_c)=(0.0,0); let (mut ea_air_s,mut ea_air_c)=(0.0,0);
-                            let (mut es_s,mut es_c)=(0.0,0);
-
-                            for s in win.buf.iter() {
-                                if let Decoded::Outdoor { air_temp_c, air_rh_pct, par_value, ea_air_kpa, es_kpa, .. } = s.data {
-                                    acc(air_temp_c, &mut air_t_s, &mut air_t_c);
-                                    acc(air_rh_pct, &mut air_rh_s, &mut air_rh_c);
-                                    acc(par_value as f32, &mut par_s, &mut par_c);
-                                    acc(ea_air_kpa, &mut ea_air_s, &mut ea_air_c);
-                                    acc(es_kpa, &mut es_s, &mut es_c);
-                                }
-                            }
-
-                            let na = NodeAvg {
-                                greenhouse_id: win.ids.0, node_id: win.ids.1, at: now,
-                                air_temp_c: mean(air_t_s, air_t_c),  leaf_temp_c: None,
-                                bag_temp_c: None,                    air_rh_pct: mean(air_rh_s, air_rh_c),
-                                bag_rh1_pct: None, bag_rh2_pct: None, bag_rh3_pct: None, bag_rh4_pct: None,
-                                bag_rh_avg_pct: None,
-                                par_value: mean(par_s, par_c),       weight_g: None,
-                                ea_air_kpa: mean(ea_air_s, ea_air_c), ea_leaf_kpa: None,
-                                es_kpa: mean(es_s, es_c),            vpd_kpa: None,
-                            };
-
-                            println!(
-                                "[AVG-60s] GH:{} Node:{} | Samples:{} | Air:{} | RH:{} | PAR:{} | Ea_air:{} | Es:{}",
-                                win.ids.0, win.ids.1, samples,
-                                fmt_opt2(na.air_temp_c, "C"),
-                                fmt_opt2(na.air_rh_pct, "%"),
-                                fmt_opt2(na.par_value, ""),
-                                fmt_opt2(na.ea_air_kpa, "kPa"),
-                                fmt_opt2(na.es_kpa, "kPa"),
-                            );
-
-                            let _ = tx_nodeavg_db.try_send(na);
-                            let _ = tx_nodeavg_gh.try_send(na);
-                            let _ = tx_nodeavg_ui.try_send(NodeAvgUi {
-                                ts_ms: now_ms(),
-                                greenhouse_id: win.ids.0,
-                                node_id: win.ids.1,
-                                air_temp_c: na.air_temp_c,
-                                leaf_temp_c: na.leaf_temp_c,
-                                bag_temp_c: na.bag_temp_c,
-                                air_rh_pct: na.air_rh_pct,
-                                bag_rh1_pct: na.bag_rh1_pct,
-                                bag_rh2_pct: na.bag_rh2_pct,
-                                bag_rh3_pct: na.bag_rh3_pct,
-                                bag_rh4_pct: na.bag_rh4_pct,
-                                bag_rh_avg_pct: na.bag_rh_avg_pct,
-                                par_value: na.par_value,
-                                weight_g: na.weight_g,
-                                ea_air_kpa: na.ea_air_kpa,
-                                ea_leaf_kpa: na.ea_leaf_kpa,
-                                es_kpa: na.es_kpa,
-                                vpd_kpa: na.vpd_kpa,
-                            });
-                        }
+                    if win.late_dropped > 0 {
+                        warn!(target: "AVG", "GH:{} Node:{} | {} late sample(s) dropped: their window is already stored",
+                              win.ids.0, win.ids.1, std::mem::take(&mut win.late_dropped));
                     }
+                    if !win.due() && !last { continue; }
+                    win.prune(now);
+                    let window_start_ms = tick_ms - span_ms;
+                    win.open_start_ms = tick_ms;
+                    let packets = win.packets.take();
+                    if win.buf.is_empty() { continue; }
+                    let meta = WindowMeta { at: now, ts_ms: tick_ms, window_start_ms, window_seq, packets, catchup: false };
+                    let na = win.close(&win.buf, meta, &maintenance_windows, &health);
+                    win.stored.insert(window_start_ms, tick_ms);
+                    win.stored.forget_before(tick_ms - backfill_ms);
+                    tx_nodeavg_db.send(na).await;
+                    tx_nodeavg_gh.send(na).await;
+                    emit_ui(&tx_nodeavg_ui, &latest_nodes, NodeAvgUi::new(&na)).await;
                 }
+                if catchup.as_ref().is_some_and(|c| c.current) && nodes.values().all(|w| w.past.is_empty()) {
+                    let report = catchup.take().unwrap_or_default().finish(tick_ms);
+                    info!(target: "AVG", "startup catch-up done: {} past window(s) of {} node(s) rebuilt from {} late sample(s), {} dropped",
+                          report.windows, report.nodes, report.samples, report.dropped);
+                    tx_catchup_done.send(report).await;
+                }
+                if last { return; }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl NodeAvg {
+    /// A 60 s window of a standard node closing at `ts_ms` with air temperature and RH only.
+    pub(crate) fn sample(greenhouse_id: u16, node_id: u16, ts_ms: i64, air_temp_c: f32) -> Self {
+        NodeAvg {
+            greenhouse_id, node_id, at: Instant::now(), ts_ms,
+            window_start_ms: ts_ms - 60_000, window_seq: 1, window_sec: 60, maintenance: false, outdoor: false,
+            air_temp_c: Some(air_temp_c), leaf_temp_c: None, bag_temp_c: None, air_rh_pct: Some(70.0),
+            bag_rh1_pct: None, bag_rh2_pct: None, bag_rh3_pct: None, bag_rh4_pct: None, bag_rh_avg_pct: None,
+            par_value: None, weight_g: None, ea_air_kpa: None, ea_leaf_kpa: None, es_kpa: None, vpd_kpa: None,
+            leaf_air_dt_c: None, transpiration_g_min: None,
+            extremes: Extremes::default(), spread: Spread::default(), samples: SampleCounts::default(),
+            derived: DerivedValues::default(),
+            received_packets: 6, lost_packets: None, measured_ms: None, catchup: false, soil: None, battery_v: None, rssi_dbm: None,
+            wind_ms: None, wind_gust_ms: None, rain_mm: None,
+        }
+    }
+}
+
+/// Runs the aggregator over `frames` until their queue closes; the last window of every node.
+#[cfg(test)]
+pub(crate) async fn last_windows(frames: Vec<Decoded>) -> HashMap<(u16, u16), NodeAvg> {
+    last_windows_calibrated(frames, Default::default()).await
+}
+
+/// `last_windows` with calibration offsets.
+#[cfg(test)]
+pub(crate) async fn last_windows_calibrated(frames: Vec<Decoded>, calibrations: CalibrationShared) -> HashMap<(u16, u16), NodeAvg> {
+    let (live, ..) = db_windows(frames, calibrations).await;
+    live.into_iter().map(|na| ((na.greenhouse_id, na.node_id), na)).collect()
+}
+
+/// Runs the aggregator over `frames` until their queue closes: the windows sent to the DB writer,
+/// live and back-filled (`catchup`), in order, and the catch-up report if one went out.
+#[cfg(test)]
+pub(crate) async fn db_windows(frames: Vec<Decoded>, calibrations: CalibrationShared) -> (Vec<NodeAvg>, Vec<NodeAvg>, Option<CatchupReport>) {
+    use crate::services::channels::{bounded_queue, lane, FullPolicy, Priority};
+    use crate::services::supervisor::Slot;
+    let lanes = Default::default();
+    let (tx, rx) = bounded_queue("decoded", frames.len().max(1), FullPolicy::DropNewest);
+    for d in frames { tx.send(d).await; }
+    drop(tx);
+    let (db, mut db_rx) = lane(&lanes, "db", Priority::Droppable, 64);
+    let (catchup, mut catchup_rx) = lane(&lanes, "catchup", Priority::Critical, 64);
+    let (catchup_done, mut report_rx) = lane(&lanes, "catchup_done", Priority::Droppable, 4);
+    let out = NodeAvgOutputs {
+        db,
+        gh: lane(&lanes, "gh", Priority::Droppable, 64).0,
+        ui: lane(&lanes, "ui", Priority::Droppable, 64).0,
+        live: lane(&lanes, "live", Priority::Droppable, 64).0,
+        status: lane(&lanes, "status", Priority::Droppable, 64).0,
+        latest: Default::default(),
+        catchup,
+        catchup_done,
+    };
+    let rx = Slot::new(rx).lease().unwrap();
+    run_rolling_avg(rx, Duration::from_secs(60), out, Default::default(), Default::default(), Default::default(), calibrations).await;
+    let (mut live, mut late) = (Vec::new(), Vec::new());
+    while let Ok(na) = db_rx.try_recv() { live.push(na); }
+    while let Ok(na) = catchup_rx.try_recv() { late.push(na); }
+    (live, late, report_rx.try_recv().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standard(gh: u16, node: u16, air_temp_c: f32) -> Decoded {
+        Decoded::Standard {
+            greenhouse_id: gh, node_id: node,
+            air_temp_c, leaf_temp_c: 23.0, bag_temp_c: 21.5, air_rh_pct: 68.0,
+            bag_rh1_pct: 80.0, bag_rh2_pct: 81.0, bag_rh3_pct: 82.0, bag_rh4_pct: 83.0, bag_rh_avg_pct: 81.5,
+            par_value: 420, weight_g: 1200.0,
+            ea_air_kpa: 2.1, ea_leaf_kpa: 2.4, es_kpa: 3.1, vpd_kpa: 1.0,
+            battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        }
+    }
+
+    fn clocked(mut d: Decoded, ts: i64) -> Decoded {
+        if let Decoded::Standard { device_ts, .. } = &mut d { *device_ts = Some(ts as u32); }
+        d
+    }
+
+    #[test]
+    fn node_clock_places_the_sample_when_plausible() {
+        let now = Instant::now();
+        let wall = now_ms();
+        assert_eq!(sample_time(now, wall, None), (now, None));
+        let ts = (wall / 1000 - 30) as u32;
+        let (at, device_ms) = sample_time(now, wall, Some(ts));
+        assert_eq!(device_ms, Some(i64::from(ts) * 1000));
+        assert_eq!(now.duration_since(at).as_secs(), 30);
+        // a node clock slightly ahead counts as now
+        assert_eq!(sample_time(now, wall, Some((wall / 1000 + 5) as u32)), (now, Some(wall)));
+        // behind by more than the skew is a late sample, back-filled up to max_backfill_secs
+        let (skew, backfill) = (aggregation().max_device_skew_secs as i64, aggregation().max_backfill_secs as i64);
+        let late = (wall / 1000 - skew - 60) as u32;
+        assert_eq!(sample_time(now, wall, Some(late)).1, Some(i64::from(late) * 1000));
+        // too far ahead, or behind beyond the back-fill horizon: arrival time, like a frame without a clock
+        assert_eq!(sample_time(now, wall, Some((wall / 1000 + skew + 60) as u32)), (now, None));
+        assert_eq!(sample_time(now, wall, Some((wall / 1000 - backfill - 60) as u32)), (now, None));
+        assert_eq!(sample_time(now, wall, Some(0)), (now, None));
+    }
+
+    #[tokio::test]
+    async fn burst_after_dropout_is_back_filled_by_measurement_time() {
+        // samples from minutes 4 and 3 ago arrive with a live one: each minute gets its own window
+        let m = now_ms() / 60_000 * 60 - 240;
+        let (live, late, report) = db_windows(vec![
+            clocked(standard(1, 3, 10.0), m + 10),
+            clocked(standard(1, 3, 20.0), m + 20),
+            clocked(standard(1, 3, 30.0), m + 70),
+            standard(1, 3, 25.0),
+        ], Default::default()).await;
+        assert_eq!(live.len(), 1);
+        assert_eq!((live[0].air_temp_c, live[0].samples.get("air_temp_c")), (Some(25.0), Some(1)));
+        assert!(!live[0].catchup);
+
+        assert_eq!(late.len(), 2);
+        let (a, b) = (late[0], late[1]);
+        assert!(a.catchup && b.catchup);
+        assert_eq!((a.window_start_ms, a.ts_ms, a.window_sec), (m * 1000, (m + 60) * 1000, 60));
+        assert_eq!((a.air_temp_c, a.samples.get("air_temp_c")), (Some(15.0), Some(2)));
+        assert_eq!(a.measured_ms, Some((m + 20) * 1000));
+        assert_eq!(a.received_packets, 2);
+        assert_eq!((b.window_start_ms, b.air_temp_c), ((m + 60) * 1000, Some(30.0)));
+
+        // the live sample says the backlog is over: one report for both windows
+        let r = report.expect("catchup_complete");
+        assert_eq!((r.windows, r.samples, r.dropped, r.nodes), (2, 3, 0, 1));
+        assert_eq!((r.from_ms, r.to_ms), (Some(m * 1000), Some((m + 120) * 1000)));
+    }
+
+    #[tokio::test]
+    async fn no_catchup_report_before_a_current_sample() {
+        // only backlog so far (the app stopped mid catch-up): windows written, nothing reported
+        let m = now_ms() / 60_000 * 60 - 240;
+        let (live, late, report) = db_windows(vec![clocked(standard(1, 3, 10.0), m + 10)], Default::default()).await;
+        assert!(live.is_empty());
+        assert_eq!(late.len(), 1);
+        assert!(report.is_none());
+        // no backlog at all: an empty report once the first current sample is in
+        let r = db_windows(vec![standard(1, 3, 25.0)], Default::default()).await.2.unwrap();
+        assert_eq!((r.windows, r.nodes, r.from_ms), (0, 0, None));
+    }
+
+    #[test]
+    fn late_samples_go_to_their_window_unless_it_is_stored() {
+        let mut win = NodeWindow::new(NodeKind::Standard, (1, 3), Duration::from_secs(60), 600_000);
+        assert_eq!(win.place(None), Place::Open);
+        assert_eq!(win.place(Some(600_000)), Place::Open);
+        assert_eq!(win.place(Some(599_999)), Place::Past(540_000));
+        assert_eq!(win.place(Some(120_500)), Place::Past(120_000));
+        // windows emitted live, or back-filled, take no more samples
+        win.stored.insert(480_000, 540_000);
+        win.stored.insert(120_000, 180_000);
+        assert_eq!(win.place(Some(500_000)), Place::Covered);
+        assert_eq!(win.place(Some(179_999)), Place::Covered);
+        assert_eq!(win.place(Some(180_000)), Place::Past(180_000));
+        // a past window overlapping the open one (a longer outdoor window) is not split
+        let outdoor = NodeWindow { open_start_ms: 660_000, ..NodeWindow::new(NodeKind::Outdoor, (1, 9), Duration::from_secs(60), 0) };
+        assert_eq!(outdoor.span_ms(), 300_000);
+        assert_eq!(outdoor.place(Some(650_000)), Place::Covered);
+        assert_eq!(outdoor.place(Some(590_000)), Place::Past(300_000));
+    }
+
+    #[test]
+    fn stored_spans_merge_and_age_out() {
+        let mut spans = Spans::default();
+        spans.insert(60, 120);
+        spans.insert(180, 240);
+        spans.insert(120, 180);
+        assert_eq!(spans.0.iter().map(|(&s, &e)| (s, e)).collect::<Vec<_>>(), [(60, 240)]);
+        spans.insert(300, 360);
+        assert!(spans.overlaps(0, 61) && spans.overlaps(239, 300) && !spans.overlaps(240, 300));
+        spans.forget_before(240);
+        assert_eq!(spans.0.len(), 1);
+        assert!(!spans.overlaps(60, 120));
+    }
+
+    #[test]
+    fn a_past_window_closes_after_a_quiet_tick() {
+        let mut win = NodeWindow::new(NodeKind::Standard, (1, 3), Duration::from_secs(60), 600_000);
+        let at = Instant::now();
+        let sample = |ms: i64| TimedSample { at, device_ms: Some(ms), data: standard(1, 3, 20.0) };
+        win.backfill(120_000, sample(150_000));
+        win.backfill(120_000, sample(130_000));
+        assert_eq!(win.past[&120_000].buf.iter().map(|s| s.device_ms).collect::<Vec<_>>(), [Some(130_000), Some(150_000)]);
+        // the burst is still arriving at the first tick
+        assert!(win.past_due(false).is_empty());
+        win.backfill(180_000, sample(190_000));
+        let due = win.past_due(false);
+        assert_eq!(due.iter().map(|(s, w)| (*s, w.buf.len())).collect::<Vec<_>>(), [(120_000, 2)]);
+        // at the end everything goes, fresh or not
+        win.backfill(240_000, sample(250_000));
+        assert_eq!(win.past_due(true).len(), 2);
+        assert!(win.past.is_empty());
+    }
+
+    fn counted(seqs: &[u16]) -> (u32, Option<u32>) {
+        let mut p = PacketCount::default();
+        for &s in seqs { p.on_frame(Some(s)); }
+        p.take()
+    }
+
+    #[test]
+    fn sequence_gaps_survive_wraparound() {
+        assert_eq!(counted(&[65533, 65534, 65535, 0, 1]), (5, Some(0)));
+        assert_eq!(counted(&[65534, 1]), (2, Some(2)), "65535 and 0 went missing");
+        assert_eq!(counted(&[7, 7, 8]), (2, Some(0)), "redelivery is neither received twice nor lost");
+    }
+
+    #[test]
+    fn a_large_backwards_jump_is_a_reboot() {
+        assert_eq!(counted(&[5000, 5001, 0, 1]), (4, Some(0)));
+        assert_eq!(counted(&[5000, 3]), (2, Some(3)), "frames 0..3 after the reboot went missing");
+        // counting goes on from the new number
+        assert_eq!(counted(&[5000, 0, 2]), (3, Some(1)));
+    }
+
+    #[test]
+    fn reordered_frames_are_not_reboots() {
+        // 12 overtakes 11: the gap it left is filled once 11 arrives, and 13 follows 12
+        assert_eq!(counted(&[10, 12, 11, 13]), (4, Some(0)));
+        // low numbers right after boot used to look like a reboot and count them all as lost
+        assert_eq!(counted(&[3, 5, 4, 6]), (4, Some(0)));
+        // a straggler from the last window takes nothing below zero
+        let mut p = PacketCount::default();
+        for s in [20, 21] { p.on_frame(Some(s)); }
+        p.take();
+        p.on_frame(Some(19));
+        assert_eq!(p.take(), (1, Some(0)));
+        p.on_frame(Some(22));
+        assert_eq!(p.take(), (1, Some(0)), "last_seq stayed at 21");
+    }
+
+    #[tokio::test]
+    async fn frames_without_a_clock_keep_arrival_time() {
+        let w = last_windows(vec![standard(1, 3, 20.0), standard(1, 3, 22.0), standard(1, 4, 25.0)]).await;
+        assert_eq!(w[&(1, 3)].air_temp_c, Some(21.0));
+        assert_eq!(w[&(1, 3)].measured_ms, None);
+        assert_eq!(w[&(1, 4)].air_temp_c, Some(25.0));
+    }
+
+    #[tokio::test]
+    async fn calibration_offsets_apply_before_averaging() {
+        use super::calibration::Offsets;
+        let cal: CalibrationShared = Default::default();
+        cal.write().unwrap().insert((1, 3), Offsets { air_temp_c: -1.5, air_rh_pct: 2.0 });
+        let w = last_windows_calibrated(vec![standard(1, 3, 20.0), standard(1, 3, 22.0), standard(1, 4, 25.0)], cal).await;
+        let na = w[&(1, 3)];
+        assert_eq!((na.air_temp_c, na.air_rh_pct), (Some(19.5), Some(70.0)));
+        assert_eq!(na.extremes.air_temp_c_min, Some(18.5));
+        // leaf side untouched, so the leaf-air delta moves with the air correction
+        assert_eq!(na.leaf_temp_c, Some(23.0));
+        assert_eq!(na.leaf_air_dt_c, Some(3.5));
+        assert_eq!((w[&(1, 4)].air_temp_c, w[&(1, 4)].air_rh_pct), (Some(25.0), Some(68.0)));
+    }
+
+    #[tokio::test]
+    async fn soil_windows_average_the_substrate_fields_only() {
+        let soil = |vwc1, ec| Decoded::Soil { greenhouse_id: 1, node_id: 9, vwc1_pct: vwc1, vwc2_pct: 40.0, vwc3_pct: f32::NAN, vwc4_pct: 30.0, ec_ms_cm: ec };
+        let na = last_windows(vec![soil(34.0, 2.0), soil(36.0, 2.4), soil(35.0, f32::NAN)]).await[&(1, 9)];
+        let s = na.soil.unwrap();
+        assert_eq!((s.vwc1_pct, s.vwc2_pct, s.vwc3_pct, s.vwc4_pct), (Some(35.0), Some(40.0), None, Some(30.0)));
+        assert!((s.ec_ms_cm.unwrap() - 2.2).abs() < 1e-6);
+        assert_eq!(na.samples.get("ec_ms_cm"), Some(2));
+        assert_eq!((na.air_temp_c, na.outdoor, na.window_sec), (None, false, 60));
+        let keys: Vec<_> = na.fields().iter().map(|f| f.0).collect();
+        assert_eq!(keys, ["vwc1_pct", "vwc2_pct", "vwc3_pct", "vwc4_pct", "ec_ms_cm"]);
+    }
+
+    fn telemetry(mut d: Decoded, batt: f32, rssi: f32) -> Decoded {
+        if let Decoded::Standard { battery_v, rssi_dbm, .. } = &mut d { (*battery_v, *rssi_dbm) = (Some(batt), Some(rssi)); }
+        d
+    }
+
+    #[tokio::test]
+    async fn telemetry_is_averaged_and_stays_none_for_old_firmware() {
+        let w = last_windows(vec![
+            telemetry(standard(1, 3, 20.0), 3.6, -70.0),
+            telemetry(standard(1, 3, 20.0), 3.8, -60.0),
+            standard(1, 3, 20.0), // a frame without telemetry in between leaves the means alone
+            standard(1, 4, 20.0),
+        ]).await;
+        let na = w[&(1, 3)];
+        assert!((na.battery_v.unwrap() - 3.7).abs() < 1e-6);
+        assert_eq!(na.rssi_dbm, Some(-65.0));
+        let keys: Vec<_> = na.fields().iter().map(|f| f.0).collect();
+        assert!(keys.contains(&"battery_v") && keys.contains(&"rssi_dbm"), "{keys:?}");
+        let old = w[&(1, 4)];
+        assert_eq!((old.battery_v, old.rssi_dbm), (None, None));
+        assert!(!old.fields().iter().any(|f| f.0 == "battery_v" || f.0 == "rssi_dbm"));
+    }
+
+    #[tokio::test]
+    async fn sentinel_samples_drop_out_field_by_field() {
+        use super::super::decoder::{decode_payload, encode_payload, F32_MISSING, U16_MISSING};
+        // six frames through the wire; every other one with a dead leaf probe and no PAR
+        let frames = (0..6).map(|i| {
+            let mut d = standard(1, 3, 20.0 + i as f32);
+            if let Decoded::Standard { leaf_temp_c, par_value, .. } = &mut d {
+                *leaf_temp_c = if i % 2 == 0 { F32_MISSING } else { 24.0 };
+                if i % 2 == 0 { *par_value = U16_MISSING; }
             }
+            decode_payload(&encode_payload(&d)).unwrap()
+        }).collect();
+        let na = last_windows(frames).await[&(1, 3)];
+        assert_eq!(na.air_temp_c, Some(22.5));
+        assert_eq!(na.samples.get("air_temp_c"), Some(6));
+        assert_eq!(na.leaf_temp_c, Some(24.0));
+        assert_eq!(na.samples.get("leaf_temp_c"), Some(3));
+        assert_eq!(na.par_value, Some(420.0));
+        assert_eq!((na.extremes.par_value_min, na.extremes.par_value_max), (Some(420.0), Some(420.0)));
+
+        // a probe dead for the whole window leaves its field None; the rest of the node still counts
+        let frames = (0..4).map(|_| {
+            let mut d = standard(1, 4, 21.0);
+            if let Decoded::Standard { leaf_temp_c, .. } = &mut d { *leaf_temp_c = F32_MISSING; }
+            decode_payload(&encode_payload(&d)).unwrap()
+        }).collect();
+        let na = last_windows(frames).await[&(1, 4)];
+        assert_eq!((na.air_temp_c, na.leaf_temp_c, na.leaf_air_dt_c), (Some(21.0), None, None));
+    }
+
+    #[tokio::test]
+    async fn outdoor_node_without_leaf_sensor_has_no_leaf_air_delta() {
+        let outdoor = |air_temp_c| Decoded::Outdoor {
+            greenhouse_id: 1, node_id: 65001, air_temp_c, air_rh_pct: 60.0, par_value: 300, ea_air_kpa: 1.2, es_kpa: 2.0,
+            wind_ms: 2.0, wind_gust_ms: 4.0, rain_tips: 0, battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        };
+        let w = last_windows(vec![outdoor(12.0), standard(1, 3, 22.0), outdoor(14.0), standard(1, 3, 24.0)]).await;
+        let out = w[&(1, 65001)];
+        assert!(out.outdoor);
+        assert_eq!((out.air_temp_c, out.leaf_temp_c, out.leaf_air_dt_c), (Some(13.0), None, None));
+        // the indoor node next to it: leaf 23.0 minus the air mean
+        let na = w[&(1, 3)];
+        assert_eq!((na.air_temp_c, na.leaf_air_dt_c), (Some(23.0), Some(0.0)));
+    }
+
+    #[test]
+    fn node_avg_ui_serializes_to_the_frontend_shape() {
+        let mut na = NodeAvg::sample(1, 7, 1_760_000_040_000, 21.5);
+        na.soil = Some(SoilAvg { vwc1_pct: Some(38.0), vwc2_pct: None, vwc3_pct: None, vwc4_pct: None, ec_ms_cm: Some(2.25) });
+        let v = serde_json::to_value(NodeAvgUi::new(&na)).unwrap();
+
+        let mut keys: Vec<&str> = v.as_object().unwrap().keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        let mut expected = vec![
+            "ts_ms", "greenhouse_id", "node_id", "window_sec", "outdoor",
+            "air_temp_c", "leaf_temp_c", "bag_temp_c", "air_rh_pct",
+            "bag_rh1_pct", "bag_rh2_pct", "bag_rh3_pct", "bag_rh4_pct", "bag_rh_avg_pct",
+            "par_value", "weight_g", "ea_air_kpa", "ea_leaf_kpa", "es_kpa", "vpd_kpa", "leaf_air_dt_c", "transpiration_g_min",
+            "air_temp_c_min", "air_temp_c_max", "air_rh_pct_min", "air_rh_pct_max", "vpd_kpa_min", "vpd_kpa_max",
+            "par_value_min", "par_value_max", "weight_g_min", "weight_g_max",
+            "air_temp_sd", "air_rh_sd", "vpd_sd", "samples",
+            "maintenance", "received_packets", "lost_packets", "battery_v", "rssi_dbm",
+            "vwc1_pct", "vwc2_pct", "vwc3_pct", "vwc4_pct", "ec_ms_cm",
+            "wind_ms", "wind_gust_ms", "rain_mm",
+        ];
+        expected.extend(na.derived.fields().map(|(key, ..)| key));
+        expected.sort_unstable();
+        assert_eq!(keys, expected, "no Instant, window bookkeeping or nesting reaches the webview");
+
+        assert_eq!(v["ts_ms"], 1_760_000_040_000i64);
+        assert_eq!((v["greenhouse_id"].as_u64(), v["node_id"].as_u64(), v["window_sec"].as_u64()), (Some(1), Some(7), Some(60)));
+        assert_eq!((v["air_temp_c"].as_f64(), v["outdoor"].as_bool()), (Some(21.5), Some(false)));
+        assert!(v["leaf_temp_c"].is_null() && v["vwc2_pct"].is_null() && v["lost_packets"].is_null());
+        assert_eq!((v["vwc1_pct"].as_f64(), v["ec_ms_cm"].as_f64()), (Some(38.0), Some(2.25)));
+        assert_eq!(v["received_packets"], 6);
+    }
+
+    #[test]
+    fn window_comes_from_the_variable_then_the_config_within_limits() {
+        assert_eq!(window_from(None, 60), Ok(Duration::from_secs(60)));
+        assert_eq!(window_from(Some(" 10 ".into()), 60), Ok(Duration::from_secs(10)));
+        assert_eq!(window_from(Some("300".into()), 60), Ok(Duration::from_secs(300)));
+        for bad in ["0", "901", "10s", ""] {
+            assert!(window_from(Some(bad.into()), 60).is_err(), "{bad:?} accepted");
+        }
+        assert!(window_from(None, 0).is_err(), "a zero window in the config is refused too");
+    }
+
+    #[test]
+    fn labels_grace_and_outdoor_spans_follow_the_window() {
+        use super::super::greenhouse_aggregator::stale_grace;
+        assert_eq!((rolling_agg(60), rolling_agg(10), rolling_agg(300)), ("rolling_60s".into(), "rolling_10s".into(), "rolling_300s".into()));
+        assert_eq!(stale_grace(Duration::from_secs(60)), Duration::from_secs(5));
+        assert_eq!(stale_grace(Duration::from_secs(12)), Duration::from_secs(1));
+        // outdoor windows stay a whole number of ticks, at least the configured 300 s
+        let spans: Vec<u64> = [1, 7, 60, 900].map(|t| NodeKind::Outdoor.span(Duration::from_secs(t)).as_secs()).into();
+        assert_eq!(spans, [300, 301, 300, 900]);
+        assert_eq!(NodeKind::Standard.span(Duration::from_secs(10)), Duration::from_secs(10));
+        assert_eq!(window_close_ms(1_760_000_004_600, Duration::from_secs(10)), 1_760_000_000_000);
+        assert_eq!(window_close_ms(1_760_000_005_000, Duration::from_secs(10)), 1_760_000_010_000);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn one_second_windows_on_paused_time() {
+        use crate::services::channels::{bounded_queue, lane, FullPolicy, Priority};
+        use crate::services::supervisor::Slot;
+        let lanes = Default::default();
+        let (tx, rx) = bounded_queue("decoded", 64, FullPolicy::DropNewest);
+        let (db, mut db_rx) = lane(&lanes, "db", Priority::Droppable, 1024);
+        let out = NodeAvgOutputs {
+            db,
+            gh: lane(&lanes, "gh", Priority::Droppable, 1024).0,
+            ui: lane(&lanes, "ui", Priority::Droppable, 1024).0,
+            live: lane(&lanes, "live", Priority::Droppable, 1024).0,
+            status: lane(&lanes, "status", Priority::Droppable, 1024).0,
+            latest: Default::default(),
+            catchup: lane(&lanes, "catchup", Priority::Critical, 1024).0,
+            catchup_done: lane(&lanes, "catchup_done", Priority::Droppable, 4).0,
+        };
+        let task = tokio::spawn(run_rolling_avg(
+            Slot::new(rx).lease().unwrap(), Duration::from_secs(1), out,
+            Default::default(), Default::default(), Default::default(), Default::default(),
+        ));
+        // four frames a second for a minute of paused time
+        for _ in 0..240 {
+            tx.send(standard(1, 3, 20.0)).await;
+            tokio::time::sleep(Duration::from_millis(250)).await;
         }
+        drop(tx);
+        task.await.unwrap();
+
+        let mut windows = Vec::new();
+        while let Ok(na) = db_rx.try_recv() { windows.push(na); }
+        // the wall clock stands still on paused time, so `align_tick` re-arms each tick 0.5 to 1.5
+        // windows ahead instead of onto the next second
+        assert!((40..=121).contains(&windows.len()), "{} windows in 60 s", windows.len());
+        assert!(windows.iter().all(|w| w.window_sec == 1 && w.air_temp_c == Some(20.0) && w.received_packets <= 6));
+        assert!(windows.windows(2).all(|p| p[1].window_seq > p[0].window_seq));
+        assert_eq!(windows.iter().map(|w| w.received_packets).sum::<u32>(), 240, "every frame in exactly one window");
+    }
+
+    #[tokio::test]
+    async fn spread_is_the_sample_deviation_of_the_window() {
+        let frame = |node, t: f32, vpd: f32| {
+            let mut d = standard(1, node, t);
+            if let Decoded::Standard { vpd_kpa, .. } = &mut d { *vpd_kpa = vpd; }
+            d
+        };
+        let outdoor = |air_temp_c| Decoded::Outdoor {
+            greenhouse_id: 1, node_id: 65001, air_temp_c, air_rh_pct: 60.0, par_value: 300, ea_air_kpa: 1.2, es_kpa: 2.0,
+            wind_ms: 2.0, wind_gust_ms: 4.0, rain_tips: 0, battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        };
+        let w = last_windows(vec![
+            frame(3, 20.0, 0.8), frame(3, 22.0, 1.0), frame(3, 24.0, 1.2), frame(3, 26.0, 1.4),
+            frame(4, 21.0, 1.0),
+            outdoor(10.0), outdoor(12.0),
+        ]).await;
+
+        // deviations from the mean 23: -3, -1, 1, 3; sum of squares 20 over n - 1 = 3
+        let s = w[&(1, 3)].spread;
+        assert!((s.air_temp_sd.unwrap() - (20.0f32 / 3.0).sqrt()).abs() < 1e-5, "{s:?}");
+        assert!((s.vpd_sd.unwrap() - (0.2f32 / 3.0).sqrt()).abs() < 1e-5, "{s:?}");
+        assert_eq!(s.air_rh_sd, Some(0.0), "steady RH over four samples");
+        // one reading is not "perfectly stable"
+        let one = w[&(1, 4)].spread;
+        assert_eq!((one.air_temp_sd, one.air_rh_sd, one.vpd_sd), (None, None, None));
+        // an outdoor station has no VPD
+        let o = w[&(1, 65001)].spread;
+        assert!((o.air_temp_sd.unwrap() - std::f32::consts::SQRT_2).abs() < 1e-5, "{o:?}");
+        assert_eq!((o.air_rh_sd, o.vpd_sd), (Some(0.0), None));
+    }
+
+    fn field(mode: AggMode, vals: &[f32]) -> FieldAgg {
+        let mut f = FieldAgg::with("par_value", mode, None);
+        for &v in vals { f.add(v); }
+        f.close();
+        f
+    }
+
+    #[test]
+    fn median_and_trimmed_mean_shrug_off_a_spike() {
+        let spiky = [400.0, 410.0, f32::NAN, 5_000.0, 420.0];
+        let value = |mode| field(mode, &spiky).value();
+        assert_eq!(value(AggMode::Mean), Some(1_557.5));
+        // four samples: the middle two averaged
+        assert_eq!(value(AggMode::Median), Some(415.0));
+        assert_eq!(value(AggMode::TrimmedMean { pct: 25 }), Some(415.0));
+        // the extremes and deviation still describe every sample
+        let f = field(AggMode::Median, &spiky);
+        assert_eq!((f.stats.count(), f.stats.min(), f.stats.max()), (4, Some(400.0), Some(5_000.0)));
+    }
+
+    #[test]
+    fn identical_samples_give_that_value_in_every_mode() {
+        for mode in [AggMode::Mean, AggMode::Median, AggMode::TrimmedMean { pct: 10 }, AggMode::TrimmedMean { pct: 49 }] {
+            let mut f = field(mode, &[300.0; 6]);
+            assert_eq!((f.value(), f.stats.sd()), (Some(300.0), Some(0.0)), "{mode:?}");
+        }
+        for mode in [AggMode::Median, AggMode::TrimmedMean { pct: 10 }] {
+            assert_eq!(field(mode, &[f32::NAN]).value(), None, "no finite sample, no value");
+        }
+    }
+
+    #[test]
+    fn stored_agg_names_the_mode() {
+        assert_eq!(mode_agg(AggMode::Mean, 60), "rolling_60s");
+        assert_eq!(mode_agg(AggMode::Median, 60), "rolling_median_60s");
+        assert_eq!(mode_agg(AggMode::TrimmedMean { pct: 10 }, 300), "rolling_trim10_300s");
+        // fixed reductions whatever the mode
+        assert_eq!((agg_mode("wind_ms"), agg_mode("battery_v")), (AggMode::Mean, AggMode::Mean));
+
+        assert_eq!(AggMode::parse(" median "), Some(AggMode::Median));
+        assert_eq!(AggMode::parse("trim: 15"), Some(AggMode::TrimmedMean { pct: 15 }));
+        for bad in ["trim:50", "trim:x", "avg", "trim"] {
+            assert_eq!(AggMode::parse(bad), None, "{bad}");
+        }
+    }
+
+    /// ~24 °C over a window with one frozen I2C read of 0.0 among them.
+    const FROZEN_READ: [f32; 12] = [24.1, 23.9, 24.0, 24.2, 0.0, 23.8, 24.1, 24.0, 23.9, 24.2, 24.0, 24.3];
+
+    fn air(vals: &[f32], reject_k: Option<f32>) -> FieldAgg {
+        let mut f = FieldAgg::with("air_temp_c", AggMode::Mean, reject_k);
+        for &v in vals { f.add(v); }
+        f.close();
+        f
+    }
+
+    #[test]
+    fn a_frozen_read_skews_the_mean_unless_rejected() {
+        let mut kept = air(&FROZEN_READ, None);
+        assert!((kept.value().unwrap() - 22.04).abs() < 0.01, "{:?}", kept.value());
+        assert_eq!(kept.rejected, 0);
+
+        let mut clean = air(&FROZEN_READ, Some(5.0));
+        assert!((clean.value().unwrap() - 24.05).abs() < 0.01, "{:?}", clean.value());
+        assert_eq!(clean.rejected, 1);
+        // extremes and deviation describe the samples kept
+        assert_eq!((clean.stats.count(), clean.stats.min()), (11, Some(23.8)));
+        assert!(clean.stats.sd().unwrap() < 0.2);
+    }
+
+    #[test]
+    fn rejection_needs_enough_samples_and_spares_plain_noise() {
+        // four samples cannot say which one is off
+        assert_eq!(air(&[24.0, 24.1, 0.0, 23.9], Some(5.0)).rejected, 0);
+        // a wide but spike-free spread loses nothing
+        assert_eq!(air(&[21.0, 23.5, 22.0, 24.8, 22.7, 23.1, 21.9], Some(5.0)).rejected, 0);
+        assert_eq!(air(&[24.0; 8], Some(5.0)).rejected, 0);
+    }
+
+    #[test]
+    fn rejected_samples_are_counted_per_sensor_for_node_health() {
+        let health = HealthCountersShared::default();
+        // two windows, each with the frozen temperature read; the first also has a dropped RH read
+        for rh_drop in [true, false] {
+            let mut fields = [FieldAgg::with("air_temp_c", AggMode::Mean, Some(5.0)), FieldAgg::with("air_rh_pct", AggMode::Mean, Some(5.0))];
+            for (i, t) in FROZEN_READ.into_iter().enumerate() {
+                fields[0].add(t);
+                fields[1].add(if rh_drop && i == 7 { 0.0 } else { 65.0 + (i % 3) as f32 * 0.1 });
+            }
+            close_fields(&mut fields, (1, 3), &health);
+        }
+
+        let m = health.lock().unwrap();
+        let outliers: Vec<_> = m[&(1, 3)].outliers.iter().map(|(k, n)| (*k, *n)).collect();
+        assert_eq!(outliers, [("air_rh_pct", 1), ("air_temp_c", 2)]);
+    }
+
+    /// Six frames of node 5: RH missing from two, leaf temperature infinite in one.
+    fn gappy_window() -> Vec<Decoded> {
+        (0..6).map(|i| {
+            let mut d = standard(1, 5, 22.0);
+            if let Decoded::Standard { air_rh_pct, leaf_temp_c, .. } = &mut d {
+                if i == 1 || i == 3 { *air_rh_pct = f32::NAN; }
+                if i == 2 { *leaf_temp_c = f32::INFINITY; }
+            }
+            d
+        }).collect()
+    }
+
+    #[tokio::test]
+    async fn sample_counts_are_the_finite_inputs_not_the_window_size() {
+        let na = last_windows(gappy_window()).await[&(1, 5)];
+        assert_eq!(na.received_packets, 6);
+        let s = na.samples;
+        assert_eq!((s.get("air_temp_c"), s.get("air_rh_pct"), s.get("leaf_temp_c"), s.get("vpd_kpa")), (Some(6), Some(4), Some(5), Some(6)));
+        assert_eq!(s.get("wind_ms"), None, "not a reading of this node");
+
+        let v = serde_json::to_value(NodeAvgUi::new(&na)).unwrap();
+        assert_eq!((v["samples"]["air_rh_pct"].as_u64(), v["samples"]["leaf_temp_c"].as_u64()), (Some(4), Some(5)));
+        assert_eq!(v["samples"].as_object().unwrap().len(), STANDARD_READINGS.len());
+    }
+
+    /// Six weights 10 s apart losing 3 g/min, with ±0.4 g of load-cell noise.
+    fn declining() -> Vec<(f64, f32)> {
+        let noise = [0.4, -0.4, 0.0, 0.4, -0.4, 0.0];
+        (0..6).map(|i| (i as f64 / 6.0, 1_200.0 - 0.5 * i as f32 + noise[i])).collect()
+    }
+
+    #[test]
+    fn transpiration_is_the_weight_loss_rate() {
+        let clean: Vec<(f64, f32)> = (0..6).map(|i| (i as f64 / 6.0, 1_200.0 - 0.5 * i as f32)).collect();
+        assert!((transpiration_g_min(&clean, 20.0).unwrap() - 3.0).abs() < 1e-3);
+        let noisy = transpiration_g_min(&declining(), 20.0).unwrap();
+        assert!((noisy - 3.27).abs() < 0.01, "{noisy}");
+    }
+
+    #[test]
+    fn an_irrigation_jump_ends_the_fit() {
+        let mut w = declining();
+        for s in &mut w[4..] { s.1 += 250.0; } // a shot before the fifth sample
+        let rate = transpiration_g_min(&w, 20.0).unwrap();
+        assert!((rate - 2.76).abs() < 0.01, "fitted on the four samples before the shot: {rate}");
+        // fitting across the shot would read as a huge negative loss
+        let all: Vec<(f64, f64)> = w.iter().map(|&(m, g)| (m, f64::from(g))).collect();
+        assert!(ls_slope(&all).unwrap() > 300.0);
+        // a rise under the threshold is noise, not irrigation
+        let mut small = declining();
+        for s in &mut small[4..] { s.1 += 15.0; }
+        assert!(transpiration_g_min(&small, 20.0).unwrap() < -10.0);
+        assert!((transpiration_g_min(&small, 10.0).unwrap() - 2.76).abs() < 0.01, "the threshold is configurable");
+    }
+
+    #[test]
+    fn transpiration_needs_two_samples_before_any_shot() {
+        assert_eq!(transpiration_g_min(&[(0.0, 1_200.0)], 20.0), None);
+        assert_eq!(transpiration_g_min(&[(0.0, 1_200.0), (0.2, 1_260.0), (0.4, 1_259.0)], 20.0), None);
+        let gaps = [(0.0, 1_200.0), (0.1, f32::NAN), (0.2, 1_199.0), (0.3, f32::INFINITY)];
+        assert!((transpiration_g_min(&gaps, 20.0).unwrap() - 5.0).abs() < 1e-3, "non-finite weights are skipped");
+    }
+
+    #[tokio::test]
+    async fn node_windows_carry_their_transpiration() {
+        // measured every 10 s over a minute two minutes ago (one back-filled window, by node clock),
+        // losing 0.5 g per 10 s, a 250 g shot at the last sample
+        let m = now_ms() / 60_000 * 60 - 120;
+        let frames: Vec<Decoded> = (0..6).map(|i| {
+            let mut d = standard(1, 6, 22.0);
+            if let Decoded::Standard { weight_g, .. } = &mut d {
+                *weight_g = 1_200.0 - 0.5 * i as f32 + if i == 5 { 250.0 } else { 0.0 };
+            }
+            clocked(d, m + 5 + 10 * i)
+        }).collect();
+        let (_, late, _) = db_windows(frames, Default::default()).await;
+        let na = late[0];
+        let rate = na.transpiration_g_min.unwrap();
+        assert!((rate - 3.0).abs() < 0.1, "{rate}");
+        assert!((serde_json::to_value(NodeAvgUi::new(&na)).unwrap()["transpiration_g_min"].as_f64().unwrap() - 3.0).abs() < 0.1);
+        // outdoor stations have no load cell
+        let outdoor = Decoded::Outdoor {
+            greenhouse_id: 1, node_id: 65001, air_temp_c: 12.0, air_rh_pct: 60.0, par_value: 300, ea_air_kpa: 1.2, es_kpa: 2.0,
+            wind_ms: 2.0, wind_gust_ms: 4.0, rain_tips: 0, battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        };
+        assert_eq!(last_windows(vec![outdoor, outdoor]).await[&(1, 65001)].transpiration_g_min, None);
     }
 }
@@ -0,0 +1,107 @@
+//! Node availability from the nodes' own `online` / `offline` messages (Last Will).
+//! - Nodes publish retained `online` on connect and leave `offline` as their Last Will on
+//!   `greenhouse/{gh}/node/{id}/status`; the subscriber listens next to every data filter (auth.rs).
+//! - Payloads are matched case-insensitively after trimming; JSON `{"status": "online"}` is accepted too.
+//!   An empty retained payload (status cleared on the broker) and anything else are ignored.
+//! - Retained messages arrive on every (re)connect: they fill in the state after startup, and a
+//!   redelivered status that matches the known one is not a change. Only changes are reported.
+//! - Bridged greenhouse ids go through the origin mapping (origin.rs) like data frames.
+//! - Changes go to a channel; `run_availability` emits them as `node_status` and appends them to
+//!   `node_status_log` (nothing stored in dry-run) for uptime. `get_node_availability` lists the map.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::services::presenter::emitter::EventSink;
+use crate::services::storage::node_status::insert_node_status;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Availability {
+    Online,
+    Offline,
+}
+
+impl Availability {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Availability::Online => "online",
+            Availability::Offline => "offline",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct StatusJson {
+    status: String,
+}
+
+/// `online` / `offline` (plain or JSON `status`); None for anything else.
+pub fn parse_availability(payload: &[u8]) -> Option<Availability> {
+    let text = std::str::from_utf8(payload).ok()?.trim();
+    let word = match serde_json::from_str::<StatusJson>(text) {
+        Ok(j) => j.status,
+        Err(_) => text.to_string(),
+    };
+    match word.trim().to_ascii_lowercase().as_str() {
+        "online" => Some(Availability::Online),
+        "offline" => Some(Availability::Offline),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeAvailability {
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+    pub status: Availability,
+    /// When the app saw the change (for a retained status: when it was first delivered).
+    pub since_ms: i64,
+    /// Learnt from a retained message: the node changed state at some earlier, unknown time.
+    pub retained: bool,
+}
+
+pub type AvailabilityShared = Arc<RwLock<BTreeMap<(u16, u16), NodeAvailability>>>;
+
+/// The subscriber's side: keeps the map and queues changes for the UI and the DB.
+#[derive(Clone)]
+pub struct AvailabilityTracker {
+    map: AvailabilityShared,
+    tx: mpsc::Sender<NodeAvailability>,
+}
+
+impl AvailabilityTracker {
+    pub fn new(map: AvailabilityShared, tx: mpsc::Sender<NodeAvailability>) -> Self {
+        AvailabilityTracker { map, tx }
+    }
+
+    /// Record one status message; returns the change when the node's state is new or different.
+    pub fn on_status(&self, ids: (u16, u16), status: Availability, retained: bool, now_ms: i64) -> Option<NodeAvailability> {
+        let mut map = self.map.write().ok()?;
+        if map.get(&ids).is_some_and(|a| a.status == status) { return None; }
+        let change = NodeAvailability { greenhouse_id: ids.0, node_id: ids.1, status, since_ms: now_ms, retained };
+        map.insert(ids, change.clone());
+        let _ = self.tx.try_send(change.clone());
+        Some(change)
+    }
+}
+
+/// Forward availability changes: `node_status` event, then a `node_status_log` row.
+pub async fn run_availability<S: EventSink>(sink: S, mut rx: mpsc::Receiver<NodeAvailability>, db_path: &'static str, dry_run: bool) {
+    while let Some(a) = rx.recv().await {
+        info!(
+            target: "NODE", "GH:{} Node:{} {}{}", a.greenhouse_id, a.node_id, a.status.as_str(),
+            if a.retained { " (retained)" } else { "" }
+        );
+        if let Ok(v) = serde_json::to_value(&a) { sink.emit_json("node_status", v); }
+        if dry_run { continue; }
+        match tokio::task::spawn_blocking(move || insert_node_status(db_path, &a)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!(target: "DB", "node_status_log insert failed: {e}"),
+            Err(e) => warn!(target: "DB", "node_status_log join error: {e}"),
+        }
+    }
+}
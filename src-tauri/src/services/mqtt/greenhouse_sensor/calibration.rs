@@ -0,0 +1,161 @@
+//! Per-node calibration offsets from the commissioning sheet (`node_name.air_temp_offset_c` / `air_rh_offset_pct`).
+//! - Applied as decoded frames enter the rolling average, so node windows, `node_live`, storage and every
+//!   greenhouse figure see corrected readings; raw captures and recordings keep what the node sent.
+//! - Air temperature and RH get their offset (RH clamped to 0-100). The air-side pressures the node derived
+//!   from them follow: `es_kpa` by the Tetens ratio of the two temperatures, `ea_air_kpa` by that and the RH
+//!   ratio, and `vpd_kpa` (leaf minus air vapour pressure) by the change in `ea_air_kpa`. Leaf readings stay.
+//! - Loaded at startup and reloaded after a commissioning import; nodes without offsets pass unchanged.
+
+use std::{collections::HashMap, sync::{Arc, RwLock}};
+
+use super::decoder::Decoded;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Offsets {
+    pub air_temp_c: f32,
+    pub air_rh_pct: f32,
+}
+
+pub type CalibrationShared = Arc<RwLock<HashMap<(u16, u16), Offsets>>>;
+
+pub fn replace_calibrations(cache: &CalibrationShared, offsets: HashMap<(u16, u16), Offsets>) {
+    if let Ok(mut m) = cache.write() { *m = offsets; }
+}
+
+/// Saturation vapour pressure (kPa) at `t` °C, Tetens.
+fn tetens_kpa(t: f32) -> f32 {
+    0.6108 * (17.27 * t / (t + 237.3)).exp()
+}
+
+/// `to / from`, or 1 when either side is missing or `from` is not positive.
+fn ratio(to: f32, from: f32) -> f32 {
+    if to.is_finite() && from.is_finite() && from > 0.0 { to / from } else { 1.0 }
+}
+
+/// Correct `msg` with its node's offsets, if any; true when it was corrected.
+pub fn apply(cache: &CalibrationShared, msg: &mut Decoded) -> bool {
+    let Some(o) = cache.read().ok().and_then(|m| m.get(&msg.ids()).copied()) else { return false };
+    correct(o, msg)
+}
+
+fn correct(o: Offsets, msg: &mut Decoded) -> bool {
+    let (air_temp_c, air_rh_pct, es_kpa, ea_air_kpa, vpd_kpa) = match msg {
+        Decoded::Standard { air_temp_c, air_rh_pct, es_kpa, ea_air_kpa, vpd_kpa, .. } =>
+            (air_temp_c, air_rh_pct, es_kpa, ea_air_kpa, Some(vpd_kpa)),
+        Decoded::Outdoor { air_temp_c, air_rh_pct, es_kpa, ea_air_kpa, .. } =>
+            (air_temp_c, air_rh_pct, es_kpa, ea_air_kpa, None),
+        Decoded::Soil { .. } => return false,
+    };
+    if o == Offsets::default() { return false; }
+    let (t0, rh0) = (*air_temp_c, *air_rh_pct);
+    let t1 = t0 + o.air_temp_c;
+    let rh1 = (rh0 + o.air_rh_pct).clamp(0.0, 100.0);
+    let es_ratio = ratio(tetens_kpa(t1), tetens_kpa(t0));
+    let ea0 = *ea_air_kpa;
+    let ea1 = ea0 * es_ratio * ratio(rh1, rh0);
+    *air_temp_c = t1;
+    *air_rh_pct = rh1;
+    *es_kpa *= es_ratio;
+    *ea_air_kpa = ea1;
+    if let Some(vpd) = vpd_kpa {
+        if ea0.is_finite() && ea1.is_finite() { *vpd -= ea1 - ea0; }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pressures consistent with 24 °C / 60 % and a 23 °C leaf.
+    fn standard(node_id: u16) -> Decoded {
+        let (es, es_leaf) = (tetens_kpa(24.0), tetens_kpa(23.0));
+        let ea = es * 0.6;
+        Decoded::Standard {
+            greenhouse_id: 1, node_id,
+            air_temp_c: 24.0, leaf_temp_c: 23.0, bag_temp_c: 21.5, air_rh_pct: 60.0,
+            bag_rh1_pct: 80.0, bag_rh2_pct: 81.0, bag_rh3_pct: 82.0, bag_rh4_pct: 83.0, bag_rh_avg_pct: 81.5,
+            par_value: 420, weight_g: 1200.0,
+            ea_air_kpa: ea, ea_leaf_kpa: es_leaf, es_kpa: es, vpd_kpa: es_leaf - ea,
+            battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        }
+    }
+
+    fn cache(entries: &[((u16, u16), Offsets)]) -> CalibrationShared {
+        Arc::new(RwLock::new(entries.iter().copied().collect()))
+    }
+
+    fn air(d: &Decoded) -> (f32, f32, f32, f32, f32, f32) {
+        match *d {
+            Decoded::Standard { air_temp_c, air_rh_pct, es_kpa, ea_air_kpa, vpd_kpa, leaf_temp_c, .. } =>
+                (air_temp_c, air_rh_pct, es_kpa, ea_air_kpa, vpd_kpa, leaf_temp_c),
+            _ => unreachable!(),
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool { (a - b).abs() < 1e-4 }
+
+    #[test]
+    fn offsets_correct_air_readings_and_keep_the_pressures_consistent() {
+        let c = cache(&[((1, 2), Offsets { air_temp_c: -0.5, air_rh_pct: 3.0 })]);
+        let mut d = standard(2);
+        assert!(apply(&c, &mut d));
+
+        let (t, rh, es, ea, vpd, leaf) = air(&d);
+        assert!(close(t, 23.5) && close(rh, 63.0) && leaf == 23.0);
+        // as if the node had measured 23.5 °C / 63 % itself
+        assert!(close(es, tetens_kpa(23.5)));
+        assert!(close(ea, tetens_kpa(23.5) * 0.63));
+        assert!(close(vpd, tetens_kpa(23.0) - ea));
+    }
+
+    #[test]
+    fn rh_is_clamped_to_its_range() {
+        let c = cache(&[((1, 2), Offsets { air_temp_c: 0.0, air_rh_pct: 15.0 })]);
+        let mut d = standard(2);
+        if let Decoded::Standard { air_rh_pct, .. } = &mut d { *air_rh_pct = 95.0; }
+        apply(&c, &mut d);
+        assert_eq!(air(&d).1, 100.0);
+    }
+
+    #[test]
+    fn nodes_without_offsets_pass_unchanged() {
+        let c = cache(&[((1, 2), Offsets { air_temp_c: 1.0, air_rh_pct: 0.0 }), ((1, 3), Offsets::default())]);
+        for node in [3, 4] {
+            let mut d = standard(node);
+            assert!(!apply(&c, &mut d));
+            assert_eq!(air(&d), air(&standard(node)));
+        }
+    }
+
+    #[test]
+    fn missing_readings_stay_missing() {
+        let c = cache(&[((1, 2), Offsets { air_temp_c: 1.0, air_rh_pct: 2.0 })]);
+        let mut d = standard(2);
+        if let Decoded::Standard { air_temp_c, .. } = &mut d { *air_temp_c = f32::NAN; }
+        apply(&c, &mut d);
+
+        let (t, rh, es, ea, vpd, _) = air(&d);
+        assert!(t.is_nan());
+        assert!(close(rh, 62.0));
+        // no temperature: pressures only follow the RH change
+        let (_, _, es0, ea0, vpd0, _) = air(&standard(2));
+        assert_eq!(es, es0);
+        assert!(close(ea, ea0 * 62.0 / 60.0));
+        assert!(close(vpd, vpd0 - (ea - ea0)));
+    }
+
+    #[test]
+    fn outdoor_stations_are_corrected_too() {
+        let c = cache(&[((1, 9), Offsets { air_temp_c: 1.0, air_rh_pct: -5.0 })]);
+        let mut d = Decoded::Outdoor {
+            greenhouse_id: 1, node_id: 9, air_temp_c: 10.0, air_rh_pct: 80.0, par_value: 100,
+            ea_air_kpa: tetens_kpa(10.0) * 0.8, es_kpa: tetens_kpa(10.0),
+            wind_ms: f32::NAN, wind_gust_ms: f32::NAN, rain_tips: 0, battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        };
+        assert!(apply(&c, &mut d));
+        let Decoded::Outdoor { air_temp_c, air_rh_pct, es_kpa, ea_air_kpa, .. } = d else { unreachable!() };
+        assert!(close(air_temp_c, 11.0) && close(air_rh_pct, 75.0));
+        assert!(close(es_kpa, tetens_kpa(11.0)) && close(ea_air_kpa, tetens_kpa(11.0) * 0.75));
+    }
+}
@@ -0,0 +1,241 @@
+//! Broker connection status of the sensor subscriber, for the "MQTT offline" banner.
+//! - The subscriber reports every transition of its reconnect loop: `Connected` (CONNACK), `Subscribed`
+//!   per granted filter, `Disconnected` with the eventloop error, `Reconnecting` with the attempt number,
+//!   the backoff before the next try and the downtime so far (backoff.rs).
+//! - Each report goes to a channel that main.rs forwards as the `mqtt_status` event (`try_send`: a full
+//!   channel drops the event, never blocks the MQTT loop) and updates the shared snapshot behind
+//!   `get_mqtt_status`, so a UI that loads later still sees the current state.
+//! - Every event also carries the ingestion pause state (ingest_pause.rs); toggling the pause re-sends
+//!   the current status with the new state.
+//! - Every event also names the broker being used or tried (`broker`; `backup` for any but the first of
+//!   the failover list, failover.rs), e.g. for "connected via backup broker".
+
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+use super::ingest_pause::{IngestPauseShared, IngestPauseState};
+use crate::services::mqtt::auth::BrokerEndpoint;
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum MqttStatus {
+    /// First connect since startup not answered yet.
+    Connecting,
+    Connected,
+    Subscribed { topic: String },
+    Disconnected { reason: String },
+    /// `attempt` counts failed tries since the connection was last healthy (1 = first retry);
+    /// `downtime_ms` runs from the first of them.
+    Reconnecting { attempt: u32, next_retry_ms: u64, downtime_ms: u64 },
+}
+
+/// The broker the subscriber is connected to or trying.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActiveBroker {
+    pub host: String,
+    pub port: u16,
+    /// Position in the failover list (0 = primary).
+    pub index: usize,
+    pub backup: bool,
+}
+
+/// One reported transition; the payload of `mqtt_status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct MqttStatusEvent {
+    #[serde(flatten)]
+    pub status: MqttStatus,
+    /// `paused`, `paused_nodes`, `held_samples`.
+    #[serde(flatten)]
+    pub ingest: IngestPauseState,
+    /// None until the first connect attempt.
+    pub broker: Option<ActiveBroker>,
+    /// Time of the transition (a pause toggle re-sends it unchanged).
+    pub ts_ms: i64,
+}
+
+/// Current state for `get_mqtt_status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct MqttStatusSnapshot {
+    pub connected: bool,
+    /// Start of the current connection, or of the current outage (None before the first connect).
+    pub since_ms: Option<i64>,
+    /// Filters granted on the current connection.
+    pub subscribed: Vec<String>,
+    /// Outages since startup, a broker unreachable at startup included.
+    pub disconnects: u64,
+    pub last: MqttStatusEvent,
+}
+
+impl Default for MqttStatusSnapshot {
+    fn default() -> Self {
+        MqttStatusSnapshot {
+            connected: false,
+            since_ms: None,
+            subscribed: Vec::new(),
+            disconnects: 0,
+            last: MqttStatusEvent { status: MqttStatus::Connecting, ingest: IngestPauseState::default(), broker: None, ts_ms: now_ms() },
+        }
+    }
+}
+
+impl MqttStatusSnapshot {
+    pub fn apply(&mut self, ev: &MqttStatusEvent) {
+        match &ev.status {
+            MqttStatus::Connecting => {}
+            MqttStatus::Connected => {
+                self.connected = true;
+                self.since_ms = Some(ev.ts_ms);
+                self.subscribed.clear();
+            }
+            MqttStatus::Subscribed { topic } => {
+                if !self.subscribed.contains(topic) { self.subscribed.push(topic.clone()); }
+            }
+            MqttStatus::Disconnected { .. } => {
+                // a failed retry is not a new outage
+                if self.connected || self.since_ms.is_none() {
+                    self.since_ms = Some(ev.ts_ms);
+                    self.disconnects += 1;
+                }
+                self.connected = false;
+                self.subscribed.clear();
+            }
+            MqttStatus::Reconnecting { .. } => self.connected = false,
+        }
+        self.last = ev.clone();
+    }
+}
+
+pub type MqttStatusShared = Arc<RwLock<MqttStatusSnapshot>>;
+
+/// The subscriber's side: updates the snapshot and queues the event for the UI.
+#[derive(Clone)]
+pub struct StatusReporter {
+    tx: mpsc::Sender<MqttStatusEvent>,
+    latest: MqttStatusShared,
+    pause: IngestPauseShared,
+    broker: Arc<RwLock<Option<ActiveBroker>>>,
+}
+
+impl StatusReporter {
+    pub fn new(tx: mpsc::Sender<MqttStatusEvent>, latest: MqttStatusShared, pause: IngestPauseShared) -> Self {
+        StatusReporter { tx, latest, pause, broker: Arc::default() }
+    }
+
+    pub fn report(&self, status: MqttStatus) {
+        let broker = self.broker.read().ok().and_then(|b| b.clone());
+        let ev = MqttStatusEvent { status, ingest: self.pause.state(), broker, ts_ms: now_ms() };
+        if let Ok(mut s) = self.latest.write() { s.apply(&ev); }
+        let _ = self.tx.try_send(ev);
+    }
+
+    /// Broker of the following reports: `index` into the failover list.
+    pub fn set_broker(&self, index: usize, broker: &BrokerEndpoint) {
+        let active = ActiveBroker { host: broker.host.clone(), port: broker.port, index, backup: index > 0 };
+        if let Ok(mut b) = self.broker.write() { *b = Some(active); }
+    }
+
+    /// Re-send the current status with the current pause state.
+    pub fn report_pause(&self) {
+        let Ok(mut s) = self.latest.write() else { return };
+        s.last.ingest = self.pause.state();
+        let _ = self.tx.try_send(s.last.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn reporter(capacity: usize) -> (StatusReporter, mpsc::Receiver<MqttStatusEvent>, MqttStatusShared, IngestPauseShared) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let (latest, pause) = (MqttStatusShared::default(), IngestPauseShared::default());
+        (StatusReporter::new(tx, latest.clone(), pause.clone()), rx, latest, pause)
+    }
+
+    fn drain(rx: &mut mpsc::Receiver<MqttStatusEvent>) -> Vec<MqttStatus> {
+        std::iter::from_fn(|| rx.try_recv().ok()).map(|e| e.status).collect()
+    }
+
+    fn down(reason: &str) -> MqttStatus { MqttStatus::Disconnected { reason: reason.into() } }
+
+    fn retry(attempt: u32) -> MqttStatus {
+        MqttStatus::Reconnecting { attempt, next_retry_ms: 500 << attempt, downtime_ms: 1_000 * attempt as u64 }
+    }
+
+    #[test]
+    fn startup_outage_and_failed_retries_count_as_one_disconnect() {
+        let (status, mut rx, latest, _) = reporter(16);
+        assert_eq!(latest.read().unwrap().last.status, MqttStatus::Connecting);
+
+        for attempt in 1..=3 {
+            status.report(down("connection refused"));
+            status.report(retry(attempt));
+        }
+        let s = latest.read().unwrap().clone();
+        assert!(!s.connected);
+        assert_eq!(s.disconnects, 1);
+        assert_eq!(s.last.status, retry(3));
+        assert_eq!(drain(&mut rx).len(), 6, "every transition reaches the UI");
+    }
+
+    #[test]
+    fn reconnect_resets_subscriptions_and_a_new_outage_is_counted() {
+        let (status, mut rx, latest, _) = reporter(16);
+        status.report(down("connection refused"));
+        status.report(retry(1));
+        status.report(MqttStatus::Connected);
+        let since = latest.read().unwrap().since_ms;
+        for topic in ["greenhouse/+/node/+/data", "greenhouse/+/node/+/data", "greenhouse/+/outdoor/+/data"] {
+            status.report(MqttStatus::Subscribed { topic: topic.into() });
+        }
+        {
+            let s = latest.read().unwrap();
+            assert!(s.connected && s.since_ms.is_some());
+            assert_eq!(s.subscribed, ["greenhouse/+/node/+/data", "greenhouse/+/outdoor/+/data"], "duplicate SUBACK listed once");
+        }
+
+        status.report(down("connection reset by peer"));
+        let s = latest.read().unwrap().clone();
+        assert!(!s.connected && s.subscribed.is_empty());
+        assert_eq!(s.disconnects, 2);
+        assert!(s.since_ms >= since, "outage starts at the disconnect");
+        assert_eq!(drain(&mut rx).last(), Some(&down("connection reset by peer")));
+    }
+
+    #[test]
+    fn a_full_channel_drops_events_but_the_snapshot_keeps_up() {
+        let (status, mut rx, latest, _) = reporter(1);
+        status.report(down("timeout"));
+        status.report(retry(1));
+        status.report(MqttStatus::Connected);
+        assert_eq!(drain(&mut rx), [down("timeout")]);
+        assert!(latest.read().unwrap().connected);
+    }
+
+    #[test]
+    fn events_carry_broker_and_pause_state() {
+        let (status, mut rx, _, pause) = reporter(16);
+        status.set_broker(1, &BrokerEndpoint { host: "backup.local".into(), port: 1883 });
+        status.report(retry(2));
+        pause.pause(Some((1, 4)));
+        status.report_pause();
+
+        let evs: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(evs.len(), 2);
+        assert_eq!(evs[0].status, evs[1].status, "a pause toggle re-sends the current status");
+        let mut v = serde_json::to_value(&evs[1]).unwrap();
+        v.as_object_mut().unwrap().remove("ts_ms");
+        assert_eq!(v, json!({
+            "state": "reconnecting", "attempt": 2, "next_retry_ms": 2000, "downtime_ms": 2000,
+            "paused": false, "paused_nodes": [{ "greenhouse_id": 1, "node_id": 4 }], "held_samples": 0,
+            "broker": { "host": "backup.local", "port": 1883, "index": 1, "backup": true },
+        }));
+    }
+}
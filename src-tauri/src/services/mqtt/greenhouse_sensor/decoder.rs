@@ -8,11 +8,113 @@
 //!   u16 par_value, u16 weight,
 //!   f32 ea_air, f32 ea_leaf, f32 es, f32 vpd
 //!
-//! - Outdoor node (65001): 22 bytes
+//! - Outdoor stations (65001, 65002, ...; any node id): 22 bytes
 //!   u16 greenhouse_id, u16 node_id,
 //!   f32 air_temp, f32 air_rh, u16 par_value, f32 ea_air, f32 es
+//!
+//! - Standard nodes on firmware v1.4 (slab scales above 65535 g): 62 bytes, no CRC trailer:
+//!   the 60-byte layout with f32 weight (grams) instead of u16 weight. Same length as a 60-byte frame
+//!   with CRC and nothing in the frame tells them apart, so v1.4 is never guessed: only nodes the schema
+//!   registry declares `standard_v14` have their 62-byte frames read this way (`decode_as`). For every
+//!   other node a 62-byte frame is a 60-byte frame with CRC, and a trailer that does not match is
+//!   `CrcMismatch`.
+//!
+//! - Newer firmware appends a CRC trailer: 62 / 24 bytes = the layout above + u16 CRC-16/CCITT-FALSE
+//!   (poly 0x1021, init 0xFFFF, no reflection) over the body, little-endian. A mismatch rejects the
+//!   frame (`CrcMismatch`); 60 / 22 byte frames without trailer are still accepted unchanged.
+//!
+//! - Standard v2: 69 bytes (71 with CRC trailer)
+//!   u8 version = 0x02, then the 60-byte standard layout, then f32 battery_v, f32 rssi_dbm
+//! - Standard v3: 71 bytes (73 with CRC trailer)
+//!   u8 version = 0x03, then the v2 layout, then u16 seq (rolling per-node counter, see `seq`)
+//! - Standard v4: 75 bytes (77 with CRC trailer)
+//!   u8 version = 0x04, then the v3 layout, then u32 device_ts (node clock, epoch seconds; nodes
+//!   buffer readings through Wi-Fi dropouts and publish them late, see `device_ts`)
+//! - Standard v5 (compact telemetry): 64 bytes (66 with CRC trailer)
+//!   u8 version = 0x05, then the 60-byte standard layout, then u16 battery_mv, i8 rssi_dbm (decoded
+//!   as `battery_v` in volts and `rssi_dbm`, like outdoor telemetry frames). No seq / device_ts.
+//! - Outdoor stations with telemetry: 26 bytes (28 with CRC trailer)
+//!   u8 type = 0x11, then the 22-byte outdoor layout, then u16 battery_mv, i8 rssi_dbm
+//!   (decoded as `battery_v` in volts and `rssi_dbm`, like standard v2 frames). 25 bytes would be a soil frame.
+//! - Outdoor weather stations: 36 bytes (38 with CRC trailer)
+//!   u8 type = 0x12, then the 25 bytes after the type byte of the telemetry layout, then f32 wind_ms,
+//!   f32 wind_gust_ms (m/s), u16 rain_tips (rain gauge tips since the previous frame). Outdoor frames
+//!   without these fields decode them as NaN / `U16_MISSING`.
+//! - Soil / substrate nodes: 25 bytes (27 with CRC trailer)
+//!   u8 type = 0x10, u16 greenhouse_id, u16 node_id, f32 vwc1..vwc4 (volumetric water content, %),
+//!   f32 ec (substrate EC, mS/cm). Keyed on the type byte: 24 bytes would be an outdoor frame with CRC.
+//!   The legacy lengths above are always v1 (a v1 frame from greenhouse 2 or 3 also starts with its
+//!   version byte); any other length starting with 0x02..0x05 / 0x10..0x12 is that
+//!   layout, and shorter than its length is `Truncated`.
+//!
+//! - JSON fallback (test rigs), tried only when the payload is none of the binary layouts:
+//!   `{"gh": 1, "node": 3, "air_temp_c": 24.1, ...}` with the field names of `Decoded`
+//!   (`greenhouse_id` / `node_id` also accepted, `ts` for `device_ts`). `"kind": "outdoor"` / `"soil"` gives
+//!   an outdoor / soil frame, no kind a standard one (or the declared layout, `decode_as`). Missing or null readings become NaN (f32) or `U16_MISSING` (u16); missing
+//!   ids, a wrong type or an unknown kind reject the frame (`InvalidJson`).
+//!
+//! - `decode_as` is for nodes whose layout the schema registry declares (mqtt/schema.rs): same parsing,
+//!   but a frame that comes out as another layout is `LayoutMismatch`. Replay and the CLI have no
+//!   registry and keep the length heuristic.
+//!
+//! - Protobuf (`proto` cargo feature, proto.rs), tried last: a `SensorReading` message with the same
+//!   field names; missing readings as in JSON. Without the feature such frames are `UnknownLength`.
+//!
+//! - `encode_payload` writes the binary layout back (simulators, test frames): the lowest version that
+//!   holds the frame's optional fields, no CRC trailer (`with_crc` appends one).
+//!
+//! - Firmware reports a sensor that failed to read as `F32_MISSING` (-999.0) / `U16_MISSING` (0xFFFF).
+//!   `decode_payload` turns the f32 sentinel into NaN, per field, so a dead probe drops out of the means
+//!   while the node's other readings still count; u16 readings keep `U16_MISSING` (see `u16_reading`).
+//!
+//! - Store-and-forward batches (nodes that buffered readings through a Wi-Fi outage): u8 count, then
+//!   that many standard frames of one layout back to back (any standard length above, with or without
+//!   CRC). `decode_batch` splits them and decodes each with `decode_payload`; a count that does not match
+//!   the frames that follow is `BatchCount`, a lone 0 byte an empty batch. `decode_payload` itself only
+//!   takes single frames. Batched samples are minutes old, so only v4 frames (with `device_ts`) can be
+//!   placed in the windows they were measured in; v1..v3 frames in a batch are `UntimedBatch`, each
+//!   rejected and counted rather than averaged into the minute the batch arrives.
+//!
+//! - `decode_payload` says why a frame was rejected (`DecodeError`); a frame whose greenhouse or node
+//!   id is 0 (never assigned) is rejected as `InvalidValue`.
+
+/// Frame layout family; what the schema registry (schema.rs) declares per node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameLayout {
+    Standard,
+    /// Standard node on firmware v1.4: 62-byte frames are the f32-weight layout, not v1 + CRC.
+    StandardV14,
+    Outdoor,
+    Soil,
+}
 
-#[derive(Debug, Clone, Copy)]
+impl FrameLayout {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FrameLayout::Standard => "standard",
+            FrameLayout::StandardV14 => "standard_v14",
+            FrameLayout::Outdoor => "outdoor",
+            FrameLayout::Soil => "soil",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        [FrameLayout::Standard, FrameLayout::StandardV14, FrameLayout::Outdoor, FrameLayout::Soil]
+            .into_iter().find(|l| l.as_str() == s)
+    }
+
+    /// Layout of the frames this declaration yields (`Decoded::layout`): v1.4 nodes send standard frames.
+    pub fn family(self) -> Self {
+        match self {
+            FrameLayout::StandardV14 => FrameLayout::Standard,
+            l => l,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum Decoded {
     Standard {
         greenhouse_id: u16,
@@ -27,11 +129,20 @@ pub enum Decoded {
         bag_rh4_pct: f32,
         bag_rh_avg_pct: f32,
         par_value: u16,
-        weight_g: u16,
+        /// f32 on the wire from firmware v1.4 (62-byte frames); u16 before (`U16_MISSING` -> NaN).
+        weight_g: f32,
         ea_air_kpa: f32,
         ea_leaf_kpa: f32,
         es_kpa: f32,
         vpd_kpa: f32,
+        /// v2..v5 frames only (whole mV in v5).
+        battery_v: Option<f32>,
+        /// v2..v5 frames only (whole dBm in v5).
+        rssi_dbm: Option<f32>,
+        /// v3 frames only.
+        seq: Option<u16>,
+        /// v4 frames only.
+        device_ts: Option<u32>,
     },
     Outdoor {
         greenhouse_id: u16,
@@ -41,69 +152,1360 @@ pub enum Decoded {
         par_value: u16,
         ea_air_kpa: f32,
         es_kpa: f32,
+        /// Weather frames (0x12) only; NaN otherwise.
+        wind_ms: f32,
+        /// Weather frames (0x12) only; NaN otherwise.
+        wind_gust_ms: f32,
+        /// Weather frames (0x12) only; `U16_MISSING` otherwise.
+        rain_tips: u16,
+        /// Telemetry (0x11) and weather (0x12) frames only.
+        battery_v: Option<f32>,
+        /// Telemetry (0x11) and weather (0x12) frames only.
+        rssi_dbm: Option<f32>,
+        /// JSON frames only (no binary outdoor layout carries these yet).
+        seq: Option<u16>,
+        device_ts: Option<u32>,
     },
+    Soil {
+        greenhouse_id: u16,
+        node_id: u16,
+        vwc1_pct: f32,
+        vwc2_pct: f32,
+        vwc3_pct: f32,
+        vwc4_pct: f32,
+        ec_ms_cm: f32,
+    },
+}
+
+impl Decoded {
+    /// (greenhouse_id, node_id) of the frame.
+    pub fn ids(&self) -> (u16, u16) {
+        match *self {
+            Decoded::Standard { greenhouse_id, node_id, .. } => (greenhouse_id, node_id),
+            Decoded::Outdoor { greenhouse_id, node_id, .. } => (greenhouse_id, node_id),
+            Decoded::Soil { greenhouse_id, node_id, .. } => (greenhouse_id, node_id),
+        }
+    }
+
+    pub fn layout(&self) -> FrameLayout {
+        match self {
+            Decoded::Standard { .. } => FrameLayout::Standard,
+            Decoded::Outdoor { .. } => FrameLayout::Outdoor,
+            Decoded::Soil { .. } => FrameLayout::Soil,
+        }
+    }
+
+    /// (battery_v, rssi_dbm) node telemetry, when the frame carries it.
+    pub fn telemetry(&self) -> (Option<f32>, Option<f32>) {
+        match *self {
+            Decoded::Standard { battery_v, rssi_dbm, .. } | Decoded::Outdoor { battery_v, rssi_dbm, .. } => (battery_v, rssi_dbm),
+            Decoded::Soil { .. } => (None, None),
+        }
+    }
+
+    /// Rolling per-node sequence counter, when the frame carries one.
+    pub fn seq(&self) -> Option<u16> {
+        match *self {
+            Decoded::Standard { seq, .. } | Decoded::Outdoor { seq, .. } => seq,
+            Decoded::Soil { .. } => None,
+        }
+    }
+
+    /// Measurement time from the node clock (epoch seconds), when the frame carries one.
+    pub fn device_ts(&self) -> Option<u32> {
+        match *self {
+            Decoded::Standard { device_ts, .. } | Decoded::Outdoor { device_ts, .. } => device_ts,
+            Decoded::Soil { .. } => None,
+        }
+    }
+
+    /// Re-home the frame under another greenhouse id (origin mapping, see origin.rs).
+    pub fn set_greenhouse_id(&mut self, id: u16) {
+        match self {
+            Decoded::Standard { greenhouse_id, .. }
+            | Decoded::Outdoor { greenhouse_id, .. }
+            | Decoded::Soil { greenhouse_id, .. } => *greenhouse_id = id,
+        }
+    }
+
+    /// Mutable access to every u16 reading by sensor key (for validation stages).
+    pub fn u16_fields_mut(&mut self) -> Vec<(&'static str, &mut u16)> {
+        match self {
+            Decoded::Standard { par_value, .. } => vec![("par_value", par_value)],
+            Decoded::Outdoor { par_value, rain_tips, .. } => vec![("par_value", par_value), ("rain_tips", rain_tips)],
+            Decoded::Soil { .. } => vec![],
+        }
+    }
+
+    /// Mutable access to every f32 reading by sensor key (for validation stages).
+    pub fn f32_fields_mut(&mut self) -> Vec<(&'static str, &mut f32)> {
+        match self {
+            Decoded::Standard {
+                air_temp_c, leaf_temp_c, bag_temp_c, air_rh_pct,
+                bag_rh1_pct, bag_rh2_pct, bag_rh3_pct, bag_rh4_pct, bag_rh_avg_pct,
+                weight_g, ea_air_kpa, ea_leaf_kpa, es_kpa, vpd_kpa, ..
+            } => vec![
+                ("air_temp_c", air_temp_c), ("leaf_temp_c", leaf_temp_c),
+                ("bag_temp_c", bag_temp_c), ("air_rh_pct", air_rh_pct),
+                ("bag_rh1_pct", bag_rh1_pct), ("bag_rh2_pct", bag_rh2_pct),
+                ("bag_rh3_pct", bag_rh3_pct), ("bag_rh4_pct", bag_rh4_pct),
+                ("bag_rh_avg_pct", bag_rh_avg_pct), ("weight_g", weight_g),
+                ("ea_air_kpa", ea_air_kpa), ("ea_leaf_kpa", ea_leaf_kpa),
+                ("es_kpa", es_kpa), ("vpd_kpa", vpd_kpa),
+            ],
+            Decoded::Outdoor { air_temp_c, air_rh_pct, ea_air_kpa, es_kpa, wind_ms, wind_gust_ms, .. } => vec![
+                ("air_temp_c", air_temp_c), ("air_rh_pct", air_rh_pct),
+                ("ea_air_kpa", ea_air_kpa), ("es_kpa", es_kpa),
+                ("wind_ms", wind_ms), ("wind_gust_ms", wind_gust_ms),
+            ],
+            Decoded::Soil { vwc1_pct, vwc2_pct, vwc3_pct, vwc4_pct, ec_ms_cm, .. } => vec![
+                ("vwc1_pct", vwc1_pct), ("vwc2_pct", vwc2_pct),
+                ("vwc3_pct", vwc3_pct), ("vwc4_pct", vwc4_pct),
+                ("ec_ms_cm", ec_ms_cm),
+            ],
+        }
+    }
 }
 
 #[inline] fn rd_u16_le(b: &[u8], o: usize) -> Option<u16> {
     b.get(o..o+2).map(|s| u16::from_le_bytes([s[0], s[1]]))
 }
+#[inline] fn rd_u32_le(b: &[u8], o: usize) -> Option<u32> {
+    b.get(o..o+4).map(|s| u32::from_le_bytes([s[0], s[1], s[2], s[3]]))
+}
 #[inline] fn rd_f32_le(b: &[u8], o: usize) -> Option<f32> {
     let a = *b.get(o)?; let a1 = *b.get(o+1)?;
     let a2 = *b.get(o+2)?; let a3 = *b.get(o+3)?;
     Some(f32::from_le_bytes([a, a1, a2, a3]))
 }
 
-pub fn decode_payload(p: &[u8]) -> Option<Decoded> {
+/// u16 reading that was not measured (firmware sentinel, JSON frames without the key); see `u16_reading`.
+pub const U16_MISSING: u16 = u16::MAX;
+
+/// f32 reading the firmware could not take (sensor missing / failed); decoded as NaN.
+pub const F32_MISSING: f32 = -999.0;
+
+/// u16 reading as f32 for averaging; `U16_MISSING` is NaN so it never enters a mean.
+#[inline] pub fn u16_reading(v: u16) -> f32 {
+    if v == U16_MISSING { f32::NAN } else { v as f32 }
+}
+
+const V2: u8 = 0x02;
+const V3: u8 = 0x03;
+const V4: u8 = 0x04;
+const V5: u8 = 0x05;
+const SOIL: u8 = 0x10;
+const OUTDOOR_TELEMETRY: u8 = 0x11;
+const OUTDOOR_WEATHER: u8 = 0x12;
+
+/// Length (without CRC trailer) of the versioned / typed layout `p` claims by its first byte.
+fn versioned_len(p: &[u8]) -> Option<usize> {
+    match p.first() {
+        Some(&V2) => Some(69),
+        Some(&V3) => Some(71),
+        Some(&V4) => Some(75),
+        Some(&V5) => Some(64),
+        Some(&SOIL) => Some(25),
+        Some(&OUTDOOR_TELEMETRY) => Some(26),
+        Some(&OUTDOOR_WEATHER) => Some(36),
+        _ => None,
+    }
+}
+
+/// CRC-16/CCITT-FALSE ("123456789" -> 0x29B1).
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Body of a frame with CRC trailer, if the trailer matches.
+fn crc_body(p: &[u8]) -> Option<&[u8]> {
+    let (body, trailer) = p.split_at(p.len().checked_sub(2)?);
+    (crc16_ccitt(body) == rd_u16_le(trailer, 0)?).then_some(body)
+}
+
+/// Parse a captured frame pasted as hex ("0a 1b ...", "0x0a1b...", with or without separators).
+pub fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim();
+    let s = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    let digits: String = s.chars().filter(|c| !matches!(c, ' ' | ':' | '-' | ',' | '\n' | '\r' | '\t')).collect();
+    hex::decode(digits).ok()
+}
+
+/// Why a frame did not decode (reported to firmware engineers in node nacks).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    UnknownLength(usize),
+    /// Shorter than the layout its version / type byte announces.
+    Truncated { expected: usize, got: usize },
+    /// Decoded, but a value no node can send (id 0 is never assigned: blank or erased config).
+    InvalidValue { field: &'static str },
+    /// Right length for a frame with CRC trailer, but the CRC does not match (RF corruption).
+    CrcMismatch,
+    /// Looks like JSON (starts with `{`) but is not a valid frame object.
+    InvalidJson,
+    /// Store-and-forward batch whose count byte disagrees with the standard frames that follow.
+    BatchCount { announced: usize, frames: usize },
+    /// Decodes, but not as the layout the schema registry declares for the node.
+    LayoutMismatch { expected: FrameLayout },
+    /// From a greenhouse with a frame key, but the HMAC trailer is missing or wrong (frame_mac.rs).
+    BadMac,
+    /// A store-and-forward batch frame without `device_ts` (before v4): no time to window it by.
+    UntimedBatch,
+}
+
+impl DecodeError {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DecodeError::UnknownLength(_) => "UnknownLength",
+            DecodeError::Truncated { .. } => "Truncated",
+            DecodeError::InvalidValue { .. } => "InvalidValue",
+            DecodeError::CrcMismatch => "CrcMismatch",
+            DecodeError::InvalidJson => "InvalidJson",
+            DecodeError::BatchCount { .. } => "BatchCount",
+            DecodeError::LayoutMismatch { .. } => "LayoutMismatch",
+            DecodeError::BadMac => "BadMac",
+            DecodeError::UntimedBatch => "UntimedBatch",
+        }
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnknownLength(n) => write!(f, "unknown frame length ({n} bytes)"),
+            DecodeError::Truncated { expected, got } => write!(f, "truncated frame ({got} of {expected} bytes)"),
+            DecodeError::InvalidValue { field } => write!(f, "invalid {field}"),
+            DecodeError::CrcMismatch => write!(f, "CRC mismatch"),
+            DecodeError::InvalidJson => write!(f, "invalid JSON frame"),
+            DecodeError::BatchCount { announced, frames } => write!(f, "batch announces {announced} frames but holds {frames}"),
+            DecodeError::LayoutMismatch { expected } => write!(f, "frame is not the {} layout registered for the node", expected.as_str()),
+            DecodeError::BadMac => write!(f, "missing or wrong HMAC"),
+            DecodeError::UntimedBatch => write!(f, "batched frame without node clock (needs v4)"),
+        }
+    }
+}
+
+/// Decode one node frame, or say why it is not one.
+pub fn decode_payload(p: &[u8]) -> Result<Decoded, DecodeError> {
+    decode_single(p, FrameLayout::Standard)
+}
+
+/// `decode_payload` for a node of layout `declared` (62-byte frames as v1.4 for `StandardV14`; JSON frames
+/// without `kind` get it).
+fn decode_single(p: &[u8], declared: FrameLayout) -> Result<Decoded, DecodeError> {
+    let vlen = versioned_len(p);
+    let announced = vlen.unwrap_or(0);
+    let short = |n| DecodeError::Truncated { expected: n, got: p.len() };
+    let mut d = match p.len() {
+        62 if declared == FrameLayout::StandardV14 => read_standard(p, 0, true).ok_or(short(62)),
+        62 | 24 if crc_body(p).is_none() => Err(DecodeError::CrcMismatch),
+        n @ (60 | 22 | 62 | 24) => decode_frame(p).ok_or(short(n)),
+        n if vlen == Some(n.wrapping_sub(2)) && crc_body(p).is_none() => Err(DecodeError::CrcMismatch),
+        n if vlen == Some(n) || vlen == Some(n.wrapping_sub(2)) => decode_frame(p).ok_or(short(n)),
+        n if n < announced => Err(short(announced)),
+        _ if looks_like_json(p) => decode_json(p, declared).ok_or(DecodeError::InvalidJson),
+        #[cfg(feature = "proto")]
+        n => super::proto::decode_proto(p).ok_or(DecodeError::UnknownLength(n)),
+        #[cfg(not(feature = "proto"))]
+        n => Err(DecodeError::UnknownLength(n)),
+    }?;
+    for (_, v) in d.f32_fields_mut() {
+        if *v == F32_MISSING { *v = f32::NAN; }
+    }
+    match d.ids() {
+        (0, _) => Err(DecodeError::InvalidValue { field: "greenhouse_id" }),
+        (_, 0) => Err(DecodeError::InvalidValue { field: "node_id" }),
+        _ => Ok(d),
+    }
+}
+
+/// Lengths a standard frame can have inside a batch (v1, v1.4 / v1 + CRC, v2..v5 with and without CRC).
+const BATCH_FRAME_LENS: [usize; 9] = [60, 62, 64, 66, 69, 71, 73, 75, 77];
+
+/// Every frame of a payload: the samples of a store-and-forward batch in order, else the single frame.
+/// Empty for an empty batch.
+pub fn decode_batch(p: &[u8]) -> Vec<Result<Decoded, DecodeError>> {
+    split_batch(p, FrameLayout::Standard)
+}
+
+/// Frames of a node whose layout the schema registry declares: JSON frames without `kind` take that
+/// layout, and a frame that parses as another layout is `LayoutMismatch` rather than forwarded as one.
+pub fn decode_as(p: &[u8], layout: FrameLayout) -> Vec<Result<Decoded, DecodeError>> {
+    split_batch(p, layout).into_iter()
+        .map(|r| r.and_then(|d| if d.layout() == layout.family() { Ok(d) } else { Err(DecodeError::LayoutMismatch { expected: layout }) }))
+        .collect()
+}
+
+fn split_batch(p: &[u8], declared: FrameLayout) -> Vec<Result<Decoded, DecodeError>> {
+    let Some((&count, frames)) = p.split_first() else { return vec![decode_single(p, declared)] };
+    let count = count as usize;
+    if count == 0 && frames.is_empty() { return Vec::new(); }
+    // 1 + count * frame length is never a single-frame length, so this cannot shadow one
+    if let Some(&n) = BATCH_FRAME_LENS.iter().find(|&&n| count > 0 && frames.len() == count * n) {
+        return frames.chunks_exact(n)
+            .map(|f| decode_single(f, declared).and_then(|d| if d.device_ts().is_some() { Ok(d) } else { Err(DecodeError::UntimedBatch) }))
+            .collect();
+    }
+    match decode_single(p, declared) {
+        Err(DecodeError::UnknownLength(_)) => match BATCH_FRAME_LENS.iter().find(|&&n| !frames.is_empty() && frames.len() % n == 0) {
+            Some(&n) => vec![Err(DecodeError::BatchCount { announced: count, frames: frames.len() / n })],
+            None => vec![Err(DecodeError::UnknownLength(p.len()))],
+        },
+        r => vec![r],
+    }
+}
+
+fn looks_like_json(p: &[u8]) -> bool {
+    p.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{')
+}
+
+#[derive(serde::Deserialize)]
+struct JsonFrame {
+    #[serde(alias = "greenhouse_id")]
+    gh: u16,
+    #[serde(alias = "node_id")]
+    node: u16,
+    kind: Option<String>,
+    air_temp_c: Option<f32>,
+    leaf_temp_c: Option<f32>,
+    bag_temp_c: Option<f32>,
+    air_rh_pct: Option<f32>,
+    bag_rh1_pct: Option<f32>,
+    bag_rh2_pct: Option<f32>,
+    bag_rh3_pct: Option<f32>,
+    bag_rh4_pct: Option<f32>,
+    bag_rh_avg_pct: Option<f32>,
+    par_value: Option<u16>,
+    weight_g: Option<f32>,
+    ea_air_kpa: Option<f32>,
+    ea_leaf_kpa: Option<f32>,
+    es_kpa: Option<f32>,
+    vpd_kpa: Option<f32>,
+    battery_v: Option<f32>,
+    rssi_dbm: Option<f32>,
+    seq: Option<u16>,
+    #[serde(alias = "device_ts")]
+    ts: Option<u32>,
+    vwc1_pct: Option<f32>,
+    vwc2_pct: Option<f32>,
+    vwc3_pct: Option<f32>,
+    vwc4_pct: Option<f32>,
+    ec_ms_cm: Option<f32>,
+    wind_ms: Option<f32>,
+    wind_gust_ms: Option<f32>,
+    rain_tips: Option<u16>,
+}
+
+fn decode_json(p: &[u8], default: FrameLayout) -> Option<Decoded> {
+    let j: JsonFrame = serde_json::from_slice(p).ok()?;
+    let f = |v: Option<f32>| v.unwrap_or(f32::NAN);
+    let u = |v: Option<u16>| v.unwrap_or(U16_MISSING);
+    let layout = match j.kind.as_deref() {
+        None => default,
+        Some(k) => FrameLayout::parse(k)?,
+    };
+    match layout.family() {
+        FrameLayout::Standard | FrameLayout::StandardV14 => Some(Decoded::Standard {
+            greenhouse_id: j.gh, node_id: j.node,
+            air_temp_c: f(j.air_temp_c), leaf_temp_c: f(j.leaf_temp_c), bag_temp_c: f(j.bag_temp_c), air_rh_pct: f(j.air_rh_pct),
+            bag_rh1_pct: f(j.bag_rh1_pct), bag_rh2_pct: f(j.bag_rh2_pct), bag_rh3_pct: f(j.bag_rh3_pct), bag_rh4_pct: f(j.bag_rh4_pct),
+            bag_rh_avg_pct: f(j.bag_rh_avg_pct), par_value: u(j.par_value), weight_g: f(j.weight_g),
+            ea_air_kpa: f(j.ea_air_kpa), ea_leaf_kpa: f(j.ea_leaf_kpa), es_kpa: f(j.es_kpa), vpd_kpa: f(j.vpd_kpa),
+            battery_v: j.battery_v, rssi_dbm: j.rssi_dbm, seq: j.seq, device_ts: j.ts,
+        }),
+        FrameLayout::Outdoor => Some(Decoded::Outdoor {
+            greenhouse_id: j.gh, node_id: j.node,
+            air_temp_c: f(j.air_temp_c), air_rh_pct: f(j.air_rh_pct), par_value: u(j.par_value),
+            ea_air_kpa: f(j.ea_air_kpa), es_kpa: f(j.es_kpa),
+            wind_ms: f(j.wind_ms), wind_gust_ms: f(j.wind_gust_ms), rain_tips: u(j.rain_tips),
+            battery_v: j.battery_v, rssi_dbm: j.rssi_dbm, seq: j.seq, device_ts: j.ts,
+        }),
+        FrameLayout::Soil => Some(Decoded::Soil {
+            greenhouse_id: j.gh, node_id: j.node,
+            vwc1_pct: f(j.vwc1_pct), vwc2_pct: f(j.vwc2_pct), vwc3_pct: f(j.vwc3_pct), vwc4_pct: f(j.vwc4_pct),
+            ec_ms_cm: f(j.ec_ms_cm),
+        }),
+    }
+}
+
+/// Standard layout starting at byte `o` (v1: 0, v2: after the version byte); `wide_weight`: f32 weight (v1.4).
+fn read_standard(p: &[u8], mut o: usize, wide_weight: bool) -> Option<Decoded> {
+    let greenhouse_id = rd_u16_le(p, o)?; o += 2;
+    let node_id       = rd_u16_le(p, o)?; o += 2;
+
+    let air_temp_c    = rd_f32_le(p, o)?; o += 4;
+    let leaf_temp_c   = rd_f32_le(p, o)?; o += 4;
+    let bag_temp_c    = rd_f32_le(p, o)?; o += 4;
+    let air_rh_pct    = rd_f32_le(p, o)?; o += 4;
+
+    let bag_rh1_pct   = rd_f32_le(p, o)?; o += 4;
+    let bag_rh2_pct   = rd_f32_le(p, o)?; o += 4;
+    let bag_rh3_pct   = rd_f32_le(p, o)?; o += 4;
+    let bag_rh4_pct   = rd_f32_le(p, o)?; o += 4;
+    let bag_rh_avg_pct= rd_f32_le(p, o)?; o += 4;
+
+    let par_value     = rd_u16_le(p, o)?; o += 2;
+    let weight_g = if wide_weight {
+        let w = rd_f32_le(p, o)?; o += 4; w
+    } else {
+        let w = u16_reading(rd_u16_le(p, o)?); o += 2; w
+    };
+
+    let ea_air_kpa    = rd_f32_le(p, o)?; o += 4;
+    let ea_leaf_kpa   = rd_f32_le(p, o)?; o += 4;
+    let es_kpa        = rd_f32_le(p, o)?; o += 4;
+    let vpd_kpa       = rd_f32_le(p, o)?; /*o += 4;*/
+
+    Some(Decoded::Standard {
+        greenhouse_id, node_id,
+        air_temp_c, leaf_temp_c, bag_temp_c, air_rh_pct,
+        bag_rh1_pct, bag_rh2_pct, bag_rh3_pct, bag_rh4_pct, bag_rh_avg_pct,
+        par_value, weight_g, ea_air_kpa, ea_leaf_kpa, es_kpa, vpd_kpa,
+        battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+    })
+}
+
+/// Outdoor layout starting at byte `o` (plain: 0, telemetry / weather: after the type byte).
+fn read_outdoor(p: &[u8], mut o: usize) -> Option<Decoded> {
+    let greenhouse_id = rd_u16_le(p, o)?; o += 2;
+    let node_id       = rd_u16_le(p, o)?; o += 2;
+
+    let air_temp_c    = rd_f32_le(p, o)?; o += 4;
+    let air_rh_pct    = rd_f32_le(p, o)?; o += 4;
+    let par_value     = rd_u16_le(p, o)?; o += 2;
+    let ea_air_kpa    = rd_f32_le(p, o)?; o += 4;
+    let es_kpa        = rd_f32_le(p, o)?; /*o += 4;*/
+
+    Some(Decoded::Outdoor {
+        greenhouse_id, node_id,
+        air_temp_c, air_rh_pct, par_value, ea_air_kpa, es_kpa,
+        wind_ms: f32::NAN, wind_gust_ms: f32::NAN, rain_tips: U16_MISSING,
+        battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+    })
+}
+
+/// Layout parse by length / version byte; `decode_payload` adds the failure reason and value checks.
+fn decode_frame(p: &[u8]) -> Option<Decoded> {
+    let vlen = versioned_len(p);
     match p.len() {
-        60 => {
-            // Standard
-            let mut o = 0usize;
+        62 | 24 => decode_frame(crc_body(p)?),
+        n if vlen == Some(n.wrapping_sub(2)) => decode_frame(crc_body(p)?),
+        60 => read_standard(p, 0, false),
+        25 if p[0] == SOIL => {
+            // Soil
+            let mut o = 1usize;
             let greenhouse_id = rd_u16_le(p, o)?; o += 2;
             let node_id       = rd_u16_le(p, o)?; o += 2;
 
-            let air_temp_c    = rd_f32_le(p, o)?; o += 4;
-            let leaf_temp_c   = rd_f32_le(p, o)?; o += 4;
-            let bag_temp_c    = rd_f32_le(p, o)?; o += 4;
-            let air_rh_pct    = rd_f32_le(p, o)?; o += 4;
+            let vwc1_pct      = rd_f32_le(p, o)?; o += 4;
+            let vwc2_pct      = rd_f32_le(p, o)?; o += 4;
+            let vwc3_pct      = rd_f32_le(p, o)?; o += 4;
+            let vwc4_pct      = rd_f32_le(p, o)?; o += 4;
+            let ec_ms_cm      = rd_f32_le(p, o)?; /*o += 4;*/
 
-            let bag_rh1_pct   = rd_f32_le(p, o)?; o += 4;
-            let bag_rh2_pct   = rd_f32_le(p, o)?; o += 4;
-            let bag_rh3_pct   = rd_f32_le(p, o)?; o += 4;
-            let bag_rh4_pct   = rd_f32_le(p, o)?; o += 4;
-            let bag_rh_avg_pct= rd_f32_le(p, o)?; o += 4;
+            Some(Decoded::Soil { greenhouse_id, node_id, vwc1_pct, vwc2_pct, vwc3_pct, vwc4_pct, ec_ms_cm })
+        }
+        22 => read_outdoor(p, 0),
+        n if vlen == Some(n) && matches!(p[0], OUTDOOR_TELEMETRY | OUTDOOR_WEATHER) => {
+            // Outdoor with telemetry (0x11), plus wind / rain (0x12)
+            let mut d = read_outdoor(p, 1)?;
+            if let Decoded::Outdoor { battery_v, rssi_dbm, wind_ms, wind_gust_ms, rain_tips, .. } = &mut d {
+                *battery_v = Some(rd_u16_le(p, 23)? as f32 / 1000.0);
+                *rssi_dbm  = Some(*p.get(25)? as i8 as f32);
+                if p[0] == OUTDOOR_WEATHER {
+                    *wind_ms      = rd_f32_le(p, 26)?;
+                    *wind_gust_ms = rd_f32_le(p, 30)?;
+                    *rain_tips    = rd_u16_le(p, 34)?;
+                }
+            }
+            Some(d)
+        }
+        n if vlen == Some(n) && p[0] == V5 => {
+            // Standard v5: compact telemetry
+            let mut d = read_standard(p, 1, false)?;
+            if let Decoded::Standard { battery_v, rssi_dbm, .. } = &mut d {
+                *battery_v = Some(rd_u16_le(p, 61)? as f32 / 1000.0);
+                *rssi_dbm  = Some(*p.get(63)? as i8 as f32);
+            }
+            Some(d)
+        }
+        n if vlen == Some(n) && matches!(p[0], V2 | V3 | V4) => {
+            // Standard v2..v4
+            let mut d = read_standard(p, 1, false)?;
+            if let Decoded::Standard { battery_v, rssi_dbm, seq, device_ts, .. } = &mut d {
+                *battery_v = Some(rd_f32_le(p, 61)?);
+                *rssi_dbm  = Some(rd_f32_le(p, 65)?);
+                if matches!(p[0], V3 | V4) { *seq = Some(rd_u16_le(p, 69)?); }
+                if p[0] == V4 { *device_ts = Some(rd_u32_le(p, 71)?); }
+            }
+            Some(d)
+        }
+        _ if looks_like_json(p) => decode_json(p, FrameLayout::Standard),
+        _ => None,
+    }
+}
 
-            let par_value     = rd_u16_le(p, o)?; o += 2;
-            let weight_g      = rd_u16_le(p, o)?; o += 2;
+#[inline] fn put_u16(out: &mut Vec<u8>, v: u16) { out.extend_from_slice(&v.to_le_bytes()); }
 
-            let ea_air_kpa    = rd_f32_le(p, o)?; o += 4;
-            let ea_leaf_kpa   = rd_f32_le(p, o)?; o += 4;
-            let es_kpa        = rd_f32_le(p, o)?; o += 4;
-            let vpd_kpa       = rd_f32_le(p, o)?; /*o += 4;*/
+/// `volts` as the u16 millivolts of a v5 frame, if that decodes to exactly `volts` (-0.0 does not).
+fn whole_mv(volts: f32) -> Option<u16> {
+    let mv = (volts * 1000.0).round();
+    ((0.0..=65535.0).contains(&mv) && (mv as u16 as f32 / 1000.0).to_bits() == volts.to_bits()).then_some(mv as u16)
+}
 
-            Some(Decoded::Standard {
-                greenhouse_id, node_id,
-                air_temp_c, leaf_temp_c, bag_temp_c, air_rh_pct,
-                bag_rh1_pct, bag_rh2_pct, bag_rh3_pct, bag_rh4_pct, bag_rh_avg_pct,
-                par_value, weight_g, ea_air_kpa, ea_leaf_kpa, es_kpa, vpd_kpa
-            })
+/// `dbm` as the i8 of a v5 frame, if that decodes to exactly `dbm`: whole, in range, not -0.0.
+fn whole_dbm(dbm: f32) -> Option<i8> {
+    ((-128.0..=127.0).contains(&dbm) && (dbm as i8 as f32).to_bits() == dbm.to_bits()).then_some(dbm as i8)
+}
+#[inline] fn put_f32(out: &mut Vec<u8>, v: f32) { out.extend_from_slice(&v.to_le_bytes()); }
+
+/// Binary frame for `d`, as a node would publish it (without CRC trailer).
+/// Standard frames use v1 without optional fields, else the lowest version that holds them
+/// (v2 battery / RSSI, v3 + seq, v4 + device_ts); fields of that version the frame lacks are
+/// written as NaN / 0. Battery and RSSI alone in whole mV / dBm (what v5 firmware sends) use v5. A weight that is no whole gram in 0..65535 selects the v1.4 layout (f32 weight,
+/// 62 bytes, not to be combined with `with_crc`; decodes only as `StandardV14`) when there are no optional fields; versioned frames
+/// carry it rounded and clamped to u16. Outdoor frames use the weather layout when they carry wind / rain,
+/// else the telemetry layout when they carry battery / RSSI (volts to whole mV, dBm rounded and clamped to i8;
+/// 0 when a weather frame has none); there is no binary slot for their seq / device_ts.
+pub fn encode_payload(d: &Decoded) -> Vec<u8> {
+    let mut out = Vec::with_capacity(77);
+    match *d {
+        Decoded::Standard {
+            greenhouse_id, node_id, air_temp_c, leaf_temp_c, bag_temp_c, air_rh_pct,
+            bag_rh1_pct, bag_rh2_pct, bag_rh3_pct, bag_rh4_pct, bag_rh_avg_pct,
+            par_value, weight_g, ea_air_kpa, ea_leaf_kpa, es_kpa, vpd_kpa,
+            battery_v, rssi_dbm, seq, device_ts,
+        } => {
+            let compact = battery_v.is_some_and(|v| whole_mv(v).is_some()) && rssi_dbm.is_some_and(|r| whole_dbm(r).is_some());
+            let version = if device_ts.is_some() { V4 }
+                else if seq.is_some() { V3 }
+                else if compact { V5 }
+                else if battery_v.is_some() || rssi_dbm.is_some() { V2 }
+                else { 1 };
+            if version != 1 { out.push(version); }
+            put_u16(&mut out, greenhouse_id);
+            put_u16(&mut out, node_id);
+            for v in [air_temp_c, leaf_temp_c, bag_temp_c, air_rh_pct,
+                      bag_rh1_pct, bag_rh2_pct, bag_rh3_pct, bag_rh4_pct, bag_rh_avg_pct] {
+                put_f32(&mut out, v);
+            }
+            put_u16(&mut out, par_value);
+            let whole_u16 = weight_g.is_nan() || (weight_g.fract() == 0.0 && (0.0..65535.0).contains(&weight_g));
+            if version == 1 && !whole_u16 {
+                put_f32(&mut out, weight_g);
+            } else {
+                put_u16(&mut out, if weight_g.is_nan() { U16_MISSING } else { weight_g.round().clamp(0.0, 65534.0) as u16 });
+            }
+            for v in [ea_air_kpa, ea_leaf_kpa, es_kpa, vpd_kpa] { put_f32(&mut out, v); }
+            if version == V5 {
+                put_u16(&mut out, battery_v.and_then(whole_mv).unwrap_or(0));
+                out.push(rssi_dbm.and_then(whole_dbm).unwrap_or(0) as u8);
+            } else if version >= V2 {
+                put_f32(&mut out, battery_v.unwrap_or(f32::NAN));
+                put_f32(&mut out, rssi_dbm.unwrap_or(f32::NAN));
+            }
+            if matches!(version, V3 | V4) { put_u16(&mut out, seq.unwrap_or(0)); }
+            if version == V4 { out.extend_from_slice(&device_ts.unwrap_or(0).to_le_bytes()); }
         }
-        22 => {
-            // Outdoor
-            let mut o = 0usize;
-            let greenhouse_id = rd_u16_le(p, o)?; o += 2;
-            let node_id       = rd_u16_le(p, o)?; o += 2;
+        Decoded::Outdoor {
+            greenhouse_id, node_id, air_temp_c, air_rh_pct, par_value, ea_air_kpa, es_kpa,
+            wind_ms, wind_gust_ms, rain_tips, battery_v, rssi_dbm, ..
+        } => {
+            let weather = !wind_ms.is_nan() || !wind_gust_ms.is_nan() || rain_tips != U16_MISSING;
+            let telemetry = weather || battery_v.is_some() || rssi_dbm.is_some();
+            if weather { out.push(OUTDOOR_WEATHER); } else if telemetry { out.push(OUTDOOR_TELEMETRY); }
+            put_u16(&mut out, greenhouse_id);
+            put_u16(&mut out, node_id);
+            put_f32(&mut out, air_temp_c);
+            put_f32(&mut out, air_rh_pct);
+            put_u16(&mut out, par_value);
+            put_f32(&mut out, ea_air_kpa);
+            put_f32(&mut out, es_kpa);
+            if telemetry {
+                put_u16(&mut out, battery_v.map_or(0, |v| (v * 1000.0).round() as u16));
+                out.push(rssi_dbm.map_or(0, |r| r.round() as i8) as u8);
+            }
+            if weather {
+                put_f32(&mut out, wind_ms);
+                put_f32(&mut out, wind_gust_ms);
+                put_u16(&mut out, rain_tips);
+            }
+        }
+        Decoded::Soil { greenhouse_id, node_id, vwc1_pct, vwc2_pct, vwc3_pct, vwc4_pct, ec_ms_cm } => {
+            out.push(SOIL);
+            put_u16(&mut out, greenhouse_id);
+            put_u16(&mut out, node_id);
+            for v in [vwc1_pct, vwc2_pct, vwc3_pct, vwc4_pct, ec_ms_cm] { put_f32(&mut out, v); }
+        }
+    }
+    out
+}
+
+/// `frame` with its CRC-16 trailer appended (newer firmware).
+pub fn with_crc(mut frame: Vec<u8>) -> Vec<u8> {
+    let crc = crc16_ccitt(&frame);
+    put_u16(&mut frame, crc);
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standard(weight_g: f32) -> Decoded {
+        Decoded::Standard {
+            greenhouse_id: 1, node_id: 3,
+            air_temp_c: 24.5, leaf_temp_c: 23.0, bag_temp_c: 21.5, air_rh_pct: 68.0,
+            bag_rh1_pct: 80.0, bag_rh2_pct: 81.0, bag_rh3_pct: 82.0, bag_rh4_pct: 83.0, bag_rh_avg_pct: 81.5,
+            par_value: 420, weight_g,
+            ea_air_kpa: 2.1, ea_leaf_kpa: 2.4, es_kpa: 3.1, vpd_kpa: 1.0,
+            battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        }
+    }
+
+    fn weight(d: &Decoded) -> f32 {
+        match *d {
+            Decoded::Standard { weight_g, .. } => weight_g,
+            _ => panic!("not a standard frame: {d:?}"),
+        }
+    }
+
+    fn single(v: Vec<Result<Decoded, DecodeError>>) -> Result<Decoded, DecodeError> {
+        assert_eq!(v.len(), 1, "{v:?}");
+        v[0]
+    }
+
+    #[test]
+    fn v14_frame_decodes_only_for_declared_nodes() {
+        let frame = encode_payload(&standard(70_000.25));
+        assert_eq!(frame.len(), 62);
+        assert_ne!(crc16_ccitt(&frame[..60]), rd_u16_le(&frame, 60).unwrap());
+        assert_eq!(decode_payload(&frame).unwrap_err(), DecodeError::CrcMismatch);
+        let d = single(decode_as(&frame, FrameLayout::StandardV14)).unwrap();
+        assert_eq!(d.layout(), FrameLayout::Standard);
+        assert_eq!(weight(&d), 70_000.25);
+    }
+
+    #[test]
+    fn v14_node_still_sends_older_layouts() {
+        let v1 = encode_payload(&standard(1200.0));
+        assert_eq!(weight(&single(decode_as(&v1, FrameLayout::StandardV14)).unwrap()), 1200.0);
+        let mut v2 = standard(1200.0);
+        if let Decoded::Standard { battery_v, .. } = &mut v2 { *battery_v = Some(3.7); }
+        assert_eq!(weight(&single(decode_as(&encode_payload(&v2), FrameLayout::StandardV14)).unwrap()), 1200.0);
+        let json = br#"{"gh": 1, "node": 3, "weight_g": 70000.5}"#;
+        assert_eq!(weight(&single(decode_as(json, FrameLayout::StandardV14)).unwrap()), 70_000.5);
+    }
+
+    #[test]
+    fn every_bit_flip_in_a_62_byte_crc_frame_is_crc_mismatch() {
+        let frame = with_crc(encode_payload(&standard(1200.0)));
+        assert_eq!(frame.len(), 62);
+        assert_eq!(weight(&decode_payload(&frame).unwrap()), 1200.0);
+        for bit in 0..frame.len() * 8 {
+            let mut f = frame.clone();
+            f[bit / 8] ^= 1 << (bit % 8);
+            assert_eq!(decode_payload(&f).unwrap_err(), DecodeError::CrcMismatch, "bit {bit}");
+        }
+    }
+
+    #[test]
+    fn v14_declaration_is_a_standard_layout() {
+        assert_eq!(FrameLayout::parse("standard_v14"), Some(FrameLayout::StandardV14));
+        let outdoor = encode_payload(&Decoded::Outdoor {
+            greenhouse_id: 1, node_id: 65001, air_temp_c: 18.0, air_rh_pct: 55.0, par_value: 900,
+            ea_air_kpa: 1.1, es_kpa: 2.0, wind_ms: f32::NAN, wind_gust_ms: f32::NAN, rain_tips: U16_MISSING,
+            battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        });
+        assert_eq!(single(decode_as(&outdoor, FrameLayout::StandardV14)).unwrap_err(),
+                   DecodeError::LayoutMismatch { expected: FrameLayout::StandardV14 });
+    }
+
+    /// Same frame, NaN included (`Decoded` has no `PartialEq`: NaN readings never compare equal).
+    fn same(a: &Decoded, b: &Decoded) -> bool {
+        format!("{a:?}") == format!("{b:?}")
+    }
+
+    fn outdoor() -> Decoded {
+        Decoded::Outdoor {
+            greenhouse_id: 1, node_id: 65001, air_temp_c: 18.0, air_rh_pct: 55.0, par_value: 900,
+            ea_air_kpa: 1.1, es_kpa: 2.0, wind_ms: f32::NAN, wind_gust_ms: f32::NAN, rain_tips: U16_MISSING,
+            battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        }
+    }
+
+    #[test]
+    fn crc16_known_vectors() {
+        assert_eq!(crc16_ccitt(b"123456789"), 0x29B1);
+        assert_eq!(crc16_ccitt(b""), 0xFFFF);
+        assert_eq!(crc16_ccitt(&[0x00]), 0xE1F0);
+        assert_eq!(crc16_ccitt(b"A"), 0xB915);
+        // the trailer is little-endian, like every other field
+        assert_eq!(with_crc(b"123456789".to_vec())[9..], [0xB1, 0x29]);
+    }
+
+    #[test]
+    fn crc_frames_decode_like_their_legacy_bodies() {
+        for d in [standard(1200.0), outdoor()] {
+            let plain = encode_payload(&d);
+            let framed = with_crc(plain.clone());
+            assert_eq!(framed.len(), plain.len() + 2);
+            assert!(same(&decode_payload(&plain).unwrap(), &d));
+            assert!(same(&decode_payload(&framed).unwrap(), &d));
+        }
+        assert_eq!(encode_payload(&standard(1200.0)).len(), 60);
+        assert_eq!(encode_payload(&outdoor()).len(), 22);
+    }
+
+    #[test]
+    fn corrupted_crc_frames_are_crc_mismatch() {
+        let outdoor = with_crc(encode_payload(&outdoor()));
+        assert_eq!(outdoor.len(), 24);
+        for bit in 0..outdoor.len() * 8 {
+            let mut f = outdoor.clone();
+            f[bit / 8] ^= 1 << (bit % 8);
+            assert_eq!(decode_payload(&f).unwrap_err(), DecodeError::CrcMismatch, "bit {bit}");
+        }
+        // a corrupted trailer is as bad as a corrupted body
+        let mut standard = with_crc(encode_payload(&standard(1200.0)));
+        standard[61] = standard[61].wrapping_add(1);
+        assert_eq!(decode_payload(&standard).unwrap_err(), DecodeError::CrcMismatch);
+        // versioned frames carry the trailer too
+        let mut v2 = self::standard(1200.0);
+        if let Decoded::Standard { battery_v, rssi_dbm, .. } = &mut v2 { *battery_v = Some(3.7); *rssi_dbm = Some(-70.5); }
+        let mut f = with_crc(encode_payload(&v2));
+        assert_eq!(f.len(), 71);
+        assert!(same(&decode_payload(&f).unwrap(), &v2));
+        f[10] ^= 0x01;
+        assert_eq!(decode_payload(&f).unwrap_err(), DecodeError::CrcMismatch);
+        let mut f = with_crc(encode_payload(&with_telemetry(self::standard(1200.0), 3.7, -70.0)));
+        assert_eq!(f.len(), 66);
+        f[62] ^= 0x01;
+        assert_eq!(decode_payload(&f).unwrap_err(), DecodeError::CrcMismatch);
+    }
+
+    #[test]
+    fn legacy_frames_without_trailer_are_not_crc_checked() {
+        // any 60 / 22 bytes parse; only the value checks apply
+        let mut f = encode_payload(&standard(1200.0));
+        f[4..8].copy_from_slice(&99.5f32.to_le_bytes());
+        match decode_payload(&f).unwrap() {
+            Decoded::Standard { air_temp_c, .. } => assert_eq!(air_temp_c, 99.5),
+            d => panic!("{d:?}"),
+        }
+    }
+
+    #[test]
+    fn undeclared_62_byte_v14_frame_is_not_read_as_v1_with_crc() {
+        // the collision: a v1.4 frame and a v1 + CRC frame are both 62 bytes. Without a registry
+        // declaration the last two bytes are read as a CRC, and for these weights they are not a valid one.
+        for w in [70_000.25f32, 65_535.5, 0.5, 1234.75] {
+            let frame = encode_payload(&standard(w));
+            assert_eq!(frame.len(), 62);
+            assert_eq!(decode_payload(&frame).unwrap_err(), DecodeError::CrcMismatch, "weight {w}");
+            assert_eq!(single(decode_batch(&frame)).unwrap_err(), DecodeError::CrcMismatch);
+        }
+    }
+
+    fn with_telemetry(mut d: Decoded, batt: f32, rssi: f32) -> Decoded {
+        match &mut d {
+            Decoded::Standard { battery_v, rssi_dbm, .. } | Decoded::Outdoor { battery_v, rssi_dbm, .. } => {
+                *battery_v = Some(batt);
+                *rssi_dbm = Some(rssi);
+            }
+            Decoded::Soil { .. } => panic!("soil frames carry no telemetry"),
+        }
+        d
+    }
+
+    #[test]
+    fn v2_frame_layout() {
+        // telemetry finer than whole mV / dBm needs the f32 slots
+        let d = with_telemetry(standard(1200.0), 3.7001, -71.5);
+        let frame = encode_payload(&d);
+        assert_eq!(frame.len(), 69);
+        assert_eq!(frame[0], 0x02);
+        assert_eq!(rd_u16_le(&frame, 1), Some(1));
+        assert_eq!(rd_u16_le(&frame, 3), Some(3));
+        assert_eq!(rd_f32_le(&frame, 61), Some(3.7001));
+        assert_eq!(rd_f32_le(&frame, 65), Some(-71.5));
+        assert!(same(&decode_payload(&frame).unwrap(), &d));
+    }
+
+    #[test]
+    fn v5_frame_layout() {
+        let d = with_telemetry(standard(1200.0), 3.712, -71.0);
+        let frame = encode_payload(&d);
+        assert_eq!((frame.len(), frame[0]), (64, 0x05));
+        assert_eq!((rd_u16_le(&frame, 1), rd_u16_le(&frame, 3)), (Some(1), Some(3)));
+        assert_eq!(rd_u16_le(&frame, 43), Some(1200));
+        assert_eq!(rd_u16_le(&frame, 61), Some(3712));
+        assert_eq!(frame[63] as i8, -71);
+        let got = decode_payload(&frame).unwrap();
+        assert!(same(&got, &d));
+        assert_eq!(got.telemetry(), (Some(3.712), Some(-71.0)));
+        assert_eq!((got.seq(), got.device_ts()), (None, None));
+        assert!(same(&decode_payload(&with_crc(frame.clone())).unwrap(), &d));
+        for n in [5, 40, 59, 61, 63] {
+            assert_eq!(decode_payload(&frame[..n]).unwrap_err(), DecodeError::Truncated { expected: 64, got: n }, "{n} bytes");
+        }
+    }
+
+    #[test]
+    fn v5_telemetry_is_whole_millivolts_and_dbm() {
+        // the extremes of the u16 / i8 slots still fit
+        for (batt, rssi) in [(0.0, -128.0), (65.535, 127.0), (3.3, 0.0)] {
+            let frame = encode_payload(&with_telemetry(standard(1200.0), batt, rssi));
+            assert_eq!(frame.len(), 64, "{batt} V {rssi} dBm");
+            assert_eq!(decode_payload(&frame).unwrap().telemetry(), (Some(batt), Some(rssi)));
+        }
+        // out of range, fractional, -0.0 or only one of the two: the f32 layout
+        for (batt, rssi) in [(65.536, -60.0), (3.3, -128.5), (3.3, 128.0), (-0.001, -60.0), (f32::NAN, -60.0), (-0.0, -60.0), (3.3, -0.0)] {
+            assert_eq!(encode_payload(&with_telemetry(standard(1200.0), batt, rssi)).len(), 69, "{batt} V {rssi} dBm");
+        }
+        let mut d = standard(1200.0);
+        if let Decoded::Standard { battery_v, .. } = &mut d { *battery_v = Some(3.3); }
+        assert_eq!(encode_payload(&d).len(), 69);
+    }
+
+    #[test]
+    fn v1_and_v2_nodes_share_a_topic() {
+        let v1 = standard(1200.0);
+        let v2 = with_telemetry(standard(800.0), 3.3, -80.0);
+        for (frame, d) in [(encode_payload(&v1), &v1), (encode_payload(&v2), &v2), (encode_payload(&v1), &v1)] {
+            let got = decode_payload(&frame).unwrap();
+            assert!(same(&got, d), "{got:?}");
+        }
+        assert_eq!(decode_payload(&encode_payload(&v1)).unwrap().telemetry(), (None, None));
+        assert_eq!(decode_payload(&encode_payload(&v2)).unwrap().telemetry(), (Some(3.3), Some(-80.0)));
+    }
 
-            let air_temp_c    = rd_f32_le(p, o)?; o += 4;
-            let air_rh_pct    = rd_f32_le(p, o)?; o += 4;
-            let par_value     = rd_u16_le(p, o)?; o += 2;
-            let ea_air_kpa    = rd_f32_le(p, o)?; o += 4;
-            let es_kpa        = rd_f32_le(p, o)?; /*o += 4;*/
+    #[test]
+    fn v1_frame_whose_first_byte_is_2_stays_v1() {
+        // greenhouse 2 puts 0x02 in the first byte of a v1 frame; the length decides
+        let mut d = standard(1200.0);
+        d.set_greenhouse_id(2);
+        let frame = encode_payload(&d);
+        assert_eq!((frame.len(), frame[0]), (60, 0x02));
+        assert!(same(&decode_payload(&frame).unwrap(), &d));
+    }
 
-            Some(Decoded::Outdoor {
-                greenhouse_id, node_id,
-                air_temp_c, air_rh_pct, par_value, ea_air_kpa, es_kpa
+    #[test]
+    fn v2_frame_too_short_is_truncated() {
+        let frame = encode_payload(&with_telemetry(standard(1200.0), 3.7, -71.5));
+        for n in [5, 40, 59, 61, 68] {
+            assert_eq!(decode_payload(&frame[..n]).unwrap_err(), DecodeError::Truncated { expected: 69, got: n }, "{n} bytes");
+        }
+    }
+
+    #[test]
+    fn partial_json_leaves_missing_fields_nan() {
+        let d = decode_payload(br#"{"gh": 1, "node": 3, "air_temp_c": 24.1, "air_rh_pct": 70, "par_value": 410}"#).unwrap();
+        match d {
+            Decoded::Standard { greenhouse_id, node_id, air_temp_c, air_rh_pct, leaf_temp_c, weight_g, par_value, battery_v, seq, device_ts, .. } => {
+                assert_eq!((greenhouse_id, node_id, air_temp_c, air_rh_pct), (1, 3, 24.1, 70.0));
+                assert!(leaf_temp_c.is_nan() && weight_g.is_nan());
+                assert_eq!(par_value, 410);
+                assert_eq!((battery_v, seq, device_ts), (None, None, None));
+            }
+            d => panic!("{d:?}"),
+        }
+        // long key names and the outdoor kind
+        let d = decode_payload(br#"{"greenhouse_id": 2, "node_id": 65001, "kind": "outdoor", "wind_ms": 3.5, "rssi_dbm": -60}"#).unwrap();
+        match d {
+            Decoded::Outdoor { greenhouse_id: 2, node_id: 65001, wind_ms, air_temp_c, par_value, rain_tips, rssi_dbm, battery_v, .. } => {
+                assert_eq!(wind_ms, 3.5);
+                assert!(air_temp_c.is_nan());
+                assert_eq!((par_value, rain_tips, rssi_dbm, battery_v), (U16_MISSING, U16_MISSING, Some(-60.0), None));
+            }
+            d => panic!("{d:?}"),
+        }
+        // -999 means missing in JSON too
+        match decode_payload(br#"{"gh": 1, "node": 3, "air_temp_c": -999}"#).unwrap() {
+            Decoded::Standard { air_temp_c, .. } => assert!(air_temp_c.is_nan()),
+            d => panic!("{d:?}"),
+        }
+    }
+
+    #[test]
+    fn json_with_wrong_types_or_no_ids_is_invalid_json() {
+        // (none of them a binary frame length: those are parsed as binary first)
+        for p in [
+            &br#"{"gh": 1, "node": 3, "air_temp_c": "hot"}"#[..],
+            br#"{"gh": "1", "node": 3, "kind": "standard"}"#,
+            br#"{"gh": 1, "node": 3, "par_value": -5}"#,
+            br#"{"gh": 1, "node": 70000, "seq": 1}"#,
+            br#"{"gh": 1, "node": 3, "seq": 1.5}"#,
+            br#"{"node": 3, "air_temp_c": 24.1}"#,
+            br#"{"gh": 1, "node": 3, "kind": "greenhouse"}"#,
+            br#"{"gh": 1, "node": 3,"#,
+            b"{}",
+        ] {
+            assert_eq!(decode_payload(p).unwrap_err(), DecodeError::InvalidJson, "{}", String::from_utf8_lossy(p));
+        }
+        assert_eq!(decode_payload(br#"{"gh": 0, "node": 3}"#).unwrap_err(), DecodeError::InvalidValue { field: "greenhouse_id" });
+    }
+
+    #[test]
+    fn neither_binary_nor_json_is_unknown_length() {
+        // (an empty payload is also a blank protobuf message with the `proto` feature: no ids, still an error)
+        assert!(decode_payload(b"").is_err());
+        for p in [&b"hello"[..], b"[1, 2, 3]", b"  \n", &[0xFF; 33], &[b'x'; 61]] {
+            assert_eq!(decode_payload(p).unwrap_err(), DecodeError::UnknownLength(p.len()), "{p:?}");
+        }
+    }
+
+    #[test]
+    fn binary_lengths_win_over_json() {
+        // a 60-byte frame of greenhouse 0x227B starts with `{`: still a binary frame
+        let mut d = standard(1200.0);
+        d.set_greenhouse_id(u16::from_le_bytes(*b"{\""));
+        let frame = encode_payload(&d);
+        assert_eq!(frame[0], b'{');
+        assert!(same(&decode_payload(&frame).unwrap(), &d));
+    }
+
+    fn with_clock(d: Decoded, seq_no: u16, ts: u32) -> Decoded {
+        let mut d = with_telemetry(d, 3.6, -65.0);
+        if let Decoded::Standard { seq, device_ts, .. } = &mut d { *seq = Some(seq_no); *device_ts = Some(ts); }
+        d
+    }
+
+    #[test]
+    fn v4_frame_carries_the_node_clock() {
+        let d = with_clock(standard(1200.0), 42, 1_760_000_123);
+        let frame = encode_payload(&d);
+        assert_eq!((frame.len(), frame[0]), (75, 0x04));
+        assert_eq!(rd_u16_le(&frame, 69), Some(42));
+        assert_eq!(rd_u32_le(&frame, 71), Some(1_760_000_123));
+        let got = decode_payload(&frame).unwrap();
+        assert!(same(&got, &d));
+        assert_eq!((got.seq(), got.device_ts()), (Some(42), Some(1_760_000_123)));
+        // with CRC trailer, and in a store-and-forward batch
+        assert_eq!(decode_payload(&with_crc(frame.clone())).unwrap().device_ts(), Some(1_760_000_123));
+        let mut batch = vec![2];
+        batch.extend(&frame);
+        batch.extend(encode_payload(&with_clock(standard(1200.0), 43, 1_760_000_133)));
+        let ts: Vec<_> = decode_batch(&batch).into_iter().map(|r| r.unwrap().device_ts()).collect();
+        assert_eq!(ts, [Some(1_760_000_123), Some(1_760_000_133)]);
+    }
+
+    #[test]
+    fn batches_without_node_clock_are_rejected_frame_by_frame() {
+        // a lone count byte of 0 is an empty batch: nothing to forward, nothing wrong
+        assert!(decode_batch(&[0u8]).is_empty());
+        let mut v3 = with_telemetry(standard(1200.0), 3.6, -65.0);
+        if let Decoded::Standard { seq, .. } = &mut v3 { *seq = Some(7); }
+        let mut untimed = vec![2];
+        for _ in 0..2 { untimed.extend(encode_payload(&v3)); }
+        let got: Vec<_> = decode_batch(&untimed).into_iter().map(Result::unwrap_err).collect();
+        assert_eq!(got, [DecodeError::UntimedBatch, DecodeError::UntimedBatch]);
+        // the same frames one at a time are fine, stamped on arrival
+        assert_eq!(decode_payload(&untimed[1..72]).unwrap().seq(), Some(7));
+    }
+
+    #[test]
+    fn frames_before_v4_have_no_node_clock() {
+        let mut v3 = with_telemetry(standard(1200.0), 3.6, -65.0);
+        if let Decoded::Standard { seq, .. } = &mut v3 { *seq = Some(7); }
+        let frame = encode_payload(&v3);
+        assert_eq!((frame.len(), frame[0]), (71, 0x03));
+        assert_eq!(decode_payload(&frame).unwrap().device_ts(), None);
+        assert_eq!(decode_payload(&encode_payload(&standard(1200.0))).unwrap().device_ts(), None);
+        assert_eq!(decode_payload(br#"{"gh": 1, "node": 3, "ts": 1760000123, "seq": 9}"#).unwrap().device_ts(), Some(1_760_000_123));
+        assert_eq!(decode_payload(br#"{"gh": 1, "node": 3, "device_ts": 1760000123}"#).unwrap().device_ts(), Some(1_760_000_123));
+    }
+
+    #[test]
+    fn v4_frame_too_short_is_truncated() {
+        let frame = encode_payload(&with_clock(standard(1200.0), 42, 1_760_000_123));
+        for n in [71, 73, 74] {
+            assert_eq!(decode_payload(&frame[..n]).unwrap_err(), DecodeError::Truncated { expected: 75, got: n }, "{n} bytes");
+        }
+        let mut f = with_crc(frame);
+        f[72] ^= 0x80;
+        assert_eq!(decode_payload(&f).unwrap_err(), DecodeError::CrcMismatch);
+    }
+
+    fn soil() -> Decoded {
+        Decoded::Soil { greenhouse_id: 1, node_id: 9, vwc1_pct: 35.5, vwc2_pct: 36.0, vwc3_pct: 34.25, vwc4_pct: 33.0, ec_ms_cm: 2.1 }
+    }
+
+    #[test]
+    fn soil_frame_layout() {
+        let frame = encode_payload(&soil());
+        assert_eq!((frame.len(), frame[0]), (25, 0x10));
+        assert_eq!((rd_u16_le(&frame, 1), rd_u16_le(&frame, 3)), (Some(1), Some(9)));
+        assert_eq!(rd_f32_le(&frame, 21), Some(2.1));
+        let d = decode_payload(&frame).unwrap();
+        assert!(same(&d, &soil()));
+        assert_eq!(d.layout(), FrameLayout::Soil);
+        assert_eq!(d.telemetry(), (None, None));
+        assert!(same(&decode_payload(&with_crc(frame)).unwrap(), &soil()));
+    }
+
+    #[test]
+    fn soil_frames_do_not_collide_with_other_layouts() {
+        let frame = encode_payload(&soil());
+        // a 25-byte payload without the soil type byte is no frame
+        let mut untyped = frame.clone();
+        untyped[0] = 0x00;
+        assert_eq!(decode_payload(&untyped).unwrap_err(), DecodeError::UnknownLength(25));
+        // nor is one cut short (24 bytes would be an outdoor frame with trailer) or corrupted behind its trailer
+        assert_eq!(decode_payload(&frame[..20]).unwrap_err(), DecodeError::Truncated { expected: 25, got: 20 });
+        assert_eq!(decode_payload(&frame[..24]).unwrap_err(), DecodeError::CrcMismatch);
+        let mut f = with_crc(frame.clone());
+        f[6] ^= 0x04;
+        assert_eq!(decode_payload(&f).unwrap_err(), DecodeError::CrcMismatch);
+        // the registry keeps soil and air nodes apart
+        assert!(same(&single(decode_as(&frame, FrameLayout::Soil)).unwrap(), &soil()));
+        assert_eq!(single(decode_as(&frame, FrameLayout::Standard)).unwrap_err(), DecodeError::LayoutMismatch { expected: FrameLayout::Standard });
+        let json = br#"{"gh": 1, "node": 9, "kind": "soil", "vwc1_pct": 35.5, "ec_ms_cm": 2.1}"#;
+        match decode_payload(json).unwrap() {
+            Decoded::Soil { vwc1_pct, vwc2_pct, ec_ms_cm, .. } => {
+                assert_eq!((vwc1_pct, ec_ms_cm), (35.5, 2.1));
+                assert!(vwc2_pct.is_nan());
+            }
+            d => panic!("{d:?}"),
+        }
+    }
+
+    #[test]
+    fn every_decode_error_variant() {
+        let v1 = encode_payload(&standard(1200.0));
+        let mut gh0 = standard(1200.0);
+        gh0.set_greenhouse_id(0);
+        let mut node0 = encode_payload(&standard(1200.0));
+        node0[2..4].copy_from_slice(&0u16.to_le_bytes());
+        let mut corrupted = with_crc(v1.clone());
+        corrupted[20] ^= 0x10;
+        let mut batch = vec![3];
+        batch.extend(&v1);
+        batch.extend(&v1);
+        let mut untimed = vec![1];
+        untimed.extend(&v1);
+        let keys = super::super::frame_mac::FrameKeys::parse(r#"{"1": "0b0b0b0b"}"#).unwrap();
+
+        let cases = [
+            (decode_payload(&v1[..59]), DecodeError::UnknownLength(59), "UnknownLength", "unknown frame length (59 bytes)"),
+            (decode_payload(&[0x03; 30]), DecodeError::Truncated { expected: 71, got: 30 }, "Truncated", "truncated frame (30 of 71 bytes)"),
+            (decode_payload(&encode_payload(&gh0)), DecodeError::InvalidValue { field: "greenhouse_id" }, "InvalidValue", "invalid greenhouse_id"),
+            (decode_payload(&node0), DecodeError::InvalidValue { field: "node_id" }, "InvalidValue", "invalid node_id"),
+            (decode_payload(&corrupted), DecodeError::CrcMismatch, "CrcMismatch", "CRC mismatch"),
+            (decode_payload(b"{\"gh\": 1"), DecodeError::InvalidJson, "InvalidJson", "invalid JSON frame"),
+            (single(decode_batch(&batch)), DecodeError::BatchCount { announced: 3, frames: 2 }, "BatchCount", "batch announces 3 frames but holds 2"),
+            (single(decode_as(&v1, FrameLayout::Outdoor)), DecodeError::LayoutMismatch { expected: FrameLayout::Outdoor },
+             "LayoutMismatch", "frame is not the outdoor layout registered for the node"),
+            (keys.verify(1, &v1).map(|_| standard(0.0)), DecodeError::BadMac, "BadMac", "missing or wrong HMAC"),
+            (single(decode_batch(&untimed)), DecodeError::UntimedBatch, "UntimedBatch", "batched frame without node clock (needs v4)"),
+        ];
+        for (got, want, name, text) in cases {
+            assert_eq!(got.unwrap_err(), want);
+            assert_eq!((want.name(), want.to_string().as_str()), (name, text));
+        }
+    }
+
+    #[test]
+    fn outdoor_telemetry_frame_layout() {
+        let d = with_telemetry(outdoor(), 3.712, -71.0);
+        let frame = encode_payload(&d);
+        assert_eq!((frame.len(), frame[0]), (26, 0x11));
+        assert_eq!(rd_u16_le(&frame, 23), Some(3712));
+        assert_eq!(frame[25] as i8, -71);
+        let got = decode_payload(&frame).unwrap();
+        assert!(same(&got, &d));
+        assert_eq!(got.telemetry(), (Some(3.712), Some(-71.0)));
+        assert!(same(&decode_payload(&with_crc(frame)).unwrap(), &d));
+    }
+
+    #[test]
+    fn outdoor_telemetry_is_whole_millivolts_and_dbm() {
+        let frame = encode_payload(&with_telemetry(outdoor(), 3.7126, -200.4));
+        assert_eq!(decode_payload(&frame).unwrap().telemetry(), (Some(3.713), Some(-128.0)));
+        let frame = encode_payload(&with_telemetry(outdoor(), 0.0, 30.0));
+        assert_eq!(decode_payload(&frame).unwrap().telemetry(), (Some(0.0), Some(30.0)));
+    }
+
+    #[test]
+    fn old_firmware_has_no_telemetry() {
+        assert_eq!(decode_payload(&encode_payload(&standard(1200.0))).unwrap().telemetry(), (None, None));
+        assert_eq!(decode_payload(&encode_payload(&outdoor())).unwrap().telemetry(), (None, None));
+        assert_eq!(decode_payload(&encode_payload(&soil())).unwrap().telemetry(), (None, None));
+        assert_eq!(decode_payload(br#"{"gh": 1, "node": 3, "battery_v": 3.9}"#).unwrap().telemetry(), (Some(3.9), None));
+    }
+
+    #[test]
+    fn sentinel_marks_one_field_missing() {
+        let mut d = standard(1200.0);
+        if let Decoded::Standard { leaf_temp_c, par_value, .. } = &mut d { *leaf_temp_c = F32_MISSING; *par_value = U16_MISSING; }
+        let frame = encode_payload(&d);
+        assert_eq!(rd_f32_le(&frame, 8), Some(-999.0));
+        match decode_payload(&frame).unwrap() {
+            Decoded::Standard { air_temp_c, leaf_temp_c, par_value, weight_g, .. } => {
+                assert!(leaf_temp_c.is_nan());
+                assert_eq!((air_temp_c, par_value, weight_g), (24.5, U16_MISSING, 1200.0));
+                assert!(u16_reading(par_value).is_nan());
+            }
+            d => panic!("{d:?}"),
+        }
+        // a missing u16 weight in a v1 frame is NaN too
+        let frame = encode_payload(&standard(f32::NAN));
+        assert_eq!(rd_u16_le(&frame, 42), Some(U16_MISSING));
+        assert!(weight(&decode_payload(&frame).unwrap()).is_nan());
+        // only exactly -999 is the sentinel
+        let mut d = standard(1200.0);
+        if let Decoded::Standard { air_temp_c, .. } = &mut d { *air_temp_c = -999.5; }
+        match decode_payload(&encode_payload(&d)).unwrap() {
+            Decoded::Standard { air_temp_c, .. } => assert_eq!(air_temp_c, -999.5),
+            d => panic!("{d:?}"),
+        }
+    }
+
+    #[test]
+    fn sentinel_in_every_layout() {
+        let soil = Decoded::Soil { greenhouse_id: 1, node_id: 9, vwc1_pct: F32_MISSING, vwc2_pct: 30.0, vwc3_pct: F32_MISSING, vwc4_pct: 31.0, ec_ms_cm: F32_MISSING };
+        match decode_payload(&encode_payload(&soil)).unwrap() {
+            Decoded::Soil { vwc1_pct, vwc2_pct, vwc3_pct, ec_ms_cm, .. } => {
+                assert!(vwc1_pct.is_nan() && vwc3_pct.is_nan() && ec_ms_cm.is_nan());
+                assert_eq!(vwc2_pct, 30.0);
+            }
+            d => panic!("{d:?}"),
+        }
+        let mut o = outdoor();
+        if let Decoded::Outdoor { air_rh_pct, .. } = &mut o { *air_rh_pct = F32_MISSING; }
+        match decode_payload(&with_crc(encode_payload(&o))).unwrap() {
+            Decoded::Outdoor { air_temp_c, air_rh_pct, .. } => assert!(air_rh_pct.is_nan() && air_temp_c == 18.0),
+            d => panic!("{d:?}"),
+        }
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Manifest {
+        tolerance: f64,
+        frame: Vec<Fixture>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Fixture {
+        file: String,
+        layout: Option<String>,
+        #[serde(default)]
+        crc: bool,
+        #[serde(default = "encodes")]
+        encodes: bool,
+        expect: Vec<toml::Table>,
+    }
+
+    fn encodes() -> bool { true }
+
+    fn golden() -> (Manifest, Vec<Vec<u8>>) {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/frames");
+        let manifest: Manifest = toml::from_str(&std::fs::read_to_string(dir.join("manifest.toml")).unwrap()).unwrap();
+        let files = manifest.frame.iter().map(|f| std::fs::read(dir.join(&f.file)).unwrap()).collect();
+        (manifest, files)
+    }
+
+    /// Every field of `d` against the manifest table: listed ones within `tol`, the rest missing.
+    fn check_fields(file: &str, d: &Decoded, expect: &toml::Table, tol: f64) {
+        let serde_json::Value::Object(got) = serde_json::to_value(d).unwrap() else { unreachable!() };
+        for (k, v) in &got {
+            match (expect.get(k), v) {
+                (None, serde_json::Value::Null) => {}
+                (None, v) => panic!("{file}: {k} = {v}, missing in the manifest"),
+                (Some(toml::Value::String(s)), v) => assert_eq!(v.as_str(), Some(s.as_str()), "{file}: {k}"),
+                (Some(toml::Value::Float(w)), serde_json::Value::Null) if w.is_nan() => {}
+                (Some(w), v) => {
+                    let w = w.as_float().or(w.as_integer().map(|i| i as f64)).unwrap();
+                    let ok = v.as_f64().is_some_and(|g| (g - w).abs() <= tol * w.abs().max(1.0));
+                    assert!(ok, "{file}: {k} = {v}, manifest {w}");
+                }
+            }
+        }
+        for k in expect.keys() { assert!(got.contains_key(k), "{file}: no field {k}"); }
+    }
+
+    fn decode_fixture(f: &Fixture, bytes: &[u8]) -> Vec<Decoded> {
+        let decoded = match f.layout.as_deref() {
+            Some(l) => decode_as(bytes, FrameLayout::parse(l).unwrap()),
+            None => decode_batch(bytes),
+        };
+        decoded.into_iter().map(|d| d.unwrap_or_else(|e| panic!("{}: {e}", f.file))).collect()
+    }
+
+    #[test]
+    fn golden_fixtures_decode_to_their_manifest() {
+        let (manifest, files) = golden();
+        for (f, bytes) in manifest.frame.iter().zip(&files) {
+            let decoded = decode_fixture(f, bytes);
+            assert_eq!(decoded.len(), f.expect.len(), "{}", f.file);
+            for (d, expect) in decoded.iter().zip(&f.expect) {
+                check_fields(&f.file, d, expect, manifest.tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn golden_fixtures_re_encode_to_their_bytes() {
+        let (manifest, files) = golden();
+        for (f, bytes) in manifest.frame.iter().zip(&files).filter(|(f, _)| f.encodes) {
+            let frames = decode_fixture(f, bytes);
+            let encode = |d: &Decoded| if f.crc { with_crc(encode_payload(d)) } else { encode_payload(d) };
+            let mut out = if frames.len() > 1 { vec![frames.len() as u8] } else { Vec::new() };
+            for d in &frames { out.extend(encode(d)); }
+            assert_eq!(&out, bytes, "{}", f.file);
+        }
+    }
+
+    #[test]
+    fn parse_hex_takes_pasted_captures() {
+        let v1 = &golden().1[0];
+        let spaced = v1.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" ");
+        assert_eq!(parse_hex(&spaced).as_ref(), Some(v1));
+        assert_eq!(parse_hex(&format!("  0X{}\n", hex::encode_upper(v1))).as_ref(), Some(v1));
+        assert_eq!(parse_hex("0a:1b-2c,3d\r\n\t4e"), Some(vec![0x0a, 0x1b, 0x2c, 0x3d, 0x4e]));
+        assert_eq!(parse_hex(""), Some(vec![]));
+        for bad in ["0a1", "0g", "+f", "0x0x0a", "ä0"] {
+            assert_eq!(parse_hex(bad), None, "{bad}");
+        }
+    }
+
+    mod props {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// Any reading the wire can carry, NaN and infinities included; never the -999 sentinel,
+        /// which decodes as NaN on purpose.
+        fn reading() -> impl Strategy<Value = f32> {
+            prop_oneof![
+                4 => any::<f32>(),
+                1 => Just(f32::NAN),
+                1 => Just(f32::INFINITY),
+                1 => Just(f32::NEG_INFINITY),
+                2 => -50.0f32..3000.0,
+            ].prop_filter("sentinel", |v| *v != F32_MISSING)
+        }
+
+        fn id() -> impl Strategy<Value = u16> {
+            1..=u16::MAX
+        }
+
+        /// Standard frames of every version: v1 (no optional fields), v2 telemetry, v3 + seq, v4 + device_ts,
+        /// v5 telemetry in whole mV / dBm. Weight is a whole u16 gram count or missing, the only values the
+        /// u16 slot holds.
+        fn standard_frame() -> impl Strategy<Value = Decoded> {
+            let readings = prop::collection::vec(reading(), 13);
+            let weight = prop_oneof![(0u16..u16::MAX).prop_map(|w| w as f32), Just(f32::NAN)];
+            (id(), id(), readings, any::<u16>(), weight, 0u8..=5, (reading(), reading(), any::<u16>(), any::<i8>()), any::<u16>(), any::<u32>())
+                .prop_map(|(gh, node, r, par, weight_g, version, (batt, rssi, mv, dbm), seq, ts)| Decoded::Standard {
+                    greenhouse_id: gh, node_id: node,
+                    air_temp_c: r[0], leaf_temp_c: r[1], bag_temp_c: r[2], air_rh_pct: r[3],
+                    bag_rh1_pct: r[4], bag_rh2_pct: r[5], bag_rh3_pct: r[6], bag_rh4_pct: r[7], bag_rh_avg_pct: r[8],
+                    par_value: par, weight_g,
+                    ea_air_kpa: r[9], ea_leaf_kpa: r[10], es_kpa: r[11], vpd_kpa: r[12],
+                    battery_v: (version >= 2).then_some(if version == 5 { mv as f32 / 1000.0 } else { batt }),
+                    rssi_dbm: (version >= 2).then_some(if version == 5 { dbm as f32 } else { rssi }),
+                    seq: (3..=4).contains(&version).then_some(seq),
+                    device_ts: (version == 4).then_some(ts),
+                })
+        }
+
+        /// Plain, telemetry (battery in whole mV, RSSI in whole dBm) and weather outdoor frames.
+        fn outdoor_frame() -> impl Strategy<Value = Decoded> {
+            let readings = prop::collection::vec(reading(), 6);
+            (id(), id(), readings, any::<u16>(), 0u8..=2, any::<u16>(), any::<i8>(), any::<u16>())
+                .prop_map(|(gh, node, r, par, kind, mv, rssi, rain)| Decoded::Outdoor {
+                    greenhouse_id: gh, node_id: node,
+                    air_temp_c: r[0], air_rh_pct: r[1], par_value: par, ea_air_kpa: r[2], es_kpa: r[3],
+                    wind_ms: if kind == 2 { r[4] } else { f32::NAN },
+                    wind_gust_ms: if kind == 2 { r[5] } else { f32::NAN },
+                    rain_tips: if kind == 2 { rain } else { U16_MISSING },
+                    battery_v: (kind >= 1).then_some(mv as f32 / 1000.0),
+                    rssi_dbm: (kind >= 1).then_some(rssi as f32),
+                    seq: None, device_ts: None,
+                })
+        }
+
+        fn soil_frame() -> impl Strategy<Value = Decoded> {
+            (id(), id(), prop::collection::vec(reading(), 5)).prop_map(|(gh, node, r)| Decoded::Soil {
+                greenhouse_id: gh, node_id: node,
+                vwc1_pct: r[0], vwc2_pct: r[1], vwc3_pct: r[2], vwc4_pct: r[3], ec_ms_cm: r[4],
             })
         }
-        _ => None,
+
+        fn any_frame() -> impl Strategy<Value = Decoded> {
+            prop_oneof![3 => standard_frame(), 2 => outdoor_frame(), 1 => soil_frame()]
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(512))]
+
+            #[test]
+            fn encode_then_decode_is_identity(d in any_frame()) {
+                let frame = encode_payload(&d);
+                let back = decode_payload(&frame);
+                prop_assert!(back.is_ok_and(|b| same(&b, &d)), "{d:?} -> {} bytes -> {back:?}", frame.len());
+            }
+
+            #[test]
+            fn crc_trailer_round_trips_too(d in any_frame()) {
+                let frame = with_crc(encode_payload(&d));
+                let back = decode_payload(&frame);
+                prop_assert!(back.is_ok_and(|b| same(&b, &d)), "{d:?} -> {back:?}");
+            }
+
+            #[test]
+            fn batches_round_trip(frames in prop::collection::vec(standard_frame(), 1..8), version in 0u8..=5) {
+                // one batch holds one layout: give every frame the same optional fields (half a dBm
+                // keeps v2 frames out of the v5 layout)
+                let frames: Vec<Decoded> = frames.into_iter().map(|mut d| {
+                    if let Decoded::Standard { battery_v, rssi_dbm, seq, device_ts, .. } = &mut d {
+                        *battery_v = (version >= 2).then_some(if version == 5 { 3.3 } else { battery_v.unwrap_or(3.3) });
+                        *rssi_dbm = (version >= 2).then_some(if version == 2 { -60.5 } else if version == 5 { -60.0 } else { rssi_dbm.unwrap_or(-60.0) });
+                        *seq = (3..=4).contains(&version).then_some(seq.unwrap_or(1));
+                        *device_ts = (version == 4).then_some(device_ts.unwrap_or(1_760_000_000));
+                    }
+                    d
+                }).collect();
+                let mut batch = vec![frames.len() as u8];
+                for d in &frames { batch.extend(encode_payload(d)); }
+                let back = decode_batch(&batch);
+                prop_assert_eq!(back.len(), frames.len());
+                for (b, d) in back.iter().zip(&frames) {
+                    // only frames with a node clock can be placed in their minute
+                    if version == 4 {
+                        prop_assert!(b.is_ok_and(|b| same(&b, d)), "{d:?} -> {b:?}");
+                    } else {
+                        prop_assert_eq!(b.as_ref().unwrap_err(), &DecodeError::UntimedBatch);
+                    }
+                }
+            }
+
+            #[test]
+            fn random_bytes_never_panic(p in prop::collection::vec(any::<u8>(), 0..=128)) {
+                let _ = decode_payload(&p);
+                let _ = decode_batch(&p);
+                for layout in [FrameLayout::Standard, FrameLayout::StandardV14, FrameLayout::Outdoor, FrameLayout::Soil] {
+                    let _ = decode_as(&p, layout);
+                }
+            }
+
+            #[test]
+            fn damaged_frames_never_panic(d in any_frame(), cut in 0usize..80, flip in any::<(usize, u8)>(), crc in any::<bool>()) {
+                let mut frame = encode_payload(&d);
+                if crc { frame = with_crc(frame); }
+                let i = flip.0 % frame.len();
+                frame[i] ^= flip.1;
+                frame.truncate(cut.min(frame.len()));
+                let _ = decode_payload(&frame);
+                let _ = decode_batch(&frame);
+            }
+        }
     }
 }
@@ -0,0 +1,136 @@
+//! Frame counters of the decode path, for the "frames/min, errors/min" badge.
+//! - Counted in the subscriber right after `decode_payload`: decoded frames per kind, CRC failures
+//!   (RF corruption) and every other `DecodeError` as malformed. Relaxed atomics, no lock on the hot path.
+//! - Readings the slew guard rejects are counted too, by the node aggregator after its guard, and so are
+//!   late samples (node clock before the open window): back-filled into their own window, or dropped
+//!   because that window is already stored.
+//! - Held in managed state above the subscriber's reconnect loop, so a broker reconnect keeps the counts;
+//!   only an app restart or `reset_decoder_stats` zeroes them.
+//! - `get_decoder_stats` and the `decoder_stats` event (every 30 s) carry the same snapshot; the UI takes
+//!   rates from the difference of two snapshots.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering::Relaxed};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::interval;
+use tracing::warn;
+
+use super::decoder::{DecodeError, Decoded};
+use crate::services::presenter::emitter::EventSink;
+
+const EVERY: Duration = Duration::from_secs(30);
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+#[derive(Debug)]
+pub struct DecoderStats {
+    standard_ok: AtomicU64,
+    outdoor_ok: AtomicU64,
+    soil_ok: AtomicU64,
+    malformed: AtomicU64,
+    crc_failed: AtomicU64,
+    slew_rejected: AtomicU64,
+    late_backfilled: AtomicU64,
+    late_dropped: AtomicU64,
+    since_ms: AtomicI64,
+}
+
+pub type DecoderStatsShared = Arc<DecoderStats>;
+
+impl Default for DecoderStats {
+    fn default() -> Self {
+        DecoderStats {
+            standard_ok: AtomicU64::new(0),
+            outdoor_ok: AtomicU64::new(0),
+            soil_ok: AtomicU64::new(0),
+            malformed: AtomicU64::new(0),
+            crc_failed: AtomicU64::new(0),
+            slew_rejected: AtomicU64::new(0),
+            late_backfilled: AtomicU64::new(0),
+            late_dropped: AtomicU64::new(0),
+            since_ms: AtomicI64::new(now_ms()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DecoderStatsSnapshot {
+    pub ts_ms: i64,
+    /// Start of counting: app start or the last reset.
+    pub since_ms: i64,
+    pub standard_ok: u64,
+    pub outdoor_ok: u64,
+    pub soil_ok: u64,
+    pub malformed: u64,
+    pub crc_failed: u64,
+    /// Readings the slew guard rejected as implausible jumps (counted in the node aggregator).
+    pub slew_rejected: u64,
+    /// Late samples placed in the past window they were measured in (counted in the node aggregator).
+    pub late_backfilled: u64,
+    /// Late samples whose window was already stored, so dropped.
+    pub late_dropped: u64,
+}
+
+impl DecoderStats {
+    /// Count one `decode_payload` outcome.
+    pub fn count(&self, res: Result<&Decoded, DecodeError>) {
+        let c = match res {
+            Ok(Decoded::Standard { .. }) => &self.standard_ok,
+            Ok(Decoded::Outdoor { .. }) => &self.outdoor_ok,
+            Ok(Decoded::Soil { .. }) => &self.soil_ok,
+            Err(DecodeError::CrcMismatch) => &self.crc_failed,
+            Err(_) => &self.malformed,
+        };
+        c.fetch_add(1, Relaxed);
+    }
+
+    /// Count readings rejected by the slew guard.
+    pub fn count_slew_rejected(&self, n: usize) {
+        if n > 0 { self.slew_rejected.fetch_add(n as u64, Relaxed); }
+    }
+
+    /// Count one late sample: back-filled into its window, or dropped.
+    pub fn count_late(&self, backfilled: bool) {
+        let c = if backfilled { &self.late_backfilled } else { &self.late_dropped };
+        c.fetch_add(1, Relaxed);
+    }
+
+    pub fn snapshot(&self) -> DecoderStatsSnapshot {
+        DecoderStatsSnapshot {
+            ts_ms: now_ms(),
+            since_ms: self.since_ms.load(Relaxed),
+            standard_ok: self.standard_ok.load(Relaxed),
+            outdoor_ok: self.outdoor_ok.load(Relaxed),
+            soil_ok: self.soil_ok.load(Relaxed),
+            malformed: self.malformed.load(Relaxed),
+            crc_failed: self.crc_failed.load(Relaxed),
+            slew_rejected: self.slew_rejected.load(Relaxed),
+            late_backfilled: self.late_backfilled.load(Relaxed),
+            late_dropped: self.late_dropped.load(Relaxed),
+        }
+    }
+
+    /// Zero every counter; frames decoded meanwhile may land on either side of the reset.
+    pub fn reset(&self) {
+        for c in [&self.standard_ok, &self.outdoor_ok, &self.soil_ok, &self.malformed, &self.crc_failed, &self.slew_rejected,
+                  &self.late_backfilled, &self.late_dropped] {
+            c.store(0, Relaxed);
+        }
+        self.since_ms.store(now_ms(), Relaxed);
+    }
+}
+
+/// Emit `decoder_stats` every 30 s.
+pub async fn run_decoder_stats<S: EventSink>(sink: S, stats: DecoderStatsShared) {
+    let mut tick = interval(EVERY);
+    loop {
+        tick.tick().await;
+        match serde_json::to_value(stats.snapshot()) {
+            Ok(v) => sink.emit_json("decoder_stats", v),
+            Err(e) => warn!(target: "UI", "serialize decoder_stats failed: {e}"),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Duplicate publish filter in front of the aggregator.
+//! - A broker bridging two sites sometimes delivers the same frame twice within a second, which
+//!   doubled that node's weight in the 60s mean.
+//! - Remembers a hash of the last payload per (greenhouse_id, node_id) and when it arrived; an
+//!   identical payload within `subscriber().duplicate_window_ms` is a duplicate. A node's real
+//!   frames always differ (readings, sequence, time), so only true repeats match.
+//! - One entry per node: memory is bounded by the fleet size.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use crate::services::mqtt::config::subscriber;
+
+#[derive(Debug, Default)]
+pub struct Dedup {
+    last: HashMap<(u16, u16), (u64, Instant)>,
+}
+
+impl Dedup {
+    /// True when `payload` from `ids` repeats the node's previous payload within the window.
+    pub fn is_duplicate(&mut self, ids: (u16, u16), payload: &[u8], now: Instant) -> bool {
+        let mut h = DefaultHasher::new();
+        payload.hash(&mut h);
+        let hash = h.finish();
+        let window = Duration::from_millis(subscriber().duplicate_window_ms);
+        match self.last.get(&ids) {
+            Some(&(prev, at)) if prev == hash && now.duration_since(at) <= window => true,
+            _ => {
+                self.last.insert(ids, (hash, now));
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mqtt::greenhouse_sensor::aggregator::last_windows;
+    use crate::services::mqtt::greenhouse_sensor::decoder::{decode_payload, encode_payload, Decoded};
+
+    fn frame(air_temp_c: f32) -> Vec<u8> {
+        encode_payload(&Decoded::Standard {
+            greenhouse_id: 1, node_id: 3,
+            air_temp_c, leaf_temp_c: 23.0, bag_temp_c: 21.5, air_rh_pct: 68.0,
+            bag_rh1_pct: 80.0, bag_rh2_pct: 81.0, bag_rh3_pct: 82.0, bag_rh4_pct: 83.0, bag_rh_avg_pct: 81.5,
+            par_value: 420, weight_g: 1200.0,
+            ea_air_kpa: 2.1, ea_leaf_kpa: 2.4, es_kpa: 3.1, vpd_kpa: 1.0,
+            battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn repeat_within_the_window_is_one_sample() {
+        let t0 = Instant::now();
+        let mut dedup = Dedup::default();
+        let p = frame(24.0);
+        let arrivals = [(0, &p), (100, &p), (10_000, &p)];
+        let kept: Vec<Decoded> = arrivals.iter()
+            .filter(|(ms, p)| !dedup.is_duplicate((1, 3), p, t0 + Duration::from_millis(*ms)))
+            .map(|(_, p)| decode_payload(p).unwrap())
+            .collect();
+        assert_eq!(kept.len(), 2, "the 100 ms repeat is dropped, the 10 s one kept");
+        let na = last_windows(kept).await[&(1, 3)];
+        assert_eq!(na.samples.get("air_temp_c"), Some(2));
+        assert_eq!(na.received_packets, 2);
+    }
+
+    #[test]
+    fn only_identical_payloads_of_the_same_node_are_duplicates() {
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let mut dedup = Dedup::default();
+        assert!(!dedup.is_duplicate((1, 3), &frame(24.0), at(0)));
+        assert!(!dedup.is_duplicate((1, 4), &frame(24.0), at(10)), "another node");
+        assert!(!dedup.is_duplicate((1, 3), &frame(24.1), at(20)), "another reading");
+        assert!(dedup.is_duplicate((1, 3), &frame(24.1), at(30)));
+        assert!(!dedup.is_duplicate((1, 3), &frame(24.0), at(40)), "only the last payload is remembered");
+    }
+
+    #[test]
+    fn window_runs_from_the_first_delivery() {
+        let t0 = Instant::now();
+        let window = subscriber().duplicate_window_ms;
+        let mut dedup = Dedup::default();
+        let p = frame(24.0);
+        assert!(!dedup.is_duplicate((1, 3), &p, t0));
+        assert!(dedup.is_duplicate((1, 3), &p, t0 + Duration::from_millis(window)), "the window is inclusive");
+        // repeats do not extend it
+        assert!(!dedup.is_duplicate((1, 3), &p, t0 + Duration::from_millis(window + 1)));
+    }
+}
@@ -0,0 +1,524 @@
+//! Configured derived metrics (`derived_metrics()` in mqtt/config.rs).
+//! - Each entry defines a new sensor key as an arithmetic expression over the per-window means
+//!   (any sensor key, or another derived key). Evaluated after aggregation, per node window and
+//!   per greenhouse window, so values flow into storage, UI events and hourly history like any sensor.
+//! - Compiled once at startup (`load_registry`): a parse error, unknown key, duplicate or cycle is a
+//!   configuration error naming every bad entry, and the pipeline does not start.
+//! - A missing input makes the result None, as does a non-finite result (e.g. ln of 0).
+//! - Values ride in `DerivedValues` (fixed slots, so NodeAvg / GhAvg stay `Copy`) and serialize
+//!   as ordinary top-level fields.
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::{collections::HashMap, sync::OnceLock};
+use tracing::info;
+
+use crate::services::mqtt::config::{derived_metrics, DerivedDef};
+
+/// Upper bound on configured metrics (slots in `DerivedValues`).
+pub const MAX_DERIVED: usize = 8;
+
+/// Sensor keys available to expressions, with units (same as NodeAvg / GhAvg `fields()`).
+pub const BASE_SENSORS: [(&str, &str); 16] = [
+    ("air_temp_c", "C"), ("leaf_temp_c", "C"), ("bag_temp_c", "C"), ("air_rh_pct", "%"),
+    ("bag_rh1_pct", "%"), ("bag_rh2_pct", "%"), ("bag_rh3_pct", "%"), ("bag_rh4_pct", "%"), ("bag_rh_avg_pct", "%"),
+    ("par_value", ""), ("weight_g", ""), ("ea_air_kpa", "kPa"), ("ea_leaf_kpa", "kPa"), ("es_kpa", "kPa"),
+    ("vpd_kpa", "kPa"), ("leaf_air_dt_c", "C"),
+];
+
+/// Node-only series (telemetry, packet counts, soil nodes); stored when present, not available to expressions.
+pub const NODE_SENSORS: [(&str, &str); 9] = [
+    ("battery_v", "V"), ("rssi_dbm", "dBm"), ("received_packets", ""), ("lost_packets", ""),
+    ("vwc1_pct", "%"), ("vwc2_pct", "%"), ("vwc3_pct", "%"), ("vwc4_pct", "%"), ("ec_ms_cm", "mS/cm"),
+];
+
+fn is_base(key: &str) -> bool { BASE_SENSORS.iter().any(|(k, _)| *k == key) }
+
+/// (key, unit) of every series the pipeline stores: sensor fields, node-only series, then active derived metrics.
+pub fn sensor_units() -> Vec<(&'static str, &'static str)> {
+    BASE_SENSORS.iter().chain(NODE_SENSORS.iter()).copied()
+        .chain(registry().metrics.iter().map(|m| (m.key, m.unit))).collect()
+}
+
+/// Integer scale for scaled storage (scaled.rs): 10^precision, capped at the 2 decimals storage keeps.
+pub fn storage_scale(key: &str) -> i64 {
+    match registry().metrics.iter().find(|m| m.key == key) {
+        Some(m) if !is_base(key) => (m.scale as i64).clamp(1, 100),
+        _ => 100,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Func { Abs, Sqrt, Exp, Ln, Min, Max }
+
+impl Func {
+    fn parse(name: &str) -> Option<(Func, usize)> {
+        Some(match name {
+            "abs" => (Func::Abs, 1),
+            "sqrt" => (Func::Sqrt, 1),
+            "exp" => (Func::Exp, 1),
+            "ln" => (Func::Ln, 1),
+            "min" => (Func::Min, 2),
+            "max" => (Func::Max, 2),
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(f64),
+    Var(&'static str),
+    Neg(Box<Expr>),
+    Bin(char, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok { Num(f64), Ident(&'static str), Op(char), LParen, RParen, Comma }
+
+fn tokenize(src: &'static str) -> Result<Vec<(usize, Tok)>, String> {
+    let bytes = src.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        let start = i;
+        match c {
+            _ if c.is_ascii_whitespace() => { i += 1; continue; }
+            '0'..='9' | '.' => {
+                while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') { i += 1; }
+                let n = src[start..i].parse().map_err(|_| format!("bad number '{}' at column {}", &src[start..i], start + 1))?;
+                out.push((start, Tok::Num(n)));
+                continue;
+            }
+            'a'..='z' | 'A'..='Z' | '_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') { i += 1; }
+                out.push((start, Tok::Ident(&src[start..i])));
+                continue;
+            }
+            '+' | '-' | '*' | '/' | '^' => out.push((start, Tok::Op(c))),
+            '(' => out.push((start, Tok::LParen)),
+            ')' => out.push((start, Tok::RParen)),
+            ',' => out.push((start, Tok::Comma)),
+            _ => return Err(format!("unexpected '{c}' at column {}", start + 1)),
+        }
+        i += 1;
+    }
+    Ok(out)
+}
+
+/// Recursive-descent parser: expr = term (+|- term)*, term = unary (*|/ unary)*,
+/// unary = -unary | power, power = atom (^ unary)?, atom = number | key | func(args) | (expr).
+struct Parser { toks: Vec<(usize, Tok)>, pos: usize, end: usize }
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> { self.toks.get(self.pos).map(|(_, t)| t) }
+    fn col(&self) -> usize { self.toks.get(self.pos).map_or(self.end, |(c, _)| *c) + 1 }
+    fn next(&mut self) -> Option<Tok> {
+        let t = self.toks.get(self.pos).map(|(_, t)| t.clone());
+        self.pos += 1;
+        t
+    }
+    fn expect(&mut self, want: Tok, what: &str) -> Result<(), String> {
+        let col = self.col();
+        match self.next() {
+            Some(t) if t == want => Ok(()),
+            _ => Err(format!("expected {what} at column {col}")),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        while let Some(Tok::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.pos += 1;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(Tok::Op(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.pos += 1;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(&Tok::Op('-')) {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        let base = self.atom()?;
+        if self.peek() == Some(&Tok::Op('^')) {
+            self.pos += 1;
+            return Ok(Expr::Bin('^', Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+    fn atom(&mut self) -> Result<Expr, String> {
+        let col = self.col();
+        match self.next() {
+            Some(Tok::Num(n)) => Ok(Expr::Num(n)),
+            Some(Tok::LParen) => {
+                let e = self.expr()?;
+                self.expect(Tok::RParen, "')'")?;
+                Ok(e)
+            }
+            Some(Tok::Ident(name)) if self.peek() == Some(&Tok::LParen) => {
+                let (f, arity) = Func::parse(name).ok_or_else(|| format!("unknown function '{name}' at column {col}"))?;
+                self.pos += 1;
+                let mut args = vec![self.expr()?];
+                while self.peek() == Some(&Tok::Comma) {
+                    self.pos += 1;
+                    args.push(self.expr()?);
+                }
+                self.expect(Tok::RParen, "')'")?;
+                if args.len() != arity {
+                    return Err(format!("{name}() takes {arity} argument(s), got {} at column {col}", args.len()));
+                }
+                Ok(Expr::Call(f, args))
+            }
+            Some(Tok::Ident(name)) => Ok(Expr::Var(name)),
+            Some(_) => Err(format!("unexpected token at column {col}")),
+            None => Err("unexpected end of expression".into()),
+        }
+    }
+}
+
+fn parse(src: &'static str) -> Result<Expr, String> {
+    let toks = tokenize(src)?;
+    let mut p = Parser { toks, pos: 0, end: src.len() };
+    let e = p.expr()?;
+    if p.pos < p.toks.len() { return Err(format!("unexpected input at column {}", p.col())); }
+    Ok(e)
+}
+
+fn vars(e: &Expr, out: &mut Vec<&'static str>) {
+    match e {
+        Expr::Num(_) => {}
+        Expr::Var(k) => out.push(k),
+        Expr::Neg(a) => vars(a, out),
+        Expr::Bin(_, a, b) => { vars(a, out); vars(b, out); }
+        Expr::Call(_, args) => args.iter().for_each(|a| vars(a, out)),
+    }
+}
+
+fn eval(e: &Expr, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+    Some(match e {
+        Expr::Num(n) => *n,
+        Expr::Var(k) => lookup(k)?,
+        Expr::Neg(a) => -eval(a, lookup)?,
+        Expr::Bin(op, a, b) => {
+            let (x, y) = (eval(a, lookup)?, eval(b, lookup)?);
+            match op { '+' => x + y, '-' => x - y, '*' => x * y, '/' => x / y, _ => x.powf(y) }
+        }
+        Expr::Call(f, args) => {
+            let x = eval(&args[0], lookup)?;
+            match f {
+                Func::Abs => x.abs(),
+                Func::Sqrt => x.sqrt(),
+                Func::Exp => x.exp(),
+                Func::Ln => x.ln(),
+                Func::Min => x.min(eval(&args[1], lookup)?),
+                Func::Max => x.max(eval(&args[1], lookup)?),
+            }
+        }
+    }).filter(|v| v.is_finite())
+}
+
+struct Compiled {
+    key: &'static str,
+    unit: &'static str,
+    scale: f64,
+    expr: Expr,
+}
+
+/// Accepted metrics in evaluation order (dependencies first); slot i of `DerivedValues` is `metrics[i]`.
+pub struct Registry {
+    metrics: Vec<Compiled>,
+}
+
+/// Parse, validate and order the configured metrics; any bad entry fails the whole set, with every
+/// rejected key and its reason.
+fn compile(defs: &[DerivedDef<'static>]) -> Result<Registry, Vec<(&'static str, String)>> {
+    let mut rejected: Vec<(&'static str, String)> = Vec::new();
+    let mut parsed: HashMap<&'static str, (DerivedDef<'static>, Expr, Vec<&'static str>)> = HashMap::new();
+    let mut order_in: Vec<&'static str> = Vec::new();
+
+    for d in defs {
+        let err = if is_base(d.key) {
+            Some(format!("'{}' is already a sensor key", d.key))
+        } else if parsed.contains_key(d.key) {
+            Some("defined twice".to_string())
+        } else {
+            match parse(d.expr) {
+                Ok(e) => {
+                    let mut deps = Vec::new();
+                    vars(&e, &mut deps);
+                    match deps.iter().find(|k| !is_base(k) && !defs.iter().any(|o| o.key == **k)) {
+                        Some(k) => Some(format!("unknown key '{k}'")),
+                        None => {
+                            deps.retain(|k| !is_base(k));
+                            parsed.insert(d.key, (*d, e, deps));
+                            order_in.push(d.key);
+                            None
+                        }
+                    }
+                }
+                Err(e) => Some(e),
+            }
+        };
+        if let Some(e) = err { rejected.push((d.key, e)); }
+    }
+
+    // Depth-first topological order; a back edge is a cycle.
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark { Visiting, Done, Bad }
+    fn visit(
+        k: &'static str,
+        parsed: &HashMap<&'static str, (DerivedDef<'static>, Expr, Vec<&'static str>)>,
+        marks: &mut HashMap<&'static str, Mark>,
+        path: &mut Vec<&'static str>,
+        order: &mut Vec<&'static str>,
+        rejected: &mut Vec<(&'static str, String)>,
+    ) -> bool {
+        match marks.get(k) {
+            Some(Mark::Done) => return true,
+            Some(Mark::Bad) => return false,
+            Some(Mark::Visiting) => {
+                let from = path.iter().position(|p| *p == k).unwrap_or(0);
+                let cycle = path[from..].iter().chain(std::iter::once(&k)).copied().collect::<Vec<_>>().join(" -> ");
+                for c in &path[from..] {
+                    marks.insert(c, Mark::Bad);
+                    rejected.push((c, format!("cycle {cycle}")));
+                }
+                return false;
+            }
+            None => {}
+        }
+        let Some((_, _, deps)) = parsed.get(k) else {
+            marks.insert(k, Mark::Bad);
+            return false; // rejected earlier
+        };
+        marks.insert(k, Mark::Visiting);
+        path.push(k);
+        let mut ok = true;
+        for d in deps {
+            if !visit(d, parsed, marks, path, order, rejected) {
+                if marks.get(k) != Some(&Mark::Bad) {
+                    rejected.push((k, format!("depends on rejected '{d}'")));
+                }
+                ok = false;
+                break;
+            }
+        }
+        path.pop();
+        if ok && marks.get(k) == Some(&Mark::Visiting) {
+            marks.insert(k, Mark::Done);
+            order.push(k);
+            true
+        } else {
+            marks.insert(k, Mark::Bad);
+            false
+        }
+    }
+
+    let mut marks = HashMap::new();
+    let mut order = Vec::new();
+    for k in &order_in {
+        visit(k, &parsed, &mut marks, &mut Vec::new(), &mut order, &mut rejected);
+    }
+    if order.len() > MAX_DERIVED {
+        for k in order.drain(MAX_DERIVED..) {
+            rejected.push((k, format!("more than {MAX_DERIVED} derived metrics configured")));
+        }
+    }
+
+    if !rejected.is_empty() { return Err(rejected); }
+
+    let metrics = order.into_iter().filter_map(|k| parsed.remove(k)).map(|(d, expr, _)| Compiled {
+        key: d.key,
+        unit: d.unit,
+        scale: 10f64.powi(d.precision.min(6) as i32),
+        expr,
+    }).collect();
+    Ok(Registry { metrics })
+}
+
+static REGISTRY: OnceLock<Result<Registry, String>> = OnceLock::new();
+static NO_METRICS: Registry = Registry { metrics: Vec::new() };
+
+/// Compile `derived_metrics()` once; Err names every rejected entry. main.rs calls this at startup and
+/// does not start the pipeline on an error.
+pub fn load_registry() -> Result<&'static Registry, String> {
+    REGISTRY.get_or_init(|| {
+        let reg = compile(derived_metrics()).map_err(|rejected| {
+            let reasons: Vec<String> = rejected.iter().map(|(key, why)| format!("'{key}': {why}")).collect();
+            format!("invalid derived metric(s): {}", reasons.join("; "))
+        })?;
+        let keys: Vec<&str> = reg.metrics.iter().map(|m| m.key).collect();
+        info!(target: "DERIVED", "{} metric(s) active: {}", keys.len(), keys.join(", "));
+        Ok(reg)
+    }).as_ref().map_err(Clone::clone)
+}
+
+/// The compiled metrics; none when the configuration was rejected (the pipeline never starts then).
+pub fn registry() -> &'static Registry {
+    load_registry().unwrap_or(&NO_METRICS)
+}
+
+/// Derived values of one window, in registry order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DerivedValues([Option<f32>; MAX_DERIVED]);
+
+impl DerivedValues {
+    /// (key, unit, value) per active metric, same shape as the sensor `fields()`.
+    pub fn fields(&self) -> impl Iterator<Item = (&'static str, &'static str, Option<f32>)> + '_ {
+        registry().metrics.iter().zip(self.0.iter()).map(|(m, v)| (m.key, m.unit, *v))
+    }
+}
+
+impl Serialize for DerivedValues {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut map = s.serialize_map(None)?;
+        for (key, _, v) in self.fields() {
+            map.serialize_entry(key, &v)?;
+        }
+        map.end()
+    }
+}
+
+/// Evaluate every active metric over one window's sensor means.
+pub fn evaluate(base: &[(&'static str, &'static str, Option<f32>)]) -> DerivedValues {
+    let mut out = DerivedValues::default();
+    for (i, m) in registry().metrics.iter().enumerate() {
+        let value = {
+            let done = &out;
+            let lookup = |k: &str| -> Option<f64> {
+                base.iter().find(|(bk, _, _)| *bk == k).and_then(|(_, _, v)| v.map(f64::from))
+                    .or_else(|| registry().metrics[..i].iter().position(|p| p.key == k).and_then(|j| done.0[j].map(f64::from)))
+            };
+            eval(&m.expr, &lookup)
+        };
+        out.0[i] = value.map(|v| ((v * m.scale).round() / m.scale) as f32);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(src: &'static str) -> Option<f64> {
+        let vars = |k: &str| match k { "air_temp_c" => Some(20.0), "air_rh_pct" => Some(50.0), _ => None };
+        eval(&parse(src).unwrap(), &vars)
+    }
+
+    fn def(key: &'static str, expr: &'static str) -> DerivedDef<'static> {
+        DerivedDef { key, unit: "", precision: 2, expr }
+    }
+
+    /// Every rejected key with its reason, in rejection order.
+    fn rejected(defs: &[DerivedDef<'static>]) -> Vec<(&'static str, String)> {
+        compile(defs).err().expect("the set should be rejected")
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        for (src, want) in [
+            ("1 + 2 * 3", 7.0),
+            ("(1 + 2) * 3", 9.0),
+            ("10 - 4 - 3", 3.0),
+            ("8 / 4 / 2", 1.0),
+            ("2 ^ 3 ^ 2", 512.0),  // right-associative
+            ("-2 ^ 2", -4.0),      // the power binds tighter than the sign
+            ("2 ^ -1", 0.5),
+            ("2 * -3", -6.0),
+            ("--3", 3.0),
+            ("1 + 2 * 3 ^ 2 / 6", 4.0),
+            ("min(3, max(1, 2)) + abs(-5) + sqrt(16)", 11.0),
+            ("exp(ln(7))", 7.0),
+            ("air_temp_c / 4 + air_rh_pct * .5", 30.0),
+        ] {
+            let got = value(src).unwrap();
+            assert!((got - want).abs() < 1e-9, "{src} = {got}, want {want}");
+        }
+    }
+
+    #[test]
+    fn malformed_expressions_name_the_column() {
+        for (src, want) in [
+            ("1 +", "unexpected end of expression"),
+            ("(1 + 2", "expected ')' at column 7"),
+            ("1 2", "unexpected input at column 3"),
+            ("air_temp_c $ 2", "unexpected '$' at column 12"),
+            ("1..2", "bad number '1..2' at column 1"),
+            ("foo(1)", "unknown function 'foo' at column 1"),
+            ("min(1)", "min() takes 2 argument(s), got 1 at column 1"),
+            ("abs(1, 2)", "abs() takes 1 argument(s), got 2 at column 1"),
+            ("* 2", "unexpected token at column 1"),
+            ("", "unexpected end of expression"),
+        ] {
+            assert_eq!(parse(src).err().as_deref(), Some(want), "{src}");
+        }
+    }
+
+    #[test]
+    fn missing_inputs_and_non_finite_results_are_none() {
+        assert_eq!(value("weight_g + 1"), None);
+        assert_eq!(value("ln(air_temp_c - 20)"), None);
+        assert_eq!(value("air_temp_c / 0"), None);
+        assert_eq!(value("sqrt(-1)"), None);
+    }
+
+    #[test]
+    fn metrics_are_ordered_dependencies_first() {
+        let reg = compile(&[
+            def("b", "a * 2"),
+            def("a", "air_temp_c + 1"),
+            def("c", "a + b + vpd_kpa"),
+        ]).ok().unwrap();
+        let keys: Vec<&str> = reg.metrics.iter().map(|m| m.key).collect();
+        assert_eq!(keys, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn invalid_entries_fail_the_whole_set() {
+        assert_eq!(rejected(&[def("ok", "air_temp_c + 1"), def("bad", "air_temp_c +")]), [("bad", "unexpected end of expression".to_string())]);
+        assert_eq!(rejected(&[def("x", "soil_moisture * 2")]), [("x", "unknown key 'soil_moisture'".to_string())]);
+        assert_eq!(rejected(&[def("vpd_kpa", "es_kpa - ea_air_kpa")]), [("vpd_kpa", "'vpd_kpa' is already a sensor key".to_string())]);
+        assert_eq!(rejected(&[def("x", "1"), def("x", "2")]), [("x", "defined twice".to_string())]);
+        // a metric built on a rejected one goes too
+        assert_eq!(rejected(&[def("y", "x + 1"), def("x", "foo(1)")]), [
+            ("x", "unknown function 'foo' at column 1".to_string()),
+            ("y", "depends on rejected 'x'".to_string()),
+        ]);
+        let many: Vec<DerivedDef<'static>> = ["m0", "m1", "m2", "m3", "m4", "m5", "m6", "m7", "m8"].iter().map(|k| def(k, "1")).collect();
+        assert_eq!(rejected(&many), [("m8", format!("more than {MAX_DERIVED} derived metrics configured"))]);
+    }
+
+    #[test]
+    fn cycles_are_detected() {
+        assert_eq!(rejected(&[def("a", "a + 1")]), [("a", "cycle a -> a".to_string())]);
+        assert_eq!(rejected(&[def("a", "b + 1"), def("b", "c * 2"), def("c", "a - air_temp_c")]), [
+            ("a", "cycle a -> b -> c -> a".to_string()),
+            ("b", "cycle a -> b -> c -> a".to_string()),
+            ("c", "cycle a -> b -> c -> a".to_string()),
+        ]);
+        // a metric merely downstream of a cycle is rejected for that, not as part of it
+        let r = rejected(&[def("d", "a + 1"), def("a", "b"), def("b", "a")]);
+        assert!(r.contains(&("d", "depends on rejected 'a'".to_string())), "{r:?}");
+        assert!(r.contains(&("a", "cycle a -> b -> a".to_string())), "{r:?}");
+    }
+
+    #[test]
+    fn shipped_config_compiles_and_evaluates() {
+        let reg = load_registry().unwrap();
+        assert!(reg.metrics.iter().any(|m| m.key == "dew_point_c"));
+        let values = evaluate(&[("air_temp_c", "C", Some(20.0)), ("air_rh_pct", "%", Some(50.0))]);
+        let dew = values.fields().find(|(k, _, _)| *k == "dew_point_c").and_then(|(_, _, v)| v);
+        assert_eq!(dew, Some(9.26));
+        let values = evaluate(&[("air_temp_c", "C", Some(20.0)), ("air_rh_pct", "%", None)]);
+        assert!(values.fields().all(|(_, _, v)| v.is_none()));
+    }
+}
@@ -0,0 +1,179 @@
+//! On-site node discovery: what is publishing on this broker right now, roster or not.
+//! - `discover_nodes` opens a separate connection subscribed to `node_discovery().topic_filter` for the
+//!   scan window. Frames it sees are only counted: nothing is forwarded to aggregation or storage.
+//! - The normal subscriber keeps its narrow subscriptions throughout; ending or cancelling the scan
+//!   (`cancel_node_discovery`) just closes the extra connection, so there is nothing to restore.
+//! - One entry per distinct topic: ids (from the frame, else from the topic), payload kind, message
+//!   count and the last decoded frame as a sample. `known` marks nodes already in `node_name`.
+//! - `adopt_node` (commissioning.rs) adds a discovered node to the roster.
+
+use rumqttc::{Event, Packet, QoS};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+use super::ack::ids_from_topic;
+use super::decoder::{decode_payload, Decoded};
+use super::origin::origin_of;
+use crate::services::mqtt::auth::mqtt_auth;
+use crate::services::mqtt::config::node_discovery;
+use crate::services::mqtt::core::new_client;
+use crate::services::node_maintenance::now_ms;
+
+/// Cancel checks happen at least this often while the broker is quiet.
+const POLL_SLICE: Duration = Duration::from_millis(250);
+
+/// Scan state shared with `cancel_node_discovery`; at most one scan runs at a time.
+#[derive(Debug, Default)]
+pub struct DiscoveryState {
+    running: AtomicBool,
+    cancel: AtomicBool,
+}
+
+pub type DiscoveryShared = Arc<DiscoveryState>;
+
+impl DiscoveryState {
+    /// Ask the running scan to stop; false when none is running.
+    pub fn cancel(&self) -> bool {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.running.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadKind {
+    Standard,
+    Outdoor,
+    Soil,
+    /// A node data topic whose payload does not decode.
+    Undecodable,
+    /// Anything that is not node data (acks, summaries, other devices).
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredTopic {
+    pub topic: String,
+    pub origin: String,
+    pub greenhouse_id: Option<u16>,
+    pub node_id: Option<u16>,
+    pub kind: PayloadKind,
+    pub messages: u64,
+    pub last_bytes: usize,
+    pub first_seen_ms: i64,
+    pub last_seen_ms: i64,
+    pub sample: Option<Decoded>,
+    pub decode_error: Option<&'static str>,
+    pub known: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveryReport {
+    pub started_ms: i64,
+    pub duration_secs: u64,
+    pub topic_filter: &'static str,
+    pub cancelled: bool,
+    /// Connection error that ended the scan early.
+    pub error: Option<String>,
+    pub messages: u64,
+    pub dropped_topics: u64,
+    /// Distinct (greenhouse, node) pairs heard that are not in the roster.
+    pub unknown_nodes: usize,
+    pub topics: Vec<DiscoveredTopic>,
+}
+
+impl DiscoveryReport {
+    /// Flag entries whose node is in `roster` and count the rest.
+    pub fn mark_known(&mut self, roster: &HashSet<(u16, u16)>) {
+        let mut unknown = HashSet::new();
+        for t in &mut self.topics {
+            let (Some(gh), Some(node)) = (t.greenhouse_id, t.node_id) else { continue };
+            t.known = roster.contains(&(gh, node));
+            if !t.known && t.kind != PayloadKind::Other { unknown.insert((gh, node)); }
+        }
+        self.unknown_nodes = unknown.len();
+    }
+}
+
+fn record(seen: &mut BTreeMap<String, DiscoveredTopic>, topic: &str, payload: &[u8], now: i64) -> bool {
+    let is_new = !seen.contains_key(topic);
+    if is_new && seen.len() >= node_discovery().max_topics { return false; }
+    let t = seen.entry(topic.to_string()).or_insert_with(|| {
+        let ids = topic.find("greenhouse/").and_then(|i| ids_from_topic(&topic[i..]));
+        DiscoveredTopic {
+            topic: topic.to_string(), origin: origin_of(topic).to_string(),
+            greenhouse_id: ids.map(|i| i.0), node_id: ids.map(|i| i.1), kind: PayloadKind::Other,
+            messages: 0, last_bytes: 0, first_seen_ms: now, last_seen_ms: now,
+            sample: None, decode_error: None, known: false,
+        }
+    });
+    t.messages += 1;
+    t.last_bytes = payload.len();
+    t.last_seen_ms = now;
+    if topic.ends_with("/data") {
+        match decode_payload(payload) {
+            Ok(d) => {
+                t.kind = match d {
+                    Decoded::Standard { .. } => PayloadKind::Standard,
+                    Decoded::Outdoor { .. } => PayloadKind::Outdoor,
+                    Decoded::Soil { .. } => PayloadKind::Soil,
+                };
+                (t.greenhouse_id, t.node_id) = (Some(d.ids().0), Some(d.ids().1));
+                t.sample = Some(d);
+                t.decode_error = None;
+            }
+            Err(e) => {
+                if t.sample.is_none() { t.kind = PayloadKind::Undecodable; }
+                t.decode_error = Some(e.name());
+            }
+        }
+    }
+    true
+}
+
+/// Scan for `duration_secs` (default and cap: `max_duration_secs`). `known` is left false; see `mark_known`.
+pub async fn discover(state: &DiscoveryState, duration_secs: Option<u64>) -> Result<DiscoveryReport, String> {
+    if state.running.swap(true, Ordering::Relaxed) { return Err("a discovery scan is already running".into()); }
+    state.cancel.store(false, Ordering::Relaxed);
+    let cfg = node_discovery();
+    let secs = duration_secs.unwrap_or(cfg.max_duration_secs).clamp(1, cfg.max_duration_secs);
+    let mut report = DiscoveryReport {
+        started_ms: now_ms(), duration_secs: secs, topic_filter: cfg.topic_filter, cancelled: false, error: None,
+        messages: 0, dropped_topics: 0, unknown_nodes: 0, topics: Vec::new(),
+    };
+    info!(target: "DISCOVERY", "scanning '{}' for {secs}s (frames are not aggregated or stored)", cfg.topic_filter);
+
+    let (client, mut eventloop) = new_client("discovery", &mqtt_auth());
+    if let Err(e) = client.subscribe(cfg.topic_filter, QoS::AtMostOnce).await {
+        state.running.store(false, Ordering::Relaxed);
+        return Err(format!("subscribe error: {e}"));
+    }
+    let deadline = Instant::now() + Duration::from_secs(secs);
+    let mut seen = BTreeMap::new();
+    while Instant::now() < deadline {
+        if state.cancel.load(Ordering::Relaxed) { report.cancelled = true; break; }
+        match timeout(POLL_SLICE, eventloop.poll()).await {
+            Ok(Ok(Event::Incoming(Packet::Publish(p)))) => {
+                report.messages += 1;
+                if !record(&mut seen, &p.topic, &p.payload, now_ms()) { report.dropped_topics += 1; }
+            }
+            Ok(Ok(_)) | Err(_) => {}
+            Ok(Err(e)) => {
+                warn!(target: "DISCOVERY", "connection error, scan ended early: {e}");
+                report.error = Some(e.to_string());
+                break;
+            }
+        }
+    }
+    let _ = client.try_disconnect();
+    state.running.store(false, Ordering::Relaxed);
+
+    report.topics = seen.into_values().collect();
+    info!(target: "DISCOVERY", "scan {}: {} message(s) on {} topic(s)",
+        if report.cancelled { "cancelled" } else { "done" }, report.messages, report.topics.len());
+    Ok(report)
+}
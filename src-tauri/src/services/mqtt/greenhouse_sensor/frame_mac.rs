@@ -0,0 +1,150 @@
+//! HMAC-signed node frames, against spoofed publishes on a shared farm network.
+//! - A greenhouse with a key in `frame_mac().keys_file` must append an 8-byte trailer to every publish:
+//!   the first 8 bytes of HMAC-SHA256(key, body), body = the whole frame before it (CRC trailer and
+//!   batches included). Greenhouses without a key are not checked and need no trailer.
+//! - Keyed by the greenhouse id in the topic (before origin mapping). A frame whose own greenhouse id is
+//!   keyed must have been verified with that key, so a keyed id cannot be sent on an unkeyed topic.
+//! - A missing or wrong trailer is `DecodeError::BadMac`: dropped and counted like any decode failure.
+//! - Keys are read once when the subscriber starts; edit the file and restart to rotate them.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fs;
+use tracing::{error, info};
+
+use super::decoder::{DecodeError, Decoded};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const MAC_LEN: usize = 8;
+
+#[derive(Debug, Default)]
+pub struct FrameKeys {
+    keys: HashMap<u16, Vec<u8>>,
+}
+
+impl FrameKeys {
+    /// Parse the keys file: `{"<greenhouse id>": "<hex key>", ...}`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let raw: HashMap<String, String> = serde_json::from_str(text).map_err(|e| format!("not a JSON object of hex keys: {e}"))?;
+        let mut keys = HashMap::new();
+        for (gh, key) in raw {
+            let id: u16 = gh.trim().parse().map_err(|_| format!("bad greenhouse id '{gh}'"))?;
+            let key = hex::decode(key.trim()).map_err(|_| format!("GH:{id}: key is not hex"))?;
+            if key.is_empty() { return Err(format!("GH:{id}: empty key")); }
+            keys.insert(id, key);
+        }
+        Ok(FrameKeys { keys })
+    }
+
+    /// Keys from `path`; none when the file does not exist or cannot be used (logged).
+    pub fn load(path: &str) -> Self {
+        let text = match fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return FrameKeys::default(),
+            Err(e) => {
+                error!(target: "MAC", "could not read {path}: {e}; frames are not verified");
+                return FrameKeys::default();
+            }
+        };
+        match FrameKeys::parse(&text) {
+            Ok(k) => {
+                info!(target: "MAC", "frame keys for {} greenhouse(s)", k.keys.len());
+                k
+            }
+            Err(e) => {
+                error!(target: "MAC", "{path}: {e}; frames are not verified");
+                FrameKeys::default()
+            }
+        }
+    }
+
+    pub fn is_keyed(&self, gh: u16) -> bool { self.keys.contains_key(&gh) }
+
+    /// Body of a publish on greenhouse `gh`'s topic: without its trailer when `gh` is keyed and the MAC
+    /// matches, unchanged when `gh` has no key.
+    pub fn verify<'a>(&self, gh: u16, p: &'a [u8]) -> Result<&'a [u8], DecodeError> {
+        let Some(key) = self.keys.get(&gh) else { return Ok(p) };
+        let (body, tag) = p.split_at(p.len().checked_sub(MAC_LEN).ok_or(DecodeError::BadMac)?);
+        let mut mac = HmacSha256::new_from_slice(key).map_err(|_| DecodeError::BadMac)?;
+        mac.update(body);
+        mac.verify_truncated_left(tag).map(|_| body).map_err(|_| DecodeError::BadMac)
+    }
+
+    /// A decoded frame claiming a keyed greenhouse other than the one it was verified for is rejected.
+    pub fn check_ids(&self, verified_gh: Option<u16>, d: Decoded) -> Result<Decoded, DecodeError> {
+        let gh = d.ids().0;
+        if self.is_keyed(gh) && verified_gh != Some(gh) { Err(DecodeError::BadMac) } else { Ok(d) }
+    }
+}
+
+/// `body` with its 8-byte trailer for `key` (`encode-frame --mac`, simulators).
+pub fn with_mac(mut body: Vec<u8>, key: &[u8]) -> Vec<u8> {
+    let Ok(mut mac) = HmacSha256::new_from_slice(key) else { return body }; // HMAC accepts any key length
+    mac.update(&body);
+    body.extend_from_slice(&mac.finalize().into_bytes()[..MAC_LEN]);
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 4231 test cases 1 and 2 (HMAC-SHA-256), truncated to the first 8 bytes as on the wire
+    const RFC4231: [(&str, &[u8], &str); 2] = [
+        ("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b", b"Hi There", "b0344c61d8db3853"),
+        ("4a656665", b"what do ya want for nothing?", "5bdcc146bf60754e"),
+    ];
+
+    fn keys(gh: u16, key_hex: &str) -> FrameKeys {
+        FrameKeys::parse(&format!(r#"{{"{gh}": "{key_hex}"}}"#)).unwrap()
+    }
+
+    #[test]
+    fn trailer_is_the_truncated_rfc4231_mac() {
+        for (key, data, mac) in RFC4231 {
+            let framed = with_mac(data.to_vec(), &hex::decode(key).unwrap());
+            assert_eq!(framed.len(), data.len() + MAC_LEN);
+            assert_eq!(hex::encode(&framed[data.len()..]), mac);
+            assert_eq!(keys(1, key).verify(1, &framed), Ok(data));
+        }
+    }
+
+    #[test]
+    fn wrong_key_or_damaged_trailer_is_bad_mac() {
+        let (key, data, _) = RFC4231[1];
+        let framed = with_mac(data.to_vec(), &hex::decode(key).unwrap());
+        assert_eq!(keys(1, "4a656666").verify(1, &framed), Err(DecodeError::BadMac));
+        assert_eq!(keys(1, RFC4231[0].0).verify(1, &framed), Err(DecodeError::BadMac));
+        let k = keys(1, key);
+        for i in 0..framed.len() {
+            let mut f = framed.clone();
+            f[i] ^= 0x01;
+            assert_eq!(k.verify(1, &f), Err(DecodeError::BadMac), "byte {i}");
+        }
+        assert_eq!(k.verify(1, data), Err(DecodeError::BadMac), "no trailer");
+        assert_eq!(k.verify(1, &framed[..5]), Err(DecodeError::BadMac), "shorter than a trailer");
+    }
+
+    #[test]
+    fn only_keyed_greenhouses_are_checked() {
+        let k = keys(1, RFC4231[1].0);
+        assert_eq!(k.verify(2, b"any payload"), Ok(&b"any payload"[..]));
+        let d = |gh| Decoded::Soil { greenhouse_id: gh, node_id: 9, vwc1_pct: 1.0, vwc2_pct: 1.0, vwc3_pct: 1.0, vwc4_pct: 1.0, ec_ms_cm: 1.0 };
+        // a frame claiming keyed greenhouse 1 must have been verified as greenhouse 1
+        assert!(k.check_ids(Some(1), d(1)).is_ok());
+        assert_eq!(k.check_ids(Some(2), d(1)).unwrap_err(), DecodeError::BadMac);
+        assert_eq!(k.check_ids(None, d(1)).unwrap_err(), DecodeError::BadMac);
+        assert!(k.check_ids(None, d(2)).is_ok());
+    }
+
+    #[test]
+    fn keys_file_errors() {
+        assert!(FrameKeys::parse("[]").is_err());
+        assert_eq!(FrameKeys::parse(r#"{"x": "00"}"#).unwrap_err(), "bad greenhouse id 'x'");
+        assert_eq!(FrameKeys::parse(r#"{"1": "zz"}"#).unwrap_err(), "GH:1: key is not hex");
+        assert_eq!(FrameKeys::parse(r#"{"1": ""}"#).unwrap_err(), "GH:1: empty key");
+        assert!(FrameKeys::parse(r#"{" 3 ": " 0b0b "}"#).unwrap().is_keyed(3));
+    }
+}
@@ -1,20 +1,56 @@
-//! Greenhouse-level 60s averages.
+//! Greenhouse-level averages over the configured window (60s by default, aggregator.rs).
 //! - Consumes NodeAvg (per-node snapshots).
-//! - Every 60s, averages available fields across freshest nodes.
-//! - Prints with two decimals; emits GhAvg to DB and UI.
+//! - Every window, averages available fields across freshest nodes; a node counts as fresh for
+//!   its own window length plus `stale_grace` (1/12 of the window, 5s at 60s), so the slower outdoor
+//!   aggregate is used whatever the tick phase.
+//! - Node averages flagged `maintenance` are left out (still stored per node by the DB writer).
+//! - Soil nodes are ignored (no air readings; they would only inflate the roster).
+//! - Outdoor stations are not part of the indoor means; they build the `outdoor` reference instead,
+//!   per `outdoor()` policy (primary with fallback, or a blend of two stations that agree).
+//! - Confidence: coverage is the fraction of the roster contributing. The roster is the greenhouse's
+//!   active nodes in `node_name` (`RosterShared`, loaded at startup and after roster changes) plus indoor
+//!   nodes that reported without being on it, less outdoor stations, soil nodes and nodes in maintenance,
+//!   and at least the configured expected count; a node that never reported since startup still counts.
+//!   `gh_confidence()` maps coverage to High/Medium/Low and can suppress the average entirely
+//!   (`gh_insufficient_data`). Stored per field with each row.
+//! - Key-field extremes (`Extremes`) are the lowest node min and highest node max among the nodes
+//!   averaged, i.e. the range any contributing node saw during the window.
+//! - `transpiration_g_min` is the mean of the node rates, stored after the derived metrics with the
+//!   coverage of the nodes that have one.
+//! - Prints with two decimals; emits GhAvg to DB, UI and the hourly aggregator. The DB, hourly and KPI
+//!   lanes are critical (channels.rs): a full one makes this task wait rather than lose an average.
+//! - Ticks half the grace after the node windows close on the wall-clock grid and stamps the window with
+//!   their close, so a greenhouse row and its node rows share `ts_ms`.
+//! - Keeps the latest GhAvg per greenhouse in shared state for commands and publishers.
+//! - When the node aggregator has gone (shutdown, its lane closed) one last window is emitted from the
+//!   final node averages and the task returns.
 
-use std::{collections::HashMap, time::Duration, time::SystemTime};
+use std::{collections::{HashMap, HashSet}, sync::{Arc, RwLock}, time::Duration, time::SystemTime};
 use tokio::sync::mpsc;
 use tokio::time::{Instant, interval};
+use tracing::{info, warn};
 
-use super::aggregator::NodeAvg;
+use super::aggregator::{align_tick, window_close_ms, Extremes, NodeAvg};
+use super::derived::{evaluate, DerivedValues};
+use super::nodes::outdoor_rank;
+use crate::services::channels::Lane;
+use crate::services::math::{acc_opt, fmt_opt2, mean};
+use crate::services::mqtt::config::{gh_confidence, outdoor, ConfidenceConfig, OutdoorPolicy};
+use crate::services::presenter::emitter::EventSink;
+use crate::services::shutdown::tick_or_closed;
+use crate::services::supervisor::Lease;
 
-const WINDOW: Duration = Duration::from_secs(60);
-const STALE_GRACE: Duration = Duration::from_secs(5); // include node avgs if <= 65s old
+/// How much older than its own window a node average may be and still count (5s at 60s).
+pub fn stale_grace(window: Duration) -> Duration {
+    window / 12
+}
 
 #[derive(Debug, Clone, Copy, serde::Serialize)]
 pub struct GhAvg {
-    pub ts_ms: i64,           // wall clock ms for UI
+    pub ts_ms: i64,           // wall clock at window close, the ts_ms of the node windows it averages
+    pub window_start_ms: i64, // ts_ms - window
+    pub window_sec: u32,      // the aggregation window
+    pub window_seq: u64,      // per-task window counter; (start, seq) identifies the window in storage
     pub greenhouse_id: u16,
     pub air_temp_c: Option<f32>,
     pub leaf_temp_c: Option<f32>,
@@ -31,87 +67,325 @@ pub struct GhAvg {
     pub ea_leaf_kpa: Option<f32>,
     pub es_kpa: Option<f32>,
     pub vpd_kpa: Option<f32>,
+    pub leaf_air_dt_c: Option<f32>, // mean of per-node leaf-air deltas (nodes with both sensors)
+    pub transpiration_g_min: Option<f32>, // mean of the node rates (standard nodes with a load cell)
+    #[serde(flatten)]
+    pub extremes: Extremes,         // min of node mins, max of node maxes
+    #[serde(flatten)]
+    pub derived: DerivedValues,     // evaluated on the greenhouse means, not averaged from nodes
     pub nodes: usize,
+    pub outdoor: Option<OutdoorRef>, // None when no outdoor station is fresh
+    pub roster: usize,
+    pub coverage: f32,               // nodes / roster
+    pub confidence: Confidence,
+    #[serde(skip)]
+    pub field_nodes: [u16; 16],      // contributing nodes per sensor field, in `fields()` order
+    #[serde(skip)]
+    pub transpiration_nodes: u16,    // nodes behind `transpiration_g_min`
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    pub fn from_coverage(coverage: f32) -> Self {
+        Self::graded(coverage, &gh_confidence())
+    }
+
+    fn graded(coverage: f32, cfg: &ConfidenceConfig) -> Self {
+        if coverage >= cfg.high_min_coverage { Confidence::High }
+        else if coverage >= cfg.medium_min_coverage { Confidence::Medium }
+        else { Confidence::Low }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutdoorSource {
+    Primary,  // the highest-priority station this greenhouse has reported
+    Fallback, // a lower-priority station while the preferred one is stale
+    Blend,    // mean of `node_id` and `blended_with`
 }
 
+/// Greenhouse outdoor reference and the station(s) it came from.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct OutdoorRef {
+    pub source: OutdoorSource,
+    pub node_id: u16,
+    pub blended_with: Option<u16>,
+    /// Two fresh stations differ by more than the configured tolerance.
+    pub disagree: bool,
+    pub air_temp_c: Option<f32>,
+    pub air_rh_pct: Option<f32>,
+    pub par_value: Option<f32>,
+    pub ea_air_kpa: Option<f32>,
+    pub es_kpa: Option<f32>,
+}
+
+/// True when both values exist and are within `tol`; a missing side counts as agreeing.
+fn within(a: Option<f32>, b: Option<f32>, tol: f32) -> bool {
+    match (a, b) {
+        (Some(x), Some(y)) => (x - y).abs() <= tol,
+        _ => true,
+    }
+}
+
+fn blend(a: Option<f32>, b: Option<f32>) -> Option<f32> {
+    match (a, b) {
+        (Some(x), Some(y)) => Some((x + y) / 2.0),
+        (x, y) => x.or(y),
+    }
+}
+
+/// Outdoor reference from fresh outdoor stations (sorted by priority); `preferred` is the best-ranked known station.
+fn outdoor_ref(stations: &[&NodeAvg], preferred: u16) -> Option<OutdoorRef> {
+    let cfg = outdoor();
+    let first = stations.first()?;
+    let second = stations.get(1);
+    let disagree = second.is_some_and(|b| {
+        !within(first.air_temp_c, b.air_temp_c, cfg.agree_air_temp_c) || !within(first.air_rh_pct, b.air_rh_pct, cfg.agree_air_rh_pct)
+    });
+    let single = OutdoorRef {
+        source: if first.node_id == preferred { OutdoorSource::Primary } else { OutdoorSource::Fallback },
+        node_id: first.node_id,
+        blended_with: None,
+        disagree,
+        air_temp_c: first.air_temp_c,
+        air_rh_pct: first.air_rh_pct,
+        par_value: first.par_value,
+        ea_air_kpa: first.ea_air_kpa,
+        es_kpa: first.es_kpa,
+    };
+    match second {
+        Some(b) if cfg.policy == OutdoorPolicy::BlendWhenAgree && !disagree => Some(OutdoorRef {
+            source: OutdoorSource::Blend,
+            blended_with: Some(b.node_id),
+            air_temp_c: blend(first.air_temp_c, b.air_temp_c),
+            air_rh_pct: blend(first.air_rh_pct, b.air_rh_pct),
+            par_value: blend(first.par_value, b.par_value),
+            ea_air_kpa: blend(first.ea_air_kpa, b.ea_air_kpa),
+            es_kpa: blend(first.es_kpa, b.es_kpa),
+            ..single
+        }),
+        _ => Some(single),
+    }
+}
+
+impl GhAvg {
+    /// Sensor fields plus configured derived metrics (and transpiration when a node has it), in storage order.
+    pub fn fields(&self) -> Vec<(&'static str, &'static str, Option<f32>)> {
+        let mut f = self.base_fields().to_vec();
+        f.extend(self.derived.fields());
+        if let Some(v) = self.transpiration_g_min { f.push(("transpiration_g_min", "g/min", Some(v))); }
+        f
+    }
+
+    /// Roster coverage per entry of `fields()`; derived metrics use the overall coverage.
+    pub fn field_coverage(&self) -> Vec<f32> {
+        let roster = self.roster.max(1) as f32;
+        self.field_nodes.iter().map(|&n| n as f32 / roster)
+            .chain(self.derived.fields().map(|_| self.coverage))
+            .chain(self.transpiration_g_min.map(|_| self.transpiration_nodes as f32 / roster))
+            .collect()
+    }
+
+    /// (sensor key, unit, value) for every averaged sensor field.
+    fn base_fields(&self) -> [(&'static str, &'static str, Option<f32>); 16] {
+        [
+            ("air_temp_c", "C", self.air_temp_c),
+            ("leaf_temp_c", "C", self.leaf_temp_c),
+            ("bag_temp_c", "C", self.bag_temp_c),
+            ("air_rh_pct", "%", self.air_rh_pct),
+            ("bag_rh1_pct", "%", self.bag_rh1_pct),
+            ("bag_rh2_pct", "%", self.bag_rh2_pct),
+            ("bag_rh3_pct", "%", self.bag_rh3_pct),
+            ("bag_rh4_pct", "%", self.bag_rh4_pct),
+            ("bag_rh_avg_pct", "%", self.bag_rh_avg_pct),
+            ("par_value", "", self.par_value),
+            ("weight_g", "", self.weight_g),
+            ("ea_air_kpa", "kPa", self.ea_air_kpa),
+            ("ea_leaf_kpa", "kPa", self.ea_leaf_kpa),
+            ("es_kpa", "kPa", self.es_kpa),
+            ("vpd_kpa", "kPa", self.vpd_kpa),
+            ("leaf_air_dt_c", "C", self.leaf_air_dt_c),
+        ]
+    }
+}
+
+/// Latest GhAvg per greenhouse (written by the aggregator, read by commands/publishers).
+pub type LatestGhShared = Arc<RwLock<HashMap<u16, GhAvg>>>;
+
 #[inline] fn now_ms() -> i64 {
     SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as i64
 }
 
-#[inline] fn mean(sum: f64, cnt: u32) -> Option<f32> {
-    if cnt == 0 { None } else { Some((sum / (cnt as f64)) as f32) }
-}
-#[inline] fn acc_opt(v: Option<f32>, sum: &mut f64, cnt: &mut u32) {
-    if let Some(x) = v { let y = x as f64; if y.is_finite() { *sum += y; *cnt += 1; } }
+
+/// Active nodes in `node_name` per greenhouse, outdoor stations left out (`load_roster` in commissioning.rs).
+pub type RosterShared = Arc<RwLock<HashMap<u16, HashSet<u16>>>>;
+
+pub fn replace_roster(cache: &RosterShared, roster: HashMap<u16, HashSet<u16>>) {
+    if let Ok(mut m) = cache.write() { *m = roster; }
 }
-#[inline] fn round2(v: f32) -> f32 { (v * 100.0).round() / 100.0 }
-fn fmt_opt2(v: Option<f32>, unit: &str) -> String {
-    match v.map(round2) {
-        Some(x) if x.is_finite() => {
-            if unit.is_empty() { format!("Some({:.2})", x) }
-            else { format!("Some({:.2}){}", x, unit) }
+
+/// `soil`: nodes that sent soil windows since startup, never part of the air roster.
+struct GHState { nodes: HashMap<u16, NodeAvg>, soil: HashSet<u16>, outdoor_disagree: bool }
+impl GHState { fn new() -> Self { Self { nodes: HashMap::new(), soil: HashSet::new(), outdoor_disagree: false } } }
+
+impl GHState {
+    /// Indoor nodes expected to contribute: the persisted roster and every indoor node that reported,
+    /// less those known to be outdoor, soil or in maintenance; at least the configured count.
+    fn roster(&self, gh_id: u16, persisted: Option<&HashSet<u16>>) -> usize {
+        let mut ids: HashSet<u16> = persisted.into_iter().flatten().copied().filter(|id| !self.soil.contains(id)).collect();
+        for v in self.nodes.values() {
+            if v.outdoor || v.maintenance { ids.remove(&v.node_id); } else { ids.insert(v.node_id); }
         }
-        _ => "None".to_string(),
+        let expected = gh_confidence().expected_nodes.iter().find(|(id, _)| *id == gh_id).map_or(0, |e| e.1);
+        ids.len().max(expected)
+    }
+}
+
+/// Coverage and confidence of `nodes` fresh nodes out of `roster`; None, after a `gh_insufficient_data`
+/// event, when `cfg` suppresses the average.
+fn rate<S: EventSink>(sink: &S, cfg: &ConfidenceConfig, gh_id: u16, ts_ms: i64, nodes: usize, roster: usize) -> Option<(f32, Confidence)> {
+    let coverage = nodes as f32 / roster.max(1) as f32;
+    if cfg.suppress_below.is_some_and(|min| coverage < min) {
+        info!(target: "GH-AVG-60s", "GH:{} | {}/{} nodes fresh; average suppressed", gh_id, nodes, roster);
+        sink.emit_json("gh_insufficient_data", serde_json::json!({
+            "ts_ms": ts_ms, "greenhouse_id": gh_id, "nodes": nodes, "roster": roster, "coverage": coverage,
+        }));
+        return None;
     }
+    Some((coverage, Confidence::graded(coverage, cfg)))
 }
 
-struct GHState { nodes: HashMap<u16, NodeAvg> }
-impl GHState { fn new() -> Self { Self { nodes: HashMap::new() } } }
+/// Where the greenhouse aggregator's windows go.
+#[derive(Clone)]
+pub struct GhAvgOutputs {
+    pub db: Lane<GhAvg>,
+    pub ui: Lane<GhAvg>,
+    pub hourly: Lane<GhAvg>,
+    pub kpi: Lane<GhAvg>,
+    /// latest GhAvg per greenhouse
+    pub latest: LatestGhShared,
+}
 
-pub async fn run_greenhouse_avg(
-    mut rx_nodeavg: mpsc::Receiver<NodeAvg>,
-    tx_ghavg_db: mpsc::Sender<GhAvg>,
-    tx_ghavg_ui: mpsc::Sender<GhAvg>,
+/// Public task on the node aggregator's `window`; `sink` receives `gh_insufficient_data` when coverage
+/// of the roster (`rosters`) is below `gh_confidence().suppress_below`.
+pub async fn run_greenhouse_avg<S: EventSink>(
+    mut rx_nodeavg: Lease<mpsc::Receiver<NodeAvg>>,
+    window: Duration,
+    out: GhAvgOutputs,
+    rosters: RosterShared,
+    sink: S,
 ) {
+    let GhAvgOutputs { db: tx_ghavg_db, ui: tx_ghavg_ui, hourly: tx_ghavg_hourly, kpi: tx_ghavg_kpi, latest } = out;
+    let grace = stale_grace(window);
     let mut gh: HashMap<u16, GHState> = HashMap::new();
-    let mut tick = interval(WINDOW);
-    tick.tick().await;
+    let mut tick = interval(window);
+    // half the grace after the node windows close, so the ones closing on the same grid tick are in
+    let offset = grace / 2;
+    align_tick(&mut tick, window, offset);
+    let mut window_seq: u64 = 0;
+    let mut closed = false; // node aggregator gone: one last greenhouse window from its final node windows
 
     loop {
         tokio::select! {
-            Some(na) = rx_nodeavg.recv() => {
-                gh.entry(na.greenhouse_id).or_insert_with(GHState::new)
-                  .nodes.insert(na.node_id, na);
+            maybe_na = rx_nodeavg.recv(), if !closed => {
+                let Some(na) = maybe_na else { closed = true; continue };
+                let st = gh.entry(na.greenhouse_id).or_insert_with(GHState::new);
+                // soil nodes have no air readings
+                if na.soil.is_some() { st.soil.insert(na.node_id); } else { st.nodes.insert(na.node_id, na); }
             }
-            _ = tick.tick() => {
+            last = tick_or_closed(&mut tick, closed) => {
                 let now = Instant::now();
-                for (gh_id, st) in gh.iter() {
+                // the node windows' close (same grid), so a greenhouse row shares its node rows' timestamp
+                let ts_ms = if last { now_ms() } else { window_close_ms(now_ms() - offset.as_millis() as i64, window) };
+                if !last { align_tick(&mut tick, window, offset); }
+                window_seq += 1;
+                for (gh_id, st) in gh.iter_mut() {
                     let fresh: Vec<&NodeAvg> = st.nodes.values()
-                        .filter(|v| now.duration_since(v.at) <= WINDOW + STALE_GRACE)
+                        // each node is fresh for its own window (outdoor stations run longer windows)
+                        .filter(|v| now.duration_since(v.at) <= Duration::from_secs(v.window_sec as u64) + grace)
                         .collect();
+                    let in_maint = fresh.iter().filter(|v| v.maintenance).count();
+                    let (mut stations, fresh): (Vec<&NodeAvg>, Vec<&NodeAvg>) =
+                        fresh.into_iter().filter(|v| !v.maintenance).partition(|v| v.outdoor);
+                    stations.sort_by_key(|v| outdoor_rank(v.node_id));
+                    let preferred = st.nodes.values().filter(|v| v.outdoor).map(|v| v.node_id).min_by_key(|&id| outdoor_rank(id));
+                    let outdoor = preferred.and_then(|p| outdoor_ref(&stations, p));
+                    let disagree = outdoor.is_some_and(|o| o.disagree);
+                    if disagree != st.outdoor_disagree {
+                        if disagree {
+                            warn!(
+                                target: "GH-AVG-60s",
+                                "GH:{} | outdoor stations {} and {} disagree (air {:?} vs {:?}); not blending",
+                                gh_id, stations[0].node_id, stations[1].node_id, stations[0].air_temp_c, stations[1].air_temp_c,
+                            );
+                        } else {
+                            info!(target: "GH-AVG-60s", "GH:{} | outdoor stations agree again", gh_id);
+                        }
+                        st.outdoor_disagree = disagree;
+                    }
                     let n_nodes = fresh.len();
                     if n_nodes == 0 {
-                        println!("[GH-AVG-60s] GH:{} | No fresh node averages (last 60s)", gh_id);
+                        if in_maint > 0 {
+                            info!(target: "GH-AVG-60s", "GH:{} | No fresh node averages outside maintenance ({} in maintenance)", gh_id, in_maint);
+                        } else {
+                            info!(target: "GH-AVG-60s", "GH:{} | No fresh node averages (last {}s)", gh_id, window.as_secs());
+                        }
                         continue;
                     }
 
+                    let roster = st.roster(*gh_id, rosters.read().ok().as_ref().and_then(|r| r.get(gh_id))).max(n_nodes);
+                    let Some((coverage, confidence)) = rate(&sink, &gh_confidence(), *gh_id, ts_ms, n_nodes, roster) else { continue };
+
+                    let mut field_nodes = [0u16; 16];
                     macro_rules! acc_field {
-                        ($getter:ident) => {{
+                        ($getter:ident, $i:expr) => {{
                             let (mut s, mut c) = (0.0f64, 0u32);
                             for v in &fresh { acc_opt(v.$getter, &mut s, &mut c); }
+                            field_nodes[$i] = c as u16;
                             mean(s, c)
                         }};
                     }
 
-                    let air_temp_c     = acc_field!(air_temp_c);
-                    let leaf_temp_c    = acc_field!(leaf_temp_c);
-                    let bag_temp_c     = acc_field!(bag_temp_c);
-                    let air_rh_pct     = acc_field!(air_rh_pct);
-                    let bag_rh1_pct    = acc_field!(bag_rh1_pct);
-                    let bag_rh2_pct    = acc_field!(bag_rh2_pct);
-                    let bag_rh3_pct    = acc_field!(bag_rh3_pct);
-                    let bag_rh4_pct    = acc_field!(bag_rh4_pct);
-                    let bag_rh_avg_pct = acc_field!(bag_rh_avg_pct);
-                    let par_value      = acc_field!(par_value);
-                    let weight_g       = acc_field!(weight_g);
-                    let ea_air_kpa     = acc_field!(ea_air_kpa);
-                    let ea_leaf_kpa    = acc_field!(ea_leaf_kpa);
-                    let es_kpa         = acc_field!(es_kpa);
-                    let vpd_kpa        = acc_field!(vpd_kpa);
-
-                    println!(
-                        "[GH-AVG-60s] GH:{} | Nodes:{} | Air:{} | Leaf:{} | Bag:{} | RH:{} | BRH1:{} | BRH2:{} | BRH3:{} | BRH4:{} | BRH_avg:{} | PAR:{} | W:{} | Ea_air:{} | Ea_leaf:{} | Es:{} | VPD:{}",
-                        gh_id, n_nodes,
+                    let air_temp_c     = acc_field!(air_temp_c, 0);
+                    let leaf_temp_c    = acc_field!(leaf_temp_c, 1);
+                    let bag_temp_c     = acc_field!(bag_temp_c, 2);
+                    let air_rh_pct     = acc_field!(air_rh_pct, 3);
+                    let bag_rh1_pct    = acc_field!(bag_rh1_pct, 4);
+                    let bag_rh2_pct    = acc_field!(bag_rh2_pct, 5);
+                    let bag_rh3_pct    = acc_field!(bag_rh3_pct, 6);
+                    let bag_rh4_pct    = acc_field!(bag_rh4_pct, 7);
+                    let bag_rh_avg_pct = acc_field!(bag_rh_avg_pct, 8);
+                    let par_value      = acc_field!(par_value, 9);
+                    let weight_g       = acc_field!(weight_g, 10);
+                    let ea_air_kpa     = acc_field!(ea_air_kpa, 11);
+                    let ea_leaf_kpa    = acc_field!(ea_leaf_kpa, 12);
+                    let es_kpa         = acc_field!(es_kpa, 13);
+                    let vpd_kpa        = acc_field!(vpd_kpa, 14);
+                    let leaf_air_dt_c  = acc_field!(leaf_air_dt_c, 15);
+                    let extremes = fresh.iter().fold(Extremes::default(), |e, v| e.merge(&v.extremes));
+                    let (mut tr_s, mut tr_c) = (0.0f64, 0u32);
+                    for v in &fresh { acc_opt(v.transpiration_g_min, &mut tr_s, &mut tr_c); }
+
+                    info!(
+                        target: "GH-AVG-60s",
+                        "GH:{} | Nodes:{}/{} ({}) | Air:{} | Leaf:{} | Bag:{} | RH:{} | BRH1:{} | BRH2:{} | BRH3:{} | BRH4:{} | BRH_avg:{} | PAR:{} | W:{} | Ea_air:{} | Ea_leaf:{} | Es:{} | VPD:{} | dT_leaf-air:{}",
+                        gh_id, n_nodes, roster, confidence.as_str(),
                         fmt_opt2(air_temp_c, "C"),
                         fmt_opt2(leaf_temp_c, "C"),
                         fmt_opt2(bag_temp_c, "C"),
@@ -127,21 +401,191 @@ pub async fn run_greenhouse_avg(
                         fmt_opt2(ea_leaf_kpa, "kPa"),
                         fmt_opt2(es_kpa, "kPa"),
                         fmt_opt2(vpd_kpa, "kPa"),
+                        fmt_opt2(leaf_air_dt_c, "C"),
                     );
 
-                    let ts_ms = now_ms();
                     let ga = GhAvg {
                         ts_ms,
+                        window_start_ms: ts_ms - window.as_millis() as i64,
+                        window_sec: window.as_secs() as u32,
+                        window_seq,
                         greenhouse_id: *gh_id,
                         air_temp_c, leaf_temp_c, bag_temp_c, air_rh_pct,
                         bag_rh1_pct, bag_rh2_pct, bag_rh3_pct, bag_rh4_pct, bag_rh_avg_pct,
-                        par_value, weight_g, ea_air_kpa, ea_leaf_kpa, es_kpa, vpd_kpa,
+                        par_value, weight_g, ea_air_kpa, ea_leaf_kpa, es_kpa, vpd_kpa, leaf_air_dt_c, extremes,
+                        transpiration_g_min: mean(tr_s, tr_c), transpiration_nodes: tr_c as u16,
+                        derived: DerivedValues::default(),
                         nodes: n_nodes,
+                        outdoor,
+                        roster, coverage, confidence, field_nodes,
                     };
-                    let _ = tx_ghavg_db.try_send(ga);
-                    let _ = tx_ghavg_ui.try_send(ga);
+                    let ga = GhAvg { derived: evaluate(&ga.base_fields()), ..ga };
+                    if let Ok(mut l) = latest.write() { l.insert(*gh_id, ga); }
+                    tx_ghavg_db.send(ga).await;
+                    tx_ghavg_ui.send(ga).await;
+                    tx_ghavg_hourly.send(ga).await;
+                    tx_ghavg_kpi.send(ga).await;
                 }
+                if last { return; }
             }
         }
     }
 }
+
+#[cfg(test)]
+impl GhAvg {
+    /// A 60 s window of three standard nodes closing at `ts_ms`, every reading set.
+    pub(crate) fn sample(greenhouse_id: u16, ts_ms: i64) -> Self {
+        GhAvg {
+            ts_ms,
+            window_start_ms: ts_ms - 60_000,
+            window_sec: 60,
+            window_seq: 1,
+            greenhouse_id,
+            air_temp_c: Some(24.0), leaf_temp_c: Some(23.0), bag_temp_c: Some(21.0), air_rh_pct: Some(70.0),
+            bag_rh1_pct: Some(80.0), bag_rh2_pct: Some(80.0), bag_rh3_pct: Some(80.0), bag_rh4_pct: Some(80.0),
+            bag_rh_avg_pct: Some(80.0), par_value: Some(400.0), weight_g: Some(12_000.0),
+            ea_air_kpa: Some(2.1), ea_leaf_kpa: Some(2.4), es_kpa: Some(3.0), vpd_kpa: Some(0.9),
+            leaf_air_dt_c: Some(-1.0), transpiration_g_min: None, transpiration_nodes: 0,
+            extremes: Extremes::default(),
+            derived: DerivedValues::default(),
+            nodes: 3,
+            outdoor: None,
+            roster: 3, coverage: 1.0, confidence: Confidence::High, field_nodes: [3; 16],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::aggregator::SoilAvg;
+    use crate::services::channels::{lane, Priority};
+    use crate::services::presenter::emitter::RecordingSink;
+    use crate::services::supervisor::Slot;
+
+    /// Runs the greenhouse aggregator over `nodes` until their channel closes; the last window of every
+    /// greenhouse, and the events it emitted.
+    async fn gh_windows(nodes: Vec<NodeAvg>) -> (HashMap<u16, GhAvg>, RecordingSink) {
+        gh_windows_on(nodes, Default::default()).await
+    }
+
+    /// `gh_windows` against a stored roster.
+    async fn gh_windows_on(nodes: Vec<NodeAvg>, rosters: RosterShared) -> (HashMap<u16, GhAvg>, RecordingSink) {
+        let lanes = Default::default();
+        let (tx, rx) = mpsc::channel(nodes.len().max(1));
+        for na in nodes { tx.send(na).await.unwrap(); }
+        drop(tx);
+        let (db, mut db_rx) = lane(&lanes, "gh_db", Priority::Droppable, 16);
+        let out = GhAvgOutputs {
+            db,
+            ui: lane(&lanes, "gh_ui", Priority::Droppable, 16).0,
+            hourly: lane(&lanes, "gh_hourly", Priority::Droppable, 16).0,
+            kpi: lane(&lanes, "gh_kpi", Priority::Droppable, 16).0,
+            latest: Default::default(),
+        };
+        let sink = RecordingSink::default();
+        run_greenhouse_avg(Slot::new(rx).lease().unwrap(), Duration::from_secs(60), out, rosters, sink.clone()).await;
+        let mut last = HashMap::new();
+        while let Ok(ga) = db_rx.try_recv() { last.insert(ga.greenhouse_id, ga); }
+        (last, sink)
+    }
+
+    #[tokio::test]
+    async fn soil_nodes_stay_out_of_the_air_averages() {
+        let t = now_ms();
+        let mut soil = NodeAvg::sample(1, 9, t, 99.0); // a soil window never carries air readings; if it did
+        soil.soil = Some(SoilAvg {
+            vwc1_pct: Some(35.0), vwc2_pct: Some(36.0), vwc3_pct: None, vwc4_pct: None, ec_ms_cm: Some(2.1),
+        });
+        let (w, _) = gh_windows(vec![NodeAvg::sample(1, 2, t, 20.0), soil, NodeAvg::sample(1, 3, t, 22.0)]).await;
+        let ga = w[&1];
+        assert_eq!(ga.air_temp_c, Some(21.0));
+        assert_eq!((ga.nodes, ga.roster), (2, 2));
+        // a greenhouse with soil nodes only has no greenhouse window at all
+        let (w, _) = gh_windows(vec![NodeAvg { greenhouse_id: 2, ..soil }]).await;
+        assert!(w.is_empty());
+    }
+
+    #[tokio::test]
+    async fn leaf_air_delta_averages_the_nodes_with_a_leaf_sensor() {
+        let t = now_ms();
+        let with_leaf = |node, air: f32, leaf: f32| NodeAvg {
+            leaf_temp_c: Some(leaf), leaf_air_dt_c: Some(leaf - air), ..NodeAvg::sample(1, node, t, air)
+        };
+        // an outdoor station has no leaf sensor: leaf and delta stay None and it must not drag the mean
+        let outdoor = NodeAvg { outdoor: true, ..NodeAvg::sample(1, 65001, t, 10.0) };
+        let (w, _) = gh_windows(vec![with_leaf(2, 22.0, 23.0), outdoor, with_leaf(3, 24.0, 27.0), NodeAvg::sample(1, 4, t, 23.0)]).await;
+        let ga = w[&1];
+        assert_eq!(ga.leaf_air_dt_c, Some(2.0));
+        assert_eq!(ga.leaf_temp_c, Some(25.0));
+
+        // no node with a leaf sensor: no delta at all
+        let (w, _) = gh_windows(vec![NodeAvg::sample(2, 2, t, 22.0), NodeAvg { greenhouse_id: 2, ..outdoor }]).await;
+        assert_eq!((w[&2].leaf_temp_c, w[&2].leaf_air_dt_c), (None, None));
+    }
+
+    #[tokio::test]
+    async fn transpiration_is_the_mean_of_the_nodes_with_a_rate() {
+        let t = now_ms();
+        let rate = |node, r| NodeAvg { transpiration_g_min: Some(r), ..NodeAvg::sample(1, node, t, 22.0) };
+        let (w, _) = gh_windows(vec![rate(2, 3.0), rate(3, 5.0), NodeAvg::sample(1, 4, t, 22.0)]).await;
+        let ga = w[&1];
+        assert_eq!((ga.transpiration_g_min, ga.transpiration_nodes, ga.nodes), (Some(4.0), 2, 3));
+        assert!(ga.fields().into_iter().any(|(key, unit, v)| key == "transpiration_g_min" && unit == "g/min" && v == Some(4.0)));
+
+        let (w, _) = gh_windows(vec![NodeAvg::sample(2, 2, t, 22.0)]).await;
+        assert_eq!(w[&2].transpiration_g_min, None);
+        assert!(!w[&2].fields().into_iter().any(|(key, ..)| key == "transpiration_g_min"));
+    }
+
+    fn cutoffs(suppress_below: Option<f32>) -> ConfidenceConfig {
+        ConfidenceConfig { high_min_coverage: 0.75, medium_min_coverage: 0.5, suppress_below, expected_nodes: &[] }
+    }
+
+    #[test]
+    fn confidence_follows_the_coverage_cutoffs() {
+        let cfg = cutoffs(None);
+        let graded: Vec<_> = [1.0, 0.75, 0.74, 0.5, 0.49, 0.0].into_iter().map(|c| Confidence::graded(c, &cfg)).collect();
+        use Confidence::*;
+        assert_eq!(graded, [High, High, Medium, Medium, Low, Low]);
+    }
+
+    #[test]
+    fn low_coverage_is_suppressed_with_an_event() {
+        let sink = RecordingSink::default();
+        let cfg = cutoffs(Some(0.4));
+        assert_eq!(rate(&sink, &cfg, 1, 60_000, 1, 4), None);
+        assert_eq!(rate(&sink, &cfg, 1, 60_000, 2, 4), Some((0.5, Confidence::Medium)));
+        let events = sink.0.lock().unwrap();
+        assert_eq!(events.len(), 1, "{events:?}");
+        assert_eq!(events[0].0, "gh_insufficient_data");
+        assert_eq!(events[0].1, serde_json::json!({ "ts_ms": 60_000, "greenhouse_id": 1, "nodes": 1, "roster": 4, "coverage": 0.25 }));
+        // without a floor every window is published, however thin
+        assert_eq!(rate(&sink, &cutoffs(None), 1, 60_000, 1, 12).map(|r| r.1), Some(Confidence::Low));
+    }
+
+    #[tokio::test]
+    async fn the_stored_roster_counts_nodes_that_have_not_reported() {
+        let t = now_ms();
+        let rosters = RosterShared::default();
+        replace_roster(&rosters, HashMap::from([(1, HashSet::from([1, 2, 3, 4, 9]))]));
+        let mut soil = NodeAvg::sample(1, 9, t, 0.0);
+        soil.soil = Some(SoilAvg { vwc1_pct: Some(35.0), vwc2_pct: None, vwc3_pct: None, vwc4_pct: None, ec_ms_cm: None });
+        // 3 and 4 are silent, 9 turns out to be a soil node
+        let (w, _) = gh_windows_on(vec![NodeAvg::sample(1, 1, t, 20.0), NodeAvg::sample(1, 2, t, 22.0), soil], rosters.clone()).await;
+        let ga = w[&1];
+        assert_eq!((ga.nodes, ga.roster, ga.coverage, ga.confidence), (2, 4, 0.5, Confidence::Medium));
+
+        // a node missing from the roster still counts, one in maintenance does not, nor does an outdoor
+        // station; 9 does again until this run hears from it (1, 2, 4, 7, 9)
+        let (w, _) = gh_windows_on(vec![
+            NodeAvg::sample(1, 1, t, 20.0), NodeAvg::sample(1, 7, t, 21.0),
+            NodeAvg { maintenance: true, ..NodeAvg::sample(1, 3, t, 30.0) },
+            NodeAvg { outdoor: true, ..NodeAvg::sample(1, 65001, t, 10.0) },
+        ], rosters).await;
+        let ga = w[&1];
+        assert_eq!((ga.nodes, ga.roster, ga.confidence), (2, 5, Confidence::Low));
+        assert_eq!(ga.coverage, 0.4);
+    }
+}
@@ -0,0 +1,322 @@
+//! Greenhouse-level hourly aggregates for the "last 24h" dashboard strip.
+//! - Consumes GhAvg (greenhouse window averages) and folds them into a running mean/min/max per field.
+//! - Hour boundaries follow the local timezone of the machine the app runs on (DST-safe);
+//!   open hours are closed by the shared local-time scheduler at every :00, even if GhAvg stops.
+//!   There is no separate site timezone: each installation serves one site and its PC must be set
+//!   to that site's timezone (reports, exports and the VPD KPI days assume the same). A DST switch
+//!   gives a 23 or 25 hour day; the repeated autumn hour is two separate hourly rows.
+//! - When an hour closes, emits GhHourly to DB and UI and keeps the recent ones in RAM
+//!   so `get_recent_hourly` never has to touch the DB. The open hour so far is kept there too
+//!   (`HourlyStore::running`), refreshed with every GhAvg, for readers that want today up to now.
+//! - Hours only move forward: a GhAvg stamped before the open hour (wall clock set back, see clock.rs)
+//!   is not folded, so an hour that already closed is never reopened or published twice.
+
+use std::{collections::{BTreeMap, HashMap, VecDeque}, sync::{Arc, RwLock}, time::SystemTime};
+use chrono::{Local, TimeZone, Timelike};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use super::greenhouse_aggregator::GhAvg;
+use crate::services::math::Stats;
+use crate::services::scheduler::{Schedule, Scheduler};
+use crate::services::supervisor::Lease;
+
+const HOUR_MS: i64 = 3_600_000;
+const KEEP_HOURS: usize = 48; // per greenhouse, enough for the 24h strip plus headroom
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+/// Start of the local-time hour containing `ts_ms` (Unix ms).
+/// Works on local minutes/seconds, so half-hour offsets and DST shifts stay correct.
+pub fn local_hour_start(ts_ms: i64) -> i64 {
+    hour_start_in(&Local, ts_ms)
+}
+
+/// Start of the local day containing `ts_ms` (earliest instant if midnight repeats,
+/// the end of the gap if midnight falls in a DST gap).
+pub fn local_day_start(ts_ms: i64) -> i64 {
+    day_start_in(&Local, ts_ms)
+}
+
+/// `local_hour_start` in `tz`.
+fn hour_start_in<Tz: TimeZone>(tz: &Tz, ts_ms: i64) -> i64 {
+    match tz.timestamp_millis_opt(ts_ms).single() {
+        Some(dt) => ts_ms - (dt.minute() as i64 * 60 + dt.second() as i64) * 1000 - ts_ms.rem_euclid(1000),
+        None => ts_ms - ts_ms.rem_euclid(HOUR_MS),
+    }
+}
+
+/// `local_day_start` in `tz`.
+fn day_start_in<Tz: TimeZone>(tz: &Tz, ts_ms: i64) -> i64 {
+    let Some(naive) = tz.timestamp_millis_opt(ts_ms).single()
+        .and_then(|t| t.date_naive().and_hms_opt(0, 0, 0)) else { return hour_start_in(tz, ts_ms) };
+    tz.from_local_datetime(&naive).earliest()
+        .or_else(|| tz.from_local_datetime(&(naive + chrono::Duration::hours(1))).earliest())
+        .map_or_else(|| hour_start_in(tz, ts_ms), |t| t.timestamp_millis())
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HourStat {
+    pub unit: String,
+    pub mean: Option<f32>,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+}
+
+/// One closed local hour for one greenhouse.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GhHourly {
+    pub hour_start_ms: i64,
+    pub greenhouse_id: u16,
+    pub nodes: usize, // max contributing nodes seen during the hour
+    pub fields: BTreeMap<String, HourStat>,
+}
+
+#[derive(Debug)]
+struct FieldAcc { unit: &'static str, stats: Stats }
+
+impl FieldAcc {
+    fn new(unit: &'static str) -> Self { Self { unit, stats: Stats::new() } }
+    fn push(&mut self, v: f32) { self.stats.add(v); }
+    fn stat(&self) -> HourStat {
+        HourStat {
+            unit: self.unit.to_string(),
+            mean: self.stats.mean(),
+            min: self.stats.min(),
+            max: self.stats.max(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct HourBucket {
+    hour_start_ms: i64,
+    nodes: usize,
+    acc: BTreeMap<&'static str, FieldAcc>,
+    set_back: bool, // skipped a GhAvg from an earlier hour (logged once per bucket)
+}
+
+impl HourBucket {
+    fn new(hour_start_ms: i64) -> Self { Self { hour_start_ms, nodes: 0, acc: BTreeMap::new(), set_back: false } }
+    fn fold(&mut self, ga: &GhAvg) {
+        self.nodes = self.nodes.max(ga.nodes);
+        for (key, unit, v) in ga.fields() {
+            if let Some(x) = v { self.acc.entry(key).or_insert_with(|| FieldAcc::new(unit)).push(x); }
+        }
+    }
+    fn close(self, greenhouse_id: u16) -> GhHourly {
+        self.so_far(greenhouse_id)
+    }
+    fn so_far(&self, greenhouse_id: u16) -> GhHourly {
+        GhHourly {
+            hour_start_ms: self.hour_start_ms,
+            greenhouse_id,
+            nodes: self.nodes,
+            fields: self.acc.iter().map(|(k, a)| (k.to_string(), a.stat())).collect(),
+        }
+    }
+}
+
+/// Recently closed hours per greenhouse (bounded), shared with the Tauri commands, plus each
+/// greenhouse's open hour so far.
+#[derive(Debug, Default)]
+pub struct HourlyStore {
+    by_gh: HashMap<u16, VecDeque<GhHourly>>,
+    running: HashMap<u16, GhHourly>,
+}
+
+impl HourlyStore {
+    pub fn push(&mut self, h: GhHourly) {
+        let q = self.by_gh.entry(h.greenhouse_id).or_default();
+        if q.back().is_some_and(|last| last.hour_start_ms >= h.hour_start_ms) { return; }
+        q.push_back(h);
+        while q.len() > KEEP_HOURS { q.pop_front(); }
+    }
+    /// Greenhouses with at least one closed hour.
+    pub fn greenhouse_ids(&self) -> Vec<u16> {
+        self.by_gh.keys().copied().collect()
+    }
+    /// Last `hours` closed hours for `gh_id`, oldest first.
+    pub fn recent(&self, gh_id: u16, hours: usize) -> Vec<GhHourly> {
+        self.by_gh.get(&gh_id)
+            .map(|q| q.iter().skip(q.len().saturating_sub(hours)).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The open (not yet closed) hour of `gh_id` up to its latest GhAvg.
+    pub fn running(&self, gh_id: u16) -> Option<&GhHourly> {
+        self.running.get(&gh_id)
+    }
+
+    fn set_running(&mut self, gh_id: u16, h: Option<GhHourly>) {
+        match h {
+            Some(h) => { self.running.insert(gh_id, h); }
+            None => { self.running.remove(&gh_id); }
+        }
+    }
+}
+
+pub type HourlyShared = Arc<RwLock<HourlyStore>>;
+
+fn publish(h: GhHourly, store: &HourlyShared, tx_db: &mpsc::Sender<GhHourly>, tx_ui: &mpsc::Sender<GhHourly>) {
+    info!(target: "GH-HOURLY", "GH:{} | Hour start:{} | Nodes(max):{} | Fields:{}",
+             h.greenhouse_id, h.hour_start_ms, h.nodes, h.fields.len());
+    if let Ok(mut s) = store.write() { s.push(h.clone()); }
+    let _ = tx_db.try_send(h.clone());
+    let _ = tx_ui.try_send(h);
+}
+
+/// Fold `ga` into its greenhouse's open hour; returns the hour it closed, when `ga` starts a later one.
+fn fold_forward(open: &mut HashMap<u16, HourBucket>, ga: &GhAvg) -> Option<GhHourly> {
+    let hour = local_hour_start(ga.ts_ms);
+    let bucket = open.entry(ga.greenhouse_id).or_insert_with(|| HourBucket::new(hour));
+    if hour < bucket.hour_start_ms {
+        if !bucket.set_back {
+            warn!(target: "GH-HOURLY", "GH:{} window stamped {} is before the open hour {}; wall clock set back? skipping until it catches up",
+                  ga.greenhouse_id, ga.ts_ms, bucket.hour_start_ms);
+            bucket.set_back = true;
+        }
+        return None;
+    }
+    let done = (hour > bucket.hour_start_ms).then(|| std::mem::replace(bucket, HourBucket::new(hour)).close(ga.greenhouse_id));
+    bucket.fold(ga);
+    done
+}
+
+/// Public task:
+/// - rx_ghavg: GhAvg stream from the greenhouse aggregator
+/// - tx_hourly_db / tx_hourly_ui: one GhHourly per greenhouse per closed local hour
+/// - store: in-memory history (refilled from the DB by the caller before starting)
+pub async fn run_hourly_avg(
+    mut rx_ghavg: Lease<mpsc::Receiver<GhAvg>>,
+    tx_hourly_db: mpsc::Sender<GhHourly>,
+    tx_hourly_ui: mpsc::Sender<GhHourly>,
+    store: HourlyShared,
+) {
+    let mut open: HashMap<u16, HourBucket> = HashMap::new();
+    let mut on_the_hour = Scheduler::new(Schedule::Hourly { minute: 0 }, Local);
+
+    loop {
+        tokio::select! {
+            Some(ga) = rx_ghavg.recv() => {
+                if let Some(done) = fold_forward(&mut open, &ga) {
+                    publish(done, &store, &tx_hourly_db, &tx_hourly_ui);
+                }
+                let so_far = open.get(&ga.greenhouse_id).map(|b| b.so_far(ga.greenhouse_id));
+                if let Ok(mut s) = store.write() { s.set_running(ga.greenhouse_id, so_far); }
+            }
+            _ = on_the_hour.wait() => {
+                let hour = local_hour_start(now_ms());
+                let closed: Vec<u16> = open.iter()
+                    .filter(|(_, b)| b.hour_start_ms < hour)
+                    .map(|(gh_id, _)| *gh_id)
+                    .collect();
+                for gh_id in closed {
+                    if let Some(done) = open.remove(&gh_id) {
+                        if let Ok(mut s) = store.write() { s.set_running(gh_id, None); }
+                        publish(done.close(gh_id), &store, &tx_hourly_db, &tx_hourly_ui);
+                    }
+                }
+            }
+            else => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour_start: i64, minute: i64) -> GhAvg {
+        GhAvg::sample(1, hour_start + minute * 60_000)
+    }
+
+    #[test]
+    fn later_hour_closes_the_open_one() {
+        let h0 = local_hour_start(1_760_000_000_000);
+        let h1 = local_hour_start(h0 + HOUR_MS + 1);
+        let mut open = HashMap::new();
+        assert!(fold_forward(&mut open, &at(h0, 10)).is_none());
+        assert!(fold_forward(&mut open, &at(h0, 59)).is_none());
+        let done = fold_forward(&mut open, &at(h1, 1)).unwrap();
+        assert_eq!(done.hour_start_ms, h0);
+        assert_eq!(done.fields["air_temp_c"].mean, Some(24.0));
+        assert_eq!(open[&1].hour_start_ms, h1);
+    }
+
+    #[test]
+    fn clock_set_back_never_reopens_an_hour() {
+        let h0 = local_hour_start(1_760_000_000_000);
+        let h1 = local_hour_start(h0 + HOUR_MS + 1);
+        let mut open = HashMap::new();
+        fold_forward(&mut open, &at(h0, 30));
+        let done = fold_forward(&mut open, &at(h1, 5)).unwrap();
+        assert_eq!(done.hour_start_ms, h0);
+        // clock stepped back into h0: skipped, h1 stays open
+        assert!(fold_forward(&mut open, &at(h0, 50)).is_none());
+        assert!(fold_forward(&mut open, &at(h0, 55)).is_none());
+        assert_eq!(open[&1].hour_start_ms, h1);
+        assert!(open[&1].set_back);
+        // caught up again: h1 closes once, holding only its own windows
+        fold_forward(&mut open, &at(h1, 40));
+        let h2 = local_hour_start(h1 + HOUR_MS + 1);
+        let done = fold_forward(&mut open, &at(h2, 0)).unwrap();
+        assert_eq!(done.hour_start_ms, h1);
+        let mut store = HourlyStore::default();
+        store.push(done.clone());
+        store.push(done);
+        assert_eq!(store.recent(1, 24).len(), 1);
+    }
+
+    fn ms(s: &str) -> i64 {
+        s.parse::<chrono::DateTime<chrono::Utc>>().unwrap().timestamp_millis()
+    }
+
+    /// Distinct hour starts of every 10 minutes in `[from, to)`, in `tz`.
+    fn hours_between<Tz: TimeZone>(tz: &Tz, from: i64, to: i64) -> Vec<i64> {
+        let mut hours: Vec<i64> = (from..to).step_by(600_000).map(|t| hour_start_in(tz, t)).collect();
+        hours.dedup();
+        hours
+    }
+
+    #[test]
+    fn dst_days_have_23_and_25_whole_hours() {
+        use crate::services::scheduler::test_tz::Berlin;
+        // spring: local 02:00 does not exist
+        let (day, next) = (ms("2025-03-29T23:00:00Z"), ms("2025-03-30T22:00:00Z"));
+        assert_eq!(day_start_in(&Berlin, ms("2025-03-30T12:00:00Z")), day);
+        assert_eq!(day_start_in(&Berlin, next), next);
+        let hours = hours_between(&Berlin, day, next);
+        assert_eq!(hours.len(), 23);
+        assert!(hours.windows(2).all(|w| w[1] - w[0] == HOUR_MS), "{hours:?}");
+
+        // autumn: local 02:00-03:00 runs twice, as two hours of their own
+        let (day, next) = (ms("2025-10-25T22:00:00Z"), ms("2025-10-26T23:00:00Z"));
+        assert_eq!(day_start_in(&Berlin, ms("2025-10-26T00:30:00Z")), day);
+        assert_eq!(day_start_in(&Berlin, ms("2025-10-26T01:30:00Z")), day);
+        let hours = hours_between(&Berlin, day, next);
+        assert_eq!(hours.len(), 25);
+        assert!(hours.windows(2).all(|w| w[1] - w[0] == HOUR_MS), "{hours:?}");
+        assert_eq!(hour_start_in(&Berlin, ms("2025-10-26T00:59:00Z")), ms("2025-10-26T00:00:00Z"));
+        assert_eq!(hour_start_in(&Berlin, ms("2025-10-26T01:00:00Z")), ms("2025-10-26T01:00:00Z"));
+    }
+
+    #[test]
+    fn half_hour_offsets_keep_local_hours() {
+        let india = chrono::FixedOffset::east_opt(5 * 3600 + 1800).unwrap();
+        assert_eq!(hour_start_in(&india, ms("2025-01-01T10:45:12.345Z")), ms("2025-01-01T10:30:00Z"));
+        assert_eq!(hour_start_in(&india, ms("2025-01-01T10:15:00Z")), ms("2025-01-01T09:30:00Z"));
+        assert_eq!(day_start_in(&india, ms("2025-01-01T10:45:00Z")), ms("2024-12-31T18:30:00Z"));
+    }
+
+    #[test]
+    fn buckets_use_the_machine_timezone() {
+        let t = 1_760_000_123_456;
+        assert_eq!(local_hour_start(t), hour_start_in(&Local, t));
+        assert_eq!(local_day_start(t), day_start_in(&Local, t));
+        let local = Local.timestamp_millis_opt(local_hour_start(t)).unwrap();
+        assert_eq!((local.minute(), local.second()), (0, 0));
+    }
+}
@@ -0,0 +1,85 @@
+//! Ingestion pause for sensor maintenance (`pause_ingestion` / `resume_ingestion`): probes being moved
+//! around send junk that would spoil an hour of averages.
+//! - Everything, or single nodes by (greenhouse id, node id) as they reach the aggregator (after origin
+//!   mapping). While paused the subscriber still decodes and counts frames (health, decoder stats, raw
+//!   capture) but holds the samples back from the aggregator; held samples are counted.
+//! - The state rides on every `mqtt_status` event and `get_mqtt_status` (conn_status.rs) for the banner.
+//! - RAM only: the app always starts ingesting.
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+#[derive(Debug, Default)]
+pub struct IngestPause {
+    all: AtomicBool,
+    nodes: RwLock<BTreeSet<(u16, u16)>>,
+    held: AtomicU64,
+}
+
+pub type IngestPauseShared = Arc<IngestPause>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PausedNode {
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IngestPauseState {
+    /// Every node paused.
+    pub paused: bool,
+    pub paused_nodes: Vec<PausedNode>,
+    /// Samples held back from the aggregator since startup.
+    pub held_samples: u64,
+}
+
+impl IngestPause {
+    /// Pause `node`, or everything for None.
+    pub fn pause(&self, node: Option<(u16, u16)>) {
+        match node {
+            Some(ids) => {
+                if let Ok(mut n) = self.nodes.write() { n.insert(ids); }
+                info!(target: "DATA", "ingestion paused for GH:{} Node:{}", ids.0, ids.1);
+            }
+            None => {
+                self.all.store(true, Ordering::Relaxed);
+                info!(target: "DATA", "ingestion paused");
+            }
+        }
+    }
+
+    /// Resume `node`, or everything (single-node pauses included) for None.
+    pub fn resume(&self, node: Option<(u16, u16)>) {
+        match node {
+            Some(ids) => {
+                if let Ok(mut n) = self.nodes.write() { n.remove(&ids); }
+                info!(target: "DATA", "ingestion resumed for GH:{} Node:{}", ids.0, ids.1);
+            }
+            None => {
+                self.all.store(false, Ordering::Relaxed);
+                if let Ok(mut n) = self.nodes.write() { n.clear(); }
+                info!(target: "DATA", "ingestion resumed");
+            }
+        }
+    }
+
+    /// Whether a sample of `ids` is held back; counts it when it is.
+    pub fn holds(&self, ids: (u16, u16)) -> bool {
+        let held = self.all.load(Ordering::Relaxed) || self.nodes.read().is_ok_and(|n| n.contains(&ids));
+        if held { self.held.fetch_add(1, Ordering::Relaxed); }
+        held
+    }
+
+    pub fn state(&self) -> IngestPauseState {
+        IngestPauseState {
+            paused: self.all.load(Ordering::Relaxed),
+            paused_nodes: self.nodes.read()
+                .map(|n| n.iter().map(|&(greenhouse_id, node_id)| PausedNode { greenhouse_id, node_id }).collect())
+                .unwrap_or_default(),
+            held_samples: self.held.load(Ordering::Relaxed),
+        }
+    }
+}
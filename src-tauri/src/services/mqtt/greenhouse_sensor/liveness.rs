@@ -0,0 +1,190 @@
+//! Node online / late / offline from the samples themselves, for nodes without a status topic and
+//! for ones that hang without disconnecting (their Last Will never fires, see availability.rs).
+//! - The node aggregator notes every sample's arrival and, on each window tick, rates every node by
+//!   how many of its own windows have passed since: `late` from `node_liveness().late_after_windows`
+//!   (2), `offline` from `offline_after_windows` (5), else `online`. Outdoor stations count their
+//!   longer window.
+//! - Only changes go out, so a recovering node reports `online` once; a node's first sample reports
+//!   it too. `run_liveness` emits them as `node_status` and appends them to `node_status_log` with
+//!   `last_seen_ms` set (nothing stored in dry-run).
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::services::mqtt::config::{node_liveness, NodeLivenessConfig};
+use crate::services::presenter::emitter::EventSink;
+use crate::services::storage::node_status::insert_node_liveness;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeState {
+    Online,
+    Late,
+    Offline,
+}
+
+impl NodeState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NodeState::Online => "online",
+            NodeState::Late => "late",
+            NodeState::Offline => "offline",
+        }
+    }
+
+    fn after(silent: Duration, window: Duration, cfg: NodeLivenessConfig) -> Self {
+        let missed = silent.as_millis() / window.as_millis().max(1);
+        if missed >= cfg.offline_after_windows as u128 {
+            NodeState::Offline
+        } else if missed >= cfg.late_after_windows as u128 {
+            NodeState::Late
+        } else {
+            NodeState::Online
+        }
+    }
+}
+
+/// `node_status` payload of a change seen by the node aggregator.
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeStatus {
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+    pub state: NodeState,
+    /// Arrival of the node's last sample.
+    pub last_seen_ms: i64,
+    /// The tick that noticed the change.
+    pub ts_ms: i64,
+}
+
+struct Seen {
+    at: Instant,
+    wall_ms: i64,
+    window: Duration,
+    state: Option<NodeState>,
+}
+
+/// Last sample per node and the state last reported for it.
+#[derive(Default)]
+pub struct Liveness {
+    nodes: HashMap<(u16, u16), Seen>,
+}
+
+impl Liveness {
+    /// A sample of `ids` arrived; `window` is the node's own window length.
+    pub fn seen(&mut self, ids: (u16, u16), at: Instant, wall_ms: i64, window: Duration) {
+        let state = self.nodes.get(&ids).and_then(|s| s.state);
+        self.nodes.insert(ids, Seen { at, wall_ms, window, state });
+    }
+
+    /// Rate every node at `now`; returns the ones whose state changed.
+    pub fn check(&mut self, now: Instant, now_ms: i64) -> Vec<NodeStatus> {
+        let cfg = node_liveness();
+        let mut changes: Vec<NodeStatus> = self.nodes.iter_mut().filter_map(|(ids, s)| {
+            let state = NodeState::after(now.duration_since(s.at), s.window, cfg);
+            if s.state == Some(state) { return None; }
+            s.state = Some(state);
+            Some(NodeStatus { greenhouse_id: ids.0, node_id: ids.1, state, last_seen_ms: s.wall_ms, ts_ms: now_ms })
+        }).collect();
+        changes.sort_by_key(|c| (c.greenhouse_id, c.node_id));
+        changes
+    }
+}
+
+/// Forward liveness changes: `node_status` event, then a `node_status_log` row.
+pub async fn run_liveness<S: EventSink>(sink: S, mut rx: mpsc::Receiver<NodeStatus>, db_path: &'static str, dry_run: bool) {
+    while let Some(s) = rx.recv().await {
+        let log = format!("GH:{} Node:{} {} (last sample {}ms ago)", s.greenhouse_id, s.node_id, s.state.as_str(), s.ts_ms - s.last_seen_ms);
+        if s.state == NodeState::Online { info!(target: "NODE", "{log}"); } else { warn!(target: "NODE", "{log}"); }
+        if let Ok(v) = serde_json::to_value(&s) { sink.emit_json("node_status", v); }
+        if dry_run { continue; }
+        match tokio::task::spawn_blocking(move || insert_node_liveness(db_path, &s)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!(target: "DB", "node_status_log insert failed: {e}"),
+            Err(e) => warn!(target: "DB", "node_status_log join error: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::presenter::emitter::RecordingSink;
+    use crate::services::storage::sqlite::open_db;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    /// State changes of the check at each of `minutes` after `t0`.
+    fn ticks(l: &mut Liveness, t0: Instant, minutes: std::ops::RangeInclusive<u32>) -> Vec<(u32, u16, NodeState)> {
+        minutes.flat_map(|m| l.check(t0 + MINUTE * m, 0).into_iter().map(move |c| (m, c.node_id, c.state))).collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_quiet_node_turns_late_then_offline() {
+        let mut l = Liveness::default();
+        let t0 = Instant::now();
+        l.seen((1, 3), t0, 1_760_000_000_000, MINUTE);
+        l.seen((1, 9), t0, 1_760_000_000_000, 5 * MINUTE); // outdoor station, 5-minute window
+        assert_eq!(ticks(&mut l, t0, 0..=6), [
+            (0, 3, NodeState::Online), (0, 9, NodeState::Online),
+            (2, 3, NodeState::Late),
+            (5, 3, NodeState::Offline),
+        ]);
+        // the station turns late only after two of its own windows
+        assert_eq!(ticks(&mut l, t0, 7..=10), [(10, 9, NodeState::Late)]);
+        let last = l.check(t0 + MINUTE * 25, 1_760_000_000_000 + 25 * 60_000);
+        assert_eq!(last.len(), 1);
+        assert_eq!((last[0].node_id, last[0].state, last[0].last_seen_ms), (9, NodeState::Offline, 1_760_000_000_000));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_recovering_node_reports_online_once() {
+        let mut l = Liveness::default();
+        let t0 = Instant::now();
+        l.seen((1, 3), t0, 0, MINUTE);
+        ticks(&mut l, t0, 0..=5);
+
+        l.seen((1, 3), t0 + MINUTE * 6, 0, MINUTE);
+        assert_eq!(ticks(&mut l, t0, 6..=6), [(6, 3, NodeState::Online)]);
+        // further samples change nothing
+        l.seen((1, 3), t0 + MINUTE * 7, 0, MINUTE);
+        assert_eq!(ticks(&mut l, t0, 7..=8), []);
+    }
+
+    #[test]
+    fn thresholds_are_windows_of_the_node() {
+        let cfg = NodeLivenessConfig { late_after_windows: 3, offline_after_windows: 10 };
+        let at = |secs| NodeState::after(Duration::from_secs(secs), Duration::from_secs(10), cfg);
+        assert_eq!([at(29), at(30), at(99), at(100)], [NodeState::Online, NodeState::Late, NodeState::Late, NodeState::Offline]);
+    }
+
+    #[tokio::test]
+    async fn changes_become_events_and_log_rows_unless_dry_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let db: &'static str = Box::leak(dir.path().join("app.db").to_str().unwrap().to_string().into_boxed_str());
+        let status = |state, ts_ms| NodeStatus { greenhouse_id: 1, node_id: 3, state, last_seen_ms: 1_000, ts_ms };
+        let logged = || -> Vec<(String, i64)> {
+            open_db(db).unwrap()
+                .prepare("SELECT status, last_seen_ms FROM node_status_log ORDER BY ts_ms").unwrap()
+                .query_map([], |r| Ok((r.get(0)?, r.get(1)?))).unwrap().map(Result::unwrap).collect()
+        };
+
+        for dry_run in [true, false] {
+            let sink = RecordingSink::default();
+            let (tx, rx) = mpsc::channel(4);
+            tx.send(status(NodeState::Late, 121_000)).await.unwrap();
+            tx.send(status(NodeState::Offline, 301_000)).await.unwrap();
+            drop(tx);
+            run_liveness(sink.clone(), rx, db, dry_run).await;
+
+            let events = sink.0.lock().unwrap();
+            assert_eq!(events.iter().map(|(e, v)| (e.as_str(), v["state"].as_str().unwrap())).collect::<Vec<_>>(),
+                       [("node_status", "late"), ("node_status", "offline")]);
+            let expect: &[(&str, i64)] = if dry_run { &[] } else { &[("late", 1_000), ("offline", 1_000)] };
+            assert_eq!(logged().iter().map(|(s, t)| (s.as_str(), *t)).collect::<Vec<_>>(), expect, "dry run {dry_run}");
+        }
+    }
+}
@@ -1,4 +1,23 @@
 pub mod subscriber;
 pub mod decoder;
+pub mod decoder_stats;
+pub mod node_rates;
+pub mod conn_status;
+pub mod availability;
+pub mod liveness;
+pub mod raw_capture;
+pub mod ingest_pause;
+#[cfg(feature = "proto")]
+pub mod proto;
+pub mod frame_mac;
+pub mod ack;
+pub mod origin;
+pub mod dedup;
+pub mod discovery;
+pub mod sanitize;
+pub mod calibration;
 pub mod aggregator;
+pub mod derived;
 pub mod greenhouse_aggregator;
+pub mod hourly_aggregator;
+pub mod nodes;
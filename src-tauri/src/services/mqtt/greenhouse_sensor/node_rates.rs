@@ -0,0 +1,128 @@
+//! Publishes per node and minute, for spotting radio trouble before a node's averages disappear.
+//! - The subscriber counts every fresh (not retained) data publish by topic ids, bridged greenhouse ids
+//!   mapped like frames, whether or not it decodes.
+//! - Once a minute the counts roll over into the `node_rates` event and `get_node_rates`. A node that
+//!   sent at least `node_rates().slow_below_per_min` in the previous minute and fewer in this one is
+//!   logged as slow; one that keeps slow is not logged again until it has recovered.
+//! - Silent nodes stay in the report at rate 0 for `keep_silent_minutes`, then are aged out.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::interval;
+use tracing::warn;
+
+use crate::services::mqtt::config::{node_rates, NodeRatesConfig};
+use crate::services::presenter::emitter::EventSink;
+
+const EVERY: Duration = Duration::from_secs(60);
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeRate {
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+    /// Publishes in the last full minute.
+    pub per_min: u32,
+    pub previous_per_min: u32,
+    /// End of the last minute the node published in.
+    pub last_seen_ms: i64,
+    /// Dropped below `slow_below_per_min` after being above it, and not recovered since.
+    pub slow: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NodeRatesReport {
+    /// End of the minute counted; 0 before the first one.
+    pub ts_ms: i64,
+    pub nodes: Vec<NodeRate>,
+}
+
+#[derive(Debug, Default)]
+pub struct NodeRates {
+    counts: Mutex<HashMap<(u16, u16), u32>>,
+    report: RwLock<NodeRatesReport>,
+}
+
+pub type NodeRatesShared = Arc<NodeRates>;
+
+impl NodeRates {
+    /// One data publish of `ids`.
+    pub fn count(&self, ids: (u16, u16)) {
+        if let Ok(mut c) = self.counts.lock() { *c.entry(ids).or_default() += 1; }
+    }
+
+    pub fn report(&self) -> NodeRatesReport {
+        self.report.read().map(|r| r.clone()).unwrap_or_default()
+    }
+}
+
+/// What the minute task remembers of one node between minutes.
+#[derive(Debug, Clone, Copy, Default)]
+struct Tracked {
+    per_min: u32,
+    last_seen_ms: i64,
+    silent_minutes: u32,
+    slow: bool,
+}
+
+/// Fold one minute of counts into `tracked`; returns the report rows and the nodes that just turned slow.
+fn roll(
+    tracked: &mut BTreeMap<(u16, u16), Tracked>,
+    counts: HashMap<(u16, u16), u32>,
+    now_ms: i64,
+    cfg: NodeRatesConfig,
+) -> (Vec<NodeRate>, Vec<NodeRate>) {
+    for ids in counts.keys() { tracked.entry(*ids).or_default(); }
+    let (mut rows, mut slowed) = (Vec::new(), Vec::new());
+    tracked.retain(|&(greenhouse_id, node_id), t| {
+        let per_min = counts.get(&(greenhouse_id, node_id)).copied().unwrap_or(0);
+        let previous_per_min = t.per_min;
+        if per_min > 0 {
+            t.last_seen_ms = now_ms;
+            t.silent_minutes = 0;
+        } else {
+            t.silent_minutes += 1;
+            if t.silent_minutes > cfg.keep_silent_minutes { return false; }
+        }
+        let was_active = previous_per_min >= cfg.slow_below_per_min;
+        let turned_slow = was_active && per_min < cfg.slow_below_per_min;
+        if per_min >= cfg.slow_below_per_min { t.slow = false; }
+        if turned_slow { t.slow = true; }
+        t.per_min = per_min;
+        let row = NodeRate { greenhouse_id, node_id, per_min, previous_per_min, last_seen_ms: t.last_seen_ms, slow: t.slow };
+        if turned_slow { slowed.push(row.clone()); }
+        rows.push(row);
+        true
+    });
+    (rows, slowed)
+}
+
+/// Roll the counts over every minute: `node_rates` event, `get_node_rates`, slow-node warnings.
+pub async fn run_node_rates<S: EventSink>(sink: S, rates: NodeRatesShared) {
+    let mut tracked: BTreeMap<(u16, u16), Tracked> = BTreeMap::new();
+    let mut tick = interval(EVERY);
+    tick.tick().await; // the first tick is immediate
+    loop {
+        tick.tick().await;
+        let counts = rates.counts.lock().map(|mut c| std::mem::take(&mut *c)).unwrap_or_default();
+        let ts_ms = now_ms();
+        let (nodes, slowed) = roll(&mut tracked, counts, ts_ms, node_rates());
+        for n in &slowed {
+            warn!(
+                target: "DATA", "GH:{} Node:{} slowed to {} msg/min (was {}); check its radio",
+                n.greenhouse_id, n.node_id, n.per_min, n.previous_per_min
+            );
+        }
+        let report = NodeRatesReport { ts_ms, nodes };
+        match serde_json::to_value(&report) {
+            Ok(v) => sink.emit_json("node_rates", v),
+            Err(e) => warn!(target: "UI", "serialize node_rates failed: {e}"),
+        }
+        if let Ok(mut r) = rates.report.write() { *r = report; }
+    }
+}
@@ -0,0 +1,21 @@
+//! Node identity helpers shared by storage and presentation.
+
+/// Display label stored in `node_name` and attached to UI events. `outdoor` is whether the node sends
+/// outdoor frames (by length or as declared in the schema registry), so any outdoor id gets an outdoor label.
+pub fn label_for(node_id: u16, outdoor: bool) -> &'static str {
+    match node_id {
+        65001 => "Outdoor_Node",
+        65002 => "Outdoor_Node_2",
+        _ if outdoor => "Outdoor_NodeXX",
+        1 => "node01", 2 => "node02", 3 => "node03", 4 => "node04",
+        5 => "node05", 6 => "node06", 7 => "node07", 8 => "node08",
+        9 => "node09", 10 => "node10", 11 => "node11", 12 => "node12",
+        _ => "nodeXX",
+    }
+}
+
+/// Position of an outdoor station in the configured priority (lower = preferred).
+pub fn outdoor_rank(node_id: u16) -> (usize, u16) {
+    let order = crate::services::mqtt::config::outdoor().station_priority;
+    (order.iter().position(|&id| id == node_id).unwrap_or(order.len()), node_id)
+}
@@ -0,0 +1,141 @@
+//! Duplicate greenhouse ids across sites sharing one (bridged) broker.
+//! - A frame's origin is its topic prefix before `greenhouse/`: "" for local nodes, "siteB" for a
+//!   frame bridged in as `siteB/greenhouse/1/node/3/data`.
+//! - Mappings (`set_gh_origin_map`, stored in `gh_origin_map`) re-home (origin, greenhouse id) under
+//!   an effective id before anything downstream sees the frame.
+//! - A greenhouse id heard from two or more unmapped origins within `forget_after_secs` is a conflict:
+//!   frames from its unmapped origins are held back (never aggregated, never stored) until all but
+//!   one origin is mapped. The first frames before detection may already be in that minute's average.
+//! - `run_origin_watch` emits `gh_origin_conflict` whenever the set of conflicts changes; the site
+//!   overview lists current conflicts.
+
+use serde::Serialize;
+use std::{collections::{BTreeMap, HashMap}, sync::{Arc, RwLock}, time::Duration};
+use tokio::time::interval;
+use tracing::{error, info};
+
+use super::decoder::Decoded;
+use crate::services::mqtt::config::gh_origin;
+use crate::services::node_maintenance::now_ms;
+use crate::services::presenter::emitter::EventSink;
+use crate::services::storage::origin_map::{load_origin_map, OriginMapping};
+
+const CHECK_EVERY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+struct SeenOrigin {
+    last_seen_ms: i64,
+    held_frames: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct OriginState {
+    map: HashMap<(String, u16), u16>,
+    /// greenhouse id in the frame -> origin -> activity
+    seen: BTreeMap<u16, BTreeMap<String, SeenOrigin>>,
+}
+
+pub type OriginShared = Arc<RwLock<OriginState>>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OriginSeen {
+    pub origin: String,
+    pub last_seen_ms: i64,
+    pub mapped_to: Option<u16>,
+    pub held_frames: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OriginConflict {
+    pub greenhouse_id: u16,
+    pub origins: Vec<OriginSeen>,
+}
+
+/// Topic prefix before `greenhouse/` ("" for local frames).
+pub fn origin_of(topic: &str) -> &str {
+    match topic.find("greenhouse/") {
+        Some(i) => topic[..i].trim_end_matches('/'),
+        None => "",
+    }
+}
+
+impl OriginState {
+    fn unmapped_recent(&self, gh_id: u16, now: i64) -> usize {
+        let forget_ms = gh_origin().forget_after_secs as i64 * 1000;
+        self.seen.get(&gh_id).map_or(0, |m| m.iter()
+            .filter(|(o, s)| now - s.last_seen_ms < forget_ms && !self.map.contains_key(&(o.to_string(), gh_id)))
+            .count())
+    }
+
+    /// Apply the origin's mapping to `d`; false when the frame must be held back (conflict).
+    pub fn route(&mut self, origin: &str, d: &mut Decoded, now: i64) -> bool {
+        let gh_id = d.ids().0;
+        self.seen.entry(gh_id).or_default().entry(origin.to_string()).or_default().last_seen_ms = now;
+        if let Some(&eff) = self.map.get(&(origin.to_string(), gh_id)) {
+            d.set_greenhouse_id(eff);
+            return true;
+        }
+        if self.unmapped_recent(gh_id, now) < 2 { return true; }
+        if let Some(s) = self.seen.get_mut(&gh_id).and_then(|m| m.get_mut(origin)) { s.held_frames += 1; }
+        false
+    }
+
+    /// Mapped greenhouse id of `origin`'s `gh_id`, or `gh_id` itself; does not count as activity.
+    pub fn effective_gh(&self, origin: &str, gh_id: u16) -> u16 {
+        self.map.get(&(origin.to_string(), gh_id)).copied().unwrap_or(gh_id)
+    }
+
+    pub fn replace_map(&mut self, mappings: &[OriginMapping]) {
+        self.map = mappings.iter().map(|m| ((m.origin.clone(), m.greenhouse_id), m.effective_gh_id)).collect();
+    }
+
+    /// Greenhouse ids currently heard from two or more unmapped origins.
+    pub fn conflicts(&self, now: i64) -> Vec<OriginConflict> {
+        self.seen.iter().filter(|(gh, _)| self.unmapped_recent(**gh, now) >= 2).map(|(&gh, origins)| OriginConflict {
+            greenhouse_id: gh,
+            origins: origins.iter().map(|(o, s)| OriginSeen {
+                origin: o.clone(),
+                last_seen_ms: s.last_seen_ms,
+                mapped_to: self.map.get(&(o.clone(), gh)).copied(),
+                held_frames: s.held_frames,
+            }).collect(),
+        }).collect()
+    }
+}
+
+/// Current conflicts (empty when the lock is poisoned).
+pub fn current_conflicts(shared: &OriginShared) -> Vec<OriginConflict> {
+    shared.read().map(|s| s.conflicts(now_ms())).unwrap_or_default()
+}
+
+/// Public task: load mappings (none in dry-run), then report conflict changes (`gh_origin_conflict`).
+pub async fn run_origin_watch<S: EventSink>(sink: S, shared: OriginShared, db_path: &'static str, dry_run: bool) {
+    if !dry_run {
+        match tokio::task::spawn_blocking(move || load_origin_map(db_path)).await {
+            Ok(Ok(m)) => if let Ok(mut s) = shared.write() { s.replace_map(&m); },
+            Ok(Err(e)) => error!(target: "ORIGIN", "load mappings failed: {e}"),
+            Err(e) => error!(target: "ORIGIN", "join error: {e}"),
+        }
+    }
+    let mut tick = interval(CHECK_EVERY);
+    let mut last: Vec<u16> = Vec::new();
+    loop {
+        tick.tick().await;
+        let conflicts = current_conflicts(&shared);
+        let ids: Vec<u16> = conflicts.iter().map(|c| c.greenhouse_id).collect();
+        if ids == last { continue; }
+        for c in conflicts.iter().filter(|c| !last.contains(&c.greenhouse_id)) {
+            let origins: Vec<&str> = c.origins.iter().map(|o| if o.origin.is_empty() { "(local)" } else { o.origin.as_str() }).collect();
+            error!(target: "ORIGIN", "GH:{} arrives from {} origins ({}); holding its frames until set_gh_origin_map maps them",
+                c.greenhouse_id, origins.len(), origins.join(", "));
+        }
+        for gh in last.iter().filter(|gh| !ids.contains(gh)) {
+            info!(target: "ORIGIN", "GH:{gh} origin conflict resolved");
+        }
+        match serde_json::to_value(&conflicts) {
+            Ok(v) => sink.emit_json("gh_origin_conflict", serde_json::json!({ "severity": "critical", "conflicts": v })),
+            Err(e) => error!(target: "ORIGIN", "serialize conflicts failed: {e}"),
+        }
+        last = ids;
+    }
+}
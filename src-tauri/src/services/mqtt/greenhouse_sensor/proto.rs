@@ -0,0 +1,268 @@
+//! Protobuf node frames (`proto` cargo feature) for firmware moving off the fixed binary layouts.
+//! - `SensorReading` carries the fields of `Decoded` by name; `kind` picks standard / outdoor / soil.
+//!   Readings are proto3 `optional`: an absent reading becomes NaN (f32) or `U16_MISSING` (u16), as in
+//!   JSON frames, so the aggregator and storage see the same `Decoded` whichever format the node sends.
+//! - `decode_payload` tries protobuf last, after the binary layouts and JSON. A protobuf frame whose
+//!   length happens to be a binary one (60, 22, 62, 24, ...) is read as binary; firmware that sends
+//!   readings as optional fields rarely lands there, but a node stuck on such a length shows up as
+//!   `InvalidValue` / garbage readings rather than as protobuf.
+//! - u16 readings travel as `uint32`; a value above 0xFFFF rejects the frame.
+//! - The tags below are the wire contract with the firmware: append new fields, never renumber.
+
+use prost::Message;
+
+use super::decoder::{Decoded, U16_MISSING};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+pub enum Kind {
+    Standard = 0,
+    Outdoor = 1,
+    Soil = 2,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SensorReading {
+    #[prost(uint32, tag = "1")]
+    pub greenhouse_id: u32,
+    #[prost(uint32, tag = "2")]
+    pub node_id: u32,
+    #[prost(enumeration = "Kind", tag = "3")]
+    pub kind: i32,
+
+    #[prost(float, optional, tag = "4")]
+    pub air_temp_c: Option<f32>,
+    #[prost(float, optional, tag = "5")]
+    pub leaf_temp_c: Option<f32>,
+    #[prost(float, optional, tag = "6")]
+    pub bag_temp_c: Option<f32>,
+    #[prost(float, optional, tag = "7")]
+    pub air_rh_pct: Option<f32>,
+    #[prost(float, optional, tag = "8")]
+    pub bag_rh1_pct: Option<f32>,
+    #[prost(float, optional, tag = "9")]
+    pub bag_rh2_pct: Option<f32>,
+    #[prost(float, optional, tag = "10")]
+    pub bag_rh3_pct: Option<f32>,
+    #[prost(float, optional, tag = "11")]
+    pub bag_rh4_pct: Option<f32>,
+    #[prost(float, optional, tag = "12")]
+    pub bag_rh_avg_pct: Option<f32>,
+    #[prost(uint32, optional, tag = "13")]
+    pub par_value: Option<u32>,
+    #[prost(float, optional, tag = "14")]
+    pub weight_g: Option<f32>,
+    #[prost(float, optional, tag = "15")]
+    pub ea_air_kpa: Option<f32>,
+    #[prost(float, optional, tag = "16")]
+    pub ea_leaf_kpa: Option<f32>,
+    #[prost(float, optional, tag = "17")]
+    pub es_kpa: Option<f32>,
+    #[prost(float, optional, tag = "18")]
+    pub vpd_kpa: Option<f32>,
+
+    #[prost(float, optional, tag = "19")]
+    pub battery_v: Option<f32>,
+    #[prost(float, optional, tag = "20")]
+    pub rssi_dbm: Option<f32>,
+    #[prost(uint32, optional, tag = "21")]
+    pub seq: Option<u32>,
+    #[prost(uint32, optional, tag = "22")]
+    pub device_ts: Option<u32>,
+
+    #[prost(float, optional, tag = "23")]
+    pub vwc1_pct: Option<f32>,
+    #[prost(float, optional, tag = "24")]
+    pub vwc2_pct: Option<f32>,
+    #[prost(float, optional, tag = "25")]
+    pub vwc3_pct: Option<f32>,
+    #[prost(float, optional, tag = "26")]
+    pub vwc4_pct: Option<f32>,
+    #[prost(float, optional, tag = "27")]
+    pub ec_ms_cm: Option<f32>,
+
+    #[prost(float, optional, tag = "28")]
+    pub wind_ms: Option<f32>,
+    #[prost(float, optional, tag = "29")]
+    pub wind_gust_ms: Option<f32>,
+    #[prost(uint32, optional, tag = "30")]
+    pub rain_tips: Option<u32>,
+}
+
+/// `Decoded` from a protobuf frame; None when it is not a `SensorReading` or a value does not fit.
+pub fn decode_proto(p: &[u8]) -> Option<Decoded> {
+    let m = SensorReading::decode(p).ok()?;
+    let greenhouse_id = u16::try_from(m.greenhouse_id).ok()?;
+    let node_id = u16::try_from(m.node_id).ok()?;
+    let f = |v: Option<f32>| v.unwrap_or(f32::NAN);
+    let u = |v: Option<u32>| v.map_or(Some(U16_MISSING), |v| u16::try_from(v).ok());
+    let seq = m.seq.map(u16::try_from).transpose().ok()?;
+    match Kind::try_from(m.kind).ok()? {
+        Kind::Standard => Some(Decoded::Standard {
+            greenhouse_id, node_id,
+            air_temp_c: f(m.air_temp_c), leaf_temp_c: f(m.leaf_temp_c), bag_temp_c: f(m.bag_temp_c), air_rh_pct: f(m.air_rh_pct),
+            bag_rh1_pct: f(m.bag_rh1_pct), bag_rh2_pct: f(m.bag_rh2_pct), bag_rh3_pct: f(m.bag_rh3_pct), bag_rh4_pct: f(m.bag_rh4_pct),
+            bag_rh_avg_pct: f(m.bag_rh_avg_pct), par_value: u(m.par_value)?, weight_g: f(m.weight_g),
+            ea_air_kpa: f(m.ea_air_kpa), ea_leaf_kpa: f(m.ea_leaf_kpa), es_kpa: f(m.es_kpa), vpd_kpa: f(m.vpd_kpa),
+            battery_v: m.battery_v, rssi_dbm: m.rssi_dbm, seq, device_ts: m.device_ts,
+        }),
+        Kind::Outdoor => Some(Decoded::Outdoor {
+            greenhouse_id, node_id,
+            air_temp_c: f(m.air_temp_c), air_rh_pct: f(m.air_rh_pct), par_value: u(m.par_value)?,
+            ea_air_kpa: f(m.ea_air_kpa), es_kpa: f(m.es_kpa),
+            wind_ms: f(m.wind_ms), wind_gust_ms: f(m.wind_gust_ms), rain_tips: u(m.rain_tips)?,
+            battery_v: m.battery_v, rssi_dbm: m.rssi_dbm, seq, device_ts: m.device_ts,
+        }),
+        Kind::Soil => Some(Decoded::Soil {
+            greenhouse_id, node_id,
+            vwc1_pct: f(m.vwc1_pct), vwc2_pct: f(m.vwc2_pct), vwc3_pct: f(m.vwc3_pct), vwc4_pct: f(m.vwc4_pct),
+            ec_ms_cm: f(m.ec_ms_cm),
+        }),
+    }
+}
+
+/// Protobuf frame for `d` (simulators, test frames); missing readings are left out.
+pub fn encode_proto(d: &Decoded) -> Vec<u8> {
+    let f = |v: f32| (!v.is_nan()).then_some(v);
+    let u = |v: u16| (v != U16_MISSING).then_some(v as u32);
+    let (greenhouse_id, node_id) = d.ids();
+    let mut m = SensorReading { greenhouse_id: greenhouse_id as u32, node_id: node_id as u32, ..Default::default() };
+    match *d {
+        Decoded::Standard {
+            air_temp_c, leaf_temp_c, bag_temp_c, air_rh_pct,
+            bag_rh1_pct, bag_rh2_pct, bag_rh3_pct, bag_rh4_pct, bag_rh_avg_pct,
+            par_value, weight_g, ea_air_kpa, ea_leaf_kpa, es_kpa, vpd_kpa,
+            battery_v, rssi_dbm, seq, device_ts, ..
+        } => {
+            m.kind = Kind::Standard as i32;
+            m.air_temp_c = f(air_temp_c); m.leaf_temp_c = f(leaf_temp_c); m.bag_temp_c = f(bag_temp_c); m.air_rh_pct = f(air_rh_pct);
+            m.bag_rh1_pct = f(bag_rh1_pct); m.bag_rh2_pct = f(bag_rh2_pct); m.bag_rh3_pct = f(bag_rh3_pct); m.bag_rh4_pct = f(bag_rh4_pct);
+            m.bag_rh_avg_pct = f(bag_rh_avg_pct); m.par_value = u(par_value); m.weight_g = f(weight_g);
+            m.ea_air_kpa = f(ea_air_kpa); m.ea_leaf_kpa = f(ea_leaf_kpa); m.es_kpa = f(es_kpa); m.vpd_kpa = f(vpd_kpa);
+            m.battery_v = battery_v; m.rssi_dbm = rssi_dbm; m.seq = seq.map(u32::from); m.device_ts = device_ts;
+        }
+        Decoded::Outdoor {
+            air_temp_c, air_rh_pct, par_value, ea_air_kpa, es_kpa, wind_ms, wind_gust_ms, rain_tips,
+            battery_v, rssi_dbm, seq, device_ts, ..
+        } => {
+            m.kind = Kind::Outdoor as i32;
+            m.air_temp_c = f(air_temp_c); m.air_rh_pct = f(air_rh_pct); m.par_value = u(par_value);
+            m.ea_air_kpa = f(ea_air_kpa); m.es_kpa = f(es_kpa);
+            m.wind_ms = f(wind_ms); m.wind_gust_ms = f(wind_gust_ms); m.rain_tips = u(rain_tips);
+            m.battery_v = battery_v; m.rssi_dbm = rssi_dbm; m.seq = seq.map(u32::from); m.device_ts = device_ts;
+        }
+        Decoded::Soil { vwc1_pct, vwc2_pct, vwc3_pct, vwc4_pct, ec_ms_cm, .. } => {
+            m.kind = Kind::Soil as i32;
+            m.vwc1_pct = f(vwc1_pct); m.vwc2_pct = f(vwc2_pct); m.vwc3_pct = f(vwc3_pct); m.vwc4_pct = f(vwc4_pct);
+            m.ec_ms_cm = f(ec_ms_cm);
+        }
+    }
+    m.encode_to_vec()
+}
+
+#[cfg(all(test, feature = "proto"))]
+mod tests {
+    use super::*;
+    use crate::services::mqtt::greenhouse_sensor::decoder::{decode_payload, DecodeError};
+    use proptest::prelude::*;
+
+    fn same(a: &Decoded, b: &Decoded) -> bool {
+        format!("{a:?}") == format!("{b:?}")
+    }
+
+    fn standard(air_temp_c: f32, leaf_temp_c: f32, par_value: u16) -> Decoded {
+        Decoded::Standard {
+            greenhouse_id: 1, node_id: 3,
+            air_temp_c, leaf_temp_c, bag_temp_c: 21.5, air_rh_pct: 68.0,
+            bag_rh1_pct: 80.0, bag_rh2_pct: f32::NAN, bag_rh3_pct: 82.0, bag_rh4_pct: 83.0, bag_rh_avg_pct: 81.5,
+            par_value, weight_g: 70_000.25,
+            ea_air_kpa: 2.1, ea_leaf_kpa: 2.4, es_kpa: 3.1, vpd_kpa: 1.0,
+            battery_v: Some(3.7), rssi_dbm: None, seq: Some(65_535), device_ts: Some(1_760_000_123),
+        }
+    }
+
+    #[test]
+    fn every_layout_round_trips() {
+        let frames = [
+            standard(24.5, 23.0, 420),
+            standard(f32::NAN, f32::INFINITY, U16_MISSING),
+            Decoded::Outdoor {
+                greenhouse_id: 2, node_id: 65001, air_temp_c: 18.0, air_rh_pct: f32::NAN, par_value: 900,
+                ea_air_kpa: 1.1, es_kpa: 2.0, wind_ms: 3.5, wind_gust_ms: 9.25, rain_tips: 0,
+                battery_v: Some(3.712), rssi_dbm: Some(-71.0), seq: None, device_ts: None,
+            },
+            Decoded::Soil { greenhouse_id: 1, node_id: 9, vwc1_pct: 35.5, vwc2_pct: f32::NAN, vwc3_pct: 34.25, vwc4_pct: 33.0, ec_ms_cm: 2.1 },
+        ];
+        for d in frames {
+            let p = encode_proto(&d);
+            let back = decode_proto(&p).unwrap();
+            assert!(same(&back, &d), "{d:?} -> {back:?}");
+        }
+    }
+
+    #[test]
+    fn absent_readings_are_missing() {
+        let p = SensorReading { greenhouse_id: 1, node_id: 3, kind: Kind::Outdoor as i32, air_temp_c: Some(18.0), ..Default::default() }.encode_to_vec();
+        match decode_proto(&p).unwrap() {
+            Decoded::Outdoor { air_temp_c, air_rh_pct, par_value, rain_tips, battery_v, seq, .. } => {
+                assert_eq!((air_temp_c, par_value, rain_tips, battery_v, seq), (18.0, U16_MISSING, U16_MISSING, None, None));
+                assert!(air_rh_pct.is_nan());
+            }
+            d => panic!("{d:?}"),
+        }
+    }
+
+    #[test]
+    fn values_that_do_not_fit_reject_the_frame() {
+        let ok = SensorReading { greenhouse_id: 1, node_id: 3, ..Default::default() };
+        assert!(decode_proto(&ok.encode_to_vec()).is_some());
+        for bad in [
+            SensorReading { greenhouse_id: 70_000, ..ok.clone() },
+            SensorReading { node_id: 65_536, ..ok.clone() },
+            SensorReading { par_value: Some(65_536), ..ok.clone() },
+            SensorReading { seq: Some(1 << 20), ..ok.clone() },
+            SensorReading { kind: 7, ..ok.clone() },
+        ] {
+            assert!(decode_proto(&bad.encode_to_vec()).is_none(), "{bad:?}");
+        }
+        assert!(decode_proto(&[0xFF, 0xFF, 0xFF]).is_none());
+    }
+
+    #[test]
+    fn decode_payload_falls_back_to_protobuf() {
+        let d = standard(24.5, 23.0, 420);
+        let p = encode_proto(&d);
+        assert!(![22, 24, 25, 26, 27, 28, 36, 38, 60, 62, 64, 66, 69, 71, 73, 75, 77].contains(&p.len()), "{} bytes", p.len());
+        assert!(same(&decode_payload(&p).unwrap(), &d));
+        // -999 from the firmware is missing, whatever the format
+        let p = SensorReading { greenhouse_id: 1, node_id: 3, air_temp_c: Some(-999.0), leaf_temp_c: Some(23.0), ..Default::default() }.encode_to_vec();
+        match decode_payload(&p).unwrap() {
+            Decoded::Standard { air_temp_c, leaf_temp_c, .. } => assert!(air_temp_c.is_nan() && leaf_temp_c == 23.0),
+            d => panic!("{d:?}"),
+        }
+        // ids are checked like any frame's
+        let p = SensorReading { greenhouse_id: 1, node_id: 0, air_temp_c: Some(20.0), ..Default::default() }.encode_to_vec();
+        assert_eq!(decode_payload(&p).unwrap_err(), DecodeError::InvalidValue { field: "node_id" });
+    }
+
+    proptest! {
+        #[test]
+        fn standard_readings_round_trip(gh in 1..=u16::MAX, node in 1..=u16::MAX, r in prop::collection::vec(any::<f32>(), 15),
+                                        par in any::<u16>(), seq in any::<Option<u16>>(), ts in any::<Option<u32>>()) {
+            let d = Decoded::Standard {
+                greenhouse_id: gh, node_id: node,
+                air_temp_c: r[0], leaf_temp_c: r[1], bag_temp_c: r[2], air_rh_pct: r[3],
+                bag_rh1_pct: r[4], bag_rh2_pct: r[5], bag_rh3_pct: r[6], bag_rh4_pct: r[7], bag_rh_avg_pct: r[8],
+                par_value: par, weight_g: r[9], ea_air_kpa: r[10], ea_leaf_kpa: r[11], es_kpa: r[12], vpd_kpa: r[13],
+                battery_v: Some(r[14]), rssi_dbm: None, seq, device_ts: ts,
+            };
+            let back = decode_proto(&encode_proto(&d));
+            prop_assert!(back.is_some_and(|b| same(&b, &d)), "{d:?} -> {back:?}");
+        }
+
+        #[test]
+        fn random_bytes_never_panic(p in prop::collection::vec(any::<u8>(), 0..=128)) {
+            let _ = decode_proto(&p);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! The last publishes the subscriber received, bytes as sent, for field debugging (`dump_raw_payloads`).
+//! - A ring of `raw_capture().entries` publishes; payloads longer than `max_payload_bytes` keep their
+//!   head only, so memory stays bounded whatever the message rate or size.
+//! - Every publish is kept with its outcome: decoded sample count and kinds, the decode error, or why it
+//!   never reached the decoder (`Status`, `Retained`).
+//! - The hot path only `try_lock`s: a publish that arrives while a dump holds the ring is skipped and
+//!   counted instead of waiting.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex};
+
+use crate::services::mqtt::config::raw_capture;
+
+#[derive(Debug, Clone)]
+struct RawEntry {
+    ts_ms: i64,
+    topic: String,
+    /// Topic ids (`greenhouse/{gh}/node/{id}`), before origin mapping.
+    ids: Option<(u16, u16)>,
+    retained: bool,
+    payload: Vec<u8>,
+    total_len: usize,
+    outcome: String,
+}
+
+#[derive(Debug, Default)]
+pub struct RawCapture {
+    ring: Mutex<VecDeque<RawEntry>>,
+    skipped: AtomicU64,
+}
+
+pub type RawCaptureShared = Arc<RawCapture>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RawPayload {
+    pub ts_ms: i64,
+    pub topic: String,
+    pub greenhouse_id: Option<u16>,
+    pub node_id: Option<u16>,
+    pub retained: bool,
+    /// Full payload length; `hex` holds at most `raw_capture().max_payload_bytes` of it.
+    pub bytes: usize,
+    pub truncated: bool,
+    pub hex: String,
+    pub outcome: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RawDump {
+    pub capacity: usize,
+    /// Publishes not captured because a dump held the ring at that moment.
+    pub skipped: u64,
+    /// Oldest first.
+    pub payloads: Vec<RawPayload>,
+}
+
+impl RawCapture {
+    /// Keep one publish; never waits for the lock.
+    pub fn record(&self, ts_ms: i64, topic: &str, ids: Option<(u16, u16)>, retained: bool, payload: &[u8], outcome: String) {
+        let cfg = raw_capture();
+        if cfg.entries == 0 { return; }
+        let Ok(mut ring) = self.ring.try_lock() else {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        while ring.len() >= cfg.entries { ring.pop_front(); }
+        ring.push_back(RawEntry {
+            ts_ms,
+            topic: topic.to_string(),
+            ids,
+            retained,
+            payload: payload[..payload.len().min(cfg.max_payload_bytes)].to_vec(),
+            total_len: payload.len(),
+            outcome,
+        });
+    }
+
+    /// Captured publishes of `node_id` (and `gh_id`) when given, else all; oldest first.
+    pub fn dump(&self, gh_id: Option<u16>, node_id: Option<u16>) -> RawDump {
+        // copy out first: hex encoding runs without the hot path waiting on it
+        let entries: Vec<RawEntry> = self.ring.lock().map(|r| {
+            r.iter().filter(|e| {
+                gh_id.is_none_or(|g| e.ids.is_some_and(|i| i.0 == g)) && node_id.is_none_or(|n| e.ids.is_some_and(|i| i.1 == n))
+            }).cloned().collect()
+        }).unwrap_or_default();
+        RawDump {
+            capacity: raw_capture().entries,
+            skipped: self.skipped.load(Ordering::Relaxed),
+            payloads: entries.into_iter().map(|e| RawPayload {
+                ts_ms: e.ts_ms,
+                greenhouse_id: e.ids.map(|i| i.0),
+                node_id: e.ids.map(|i| i.1),
+                retained: e.retained,
+                bytes: e.total_len,
+                truncated: e.payload.len() < e.total_len,
+                hex: hex::encode(&e.payload),
+                topic: e.topic,
+                outcome: e.outcome,
+            }).collect(),
+        }
+    }
+}
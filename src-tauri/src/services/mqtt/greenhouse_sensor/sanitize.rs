@@ -0,0 +1,321 @@
+//! Validation stage between decode and aggregation.
+//! - Range check (subscriber, right after decode): a reading outside its physically plausible range
+//!   (`RANGE_LIMITS`, e.g. RH 4000 % or -700 °C from a sensor glitch) is scrubbed to the missing
+//!   sentinel (NaN, `U16_MISSING`); the rest of the frame is kept. Counted per node for the health score.
+//! - Slew-rate guard: a reading that jumps further than physically plausible from the node's
+//!   previous accepted value is rejected (set to NaN, which the aggregator treats as missing)
+//!   unless the next sample confirms the new level. Catches loose-connector glitches whose
+//!   values are individually plausible.
+//! - Slew limits are configured per field (`slew_guard()` in mqtt/config.rs); fields not listed (PAR,
+//!   weight) are never slew-guarded. Rejections are counted per node (health score) and in total
+//!   (`decoder_stats`).
+
+use std::{collections::HashMap, time::Duration};
+use tokio::time::Instant;
+use tracing::warn;
+
+use super::decoder::{Decoded, U16_MISSING};
+use crate::services::mqtt::config::{slew_guard, SlewGuardConfig};
+
+/// (field, min, max), inclusive. Fields not listed are never range-checked.
+const RANGE_LIMITS: &[(&str, f32, f32)] = &[
+    ("air_temp_c",     -40.0, 80.0),
+    ("leaf_temp_c",    -40.0, 80.0),
+    ("bag_temp_c",     -40.0, 80.0),
+    ("air_rh_pct",     0.0,   100.0),
+    ("bag_rh1_pct",    0.0,   100.0),
+    ("bag_rh2_pct",    0.0,   100.0),
+    ("bag_rh3_pct",    0.0,   100.0),
+    ("bag_rh4_pct",    0.0,   100.0),
+    ("bag_rh_avg_pct", 0.0,   100.0),
+    ("par_value",      0.0,   3000.0),
+    ("weight_g",       0.0,   1_000_000.0),
+    ("ea_air_kpa",     0.0,   15.0),
+    ("ea_leaf_kpa",    0.0,   15.0),
+    ("es_kpa",         0.0,   15.0),
+    ("vpd_kpa",        0.0,   15.0),
+    ("vwc1_pct",       0.0,   100.0),
+    ("vwc2_pct",       0.0,   100.0),
+    ("vwc3_pct",       0.0,   100.0),
+    ("vwc4_pct",       0.0,   100.0),
+    ("ec_ms_cm",       0.0,   20.0),
+    ("wind_ms",        0.0,   75.0),
+    ("wind_gust_ms",   0.0,   100.0),
+    ("rain_tips",      0.0,   1000.0),
+];
+
+fn range_limit(field: &str) -> Option<(f32, f32)> {
+    RANGE_LIMITS.iter().find(|(f, _, _)| *f == field).map(|&(_, lo, hi)| (lo, hi))
+}
+
+/// Replace out-of-range readings in `msg` with the missing sentinel; returns the scrubbed fields.
+pub fn scrub_out_of_range(msg: &mut Decoded) -> Vec<&'static str> {
+    let (gh_id, node_id) = msg.ids();
+    let mut scrubbed = Vec::new();
+    for (field, v) in msg.f32_fields_mut() {
+        let Some((lo, hi)) = range_limit(field) else { continue };
+        if v.is_finite() && !(lo..=hi).contains(v) {
+            warn!(target: "RANGE", "GH:{} Node:{} {} = {:.2} outside {}..{}, scrubbed", gh_id, node_id, field, *v, lo, hi);
+            *v = f32::NAN;
+            scrubbed.push(field);
+        }
+    }
+    for (field, v) in msg.u16_fields_mut() {
+        let Some((lo, hi)) = range_limit(field) else { continue };
+        if *v != U16_MISSING && !(lo..=hi).contains(&(*v as f32)) {
+            warn!(target: "RANGE", "GH:{} Node:{} {} = {} outside {}..{}, scrubbed", gh_id, node_id, field, *v, lo, hi);
+            *v = U16_MISSING;
+            scrubbed.push(field);
+        }
+    }
+    scrubbed
+}
+
+#[inline] fn allowed(max_delta: f32, per: Duration, since: Duration) -> f32 {
+    max_delta * (since.as_secs_f32() / per.as_secs_f32()).max(1.0)
+}
+
+#[derive(Debug)]
+struct FieldTrack {
+    last: f32,
+    at: Instant,
+    pending: Option<(f32, Instant)>, // rejected jump waiting for confirmation
+}
+
+/// Per-node, per-field slew state. Bounded by nodes × guarded fields.
+#[derive(Debug)]
+pub struct SlewGuard {
+    limits: &'static [(&'static str, f32, u64)],
+    tracks: HashMap<(u16, u16, &'static str), FieldTrack>,
+    rejected_total: u64,
+}
+
+impl Default for SlewGuard {
+    fn default() -> Self {
+        SlewGuard::new(slew_guard())
+    }
+}
+
+impl SlewGuard {
+    pub fn new(cfg: SlewGuardConfig) -> Self {
+        SlewGuard { limits: if cfg.enabled { cfg.limits } else { &[] }, tracks: HashMap::new(), rejected_total: 0 }
+    }
+
+    fn limit(&self, field: &str) -> Option<(f32, Duration)> {
+        self.limits.iter().find(|(f, _, _)| *f == field).map(|&(_, d, per)| (d, Duration::from_secs(per.max(1))))
+    }
+
+    /// Blank out implausible jumps in `msg` in place; returns the rejected fields.
+    pub fn check(&mut self, now: Instant, msg: &mut Decoded) -> Vec<&'static str> {
+        let (gh_id, node_id) = msg.ids();
+        let mut rejected = Vec::new();
+        for (field, v) in msg.f32_fields_mut() {
+            let Some((max_delta, per)) = self.limit(field) else { continue };
+            if !v.is_finite() { continue; }
+
+            let Some(t) = self.tracks.get_mut(&(gh_id, node_id, field)) else {
+                self.tracks.insert((gh_id, node_id, field), FieldTrack { last: *v, at: now, pending: None });
+                continue;
+            };
+
+            let within_last = (*v - t.last).abs() <= allowed(max_delta, per, now.duration_since(t.at));
+            let confirms_pending = t.pending
+                .is_some_and(|(p, p_at)| (*v - p).abs() <= allowed(max_delta, per, now.duration_since(p_at)));
+
+            if within_last || confirms_pending {
+                *t = FieldTrack { last: *v, at: now, pending: None };
+            } else {
+                self.rejected_total += 1;
+                warn!(
+                    target: "SLEW",
+                    "GH:{} Node:{} {} jump {:.2} -> {:.2} rejected (total {})",
+                    gh_id, node_id, field, t.last, *v, self.rejected_total
+                );
+                t.pending = Some((*v, now));
+                *v = f32::NAN;
+                rejected.push(field);
+            }
+        }
+        rejected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standard(v: f32, par_value: u16) -> Decoded {
+        Decoded::Standard {
+            greenhouse_id: 1, node_id: 3,
+            air_temp_c: v, leaf_temp_c: v, bag_temp_c: v, air_rh_pct: v,
+            bag_rh1_pct: v, bag_rh2_pct: v, bag_rh3_pct: v, bag_rh4_pct: v, bag_rh_avg_pct: v,
+            par_value, weight_g: v, ea_air_kpa: v, ea_leaf_kpa: v, es_kpa: v, vpd_kpa: v,
+            battery_v: Some(v), rssi_dbm: Some(v), seq: None, device_ts: None,
+        }
+    }
+
+    fn outdoor(v: f32, tips: u16) -> Decoded {
+        Decoded::Outdoor {
+            greenhouse_id: 1, node_id: 65001, air_temp_c: v, air_rh_pct: v, par_value: tips, ea_air_kpa: v, es_kpa: v,
+            wind_ms: v, wind_gust_ms: v, rain_tips: tips, battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        }
+    }
+
+    fn soil(v: f32) -> Decoded {
+        Decoded::Soil { greenhouse_id: 1, node_id: 9, vwc1_pct: v, vwc2_pct: v, vwc3_pct: v, vwc4_pct: v, ec_ms_cm: v }
+    }
+
+    fn reading(d: &mut Decoded, field: &str) -> f32 {
+        if let Some((_, v)) = d.u16_fields_mut().into_iter().find(|(f, _)| *f == field) {
+            return if *v == U16_MISSING { f32::NAN } else { *v as f32 };
+        }
+        d.f32_fields_mut().into_iter().find(|(f, _)| *f == field).map(|(_, v)| *v).unwrap()
+    }
+
+    /// A frame holding `v` in `field` and a plausible value everywhere else.
+    fn with(field: &str, v: f32) -> Decoded {
+        let mut d = match field {
+            "wind_ms" | "wind_gust_ms" | "rain_tips" => outdoor(1.0, 1),
+            f if f.starts_with("vwc") || f == "ec_ms_cm" => soil(1.0),
+            _ => standard(1.0, 1),
+        };
+        if let Some((_, p)) = d.u16_fields_mut().into_iter().find(|(f, _)| *f == field) { *p = v as u16; }
+        if let Some((_, p)) = d.f32_fields_mut().into_iter().find(|(f, _)| *f == field) { *p = v; }
+        d
+    }
+
+    #[test]
+    fn range_boundaries_are_inclusive() {
+        for &(field, lo, hi) in RANGE_LIMITS {
+            for v in [lo, hi] {
+                let mut d = with(field, v);
+                assert_eq!(scrub_out_of_range(&mut d), Vec::<&str>::new(), "{field} = {v}");
+                assert_eq!(reading(&mut d, field), v, "{field}");
+            }
+            // the u16 readings cannot go below 0 or between whole numbers
+            let outside = if field == "par_value" || field == "rain_tips" { vec![hi + 1.0] } else { vec![lo - lo.abs().max(1.0) * 1e-3, hi + hi.abs().max(1.0) * 1e-3] };
+            for v in outside {
+                let mut d = with(field, v);
+                assert_eq!(scrub_out_of_range(&mut d), [field], "{field} = {v}");
+                assert!(reading(&mut d, field).is_nan(), "{field}");
+            }
+        }
+    }
+
+    #[test]
+    fn every_listed_field_is_a_reading() {
+        for &(field, lo, hi) in RANGE_LIMITS {
+            assert!(lo < hi, "{field}");
+            let mut d = with(field, lo);
+            let known = d.f32_fields_mut().iter().any(|(f, _)| *f == field) || d.u16_fields_mut().iter().any(|(f, _)| *f == field);
+            assert!(known, "{field} is in RANGE_LIMITS but no frame carries it");
+        }
+    }
+
+    #[test]
+    fn garbage_frame_is_scrubbed_field_by_field() {
+        for (mut d, readings) in [(standard(4e6, 5000), 15), (standard(-700.0, 3001), 15), (outdoor(-50.0, 4000), 8), (soil(1e9), 5)] {
+            let ids = d.ids();
+            assert_eq!(scrub_out_of_range(&mut d).len(), readings, "{d:?}");
+            assert_eq!(d.ids(), ids);
+            assert!(d.f32_fields_mut().iter().all(|(_, v)| v.is_nan()), "{d:?}");
+            assert!(d.u16_fields_mut().iter().all(|(_, v)| **v == U16_MISSING), "{d:?}");
+        }
+        // the telemetry is no reading: left alone
+        let mut d = standard(4e6, 5000);
+        scrub_out_of_range(&mut d);
+        assert_eq!(d.telemetry(), (Some(4e6), Some(4e6)));
+    }
+
+    #[test]
+    fn missing_readings_are_not_counted_as_scrubbed() {
+        let mut d = standard(f32::NAN, U16_MISSING);
+        assert!(scrub_out_of_range(&mut d).is_empty());
+        let mut d = standard(f32::INFINITY, U16_MISSING);
+        assert!(scrub_out_of_range(&mut d).is_empty(), "non-finite values are already missing for the aggregator");
+    }
+
+    /// Air temperature samples `(secs, value)` of one node through `guard`; the value each sample kept.
+    fn air_temps(guard: &mut SlewGuard, samples: &[(u64, f32)]) -> Vec<f32> {
+        let t0 = Instant::now();
+        samples.iter().map(|&(secs, v)| {
+            let mut d = with("air_temp_c", v);
+            let rejected = guard.check(t0 + Duration::from_secs(secs), &mut d);
+            let kept = reading(&mut d, "air_temp_c");
+            assert_eq!(rejected.is_empty(), kept.is_finite(), "{rejected:?}");
+            kept
+        }).collect()
+    }
+
+    fn kept(values: &[f32]) -> Vec<Option<f32>> {
+        values.iter().map(|v| v.is_finite().then_some(*v)).collect()
+    }
+
+    #[test]
+    fn single_glitch_is_rejected_and_the_level_continues() {
+        let mut guard = SlewGuard::default();
+        let out = air_temps(&mut guard, &[(0, 24.0), (10, 3.0), (20, 24.0), (30, 24.5)]);
+        assert_eq!(kept(&out), [Some(24.0), None, Some(24.0), Some(24.5)]);
+        assert_eq!(guard.rejected_total, 1);
+    }
+
+    #[test]
+    fn genuine_step_is_rejected_once_then_accepted() {
+        let mut guard = SlewGuard::default();
+        let out = air_temps(&mut guard, &[(0, 24.0), (10, 30.0), (20, 30.2), (30, 30.4), (40, 24.0)]);
+        assert_eq!(kept(&out), [Some(24.0), None, Some(30.2), Some(30.4), None], "the confirmed level is the new reference");
+        assert_eq!(guard.rejected_total, 2);
+    }
+
+    #[test]
+    fn allowed_change_grows_with_the_time_since_the_last_value() {
+        // 5 °C per 10 s: 9 °C after 20 s is plausible, 9 °C after 1 s is not (one period's worth at least)
+        let out = air_temps(&mut SlewGuard::default(), &[(0, 24.0), (20, 33.0), (21, 42.0), (22, 37.5)]);
+        assert_eq!(kept(&out), [Some(24.0), Some(33.0), None, Some(37.5)]);
+    }
+
+    #[test]
+    fn nodes_and_fields_are_tracked_apart() {
+        let mut guard = SlewGuard::default();
+        let t0 = Instant::now();
+        guard.check(t0, &mut with("air_temp_c", 24.0));
+        // another node at another level is its own first sample
+        let mut other = with("air_temp_c", 3.0);
+        if let Decoded::Standard { node_id, .. } = &mut other { *node_id = 4; }
+        assert!(guard.check(t0 + Duration::from_secs(10), &mut other).is_empty());
+        // a glitch in one field leaves the rest of the frame alone
+        let mut d = with("air_temp_c", 3.0);
+        assert_eq!(guard.check(t0 + Duration::from_secs(10), &mut d), ["air_temp_c"]);
+        assert_eq!(reading(&mut d, "leaf_temp_c"), 1.0);
+    }
+
+    #[test]
+    fn jumpy_fields_are_never_guarded() {
+        let limits = slew_guard().limits;
+        assert!(!limits.iter().any(|(f, _, _)| matches!(*f, "par_value" | "weight_g")));
+        let mut guard = SlewGuard::default();
+        let t0 = Instant::now();
+        for (i, w) in [0.0, 5000.0, 0.0, 80_000.0].into_iter().enumerate() {
+            let mut d = with("weight_g", w);
+            assert!(guard.check(t0 + Duration::from_secs(i as u64), &mut d).is_empty());
+            assert_eq!(reading(&mut d, "weight_g"), w);
+        }
+        assert_eq!(guard.rejected_total, 0);
+    }
+
+    #[test]
+    fn limits_come_from_the_config() {
+        let cfg = SlewGuardConfig { enabled: true, limits: &[("air_temp_c", 1.0, 10)] };
+        let out = air_temps(&mut SlewGuard::new(cfg), &[(0, 24.0), (10, 26.0), (20, 24.5)]);
+        assert_eq!(kept(&out), [Some(24.0), None, Some(24.5)]);
+        // unlisted under this config: RH may jump
+        let mut guard = SlewGuard::new(cfg);
+        let t0 = Instant::now();
+        guard.check(t0, &mut with("air_rh_pct", 30.0));
+        assert!(guard.check(t0 + Duration::from_secs(10), &mut with("air_rh_pct", 95.0)).is_empty());
+
+        let off = SlewGuardConfig { enabled: false, ..slew_guard() };
+        let out = air_temps(&mut SlewGuard::new(off), &[(0, 24.0), (10, 3.0), (20, 24.0)]);
+        assert_eq!(kept(&out), [Some(24.0), Some(3.0), Some(24.0)]);
+    }
+}
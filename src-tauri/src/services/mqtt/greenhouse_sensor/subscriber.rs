@@ -1,55 +1,522 @@
 //! Resilient, non-blocking MQTT subscriber for greenhouse sensor data.
-//! - Sends decoded samples to the rolling-average aggregator via mpsc.
+//! - Sends decoded samples to the rolling-average aggregator via a bounded queue; a full queue drops or
+//!   waits per `channel_config().decoded_policy` (channels.rs).
 //! - No raw prints here (keeps terminal output to 60s AVG only).
+//! - Nodes in ack mode get an ack / nack per frame on this same connection (see ack.rs).
+//! - Subscribes to every filter in `sensor_subscriptions()` (mqtt.toml, see auth.rs) with one SUBSCRIBE
+//!   per connection, so a reconnect re-subscribes all of them; every filter goes through the same decode
+//!   path. The QoS the broker granted per filter is kept for `get_subscription_info`.
+//! - Connection transitions (connected, subscribed, disconnected, reconnecting with attempt, backoff and
+//!   downtime) are reported for the `mqtt_status` event and `get_mqtt_status` (conn_status.rs).
+//! - Reconnects back off with jitter per `reconnect()` (backoff.rs); the backoff starts over only after a
+//!   connection stayed up for a while. Single failures are warnings; every minute of continuous failure
+//!   is logged as an error.
+//! - With `[[broker]]` failover brokers in mqtt.toml each reconnect tries the next one, each with its own
+//!   backoff, starting at the one that last worked (failover.rs).
+//! - Messages on `…/node/{id}/status` topics are node availability (availability.rs), not frames: parsed
+//!   and recorded per node (retained ones included), never decoded.
+//! - Frames from bridged sites (`{origin}/greenhouse/...`) go through the origin mapping and duplicate-id
+//!   check before they are forwarded (see origin.rs).
+//! - Frames, decode failures and CRC failures are counted per node for the health score (node_health.rs);
+//!   CRC failures (RF corruption) are logged apart from malformed payloads (wrong firmware).
+//! - Readings outside their plausible range are scrubbed before forwarding (sanitize.rs).
+//! - While ingestion is paused (all or per node, ingest_pause.rs) samples are decoded and counted as
+//!   usual but not forwarded.
+//! - The ids in the topic are checked against the ids in the frame (before origin mapping); a mismatch
+//!   (misflashed node) is logged with both and, per `subscriber().topic_id_mismatch`, dropped.
+//! - Identical frames from one node within `subscriber().duplicate_window_ms` (bridged double delivery)
+//!   are dropped after the first (dedup.rs) and counted as `Duplicate`.
+//! - A store-and-forward batch (decoder.rs) is split and each sample goes through the steps below on its
+//!   own; duplicate detection looks at the whole publish. Batched samples are placed by their `device_ts`
+//!   (the aggregator back-fills the windows of samples older than the node's open window, aggregator.rs);
+//!   batch frames without one (v1..v3) are rejected and counted as `UntimedBatch`, and an empty batch
+//!   forwards nothing.
+//! - Nodes with a layout in the schema registry (schema.rs, by topic ids) are decoded as that layout
+//!   (`decode_as`); a frame of another layout is `LayoutMismatch`. Other nodes keep the length heuristic.
+//! - Greenhouses with a key in `frame_mac().keys_file` must sign their frames (frame_mac.rs); a missing
+//!   or wrong HMAC trailer is `BadMac`. Other greenhouses are not checked.
+//! - Retained data frames follow `subscriber().retained`: by default they are used on the first connection
+//!   after startup (UI fill) and dropped after a reconnect, where they would land in the current window
+//!   as if fresh. Dropped ones are counted as `Retained`; no ack is sent for them.
+//! - On app shutdown (shutdown.rs) the subscriber sends DISCONNECT (so the broker does not publish our
+//!   will or hold the session as lost) and returns, closing the decoded queue behind it.
+//! - Fresh data publishes are counted per node and minute for the slow-node check (node_rates.rs).
+//! - Every publish is kept with its outcome in the raw capture ring (raw_capture.rs, `dump_raw_payloads`),
+//!   and appended to the traffic recording when one was asked for (recording.rs, `--record`).
+//! - Decode failures are logged with their reason and counted per `DecodeError` variant
+//!   (`decode_errors` in `get_pipeline_stats`); decoded frames per kind and failures are also counted
+//!   for the frame-rate badge (decoder_stats.rs).
 
-use rumqttc::{Event, Packet, QoS};
-use std::time::Duration;
-use tokio::{sync::mpsc, time::sleep};
+use rumqttc::{AsyncClient, Event, EventLoop, Outgoing, Packet, Publish, QoS, SubscribeFilter, SubscribeReasonCode};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::{sleep, timeout};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
-use crate::services::mqtt::config::mqtt_auth;
+use crate::services::channels::QueueSender;
+use crate::services::mqtt::failover::Failover;
+use crate::services::mqtt::auth::{last_broker, mqtt_auth, mqtt_brokers, remember_broker, sensor_subscriptions, Subscription};
+use crate::services::mqtt::config::{frame_mac, reconnect, subscriber, RetainedPolicy, TopicIdPolicy};
 use crate::services::mqtt::core::new_client;
-use super::decoder::{decode_payload, Decoded};
+use crate::services::mqtt::recording::Recorder;
+use crate::services::mqtt::schema::SchemaShared;
+use crate::services::node_health::{count_frame, count_out_of_range, HealthCountersShared};
+use crate::services::supervisor::Lease;
+use super::ack::{ids_from_topic, AckShared, Acker};
+use super::decoder::{decode_as, decode_batch, DecodeError, Decoded};
+use super::availability::{parse_availability, AvailabilityTracker};
+use super::conn_status::{MqttStatus, StatusReporter};
+use super::raw_capture::RawCaptureShared;
+use super::ingest_pause::IngestPauseShared;
+use super::decoder_stats::DecoderStatsShared;
+use super::node_rates::NodeRatesShared;
+use super::dedup::Dedup;
+use super::frame_mac::FrameKeys;
+use super::origin::{origin_of, OriginShared};
+use super::sanitize::scrub_out_of_range;
 
-/// Public entry: provide a Sender so we never block on the hot path.
-/// We use `try_send` to avoid backpressure stalls; if full, we drop a sample.
-pub async fn run_debug_subscriber(tx: mpsc::Sender<Decoded>) {
-    let auth = mqtt_auth();
-    let topic = "greenhouse/+/node/+/data";
+/// Frames dropped since startup by reason: `DecodeError::name`, `TopicIdMismatch`, `Duplicate`, `Retained`.
+pub type DecodeErrorsShared = Arc<RwLock<BTreeMap<&'static str, u64>>>;
 
-    let mut backoff_ms: u64 = 250;
+/// One configured filter and what the broker made of it on the current connection.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriptionInfo {
+    pub filter: String,
+    pub requested_qos: u8,
+    /// None until the SUBACK arrives (or while disconnected).
+    pub granted_qos: Option<u8>,
+    pub rejected: bool,
+}
 
-    loop {
-        let (client, mut eventloop) = new_client("sensor-subscriber", auth);
+/// Subscriptions of the sensor subscriber, in subscribe order.
+pub type SubscriptionsShared = Arc<RwLock<Vec<SubscriptionInfo>>>;
 
-        if let Err(e) = client.subscribe(topic, QoS::AtLeastOnce).await {
-            eprintln!("[MQTT] subscribe error: {e}");
-            sleep(Duration::from_millis(backoff_ms)).await;
-            backoff_ms = (backoff_ms * 2).min(10_000);
-            continue;
+/// Shared state the subscriber reads and updates, handed over as one bundle.
+#[derive(Clone)]
+pub struct SubscriberShared {
+    pub acks: AckShared,
+    pub origins: OriginShared,
+    pub health: HealthCountersShared,
+    pub decode_errors: DecodeErrorsShared,
+    pub decoder_stats: DecoderStatsShared,
+    pub schemas: SchemaShared,
+    pub subscriptions: SubscriptionsShared,
+    pub status: StatusReporter,
+    pub availability: AvailabilityTracker,
+    pub raw: RawCaptureShared,
+    pub pause: IngestPauseShared,
+    pub rates: NodeRatesShared,
+    /// Traffic recording to write (`--record`), None when not recording.
+    pub record_to: Option<PathBuf>,
+}
+
+/// Forget the granted QoS of every filter; a new connection starts unacknowledged.
+fn reset_subscriptions(shared: &SubscriptionsShared, subs: &[Subscription]) {
+    if let Ok(mut s) = shared.write() {
+        *s = subs.iter().map(|c| SubscriptionInfo { filter: c.filter.clone(), requested_qos: c.qos, granted_qos: None, rejected: false }).collect();
+    }
+}
+
+/// Record the broker's per-filter return codes, which come in subscribe order.
+fn record_suback(shared: &SubscriptionsShared, status: &StatusReporter, codes: &[SubscribeReasonCode]) {
+    let Ok(mut s) = shared.write() else { return };
+    for (info, code) in s.iter_mut().zip(codes) {
+        match code {
+            SubscribeReasonCode::Success(q) => {
+                info.granted_qos = Some(*q as u8);
+                info!(target: "MQTT", "Subscribed: '{}' (qos {}, granted {})", info.filter, info.requested_qos, *q as u8);
+                status.report(MqttStatus::Subscribed { topic: info.filter.clone() });
+            }
+            SubscribeReasonCode::Failure => {
+                info.rejected = true;
+                error!(target: "MQTT", "broker rejected subscription '{}'", info.filter);
+            }
+        }
+    }
+}
+
+/// (greenhouse_id, node_id) of a node data topic, local or bridged (`{origin}/greenhouse/...`).
+fn topic_ids(topic: &str) -> Option<(u16, u16)> {
+    topic.find("greenhouse/").and_then(|i| ids_from_topic(&topic[i..]))
+}
+
+/// Topic ids when they disagree with the frame's own ids; None when they match or the topic has none.
+fn topic_mismatch(topic: &str, decoded: &Decoded) -> Option<(u16, u16)> {
+    topic_ids(topic).filter(|&t| t != decoded.ids())
+}
+
+/// Whether a data publish goes on to decoding; only retained ones can be refused. `reconnected` is false
+/// on the first connection since startup.
+fn accept_retained(p: &Publish, policy: RetainedPolicy, reconnected: bool) -> bool {
+    // FirstConnect keeps them until the first reconnect
+    !p.retain || (policy != RetainedPolicy::Drop && (policy == RetainedPolicy::Keep || !reconnected))
+}
+
+/// Availability message on a node status topic; bridged greenhouse ids are mapped like frames.
+fn on_status_message(topic: &str, payload: &[u8], retained: bool, tracker: &AvailabilityTracker, origins: &OriginShared, now_ms: i64) {
+    let Some((gh, node)) = topic_ids(topic) else { return };
+    if payload.is_empty() { return; } // retained status cleared on the broker
+    let Some(status) = parse_availability(payload) else {
+        warn!(target: "DATA", "unknown node status '{}' on '{topic}'", String::from_utf8_lossy(payload));
+        return;
+    };
+    let gh = origins.read().map(|o| o.effective_gh(origin_of(topic), gh)).unwrap_or(gh);
+    tracker.on_status((gh, node), status, retained, now_ms);
+}
+
+/// Every sample of one publish: MAC checked for keyed greenhouses, then decoded as the node's declared
+/// layout or by length.
+fn decode_publish(topic: &str, payload: &[u8], keys: &FrameKeys, schemas: &SchemaShared) -> Vec<Result<Decoded, DecodeError>> {
+    let ids = topic_ids(topic);
+    let body = match ids.map_or(Ok(payload), |(gh, _)| keys.verify(gh, payload)) {
+        Ok(b) => b,
+        Err(e) => return vec![Err(e)],
+    };
+    let frames = match ids.and_then(|(gh, node)| schemas.read().ok().and_then(|s| s.layout_for(gh, node))) {
+        Some(layout) => decode_as(body, layout),
+        None => decode_batch(body),
+    };
+    frames.into_iter().map(|r| r.and_then(|d| keys.check_ids(ids.map(|t| t.0), d))).collect()
+}
+
+/// Samples of one recorded data publish that the live subscriber would forward (replay path of
+/// recording.rs): decoded, topic ids checked per `subscriber().topic_id_mismatch`, range scrubbed.
+pub(crate) fn replay_publish(topic: &str, payload: &[u8], keys: &FrameKeys, schemas: &SchemaShared) -> Vec<Decoded> {
+    if topic.ends_with("/status") { return Vec::new(); }
+    let mut out = Vec::new();
+    for res in decode_publish(topic, payload, keys, schemas) {
+        match res {
+            Ok(mut decoded) => {
+                if topic_mismatch(topic, &decoded).is_some() && subscriber().topic_id_mismatch != TopicIdPolicy::Warn { continue; }
+                scrub_out_of_range(&mut decoded);
+                out.push(decoded);
+            }
+            Err(e) => warn!(target: "REPLAY", "decode skipped: {e} on '{topic}'"),
         }
+    }
+    out
+}
 
-        println!("[MQTT] Subscribed: '{topic}'");
+/// Report the next retry (an error log after every further minute down) and wait for it; false when
+/// the app shuts down meanwhile.
+async fn wait_to_retry(failover: &mut Failover, status: &StatusReporter, shutdown: &CancellationToken) -> bool {
+    let r = failover.on_failure(Instant::now());
+    if r.loud {
+        error!(target: "MQTT", "broker unreachable for {}s ({} attempts); still retrying", r.downtime_ms / 1000, r.attempt);
+    }
+    let (_, next) = failover.current();
+    status.set_broker(r.next, next);
+    status.report(MqttStatus::Reconnecting { attempt: r.attempt, next_retry_ms: r.delay_ms, downtime_ms: r.downtime_ms });
+    tokio::select! {
+        _ = sleep(Duration::from_millis(r.delay_ms)) => true,
+        _ = shutdown.cancelled() => false,
+    }
+}
 
+/// Send DISCONNECT and drive the event loop until it is out (at most a second).
+async fn disconnect(client: &AsyncClient, eventloop: &mut EventLoop) {
+    if client.try_disconnect().is_err() { return; }
+    let sent = timeout(Duration::from_secs(1), async {
         loop {
             match eventloop.poll().await {
+                Ok(Event::Outgoing(Outgoing::Disconnect)) => return true,
+                Ok(_) => {}
+                Err(_) => return false,
+            }
+        }
+    }).await;
+    if sent == Ok(true) { info!(target: "MQTT", "disconnected from broker"); }
+}
+
+/// Public entry: samples go to `tx`, whose full policy decides between dropping and a bounded wait.
+/// Returns once `shutdown` is cancelled.
+pub async fn run_debug_subscriber(tx: Lease<QueueSender<Decoded>>, shared: SubscriberShared, shutdown: CancellationToken) {
+    let SubscriberShared { acks, origins, health, decode_errors, decoder_stats, schemas, subscriptions, status, availability, raw, pause, rates, record_to } = shared;
+    let subs = sensor_subscriptions();
+    let mut acker = Acker::default();
+    let mut dedup = Dedup::default(); // survives reconnects: a redelivery after reconnect is still a duplicate
+    let keys = FrameKeys::load(frame_mac().keys_file);
+    let mut recorder = record_to.and_then(|path| match Recorder::create(&path) {
+        Ok(r) => {
+            info!(target: "MQTT", "recording traffic to {}", path.display());
+            Some(r)
+        }
+        Err(e) => {
+            error!(target: "MQTT", "cannot record traffic to {}: {e}", path.display());
+            None
+        }
+    });
+
+    let mut failover = Failover::new(mqtt_brokers().to_vec(), last_broker(), reconnect(), Instant::now());
+    let mut remembered = last_broker();
+    let mut sessions: u32 = 0; // CONNACKs since startup
+
+    loop {
+        let (index, broker) = failover.current();
+        status.set_broker(index, broker);
+        let mut auth = mqtt_auth();
+        auth.host = broker.host.clone();
+        auth.port = broker.port;
+        let (client, mut eventloop) = new_client("sensor-subscriber", &auth);
+
+        reset_subscriptions(&subscriptions, subs);
+        let filters = subs.iter().map(|s| SubscribeFilter::new(s.filter.clone(), rumqttc::qos(s.qos).unwrap_or(QoS::AtLeastOnce)));
+        if subs.is_empty() {
+            warn!(target: "MQTT", "no sensor subscriptions configured");
+        } else if let Err(e) = client.subscribe_many(filters).await {
+            error!(target: "MQTT", "subscribe error: {e}");
+            status.report(MqttStatus::Disconnected { reason: format!("subscribe error: {e}") });
+            if !wait_to_retry(&mut failover, &status, &shutdown).await { return; }
+            continue;
+        }
+
+        loop {
+            let polled = tokio::select! {
+                polled = eventloop.poll() => polled,
+                _ = shutdown.cancelled() => {
+                    disconnect(&client, &mut eventloop).await;
+                    return;
+                }
+            };
+            if polled.is_ok() { failover.on_alive(Instant::now()); }
+            match polled {
                 Ok(Event::Incoming(Packet::Publish(p))) => {
-                    if let Some(decoded) = decode_payload(&p.payload) {
-                        // Non-blocking send; drop if channel is full to keep MQTT loop hot.
-                        let _ = tx.try_send(decoded);
-                    } else {
-                        eprintln!("[DATA] decode skipped: malformed payload ({} bytes)", p.payload.len());
+                    let started = Instant::now();
+                    let received_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0);
+                    if let Some(r) = recorder.as_mut() {
+                        if let Err(e) = r.write(&p.topic, &p.payload) {
+                            error!(target: "MQTT", "traffic recording stopped: {e}");
+                            recorder = None;
+                        }
+                    }
+                    if p.topic.ends_with("/status") {
+                        raw.record(received_ms, &p.topic, topic_ids(&p.topic), p.retain, &p.payload, "Status".to_string());
+                        on_status_message(&p.topic, &p.payload, p.retain, &availability, &origins, received_ms);
+                        continue;
+                    }
+                    if let Some((gh, node)) = topic_ids(&p.topic).filter(|_| !p.retain) {
+                        let gh = origins.read().map(|o| o.effective_gh(origin_of(&p.topic), gh)).unwrap_or(gh);
+                        rates.count((gh, node));
+                    }
+                    if !accept_retained(&p, subscriber().retained, sessions > 1) {
+                        if let Ok(mut m) = decode_errors.write() { *m.entry("Retained").or_default() += 1; }
+                        raw.record(received_ms, &p.topic, topic_ids(&p.topic), p.retain, &p.payload, "Retained".to_string());
+                        continue;
                     }
+                    let frames = decode_publish(&p.topic, &p.payload, &keys, &schemas);
+                    let decode_us = started.elapsed().as_micros() as u64;
+                    let outcome = frames.iter().map(|r| match r { Ok(d) => d.layout().as_str(), Err(e) => e.name() }).collect::<Vec<_>>().join(",");
+                    raw.record(received_ms, &p.topic, topic_ids(&p.topic), p.retain, &p.payload, outcome);
+                    // once per publish: a redelivered batch repeats every one of its samples
+                    let duplicate = frames.iter().find_map(|r| r.as_ref().ok())
+                        .is_some_and(|d| dedup.is_duplicate(d.ids(), &p.payload, started));
+                    let ack_on = acks.read().is_ok_and(|m| !m.is_empty());
+                    for res in frames {
+                        decoder_stats.count(res.as_ref().map_err(|e| *e));
+                        if ack_on {
+                            let ids = match &res { Ok(d) => Some(d.ids()), Err(_) => ids_from_topic(&p.topic) };
+                            if let Some((topic, payload)) = ids.and_then(|ids| acker.on_frame(&acks, ids, res.as_ref().map_err(|e| *e), p.payload.len(), received_ms, decode_us)) {
+                                if let Err(e) = client.try_publish(topic, QoS::AtMostOnce, false, payload) {
+                                    warn!(target: "ACK", "publish skipped: {e}");
+                                }
+                            }
+                        }
+                        match res {
+                            Ok(mut decoded) => {
+                                if let Some(t) = topic_mismatch(&p.topic, &decoded) {
+                                    if let Ok(mut m) = decode_errors.write() { *m.entry("TopicIdMismatch").or_default() += 1; }
+                                    let (gh, node) = decoded.ids();
+                                    let keep = subscriber().topic_id_mismatch == TopicIdPolicy::Warn;
+                                    warn!(
+                                        target: "DATA", "topic ids GH:{} Node:{} but payload ids GH:{} Node:{} on '{}'{}",
+                                        t.0, t.1, gh, node, p.topic, if keep { "" } else { "; dropped" }
+                                    );
+                                    if !keep { continue; }
+                                }
+                                if duplicate {
+                                    if let Ok(mut m) = decode_errors.write() { *m.entry("Duplicate").or_default() += 1; }
+                                    continue;
+                                }
+                                let pass = origins.write().map(|mut o| o.route(origin_of(&p.topic), &mut decoded, received_ms)).unwrap_or(true);
+                                if pass {
+                                    let scrubbed = scrub_out_of_range(&mut decoded);
+                                    count_frame(&health, decoded.ids(), Ok(&decoded));
+                                    count_out_of_range(&health, decoded.ids(), scrubbed.len());
+                                    if pause.holds(decoded.ids()) { continue; }
+                                    tx.send(decoded).await;
+                                }
+                            }
+                            Err(e) => {
+                                if let Some(ids) = topic_ids(&p.topic) { count_frame(&health, ids, Err(e)); }
+                                if let Ok(mut m) = decode_errors.write() { *m.entry(e.name()).or_default() += 1; }
+                                if e == DecodeError::CrcMismatch {
+                                    warn!(target: "DATA", "decode skipped: CRC mismatch ({} bytes) on '{}'", p.payload.len(), p.topic);
+                                } else {
+                                    warn!(target: "DATA", "decode skipped: {e} on '{}'", p.topic);
+                                }
+                            }
+                        }
+                    }
+                }
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    failover.on_connected(Instant::now());
+                    sessions += 1;
+                    if index > 0 { info!(target: "MQTT", "connected via backup broker {}:{}", auth.host, auth.port); }
+                    if index != remembered {
+                        remember_broker(failover.current().1);
+                        remembered = index;
+                    }
+                    status.report(MqttStatus::Connected);
                 }
+                Ok(Event::Incoming(Packet::SubAck(a))) => record_suback(&subscriptions, &status, &a.return_codes),
                 Ok(Event::Incoming(_)) => {}
                 Ok(Event::Outgoing(_)) => {}
                 Err(e) => {
-                    eprintln!("[MQTT] eventloop error: {e}");
+                    warn!(target: "MQTT", "eventloop error: {e}");
+                    reset_subscriptions(&subscriptions, subs);
+                    status.report(MqttStatus::Disconnected { reason: e.to_string() });
                     break; // reconnect with backoff
                 }
             }
         }
 
-        sleep(Duration::from_millis(backoff_ms)).await;
-        backoff_ms = (backoff_ms * 2).min(10_000);
+        if !wait_to_retry(&mut failover, &status, &shutdown).await { return; }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::decoder::encode_payload;
+
+    fn frame(gh: u16, node: u16) -> Vec<u8> {
+        encode_payload(&standard(gh, node, None))
+    }
+
+    /// A v4 frame measured at `device_ts`, as store-and-forward batches carry them.
+    fn timed_frame(gh: u16, node: u16, device_ts: u32) -> Vec<u8> {
+        encode_payload(&standard(gh, node, Some(device_ts)))
+    }
+
+    fn standard(gh: u16, node: u16, device_ts: Option<u32>) -> Decoded {
+        Decoded::Standard {
+            greenhouse_id: gh, node_id: node,
+            air_temp_c: 24.5, leaf_temp_c: 23.0, bag_temp_c: 21.5, air_rh_pct: 68.0,
+            bag_rh1_pct: 80.0, bag_rh2_pct: 81.0, bag_rh3_pct: 82.0, bag_rh4_pct: 83.0, bag_rh_avg_pct: 81.5,
+            par_value: 420, weight_g: 1200.0,
+            ea_air_kpa: 2.1, ea_leaf_kpa: 2.4, es_kpa: 3.1, vpd_kpa: 1.0,
+            battery_v: device_ts.map(|_| 3.7), rssi_dbm: device_ts.map(|_| -60.0), seq: device_ts.map(|t| t as u16), device_ts,
+        }
+    }
+
+    /// (topic mismatch, forwarded by the replay path) of every frame of a synthetic publish.
+    fn check(p: &Publish) -> (Vec<Option<(u16, u16)>>, usize) {
+        let (keys, schemas) = (FrameKeys::default(), SchemaShared::default());
+        let mismatches = decode_publish(&p.topic, &p.payload, &keys, &schemas).into_iter()
+            .map(|r| topic_mismatch(&p.topic, &r.unwrap()))
+            .collect();
+        (mismatches, replay_publish(&p.topic, &p.payload, &keys, &schemas).len())
+    }
+
+    #[test]
+    fn matching_topic_and_payload_ids_pass() {
+        let p = Publish::new("greenhouse/1/node/3/data", QoS::AtLeastOnce, frame(1, 3));
+        assert_eq!(check(&p), (vec![None], 1));
+        // bridged brokers prefix the topic
+        let p = Publish::new("site-b/greenhouse/1/node/3/data", QoS::AtLeastOnce, frame(1, 3));
+        assert_eq!(check(&p), (vec![None], 1));
+    }
+
+    #[test]
+    fn misflashed_node_is_caught_and_dropped() {
+        // the misflashed node: topic says GH 2 node 7, the frame says GH 1 node 3
+        let p = Publish::new("greenhouse/2/node/7/data", QoS::AtLeastOnce, frame(1, 3));
+        assert!(subscriber().topic_id_mismatch == TopicIdPolicy::Drop);
+        assert_eq!(check(&p), (vec![Some((2, 7))], 0));
+        // one node id off is enough
+        let p = Publish::new("greenhouse/1/node/4/data", QoS::AtLeastOnce, frame(1, 3));
+        assert_eq!(check(&p), (vec![Some((1, 4))], 0));
+    }
+
+    #[test]
+    fn batch_frames_are_checked_one_by_one() {
+        let mut batch = vec![3];
+        for (i, node) in [3, 5, 3].into_iter().enumerate() { batch.extend(timed_frame(1, node, 1_760_000_000 + 10 * i as u32)); }
+        let p = Publish::new("greenhouse/1/node/3/data", QoS::AtLeastOnce, batch);
+        assert_eq!(check(&p), (vec![None, Some((1, 3)), None], 2));
+    }
+
+    #[test]
+    fn batches_need_the_node_clock() {
+        let (keys, schemas) = (FrameKeys::default(), SchemaShared::default());
+        let topic = "greenhouse/1/node/3/data";
+        // v1 frames in a batch have no measurement time: each one rejected, none forwarded
+        let mut untimed = vec![2];
+        for _ in 0..2 { untimed.extend(frame(1, 3)); }
+        let got: Vec<_> = decode_publish(topic, &untimed, &keys, &schemas).into_iter().map(Result::unwrap_err).collect();
+        assert_eq!(got, [DecodeError::UntimedBatch, DecodeError::UntimedBatch]);
+        assert!(replay_publish(topic, &untimed, &keys, &schemas).is_empty());
+        // an empty batch holds no samples, and no error either
+        assert!(decode_publish(topic, &[0u8], &keys, &schemas).is_empty());
+        assert!(replay_publish(topic, &[0u8], &keys, &schemas).is_empty());
+    }
+
+    #[test]
+    fn topics_without_ids_are_not_checked() {
+        for topic in ["lab/rig/data", "greenhouse/x/node/3/data", "greenhouse/1/sensor/3/data"] {
+            let p = Publish::new(topic, QoS::AtMostOnce, frame(1, 3));
+            assert_eq!(check(&p), (vec![None], 1), "{topic}");
+        }
+    }
+
+    #[test]
+    fn declared_layout_is_used_before_the_length_guess() {
+        use crate::services::mqtt::greenhouse_sensor::decoder::FrameLayout;
+        use crate::services::storage::node_schema::NodeSchemaEntry;
+        let keys = FrameKeys::default();
+        let schemas = SchemaShared::default();
+        let p = frame(1, 3);
+        assert!(decode_publish("greenhouse/1/node/3/data", &p, &keys, &schemas)[0].is_ok());
+        schemas.write().unwrap().replace(vec![
+            NodeSchemaEntry { greenhouse_id: Some(1), node_from: 3, node_to: 3, layout: FrameLayout::Soil, updated_ms: 0 },
+        ]);
+        assert_eq!(decode_publish("greenhouse/1/node/3/data", &p, &keys, &schemas)[0].unwrap_err(),
+                   DecodeError::LayoutMismatch { expected: FrameLayout::Soil });
+        // looked up by the topic ids: other nodes keep the length heuristic
+        assert!(decode_publish("greenhouse/1/node/4/data", &frame(1, 4), &keys, &schemas)[0].is_ok());
+    }
+
+    fn publish(retain: bool) -> Publish {
+        let mut p = Publish::new("greenhouse/1/node/3/data", QoS::AtLeastOnce, frame(1, 3));
+        p.retain = retain;
+        p
+    }
+
+    #[test]
+    fn live_frames_pass_under_every_retained_policy() {
+        for policy in [RetainedPolicy::Keep, RetainedPolicy::FirstConnect, RetainedPolicy::Drop] {
+            for reconnected in [false, true] {
+                assert!(accept_retained(&publish(false), policy, reconnected));
+            }
+        }
+    }
+
+    #[test]
+    fn retained_frames_follow_the_policy_and_the_session() {
+        let retained = publish(true);
+        // (policy, first connection, after a reconnect)
+        for (policy, first, later) in [
+            (RetainedPolicy::Keep, true, true),
+            (RetainedPolicy::FirstConnect, true, false),
+            (RetainedPolicy::Drop, false, false),
+        ] {
+            assert_eq!(accept_retained(&retained, policy, false), first);
+            assert_eq!(accept_retained(&retained, policy, true), later);
+        }
+        assert!(subscriber().retained == RetainedPolicy::FirstConnect, "startup UI fill is the default");
     }
 }
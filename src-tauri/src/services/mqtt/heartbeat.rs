@@ -0,0 +1,104 @@
+//! Retained app heartbeat, so ops can see from the broker whether each kiosk app is alive.
+//! - `{heartbeat().topic_prefix}/{client_id}/status` with the publisher's client id (publisher.rs), which
+//!   is unique per installation. JSON: `state` "online", app version, uptime, decoded frames (since start
+//!   or `reset_decoder_stats`), DB writes (batch flushes), time.
+//! - Sent every `every_secs` and right after each (re)connect; nothing before the first connect, and
+//!   nothing is queued while the broker is down.
+//! - The publisher connects with a retained Last Will `{"state":"offline"}` on the same topic, so a
+//!   crash or lost network shows within the keep-alive. A normal exit sends it itself (DISCONNECT
+//!   suppresses the will).
+//! - An empty `topic_prefix` turns the heartbeat and the will off.
+
+use rumqttc::{LastWill, QoS};
+use serde::Serialize;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::{interval, sleep};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use super::auth::{mqtt_auth, MqttAuth};
+use super::config::heartbeat;
+use super::core::client_id;
+use super::greenhouse_sensor::decoder_stats::DecoderStatsShared;
+use super::publisher::{connected_client, PublisherShared};
+use crate::services::storage::sqlite::StorageStatsShared;
+
+pub const OFFLINE: &str = r#"{"state":"offline"}"#;
+/// How often the task looks for a new connection between heartbeats.
+const CHECK_EVERY: Duration = Duration::from_secs(1);
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Heartbeat {
+    pub state: &'static str,
+    pub client_id: String,
+    pub app_version: &'static str,
+    pub uptime_secs: u64,
+    pub decoded_frames: u64,
+    pub db_writes: u64,
+    pub ts_ms: i64,
+}
+
+/// `{topic_prefix}/{publisher client id}/{leaf}`, this app's topics for ops (`query` too, query.rs);
+/// None when the prefix is empty.
+pub fn app_topic(auth: &MqttAuth, leaf: &str) -> Option<String> {
+    let prefix = heartbeat().topic_prefix.trim_end_matches('/');
+    (!prefix.is_empty()).then(|| format!("{prefix}/{}/{leaf}", client_id("publisher", auth)))
+}
+
+/// Topic of the publisher's heartbeat; None when turned off.
+pub fn heartbeat_topic(auth: &MqttAuth) -> Option<String> {
+    app_topic(auth, "status")
+}
+
+/// Retained `OFFLINE` on `topic`.
+pub fn last_will(topic: String) -> LastWill {
+    LastWill::new(topic, OFFLINE, QoS::AtLeastOnce, true)
+}
+
+/// Public task: heartbeat on the publisher's client until `shutdown`, then `OFFLINE`.
+pub async fn run_heartbeat(publisher: PublisherShared, decoder_stats: DecoderStatsShared, storage_stats: StorageStatsShared, shutdown: CancellationToken) {
+    let auth = mqtt_auth();
+    let Some(topic) = heartbeat_topic(&auth) else { return };
+    let every = Duration::from_secs(heartbeat().every_secs.max(1));
+    let started = Instant::now();
+    let mut tick = interval(CHECK_EVERY);
+    let mut sent: Option<(u64, Instant)> = None; // connection number and time of the last heartbeat
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = shutdown.cancelled() => break,
+        }
+        let Some((client, session)) = connected_client(&publisher) else { continue };
+        if sent.is_some_and(|(s, at)| s == session && at.elapsed() < every) { continue; }
+        if sent.is_none() { info!(target: "MQTT", "app heartbeat on '{topic}' every {}s", every.as_secs()); }
+        let snap = decoder_stats.snapshot();
+        let beat = Heartbeat {
+            state: "online",
+            client_id: client_id("publisher", &auth),
+            app_version: env!("CARGO_PKG_VERSION"),
+            uptime_secs: started.elapsed().as_secs(),
+            decoded_frames: snap.standard_ok + snap.outdoor_ok + snap.soil_ok,
+            db_writes: storage_stats.read().map(|s| s.flushes).unwrap_or(0),
+            ts_ms: now_ms(),
+        };
+        match serde_json::to_vec(&beat) {
+            Ok(payload) => {
+                if let Err(e) = client.try_publish(topic.as_str(), QoS::AtLeastOnce, true, payload) {
+                    warn!(target: "MQTT", "heartbeat skipped: {e}");
+                }
+            }
+            Err(e) => warn!(target: "MQTT", "serialize heartbeat failed: {e}"),
+        }
+        sent = Some((session, Instant::now()));
+    }
+    // the publisher keeps running through shutdown; give it a moment to send
+    if let Some((client, _)) = connected_client(&publisher) {
+        if client.try_publish(topic.as_str(), QoS::AtLeastOnce, true, OFFLINE).is_ok() {
+            sleep(Duration::from_millis(300)).await;
+        }
+    }
+}
@@ -1,3 +1,15 @@
 pub mod config;
+pub mod auth;
+pub mod credentials;
 pub mod core;
+pub mod backoff;
+pub mod failover;
 pub mod greenhouse_sensor;
+pub mod site_summary;
+pub mod remote_cmd;
+pub mod publisher;
+pub mod heartbeat;
+pub mod query;
+pub mod replay;
+pub mod recording;
+pub mod schema;
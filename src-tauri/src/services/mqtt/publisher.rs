@@ -0,0 +1,186 @@
+//! Messages from the dashboard to nodes (`publish_mqtt`), e.g. `tare` / `reboot` on `greenhouse/{gh}/node/{id}/cmd`.
+//! - A dedicated `publisher` client stays connected in the background (same backoff as the subscriber,
+//!   backoff.rs); the sensor subscriber's client is rebuilt on every reconnect and is not shared.
+//! - Topics must start with one of `control_publish().allowed_prefixes` (`+` matches one level); wildcards
+//!   and empty levels are refused. Payload is text or base64 bytes, at most `max_payload_bytes`.
+//! - While the client is not connected the command fails at once instead of queueing; a full request
+//!   queue fails too (`try_publish`).
+//! - The same client carries the app heartbeat and its Last Will (heartbeat.rs).
+
+use base64::Engine;
+use rumqttc::{AsyncClient, Event, Packet};
+use serde::Deserialize;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use super::auth::mqtt_auth;
+use super::backoff::Backoff;
+use super::config::{control_publish, reconnect};
+use super::core::new_client_with;
+use super::heartbeat::{heartbeat_topic, last_will};
+
+#[derive(Default)]
+pub struct PublisherState {
+    client: Option<AsyncClient>,
+    connected: bool,
+    /// CONNACKs since startup.
+    sessions: u64,
+}
+
+pub type PublisherShared = Arc<RwLock<PublisherState>>;
+
+/// `{"text": "tare"}` or `{"base64": "AQID"}`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PublishPayload {
+    Text(String),
+    Base64(String),
+}
+
+impl PublishPayload {
+    fn into_bytes(self) -> Result<Vec<u8>, String> {
+        match self {
+            PublishPayload::Text(s) => Ok(s.into_bytes()),
+            PublishPayload::Base64(s) => base64::engine::general_purpose::STANDARD
+                .decode(s.trim())
+                .map_err(|e| format!("payload is not valid base64: {e}")),
+        }
+    }
+}
+
+/// Ok when `topic` is a concrete topic starting with one of `prefixes`, level by level.
+pub fn check_topic(topic: &str, prefixes: &[&str]) -> Result<(), String> {
+    if topic.is_empty() || topic.contains(['+', '#', '\0']) {
+        return Err(format!("'{topic}' is not a publishable topic"));
+    }
+    let levels: Vec<&str> = topic.split('/').collect();
+    if levels.iter().any(|l| l.is_empty()) {
+        return Err(format!("'{topic}' has an empty level"));
+    }
+    let allowed = prefixes.iter().any(|p| {
+        let want: Vec<&str> = p.split('/').collect();
+        want.len() <= levels.len() && want.iter().zip(&levels).all(|(w, l)| *w == "+" || w == l)
+    });
+    if allowed { Ok(()) } else { Err(format!("'{topic}' is not under an allowed command prefix ({})", prefixes.join(", "))) }
+}
+
+/// Validate and hand one message to the publisher client.
+pub fn publish(shared: &PublisherShared, topic: &str, payload: PublishPayload, qos: u8, retain: bool) -> Result<(), String> {
+    let cfg = control_publish();
+    check_topic(topic, cfg.allowed_prefixes)?;
+    let qos = rumqttc::qos(qos).map_err(|_| format!("QoS {qos} is not 0, 1 or 2"))?;
+    let bytes = payload.into_bytes()?;
+    if bytes.len() > cfg.max_payload_bytes {
+        return Err(format!("payload of {} bytes is over the {} byte limit", bytes.len(), cfg.max_payload_bytes));
+    }
+    let state = shared.read().map_err(|_| "publisher state unavailable".to_string())?;
+    let client = match (&state.client, state.connected) {
+        (Some(c), true) => c,
+        _ => return Err("MQTT publisher is not connected".to_string()),
+    };
+    let len = bytes.len();
+    client.try_publish(topic, qos, retain, bytes).map_err(|e| format!("publish failed: {e}"))?;
+    info!(target: "CMD", "published {len} bytes to '{topic}' (qos {}, retain {retain})", qos as u8);
+    Ok(())
+}
+
+/// The client and its connection number while connected.
+pub fn connected_client(shared: &PublisherShared) -> Option<(AsyncClient, u64)> {
+    let s = shared.read().ok()?;
+    s.client.clone().filter(|_| s.connected).map(|c| (c, s.sessions))
+}
+
+/// Public task: keep the publisher client connected.
+pub async fn run_publisher(shared: PublisherShared) {
+    let mut backoff = Backoff::new(reconnect());
+    loop {
+        let auth = mqtt_auth();
+        let (client, mut eventloop) = new_client_with("publisher", &auth, heartbeat_topic(&auth).map(last_will));
+        if let Ok(mut s) = shared.write() {
+            s.client = Some(client);
+            s.connected = false;
+        }
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    backoff.on_connected(Instant::now());
+                    if let Ok(mut s) = shared.write() {
+                        s.connected = true;
+                        s.sessions += 1;
+                    }
+                }
+                Ok(_) => backoff.on_alive(Instant::now()),
+                Err(e) => {
+                    warn!(target: "CMD", "publisher eventloop error: {e}");
+                    if let Ok(mut s) = shared.write() { s.connected = false; }
+                    break;
+                }
+            }
+        }
+        let r = backoff.on_failure(Instant::now());
+        if r.loud { error!(target: "CMD", "publisher: broker unreachable for {}s; still retrying", r.downtime_ms / 1000); }
+        sleep(Duration::from_millis(r.delay_ms)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CMD: &[&str] = &["greenhouse/+/node/+/cmd"];
+
+    #[test]
+    fn topics_under_an_allowed_prefix_pass() {
+        for topic in ["greenhouse/1/node/3/cmd", "greenhouse/12/node/40/cmd/tare", "greenhouse/a/node/b/cmd"] {
+            assert_eq!(check_topic(topic, CMD), Ok(()), "{topic}");
+        }
+        assert_eq!(check_topic("lab/rig/cmd", &["greenhouse/+/node/+/cmd", "lab/rig"]), Ok(()));
+    }
+
+    #[test]
+    fn topics_outside_the_allow_list_are_refused() {
+        for topic in [
+            "greenhouse/1/node/3/data",       // sensor data, not a command
+            "greenhouse/1/node/3",            // shorter than the prefix
+            "greenhouse/1/cmd",
+            "site-b/greenhouse/1/node/3/cmd", // prefixes anchor at the first level
+            "greenhouse/1/node/3/cmdx",       // levels match whole, not as text prefixes
+        ] {
+            let err = check_topic(topic, CMD).unwrap_err();
+            assert!(err.contains("not under an allowed command prefix"), "{topic}: {err}");
+        }
+    }
+
+    #[test]
+    fn wildcards_and_empty_levels_are_refused() {
+        for topic in ["greenhouse/+/node/3/cmd", "greenhouse/1/node/3/cmd/#", "", "greenhouse/1/node/3/cmd\0"] {
+            assert!(check_topic(topic, CMD).unwrap_err().contains("not a publishable topic"), "{topic:?}");
+        }
+        for topic in ["greenhouse//node/3/cmd", "greenhouse/1/node/3/cmd/", "/greenhouse/1/node/3/cmd"] {
+            assert!(check_topic(topic, CMD).unwrap_err().contains("empty level"), "{topic}");
+        }
+        assert!(check_topic("greenhouse/1/node/3/cmd", &[]).is_err(), "an empty allow-list allows nothing");
+    }
+
+    #[test]
+    fn publish_validates_before_it_needs_a_connection() {
+        let shared = PublisherShared::default();
+        let text = || PublishPayload::Text("tare".into());
+        assert!(publish(&shared, "greenhouse/1/node/3/data", text(), 1, false).unwrap_err().contains("allowed command prefix"));
+        assert_eq!(publish(&shared, "greenhouse/1/node/3/cmd", text(), 3, false).unwrap_err(), "QoS 3 is not 0, 1 or 2");
+        let bad = PublishPayload::Base64("not base64!".into());
+        assert!(publish(&shared, "greenhouse/1/node/3/cmd", bad, 1, false).unwrap_err().contains("not valid base64"));
+        let big = PublishPayload::Text("x".repeat(control_publish().max_payload_bytes + 1));
+        assert!(publish(&shared, "greenhouse/1/node/3/cmd", big, 1, false).unwrap_err().contains("byte limit"));
+        // valid, but no client: fails at once instead of queueing
+        assert_eq!(publish(&shared, "greenhouse/1/node/3/cmd", text(), 1, false).unwrap_err(), "MQTT publisher is not connected");
+    }
+
+    #[test]
+    fn base64_payloads_decode_to_bytes() {
+        assert_eq!(PublishPayload::Base64(" AQID ".into()).into_bytes(), Ok(vec![1, 2, 3]));
+        assert_eq!(PublishPayload::Text("reboot".into()).into_bytes(), Ok(b"reboot".to_vec()));
+    }
+}
@@ -0,0 +1,184 @@
+//! Read-only queries from headless tools (`remote_query().enabled`).
+//! - Listens on `{heartbeat().topic_prefix}/{client_id}/query` (same id as the heartbeat, heartbeat.rs),
+//!   answers on `…/query/response`. Request `{"type", "id"?, "greenhouse_id"?, "node_id"?}`; `id` is
+//!   echoed for correlation.
+//! - `latest_gh_avg`: the last greenhouse average of `greenhouse_id`, or of every greenhouse.
+//!   `latest_node_avg`: the last window of `node_id` in `greenhouse_id`, or of every node in it, or of
+//!   every node. Read from the aggregators' latest snapshots; nothing touches the DB.
+//! - Unknown types and malformed requests get an error reply. An answer over `max_reply_bytes` is
+//!   replaced by an error asking for a narrower query.
+//! - At most `max_per_min` queries a minute are answered; the rest are dropped without a reply (a reply
+//!   would feed the flood) and counted in one warning per minute.
+//! - Unlike remote_cmd.rs there is no signature: queries only read what the dashboard shows.
+
+use rumqttc::{AsyncClient, Event, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use super::auth::mqtt_auth;
+use super::backoff::Backoff;
+use super::config::{reconnect, remote_query};
+use super::core::new_client;
+use super::greenhouse_sensor::aggregator::LatestNodeShared;
+use super::greenhouse_sensor::greenhouse_aggregator::LatestGhShared;
+use super::heartbeat::app_topic;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryRequest {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    id: Value,
+    greenhouse_id: Option<u16>,
+    node_id: Option<u16>,
+}
+
+#[derive(Debug, Serialize)]
+struct QueryReply {
+    id: Value,
+    #[serde(rename = "type")]
+    kind: String,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    ts_ms: i64,
+}
+
+/// The snapshots a query reads.
+#[derive(Clone)]
+pub struct QuerySources {
+    pub latest_gh: LatestGhShared,
+    pub latest_nodes: LatestNodeShared,
+}
+
+/// Fixed one-minute window of answered queries.
+#[derive(Debug)]
+struct RateLimit {
+    window_start: Instant,
+    answered: u32,
+    dropped: u64,
+}
+
+impl RateLimit {
+    fn new(now: Instant) -> Self {
+        RateLimit { window_start: now, answered: 0, dropped: 0 }
+    }
+
+    /// Whether one more query may be answered; at a new window returns the count dropped in the last one.
+    fn allow(&mut self, now: Instant, max_per_min: u32) -> (bool, Option<u64>) {
+        let mut dropped_last = None;
+        if now.duration_since(self.window_start) >= RATE_WINDOW {
+            if self.dropped > 0 { dropped_last = Some(self.dropped); }
+            *self = RateLimit::new(now);
+        }
+        let ok = self.answered < max_per_min;
+        if ok { self.answered += 1; } else { self.dropped += 1; }
+        (ok, dropped_last)
+    }
+}
+
+fn answer(req: &QueryRequest, src: &QuerySources) -> Result<Value, String> {
+    match req.kind.as_str() {
+        "latest_gh_avg" => {
+            let latest = src.latest_gh.read().map_err(|_| "latest averages unavailable".to_string())?;
+            match req.greenhouse_id {
+                Some(gh) => latest.get(&gh).map(|a| json!(a)).ok_or_else(|| format!("no average for greenhouse {gh} yet")),
+                None => {
+                    let mut all: Vec<_> = latest.values().collect();
+                    all.sort_by_key(|a| a.greenhouse_id);
+                    Ok(json!(all))
+                }
+            }
+        }
+        "latest_node_avg" => {
+            let latest = src.latest_nodes.read().map_err(|_| "latest averages unavailable".to_string())?;
+            match (req.greenhouse_id, req.node_id) {
+                (Some(gh), Some(node)) => latest.get(&(gh, node)).map(|a| json!(a))
+                    .ok_or_else(|| format!("no average for GH:{gh} Node:{node} yet")),
+                (None, Some(_)) => Err("node_id needs greenhouse_id".to_string()),
+                (gh, None) => {
+                    let mut rows: Vec<_> = latest.iter().filter(|((g, _), _)| gh.is_none_or(|want| *g == want)).collect();
+                    rows.sort_by_key(|(ids, _)| **ids);
+                    Ok(json!(rows.into_iter().map(|(_, a)| a).collect::<Vec<_>>()))
+                }
+            }
+        }
+        other => Err(format!("unknown query type '{other}' (latest_gh_avg, latest_node_avg)")),
+    }
+}
+
+/// Serialized reply to one request payload, within `max_reply_bytes`.
+fn reply_for(payload: &[u8], src: &QuerySources, max_reply_bytes: usize) -> Vec<u8> {
+    let (id, kind, res) = match serde_json::from_slice::<QueryRequest>(payload) {
+        Ok(req) => (req.id.clone(), req.kind.clone(), answer(&req, src)),
+        Err(e) => (Value::Null, String::new(), Err(format!("malformed query: {e}"))),
+    };
+    let reply = |res: Result<Value, String>| {
+        let (ok, result, error) = match res {
+            Ok(v) => (true, Some(v), None),
+            Err(e) => (false, None, Some(e)),
+        };
+        serde_json::to_vec(&QueryReply { id: id.clone(), kind: kind.clone(), ok, result, error, ts_ms: now_ms() }).unwrap_or_default()
+    };
+    let out = reply(res);
+    if out.len() <= max_reply_bytes { return out; }
+    reply(Err(format!("reply of {} bytes is over the {max_reply_bytes} byte limit; ask for one greenhouse or node", out.len())))
+}
+
+fn publish_reply(client: &AsyncClient, topic: &str, reply: Vec<u8>) {
+    if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, false, reply) {
+        warn!(target: "CMD", "query reply skipped: {e}");
+    }
+}
+
+/// Public task: answer queries until the app exits; no-op when disabled or without a topic prefix.
+pub async fn run_query_responder(src: QuerySources) {
+    let cfg = remote_query();
+    if !cfg.enabled { return; }
+    let auth = mqtt_auth();
+    let (Some(query_topic), Some(reply_topic)) = (app_topic(&auth, "query"), app_topic(&auth, "query/response")) else { return };
+    let mut limit = RateLimit::new(Instant::now());
+    let mut backoff = Backoff::new(reconnect());
+
+    loop {
+        let (client, mut eventloop) = new_client("query", &mqtt_auth());
+        if let Err(e) = client.subscribe(query_topic.as_str(), QoS::AtMostOnce).await {
+            error!(target: "CMD", "query subscribe error: {e}");
+        } else {
+            loop {
+                match eventloop.poll().await {
+                    Ok(Event::Incoming(Packet::Publish(p))) => {
+                        backoff.on_alive(Instant::now());
+                        let (ok, dropped) = limit.allow(Instant::now(), cfg.max_per_min);
+                        if let Some(n) = dropped { warn!(target: "CMD", "{n} queries over the limit of {}/min dropped", cfg.max_per_min); }
+                        if !ok { continue; }
+                        publish_reply(&client, &reply_topic, reply_for(&p.payload, &src, cfg.max_reply_bytes));
+                    }
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        backoff.on_connected(Instant::now());
+                        info!(target: "CMD", "Listening: '{query_topic}'");
+                    }
+                    Ok(_) => backoff.on_alive(Instant::now()),
+                    Err(e) => {
+                        warn!(target: "CMD", "query eventloop error: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+        let r = backoff.on_failure(Instant::now());
+        if r.loud { error!(target: "CMD", "query responder: broker unreachable for {}s; still retrying", r.downtime_ms / 1000); }
+        sleep(Duration::from_millis(r.delay_ms)).await;
+    }
+}
@@ -0,0 +1,192 @@
+//! Raw traffic recordings for offline development: record a day at a site, replay it without a broker.
+//! - `--record <file>` (or `APPTEST_RECORD`) makes the subscriber append every publish it receives.
+//!   `--replay <file>` (or `APPTEST_REPLAY`) runs `run_replay_source` instead of the subscriber, at
+//!   `--replay-speed <factor>` (`APPTEST_REPLAY_SPEED`, default 1; 0 = as fast as possible).
+//! - File: `MAGIC`, then per publish u32 ms since the recording started, u16 topic length, topic (UTF-8),
+//!   u32 payload length, payload; all little-endian. A truncated last record (app killed) is ignored.
+//! - Replay sends data topics through the subscriber's decode path (MAC check, declared layouts, topic id
+//!   check, range check) into the same decoded queue, so aggregation and storage run as on site. Dedup,
+//!   origin mapping, acks and health counters are live-only.
+//! - The JSON-lines files of replay.rs are for single-frame support cases; these are for whole days.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use super::config::frame_mac;
+use super::greenhouse_sensor::decoder::Decoded;
+use super::greenhouse_sensor::frame_mac::FrameKeys;
+use super::greenhouse_sensor::subscriber::replay_publish;
+use super::schema::SchemaShared;
+use crate::services::channels::QueueSender;
+
+pub const MAGIC: &[u8; 8] = b"GHRAW01\n";
+/// Buffered records reach the file at least this often.
+const FLUSH_EVERY: Duration = Duration::from_secs(1);
+
+/// What main.rs was asked to do with recordings.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordingMode {
+    Record(PathBuf),
+    Replay { path: PathBuf, speed: f64 },
+}
+
+fn arg_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1)).cloned()
+}
+
+/// From the command line, else the environment; replay wins over record.
+pub fn recording_mode() -> Option<RecordingMode> {
+    let args: Vec<String> = std::env::args().collect();
+    let get = |flag: &str, var: &str| arg_value(&args, flag).or_else(|| std::env::var(var).ok()).filter(|v| !v.trim().is_empty());
+    if let Some(path) = get("--replay", "APPTEST_REPLAY") {
+        let speed = get("--replay-speed", "APPTEST_REPLAY_SPEED").map_or(1.0, |s| s.trim().parse().unwrap_or_else(|_| {
+            warn!(target: "REPLAY", "replay speed '{s}' is not a number; using 1");
+            1.0
+        }));
+        return Some(RecordingMode::Replay { path: PathBuf::from(path), speed });
+    }
+    get("--record", "APPTEST_RECORD").map(|p| RecordingMode::Record(PathBuf::from(p)))
+}
+
+/// Appends publishes to a recording; the clock starts when the file is opened.
+pub struct Recorder {
+    out: BufWriter<File>,
+    started: Instant,
+    last_flush: Instant,
+}
+
+impl Recorder {
+    /// Start a new recording at `path` (an existing file is replaced).
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut out = BufWriter::new(OpenOptions::new().create(true).write(true).truncate(true).open(path)?);
+        out.write_all(MAGIC)?;
+        let now = Instant::now();
+        Ok(Recorder { out, started: now, last_flush: now })
+    }
+
+    pub fn write(&mut self, topic: &str, payload: &[u8]) -> io::Result<()> {
+        let now = Instant::now();
+        let rel_ms = now.duration_since(self.started).as_millis().min(u32::MAX as u128) as u32;
+        let topic_len = u16::try_from(topic.len()).map_err(|_| io::Error::new(ErrorKind::InvalidInput, "topic too long"))?;
+        let payload_len = u32::try_from(payload.len()).map_err(|_| io::Error::new(ErrorKind::InvalidInput, "payload too long"))?;
+        self.out.write_all(&rel_ms.to_le_bytes())?;
+        self.out.write_all(&topic_len.to_le_bytes())?;
+        self.out.write_all(topic.as_bytes())?;
+        self.out.write_all(&payload_len.to_le_bytes())?;
+        self.out.write_all(payload)?;
+        if now.duration_since(self.last_flush) >= FLUSH_EVERY {
+            self.out.flush()?;
+            self.last_flush = now;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedPublish {
+    pub rel_ms: u32,
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+/// Reads a recording record by record.
+pub struct RecordingReader<R: Read> {
+    input: R,
+}
+
+/// Fill `buf`; false on a clean end of file before the first byte, an error on a partial read.
+fn read_exact_or_eof<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "truncated record")),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+impl<R: Read> RecordingReader<R> {
+    /// Check the header.
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not a traffic recording"));
+        }
+        Ok(RecordingReader { input })
+    }
+
+    /// Next publish; None at the end of the file or at a truncated last record.
+    pub fn next_publish(&mut self) -> io::Result<Option<RecordedPublish>> {
+        let mut head = [0u8; 6];
+        if !read_exact_or_eof(&mut self.input, &mut head)? { return Ok(None); }
+        let rel_ms = u32::from_le_bytes([head[0], head[1], head[2], head[3]]);
+        let mut topic = vec![0u8; u16::from_le_bytes([head[4], head[5]]) as usize];
+        let mut len = [0u8; 4];
+        let mut payload = Vec::new();
+        let complete = (|| -> io::Result<()> {
+            self.input.read_exact(&mut topic)?;
+            self.input.read_exact(&mut len)?;
+            payload = vec![0u8; u32::from_le_bytes(len) as usize];
+            self.input.read_exact(&mut payload)
+        })();
+        match complete {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let topic = String::from_utf8(topic).map_err(|_| io::Error::new(ErrorKind::InvalidData, "topic is not UTF-8"))?;
+        Ok(Some(RecordedPublish { rel_ms, topic, payload }))
+    }
+}
+
+/// Public task: replay `path` into `tx` with the recorded gaps divided by `speed_factor`
+/// (0 or less = no waiting). Ends at the end of the file or on `shutdown`.
+pub async fn run_replay_source(path: PathBuf, speed_factor: f64, tx: QueueSender<Decoded>, schemas: SchemaShared, shutdown: CancellationToken) {
+    let mut reader = match File::open(&path).and_then(|f| RecordingReader::new(BufReader::new(f))) {
+        Ok(r) => r,
+        Err(e) => {
+            error!(target: "REPLAY", "cannot replay {}: {e}", path.display());
+            return;
+        }
+    };
+    info!(target: "REPLAY", "replaying {} at {speed_factor}x", path.display());
+    let keys = FrameKeys::load(frame_mac().keys_file);
+    let started = Instant::now();
+    let (mut publishes, mut samples) = (0u64, 0u64);
+    while !shutdown.is_cancelled() {
+        let rec = match reader.next_publish() {
+            Ok(Some(r)) => r,
+            Ok(None) => break,
+            Err(e) => {
+                error!(target: "REPLAY", "{} unreadable after {publishes} publishes: {e}", path.display());
+                break;
+            }
+        };
+        if speed_factor > 0.0 {
+            let due = started + Duration::from_secs_f64(rec.rel_ms as f64 / 1000.0 / speed_factor);
+            let now = Instant::now();
+            if due > now {
+                tokio::select! {
+                    _ = sleep(due - now) => {}
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+        }
+        publishes += 1;
+        for d in replay_publish(&rec.topic, &rec.payload, &keys, &schemas) {
+            samples += 1;
+            tx.send(d).await;
+        }
+    }
+    info!(target: "REPLAY", "replay of {} done: {publishes} publishes, {samples} samples", path.display());
+}
@@ -0,0 +1,302 @@
+//! Remote commands from the site integrator (opt-in: only with `remote_cmd_secret` in mqtt.toml).
+//! - Listens on `greenhouse/app/{client_id}/cmd`, answers on `greenhouse/app/{client_id}/resp`.
+//! - Envelope `{"body": "<json string>", "sig": "<hex HMAC-SHA256(remote_cmd_secret, body)>"}`;
+//!   body `{"id", "ts_ms", "op", "args"}`. Bad signature, malformed body, `ts_ms` outside the
+//!   skew window or before this process started, a replayed id or an op outside the allow-list is
+//!   rejected and counted.
+//! - Replays: accepted ids are remembered while their `ts_ms` is inside the skew window. The memory
+//!   does not survive a restart, so commands signed before the process started are refused outright.
+//! - Allow-list: `diagnostic_summary` (published to the response topic), `backup_db`,
+//!   `set_window`, `set_thresholds`. The last two are refused: the aggregation window is fixed at
+//!   startup (`APPTEST_WINDOW_SECS` or `aggregation().window_secs`), and no alert threshold can be
+//!   changed at runtime.
+//! - Every accepted command lands in `command_audit` and is mirrored as a `remote_command` event.
+
+use hmac::{Hmac, Mac};
+use rumqttc::{AsyncClient, Event, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::{
+    collections::VecDeque,
+    sync::{atomic::{AtomicU64, Ordering}, Arc},
+    time::{Duration, SystemTime},
+};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use super::auth::{mqtt_auth, remote_cmd_secret};
+use super::config::{remote_cmd, site_summary};
+use super::core::new_client;
+use super::greenhouse_sensor::aggregator::LatestNodeShared;
+use super::greenhouse_sensor::greenhouse_aggregator::LatestGhShared;
+use super::site_summary::build_summary;
+use crate::services::presenter::emitter::EventSink;
+use crate::services::storage::{audit::{record_command, CommandRecord}, maintenance::backup_db, sqlite::db_size_bytes};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const ALLOWED_OPS: &[&str] = &["diagnostic_summary", "backup_db", "set_window", "set_thresholds"];
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+#[derive(Deserialize)]
+struct Envelope { body: String, sig: String }
+
+#[derive(Deserialize)]
+struct CmdBody {
+    id: String,
+    ts_ms: i64,
+    op: String,
+    #[serde(default)]
+    args: Value,
+}
+
+/// Result mirrored to the UI and the response topic.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandOutcome {
+    pub id: String,
+    pub op: String,
+    pub ok: bool,
+    pub result: Value,
+}
+
+fn signature_ok(secret: &[u8], env: &Envelope) -> bool {
+    let Ok(sig) = hex::decode(env.sig.trim()) else { return false };
+    let Ok(mut mac) = HmacSha256::new_from_slice(secret) else { return false };
+    mac.update(env.body.as_bytes());
+    mac.verify_slice(&sig).is_ok()
+}
+
+/// Replay memory: ids accepted within the skew window, and when this process started.
+struct ReplayGuard {
+    started_ms: i64,
+    max_skew_ms: i64,
+    max_ids: usize,
+    seen: VecDeque<(String, i64)>,
+}
+
+impl ReplayGuard {
+    fn new(started_ms: i64, max_skew_ms: i64, max_ids: usize) -> Self {
+        Self { started_ms, max_skew_ms, max_ids, seen: VecDeque::new() }
+    }
+
+    /// Whether `body` may run at `now`; remembers its id if so.
+    fn admit(&mut self, body: &CmdBody, now: i64) -> Result<(), &'static str> {
+        if body.ts_ms < self.started_ms { return Err("signed before this process started"); }
+        if (now - body.ts_ms).abs() > self.max_skew_ms { return Err("stale or future ts_ms"); }
+        // an id older than the skew window can no longer pass the check above
+        self.seen.retain(|(_, ts)| now - ts <= self.max_skew_ms);
+        if self.seen.iter().any(|(id, _)| *id == body.id) { return Err("replayed id"); }
+        if self.seen.len() >= self.max_ids { return Err("too many commands in the skew window"); }
+        self.seen.push_back((body.id.clone(), body.ts_ms));
+        Ok(())
+    }
+}
+
+/// Signature, allow-list, freshness and replay checks.
+fn authenticate(payload: &[u8], secret: &[u8], now: i64, guard: &mut ReplayGuard) -> Result<CmdBody, &'static str> {
+    let env: Envelope = serde_json::from_slice(payload).map_err(|_| "malformed envelope")?;
+    if !signature_ok(secret, &env) { return Err("bad signature"); }
+    let body: CmdBody = serde_json::from_str(&env.body).map_err(|_| "malformed body")?;
+    if !ALLOWED_OPS.contains(&body.op.as_str()) { return Err("op not allowed"); }
+    guard.admit(&body, now)?;
+    Ok(body)
+}
+
+async fn execute(op: &str, (latest, nodes): (&LatestGhShared, &LatestNodeShared), db_path: &'static str, rejected: u64) -> Result<Value, String> {
+    match op {
+        "diagnostic_summary" => {
+            let summary = match (latest.read(), nodes.read()) {
+                (Ok(l), Ok(n)) => build_summary(site_summary().site_id, &l, &n, db_size_bytes(db_path), now_ms()),
+                _ => return Err("latest averages unavailable".into()),
+            };
+            Ok(json!({ "summary": summary, "rejected_commands": rejected }))
+        }
+        "backup_db" => {
+            let ts = now_ms();
+            let path = tokio::task::spawn_blocking(move || backup_db(db_path, ts)).await
+                .map_err(|e| format!("join error: {e}"))??;
+            Ok(json!({ "path": path }))
+        }
+        "set_window" => Err("not supported remotely: the aggregation window is fixed at startup; set APPTEST_WINDOW_SECS on the site PC and restart the app".into()),
+        "set_thresholds" => Err("not supported: no alert threshold can be changed at runtime in this version".into()),
+        _ => Err("op not allowed".into()),
+    }
+}
+
+/// What a command handler needs, cloned per accepted command.
+struct CmdCtx<S: EventSink> {
+    client: AsyncClient,
+    resp_topic: String,
+    latest: LatestGhShared,
+    nodes: LatestNodeShared,
+    db_path: &'static str,
+    sink: Arc<S>,
+}
+
+impl<S: EventSink> Clone for CmdCtx<S> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            resp_topic: self.resp_topic.clone(),
+            latest: self.latest.clone(),
+            nodes: self.nodes.clone(),
+            db_path: self.db_path,
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+/// Run one accepted command: execute, audit, answer on the response topic, mirror to the UI.
+async fn handle<S: EventSink>(ctx: CmdCtx<S>, body: CmdBody, source: String, rejected: u64) {
+    let CmdCtx { client, resp_topic, latest, nodes, db_path, sink } = ctx;
+    let result = execute(&body.op, (&latest, &nodes), db_path, rejected).await;
+    let outcome = CommandOutcome {
+        id: body.id.clone(),
+        op: body.op.clone(),
+        ok: result.is_ok(),
+        result: result.clone().unwrap_or_else(|e| json!({ "error": e })),
+    };
+    info!(target: "CMD", "{} id={} ok={}", outcome.op, outcome.id, outcome.ok);
+
+    let rec = CommandRecord {
+        ts_ms: now_ms(),
+        source,
+        cmd_id: body.id,
+        op: body.op,
+        args: body.args.to_string(),
+        ok: outcome.ok,
+        detail: match &result { Ok(v) if outcome.op == "backup_db" => v.to_string(), Ok(_) => "ok".into(), Err(e) => e.clone() },
+    };
+    match tokio::task::spawn_blocking(move || record_command(db_path, &rec)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!(target: "CMD", "audit insert failed: {e}"),
+        Err(e) => error!(target: "CMD", "audit join error: {e}"),
+    }
+
+    if let Ok(payload) = serde_json::to_vec(&outcome) {
+        if let Err(e) = client.try_publish(resp_topic, QoS::AtLeastOnce, false, payload) {
+            warn!(target: "CMD", "response publish skipped: {e}");
+        }
+    }
+    if let Ok(v) = serde_json::to_value(&outcome) {
+        sink.emit_json("remote_command", v);
+    }
+}
+
+/// Public task: no-op unless mqtt.toml (or the environment) sets `remote_cmd_secret`.
+pub async fn run_remote_commands<S: EventSink>(sink: S, latest: LatestGhShared, nodes: LatestNodeShared, db_path: &'static str) {
+    let cfg = remote_cmd();
+    let secret = remote_cmd_secret();
+    if secret.is_empty() { return; }
+    let cmd_topic = format!("greenhouse/app/{}/cmd", cfg.client_id);
+    let resp_topic = format!("greenhouse/app/{}/resp", cfg.client_id);
+    let sink = Arc::new(sink);
+    let rejected = Arc::new(AtomicU64::new(0));
+    let mut guard = ReplayGuard::new(now_ms(), cfg.max_skew_secs * 1000, cfg.max_seen_ids);
+    let mut backoff_ms: u64 = 250;
+
+    loop {
+        let (client, mut eventloop) = new_client("remote-cmd", &mqtt_auth());
+        if let Err(e) = client.subscribe(cmd_topic.as_str(), QoS::AtLeastOnce).await {
+            error!(target: "CMD", "subscribe error: {e}");
+            sleep(Duration::from_millis(backoff_ms)).await;
+            backoff_ms = (backoff_ms * 2).min(10_000);
+            continue;
+        }
+        info!(target: "CMD", "Listening: '{cmd_topic}'");
+        let ctx = CmdCtx { client, resp_topic: resp_topic.clone(), latest: latest.clone(), nodes: nodes.clone(), db_path, sink: sink.clone() };
+
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::Publish(p))) => {
+                    match authenticate(&p.payload, secret.as_bytes(), now_ms(), &mut guard) {
+                        Ok(body) => {
+                            backoff_ms = 250;
+                            let source = format!("mqtt:{}", p.topic);
+                            tokio::spawn(handle(ctx.clone(), body, source, rejected.load(Ordering::Relaxed)));
+                        }
+                        Err(why) => {
+                            let n = rejected.fetch_add(1, Ordering::Relaxed) + 1;
+                            warn!(target: "CMD", "rejected ({why}), {} bytes; total rejected {n}", p.payload.len());
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    error!(target: "CMD", "eventloop error: {e}");
+                    break;
+                }
+            }
+        }
+
+        sleep(Duration::from_millis(backoff_ms)).await;
+        backoff_ms = (backoff_ms * 2).min(10_000);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"integrator-key";
+    const START: i64 = 1_760_000_000_000;
+
+    fn signed(id: &str, ts_ms: i64, op: &str, key: &[u8]) -> Vec<u8> {
+        let body = json!({ "id": id, "ts_ms": ts_ms, "op": op, "args": {} }).to_string();
+        let mut mac = HmacSha256::new_from_slice(key).unwrap();
+        mac.update(body.as_bytes());
+        let sig = hex::encode(mac.finalize().into_bytes());
+        serde_json::to_vec(&json!({ "body": body, "sig": sig })).unwrap()
+    }
+
+    fn guard() -> ReplayGuard { ReplayGuard::new(START, 300_000, 4) }
+
+    #[test]
+    fn signed_command_is_accepted_once() {
+        let mut g = guard();
+        let now = START + 60_000;
+        let cmd = signed("c1", now - 1_000, "backup_db", SECRET);
+        assert_eq!(authenticate(&cmd, SECRET, now, &mut g).unwrap().op, "backup_db");
+        assert_eq!(authenticate(&cmd, SECRET, now + 5_000, &mut g).err(), Some("replayed id"));
+    }
+
+    #[test]
+    fn bad_or_missing_signatures_are_rejected() {
+        let mut g = guard();
+        let now = START + 60_000;
+        assert_eq!(authenticate(&signed("c1", now, "backup_db", b"other"), SECRET, now, &mut g).err(), Some("bad signature"));
+        assert_eq!(authenticate(br#"{"body": "{}"}"#, SECRET, now, &mut g).err(), Some("malformed envelope"));
+        assert_eq!(authenticate(&signed("c2", now, "drop_tables", SECRET), SECRET, now, &mut g).err(), Some("op not allowed"));
+    }
+
+    #[test]
+    fn commands_from_before_start_or_outside_the_window_are_refused() {
+        let mut g = guard();
+        // captured before a restart and replayed inside the skew window
+        let now = START + 10_000;
+        assert_eq!(authenticate(&signed("old", START - 20_000, "backup_db", SECRET), SECRET, now, &mut g).err(),
+                   Some("signed before this process started"));
+        let later = START + 3_600_000;
+        assert_eq!(authenticate(&signed("late", later - 301_000, "backup_db", SECRET), SECRET, later, &mut g).err(),
+                   Some("stale or future ts_ms"));
+        assert_eq!(authenticate(&signed("ahead", later + 301_000, "backup_db", SECRET), SECRET, later, &mut g).err(),
+                   Some("stale or future ts_ms"));
+    }
+
+    #[test]
+    fn replay_memory_is_bounded_by_the_skew_window() {
+        let mut g = guard();
+        let now = START + 10_000;
+        for i in 0..4 { authenticate(&signed(&format!("c{i}"), now, "backup_db", SECRET), SECRET, now, &mut g).unwrap(); }
+        assert_eq!(authenticate(&signed("c4", now, "backup_db", SECRET), SECRET, now, &mut g).err(),
+                   Some("too many commands in the skew window"));
+        // once the first ones age out of the window there is room again
+        let later = now + 301_000;
+        authenticate(&signed("c5", later, "backup_db", SECRET), SECRET, later, &mut g).unwrap();
+        assert_eq!(g.seen.len(), 1);
+    }
+}
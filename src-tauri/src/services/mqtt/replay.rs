@@ -0,0 +1,100 @@
+//! Replay files: captured MQTT traffic as JSON lines, one `{"ts", "topic", "payload_hex"}` per message.
+//! - `ts` is the receive time in unix ms; `payload_hex` is the raw payload in any form `parse_hex` accepts.
+//! - Blank lines and lines starting with `#` are skipped, so a support engineer can annotate a file.
+//! - `decode_replay` runs every record through the same steps as the live subscriber (topic check,
+//!   decoder, range check, slew guard) with the guard's clock driven by `ts`; no broker or database is involved.
+//!   The `decode-replay` command line (cli.rs) writes the result as CSV.
+//! - A store-and-forward batch gives one outcome per sample, all with the record's line number.
+
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, time::Duration};
+use tokio::time::Instant;
+
+use super::greenhouse_sensor::ack::ids_from_topic;
+use super::greenhouse_sensor::decoder::{parse_hex, decode_batch, Decoded};
+use super::greenhouse_sensor::origin::origin_of;
+use super::greenhouse_sensor::sanitize::{scrub_out_of_range, SlewGuard};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayRecord {
+    pub ts: i64,
+    pub topic: String,
+    pub payload_hex: String,
+}
+
+/// Records with their 1-based line numbers; unparsable lines are kept as errors.
+pub type ReplayLines = Vec<(usize, Result<ReplayRecord, String>)>;
+
+/// Records of a replay file, in file order.
+pub fn load_replay(path: &Path) -> Result<ReplayLines, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+    Ok(text.lines().enumerate()
+        .filter(|(_, l)| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+        .map(|(i, l)| (i + 1, serde_json::from_str::<ReplayRecord>(l).map_err(|e| format!("bad record: {e}"))))
+        .collect())
+}
+
+/// Outcome of one replayed record (or one sample of a batch record).
+#[derive(Debug, Clone)]
+pub struct ReplayOutcome {
+    pub line: usize,
+    pub ts: Option<i64>,
+    pub topic: String,
+    pub origin: String,
+    /// (greenhouse_id, node_id) from the frame, else from the topic.
+    pub ids: Option<(u16, u16)>,
+    /// Frame after the range check and slew guard (scrubbed / rejected readings are missing).
+    pub decoded: Option<Decoded>,
+    pub out_of_range: Vec<&'static str>,
+    pub slew_rejected: Vec<&'static str>,
+    pub error: Option<String>,
+}
+
+/// Run every record through topic check, decoder, range check and slew guard, in file order.
+pub fn decode_replay(records: ReplayLines) -> Vec<ReplayOutcome> {
+    let mut guard = SlewGuard::default();
+    let started = Instant::now();
+    let first_ts = records.iter().find_map(|(_, r)| r.as_ref().ok().map(|r| r.ts)).unwrap_or(0);
+    records.into_iter().flat_map(|(line, rec)| {
+        let rec = match rec {
+            Ok(r) => r,
+            Err(e) => return vec![ReplayOutcome {
+                line, ts: None, topic: String::new(), origin: String::new(), ids: None,
+                decoded: None, out_of_range: Vec::new(), slew_rejected: Vec::new(), error: Some(e),
+            }],
+        };
+        let origin = origin_of(&rec.topic).to_string();
+        let topic_ids = rec.topic.find("greenhouse/").and_then(|i| ids_from_topic(&rec.topic[i..]));
+        let mut out = ReplayOutcome {
+            line, ts: Some(rec.ts), topic: rec.topic.clone(), origin, ids: topic_ids,
+            decoded: None, out_of_range: Vec::new(), slew_rejected: Vec::new(), error: None,
+        };
+        if topic_ids.is_none() || !rec.topic.ends_with("/data") {
+            out.error = Some("not a node data topic".into());
+            return vec![out];
+        }
+        let Some(bytes) = parse_hex(&rec.payload_hex) else {
+            out.error = Some("payload_hex is not valid hex".into());
+            return vec![out];
+        };
+        let frames = decode_batch(&bytes);
+        if frames.is_empty() {
+            out.error = Some("empty batch".into());
+            return vec![out];
+        }
+        frames.into_iter().map(|res| {
+            let mut out = out.clone();
+            match res {
+                Ok(mut d) => {
+                    out.out_of_range = scrub_out_of_range(&mut d);
+                    let at = started + Duration::from_millis((rec.ts - first_ts).max(0) as u64);
+                    out.slew_rejected = guard.check(at, &mut d);
+                    out.ids = Some(d.ids());
+                    out.decoded = Some(d);
+                }
+                Err(e) => out.error = Some(format!("{} ({} bytes)", e.name(), bytes.len())),
+            }
+            out
+        }).collect()
+    }).collect()
+}
@@ -0,0 +1,138 @@
+//! Schema registry: which frame layout a node sends, so decoding need not guess from the payload length.
+//! - Ranges come from `node_schema` (storage/node_schema.rs), loaded at startup and replaced by
+//!   `set_node_schema`. Lookups use the ids in the topic, before origin mapping.
+//! - A range for the node's greenhouse beats one for every greenhouse; among those the narrowest wins.
+//! - Nodes without a range keep the length heuristic of `decode_batch`; declared nodes go through
+//!   `decode_as`, which also marks them outdoor for `label_for` whatever their id.
+//! - `standard_v14` is how firmware v1.4 nodes are told apart: their 62-byte frames (f32 weight, no CRC)
+//!   look exactly like a 60-byte frame with CRC, which is what an undeclared node's are taken for.
+
+use std::sync::{Arc, RwLock};
+use tracing::{error, info};
+
+use super::greenhouse_sensor::decoder::FrameLayout;
+use crate::services::storage::node_schema::{load_node_schema, NodeSchemaEntry};
+
+#[derive(Debug, Default)]
+pub struct SchemaRegistry {
+    entries: Vec<NodeSchemaEntry>,
+}
+
+pub type SchemaShared = Arc<RwLock<SchemaRegistry>>;
+
+impl SchemaRegistry {
+    pub fn replace(&mut self, entries: Vec<NodeSchemaEntry>) {
+        self.entries = entries;
+    }
+
+    /// Declared layout of (greenhouse_id, node_id); None = unknown node, use the length heuristic.
+    pub fn layout_for(&self, gh: u16, node: u16) -> Option<FrameLayout> {
+        self.entries.iter()
+            .filter(|e| e.greenhouse_id.is_none_or(|g| g == gh) && (e.node_from..=e.node_to).contains(&node))
+            .min_by_key(|e| (e.greenhouse_id.is_none(), e.node_to - e.node_from))
+            .map(|e| e.layout)
+    }
+}
+
+/// Fill the registry from the DB; until this finishes every node uses the length heuristic.
+pub async fn load_schema_registry(shared: SchemaShared, db_path: &'static str) {
+    match tokio::task::spawn_blocking(move || load_node_schema(db_path)).await {
+        Ok(Ok(entries)) => {
+            info!(target: "SCHEMA", "{} node layout range(s) declared", entries.len());
+            if let Ok(mut s) = shared.write() { s.replace(entries); }
+        }
+        Ok(Err(e)) => error!(target: "SCHEMA", "load node_schema failed: {e}"),
+        Err(e) => error!(target: "SCHEMA", "join error: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mqtt::greenhouse_sensor::decoder::{decode_as, decode_batch, encode_payload, DecodeError, Decoded};
+    use crate::services::mqtt::greenhouse_sensor::nodes::label_for;
+    use crate::services::storage::node_schema::set_node_schema;
+
+    fn entry(greenhouse_id: Option<u16>, node_from: u16, node_to: u16, layout: FrameLayout) -> NodeSchemaEntry {
+        NodeSchemaEntry { greenhouse_id, node_from, node_to, layout, updated_ms: 0 }
+    }
+
+    fn registry(entries: Vec<NodeSchemaEntry>) -> SchemaRegistry {
+        let mut r = SchemaRegistry::default();
+        r.replace(entries);
+        r
+    }
+
+    /// Frames of a node as the subscriber decodes them: as declared, else by length.
+    fn decode(r: &SchemaRegistry, gh: u16, node: u16, p: &[u8]) -> Vec<Result<Decoded, DecodeError>> {
+        match r.layout_for(gh, node) {
+            Some(layout) => decode_as(p, layout),
+            None => decode_batch(p),
+        }
+    }
+
+    #[test]
+    fn narrowest_range_of_the_own_greenhouse_wins() {
+        let r = registry(vec![
+            entry(None, 1, 100, FrameLayout::Standard),
+            entry(None, 40, 49, FrameLayout::Soil),
+            entry(Some(2), 1, 100, FrameLayout::StandardV14),
+            entry(Some(2), 7, 7, FrameLayout::Outdoor),
+        ]);
+        assert_eq!(r.layout_for(1, 5), Some(FrameLayout::Standard));
+        assert_eq!(r.layout_for(1, 40), Some(FrameLayout::Soil));
+        assert_eq!(r.layout_for(1, 49), Some(FrameLayout::Soil), "ranges are inclusive");
+        assert_eq!(r.layout_for(2, 40), Some(FrameLayout::StandardV14), "own greenhouse beats any greenhouse");
+        assert_eq!(r.layout_for(2, 7), Some(FrameLayout::Outdoor));
+        assert_eq!(r.layout_for(1, 101), None);
+        assert_eq!(SchemaRegistry::default().layout_for(1, 5), None);
+    }
+
+    #[test]
+    fn declaration_overrides_the_length_guess() {
+        let standard = Decoded::Standard {
+            greenhouse_id: 1, node_id: 3,
+            air_temp_c: 24.5, leaf_temp_c: 23.0, bag_temp_c: 21.5, air_rh_pct: 68.0,
+            bag_rh1_pct: 80.0, bag_rh2_pct: 81.0, bag_rh3_pct: 82.0, bag_rh4_pct: 83.0, bag_rh_avg_pct: 81.5,
+            par_value: 420, weight_g: 70_000.25,
+            ea_air_kpa: 2.1, ea_leaf_kpa: 2.4, es_kpa: 3.1, vpd_kpa: 1.0,
+            battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        };
+        let v14 = encode_payload(&standard);
+        let none = SchemaRegistry::default();
+        let declared = registry(vec![entry(Some(1), 3, 3, FrameLayout::StandardV14), entry(None, 7, 7, FrameLayout::Outdoor)]);
+        // 62 bytes: v1 + CRC by length (and a bad CRC), the f32-weight layout as declared
+        assert_eq!(decode(&none, 1, 3, &v14)[0].unwrap_err(), DecodeError::CrcMismatch);
+        match decode(&declared, 1, 3, &v14)[0].unwrap() {
+            Decoded::Standard { weight_g, .. } => assert_eq!(weight_g, 70_000.25),
+            d => panic!("{d:?}"),
+        }
+        // JSON without `kind` is standard by default, outdoor for a node declared outdoor
+        let json = br#"{"gh": 1, "node": 7, "air_temp_c": 18.0, "wind_ms": 2.5}"#;
+        assert!(matches!(decode(&none, 1, 7, json)[0], Ok(Decoded::Standard { .. })));
+        assert!(matches!(decode(&declared, 1, 7, json)[0], Ok(Decoded::Outdoor { wind_ms: 2.5, .. })));
+        // and a standard frame from a declared outdoor node is refused rather than mistaken for one
+        let mut v1 = standard;
+        if let Decoded::Standard { weight_g, .. } = &mut v1 { *weight_g = 1200.0; }
+        assert_eq!(decode(&declared, 1, 7, &encode_payload(&v1))[0].unwrap_err(),
+                   DecodeError::LayoutMismatch { expected: FrameLayout::Outdoor });
+        // a declared outdoor node gets an outdoor label whatever its id
+        assert_eq!(label_for(7, declared.layout_for(1, 7) == Some(FrameLayout::Outdoor)), "Outdoor_NodeXX");
+        assert_eq!(label_for(7, false), "node07");
+    }
+
+    #[test]
+    fn ranges_round_trip_through_node_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let db = db.to_str().unwrap();
+        set_node_schema(db, None, 40, 49, Some(FrameLayout::Soil), 1).unwrap();
+        set_node_schema(db, Some(2), 7, 7, Some(FrameLayout::Outdoor), 2).unwrap();
+        let entries = set_node_schema(db, None, 40, 49, Some(FrameLayout::StandardV14), 3).unwrap();
+        assert_eq!(entries.len(), 2, "setting a range again replaces it");
+        let r = registry(entries);
+        assert_eq!((r.layout_for(1, 45), r.layout_for(2, 7), r.layout_for(1, 7)),
+                   (Some(FrameLayout::StandardV14), Some(FrameLayout::Outdoor), None));
+        assert!(set_node_schema(db, Some(2), 7, 7, None, 4).unwrap().iter().all(|e| e.greenhouse_id.is_none()));
+    }
+}
@@ -0,0 +1,191 @@
+//! Retained site summary for remote monitoring (one heartbeat message per site).
+//! - Every 5 min publishes, per greenhouse, the latest key averages and online node count,
+//!   plus app version and DB size, to `greenhouse/site/{site_id}/summary` (retained JSON).
+//! - Online nodes are counted from each node's latest window at publish time, by the liveness rule
+//!   (liveness.rs: online until `late_after_windows` of its own windows pass without one), not from the
+//!   greenhouse average, which stops updating when a whole greenhouse goes quiet.
+//! - Broker down: the cycle is skipped and nothing is queued; the next cycle sends fresh data.
+
+use rumqttc::{Event, Packet, QoS};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    time::{Duration, SystemTime},
+};
+use tokio::time::{interval, sleep};
+use tracing::{error, warn};
+
+use super::auth::mqtt_auth;
+use super::config::{node_liveness, site_summary};
+use super::core::new_client;
+use super::greenhouse_sensor::aggregator::{LatestNodeShared, NodeAvgUi};
+use super::greenhouse_sensor::greenhouse_aggregator::{GhAvg, LatestGhShared};
+use crate::services::storage::sqlite::db_size_bytes;
+
+/// Bump when fields are renamed/removed so head office can branch on it.
+pub const SUMMARY_SCHEMA_VERSION: u32 = 1;
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GhSummary {
+    pub greenhouse_id: u16,
+    pub ts_ms: i64, // when the greenhouse average was computed
+    pub air_temp_c: Option<f32>,
+    pub air_rh_pct: Option<f32>,
+    pub vpd_kpa: Option<f32>,
+    pub par_value: Option<f32>,
+    /// Nodes of the greenhouse whose latest window is recent (see the module docs).
+    pub nodes_online: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SiteSummary {
+    pub schema_version: u32,
+    pub site_id: String,
+    pub ts_ms: i64,
+    pub app_version: &'static str,
+    pub db_size_bytes: u64,
+    pub greenhouses: Vec<GhSummary>,
+}
+
+/// A node is online at `now_ms` until `late_after_windows` of its windows pass without a new one.
+fn online(n: &NodeAvgUi, now_ms: i64) -> bool {
+    now_ms - n.ts_ms < node_liveness().late_after_windows as i64 * n.window_sec as i64 * 1000
+}
+
+pub fn build_summary(
+    site_id: &str, latest: &HashMap<u16, GhAvg>, nodes: &HashMap<(u16, u16), NodeAvgUi>, db_size_bytes: u64, now_ms: i64,
+) -> SiteSummary {
+    let mut greenhouses: Vec<GhSummary> = latest.values().map(|ga| GhSummary {
+        greenhouse_id: ga.greenhouse_id,
+        ts_ms: ga.ts_ms,
+        air_temp_c: ga.air_temp_c,
+        air_rh_pct: ga.air_rh_pct,
+        vpd_kpa: ga.vpd_kpa,
+        par_value: ga.par_value,
+        nodes_online: nodes.values().filter(|n| n.greenhouse_id == ga.greenhouse_id && online(n, now_ms)).count(),
+    }).collect();
+    greenhouses.sort_by_key(|g| g.greenhouse_id);
+
+    SiteSummary {
+        schema_version: SUMMARY_SCHEMA_VERSION,
+        site_id: site_id.to_string(),
+        ts_ms: now_ms,
+        app_version: env!("CARGO_PKG_VERSION"),
+        db_size_bytes,
+        greenhouses,
+    }
+}
+
+/// Public task: publishes the site summary on its own client until the app exits.
+pub async fn run_site_summary(latest: LatestGhShared, nodes: LatestNodeShared, db_path: &'static str) {
+    let cfg = site_summary();
+    let topic = format!("greenhouse/site/{}/summary", cfg.site_id);
+    let (client, mut eventloop) = new_client("site-summary", &mqtt_auth());
+
+    // Drive the connection separately; publishing only happens while connected.
+    let connected = Arc::new(AtomicBool::new(false));
+    let conn_flag = connected.clone();
+    tokio::spawn(async move {
+        let mut backoff_ms: u64 = 250;
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    conn_flag.store(true, Ordering::Relaxed);
+                    backoff_ms = 250;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    if conn_flag.swap(false, Ordering::Relaxed) {
+                        error!(target: "SUMMARY", "eventloop error: {e}");
+                    }
+                    sleep(Duration::from_millis(backoff_ms)).await;
+                    backoff_ms = (backoff_ms * 2).min(10_000);
+                }
+            }
+        }
+    });
+
+    let mut tick = interval(Duration::from_secs(cfg.every_secs));
+    loop {
+        tick.tick().await;
+        if !connected.load(Ordering::Relaxed) {
+            warn!(target: "SUMMARY", "broker not connected, skipping this cycle");
+            continue;
+        }
+        let summary = match (latest.read(), nodes.read()) {
+            (Ok(l), Ok(n)) => build_summary(cfg.site_id, &l, &n, db_size_bytes(db_path), now_ms()),
+            _ => continue,
+        };
+        match serde_json::to_vec(&summary) {
+            Ok(payload) => {
+                if let Err(e) = client.try_publish(topic.as_str(), QoS::AtLeastOnce, true, payload) {
+                    warn!(target: "SUMMARY", "publish skipped: {e}");
+                }
+            }
+            Err(e) => error!(target: "SUMMARY", "serialize error: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mqtt::greenhouse_sensor::aggregator::NodeAvg;
+    use serde_json::json;
+
+    const NOW: i64 = 1_760_000_040_000;
+
+    fn node(gh: u16, id: u16, ts_ms: i64) -> ((u16, u16), NodeAvgUi) {
+        ((gh, id), NodeAvgUi::new(&NodeAvg::sample(gh, id, ts_ms, 21.0)))
+    }
+
+    #[test]
+    fn summary_serializes_to_schema_v1() {
+        let mut ga = GhAvg::sample(2, NOW - 30_000);
+        (ga.par_value, ga.vpd_kpa) = (None, Some(0.875));
+        let nodes = HashMap::from([node(2, 1, NOW - 30_000)]);
+        let summary = build_summary("north", &HashMap::from([(2, ga)]), &nodes, 1_234_567, NOW);
+        assert_eq!(serde_json::to_value(&summary).unwrap(), json!({
+            "schema_version": 1,
+            "site_id": "north",
+            "ts_ms": NOW,
+            "app_version": env!("CARGO_PKG_VERSION"),
+            "db_size_bytes": 1_234_567,
+            "greenhouses": [{
+                "greenhouse_id": 2,
+                "ts_ms": NOW - 30_000,
+                "air_temp_c": 24.0,
+                "air_rh_pct": 70.0,
+                "vpd_kpa": 0.875,
+                "par_value": null,
+                "nodes_online": 1,
+            }],
+        }));
+    }
+
+    #[test]
+    fn empty_site_still_has_a_heartbeat() {
+        let v = serde_json::to_value(build_summary("north", &HashMap::new(), &HashMap::new(), 0, NOW)).unwrap();
+        assert_eq!((v["schema_version"].as_u64(), v["greenhouses"].as_array().map(Vec::len)), (Some(1), Some(0)));
+    }
+
+    #[test]
+    fn nodes_online_follows_the_nodes_not_the_greenhouse_average() {
+        // the greenhouse average is from three nodes, an hour ago; since then two went quiet
+        let latest = HashMap::from([(1, GhAvg::sample(1, NOW - 3_600_000)), (2, GhAvg::sample(2, NOW))]);
+        let nodes = HashMap::from([
+            node(1, 1, NOW - 60_000),
+            node(1, 2, NOW - 121_000), // two 60 s windows missed: late
+            node(1, 3, NOW - 3_600_000),
+            node(2, 4, NOW),
+        ]);
+        let s = build_summary("north", &latest, &nodes, 0, NOW);
+        let online: Vec<(u16, usize)> = s.greenhouses.iter().map(|g| (g.greenhouse_id, g.nodes_online)).collect();
+        assert_eq!(online, [(1, 1), (2, 1)]);
+    }
+}
@@ -0,0 +1,355 @@
+//! Per-node health score (0–100) for the fleet dashboard.
+//! - Every `every_secs` the frame counters of the last period are turned into components in 0..1:
+//!   delivery (frames vs the node's expected interval), decode success (CRC failures counted apart), data quality (readings kept by
+//!   the range check and the slew guard, sanitize.rs), battery and RSSI (v2 / outdoor telemetry frames only, decoder.rs) and reboots, which frames do not carry.
+//! - `health_score` is a weighted mean of the components that are present: a missing component drops
+//!   out and the other weights are renormalised instead of counting as zero.
+//! - Results go to `NodeHealthShared` (`list_nodes`), the `node_health` event, a daily snapshot row per
+//!   node (`node_health_daily`, `get_node_health_history`) and, opt-in, retained MQTT messages on
+//!   `greenhouse/{gh}/node/{id}/health`.
+//! - Nodes heard since startup keep being scored; a silent node scores on delivery alone (0).
+//! - Samples the node aggregator drops as window outliers are listed per sensor key (`outliers`), not
+//!   scored: a key that keeps appearing points at one faulty sensor on an otherwise healthy node.
+
+use rumqttc::{Event, Packet, QoS};
+use serde::Serialize;
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex, RwLock}, time::Duration};
+use tokio::time::{interval, sleep};
+use tracing::{error, info, warn};
+
+use crate::services::mqtt::auth::mqtt_auth;
+use crate::services::mqtt::config::{aggregation, outdoor};
+use crate::services::mqtt::core::new_client;
+use crate::services::mqtt::greenhouse_sensor::decoder::{DecodeError, Decoded};
+use crate::services::mqtt::greenhouse_sensor::hourly_aggregator::local_day_start;
+use crate::services::node_maintenance::now_ms;
+use crate::services::presenter::emitter::EventSink;
+use crate::services::storage::health::{load_expected_intervals, upsert_health_days};
+
+#[derive(Debug, Clone, Copy)]
+pub struct HealthWeights {
+    pub delivery: f32,
+    pub decode: f32,
+    pub battery: f32,
+    pub rssi: f32,
+    pub quality: f32,
+    pub reboots: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NodeHealthConfig {
+    pub every_secs: u64,
+    /// Expected publish interval of indoor nodes without `expected_interval_secs` in the roster.
+    pub default_interval_secs: u64,
+    pub weights: HealthWeights,
+    /// Battery voltage scored 0 at `.0` and 1 at `.1` (linear in between).
+    pub battery_v: (f32, f32),
+    /// Mean RSSI scored 0 at `.0` and 1 at `.1` dBm.
+    pub rssi_dbm: (f32, f32),
+    /// Publish each score retained on `greenhouse/{gh}/node/{id}/health`.
+    pub publish: bool,
+}
+
+pub const fn node_health_config() -> NodeHealthConfig {
+    NodeHealthConfig {
+        every_secs: 300,
+        default_interval_secs: 10,
+        weights: HealthWeights { delivery: 0.35, decode: 0.15, battery: 0.15, rssi: 0.10, quality: 0.15, reboots: 0.10 },
+        battery_v: (3.3, 4.1),
+        rssi_dbm: (-95.0, -65.0),
+        publish: false,
+    }
+}
+
+/// Frame counters since the last score, by (greenhouse_id, node_id).
+#[derive(Debug, Clone, Default)]
+pub struct FrameCounts {
+    /// Last battery voltage reported (v2 / outdoor telemetry frames).
+    pub battery_v: Option<f32>,
+    pub rssi_sum: f64,
+    pub rssi_n: u32,
+    pub frames: u64,
+    pub decode_failures: u64,
+    /// Frames rejected by their CRC trailer (RF corruption), apart from other decode failures.
+    pub crc_failures: u64,
+    pub readings: u64,
+    pub rejected_readings: u64,
+    /// Readings scrubbed as physically implausible before aggregation (not part of `readings`).
+    pub out_of_range: u64,
+    /// Samples dropped as window outliers (`aggregation().reject_outliers`), per sensor key.
+    pub outliers: BTreeMap<&'static str, u64>,
+}
+
+pub type HealthCountersShared = Arc<Mutex<HashMap<(u16, u16), FrameCounts>>>;
+
+/// Subscriber side: one frame from `ids`, decoded or rejected.
+pub fn count_frame(counters: &HealthCountersShared, ids: (u16, u16), outcome: Result<&Decoded, DecodeError>) {
+    if let Ok(mut m) = counters.lock() {
+        let c = m.entry(ids).or_default();
+        match outcome {
+            Ok(d) => {
+                c.frames += 1;
+                let (battery_v, rssi_dbm) = d.telemetry();
+                if let Some(v) = battery_v.filter(|v| v.is_finite()) { c.battery_v = Some(v); }
+                if let Some(r) = rssi_dbm.filter(|r| r.is_finite()) { c.rssi_sum += r as f64; c.rssi_n += 1; }
+            }
+            Err(DecodeError::CrcMismatch) => c.crc_failures += 1,
+            Err(_) => c.decode_failures += 1,
+        }
+    }
+}
+
+/// Subscriber side: `n` readings of a frame from `ids` scrubbed by the range check.
+pub fn count_out_of_range(counters: &HealthCountersShared, ids: (u16, u16), n: usize) {
+    if n == 0 { return; }
+    if let Ok(mut m) = counters.lock() {
+        m.entry(ids).or_default().out_of_range += n as u64;
+    }
+}
+
+/// Aggregator side: samples dropped as window outliers, per sensor key.
+pub fn count_outliers(counters: &HealthCountersShared, ids: (u16, u16), rejected: &[(&'static str, usize)]) {
+    if let Ok(mut m) = counters.lock() {
+        let c = m.entry(ids).or_default();
+        for &(key, n) in rejected { *c.outliers.entry(key).or_default() += n as u64; }
+    }
+}
+
+/// Aggregator side: `readings` finite readings of which the slew guard rejected `rejected`.
+pub fn count_readings(counters: &HealthCountersShared, ids: (u16, u16), readings: usize, rejected: usize) {
+    if let Ok(mut m) = counters.lock() {
+        let c = m.entry(ids).or_default();
+        c.readings += readings as u64;
+        c.rejected_readings += rejected as u64;
+    }
+}
+
+/// Components in 0..1 (1 = healthy); None = not measured.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct HealthComponents {
+    pub delivery: Option<f32>,
+    pub decode: Option<f32>,
+    pub battery: Option<f32>,
+    pub rssi: Option<f32>,
+    pub quality: Option<f32>,
+    pub reboots: Option<f32>,
+}
+
+/// Weighted mean of the present components, 0–100 (None when nothing was measured).
+pub fn health_score(c: &HealthComponents, w: &HealthWeights) -> Option<f32> {
+    let parts = [
+        (c.delivery, w.delivery), (c.decode, w.decode), (c.battery, w.battery),
+        (c.rssi, w.rssi), (c.quality, w.quality), (c.reboots, w.reboots),
+    ];
+    let (sum, weight) = parts.iter()
+        .filter_map(|(v, w)| v.filter(|_| *w > 0.0).map(|v| (v.clamp(0.0, 1.0) * w, *w)))
+        .fold((0.0, 0.0), |a, b| (a.0 + b.0, a.1 + b.1));
+    (weight > 0.0).then(|| (sum / weight * 100.0).round())
+}
+
+fn ratio(good: u64, total: u64) -> Option<f32> {
+    (total > 0).then(|| good as f32 / total as f32)
+}
+
+/// 0 at `lo`, 1 at `hi`, linear in between.
+fn scale(v: f32, (lo, hi): (f32, f32)) -> f32 {
+    ((v - lo) / (hi - lo)).clamp(0.0, 1.0)
+}
+
+/// Components for one period of `period_secs` with the node's expected interval.
+pub fn components(c: &FrameCounts, period_secs: u64, interval_secs: u64) -> HealthComponents {
+    let expected = (period_secs as f32 / interval_secs.max(1) as f32).max(1.0);
+    HealthComponents {
+        delivery: Some((c.frames as f32 / expected).min(1.0)),
+        decode: ratio(c.frames, c.frames + c.decode_failures + c.crc_failures),
+        quality: ratio(c.readings - c.rejected_readings.min(c.readings), c.readings + c.out_of_range),
+        battery: c.battery_v.map(|v| scale(v, node_health_config().battery_v)),
+        rssi: (c.rssi_n > 0).then(|| scale((c.rssi_sum / c.rssi_n as f64) as f32, node_health_config().rssi_dbm)),
+        reboots: None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeHealth {
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+    pub ts_ms: i64,
+    pub score: Option<f32>,
+    pub components: HealthComponents,
+    pub frames: u64,
+    pub expected_frames: u64,
+    pub decode_failures: u64,
+    pub crc_failures: u64,
+    /// Readings scrubbed as out of range this period; a steady count points at a dying sensor.
+    pub out_of_range: u64,
+    /// Samples rejected as window outliers this period, per sensor key; a key that keeps showing up
+    /// points at a faulty sensor.
+    pub outliers: BTreeMap<&'static str, u64>,
+}
+
+/// Latest score by (greenhouse_id, node_id).
+pub type NodeHealthShared = Arc<RwLock<HashMap<(u16, u16), NodeHealth>>>;
+
+fn expected_interval(ids: (u16, u16), roster: &HashMap<(u16, u16), u64>) -> u64 {
+    if let Some(&s) = roster.get(&ids) { return s; }
+    if outdoor().station_priority.contains(&ids.1) { aggregation().outdoor_expected_interval_secs } else { node_health_config().default_interval_secs }
+}
+
+/// Keeps the MQTT connection for `publish` alive; true while connected.
+fn spawn_publisher() -> (rumqttc::AsyncClient, Arc<AtomicBool>) {
+    let (client, mut eventloop) = new_client("node-health", &mqtt_auth());
+    let connected = Arc::new(AtomicBool::new(false));
+    let flag = connected.clone();
+    tokio::spawn(async move {
+        let mut backoff_ms: u64 = 250;
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => { flag.store(true, Ordering::Relaxed); backoff_ms = 250; }
+                Ok(_) => {}
+                Err(e) => {
+                    if flag.swap(false, Ordering::Relaxed) { error!(target: "HEALTH", "eventloop error: {e}"); }
+                    sleep(Duration::from_millis(backoff_ms)).await;
+                    backoff_ms = (backoff_ms * 2).min(10_000);
+                }
+            }
+        }
+    });
+    (client, connected)
+}
+
+/// Public task: score every node heard since startup each period (`node_health`, daily snapshots, MQTT).
+pub async fn run_node_health<S: EventSink>(sink: S, counters: HealthCountersShared, shared: NodeHealthShared, db_path: &'static str, dry_run: bool) {
+    let cfg = node_health_config();
+    let publisher = cfg.publish.then(spawn_publisher);
+    let mut heard: BTreeSet<(u16, u16)> = BTreeSet::new();
+    let mut tick = interval(Duration::from_secs(cfg.every_secs));
+    tick.tick().await; // first period starts now
+    loop {
+        tick.tick().await;
+        let counts: HashMap<(u16, u16), FrameCounts> = counters.lock().map(|mut m| std::mem::take(&mut *m)).unwrap_or_default();
+        heard.extend(counts.keys().copied());
+        let roster = if dry_run { HashMap::new() } else {
+            tokio::task::spawn_blocking(move || load_expected_intervals(db_path)).await
+                .ok().and_then(|r| r.map_err(|e| warn!(target: "HEALTH", "roster intervals unavailable: {e}")).ok())
+                .unwrap_or_default()
+        };
+        let ts_ms = now_ms();
+        let scores: Vec<NodeHealth> = heard.iter().map(|&ids| {
+            let c = counts.get(&ids).cloned().unwrap_or_default();
+            let interval_secs = expected_interval(ids, &roster);
+            let comps = components(&c, cfg.every_secs, interval_secs);
+            NodeHealth {
+                greenhouse_id: ids.0, node_id: ids.1, ts_ms,
+                score: health_score(&comps, &cfg.weights), components: comps,
+                frames: c.frames, expected_frames: cfg.every_secs / interval_secs.max(1),
+                decode_failures: c.decode_failures, crc_failures: c.crc_failures, out_of_range: c.out_of_range,
+                outliers: c.outliers,
+            }
+        }).collect();
+        if scores.is_empty() { continue; }
+
+        if let Ok(mut m) = shared.write() {
+            for h in &scores { m.insert((h.greenhouse_id, h.node_id), h.clone()); }
+        }
+        match serde_json::to_value(&scores) {
+            Ok(v) => sink.emit_json("node_health", v),
+            Err(e) => error!(target: "HEALTH", "serialize scores failed: {e}"),
+        }
+        if let Some((client, connected)) = &publisher {
+            if connected.load(Ordering::Relaxed) {
+                for h in &scores {
+                    let topic = format!("greenhouse/{}/node/{}/health", h.greenhouse_id, h.node_id);
+                    let payload = serde_json::json!({ "ts_ms": h.ts_ms, "score": h.score, "components": h.components }).to_string();
+                    if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, true, payload) {
+                        warn!(target: "HEALTH", "publish skipped: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+        if dry_run {
+            info!(target: "HEALTH", "DRY RUN: would store {} health snapshot(s)", scores.len());
+            continue;
+        }
+        let day = local_day_start(ts_ms);
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = upsert_health_days(db_path, day, &scores) {
+                warn!(target: "HEALTH", "storing health snapshots failed: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: HealthWeights = node_health_config().weights;
+
+    fn all(v: f32) -> HealthComponents {
+        HealthComponents { delivery: Some(v), decode: Some(v), battery: Some(v), rssi: Some(v), quality: Some(v), reboots: Some(v) }
+    }
+
+    #[test]
+    fn uniform_components_score_their_own_value() {
+        assert_eq!(health_score(&all(1.0), &W), Some(100.0));
+        assert_eq!(health_score(&all(0.5), &W), Some(50.0));
+        assert_eq!(health_score(&all(0.0), &W), Some(0.0));
+    }
+
+    #[test]
+    fn representative_node_is_the_weighted_mean() {
+        // mains-powered indoor node at the edge of coverage: no battery, weak RSSI, a few lost frames
+        let c = HealthComponents { delivery: Some(0.9), decode: Some(0.97), battery: None, rssi: Some(0.2), quality: Some(1.0), reboots: None };
+        // (0.9 * 0.35 + 0.97 * 0.15 + 0.2 * 0.10 + 1.0 * 0.15) / (0.35 + 0.15 + 0.10 + 0.15) = 0.8407
+        assert_eq!(health_score(&c, &W), Some(84.0));
+    }
+
+    #[test]
+    fn missing_components_renormalise_instead_of_scoring_zero() {
+        let only_delivery = HealthComponents { delivery: Some(0.8), ..Default::default() };
+        assert_eq!(health_score(&only_delivery, &W), Some(80.0));
+        assert_eq!(health_score(&HealthComponents::default(), &W), None);
+        // a zero weight takes its component out the same way
+        let no_rssi = HealthWeights { rssi: 0.0, ..W };
+        let c = HealthComponents { rssi: Some(0.0), ..all(1.0) };
+        assert_eq!(health_score(&c, &no_rssi), Some(100.0));
+        assert_eq!(health_score(&c, &W), Some(90.0));
+    }
+
+    #[test]
+    fn components_out_of_range_are_clamped() {
+        assert_eq!(health_score(&all(1.7), &W), Some(100.0));
+        assert_eq!(health_score(&all(-0.3), &W), Some(0.0));
+    }
+
+    #[test]
+    fn frame_counts_become_components() {
+        let c = FrameCounts {
+            battery_v: Some(3.7), rssi_sum: -80.0 * 20.0, rssi_n: 20,
+            frames: 27, decode_failures: 2, crc_failures: 1,
+            readings: 100, rejected_readings: 5, out_of_range: 5,
+            ..Default::default()
+        };
+        // 5 minutes at one frame every 10 s: 30 expected
+        let h = components(&c, 300, 10);
+        let close = |a: Option<f32>, b: f32| a.is_some_and(|a| (a - b).abs() < 1e-6);
+        assert!(close(h.delivery, 0.9) && close(h.decode, 0.9), "{h:?}");
+        assert!(close(h.quality, 95.0 / 105.0), "{h:?}");
+        assert!(close(h.battery, 0.5) && close(h.rssi, 0.5), "{h:?}");
+        assert!(h.reboots.is_none());
+    }
+
+    #[test]
+    fn silent_and_chatty_nodes() {
+        let silent = components(&FrameCounts::default(), 300, 10);
+        assert_eq!(silent.delivery, Some(0.0));
+        assert!(silent.decode.is_none() && silent.quality.is_none() && silent.battery.is_none() && silent.rssi.is_none());
+        assert_eq!(health_score(&silent, &W), Some(0.0));
+
+        let chatty = FrameCounts { frames: 90, ..Default::default() };
+        assert_eq!(components(&chatty, 300, 10).delivery, Some(1.0));
+        // an outdoor station publishing every 10 min, scored over 5 min: one frame is enough
+        assert_eq!(components(&FrameCounts { frames: 1, ..Default::default() }, 300, 600).delivery, Some(1.0));
+    }
+}
@@ -0,0 +1,159 @@
+//! Node maintenance mode (a technician recalibrating or swapping sensors on one node).
+//! - `set_node_maintenance` opens / closes a window; open windows live in `MaintenanceShared` so the
+//!   node aggregator can flag every window that overlaps one (`NodeAvg::maintenance`).
+//! - Flagged node averages are still stored and shown, but the greenhouse aggregator leaves them out,
+//!   so greenhouse averages, hourly rows and the VPD KPI never see them.
+//! - The watchdog emits `maintenance_reminder` once a window has been open for `remind_after_secs`
+//!   and closes it on its own after `max_duration_secs` (someone forgot).
+//! - In dry-run the windows live in memory only (`start_in_memory` / `end_in_memory`): the aggregators
+//!   flag the same averages, nothing is read from or written to `maintenance_window`.
+
+use std::{collections::HashMap, sync::{Arc, RwLock}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use tokio::time::interval;
+use tracing::{error, info};
+
+use crate::services::presenter::emitter::EventSink;
+use crate::services::storage::maint_window::{end_maintenance, load_open_windows, mark_reminded, MaintenanceWindow};
+
+#[derive(Debug, Clone, Copy)]
+pub struct MaintenanceConfig {
+    pub remind_after_secs: i64,
+    pub max_duration_secs: i64,
+    /// Closed windows stay in memory this long so windows that straddle the close are still flagged.
+    pub keep_closed_secs: i64,
+}
+
+pub const fn maintenance_config() -> MaintenanceConfig {
+    MaintenanceConfig {
+        remind_after_secs: 3_600,
+        max_duration_secs: 8 * 3_600,
+        keep_closed_secs: 15 * 60, // longer than the outdoor window
+    }
+}
+
+const CHECK_EVERY: Duration = Duration::from_secs(60);
+
+/// Current (and just-closed) windows by (greenhouse_id, node_id).
+pub type MaintenanceShared = Arc<RwLock<HashMap<(u16, u16), MaintenanceWindow>>>;
+
+#[inline] pub fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+/// True when the node's aggregation window starting at `window_start_ms` overlaps maintenance.
+pub fn in_maintenance(shared: &MaintenanceShared, ids: (u16, u16), window_start_ms: i64) -> bool {
+    shared.read().ok().and_then(|m| m.get(&ids).map(|w| w.covers(window_start_ms))).unwrap_or(false)
+}
+
+/// Record an opened / closed window and tell every window (`node_maintenance`).
+pub fn publish<S: EventSink>(shared: &MaintenanceShared, sink: &S, w: &MaintenanceWindow) {
+    if let Ok(mut m) = shared.write() { m.insert((w.greenhouse_id, w.node_id), w.clone()); }
+    info!(
+        target: "MAINT",
+        "GH:{} Node:{} maintenance {}{}",
+        w.greenhouse_id, w.node_id,
+        if w.ended_ms.is_some() { "ended" } else { "started" },
+        if w.auto_closed { " (auto-closed after max duration)" } else { "" },
+    );
+    if let Ok(v) = serde_json::to_value(w) { sink.emit_json("node_maintenance", v); }
+}
+
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> rusqlite::Result<T> + Send + 'static) -> Option<T> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(Ok(v)) => Some(v),
+        Ok(Err(e)) => { error!(target: "MAINT", "DB error: {e}"); None }
+        Err(e) => { error!(target: "MAINT", "join error: {e}"); None }
+    }
+}
+
+/// Dry-run `start_maintenance`: the node's open window, or a new one (id 0) kept only in `shared`.
+pub fn start_in_memory(shared: &MaintenanceShared, gh_id: u16, node_id: u16, note: &str, now_ms: i64) -> MaintenanceWindow {
+    let open = shared.read().ok().and_then(|m| m.get(&(gh_id, node_id)).filter(|w| w.ended_ms.is_none()).cloned());
+    open.unwrap_or_else(|| MaintenanceWindow {
+        id: 0, greenhouse_id: gh_id, node_id, started_ms: now_ms, ended_ms: None,
+        note: note.to_string(), auto_closed: false, reminded_ms: None,
+    })
+}
+
+/// Dry-run `end_maintenance`: the node's open window closed at `now_ms`; None when there was none.
+pub fn end_in_memory(shared: &MaintenanceShared, gh_id: u16, node_id: u16, note: &str, now_ms: i64, auto: bool) -> Option<MaintenanceWindow> {
+    let mut w = shared.read().ok()?.get(&(gh_id, node_id)).filter(|w| w.ended_ms.is_none()).cloned()?;
+    if !note.is_empty() {
+        w.note = if w.note.is_empty() { note.to_string() } else { format!("{}; {note}", w.note) };
+    }
+    w.ended_ms = Some(now_ms);
+    w.auto_closed = auto;
+    Some(w)
+}
+
+/// Public task: reload open windows, then remind / auto-close / forget closed windows every minute.
+/// In dry-run nothing is reloaded and closes / reminders stay in memory.
+pub async fn run_maintenance_watch<S: EventSink>(sink: S, shared: MaintenanceShared, db_path: &'static str, dry_run: bool) {
+    if !dry_run {
+        if let Some(open) = blocking(move || load_open_windows(db_path)).await {
+            if let Ok(mut m) = shared.write() {
+                for w in open { m.insert((w.greenhouse_id, w.node_id), w); }
+            }
+        }
+    }
+    let cfg = maintenance_config();
+    let mut tick = interval(CHECK_EVERY);
+    loop {
+        tick.tick().await;
+        let now = now_ms();
+        let windows: Vec<MaintenanceWindow> = match shared.write() {
+            Ok(mut m) => {
+                m.retain(|_, w| w.ended_ms.is_none_or(|end| now - end < cfg.keep_closed_secs * 1000));
+                m.values().filter(|w| w.ended_ms.is_none()).cloned().collect()
+            }
+            Err(_) => continue,
+        };
+
+        for w in windows {
+            let open_secs = (now - w.started_ms) / 1000;
+            let (gh_id, node_id) = (w.greenhouse_id, w.node_id);
+            if open_secs >= cfg.max_duration_secs {
+                let closed = if dry_run {
+                    end_in_memory(&shared, gh_id, node_id, "", now, true)
+                } else {
+                    blocking(move || end_maintenance(db_path, gh_id, node_id, "", now, true)).await.flatten()
+                };
+                if let Some(closed) = closed { publish(&shared, &sink, &closed); }
+            } else if open_secs >= cfg.remind_after_secs && w.reminded_ms.is_none() {
+                let id = w.id;
+                if !dry_run && blocking(move || mark_reminded(db_path, id, now)).await.is_none() { continue; }
+                let w = MaintenanceWindow { reminded_ms: Some(now), ..w };
+                if let Ok(mut m) = shared.write() { m.insert((gh_id, node_id), w.clone()); }
+                info!(target: "MAINT", "GH:{gh_id} Node:{node_id} still in maintenance after {} min", open_secs / 60);
+                sink.emit_json("maintenance_reminder", serde_json::json!({
+                    "window": w,
+                    "open_secs": open_secs,
+                    "auto_close_in_secs": cfg.max_duration_secs - open_secs,
+                }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dry_run_windows_open_and_close_in_memory() {
+        let shared = MaintenanceShared::default();
+        assert!(end_in_memory(&shared, 1, 2, "", 1_000, false).is_none(), "nothing open yet");
+
+        let w = start_in_memory(&shared, 1, 2, "swap probe", 1_000);
+        assert_eq!((w.id, w.started_ms, w.ended_ms), (0, 1_000, None));
+        shared.write().unwrap().insert((1, 2), w);
+        assert!(in_maintenance(&shared, (1, 2), 50_000));
+        assert_eq!(start_in_memory(&shared, 1, 2, "again", 2_000).started_ms, 1_000, "an open window is reused");
+
+        let closed = end_in_memory(&shared, 1, 2, "done", 60_000, false).unwrap();
+        assert_eq!((closed.ended_ms, closed.note.as_str()), (Some(60_000), "swap probe; done"));
+        shared.write().unwrap().insert((1, 2), closed);
+        assert!(in_maintenance(&shared, (1, 2), 0) && !in_maintenance(&shared, (1, 2), 60_000));
+        assert!(end_in_memory(&shared, 1, 2, "", 70_000, false).is_none());
+    }
+}
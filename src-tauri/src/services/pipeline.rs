@@ -0,0 +1,350 @@
+//! The data path as one restartable unit (`restart_pipeline`, commands.rs).
+//! - A run of the data path is the source (MQTT subscriber, or the `--replay` file), the decoded queue,
+//!   the node aggregator, the node -> greenhouse lane and the greenhouse aggregator, each under
+//!   `supervise`. A run has its own token, a child of the app's shutdown token, so the app exit stops
+//!   it as before.
+//! - Everything behind the aggregators outlives a restart: storage with its open batch, the hourly
+//!   stage with its open hour, the VPD KPI, liveness, the UI emitter, and the lanes into them. A run
+//!   sends on clones of those lanes; `close` drops the originals on exit so they still close.
+//! - A restart checks the window, reads mqtt.toml and the environment again (`reload_mqtt_auth`), then
+//!   stops the running run the way the app exit does (shutdown.rs): the source stops, and each
+//!   aggregator flushes its partial windows once its input closes. The new run gets fresh internal
+//!   channels that count on the old ones' counters (`get_pipeline_stats` totals stay since startup).
+//! - `pipeline_restart` reports each step (`reloading`, `stopping`, `starting`, `running`, `failed`).
+//!   Settings that do not load fail the restart before anything stops, and the running data path stays
+//!   as it was. Stages still running after `SHUTDOWN_TIMEOUT` are listed as `abandoned` and left to
+//!   finish beside the new run. A run that cannot start leaves the data path stopped: `failed` with
+//!   `running` false, and nothing is recorded until the app restarts.
+//! - Channel capacities, the DB path and the aggregation settings are built in (channels.rs, lib.rs,
+//!   config.rs), and `APPTEST_*` variables are read from the environment the app was launched with; a
+//!   restart starts with the values it already had. What it picks up is mqtt.toml: broker, login,
+//!   subscriptions and session settings, for the subscriber at once and for other clients on their
+//!   next connect.
+
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::services::channels::{channel_config, lane, requeue, Lane, LanesShared, Priority, QueueCountersShared};
+use crate::services::mqtt::auth::reload_mqtt_auth;
+use crate::services::mqtt::greenhouse_sensor::{
+    aggregator::{aggregation_window, run_rolling_avg, CatchupReport, LatestNodeShared, NodeAvg, NodeAvgOutputs, NodeAvgUi, NodeLive},
+    calibration::CalibrationShared,
+    decoder::Decoded,
+    decoder_stats::DecoderStatsShared,
+    greenhouse_aggregator::{run_greenhouse_avg, GhAvgOutputs, RosterShared},
+    liveness::NodeStatus,
+    subscriber::{run_debug_subscriber, SubscriberShared},
+};
+use crate::services::mqtt::recording::run_replay_source;
+use crate::services::mqtt::schema::SchemaShared;
+use crate::services::node_health::HealthCountersShared;
+use crate::services::node_maintenance::MaintenanceShared;
+use crate::services::presenter::emitter::EventSink;
+use crate::services::shutdown::{join_within, Shutdown, SHUTDOWN_TIMEOUT};
+use crate::services::supervisor::{supervise, Slot};
+
+/// Tasks of a run, as tracked by `Shutdown`.
+const STAGES: [&str; 3] = ["subscriber", "rolling_avg", "greenhouse_avg"];
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+/// Where a run's samples come from.
+#[derive(Clone)]
+pub enum Source {
+    /// The sensor subscriber on the configured broker.
+    Mqtt(SubscriberShared),
+    /// A traffic recording (recording.rs); a restart plays it again from the start.
+    Replay { path: PathBuf, speed: f64, schemas: SchemaShared },
+}
+
+/// `NodeAvgOutputs` without the lane into the greenhouse aggregator, which every run builds anew.
+#[derive(Clone)]
+pub struct NodeOutputs {
+    pub db: Lane<NodeAvg>,
+    pub ui: Lane<NodeAvgUi>,
+    pub live: Lane<NodeLive>,
+    pub status: Lane<NodeStatus>,
+    pub latest: LatestNodeShared,
+    pub catchup: Lane<NodeAvg>,
+    pub catchup_done: Lane<CatchupReport>,
+}
+
+impl NodeOutputs {
+    fn with_gh(self, gh: Lane<NodeAvg>) -> NodeAvgOutputs {
+        let NodeOutputs { db, ui, live, status, latest, catchup, catchup_done } = self;
+        NodeAvgOutputs { db, gh, ui, live, status, latest, catchup, catchup_done }
+    }
+}
+
+/// What every run of the data path is built from; outlives the runs.
+#[derive(Clone)]
+pub struct DataPath {
+    pub source: Source,
+    /// Registry the run's node -> greenhouse lane joins.
+    pub lanes: LanesShared,
+    /// Counters (and name, capacity, policy) of the decoded queue.
+    pub decoded: QueueCountersShared,
+    pub nodes: NodeOutputs,
+    pub greenhouses: GhAvgOutputs,
+    pub rosters: RosterShared,
+    pub calibrations: CalibrationShared,
+    pub maintenance: MaintenanceShared,
+    pub health: HealthCountersShared,
+    pub decoder_stats: DecoderStatsShared,
+}
+
+/// Step of a restart, as `pipeline_restart` reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartStep {
+    Reloading,
+    Stopping,
+    Starting,
+    Running,
+    Failed,
+}
+
+/// Payload of `pipeline_restart`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RestartProgress {
+    pub step: RestartStep,
+    /// Whether a data path is running after this step; false after `failed` is fatal.
+    pub running: bool,
+    /// Stages of the old run that had not stopped within `SHUTDOWN_TIMEOUT`.
+    pub abandoned: Vec<&'static str>,
+    pub error: Option<String>,
+    pub ts_ms: i64,
+}
+
+/// Result of `restart_pipeline`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RestartReport {
+    pub window_secs: u64,
+    pub abandoned: Vec<&'static str>,
+    pub took_ms: u64,
+}
+
+/// The running data path and how to build the next one.
+pub struct Pipeline<S: EventSink + Clone> {
+    sink: S,
+    /// None once the app exits (`close`).
+    path: Mutex<Option<DataPath>>,
+    /// Token of the running run; held for the whole of a restart.
+    run: tokio::sync::Mutex<CancellationToken>,
+    app_stop: CancellationToken,
+}
+
+impl<S: EventSink + Clone> Pipeline<S> {
+    pub fn new(path: DataPath, sink: S, shutdown: &Shutdown) -> Self {
+        let app_stop = shutdown.token();
+        Pipeline { sink, path: Mutex::new(Some(path)), run: tokio::sync::Mutex::new(app_stop.child_token()), app_stop }
+    }
+
+    /// Start the first run; the window it runs on.
+    pub async fn start(&self, shutdown: &Shutdown) -> Result<Duration, String> {
+        let stop = self.run.lock().await.clone();
+        self.spawn(shutdown, stop)
+    }
+
+    /// Stop the running run, flushing it, and start a new one on the current settings.
+    pub async fn restart(&self, shutdown: &Shutdown) -> Result<RestartReport, String> {
+        let Ok(mut run) = self.run.try_lock() else { return Err("a pipeline restart is already running".to_string()) };
+        if self.app_stop.is_cancelled() { return Err("the app is shutting down".to_string()); }
+        let started = Instant::now();
+
+        self.report(RestartStep::Reloading, true, &[], None);
+        if let Err(e) = aggregation_window().and_then(|_| reload_mqtt_auth()) {
+            warn!(target: "PIPE", "restart refused, the data path keeps running: {e}");
+            self.report(RestartStep::Failed, true, &[], Some(&e));
+            return Err(e);
+        }
+
+        info!(target: "PIPE", "restarting the data path");
+        self.report(RestartStep::Stopping, false, &[], None);
+        run.cancel();
+        let abandoned = join_within(shutdown.untrack(&STAGES), SHUTDOWN_TIMEOUT).await;
+        if !abandoned.is_empty() {
+            warn!(target: "PIPE", "still running after {}s, left to finish: {}", SHUTDOWN_TIMEOUT.as_secs(), abandoned.join(", "));
+        }
+
+        self.report(RestartStep::Starting, false, &abandoned, None);
+        *run = self.app_stop.child_token();
+        match self.spawn(shutdown, run.clone()) {
+            Ok(window) => {
+                let took_ms = started.elapsed().as_millis() as u64;
+                info!(target: "PIPE", "data path restarted in {took_ms}ms (window {}s)", window.as_secs());
+                self.report(RestartStep::Running, true, &abandoned, None);
+                Ok(RestartReport { window_secs: window.as_secs(), abandoned, took_ms })
+            }
+            Err(e) => {
+                error!(target: "PIPE", "data path not restarted: {e}; nothing is recorded until the app restarts");
+                self.report(RestartStep::Failed, false, &abandoned, Some(&e));
+                Err(e)
+            }
+        }
+    }
+
+    /// Drop the lanes held for later runs so their consumers see them close once the run stops; on exit.
+    pub fn close(&self) {
+        self.path.lock().unwrap_or_else(PoisonError::into_inner).take();
+    }
+
+    fn report(&self, step: RestartStep, running: bool, abandoned: &[&'static str], error: Option<&str>) {
+        let ev = RestartProgress { step, running, abandoned: abandoned.to_vec(), error: error.map(str::to_string), ts_ms: now_ms() };
+        if let Ok(v) = serde_json::to_value(&ev) { self.sink.emit_json("pipeline_restart", v); }
+    }
+
+    /// Build a run's channels and start its stages on `stop`; they are tracked in `shutdown`.
+    fn spawn(&self, shutdown: &Shutdown, stop: CancellationToken) -> Result<Duration, String> {
+        let window = aggregation_window()?;
+        let path = self.path.lock().unwrap_or_else(PoisonError::into_inner).clone().ok_or("the app is shutting down")?;
+        let (tx_decoded, rx_decoded) = requeue::<Decoded>(&path.decoded);
+        let (tx_nodeavg_for_gh, rx_nodeavg_for_gh) = lane::<NodeAvg>(&path.lanes, "nodeavg_gh", Priority::Droppable, channel_config().nodeavg);
+
+        // Greenhouse aggregator (NodeAvg -> GhAvg -> DB & UI)
+        let rx_nodeavg_for_gh = Slot::new(rx_nodeavg_for_gh);
+        let (gh_outputs, rosters, gh_sink) = (path.greenhouses, path.rosters, self.sink.clone());
+        shutdown.track("greenhouse_avg", tauri::async_runtime::spawn(supervise("greenhouse_avg", self.sink.clone(), stop.clone(), move || {
+            let (rx, out, sink, rosters) = (rx_nodeavg_for_gh.lease(), gh_outputs.clone(), gh_sink.clone(), rosters.clone());
+            async move {
+                let Some(rx) = rx else { return };
+                run_greenhouse_avg(rx, window, out, rosters, sink).await;
+            }
+        })));
+
+        // Node rolling averages (Decoded -> NodeAvg for GH & DB & UI)
+        let rx_decoded = Slot::new(rx_decoded);
+        let node_outputs = path.nodes.with_gh(tx_nodeavg_for_gh);
+        let (maintenance, health, decoder_stats, calibrations) = (path.maintenance, path.health, path.decoder_stats, path.calibrations);
+        shutdown.track("rolling_avg", tauri::async_runtime::spawn(supervise("rolling_avg", self.sink.clone(), stop.clone(), move || {
+            let (rx, out) = (rx_decoded.lease(), node_outputs.clone());
+            let (maintenance, health, stats) = (maintenance.clone(), health.clone(), decoder_stats.clone());
+            let calibrations = calibrations.clone();
+            async move {
+                let Some(rx) = rx else { return };
+                run_rolling_avg(rx, window, out, maintenance, health, stats, calibrations).await;
+            }
+        })));
+
+        // Live broker traffic, or a traffic recording played back in its place (--replay)
+        let source = match path.source {
+            Source::Replay { path, speed, schemas } => {
+                tauri::async_runtime::spawn(run_replay_source(path, speed, tx_decoded, schemas, stop))
+            }
+            Source::Mqtt(shared) => {
+                let tx_decoded = Slot::new(tx_decoded);
+                let subscriber_stop = stop.clone();
+                tauri::async_runtime::spawn(supervise("subscriber", self.sink.clone(), stop, move || {
+                    let (tx, shared, stop) = (tx_decoded.lease(), shared.clone(), subscriber_stop.clone());
+                    async move {
+                        let Some(tx) = tx else { return };
+                        run_debug_subscriber(tx, shared, stop).await;
+                    }
+                }))
+            }
+        };
+        shutdown.track("subscriber", source);
+        Ok(window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::channels::{lane_stats, queue_counters, FullPolicy};
+    use crate::services::mqtt::greenhouse_sensor::decoder::encode_payload;
+    use crate::services::mqtt::greenhouse_sensor::greenhouse_aggregator::GhAvg;
+    use crate::services::mqtt::recording::MAGIC;
+    use crate::services::presenter::emitter::RecordingSink;
+    use tokio::time::sleep;
+
+    /// A recording of one v1 frame of GH 1 node 2 at once and another an hour later.
+    fn recording(dir: &std::path::Path) -> PathBuf {
+        let frame = encode_payload(&Decoded::Standard {
+            greenhouse_id: 1, node_id: 2,
+            air_temp_c: 24.5, leaf_temp_c: 23.0, bag_temp_c: 21.5, air_rh_pct: 68.0,
+            bag_rh1_pct: 80.0, bag_rh2_pct: 81.0, bag_rh3_pct: 82.0, bag_rh4_pct: 83.0, bag_rh_avg_pct: 81.5,
+            par_value: 420, weight_g: 1200.0,
+            ea_air_kpa: 2.1, ea_leaf_kpa: 2.4, es_kpa: 3.1, vpd_kpa: 1.0,
+            battery_v: None, rssi_dbm: None, seq: None, device_ts: None,
+        });
+        let topic = "greenhouse/1/node/2/data";
+        let mut file = MAGIC.to_vec();
+        for rel_ms in [0u32, 3_600_000] {
+            file.extend(rel_ms.to_le_bytes());
+            file.extend((topic.len() as u16).to_le_bytes());
+            file.extend(topic.as_bytes());
+            file.extend((frame.len() as u32).to_le_bytes());
+            file.extend(&frame);
+        }
+        let path = dir.join("site.ghraw");
+        std::fs::write(&path, file).unwrap();
+        path
+    }
+
+    fn steps(sink: &RecordingSink) -> Vec<String> {
+        sink.0.lock().unwrap().iter()
+            .filter(|(e, _)| e == "pipeline_restart")
+            .map(|(_, v)| v["step"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn a_restart_flushes_the_running_windows_and_starts_over_on_the_same_lanes() {
+        let dir = tempfile::tempdir().unwrap();
+        let lanes = LanesShared::default();
+        let (db, mut rx_nodes) = lane::<NodeAvg>(&lanes, "nodeavg_db", Priority::Droppable, 16);
+        let (ui, _rx_ui) = lane(&lanes, "nodeavg_ui", Priority::Droppable, 16);
+        let (live, _rx_live) = lane(&lanes, "nodelive_ui", Priority::Droppable, 16);
+        let (status, _rx_status) = lane(&lanes, "node_status", Priority::Droppable, 16);
+        let (catchup, _rx_catchup) = lane(&lanes, "nodeavg_catchup", Priority::Critical, 16);
+        let (catchup_done, _rx_done) = lane(&lanes, "catchup_done", Priority::Droppable, 4);
+        let (gh_db, mut rx_ghs) = lane::<GhAvg>(&lanes, "ghavg_db", Priority::Critical, 16);
+        let (gh_ui, _rx_gh_ui) = lane(&lanes, "ghavg_ui", Priority::Droppable, 16);
+        let (hourly, _rx_hourly) = lane(&lanes, "ghavg_hourly", Priority::Critical, 16);
+        let (kpi, _rx_kpi) = lane(&lanes, "ghavg_kpi", Priority::Critical, 16);
+        let path = DataPath {
+            source: Source::Replay { path: recording(dir.path()), speed: 1.0, schemas: SchemaShared::default() },
+            lanes: lanes.clone(),
+            decoded: queue_counters("decoded", 64, FullPolicy::DropNewest),
+            nodes: NodeOutputs { db, ui, live, status, latest: LatestNodeShared::default(), catchup, catchup_done },
+            greenhouses: GhAvgOutputs { db: gh_db, ui: gh_ui, hourly, kpi, latest: Default::default() },
+            rosters: RosterShared::default(),
+            calibrations: CalibrationShared::default(),
+            maintenance: MaintenanceShared::default(),
+            health: HealthCountersShared::default(),
+            decoder_stats: DecoderStatsShared::default(),
+        };
+        let (sink, shutdown) = (RecordingSink::default(), Shutdown::default());
+        let pipeline = Pipeline::new(path, sink.clone(), &shutdown);
+        pipeline.start(&shutdown).await.unwrap();
+        sleep(Duration::from_millis(300)).await;
+        assert!(rx_nodes.try_recv().is_err(), "the window is still open");
+
+        // the replay waits an hour for its second frame; the restart stops it and flushes the open windows
+        let report = pipeline.restart(&shutdown).await.unwrap();
+        assert!(report.abandoned.is_empty());
+        assert_eq!(steps(&sink), ["reloading", "stopping", "starting", "running"]);
+        let node = rx_nodes.try_recv().expect("the node window flushed by the stopping run");
+        assert_eq!((node.greenhouse_id, node.node_id, node.air_temp_c), (1, 2, Some(24.5)));
+        assert_eq!(rx_ghs.try_recv().expect("the greenhouse window flushed by the stopping run").greenhouse_id, 1);
+
+        // the new run plays the recording again into the same consumers
+        sleep(Duration::from_millis(300)).await;
+        pipeline.close();
+        shutdown.stop(SHUTDOWN_TIMEOUT);
+        assert_eq!(rx_nodes.recv().await.map(|n| n.node_id), Some(2));
+        assert!(rx_nodes.recv().await.is_none(), "closed once the run stopped and the originals were dropped");
+
+        let names: Vec<&str> = lane_stats(&lanes).iter().map(|l| l.name).collect();
+        assert_eq!(names.iter().filter(|n| **n == "nodeavg_gh").count(), 1, "{names:?}");
+        assert_eq!(lane_stats(&lanes).iter().find(|l| l.name == "nodeavg_gh").unwrap().sent, 2);
+        assert!(sink.0.lock().unwrap().iter().all(|(e, _)| e != "task_failed"), "a stop is not a failure");
+    }
+}
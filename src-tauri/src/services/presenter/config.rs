@@ -0,0 +1,42 @@
+#[derive(Clone, Copy)]
+pub struct KioskConfig {
+    /// Where the lobby-screen snapshot is written (relative to the working dir); None disables it.
+    pub snapshot_path: Option<&'static str>,
+    pub every_secs: u64,
+}
+
+pub const fn kiosk_config() -> KioskConfig {
+    KioskConfig {
+        snapshot_path: Some("data/kiosk_snapshot.json"),
+        every_secs: 60,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct LiveConfig {
+    /// How often the node aggregator emits `node_live` (latest raw sample per node); 0 turns it off.
+    pub every_secs: u64,
+    /// Nodes without a sample this recent are left out instead of repeating a stale value.
+    pub max_age_secs: u64,
+}
+
+pub const fn live_snapshot() -> LiveConfig {
+    LiveConfig {
+        every_secs: 5,
+        max_age_secs: 15,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct PayloadCapConfig {
+    /// Per-event size caps in serialized bytes; events not listed use `default_max_bytes`.
+    pub caps: &'static [(&'static str, usize)],
+    pub default_max_bytes: usize,
+}
+
+pub const fn payload_caps() -> PayloadCapConfig {
+    PayloadCapConfig {
+        caps: &[("node_avg", 2048), ("gh_avg", 2048), ("site_overview", 16_384)],
+        default_max_bytes: 65_536,
+    }
+}
@@ -0,0 +1,363 @@
+//! UI event emitter.
+//! - Consumes the UI channels (NodeAvgUi, NodeLive, GhAvg, GhHourly, ClockAdjustment) and turns each message into the
+//!   exact JSON payload the frontend listens for.
+//! - Adds node labels and display prefs, and rounds floats to two decimals (same precision as terminal and DB).
+//! - Emits through the `EventSink` trait: AppHandle in production, anything in tests/tools.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::HashMap, sync::{Arc, RwLock}};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::services::mqtt::greenhouse_sensor::{
+    aggregator::{NodeAvgUi, NodeLive},
+    greenhouse_aggregator::GhAvg,
+    hourly_aggregator::GhHourly,
+    nodes::label_for,
+};
+use crate::services::clock::ClockAdjustment;
+use crate::services::report::vpd_kpi::VpdKpiShared;
+use crate::services::storage::node_meta::NodeDisplayPrefs;
+use crate::services::supervisor::Lease;
+
+const PRECISION: i32 = 2;
+
+/// Display prefs by (greenhouse_id, node_id), attached to `node_avg` payloads.
+pub type DisplayPrefsShared = Arc<RwLock<HashMap<(u16, u16), NodeDisplayPrefs>>>;
+
+pub fn replace_display_prefs(cache: &DisplayPrefsShared, list: Vec<NodeDisplayPrefs>) {
+    if let Ok(mut c) = cache.write() {
+        *c = list.into_iter().map(|p| ((p.greenhouse_id, p.node_id), p)).collect();
+    }
+}
+
+/// Where presented events go.
+pub trait EventSink: Send + Sync + 'static {
+    fn emit_json(&self, event: &str, payload: Value);
+
+    /// `payload` already serialized to JSON; a sink that forwards text sends it as is.
+    fn emit_str(&self, event: &str, payload: String) {
+        match serde_json::from_str(&payload) {
+            Ok(v) => self.emit_json(event, v),
+            Err(e) => warn!(target: "UI", "emit {event}: invalid JSON: {e}"),
+        }
+    }
+}
+
+impl EventSink for tauri::AppHandle {
+    fn emit_json(&self, event: &str, payload: Value) {
+        use tauri::Emitter;
+        if let Err(e) = self.emit(event, payload) {
+            warn!(target: "UI", "emit {event} failed: {e}");
+        }
+    }
+
+    fn emit_str(&self, event: &str, payload: String) {
+        use tauri::Emitter;
+        if let Err(e) = Emitter::emit_str(self, event, payload) {
+            warn!(target: "UI", "emit {event} failed: {e}");
+        }
+    }
+}
+
+/// Records every event, for tests.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct RecordingSink(pub Arc<std::sync::Mutex<Vec<(String, Value)>>>);
+
+#[cfg(test)]
+impl EventSink for RecordingSink {
+    fn emit_json(&self, event: &str, payload: Value) {
+        self.0.lock().unwrap().push((event.to_string(), payload));
+    }
+}
+
+/// Round every non-integer number in `v` to `places` decimals (ids/timestamps stay untouched).
+fn round_floats(v: &mut Value, places: i32) {
+    match v {
+        Value::Number(n) if n.is_f64() => {
+            let scale = 10f64.powi(places);
+            if let Some(r) = n.as_f64().map(|x| (x * scale).round() / scale).and_then(serde_json::Number::from_f64) {
+                *n = r;
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|x| round_floats(x, places)),
+        Value::Object(map) => map.values_mut().for_each(|x| round_floats(x, places)),
+        _ => {}
+    }
+}
+
+pub struct UiEmitter<S: EventSink> {
+    sink: S,
+    prefs: DisplayPrefsShared,
+    vpd_kpi: VpdKpiShared,
+}
+
+impl<S: EventSink> UiEmitter<S> {
+    pub fn new(sink: S, prefs: DisplayPrefsShared, vpd_kpi: VpdKpiShared) -> Self { Self { sink, prefs, vpd_kpi } }
+
+    fn present<T: Serialize>(&self, event: &str, msg: &T, extra: impl FnOnce(&mut Value)) {
+        match serde_json::to_value(msg) {
+            Ok(mut v) => {
+                extra(&mut v);
+                round_floats(&mut v, PRECISION);
+                self.sink.emit_json(event, v);
+            }
+            Err(e) => warn!(target: "UI", "serialize {event} failed: {e}"),
+        }
+    }
+
+    /// `node_avg`: per-node window averages plus the node label and display prefs.
+    pub fn node_avg(&self, na: &NodeAvgUi) {
+        let display = self.prefs.read().ok()
+            .and_then(|p| p.get(&(na.greenhouse_id, na.node_id)).cloned())
+            .and_then(|p| serde_json::to_value(p).ok())
+            .unwrap_or(Value::Null);
+        self.present("node_avg", na, |v| {
+            v["label"] = Value::from(label_for(na.node_id, na.outdoor));
+            v["display"] = display;
+        });
+    }
+
+    /// `node_live`: a node's latest sample between windows, with its label.
+    pub fn node_live(&self, nl: &NodeLive) {
+        self.present("node_live", nl, |v| v["label"] = Value::from(label_for(nl.node_id, nl.outdoor)));
+    }
+
+    /// `gh_avg`: greenhouse window averages plus today's VPD KPI so far
+    /// (updated in parallel, so it may not include this window yet).
+    pub fn gh_avg(&self, ga: &GhAvg) {
+        let kpi = self.vpd_kpi.read().ok()
+            .and_then(|m| m.get(&ga.greenhouse_id).copied())
+            .map(|d| serde_json::json!({
+                "in_band_min": d.in_band_min,
+                "out_band_min": d.out_band_min,
+                "unknown_min": d.unknown_min,
+                "pct_in_band": d.pct_in_band(),
+            }))
+            .unwrap_or(Value::Null);
+        self.present("gh_avg", ga, |v| v["vpd_kpi_today"] = kpi);
+    }
+
+    /// `gh_hourly`: closed local hours.
+    pub fn gh_hourly(&self, h: &GhHourly) {
+        self.present("gh_hourly", h, |_| {});
+    }
+
+    /// `clock_adjusted`: wall-clock jump with its quarantined interval.
+    pub fn clock_adjusted(&self, adj: &ClockAdjustment) {
+        self.present("clock_adjusted", adj, |_| {});
+    }
+
+    /// Forward all UI channels until every sender is gone.
+    pub async fn run(
+        self,
+        mut rx_node: Lease<mpsc::Receiver<NodeAvgUi>>,
+        mut rx_live: Lease<mpsc::Receiver<NodeLive>>,
+        mut rx_gh: Lease<mpsc::Receiver<GhAvg>>,
+        mut rx_hourly: Lease<mpsc::Receiver<GhHourly>>,
+        mut rx_clock: Lease<mpsc::Receiver<ClockAdjustment>>,
+    ) {
+        loop {
+            tokio::select! {
+                Some(na) = rx_node.recv() => self.node_avg(&na),
+                Some(nl) = rx_live.recv() => self.node_live(&nl),
+                Some(ga) = rx_gh.recv() => self.gh_avg(&ga),
+                Some(h) = rx_hourly.recv() => self.gh_hourly(&h),
+                Some(adj) = rx_clock.recv() => self.clock_adjusted(&adj),
+                else => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mqtt::greenhouse_sensor::aggregator::NodeAvg;
+    use crate::services::mqtt::greenhouse_sensor::hourly_aggregator::HourStat;
+    use crate::services::storage::daily::VpdDay;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn emitter() -> (UiEmitter<RecordingSink>, RecordingSink, DisplayPrefsShared, VpdKpiShared) {
+        let (sink, prefs, kpi) = (RecordingSink::default(), DisplayPrefsShared::default(), VpdKpiShared::default());
+        (UiEmitter::new(sink.clone(), prefs.clone(), kpi.clone()), sink, prefs, kpi)
+    }
+
+    /// The single event `sink` recorded.
+    fn only(sink: &RecordingSink) -> (String, Value) {
+        let mut events = sink.0.lock().unwrap();
+        assert_eq!(events.len(), 1, "{events:?}");
+        events.pop().unwrap()
+    }
+
+    /// One object from several `json!` objects (a single one this wide exceeds the macro's recursion limit).
+    fn merged(parts: impl IntoIterator<Item = Value>) -> Value {
+        let mut out = serde_json::Map::new();
+        for part in parts {
+            let Value::Object(map) = part else { panic!("not an object: {part}") };
+            out.extend(map);
+        }
+        Value::Object(out)
+    }
+
+    /// The sensor extremes and spreads of a window without any.
+    fn no_extremes() -> Value {
+        json!({
+            "air_temp_c_min": null, "air_temp_c_max": null, "air_rh_pct_min": null, "air_rh_pct_max": null,
+            "vpd_kpa_min": null, "vpd_kpa_max": null, "par_value_min": null, "par_value_max": null,
+            "weight_g_min": null, "weight_g_max": null,
+        })
+    }
+
+    #[test]
+    fn node_avg_payload_is_rounded_labelled_and_carries_display_prefs() {
+        let (ui, sink, prefs, _) = emitter();
+        replace_display_prefs(&prefs, vec![NodeDisplayPrefs {
+            greenhouse_id: 2, node_id: 3, color: Some("#22aa44".into()), sort_order: Some(1), icon: None, hidden: false,
+        }]);
+        let mut na = NodeAvg::sample(2, 3, 1_760_000_000_000, 24.456);
+        na.air_rh_pct = Some(70.0 / 3.0);
+        na.lost_packets = Some(1);
+        ui.node_avg(&NodeAvgUi::new(&na));
+
+        let (event, payload) = only(&sink);
+        assert_eq!(event, "node_avg");
+        assert_eq!(payload, merged([
+            json!({
+                "ts_ms": 1_760_000_000_000i64, "greenhouse_id": 2, "node_id": 3, "window_sec": 60, "outdoor": false,
+                "label": "node03",
+                "display": { "greenhouse_id": 2, "node_id": 3, "color": "#22aa44", "sort_order": 1, "icon": null, "hidden": false },
+            }),
+            json!({
+                "air_temp_c": 24.46, "leaf_temp_c": null, "bag_temp_c": null, "air_rh_pct": 23.33,
+                "bag_rh1_pct": null, "bag_rh2_pct": null, "bag_rh3_pct": null, "bag_rh4_pct": null, "bag_rh_avg_pct": null,
+                "par_value": null, "weight_g": null, "ea_air_kpa": null, "ea_leaf_kpa": null, "es_kpa": null, "vpd_kpa": null,
+                "leaf_air_dt_c": null, "transpiration_g_min": null,
+            }),
+            no_extremes(),
+            json!({
+                "air_temp_sd": null, "air_rh_sd": null, "vpd_sd": null,
+                "samples": {}, "dew_point_c": null,
+                "maintenance": false, "received_packets": 6, "lost_packets": 1, "battery_v": null, "rssi_dbm": null,
+                "wind_ms": null, "wind_gust_ms": null, "rain_mm": null,
+            }),
+        ]));
+    }
+
+    #[test]
+    fn node_avg_without_prefs_has_null_display_and_outdoor_labels() {
+        let (ui, sink, _, _) = emitter();
+        let mut na = NodeAvg::sample(1, 65002, 1_760_000_000_000, 12.0);
+        na.outdoor = true;
+        na.wind_ms = Some(3.456);
+        ui.node_avg(&NodeAvgUi::new(&na));
+        let (_, payload) = only(&sink);
+        assert_eq!(payload["label"], "Outdoor_Node_2");
+        assert_eq!(payload["display"], Value::Null);
+        assert_eq!(payload["wind_ms"], 3.46);
+        assert_eq!(payload["outdoor"], true);
+    }
+
+    #[test]
+    fn gh_avg_payload_carries_todays_vpd_kpi() {
+        let (ui, sink, _, kpi) = emitter();
+        kpi.write().unwrap().insert(2, VpdDay {
+            greenhouse_id: 2, day_start_ms: 1_759_960_800_000, in_band_min: 200.0, out_band_min: 100.0,
+            unknown_min: 5.0, vpd_min_kpa: 0.4, vpd_max_kpa: 1.6, daylight_par_min: 305.0,
+        });
+        ui.gh_avg(&GhAvg::sample(2, 1_760_000_000_000));
+        // the sample of greenhouse 1 has no KPI yet
+        ui.gh_avg(&GhAvg::sample(1, 1_760_000_000_000));
+
+        let events = sink.0.lock().unwrap().clone();
+        assert_eq!(events.len(), 2);
+        let (event, payload) = &events[0];
+        assert_eq!(event, "gh_avg");
+        assert_eq!(*payload, merged([
+            json!({
+                "ts_ms": 1_760_000_000_000i64, "window_start_ms": 1_759_999_940_000i64, "window_sec": 60, "window_seq": 1,
+                "greenhouse_id": 2, "nodes": 3, "roster": 3, "coverage": 1.0, "confidence": "high", "outdoor": null,
+            }),
+            json!({
+                "air_temp_c": 24.0, "leaf_temp_c": 23.0, "bag_temp_c": 21.0, "air_rh_pct": 70.0,
+                "bag_rh1_pct": 80.0, "bag_rh2_pct": 80.0, "bag_rh3_pct": 80.0, "bag_rh4_pct": 80.0, "bag_rh_avg_pct": 80.0,
+                "par_value": 400.0, "weight_g": 12000.0, "ea_air_kpa": 2.1, "ea_leaf_kpa": 2.4, "es_kpa": 3.0, "vpd_kpa": 0.9,
+                "leaf_air_dt_c": -1.0, "transpiration_g_min": null,
+            }),
+            no_extremes(),
+            json!({
+                "dew_point_c": null,
+                "vpd_kpi_today": { "in_band_min": 200.0, "out_band_min": 100.0, "unknown_min": 5.0, "pct_in_band": 66.67 },
+            }),
+        ]));
+        assert_eq!(events[1].1["greenhouse_id"], 1);
+        assert_eq!(events[1].1["vpd_kpi_today"], Value::Null);
+    }
+
+    #[test]
+    fn node_live_payload_lists_finite_readings() {
+        let (ui, sink, _, _) = emitter();
+        ui.node_live(&NodeLive {
+            ts_ms: 1_760_000_012_345, greenhouse_id: 1, node_id: 12, outdoor: false,
+            values: BTreeMap::from([("air_temp_c", 21.005), ("par_value", 512.0)]),
+        });
+        assert_eq!(only(&sink), ("node_live".to_string(), json!({
+            "ts_ms": 1_760_000_012_345i64, "greenhouse_id": 1, "node_id": 12, "outdoor": false,
+            "values": { "air_temp_c": 21.0, "par_value": 512.0 },
+            "label": "node12",
+        })));
+    }
+
+    #[test]
+    fn hourly_and_clock_payloads_pass_through_rounded() {
+        let (ui, sink, _, _) = emitter();
+        ui.gh_hourly(&GhHourly {
+            hour_start_ms: 1_759_996_800_000, greenhouse_id: 1, nodes: 4,
+            fields: BTreeMap::from([("air_temp_c".to_string(), HourStat { unit: "C".into(), mean: Some(22.125), min: Some(20.0), max: None })]),
+        });
+        ui.clock_adjusted(&ClockAdjustment {
+            detected_ms: 1_760_000_000_000, delta_ms: -3_600_000,
+            from_ms: 1_759_996_400_000, to_ms: 1_760_000_060_000,
+            before_from_ms: 1_759_999_940_000, before_to_ms: 1_760_003_600_000,
+        });
+        let events = sink.0.lock().unwrap().clone();
+        assert_eq!(events, [
+            ("gh_hourly".to_string(), json!({
+                "hour_start_ms": 1_759_996_800_000i64, "greenhouse_id": 1, "nodes": 4,
+                "fields": { "air_temp_c": { "unit": "C", "mean": 22.13, "min": 20.0, "max": null } },
+            })),
+            ("clock_adjusted".to_string(), json!({
+                "detected_ms": 1_760_000_000_000i64, "delta_ms": -3_600_000,
+                "from_ms": 1_759_996_400_000i64, "to_ms": 1_760_000_060_000i64,
+                "before_from_ms": 1_759_999_940_000i64, "before_to_ms": 1_760_003_600_000i64,
+            })),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn run_routes_every_channel_and_ends_with_its_senders() {
+        use crate::services::supervisor::Slot;
+        let (ui, sink, _, _) = emitter();
+        let (node_tx, node_rx) = mpsc::channel(4);
+        let (live_tx, live_rx) = mpsc::channel(4);
+        let (gh_tx, gh_rx) = mpsc::channel(4);
+        let (hourly_tx, hourly_rx) = mpsc::channel(4);
+        let (clock_tx, clock_rx) = mpsc::channel::<ClockAdjustment>(4);
+        node_tx.send(NodeAvgUi::new(&NodeAvg::sample(1, 1, 1_000, 20.0))).await.unwrap();
+        gh_tx.send(GhAvg::sample(1, 1_000)).await.unwrap();
+        drop((node_tx, live_tx, gh_tx, hourly_tx, clock_tx));
+
+        fn lease<T>(rx: mpsc::Receiver<T>) -> Lease<mpsc::Receiver<T>> { Slot::new(rx).lease().unwrap() }
+        tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            ui.run(lease(node_rx), lease(live_rx), lease(gh_rx), lease(hourly_rx), lease(clock_rx)),
+        ).await.expect("run returns once every sender is gone");
+
+        let mut names: Vec<String> = sink.0.lock().unwrap().iter().map(|(e, _)| e.clone()).collect();
+        names.sort();
+        assert_eq!(names, ["gh_avg", "node_avg"]);
+    }
+}
@@ -0,0 +1,185 @@
+//! Kiosk snapshot: the sanctioned way for the lobby screen to read live data.
+//! - Every `kiosk_config().every_secs` a small JSON file is written to `snapshot_path`: latest GhAvg per
+//!   greenhouse, today's (local) extremes and the active alert count. Extremes cover the closed hours
+//!   since midnight, the running hour so far and the latest window. A greenhouse whose latest GhAvg is
+//!   older than `STALE_AFTER_WINDOWS` of its window stays listed but is flagged `stale`, with its age. The lobby app reads this file
+//!   instead of opening our SQLite DB, so it never takes a lock on it.
+//! - Written as temp file + fsync + rename, so readers always see a complete, valid document,
+//!   even if the app is killed mid-write.
+//! - Built from shared in-memory state only, with the file I/O on a blocking thread; the pipeline never waits on it.
+//! - `schema_version` changes whenever a field is renamed or removed (adding fields keeps it).
+
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, io::Write, path::{Path, PathBuf}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+use crate::services::mqtt::greenhouse_sensor::{
+    greenhouse_aggregator::{GhAvg, LatestGhShared},
+    hourly_aggregator::{local_day_start, HourlyShared},
+};
+use crate::services::storage::sqlite::absolute_path;
+use super::config::kiosk_config;
+use super::overview::STALE_AFTER_WINDOWS;
+
+pub const KIOSK_SCHEMA_VERSION: u32 = 1;
+const DAY_HOURS: usize = 24;
+/// Fields with daily extremes on the lobby screen.
+const EXTREME_KEYS: &[&str] = &["air_temp_c", "air_rh_pct", "vpd_kpa"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Extreme {
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KioskGreenhouse {
+    pub latest: GhAvg,
+    /// Seconds since `latest` closed.
+    pub age_s: i64,
+    /// `latest` is too old to show as current.
+    pub stale: bool,
+    /// Since local midnight: closed hours, the running hour and the latest window.
+    pub today: BTreeMap<&'static str, Extreme>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KioskSnapshot {
+    pub schema_version: u32,
+    pub generated_ms: i64,
+    pub greenhouses: Vec<KioskGreenhouse>,
+    /// None until an alert engine exists; the field is reserved so readers need no schema bump.
+    pub active_alerts: Option<u32>,
+}
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+fn fold(acc: Option<f32>, v: Option<f32>, f: fn(f32, f32) -> f32) -> Option<f32> {
+    match (acc, v) {
+        (Some(a), Some(b)) => Some(f(a, b)),
+        (a, b) => a.or(b),
+    }
+}
+
+pub fn kiosk_snapshot(latest: &LatestGhShared, hourly: &HourlyShared) -> KioskSnapshot {
+    let now = now_ms();
+    let midnight = local_day_start(now);
+    let latest = latest.read().map(|m| m.clone()).unwrap_or_default();
+    let store = hourly.read().ok();
+
+    let greenhouses = latest.into_values().map(|ga| {
+        let mut hours = store.as_ref().map(|s| s.recent(ga.greenhouse_id, DAY_HOURS)).unwrap_or_default();
+        hours.extend(store.as_ref().and_then(|s| s.running(ga.greenhouse_id)).cloned());
+        let fields = ga.fields();
+        let today = EXTREME_KEYS.iter().map(|&key| {
+            let current = fields.iter().find(|(k, _, _)| *k == key).and_then(|&(_, _, v)| v);
+            let (min, max) = hours.iter()
+                .filter(|h| h.hour_start_ms >= midnight)
+                .filter_map(|h| h.fields.get(key))
+                .fold((current, current), |(lo, hi), s| (fold(lo, s.min, f32::min), fold(hi, s.max, f32::max)));
+            (key, Extreme { min, max })
+        }).collect();
+        let age_ms = (now - ga.ts_ms).max(0);
+        let stale = age_ms > STALE_AFTER_WINDOWS * ga.window_sec as i64 * 1000;
+        KioskGreenhouse { latest: ga, age_s: age_ms / 1000, stale, today }
+    }).collect();
+
+    KioskSnapshot { schema_version: KIOSK_SCHEMA_VERSION, generated_ms: now, greenhouses, active_alerts: None }
+}
+
+/// Write `bytes` to `path` so readers only ever see the old or the new complete file.
+fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() { fs::create_dir_all(dir)?; }
+    let tmp = PathBuf::from(format!("{}.tmp", path.display()));
+    let mut f = fs::File::create(&tmp)?;
+    f.write_all(bytes)?;
+    f.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// Periodic writer; returns immediately when no snapshot path is configured.
+pub async fn run_kiosk_snapshot(latest: LatestGhShared, hourly: HourlyShared) {
+    let cfg = kiosk_config();
+    let Some(rel) = cfg.snapshot_path else { return };
+    let path = absolute_path(rel);
+    info!(target: "KIOSK", "snapshot v{KIOSK_SCHEMA_VERSION} -> {} every {}s", path.display(), cfg.every_secs);
+
+    let mut tick = interval(Duration::from_secs(cfg.every_secs.max(1)));
+    loop {
+        tick.tick().await;
+        let bytes = match serde_json::to_vec(&kiosk_snapshot(&latest, &hourly)) {
+            Ok(b) => b,
+            Err(e) => { warn!(target: "KIOSK", "serialize failed: {e}"); continue; }
+        };
+        let p = path.clone();
+        match tokio::task::spawn_blocking(move || write_atomic(&p, &bytes)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!(target: "KIOSK", "write {} failed: {e}", path.display()),
+            Err(e) => error!(target: "KIOSK", "join error: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mqtt::greenhouse_sensor::hourly_aggregator::{local_hour_start, run_hourly_avg};
+    use crate::services::supervisor::Slot;
+    use tokio::sync::mpsc;
+
+    /// Folds `windows` through the hourly aggregator into a fresh store, as the pipeline does.
+    async fn hourly_of(windows: Vec<GhAvg>) -> HourlyShared {
+        let store = HourlyShared::default();
+        let (tx, rx) = mpsc::channel(windows.len().max(1));
+        for ga in windows { tx.send(ga).await.unwrap(); }
+        drop(tx);
+        let (db, _db_rx) = mpsc::channel(8);
+        let (ui, _ui_rx) = mpsc::channel(8);
+        let run = run_hourly_avg(Slot::new(rx).lease().unwrap(), db, ui, store.clone());
+        // the task also waits for the next :00; it has folded everything once the channel is drained
+        let _ = tokio::time::timeout(Duration::from_millis(200), run).await;
+        store
+    }
+
+    fn with_air(ts_ms: i64, air_temp_c: f32) -> GhAvg {
+        GhAvg { air_temp_c: Some(air_temp_c), ..GhAvg::sample(1, ts_ms) }
+    }
+
+    #[tokio::test]
+    async fn extremes_include_the_running_hour() {
+        let now = now_ms();
+        let hour = local_hour_start(now);
+        // only windows of the open hour: no hour has closed yet
+        let t = |min: i64| (hour + min * 60_000).min(now);
+        let hourly = hourly_of(vec![with_air(t(0), 18.0), with_air(t(1), 31.0), with_air(t(2), 24.0)]).await;
+
+        let latest = LatestGhShared::default();
+        latest.write().unwrap().insert(1, with_air(now, 24.0));
+        let snap = kiosk_snapshot(&latest, &hourly);
+        let gh = &snap.greenhouses[0];
+        assert_eq!((gh.today["air_temp_c"].min, gh.today["air_temp_c"].max), (Some(18.0), Some(31.0)));
+        assert!(!gh.stale);
+    }
+
+    #[test]
+    fn old_greenhouse_is_listed_as_stale() {
+        let now = now_ms();
+        let latest = LatestGhShared::default();
+        latest.write().unwrap().insert(1, GhAvg::sample(1, now - 60_000));
+        latest.write().unwrap().insert(2, GhAvg::sample(2, now - 10 * 60_000));
+        let snap = kiosk_snapshot(&latest, &HourlyShared::default());
+        let mut flags: Vec<(u16, bool, bool)> = snap.greenhouses.iter()
+            .map(|g| (g.latest.greenhouse_id, g.stale, (g.age_s - (now - g.latest.ts_ms) / 1000).abs() <= 1))
+            .collect();
+        flags.sort();
+        assert_eq!(flags, [(1, false, true), (2, true, true)]);
+
+        let v = serde_json::to_value(&snap).unwrap();
+        assert_eq!(v["schema_version"], KIOSK_SCHEMA_VERSION);
+        assert!(v["greenhouses"][0]["stale"].is_boolean());
+        assert!(v["greenhouses"][0]["age_s"].is_i64());
+    }
+}
@@ -0,0 +1,147 @@
+//! Metered event sink: measures and caps every payload sent to the webview.
+//! - Per event type: count, serialized bytes (last/max/total) and emit rate since startup,
+//!   read through `get_pipeline_stats`.
+//! - A payload over its cap (`payload_caps()`) loses optional top-level fields in the fixed
+//!   order of `OPTIONAL_FIELDS` until it fits, and is marked `truncated: true`; the frontend
+//!   then fetches the dropped parts through their commands. Required fields are never dropped,
+//!   so a payload can stay over its cap (counted as `over_cap`).
+//! - The payload is serialized here once, measured, and handed on as JSON text (`emit_str`), so the
+//!   webview path does not serialize it again.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, sync::{Arc, RwLock}, time::{SystemTime, UNIX_EPOCH}};
+
+use super::config::payload_caps;
+use super::emitter::EventSink;
+
+/// Optional payload fields in strip order (heaviest / least needed first), with where to get them back.
+const OPTIONAL_FIELDS: &[&str] = &[
+    "sparkline",     // reserved for chart sparklines; always dropped first
+    "vpd_kpi_today", // gh_avg: get_vpd_kpi
+    "display",       // node_avg: get_node_display_prefs
+];
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EventStat {
+    pub count: u64,
+    pub last_bytes: usize,
+    pub max_bytes: usize,
+    pub total_bytes: u64,
+    pub truncated: u64,
+    pub over_cap: u64,
+    pub first_ms: i64,
+    pub last_ms: i64,
+    pub per_min: f64,
+}
+
+pub type EmitStatsShared = Arc<RwLock<BTreeMap<String, EventStat>>>;
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+fn cap_for(event: &str) -> usize {
+    let cfg = payload_caps();
+    cfg.caps.iter().find(|(e, _)| *e == event).map_or(cfg.default_max_bytes, |&(_, c)| c)
+}
+
+/// Serialize `payload`, stripping optional fields until it fits `cap`. Returns (JSON, truncated).
+/// A payload within its cap is serialized once; each stripped field costs one more pass.
+fn enforce_cap(mut payload: Value, cap: usize) -> (String, bool) {
+    let mut json = payload.to_string();
+    let mut truncated = false;
+    if let Value::Object(map) = &mut payload {
+        for key in OPTIONAL_FIELDS {
+            if json.len() <= cap { break; }
+            if map.remove(*key).is_some() {
+                truncated = true;
+                map.insert("truncated".into(), Value::Bool(true));
+                json = serde_json::to_string(&*map).unwrap_or_default();
+            }
+        }
+    }
+    (json, truncated)
+}
+
+#[derive(Clone)]
+pub struct MeteredSink<S: EventSink> {
+    inner: Arc<S>,
+    stats: EmitStatsShared,
+}
+
+impl<S: EventSink> MeteredSink<S> {
+    pub fn new(inner: S, stats: EmitStatsShared) -> Self { Self { inner: Arc::new(inner), stats } }
+}
+
+impl<S: EventSink> EventSink for MeteredSink<S> {
+    fn emit_json(&self, event: &str, payload: Value) {
+        let cap = cap_for(event);
+        let (json, truncated) = enforce_cap(payload, cap);
+        let bytes = json.len();
+        if let Ok(mut m) = self.stats.write() {
+            let now = now_ms();
+            let s = m.entry(event.to_string()).or_insert_with(|| EventStat { first_ms: now, ..Default::default() });
+            s.count += 1;
+            s.last_bytes = bytes;
+            s.max_bytes = s.max_bytes.max(bytes);
+            s.total_bytes += bytes as u64;
+            s.truncated += truncated as u64;
+            s.over_cap += (bytes > cap) as u64;
+            s.last_ms = now;
+            let mins = (now - s.first_ms) as f64 / 60_000.0;
+            s.per_min = if mins > 0.0 { (s.count - 1) as f64 / mins } else { 0.0 };
+        }
+        self.inner.emit_str(event, json);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::presenter::emitter::RecordingSink;
+    use serde_json::json;
+
+    fn metered() -> (MeteredSink<RecordingSink>, RecordingSink, EmitStatsShared) {
+        let rec = RecordingSink::default();
+        let stats: EmitStatsShared = Default::default();
+        (MeteredSink::new(rec.clone(), stats.clone()), rec, stats)
+    }
+
+    #[test]
+    fn payload_within_cap_passes_unchanged_and_is_measured() {
+        let (sink, rec, stats) = metered();
+        let payload = json!({ "greenhouse_id": 1, "air_temp_c": 23.4, "display": { "color": "#00ff00" } });
+        let bytes = payload.to_string().len();
+        sink.emit_json("node_avg", payload.clone());
+
+        assert_eq!(rec.0.lock().unwrap().as_slice(), &[("node_avg".to_string(), payload)]);
+        let s = &stats.read().unwrap()["node_avg"];
+        assert_eq!((s.count, s.last_bytes, s.truncated, s.over_cap), (1, bytes, 0, 0));
+    }
+
+    #[test]
+    fn over_cap_strips_optional_fields_in_order_and_flags_truncated() {
+        let (json, truncated) = enforce_cap(
+            json!({ "id": 1, "display": "d".repeat(40), "sparkline": "s".repeat(40), "vpd_kpi_today": 1 }),
+            100,
+        );
+        assert!(truncated);
+        // sparkline goes first; that alone brings it under the cap, so display and the KPI stay
+        let v: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(v, json!({ "id": 1, "display": "d".repeat(40), "vpd_kpi_today": 1, "truncated": true }));
+        assert!(json.len() <= 100);
+    }
+
+    #[test]
+    fn required_fields_are_never_stripped() {
+        let (sink, rec, stats) = metered();
+        sink.emit_json("node_avg", json!({ "id": 1, "big": "x".repeat(3000) }));
+
+        let (_, got) = &rec.0.lock().unwrap()[0];
+        assert_eq!(got["big"].as_str().map(str::len), Some(3000));
+        assert!(got.get("truncated").is_none());
+        let s = &stats.read().unwrap()["node_avg"];
+        assert_eq!((s.truncated, s.over_cap), (0, 1));
+    }
+}
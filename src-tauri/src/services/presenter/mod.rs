@@ -0,0 +1,8 @@
+//! Presentation layer between the pipeline and the webview.
+//! - Everything that shapes what the frontend receives lives here, not in `main.rs`.
+
+pub mod config;
+pub mod emitter;
+pub mod kiosk;
+pub mod metered;
+pub mod overview;
@@ -0,0 +1,102 @@
+//! Cross-greenhouse comparison ("site overview").
+//! - One row per greenhouse: current VPD / air temp / node coverage from the latest GhAvg,
+//!   24h air-temp extremes and DLI from the closed hourly aggregates.
+//! - Every row carries its data age; a greenhouse whose last GhAvg is older than
+//!   `STALE_AFTER_WINDOWS` of its own window is flagged `stale` so old numbers are never shown as current.
+//! - Same payload for `get_site_overview` and the per-minute `site_overview` event.
+//! - `origin_conflicts` lists greenhouse ids arriving from several sites (see origin.rs).
+
+use serde::Serialize;
+use std::{collections::BTreeSet, time::{Duration, SystemTime, UNIX_EPOCH}};
+use tokio::time::interval;
+use tracing::warn;
+
+use crate::services::mqtt::greenhouse_sensor::{
+    greenhouse_aggregator::LatestGhShared,
+    hourly_aggregator::{GhHourly, HourStat, HourlyShared},
+    origin::{current_conflicts, OriginConflict, OriginShared},
+};
+use super::emitter::EventSink;
+
+const EVERY: Duration = Duration::from_secs(60);
+pub(super) const STALE_AFTER_WINDOWS: i64 = 3; // missed GhAvg windows
+const DAY_HOURS: usize = 24;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GhOverview {
+    pub greenhouse_id: u16,
+    pub last_ts_ms: Option<i64>,
+    pub age_s: Option<i64>,
+    pub stale: bool,
+    pub vpd_kpa: Option<f32>,
+    pub air_temp_c: Option<f32>,
+    pub nodes: usize,
+    pub air_temp_min_24h: Option<f32>,
+    pub air_temp_max_24h: Option<f32>,
+    pub dli_24h_mol_m2: Option<f32>, // from hourly PAR means (umol/m2/s), closed hours only
+    pub hours_24h: usize,            // closed hours behind the 24h figures
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SiteOverview {
+    pub ts_ms: i64,
+    pub greenhouses: Vec<GhOverview>,
+    pub origin_conflicts: Vec<OriginConflict>, // frames of these ids are held until mapped
+}
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+fn hourly_field<'a>(hours: &'a [GhHourly], key: &'a str) -> impl Iterator<Item = &'a HourStat> + 'a {
+    hours.iter().filter_map(move |h| h.fields.get(key))
+}
+
+/// Build the overview from shared state (cheap, no DB access).
+pub fn site_overview(latest: &LatestGhShared, hourly: &HourlyShared, origins: &OriginShared) -> SiteOverview {
+    let now = now_ms();
+    let latest = latest.read().map(|m| m.clone()).unwrap_or_default();
+    let (ids, day): (BTreeSet<u16>, Vec<(u16, Vec<GhHourly>)>) = match hourly.read() {
+        Ok(store) => {
+            let ids: BTreeSet<u16> = latest.keys().copied().chain(store.greenhouse_ids()).collect();
+            let day = ids.iter().map(|&id| (id, store.recent(id, DAY_HOURS))).collect();
+            (ids, day)
+        }
+        Err(_) => (latest.keys().copied().collect(), Vec::new()),
+    };
+
+    let greenhouses = ids.into_iter().map(|gh_id| {
+        let ga = latest.get(&gh_id);
+        let hours: &[GhHourly] = day.iter().find(|(id, _)| *id == gh_id).map(|(_, h)| h.as_slice()).unwrap_or(&[]);
+        let age_ms = ga.map(|g| now - g.ts_ms);
+        let par_means: Vec<f32> = hourly_field(hours, "par_value").filter_map(|s| s.mean).collect();
+        GhOverview {
+            greenhouse_id: gh_id,
+            last_ts_ms: ga.map(|g| g.ts_ms),
+            age_s: age_ms.map(|a| a / 1000),
+            stale: !matches!((age_ms, ga), (Some(a), Some(g)) if a <= STALE_AFTER_WINDOWS * g.window_sec as i64 * 1000),
+            vpd_kpa: ga.and_then(|g| g.vpd_kpa),
+            air_temp_c: ga.and_then(|g| g.air_temp_c),
+            nodes: ga.map_or(0, |g| g.nodes),
+            air_temp_min_24h: hourly_field(hours, "air_temp_c").filter_map(|s| s.min).reduce(f32::min),
+            air_temp_max_24h: hourly_field(hours, "air_temp_c").filter_map(|s| s.max).reduce(f32::max),
+            dli_24h_mol_m2: (!par_means.is_empty())
+                .then(|| par_means.iter().map(|p| p.max(0.0) * 3600.0 / 1_000_000.0).sum()),
+            hours_24h: hours.len(),
+        }
+    }).collect();
+
+    SiteOverview { ts_ms: now, greenhouses, origin_conflicts: current_conflicts(origins) }
+}
+
+/// Emit `site_overview` every minute so the comparison screen stays live without polling.
+pub async fn run_site_overview<S: EventSink>(sink: S, latest: LatestGhShared, hourly: HourlyShared, origins: OriginShared) {
+    let mut tick = interval(EVERY);
+    loop {
+        tick.tick().await;
+        match serde_json::to_value(site_overview(&latest, &hourly, &origins)) {
+            Ok(v) => sink.emit_json("site_overview", v),
+            Err(e) => warn!(target: "UI", "serialize site_overview failed: {e}"),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+#[derive(Clone, Copy)]
+pub struct ReportConfig {
+    /// Local time the night shift hands over; the scheduled report covers the `shift_hours` before it.
+    pub shift_change_hour: u32,
+    pub shift_change_minute: u32,
+    pub shift_hours: i64,
+    /// Default VPD target band (kPa); per-greenhouse overrides live in `vpd_kpi_config`.
+    pub vpd_min_kpa: f32,
+    pub vpd_max_kpa: f32,
+}
+
+pub const fn report_config() -> ReportConfig {
+    ReportConfig {
+        shift_change_hour: 6,
+        shift_change_minute: 0,
+        shift_hours: 12,
+        vpd_min_kpa: 0.4,
+        vpd_max_kpa: 1.2,
+    }
+}
+
+/// VPD KPI ("% of daylight within the target band") per greenhouse.
+#[derive(Clone, Copy)]
+pub struct VpdKpiConfig {
+    pub vpd_min_kpa: f32,
+    pub vpd_max_kpa: f32,
+    /// A window counts as daylight when the greenhouse PAR mean is at or above this (umol/m2/s).
+    pub daylight_par_min: f32,
+}
+
+/// Greenhouses whose crop needs a different band or light threshold than the default.
+const VPD_KPI_OVERRIDES: &[(u16, VpdKpiConfig)] = &[];
+
+pub fn vpd_kpi_config(gh_id: u16) -> VpdKpiConfig {
+    let r = report_config();
+    VPD_KPI_OVERRIDES.iter().find(|(id, _)| *id == gh_id).map(|&(_, c)| c).unwrap_or(VpdKpiConfig {
+        vpd_min_kpa: r.vpd_min_kpa,
+        vpd_max_kpa: r.vpd_max_kpa,
+        daylight_par_min: 50.0,
+    })
+}
+
+/// One scheduled CSV export: yesterday's data of one greenhouse into `dest_dir`.
+#[derive(Clone, Copy)]
+pub struct ExportJob {
+    pub greenhouse_id: u16,
+    /// Usually a mounted network share.
+    pub dest_dir: &'static str,
+    /// Placeholders: {gh}, {date} (YYYY-MM-DD), {yyyy}, {mm}, {dd}.
+    pub file_template: &'static str,
+    /// Sensor keys / agg names to include; empty means all.
+    pub sensors: &'static [&'static str],
+    pub aggs: &'static [&'static str],
+    /// Add source / revision / revised_ms columns, so the consultant can spot rebuilt rows.
+    pub provenance: bool,
+    /// Export the greenhouse's node rows on their window grid (compacted windows filled) instead of
+    /// its averages.
+    pub nodes: bool,
+    /// Exported files matching the template kept in `dest_dir` (oldest removed first); 0 keeps all.
+    pub keep_files: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct ExportConfig {
+    /// Local time of the daily run (after midnight, so "yesterday" is complete).
+    pub hour: u32,
+    pub minute: u32,
+    pub max_attempts: u32,
+    pub retry_base_secs: u64,
+    pub jobs: &'static [ExportJob],
+}
+
+pub const fn export_config() -> ExportConfig {
+    ExportConfig {
+        hour: 5,
+        minute: 30,
+        max_attempts: 6,     // 1, 2, 4, 8, 16 min between attempts
+        retry_base_secs: 60,
+        jobs: &[],
+    }
+}
+
+/// Slab drainage check after irrigation shots (see drainage.rs).
+#[derive(Clone, Copy)]
+pub struct DrainageConfig {
+    /// Rise between two consecutive node windows that counts as an irrigation shot (g).
+    pub shot_min_rise_g: f64,
+    /// Windows further apart than this are a gap: a rise across it is not taken as a shot.
+    pub max_gap_secs: i64,
+    /// Decay is measured over this long after the last shot of an event.
+    pub follow_mins: i64,
+    /// Share of the follow window's 60s windows that must be present to judge the event.
+    pub min_coverage: f64,
+    /// Baseline: median drained fraction of the node's normal events over the previous days.
+    pub baseline_days: i64,
+    pub min_baseline_events: usize,
+    /// Drained fraction below this share of the baseline: blocked drain.
+    pub blocked_below: f64,
+    /// Drained fraction above this multiple of the baseline: leak.
+    pub leak_above: f64,
+    /// Minute past each hour the analysis runs.
+    pub run_minute: u32,
+}
+
+pub const fn drainage_config() -> DrainageConfig {
+    DrainageConfig {
+        shot_min_rise_g: 150.0,
+        max_gap_secs: 300,
+        follow_mins: 60,
+        min_coverage: 0.6,
+        baseline_days: 7,
+        min_baseline_events: 5,
+        blocked_below: 0.4,
+        leak_above: 2.0,
+        run_minute: 10,
+    }
+}
@@ -0,0 +1,286 @@
+//! Slab drainage check: flag slabs that do not drain (blocked) or drain far too fast (leak) after irrigation.
+//! - Runs every hour on the stored 60s node rows of the last few hours. An irrigation shot is a weight rise
+//!   of at least `shot_min_rise_g` between two consecutive windows (not across a gap).
+//! - Decay is measured over `follow_mins` after the last shot of an event: a shot inside that window
+//!   (overlapping shots) joins the event and restarts the window. Events still inside their window wait
+//!   for the next run.
+//! - drained fraction = (peak - end) / (peak - weight before the first shot); end is the median of the
+//!   window's last 10 minutes, so a few missing windows do not matter. Too few windows: `insufficient_data`.
+//! - Each node is compared with itself: the median fraction of its normal events over the previous
+//!   `baseline_days`. Below `blocked_below` × baseline is `blocked`, above `leak_above` × baseline is
+//!   `leak`; both log a warning and emit `drainage_alert`. Every event lands in `drainage_event` for
+//!   `get_drainage_analysis`.
+
+use chrono::{Local, NaiveDate, TimeZone};
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use super::config::{drainage_config, DrainageConfig};
+use crate::services::mqtt::greenhouse_sensor::hourly_aggregator::local_day_start;
+use crate::services::presenter::emitter::EventSink;
+use crate::services::scheduler::{Schedule, Scheduler};
+use crate::services::storage::drainage::{insert_event, load_baseline, load_events, load_weight_traces, DrainageEvent};
+use crate::services::storage::sqlite::open_db;
+
+const HOUR_MS: i64 = 3_600_000;
+const DAY_MS: i64 = 24 * HOUR_MS;
+/// Hours of shots looked at per run; the app can be down this long without missing an event.
+const LOOKBACK_HOURS: i64 = 6;
+const END_SPAN_MS: i64 = 10 * 60_000;
+
+/// One irrigation event found in a weight trace, before it is judged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShotDecay {
+    pub shot_ms: i64,
+    pub shots: u32,
+    pub peak_ms: i64,
+    pub pre_g: f64,
+    pub peak_g: f64,
+    pub end_g: f64,
+    pub drained_frac: f64,
+    pub coverage: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Normal,
+    Blocked,
+    Leak,
+    NoBaseline,
+    InsufficientData,
+}
+
+impl Verdict {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Verdict::Normal => "normal",
+            Verdict::Blocked => "blocked",
+            Verdict::Leak => "leak",
+            Verdict::NoBaseline => "no_baseline",
+            Verdict::InsufficientData => "insufficient_data",
+        }
+    }
+}
+
+fn median(v: &mut [f64]) -> Option<f64> {
+    if v.is_empty() { return None; }
+    v.sort_by(f64::total_cmp);
+    let m = v.len() / 2;
+    Some(if v.len().is_multiple_of(2) { (v[m - 1] + v[m]) / 2.0 } else { v[m] })
+}
+
+/// Completed irrigation events in `trace` (ts_ms, g; oldest first).
+pub fn detect(trace: &[(i64, f64)], cfg: &DrainageConfig) -> Vec<ShotDecay> {
+    let follow = cfg.follow_mins * 60_000;
+    let rise = |k: usize| trace[k].0 - trace[k - 1].0 <= cfg.max_gap_secs * 1000 && trace[k].1 - trace[k - 1].1 >= cfg.shot_min_rise_g;
+    let mut out = Vec::new();
+    let mut i = 1;
+    while i < trace.len() {
+        if !rise(i) { i += 1; continue; }
+        let (shot_ms, pre_g) = (trace[i].0, trace[i - 1].1);
+        let (mut shots, mut peak, mut j) = (1, i, i + 1);
+        while j < trace.len() && trace[j].0 <= trace[peak].0 + follow {
+            let adjacent = j == peak + 1;
+            if rise(j) && !adjacent {
+                shots += 1; // another shot while the slab was draining: measure after this one
+                peak = j;
+            } else if adjacent && trace[j].1 > trace[peak].1 {
+                peak = j; // still filling
+            }
+            j += 1;
+        }
+        let (peak_ms, peak_g) = trace[peak];
+        let end_ms = peak_ms + follow;
+        if trace.last().is_none_or(|l| l.0 < end_ms) { break; } // window not over yet
+        let window: Vec<(i64, f64)> = trace[peak + 1..].iter().take_while(|s| s.0 <= end_ms).copied().collect();
+        let mut tail: Vec<f64> = window.iter().filter(|s| s.0 > end_ms - END_SPAN_MS).map(|s| s.1).collect();
+        let end_g = median(&mut tail).unwrap_or(peak_g);
+        out.push(ShotDecay {
+            shot_ms, shots, peak_ms, pre_g, peak_g, end_g,
+            drained_frac: (peak_g - end_g) / (peak_g - pre_g).max(1.0),
+            coverage: (window.len() as f64 / cfg.follow_mins.max(1) as f64).min(1.0),
+        });
+        i = j;
+    }
+    out
+}
+
+/// Judge one event against the node's own earlier drained fractions.
+pub fn judge(d: &ShotDecay, baseline: &[f64], cfg: &DrainageConfig) -> (Verdict, Option<f64>) {
+    if d.coverage < cfg.min_coverage { return (Verdict::InsufficientData, None); }
+    let base = if baseline.len() >= cfg.min_baseline_events { median(&mut baseline.to_vec()) } else { None };
+    let Some(base) = base.filter(|b| *b > 0.01) else { return (Verdict::NoBaseline, base) };
+    let ratio = d.drained_frac / base;
+    let verdict = if ratio < cfg.blocked_below {
+        Verdict::Blocked
+    } else if ratio > cfg.leak_above {
+        Verdict::Leak
+    } else {
+        Verdict::Normal
+    };
+    (verdict, Some(base))
+}
+
+/// Blocking: analyse the hours before `now_ms`, store new events and return those that need an alert.
+fn analyze(db_path: &str, now_ms: i64) -> Result<Vec<DrainageEvent>, String> {
+    let cfg = drainage_config();
+    let db = |e: rusqlite::Error| e.to_string();
+    let from_ms = now_ms - LOOKBACK_HOURS * HOUR_MS - cfg.follow_mins * 60_000;
+    let conn = open_db(db_path).map_err(db)?;
+    let traces = load_weight_traces(&conn, from_ms, now_ms).map_err(db)?;
+    let tx = conn.unchecked_transaction().map_err(db)?;
+    let mut alerts = Vec::new();
+    for ((gh_id, node_id), trace) in traces {
+        for d in detect(&trace, &cfg) {
+            if d.shot_ms < from_ms + HOUR_MS { continue; } // may have started before the trace
+            let baseline = load_baseline(&tx, gh_id, node_id, d.shot_ms - cfg.baseline_days * DAY_MS, d.shot_ms).map_err(db)?;
+            let (verdict, baseline_frac) = judge(&d, &baseline, &cfg);
+            let ev = DrainageEvent {
+                greenhouse_id: gh_id, node_id, shot_ms: d.shot_ms, shots: d.shots, peak_ms: d.peak_ms,
+                pre_g: d.pre_g, peak_g: d.peak_g, end_g: d.end_g, drained_frac: d.drained_frac, coverage: d.coverage,
+                baseline_frac, baseline_events: baseline.len(), verdict: verdict.as_str().into(), analyzed_ms: now_ms,
+            };
+            if insert_event(&tx, &ev).map_err(db)? && matches!(verdict, Verdict::Blocked | Verdict::Leak) {
+                alerts.push(ev);
+            }
+        }
+    }
+    tx.commit().map_err(db)?;
+    Ok(alerts)
+}
+
+/// Public task: hourly drainage analysis; blocked / leaking slabs -> `drainage_alert`.
+pub async fn run_drainage_analysis<S: EventSink>(sink: S, db_path: &'static str, dry_run: bool) {
+    if dry_run {
+        info!(target: "DRAINAGE", "DRY RUN: drainage analysis off (no stored node rows)");
+        return;
+    }
+    let mut hourly = Scheduler::new(Schedule::Hourly { minute: drainage_config().run_minute }, Local);
+    loop {
+        let due = hourly.wait().await.timestamp_millis();
+        match tokio::task::spawn_blocking(move || analyze(db_path, due)).await {
+            Ok(Ok(alerts)) => for ev in alerts {
+                warn!(target: "DRAINAGE", "GH:{} Node:{} {} after irrigation at {}: drained {:.0}% vs usual {:.0}%",
+                    ev.greenhouse_id, ev.node_id, ev.verdict, ev.shot_ms,
+                    ev.drained_frac * 100.0, ev.baseline_frac.unwrap_or(0.0) * 100.0);
+                match serde_json::to_value(&ev) {
+                    Ok(v) => sink.emit_json("drainage_alert", serde_json::json!({ "severity": "warning", "event": v })),
+                    Err(e) => error!(target: "DRAINAGE", "serialize alert failed: {e}"),
+                }
+            },
+            Ok(Err(e)) => warn!(target: "DRAINAGE", "analysis failed: {e}"),
+            Err(e) => error!(target: "DRAINAGE", "join error: {e}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DrainageDay {
+    pub node_id: u16,
+    pub date: String,
+    pub day_start_ms: i64,
+    pub blocked_below: f64,
+    pub leak_above: f64,
+    pub events: Vec<DrainageEvent>,
+}
+
+/// Blocking: stored events of `node_id` on local `date` (YYYY-MM-DD).
+pub fn drainage_analysis(db_path: &str, node_id: u16, date: &str) -> Result<DrainageDay, String> {
+    let day = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|e| format!("date '{date}': {e}"))?;
+    let noon = day.and_hms_opt(12, 0, 0).and_then(|t| Local.from_local_datetime(&t).earliest())
+        .ok_or_else(|| format!("date '{date}' has no local noon"))?.timestamp_millis();
+    let (from_ms, to_ms) = (local_day_start(noon), local_day_start(noon + DAY_MS));
+    let cfg = drainage_config();
+    Ok(DrainageDay {
+        node_id, date: date.to_string(), day_start_ms: from_ms,
+        blocked_below: cfg.blocked_below, leak_above: cfg.leak_above,
+        events: load_events(db_path, node_id, from_ms, to_ms).map_err(|e| e.to_string())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T0: i64 = 1_760_000_040_000;
+    const MIN: i64 = 60_000;
+
+    /// One 60s window a minute: a 10 kg slab, a 600 g shot at minute 30 of which `drain` runs off
+    /// over the next 45 minutes (above 1: the slab ends lighter than before the shot).
+    fn trace(drain: f64) -> Vec<(i64, f64)> {
+        (0..=120).map(|m| {
+            let g = if m < 30 { 10_000.0 } else { 10_600.0 - 600.0 * drain * ((m - 30) as f64 / 45.0).min(1.0) };
+            (T0 + m * MIN, g)
+        }).collect()
+    }
+
+    /// Verdict on the one event of `t` against five healthy days.
+    fn verdict(t: &[(i64, f64)]) -> Verdict {
+        let cfg = drainage_config();
+        let baseline: Vec<f64> = [0.46, 0.5, 0.52, 0.48, 0.55].iter().map(|&d| detect(&trace(d), &cfg)[0].drained_frac).collect();
+        let events = detect(t, &cfg);
+        assert_eq!(events.len(), 1);
+        judge(&events[0], &baseline, &cfg).0
+    }
+
+    #[test]
+    fn healthy_slab_drains_its_usual_share() {
+        let d = detect(&trace(0.5), &drainage_config())[0];
+        assert_eq!((d.shot_ms, d.peak_ms, d.shots), (T0 + 30 * MIN, T0 + 30 * MIN, 1));
+        assert_eq!((d.pre_g, d.peak_g, d.end_g), (10_000.0, 10_600.0, 10_300.0));
+        assert!((d.drained_frac - 0.5).abs() < 1e-9 && d.coverage == 1.0, "{d:?}");
+        assert_eq!(verdict(&trace(0.5)), Verdict::Normal);
+    }
+
+    #[test]
+    fn flat_weight_after_the_shot_is_a_blocked_drain() {
+        assert_eq!(verdict(&trace(0.1)), Verdict::Blocked);
+    }
+
+    #[test]
+    fn weight_crashing_below_the_pre_shot_level_is_a_leak() {
+        assert_eq!(verdict(&trace(1.4)), Verdict::Leak);
+    }
+
+    #[test]
+    fn judging_needs_a_baseline_of_the_node_itself() {
+        let cfg = drainage_config();
+        let d = detect(&trace(0.1), &cfg)[0];
+        assert_eq!(judge(&d, &[0.5; 4], &cfg), (Verdict::NoBaseline, None));
+        assert_eq!(judge(&d, &[0.0; 5], &cfg), (Verdict::NoBaseline, Some(0.0)));
+    }
+
+    #[test]
+    fn overlapping_shots_are_one_event_measured_after_the_last() {
+        // a second 400 g shot 20 minutes into the first one's decay
+        let t: Vec<_> = trace(0.5).into_iter().map(|(ts, g)| (ts, if ts >= T0 + 50 * MIN { g + 400.0 } else { g })).collect();
+        let events = detect(&t, &drainage_config());
+        assert_eq!(events.len(), 1);
+        let d = events[0];
+        assert_eq!((d.shot_ms, d.peak_ms, d.shots, d.pre_g), (T0 + 30 * MIN, T0 + 50 * MIN, 2, 10_000.0));
+        assert_eq!(d.end_g, 10_700.0);
+    }
+
+    #[test]
+    fn missing_windows_thin_the_event_until_it_cannot_be_judged() {
+        let keep = |every: i64, of: i64| -> Vec<(i64, f64)> {
+            trace(0.5).into_iter().filter(|(ts, _)| { let m = (ts - T0) / MIN; m <= 30 || m % of < every }).collect()
+        };
+        // a third of the windows lost: still judged, same result
+        let d = detect(&keep(2, 3), &drainage_config())[0];
+        assert!((d.drained_frac - 0.5).abs() < 1e-9 && (0.6..1.0).contains(&d.coverage), "{d:?}");
+        assert_eq!(verdict(&keep(2, 3)), Verdict::Normal);
+        // three of four lost
+        assert_eq!(verdict(&keep(1, 4)), Verdict::InsufficientData);
+    }
+
+    #[test]
+    fn rise_across_a_gap_or_an_unfinished_window_is_no_event() {
+        let cfg = drainage_config();
+        let gap: Vec<_> = trace(0.5).into_iter().filter(|(ts, _)| !(T0 + 25 * MIN..T0 + 30 * MIN).contains(ts)).collect();
+        assert!(detect(&gap, &cfg).is_empty());
+        let running = &trace(0.5)[..80];
+        assert!(detect(running, &cfg).is_empty());
+    }
+}
@@ -0,0 +1,234 @@
+//! Scheduled per-greenhouse CSV export (e.g. yesterday's data onto the consultant's share).
+//! - Runs daily at `export_config()` local time on the shared scheduler; each job exports the
+//!   previous local day through the storage CSV engine.
+//! - A failing job (share offline) retries with doubling delays without holding up other jobs;
+//!   the final outcome, success or not, lands in `export_history` (`get_export_history`).
+//! - After a successful export, older files matching the job's template beyond `keep_files` are removed.
+//!   A file matches only when its whole name does: the template's literal text with real dates in the
+//!   placeholders, so anything else on the share is left alone.
+
+use chrono::{Local, TimeZone};
+use std::{fs, path::{Path, PathBuf}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use super::config::{export_config, ExportJob};
+use crate::services::mqtt::greenhouse_sensor::hourly_aggregator::local_day_start;
+use crate::services::scheduler::{Schedule, Scheduler};
+use crate::services::storage::export::{export_greenhouse_csv, record_export, CsvOptions, ExportRecord};
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+/// File name for the day starting at `day_start_ms` (local).
+fn file_name(job: &ExportJob, day_start_ms: i64) -> String {
+    let day = Local.timestamp_millis_opt(day_start_ms).single().unwrap_or_else(Local::now);
+    job.file_template
+        .replace("{gh}", &job.greenhouse_id.to_string())
+        .replace("{date}", &day.format("%Y-%m-%d").to_string())
+        .replace("{yyyy}", &day.format("%Y").to_string())
+        .replace("{mm}", &day.format("%m").to_string())
+        .replace("{dd}", &day.format("%d").to_string())
+}
+
+/// One piece of a file template: literal text, or a date placeholder of fixed width.
+#[derive(Debug, PartialEq)]
+enum Part {
+    Lit(String),
+    Date,
+    Year,
+    Month,
+    Day,
+}
+
+/// `job.file_template` split into literals ({gh} and unknown placeholders included) and date fields.
+fn template_parts(job: &ExportJob) -> Vec<Part> {
+    let mut parts = Vec::new();
+    let mut lit = String::new();
+    let mut rest = job.file_template;
+    while !rest.is_empty() {
+        let field = [("{date}", Some(Part::Date)), ("{yyyy}", Some(Part::Year)), ("{mm}", Some(Part::Month)),
+                     ("{dd}", Some(Part::Day)), ("{gh}", None)]
+            .into_iter().find(|(p, _)| rest.starts_with(p));
+        match field {
+            Some((p, Some(part))) => {
+                if !lit.is_empty() { parts.push(Part::Lit(std::mem::take(&mut lit))); }
+                parts.push(part);
+                rest = &rest[p.len()..];
+            }
+            Some((p, None)) => {
+                lit.push_str(&job.greenhouse_id.to_string());
+                rest = &rest[p.len()..];
+            }
+            None => {
+                let c = rest.chars().next().unwrap_or_default();
+                lit.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if !lit.is_empty() { parts.push(Part::Lit(lit)); }
+    parts
+}
+
+/// Whether `name` is exactly a file `file_name` could have written: every literal in place and every
+/// date field a real date of its width.
+fn matches_template(parts: &[Part], name: &str) -> bool {
+    let digits = |s: &str, range: std::ops::RangeInclusive<u32>| {
+        s.bytes().all(|b| b.is_ascii_digit()) && s.parse().is_ok_and(|v| range.contains(&v))
+    };
+    let mut rest = name;
+    for part in parts {
+        let width = match part {
+            Part::Lit(l) => l.len(),
+            Part::Date => 10,
+            Part::Year => 4,
+            Part::Month | Part::Day => 2,
+        };
+        let Some(field) = rest.get(..width) else { return false };
+        let ok = match part {
+            Part::Lit(l) => field == l,
+            Part::Date => field.bytes().all(|b| b.is_ascii_digit() || b == b'-')
+                && chrono::NaiveDate::parse_from_str(field, "%Y-%m-%d").is_ok(),
+            Part::Year => digits(field, 0..=9999),
+            Part::Month => digits(field, 1..=12),
+            Part::Day => digits(field, 1..=31),
+        };
+        if !ok { return false; }
+        rest = &rest[width..];
+    }
+    rest.is_empty()
+}
+
+/// Keep the newest `keep` files in `dir` whose whole name matches this job's template; other files are
+/// never touched.
+fn apply_retention(job: &ExportJob, dir: &Path, keep: usize) -> std::io::Result<usize> {
+    let parts = template_parts(job);
+    let mut files: Vec<(SystemTime, PathBuf)> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_str().is_some_and(|n| matches_template(&parts, n)))
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect();
+    files.sort_by_key(|f| std::cmp::Reverse(f.0));
+    let mut removed = 0;
+    for (_, path) in files.into_iter().skip(keep) {
+        if fs::remove_file(&path).is_ok() { removed += 1; }
+    }
+    Ok(removed)
+}
+
+/// Export one job for one day, retrying with backoff; records the final outcome.
+async fn run_job(job: ExportJob, db_path: &'static str, from_ms: i64, to_ms: i64, dry_run: bool) {
+    let cfg = export_config();
+    let out = Path::new(job.dest_dir).join(file_name(&job, from_ms));
+    let mut attempts = 0;
+    let result = loop {
+        attempts += 1;
+        let path = out.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            let opts = CsvOptions { sensors: job.sensors, aggs: job.aggs, provenance: job.provenance, nodes: job.nodes, ..Default::default() };
+            export_greenhouse_csv(db_path, job.greenhouse_id, from_ms, to_ms, opts, &path)
+        }).await.unwrap_or_else(|e| Err(format!("join error: {e}")));
+        match res {
+            Ok(rows) => break Ok(rows),
+            Err(e) if attempts < cfg.max_attempts => {
+                let wait = cfg.retry_base_secs << (attempts - 1).min(10);
+                warn!(target: "EXPORT", "GH:{} attempt {attempts} failed ({e}); retrying in {wait}s", job.greenhouse_id);
+                sleep(Duration::from_secs(wait)).await;
+            }
+            Err(e) => break Err(e),
+        }
+    };
+
+    let (ok, rows, detail) = match result {
+        Ok(rows) => {
+            info!(target: "EXPORT", "GH:{} wrote {rows} rows to {}", job.greenhouse_id, out.display());
+            let pruned = if job.keep_files > 0 { apply_retention(&job, Path::new(job.dest_dir), job.keep_files).unwrap_or(0) } else { 0 };
+            (true, rows as i64, if pruned > 0 { format!("removed {pruned} old file(s)") } else { "ok".into() })
+        }
+        Err(e) => {
+            warn!(target: "EXPORT", "GH:{} giving up after {attempts} attempts: {e}", job.greenhouse_id);
+            (false, 0, e)
+        }
+    };
+    let rec = ExportRecord {
+        ts_ms: now_ms(), greenhouse_id: job.greenhouse_id, from_ms, to_ms,
+        path: out.display().to_string(), rows, ok, attempts, detail,
+    };
+    if dry_run {
+        info!(target: "EXPORT", "DRY RUN: export outcome not recorded");
+        return;
+    }
+    match tokio::task::spawn_blocking(move || record_export(db_path, &rec)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!(target: "EXPORT", "history insert failed: {e}"),
+        Err(e) => error!(target: "EXPORT", "history join error: {e}"),
+    }
+}
+
+/// Public task: no-op without configured jobs. Files are still written in dry-run; history is not.
+pub async fn run_scheduled_exports(db_path: &'static str, dry_run: bool) {
+    let cfg = export_config();
+    if cfg.jobs.is_empty() { return; }
+    let mut daily = Scheduler::new(Schedule::Daily { hour: cfg.hour, minute: cfg.minute }, Local);
+    loop {
+        let due = daily.wait().await.timestamp_millis();
+        let to_ms = local_day_start(due);
+        let from_ms = local_day_start(to_ms - 1);
+        for job in cfg.jobs {
+            tokio::spawn(run_job(*job, db_path, from_ms, to_ms, dry_run));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn job(file_template: &'static str, keep_files: usize) -> ExportJob {
+        ExportJob {
+            greenhouse_id: 1, dest_dir: "", file_template,
+            sensors: &[], aggs: &[], provenance: false, nodes: false, keep_files,
+        }
+    }
+
+    #[test]
+    fn template_matches_whole_names_only() {
+        let parts = template_parts(&job("gh{gh}_{date}.csv", 0));
+        assert_eq!(parts, vec![Part::Lit("gh1_".into()), Part::Date, Part::Lit(".csv".into())]);
+        assert!(matches_template(&parts, "gh1_2026-10-15.csv"));
+        for name in ["gh1_2026-10-15.csv.tmp", "gh1_2026-10-15.csv.bak", "gh12_2026-10-15.csv", "gh1_2026-13-01.csv",
+                     "gh1_2026-02-30.csv", "gh1_notes.csv", "gh1_2026-1-15.csv", "gh1_+2026-10-1.csv", "gh1_.csv"] {
+            assert!(!matches_template(&parts, name), "{name}");
+        }
+        let split = template_parts(&job("{yyyy}/{mm}{dd}-gh{gh}.csv", 0));
+        assert!(matches_template(&split, "2026/1015-gh1.csv"));
+        assert!(!matches_template(&split, "2026/1315-gh1.csv"));
+        assert!(!matches_template(&split, "2026/10ab-gh1.csv"));
+    }
+
+    #[test]
+    fn retention_keeps_newest_and_leaves_foreign_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let touch = |name: &str, age_days: u64| {
+            let f = File::create(dir.path().join(name)).unwrap();
+            f.set_modified(SystemTime::now() - Duration::from_secs(age_days * 86_400)).unwrap();
+        };
+        for (day, age) in [(11, 4), (12, 3), (13, 2), (14, 1)] { touch(&format!("gh1_2026-10-{day}.csv"), age); }
+        let foreign = ["gh1_2026-10-10.csv.tmp", "gh1_notes.csv", "gh2_2026-10-01.csv", "readme.txt", "gh1_2026-10-09.csv.bak"];
+        for name in foreign { touch(name, 30); }
+        fs::create_dir(dir.path().join("gh1_2026-10-01.csv")).unwrap();
+
+        assert_eq!(apply_retention(&job("gh{gh}_{date}.csv", 2), dir.path(), 2).unwrap(), 2);
+        let mut left: Vec<String> = fs::read_dir(dir.path()).unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        left.sort();
+        let mut expected: Vec<String> = foreign.iter().map(|s| s.to_string())
+            .chain(["gh1_2026-10-13.csv", "gh1_2026-10-14.csv", "gh1_2026-10-01.csv"].map(String::from)).collect();
+        expected.sort();
+        assert_eq!(left, expected);
+    }
+}
@@ -0,0 +1,7 @@
+//! Generated operator reports (assembled from stored aggregates, never from live state).
+
+pub mod config;
+pub mod shift;
+pub mod vpd_kpi;
+pub mod export;
+pub mod drainage;
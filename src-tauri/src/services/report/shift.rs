@@ -0,0 +1,256 @@
+//! Night-shift report per greenhouse.
+//! - Lowest greenhouse air temp and when it occurred, time outside the VPD target band,
+//!   per-node availability, clock adjustments and node maintenance windows inside the shift.
+//! - Built from stored rolling rows of any window (60s indoor by default, longer outdoor); availability
+//!   counts windows with at least one stored value, so compact mode can under-report nodes that sat
+//!   inside every deadband.
+//! - `run_shift_reports` generates one report per greenhouse at each local shift change and
+//!   emits `shift_report_ready`.
+
+use chrono::Local;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::fmt::Write as _;
+use tracing::{error, info, warn};
+
+use super::config::{report_config, vpd_kpi_config};
+use crate::services::presenter::emitter::EventSink;
+use crate::services::scheduler::{Schedule, Scheduler};
+use crate::services::storage::maint_window::{load_windows, MaintenanceWindow};
+use crate::services::storage::scaled::GH_VALUE_SQL;
+use crate::services::storage::sqlite::open_db;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeAvailability {
+    pub node_id: u16,
+    pub label: String,
+    pub windows: i64,
+    pub availability_pct: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShiftReport {
+    pub greenhouse_id: u16,
+    pub from_ms: i64,
+    pub to_ms: i64,
+    pub min_air_temp_c: Option<f32>,
+    pub min_air_temp_at_ms: Option<i64>,
+    pub vpd_band_kpa: (f32, f32),
+    pub vpd_hours_outside: f32,
+    pub vpd_hours_covered: f32,
+    pub nodes: Vec<NodeAvailability>,
+    pub clock_adjustments: i64,
+    pub maintenance: Vec<MaintenanceWindow>, // overlapping the shift; those nodes are not in the greenhouse figures
+}
+
+fn min_air_temp(conn: &Connection, gh_id: u16, from_ms: i64, to_ms: i64) -> rusqlite::Result<Option<(f32, i64)>> {
+    conn.query_row(
+        &format!("SELECT {GH_VALUE_SQL} AS v, ga.ts_ms FROM greenhouse_average ga
+         JOIN sensor_type st ON st.id = ga.sensor_type_id
+         WHERE ga.greenhouse_id = ?1 AND st.key = 'air_temp_c' AND ga.agg LIKE 'rolling_%'
+           AND ga.ts_ms >= ?2 AND ga.ts_ms < ?3 AND ga.value IS NOT NULL
+         ORDER BY v ASC, ga.ts_ms ASC LIMIT 1"),
+        params![gh_id, from_ms, to_ms],
+        |r| Ok((r.get::<_, f64>(0)? as f32, r.get(1)?)),
+    ).optional()
+}
+
+/// (seconds outside band, seconds with a VPD value)
+fn vpd_outside(conn: &Connection, gh_id: u16, from_ms: i64, to_ms: i64, band: (f32, f32)) -> rusqlite::Result<(i64, i64)> {
+    conn.query_row(
+        &format!("SELECT COALESCE(SUM(CASE WHEN {GH_VALUE_SQL} < ?4 OR {GH_VALUE_SQL} > ?5 THEN ga.window_sec ELSE 0 END), 0),
+                COALESCE(SUM(ga.window_sec), 0)
+         FROM greenhouse_average ga JOIN sensor_type st ON st.id = ga.sensor_type_id
+         WHERE ga.greenhouse_id = ?1 AND st.key = 'vpd_kpa' AND ga.agg LIKE 'rolling_%'
+           AND ga.ts_ms >= ?2 AND ga.ts_ms < ?3 AND ga.value IS NOT NULL"),
+        params![gh_id, from_ms, to_ms, band.0 as f64, band.1 as f64],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )
+}
+
+fn node_availability(conn: &Connection, gh_id: u16, from_ms: i64, to_ms: i64) -> rusqlite::Result<Vec<NodeAvailability>> {
+    let span_ms = (to_ms - from_ms).max(1) as f32;
+    let mut stmt = conn.prepare(
+        "SELECT n.node_id, n.label, COUNT(DISTINCT nv.window_start_ms), COALESCE(MAX(nv.window_sec), 60)
+         FROM node_name n
+         LEFT JOIN node_values nv ON nv.node_id = n.id AND nv.agg LIKE 'rolling_%'
+              AND nv.ts_ms >= ?2 AND nv.ts_ms < ?3
+         WHERE n.greenhouse_id = ?1 AND n.active = 1 AND n.merged_into IS NULL
+         GROUP BY n.id ORDER BY n.node_id",
+    )?;
+    let rows = stmt.query_map(params![gh_id, from_ms, to_ms], |r| {
+        let (windows, window_sec): (i64, i64) = (r.get(2)?, r.get(3)?);
+        Ok(NodeAvailability {
+            node_id: r.get(0)?,
+            label: r.get(1)?,
+            windows,
+            availability_pct: (windows as f32 * window_sec as f32 * 1000.0 / span_ms * 100.0).min(100.0),
+        })
+    })?;
+    rows.collect()
+}
+
+/// Blocking: assemble the report for `gh_id` over `[from_ms, to_ms)`.
+pub fn shift_report(db_path: &str, gh_id: u16, from_ms: i64, to_ms: i64) -> Result<ShiftReport, String> {
+    if to_ms <= from_ms { return Err("report range is empty (to must be after from)".into()); }
+    let cfg = vpd_kpi_config(gh_id);
+    let band = (cfg.vpd_min_kpa, cfg.vpd_max_kpa);
+    let conn = open_db(db_path).map_err(|e| e.to_string())?;
+    let min = min_air_temp(&conn, gh_id, from_ms, to_ms).map_err(|e| e.to_string())?;
+    let (outside_s, covered_s) = vpd_outside(&conn, gh_id, from_ms, to_ms, band).map_err(|e| e.to_string())?;
+    let nodes = node_availability(&conn, gh_id, from_ms, to_ms).map_err(|e| e.to_string())?;
+    let clock_adjustments = conn.query_row(
+        "SELECT COUNT(*) FROM clock_adjustment WHERE from_ms < ?2 AND to_ms >= ?1",
+        params![from_ms, to_ms],
+        |r| r.get(0),
+    ).map_err(|e| e.to_string())?;
+    let maintenance = load_windows(&conn, gh_id, from_ms, to_ms).map_err(|e| e.to_string())?;
+
+    Ok(ShiftReport {
+        greenhouse_id: gh_id,
+        from_ms,
+        to_ms,
+        min_air_temp_c: min.map(|m| m.0),
+        min_air_temp_at_ms: min.map(|m| m.1),
+        vpd_band_kpa: band,
+        vpd_hours_outside: outside_s as f32 / 3600.0,
+        vpd_hours_covered: covered_s as f32 / 3600.0,
+        nodes,
+        clock_adjustments,
+        maintenance,
+    })
+}
+
+fn local_time(ts_ms: i64) -> String {
+    use chrono::TimeZone;
+    Local.timestamp_millis_opt(ts_ms).single()
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| ts_ms.to_string())
+}
+
+/// `s` as HTML text or attribute value.
+fn esc(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Printable single-page HTML for the report; every string from the database (node labels, maintenance
+/// notes) goes through `esc`.
+pub fn render_html(r: &ShiftReport) -> String {
+    let at = |ts_ms: i64| esc(&local_time(ts_ms));
+    let mut h = String::new();
+    let _ = write!(h, "<!doctype html><html><head><meta charset=\"utf-8\"><title>Shift report GH {}</title></head><body>", r.greenhouse_id);
+    let _ = write!(h, "<h1>Greenhouse {} shift report</h1><p>{} to {}</p>", r.greenhouse_id, at(r.from_ms), at(r.to_ms));
+    let _ = match (r.min_air_temp_c, r.min_air_temp_at_ms) {
+        (Some(t), Some(ts)) => write!(h, "<p>Lowest air temperature: <b>{:.2} C</b> at {}</p>", t, at(ts)),
+        _ => write!(h, "<p>Lowest air temperature: no data</p>"),
+    };
+    let _ = write!(h, "<p>VPD outside {:.2}-{:.2} kPa: <b>{:.2} h</b> of {:.2} h with data</p>",
+                   r.vpd_band_kpa.0, r.vpd_band_kpa.1, r.vpd_hours_outside, r.vpd_hours_covered);
+    let _ = write!(h, "<table border=\"1\" cellpadding=\"4\"><tr><th>Node</th><th>Label</th><th>Windows</th><th>Availability</th></tr>");
+    for n in &r.nodes {
+        let _ = write!(h, "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td></tr>", n.node_id, esc(&n.label), n.windows, n.availability_pct);
+    }
+    let _ = write!(h, "</table>");
+    if r.clock_adjustments > 0 {
+        let _ = write!(h, "<p>Clock adjusted {} time(s) during the shift; some timestamps are approximate.</p>", r.clock_adjustments);
+    }
+    for w in &r.maintenance {
+        let end = w.ended_ms.map(at).unwrap_or_else(|| "still open".into());
+        let note = esc(&w.note);
+        let _ = write!(h, "<p>Node {} in maintenance {} to {} (excluded from greenhouse figures){}{}</p>",
+                       w.node_id, at(w.started_ms), end, if note.is_empty() { "" } else { ": " }, note);
+    }
+    h.push_str("</body></html>");
+    h
+}
+
+fn greenhouse_ids(db_path: &str) -> rusqlite::Result<Vec<u16>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare("SELECT id FROM greenhouse_id ORDER BY id")?;
+    let ids = stmt.query_map([], |r| r.get(0))?;
+    ids.collect()
+}
+
+/// Public task: at every local shift change, report the shift that just ended for each greenhouse.
+pub async fn run_shift_reports<S: EventSink>(sink: S, db_path: &'static str) {
+    let cfg = report_config();
+    let mut at_handover = Scheduler::new(
+        Schedule::Daily { hour: cfg.shift_change_hour, minute: cfg.shift_change_minute },
+        Local,
+    );
+    loop {
+        let due = at_handover.wait().await.timestamp_millis();
+        let from_ms = due - cfg.shift_hours * 3_600_000;
+        let reports = tokio::task::spawn_blocking(move || -> Result<Vec<ShiftReport>, String> {
+            greenhouse_ids(db_path).map_err(|e| e.to_string())?
+                .into_iter()
+                .map(|gh_id| shift_report(db_path, gh_id, from_ms, due))
+                .collect()
+        }).await;
+        match reports {
+            Ok(Ok(list)) => {
+                for r in list {
+                    info!(target: "REPORT", "shift report ready GH:{} ({}..{})", r.greenhouse_id, r.from_ms, r.to_ms);
+                    match serde_json::to_value(&r) {
+                        Ok(v) => sink.emit_json("shift_report_ready", v),
+                        Err(e) => warn!(target: "REPORT", "serialize failed: {e}"),
+                    }
+                }
+            }
+            Ok(Err(e)) => warn!(target: "REPORT", "shift report failed: {e}"),
+            Err(e) => error!(target: "REPORT", "shift report join error: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> ShiftReport {
+        ShiftReport {
+            greenhouse_id: 2,
+            from_ms: 1_760_000_000_000,
+            to_ms: 1_760_043_200_000,
+            min_air_temp_c: Some(14.25),
+            min_air_temp_at_ms: Some(1_760_020_000_000),
+            vpd_band_kpa: (0.4, 1.2),
+            vpd_hours_outside: 1.5,
+            vpd_hours_covered: 12.0,
+            nodes: vec![NodeAvailability {
+                node_id: 3, label: "<script>alert('x')</script> & \"row 4\"".into(), windows: 700, availability_pct: 97.2,
+            }],
+            clock_adjustments: 0,
+            maintenance: vec![MaintenanceWindow {
+                id: 1, greenhouse_id: 2, node_id: 3, started_ms: 1_760_010_000_000, ended_ms: None,
+                note: "<img src=x onerror=alert(1)> swap 'probe'".into(), auto_closed: false, reminded_ms: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn escapes_all_five_characters() {
+        assert_eq!(esc(r#"<a href="x">Tom's & Jerry</a>"#), "&lt;a href=&quot;x&quot;&gt;Tom&#39;s &amp; Jerry&lt;/a&gt;");
+        assert_eq!(esc("plain text"), "plain text");
+    }
+
+    #[test]
+    fn labels_and_notes_cannot_inject_markup() {
+        let html = render_html(&report());
+        assert!(!html.contains("<script>") && !html.contains("<img"), "{html}");
+        assert!(html.contains("&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt; &amp; &quot;row 4&quot;"));
+        assert!(html.contains(": &lt;img src=x onerror=alert(1)&gt; swap &#39;probe&#39;</p>"));
+        assert!(html.contains("still open"));
+    }
+}
@@ -0,0 +1,196 @@
+//! VPD KPI: share of daylight spent inside the VPD target band, per greenhouse per local day.
+//! - Consumes GhAvg. A window is daylight when its PAR mean reaches the greenhouse's
+//!   `daylight_par_min`; daylight windows add their length to in-band, out-of-band or
+//!   unknown (no VPD) minutes. Night windows are not counted.
+//! - Band and light threshold come from `vpd_kpi_config(gh_id)` and are stored with each day.
+//! - Today's running totals live in `VpdKpiShared` (attached to `gh_avg` by the UI emitter) and
+//!   are persisted to `greenhouse_daily` every `PERSIST_EVERY`, when a day closes and when the
+//!   GhAvg channel closes; on startup today's stored row is picked up again, so a restart does not
+//!   reset the day.
+//! - The task awaits each write before taking the next snapshot, so writes land in order and an
+//!   older snapshot never overwrites a newer one.
+
+use serde::Serialize;
+use std::{collections::HashMap, sync::{Arc, RwLock}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use tokio::{sync::mpsc, time::interval};
+use tracing::{info, warn};
+
+use super::config::vpd_kpi_config;
+use crate::services::mqtt::greenhouse_sensor::{greenhouse_aggregator::GhAvg, hourly_aggregator::local_day_start};
+use crate::services::storage::daily::{load_vpd_day, load_vpd_days, upsert_vpd_days, VpdDay};
+
+const PERSIST_EVERY: Duration = Duration::from_secs(300);
+
+/// Today's running totals per greenhouse.
+pub type VpdKpiShared = Arc<RwLock<HashMap<u16, VpdDay>>>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VpdKpi {
+    pub greenhouse_id: u16,
+    pub from_ms: i64,
+    pub to_ms: i64,
+    pub days: Vec<VpdDay>,
+    pub in_band_min: f64,
+    pub out_band_min: f64,
+    pub unknown_min: f64,
+    pub pct_in_band: Option<f64>,
+}
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+fn empty_day(gh_id: u16, day_start_ms: i64) -> VpdDay {
+    let cfg = vpd_kpi_config(gh_id);
+    VpdDay {
+        greenhouse_id: gh_id,
+        day_start_ms,
+        in_band_min: 0.0,
+        out_band_min: 0.0,
+        unknown_min: 0.0,
+        vpd_min_kpa: cfg.vpd_min_kpa,
+        vpd_max_kpa: cfg.vpd_max_kpa,
+        daylight_par_min: cfg.daylight_par_min,
+    }
+}
+
+/// Count one GhAvg window into `day`.
+fn count(day: &mut VpdDay, ga: &GhAvg) {
+    if !matches!(ga.par_value, Some(p) if p >= day.daylight_par_min) { return; }
+    let minutes = (ga.ts_ms - ga.window_start_ms).max(0) as f64 / 60_000.0;
+    match ga.vpd_kpa {
+        Some(v) if (day.vpd_min_kpa..=day.vpd_max_kpa).contains(&v) => day.in_band_min += minutes,
+        Some(_) => day.out_band_min += minutes,
+        None => day.unknown_min += minutes,
+    }
+}
+
+/// Store `days` and wait for the write.
+async fn persist(db_path: &'static str, days: Vec<VpdDay>, dry_run: bool) {
+    if days.is_empty() { return; }
+    if dry_run {
+        info!(target: "KPI", "DRY RUN: would store {} VPD day row(s)", days.len());
+        return;
+    }
+    match tokio::task::spawn_blocking(move || upsert_vpd_days(db_path, &days, now_ms())).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!(target: "KPI", "storing VPD days failed: {e}"),
+        Err(e) => warn!(target: "KPI", "storing VPD days panicked: {e}"),
+    }
+}
+
+fn today_snapshot(shared: &VpdKpiShared) -> Vec<VpdDay> {
+    shared.read().map(|m| m.values().copied().collect()).unwrap_or_default()
+}
+
+/// Public async task: fold GhAvg into today's VPD KPI and persist it; returns once `rx` closes.
+pub async fn run_vpd_kpi(mut rx: mpsc::Receiver<GhAvg>, shared: VpdKpiShared, db_path: &'static str, dry_run: bool) {
+    let mut tick = interval(PERSIST_EVERY);
+    tick.tick().await; // first tick is immediate
+    loop {
+        tokio::select! {
+            maybe_ga = rx.recv() => {
+                let Some(ga) = maybe_ga else {
+                    persist(db_path, today_snapshot(&shared), dry_run).await;
+                    break;
+                };
+                let day_start = local_day_start(ga.window_start_ms);
+                let gh_id = ga.greenhouse_id;
+                let known = shared.read().ok().and_then(|m| m.get(&gh_id).copied());
+                let mut closed = None;
+                let mut day = match known {
+                    Some(d) if d.day_start_ms == day_start => d,
+                    other => {
+                        closed = other;
+                        // first window for this day: resume from the DB if the app restarted mid-day
+                        let stored = if dry_run { None } else {
+                            tokio::task::spawn_blocking(move || load_vpd_day(db_path, gh_id, day_start)).await
+                                .ok().and_then(|r| r.ok()).flatten()
+                        };
+                        stored.unwrap_or_else(|| empty_day(gh_id, day_start))
+                    }
+                };
+                count(&mut day, &ga);
+                if let Ok(mut m) = shared.write() { m.insert(gh_id, day); }
+                if let Some(prev) = closed {
+                    info!(target: "KPI", "gh={gh_id} day closed: {:.0} min in band, {:.0} out", prev.in_band_min, prev.out_band_min);
+                    persist(db_path, vec![prev], dry_run).await;
+                }
+            }
+            _ = tick.tick() => persist(db_path, today_snapshot(&shared), dry_run).await,
+        }
+    }
+}
+
+/// Blocking: stored days of `gh_id` starting in `[from_ms, to_ms)` with range totals.
+pub fn vpd_kpi(db_path: &str, gh_id: u16, from_ms: i64, to_ms: i64) -> Result<VpdKpi, String> {
+    if to_ms <= from_ms { return Err("KPI range is empty (to must be after from)".into()); }
+    let days = load_vpd_days(db_path, gh_id, from_ms, to_ms).map_err(|e| e.to_string())?;
+    let mut total = empty_day(gh_id, from_ms);
+    for d in &days {
+        total.in_band_min += d.in_band_min;
+        total.out_band_min += d.out_band_min;
+        total.unknown_min += d.unknown_min;
+    }
+    Ok(VpdKpi {
+        greenhouse_id: gh_id,
+        from_ms,
+        to_ms,
+        pct_in_band: total.pct_in_band(),
+        in_band_min: total.in_band_min,
+        out_band_min: total.out_band_min,
+        unknown_min: total.unknown_min,
+        days,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::storage::daily::load_vpd_days;
+
+    fn window(ts_ms: i64, par: f32, vpd: Option<f32>) -> GhAvg {
+        GhAvg { par_value: Some(par), vpd_kpa: vpd, ..GhAvg::sample(1, ts_ms) }
+    }
+
+    async fn run(db: &'static str, windows: Vec<GhAvg>) -> VpdKpiShared {
+        let (tx, rx) = mpsc::channel(windows.len().max(1));
+        for ga in windows { tx.send(ga).await.unwrap(); }
+        drop(tx);
+        let shared = VpdKpiShared::default();
+        tokio::time::timeout(Duration::from_secs(10), run_vpd_kpi(rx, shared.clone(), db, false)).await
+            .expect("the task ends with its channel");
+        shared
+    }
+
+    #[tokio::test]
+    async fn closed_and_running_days_are_stored_with_their_final_totals() {
+        let dir = tempfile::tempdir().unwrap();
+        let db: &'static str = Box::leak(dir.path().join("app.db").to_str().unwrap().to_owned().into_boxed_str());
+        let day = 86_400_000;
+        let noon = local_day_start(now_ms()) - 2 * day + day / 2;
+        let next_noon = noon + day;
+
+        let shared = run(db, vec![
+            window(noon, 400.0, Some(0.9)),
+            window(noon + 60_000, 400.0, Some(0.9)),
+            window(noon + 120_000, 400.0, Some(2.5)),
+            window(noon + 180_000, 400.0, None),
+            window(noon + 240_000, 10.0, Some(2.5)), // too dark: not counted
+            window(next_noon, 400.0, Some(0.9)),
+        ]).await;
+        assert_eq!(shared.read().unwrap()[&1].in_band_min, 1.0);
+
+        let minutes = |d: &VpdDay| (d.day_start_ms, d.in_band_min, d.out_band_min, d.unknown_min);
+        let stored: Vec<_> = load_vpd_days(db, 1, noon - day, next_noon + day).unwrap().iter().map(minutes).collect();
+        assert_eq!(stored, [
+            (local_day_start(noon), 2.0, 1.0, 1.0),
+            (local_day_start(next_noon), 1.0, 0.0, 0.0),
+        ]);
+
+        // a restart mid-day picks the stored running day up again
+        run(db, vec![window(next_noon + 60_000, 400.0, Some(2.5))]).await;
+        let stored: Vec<_> = load_vpd_days(db, 1, next_noon - day / 2, next_noon + day).unwrap().iter().map(minutes).collect();
+        assert_eq!(stored, [(local_day_start(next_noon), 1.0, 1.0, 0.0)]);
+    }
+}
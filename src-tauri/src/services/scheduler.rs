@@ -0,0 +1,198 @@
+//! Local-time scheduling shared by periodic jobs ("every hour at :00", "daily at 06:00").
+//! - Next-run arithmetic is done on local wall time, then resolved through the timezone:
+//!   hourly: a wall time skipped by a DST gap does not fire, and a repeated hour on fall-back
+//!   fires twice (once per real hour), so hourly jobs always see real hours.
+//!   daily: a time inside a DST gap runs right after the gap; an ambiguous one runs once (earliest).
+//! - Missed runs (laptop asleep, clock jumped) are caught up once, not once per missed slot.
+//! - Waiting re-checks the wall clock at least every minute, so suspend/resume is noticed.
+
+use chrono::{DateTime, Duration as TimeDelta, LocalResult, NaiveDateTime, TimeZone, Timelike, Utc};
+use std::time::Duration;
+use tokio::time::sleep;
+
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    Hourly { minute: u32 },
+    Daily { hour: u32, minute: u32 },
+}
+
+/// Every real instant a local wall time maps to (0, 1 or 2 of them).
+fn resolve<Tz: TimeZone>(tz: &Tz, naive: NaiveDateTime) -> Vec<DateTime<Utc>> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(t) => vec![t.with_timezone(&Utc)],
+        LocalResult::Ambiguous(a, b) => vec![a.with_timezone(&Utc), b.with_timezone(&Utc)],
+        LocalResult::None => vec![],
+    }
+}
+
+impl Schedule {
+    /// First run strictly after `after`.
+    pub fn next_after<Tz: TimeZone>(&self, tz: &Tz, after: DateTime<Utc>) -> DateTime<Utc> {
+        let local = after.with_timezone(tz).naive_local();
+        match *self {
+            Schedule::Hourly { minute } => {
+                let base = local.date().and_hms_opt(local.hour(), minute.min(59), 0).unwrap_or(local);
+                (0..=49)
+                    .flat_map(|h| resolve(tz, base + TimeDelta::hours(h)))
+                    .find(|t| *t > after)
+                    .unwrap_or(after + TimeDelta::hours(1))
+            }
+            Schedule::Daily { hour, minute } => {
+                (0..=2)
+                    .filter_map(|d| (local.date() + TimeDelta::days(d)).and_hms_opt(hour.min(23), minute.min(59), 0))
+                    .filter_map(|naive| {
+                        // in a DST gap, shift forward by the gap (at most an hour) and run then
+                        resolve(tz, naive).into_iter()
+                            .chain(resolve(tz, naive + TimeDelta::hours(1)))
+                            .next()
+                    })
+                    .find(|t| *t > after)
+                    .unwrap_or(after + TimeDelta::days(1))
+            }
+        }
+    }
+}
+
+/// Async wrapper that sleeps until each scheduled instant.
+pub struct Scheduler<Tz: TimeZone> {
+    schedule: Schedule,
+    tz: Tz,
+    next: DateTime<Utc>,
+}
+
+impl<Tz: TimeZone> Scheduler<Tz> {
+    pub fn new(schedule: Schedule, tz: Tz) -> Self {
+        let next = schedule.next_after(&tz, Utc::now());
+        Self { schedule, tz, next }
+    }
+
+    /// Wait for the next run and return the instant it was scheduled for.
+    /// If several runs were missed, this returns once and the following run is in the future.
+    pub async fn wait(&mut self) -> DateTime<Utc> {
+        loop {
+            let now = Utc::now();
+            if now >= self.next {
+                let due = self.next;
+                self.next = self.schedule.next_after(&self.tz, now);
+                return due;
+            }
+            let left = (self.next - now).to_std().unwrap_or_default();
+            sleep(left.min(MAX_SLEEP)).await;
+        }
+    }
+}
+
+/// A fixed-rule DST timezone for tests; chrono alone has no tz database.
+#[cfg(test)]
+pub(crate) mod test_tz {
+    use chrono::{Datelike, Duration as TimeDelta, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Weekday};
+
+    /// Central European time with EU summer time (last Sunday of March to last Sunday of October, 01:00 UTC).
+    #[derive(Debug, Clone, Copy)]
+    pub(crate) struct Berlin;
+
+    fn last_sunday(year: i32, month: u32) -> NaiveDate {
+        (24..=31).rev().filter_map(|d| NaiveDate::from_ymd_opt(year, month, d)).find(|d| d.weekday() == Weekday::Sun).unwrap()
+    }
+
+    impl TimeZone for Berlin {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self { Berlin }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let fits = |h: i64| {
+                let o = self.offset_from_utc_datetime(&(*local - TimeDelta::hours(h)));
+                (o.local_minus_utc() as i64 == h * 3600).then_some(o)
+            };
+            match (fits(2), fits(1)) {
+                (Some(summer), Some(winter)) => LocalResult::Ambiguous(summer, winter),
+                (Some(o), None) | (None, Some(o)) => LocalResult::Single(o),
+                (None, None) => LocalResult::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let switch = |month| last_sunday(utc.year(), month).and_hms_opt(1, 0, 0).unwrap();
+            let summer = (switch(3)..switch(10)).contains(utc);
+            FixedOffset::east_opt(if summer { 7200 } else { 3600 }).unwrap()
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_tz::Berlin;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    /// The first `n` runs after `from`, as UTC instants.
+    fn runs(schedule: Schedule, from: &str, n: usize) -> Vec<DateTime<Utc>> {
+        std::iter::successors(Some(utc(from)), |&t| Some(schedule.next_after(&Berlin, t))).skip(1).take(n).collect()
+    }
+
+    fn local_hours(runs: &[DateTime<Utc>]) -> Vec<u32> {
+        runs.iter().map(|t| t.with_timezone(&Berlin).hour()).collect()
+    }
+
+    #[test]
+    fn hourly_skips_the_hour_lost_in_spring() {
+        // 2026-03-29: 02:00 CET does not exist, clocks go from 01:59 to 03:00
+        let r = runs(Schedule::Hourly { minute: 0 }, "2026-03-28T23:30:00Z", 3);
+        assert_eq!(r, [utc("2026-03-29T00:00:00Z"), utc("2026-03-29T01:00:00Z"), utc("2026-03-29T02:00:00Z")]);
+        assert_eq!(local_hours(&r), [1, 3, 4]);
+    }
+
+    #[test]
+    fn hourly_fires_for_both_repeated_hours_in_autumn() {
+        // 2026-10-25: 02:00..03:00 happens twice, first in summer time, then in winter time
+        let r = runs(Schedule::Hourly { minute: 0 }, "2026-10-24T23:30:00Z", 3);
+        assert_eq!(r, [utc("2026-10-25T00:00:00Z"), utc("2026-10-25T01:00:00Z"), utc("2026-10-25T02:00:00Z")]);
+        assert_eq!(local_hours(&r), [2, 2, 3]);
+    }
+
+    #[test]
+    fn daily_time_in_the_spring_gap_runs_right_after_it() {
+        let r = runs(Schedule::Daily { hour: 2, minute: 30 }, "2026-03-28T12:00:00Z", 2);
+        // 02:30 is skipped on the 29th: 03:30 CEST instead, then 02:30 CEST as usual
+        assert_eq!(r, [utc("2026-03-29T01:30:00Z"), utc("2026-03-30T00:30:00Z")]);
+    }
+
+    #[test]
+    fn daily_time_in_the_repeated_hour_runs_once() {
+        let r = runs(Schedule::Daily { hour: 2, minute: 30 }, "2026-10-24T12:00:00Z", 2);
+        // the first (summer time) 02:30 on the 25th, not the second one an hour later
+        assert_eq!(r, [utc("2026-10-25T00:30:00Z"), utc("2026-10-26T01:30:00Z")]);
+    }
+
+    #[test]
+    fn daily_runs_at_the_same_wall_time_across_the_change() {
+        let r = runs(Schedule::Daily { hour: 6, minute: 0 }, "2026-10-24T12:00:00Z", 2);
+        assert_eq!(r, [utc("2026-10-25T05:00:00Z"), utc("2026-10-26T05:00:00Z")]);
+        assert_eq!(local_hours(&r), [6, 6]);
+    }
+
+    #[tokio::test]
+    async fn runs_missed_while_suspended_are_caught_up_once() {
+        // asleep for five hours: five hourly runs went by
+        let overdue = Utc::now() - TimeDelta::hours(5);
+        let mut s = Scheduler { schedule: Schedule::Hourly { minute: 0 }, tz: Berlin, next: overdue };
+        assert_eq!(s.wait().await, overdue);
+        assert!(s.next > Utc::now() && s.next - Utc::now() <= TimeDelta::hours(1));
+        assert!(tokio::time::timeout(Duration::from_millis(50), s.wait()).await.is_err(), "a missed run fired twice");
+    }
+}
@@ -0,0 +1,101 @@
+//! Orderly stop of the pipeline when the app exits (`RunEvent::Exit`).
+//! - One `CancellationToken` for the whole app. The sources (MQTT subscriber, replay) stop on it and
+//!   the subscriber sends DISCONNECT; storage stops on it too, because the clock watch never closes
+//!   its input. The data path runs on a child token so `restart_pipeline` can stop it alone the same
+//!   way (pipeline.rs).
+//! - Aggregators stop when their input closes instead of on the token, so each stage still gets
+//!   everything the stage before it emitted: node windows, then greenhouse windows (partial ones
+//!   included), then the storage batch, written last.
+//! - Storage waits for its inputs to close at most `STORAGE_DRAIN`: a sender still held (a stuck
+//!   aggregator, or a supervisor's lease that has not been let go) must not keep it from writing its
+//!   batch before `SHUTDOWN_TIMEOUT`.
+//! - main.rs waits for the tracked tasks at most `SHUTDOWN_TIMEOUT`; tasks still running then are
+//!   logged and die with the process.
+
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::async_runtime::JoinHandle;
+use tokio::time::Interval;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Leaves time for the final flush inside `SHUTDOWN_TIMEOUT`.
+pub const STORAGE_DRAIN: Duration = Duration::from_secs(3);
+
+#[derive(Default)]
+pub struct Shutdown {
+    token: CancellationToken,
+    tasks: Mutex<Vec<(&'static str, JoinHandle<()>)>>,
+}
+
+impl Shutdown {
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Wait for `handle` on exit.
+    pub fn track(&self, name: &'static str, handle: JoinHandle<()>) {
+        if let Ok(mut t) = self.tasks.lock() { t.push((name, handle)); }
+    }
+
+    /// No longer wait for the tasks named in `names` on exit; their handles, for the caller to wait on.
+    pub fn untrack(&self, names: &[&str]) -> Vec<(&'static str, JoinHandle<()>)> {
+        let Ok(mut t) = self.tasks.lock() else { return Vec::new() };
+        let (named, rest) = std::mem::take(&mut *t).into_iter().partition(|(name, _)| names.contains(name));
+        *t = rest;
+        named
+    }
+
+    /// Cancel and wait up to `timeout` for the tracked tasks; called once from the exit handler, which
+    /// is not async, while the tasks keep running on the async runtime.
+    pub fn stop(&self, timeout: Duration) {
+        self.token.cancel();
+        let tasks = self.tasks.lock().map(|mut t| std::mem::take(&mut *t)).unwrap_or_default();
+        if tasks.is_empty() { return; }
+        let mut pending: Vec<&'static str> = tasks.iter().map(|(name, _)| *name).collect();
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        for (name, handle) in tasks {
+            let done_tx = done_tx.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = handle.await { warn!(target: "PIPE", "task '{name}' failed during shutdown: {e}"); }
+                let _ = done_tx.send(name);
+            });
+        }
+        let deadline = std::time::Instant::now() + timeout;
+        while !pending.is_empty() {
+            let left = deadline.saturating_duration_since(std::time::Instant::now());
+            match done_rx.recv_timeout(left) {
+                Ok(name) => pending.retain(|p| *p != name),
+                Err(_) => break,
+            }
+        }
+        if pending.is_empty() {
+            info!(target: "PIPE", "pipeline stopped cleanly");
+        } else {
+            warn!(target: "PIPE", "still running after {}s, abandoned: {}", timeout.as_secs(), pending.join(", "));
+        }
+    }
+}
+
+/// Wait up to `timeout` for `tasks` (from async code, unlike `Shutdown::stop`); the names of those still
+/// running then, which are left to finish on their own.
+pub async fn join_within(tasks: Vec<(&'static str, JoinHandle<()>)>, timeout: Duration) -> Vec<&'static str> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut pending = Vec::new();
+    for (name, handle) in tasks {
+        match tokio::time::timeout_at(deadline, handle).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!(target: "PIPE", "task '{name}' failed while stopping: {e}"),
+            Err(_) => pending.push(name),
+        }
+    }
+    pending
+}
+
+/// Next window tick, or at once (true) when the input has closed and the last, partial window is due.
+pub async fn tick_or_closed(tick: &mut Interval, closed: bool) -> bool {
+    if closed { return true; }
+    tick.tick().await;
+    false
+}
@@ -0,0 +1,34 @@
+//! Command audit log (`command_audit`): who asked the app to do what, and how it went.
+//! - Written for every accepted remote command, whatever its outcome, and for every node changed
+//!   by a commissioning import.
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+use super::sqlite::open_db;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandRecord {
+    pub ts_ms: i64,
+    pub source: String, // e.g. "mqtt:greenhouse/app/site-01/cmd"
+    pub cmd_id: String,
+    pub op: String,
+    pub args: String,   // JSON as received
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Append one record on an open connection (e.g. inside the transaction it describes).
+pub(crate) fn insert_command(conn: &Connection, rec: &CommandRecord) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO command_audit(ts_ms, source, cmd_id, op, args, ok, detail)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![rec.ts_ms, rec.source, rec.cmd_id, rec.op, rec.args, rec.ok as i64, rec.detail],
+    )?;
+    Ok(())
+}
+
+/// Blocking: append one record.
+pub fn record_command(db_path: &str, rec: &CommandRecord) -> rusqlite::Result<()> {
+    insert_command(&open_db(db_path)?, rec)
+}
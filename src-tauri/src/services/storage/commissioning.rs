@@ -0,0 +1,386 @@
+//! Commissioning sheet import: node roster and metadata from the engineers' spreadsheet (saved as CSV).
+//! - Header row required; columns in any order, `,` or `;` separated (Excel regional settings), quotes allowed:
+//!   `greenhouse_id, node_id, label` (required) and `zone, air_temp_offset_c, air_rh_offset_pct,
+//!   plant_area_m2, expected_interval_secs` (optional; an empty cell clears the value).
+//! - Validation errors (duplicate nodes, unknown greenhouses, out-of-range values) block the whole import.
+//! - The report is a diff against `node_name`: inserted, updated (field by field) and unchanged nodes.
+//!   Re-importing the same sheet changes nothing.
+//! - `dry_run` runs the same statements and rolls back; a real run records one `command_audit` row per changed node.
+//! - Calibration offsets are applied to incoming readings (calibration.rs); `load_calibrations` feeds it.
+//! - `load_roster` gives the greenhouse aggregator the nodes its coverage counts against.
+//! - `adopt_node` adds a single node found by a discovery scan (and its greenhouse) without a sheet.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::audit::{insert_command, CommandRecord};
+use super::sqlite::open_db;
+use crate::services::mqtt::config::outdoor;
+use crate::services::mqtt::greenhouse_sensor::calibration::Offsets;
+
+const REQUIRED: [&str; 3] = ["greenhouse_id", "node_id", "label"];
+const OPTIONAL: [&str; 5] = ["zone", "air_temp_offset_c", "air_rh_offset_pct", "plant_area_m2", "expected_interval_secs"];
+
+/// One node as described by the sheet (or as stored).
+#[derive(Debug, Clone, PartialEq)]
+struct NodeSheetRow {
+    greenhouse_id: u16,
+    node_id: u16,
+    label: String,
+    zone: Option<String>,
+    air_temp_offset_c: Option<f64>,
+    air_rh_offset_pct: Option<f64>,
+    plant_area_m2: Option<f64>,
+    expected_interval_secs: Option<i64>,
+}
+
+impl NodeSheetRow {
+    /// (column, value as text) for diffing; numbers print the way the sheet would write them.
+    fn values(&self) -> [(&'static str, Option<String>); 6] {
+        [
+            ("label", Some(self.label.clone())),
+            ("zone", self.zone.clone()),
+            ("air_temp_offset_c", self.air_temp_offset_c.map(|v| v.to_string())),
+            ("air_rh_offset_pct", self.air_rh_offset_pct.map(|v| v.to_string())),
+            ("plant_area_m2", self.plant_area_m2.map(|v| v.to_string())),
+            ("expected_interval_secs", self.expected_interval_secs.map(|v| v.to_string())),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeAction {
+    Insert,
+    Update,
+    Unchanged,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeDiff {
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+    pub action: NodeAction,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SheetError {
+    pub line: usize, // 1-based, header is line 1
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub applied: bool,
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub nodes: Vec<NodeDiff>,
+    pub errors: Vec<SheetError>,
+}
+
+/// Split one CSV line on `delim`, honouring double quotes ("" inside quotes is a literal quote).
+fn split_line(line: &str, delim: char) -> Vec<String> {
+    let (mut out, mut cur, mut quoted) = (Vec::new(), String::new(), false);
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => { cur.push('"'); chars.next(); }
+            '"' => quoted = !quoted,
+            c if c == delim && !quoted => out.push(std::mem::take(&mut cur).trim().to_string()),
+            c => cur.push(c),
+        }
+    }
+    out.push(cur.trim().to_string());
+    out
+}
+
+fn id(cell: &str, col: &str, errs: &mut Vec<String>) -> Option<u16> {
+    let v = cell.parse().ok();
+    if v.is_none() { errs.push(format!("{col} '{cell}' is not a valid id")); }
+    v
+}
+
+/// Optional number within `range`; decimal commas are accepted.
+fn opt_num<T: std::str::FromStr + Into<f64> + Copy>(cell: &str, col: &str, range: (f64, f64), errs: &mut Vec<String>) -> Option<T> {
+    if cell.is_empty() { return None; }
+    match cell.replace(',', ".").parse::<T>() {
+        Ok(v) if (range.0..=range.1).contains(&v.into()) => Some(v),
+        Ok(_) => { errs.push(format!("{col} {cell} is outside {}..{}", range.0, range.1)); None }
+        Err(_) => { errs.push(format!("{col} '{cell}' is not a number")); None }
+    }
+}
+
+/// Parse and validate the sheet text into (header columns, rows); rows with errors are left out and reported.
+fn parse_sheet(text: &str) -> (Vec<String>, Vec<(usize, NodeSheetRow)>, Vec<SheetError>) {
+    let mut errors = Vec::new();
+    let mut lines = text.trim_start_matches('\u{feff}').lines().enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+    let Some((_, header)) = lines.next() else {
+        return (Vec::new(), Vec::new(), vec![SheetError { line: 1, message: "sheet is empty".into() }]);
+    };
+    let delim = if header.contains(';') && !header.contains(',') { ';' } else { ',' };
+    let cols: Vec<String> = split_line(header, delim).into_iter().map(|c| c.to_ascii_lowercase()).collect();
+    for c in &cols {
+        if !REQUIRED.contains(&c.as_str()) && !OPTIONAL.contains(&c.as_str()) {
+            errors.push(SheetError { line: 1, message: format!("unknown column '{c}'") });
+        }
+    }
+    for r in REQUIRED {
+        if !cols.iter().any(|c| c == r) {
+            errors.push(SheetError { line: 1, message: format!("missing required column '{r}'") });
+        }
+    }
+    if !errors.is_empty() { return (cols, Vec::new(), errors); }
+
+    let mut rows = Vec::new();
+    let mut seen = HashSet::new();
+    for (i, line) in lines {
+        let cells = split_line(line, delim);
+        let cell = |name: &str| cols.iter().position(|c| c == name).and_then(|p| cells.get(p)).map_or("", |s| s.as_str());
+        let mut errs = Vec::new();
+        let greenhouse_id = id(cell("greenhouse_id"), "greenhouse_id", &mut errs);
+        let node_id = id(cell("node_id"), "node_id", &mut errs);
+        let label = cell("label").to_string();
+        if label.is_empty() || label.chars().count() > 32 { errs.push("label must be 1-32 characters".into()); }
+        let row = NodeSheetRow {
+            greenhouse_id: greenhouse_id.unwrap_or(0),
+            node_id: node_id.unwrap_or(0),
+            label,
+            zone: Some(cell("zone").to_string()).filter(|z| !z.is_empty()),
+            air_temp_offset_c: opt_num(cell("air_temp_offset_c"), "air_temp_offset_c", (-5.0, 5.0), &mut errs),
+            air_rh_offset_pct: opt_num(cell("air_rh_offset_pct"), "air_rh_offset_pct", (-15.0, 15.0), &mut errs),
+            plant_area_m2: opt_num(cell("plant_area_m2"), "plant_area_m2", (0.0, 100_000.0), &mut errs),
+            expected_interval_secs: opt_num::<u32>(cell("expected_interval_secs"), "expected_interval_secs", (1.0, 3600.0), &mut errs).map(i64::from),
+        };
+        if let (Some(gh), Some(node)) = (greenhouse_id, node_id) {
+            if !seen.insert((gh, node)) { errs.push(format!("GH {gh} node {node} appears more than once")); }
+        }
+        if errs.is_empty() {
+            rows.push((i + 1, row));
+        } else {
+            errors.extend(errs.into_iter().map(|message| SheetError { line: i + 1, message }));
+        }
+    }
+    (cols, rows, errors)
+}
+
+fn stored_row(conn: &Connection, gh_id: u16, node_id: u16) -> rusqlite::Result<Option<NodeSheetRow>> {
+    conn.query_row(
+        "SELECT label, zone, air_temp_offset_c, air_rh_offset_pct, plant_area_m2, expected_interval_secs
+         FROM node_name WHERE greenhouse_id=?1 AND node_id=?2",
+        params![gh_id, node_id],
+        |r| Ok(NodeSheetRow {
+            greenhouse_id: gh_id,
+            node_id,
+            label: r.get(0)?,
+            zone: r.get(1)?,
+            air_temp_offset_c: r.get(2)?,
+            air_rh_offset_pct: r.get(3)?,
+            plant_area_m2: r.get(4)?,
+            expected_interval_secs: r.get(5)?,
+        }),
+    ).optional()
+}
+
+/// Columns the sheet did not include keep their stored value.
+fn merge_absent(mut row: NodeSheetRow, stored: &NodeSheetRow, present: &dyn Fn(&str) -> bool) -> NodeSheetRow {
+    if !present("zone") { row.zone = stored.zone.clone(); }
+    if !present("air_temp_offset_c") { row.air_temp_offset_c = stored.air_temp_offset_c; }
+    if !present("air_rh_offset_pct") { row.air_rh_offset_pct = stored.air_rh_offset_pct; }
+    if !present("plant_area_m2") { row.plant_area_m2 = stored.plant_area_m2; }
+    if !present("expected_interval_secs") { row.expected_interval_secs = stored.expected_interval_secs; }
+    row
+}
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+/// Blocking: validate the sheet at `path`, diff it against `node_name` and (unless `dry_run`) apply it in one transaction.
+pub fn import_commissioning_sheet(db_path: &str, path: &Path, dry_run: bool) -> Result<ImportReport, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+    let (cols, rows, mut errors) = parse_sheet(&text);
+    let present = |name: &str| cols.iter().any(|c| c == name);
+
+    let conn = open_db(db_path).map_err(|e| e.to_string())?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let mut nodes = Vec::new();
+    for (line, row) in rows {
+        let known_gh: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM greenhouse_id WHERE id=?1)", params![row.greenhouse_id], |r| r.get(0),
+        ).map_err(|e| e.to_string())?;
+        if !known_gh {
+            errors.push(SheetError { line, message: format!("unknown greenhouse {}", row.greenhouse_id) });
+            continue;
+        }
+        let stored = stored_row(&tx, row.greenhouse_id, row.node_id).map_err(|e| e.to_string())?;
+        let (action, row, changes) = match &stored {
+            None => {
+                let changes = row.values().into_iter()
+                    .filter(|(_, v)| v.is_some())
+                    .map(|(field, to)| FieldChange { field, from: None, to })
+                    .collect();
+                (NodeAction::Insert, row, changes)
+            }
+            Some(old) => {
+                let row = merge_absent(row, old, &present);
+                let changes: Vec<FieldChange> = old.values().into_iter().zip(row.values())
+                    .filter(|((_, a), (_, b))| a != b)
+                    .map(|((field, from), (_, to))| FieldChange { field, from, to })
+                    .collect();
+                (if changes.is_empty() { NodeAction::Unchanged } else { NodeAction::Update }, row, changes)
+            }
+        };
+        nodes.push((action, row, changes));
+    }
+
+    errors.sort_by_key(|e| e.line);
+    let mut report = ImportReport {
+        dry_run,
+        applied: false,
+        inserted: nodes.iter().filter(|n| n.0 == NodeAction::Insert).count(),
+        updated: nodes.iter().filter(|n| n.0 == NodeAction::Update).count(),
+        unchanged: nodes.iter().filter(|n| n.0 == NodeAction::Unchanged).count(),
+        nodes: Vec::new(),
+        errors,
+    };
+
+    if report.errors.is_empty() {
+        let ts_ms = now_ms();
+        for (action, row, changes) in &nodes {
+            if *action == NodeAction::Unchanged { continue; }
+            tx.execute(
+                "INSERT OR IGNORE INTO node_name(greenhouse_id, node_id, label) VALUES (?1, ?2, ?3)",
+                params![row.greenhouse_id, row.node_id, row.label],
+            ).map_err(|e| e.to_string())?;
+            tx.execute(
+                "UPDATE node_name SET label=?3, zone=?4, air_temp_offset_c=?5, air_rh_offset_pct=?6,
+                        plant_area_m2=?7, expected_interval_secs=?8
+                 WHERE greenhouse_id=?1 AND node_id=?2",
+                params![row.greenhouse_id, row.node_id, row.label, row.zone, row.air_temp_offset_c,
+                        row.air_rh_offset_pct, row.plant_area_m2, row.expected_interval_secs],
+            ).map_err(|e| e.to_string())?;
+            let args = serde_json::json!({
+                "greenhouse_id": row.greenhouse_id, "node_id": row.node_id, "changes": changes,
+            });
+            insert_command(&tx, &CommandRecord {
+                ts_ms,
+                source: format!("import:{}", path.display()),
+                cmd_id: format!("commission-{ts_ms}-{}-{}", row.greenhouse_id, row.node_id),
+                op: if *action == NodeAction::Insert { "commission_node_insert".into() } else { "commission_node_update".into() },
+                args: args.to_string(),
+                ok: true,
+                detail: format!("{} field(s)", changes.len()),
+            }).map_err(|e| e.to_string())?;
+        }
+        if !dry_run {
+            tx.commit().map_err(|e| e.to_string())?;
+            report.applied = true;
+        }
+    }
+
+    report.nodes = nodes.into_iter().map(|(action, row, changes)| NodeDiff {
+        greenhouse_id: row.greenhouse_id, node_id: row.node_id, action, changes,
+    }).collect();
+    Ok(report)
+}
+
+/// Blocking: calibration offsets of every roster node that has one, by (greenhouse_id, node_id).
+pub fn load_calibrations(db_path: &str) -> rusqlite::Result<HashMap<(u16, u16), Offsets>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(
+        "SELECT greenhouse_id, node_id, COALESCE(air_temp_offset_c, 0), COALESCE(air_rh_offset_pct, 0) FROM node_name
+         WHERE COALESCE(air_temp_offset_c, 0) != 0 OR COALESCE(air_rh_offset_pct, 0) != 0",
+    )?;
+    let rows = stmt.query_map([], |r| Ok((
+        (r.get::<_, u16>(0)?, r.get::<_, u16>(1)?),
+        Offsets { air_temp_c: r.get::<_, f64>(2)? as f32, air_rh_pct: r.get::<_, f64>(3)? as f32 },
+    )))?;
+    rows.collect()
+}
+
+/// Blocking: active, unmerged roster nodes per greenhouse, outdoor stations (by priority list or label) left out.
+pub fn load_roster(db_path: &str) -> rusqlite::Result<HashMap<u16, HashSet<u16>>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(
+        "SELECT greenhouse_id, node_id FROM node_name WHERE active = 1 AND merged_into IS NULL AND label NOT LIKE 'Outdoor%'",
+    )?;
+    let mut roster: HashMap<u16, HashSet<u16>> = HashMap::new();
+    for row in stmt.query_map([], |r| Ok((r.get::<_, u16>(0)?, r.get::<_, u16>(1)?)))? {
+        let (gh_id, node_id) = row?;
+        if outdoor().station_priority.contains(&node_id) { continue; }
+        roster.entry(gh_id).or_default().insert(node_id);
+    }
+    Ok(roster)
+}
+
+/// Blocking: add `gh_id`/`node_id` to the roster (greenhouse included) unless it is already there.
+/// `label` defaults to `nodeNN`; an existing node keeps its label. Audited like a sheet import.
+pub fn adopt_node(db_path: &str, gh_id: u16, node_id: u16, label: Option<String>) -> Result<NodeDiff, String> {
+    let label = label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).unwrap_or_else(|| format!("node{node_id:02}"));
+    let conn = open_db(db_path).map_err(|e| e.to_string())?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute("INSERT OR IGNORE INTO greenhouse_id(id) VALUES (?1)", params![gh_id]).map_err(|e| e.to_string())?;
+    let inserted = tx.execute(
+        "INSERT OR IGNORE INTO node_name(greenhouse_id, node_id, label) VALUES (?1, ?2, ?3)",
+        params![gh_id, node_id, label],
+    ).map_err(|e| e.to_string())? > 0;
+    if !inserted {
+        return Ok(NodeDiff { greenhouse_id: gh_id, node_id, action: NodeAction::Unchanged, changes: Vec::new() });
+    }
+    let ts_ms = now_ms();
+    insert_command(&tx, &CommandRecord {
+        ts_ms,
+        source: "discovery".into(),
+        cmd_id: format!("adopt-{ts_ms}-{gh_id}-{node_id}"),
+        op: "adopt_node".into(),
+        args: serde_json::json!({ "greenhouse_id": gh_id, "node_id": node_id, "label": label }).to_string(),
+        ok: true,
+        detail: "added to roster".into(),
+    }).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(NodeDiff {
+        greenhouse_id: gh_id, node_id, action: NodeAction::Insert,
+        changes: vec![FieldChange { field: "label", from: None, to: Some(label) }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applied_offsets_load_for_calibration() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db").to_string_lossy().into_owned();
+        open_db(&db).unwrap().execute("INSERT INTO greenhouse_id(id) VALUES (1)", []).unwrap();
+        let sheet = dir.path().join("sheet.csv");
+        std::fs::write(&sheet, "greenhouse_id;node_id;label;air_temp_offset_c;air_rh_offset_pct\n\
+                                1;2;North;-0.4;\n1;3;South;0;2.5\n1;4;East;;\n").unwrap();
+
+        let dry = import_commissioning_sheet(&db, &sheet, true).unwrap();
+        assert!(!dry.applied && dry.errors.is_empty());
+        assert!(load_calibrations(&db).unwrap().is_empty());
+
+        assert!(import_commissioning_sheet(&db, &sheet, false).unwrap().applied);
+        let mut offsets: Vec<_> = load_calibrations(&db).unwrap().into_iter().collect();
+        offsets.sort_by_key(|(ids, _)| *ids);
+        assert_eq!(offsets, [
+            ((1, 2), Offsets { air_temp_c: -0.4, air_rh_pct: 0.0 }),
+            ((1, 3), Offsets { air_temp_c: 0.0, air_rh_pct: 2.5 }),
+        ]);
+    }
+}
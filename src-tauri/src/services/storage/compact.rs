@@ -0,0 +1,180 @@
+//! Opt-in compact storage for `node_values` (deadband / delta compression).
+//! - Per (node, sensor) the writer remembers the last *stored* value and skips a new row
+//!   when it moved less than the sensor's deadband, so the error never accumulates.
+//! - A keyframe is still written every `KEYFRAME_MS` and on every None <-> Some change,
+//!   so readers forward-filling between rows never have to look back far or across a gap.
+//! - Storage-only: aggregation, UI and greenhouse averages still see every window.
+//! - Decisions are staged per flush and only remembered once the flush commits (`commit` /
+//!   `rollback`), so a failed transaction never suppresses the next real write.
+//! - Readers put the skipped windows back with `forward_fill` (node history, node CSV export).
+
+use std::collections::HashMap;
+
+pub(crate) const KEYFRAME_MS: i64 = 15 * 60 * 1000;
+
+/// Smallest change worth a new row, per sensor key (same units as stored values).
+const DEADBANDS: &[(&str, f64)] = &[
+    ("air_temp_c", 0.05), ("leaf_temp_c", 0.05), ("bag_temp_c", 0.05), ("leaf_air_dt_c", 0.05),
+    ("air_rh_pct", 0.2),
+    ("bag_rh1_pct", 0.2), ("bag_rh2_pct", 0.2), ("bag_rh3_pct", 0.2), ("bag_rh4_pct", 0.2),
+    ("bag_rh_avg_pct", 0.2),
+    ("par_value", 1.0), ("weight_g", 1.0),
+    ("ea_air_kpa", 0.005), ("ea_leaf_kpa", 0.005), ("es_kpa", 0.005), ("vpd_kpa", 0.005),
+];
+
+fn deadband(key: &str) -> Option<f64> {
+    DEADBANDS.iter().find(|(k, _)| *k == key).map(|&(_, d)| d)
+}
+
+/// Whether stored rows of `key` / `agg` may have been skipped: window means of deadband sensors
+/// (extremes and deviations are always written).
+pub(crate) fn compacted(key: &str, agg: &str) -> bool {
+    deadband(key).is_some() && agg.starts_with("rolling_")
+}
+
+#[derive(Debug, Default)]
+pub struct Compactor {
+    last: HashMap<(i64, &'static str), (Option<f64>, i64)>, // (node rowid, key) -> (stored value, ts)
+    skipped: HashMap<&'static str, u64>,
+    pending: HashMap<(i64, &'static str), (Option<f64>, i64)>, // kept in the running flush, not committed yet
+    pending_skipped: HashMap<&'static str, u64>,
+}
+
+impl Compactor {
+    /// Whether this (already rounded) value should be written; stages it if so (see `commit`).
+    pub fn keep(&mut self, node_rowid: i64, key: &'static str, val: Option<f64>, ts: i64) -> bool {
+        let Some(db) = deadband(key) else { return true };
+        let slot = (node_rowid, key);
+        let keep = match (self.pending.get(&slot).or_else(|| self.last.get(&slot)), val) {
+            (None, _) => true,
+            (Some(&(_, at)), _) if ts - at >= KEYFRAME_MS => true,
+            (Some(&(Some(prev), _)), Some(v)) => (v - prev).abs() >= db,
+            (Some(&(None, _)), None) => false,
+            _ => true, // None <-> Some transition
+        };
+        if keep {
+            self.pending.insert(slot, (val, ts));
+        } else {
+            *self.pending_skipped.entry(key).or_default() += 1;
+        }
+        keep
+    }
+
+    /// The flush committed: its kept values become the ones compared against.
+    pub fn commit(&mut self) {
+        self.last.extend(self.pending.drain());
+        for (key, n) in self.pending_skipped.drain() {
+            *self.skipped.entry(key).or_default() += n;
+        }
+    }
+
+    /// The flush failed: forget what it staged, so the next window is compared with what is stored.
+    pub fn rollback(&mut self) {
+        self.pending.clear();
+        self.pending_skipped.clear();
+    }
+
+    pub fn skipped_total(&self) -> u64 {
+        self.skipped.values().sum()
+    }
+}
+
+/// A stored series (ts_ms, value), oldest first, put back on its `step_ms` grid: after each row the
+/// windows the compactor may have skipped repeat its value, flagged `true`. Filling stops short of the
+/// next row, at `KEYFRAME_MS` (a longer gap is real missing data) and after `until_ms`.
+pub(crate) fn forward_fill(rows: &[(i64, Option<f64>)], step_ms: i64, until_ms: i64) -> Vec<(i64, Option<f64>, bool)> {
+    let step = step_ms.max(1);
+    let mut out = Vec::with_capacity(rows.len());
+    for (i, &(ts, val)) in rows.iter().enumerate() {
+        out.push((ts, val, false));
+        // node-clock stamps jitter around the grid: a row within half a step is the next window
+        let end = rows.get(i + 1).map_or(until_ms + 1, |&(next, _)| next - step / 2);
+        let mut t = ts + step;
+        while t < end && t - ts < KEYFRAME_MS {
+            out.push((t, val, true));
+            t += step;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN: i64 = 60_000;
+
+    fn r2(v: f64) -> f64 {
+        (v * 100.0).round() / 100.0
+    }
+
+    type Series = Vec<(i64, Option<f64>)>;
+
+    /// A day of 1-minute windows: diurnal air temperature, PAR dark at night, a slowly drying bag.
+    fn synthetic_day() -> Vec<(&'static str, Series)> {
+        let day = |f: &dyn Fn(f64) -> f64| (0..1440).map(|m| (m as i64 * MIN, Some(r2(f(m as f64))))).collect::<Series>();
+        let sun = |m: f64| (std::f64::consts::PI * (m - 360.0) / 720.0).sin();
+        vec![
+            ("air_temp_c", day(&|m| 23.0 + 5.0 * sun(m))),
+            ("par_value", day(&|m| (900.0 * sun(m)).max(0.0))),
+            ("weight_g", day(&|m| 12_000.0 - 0.25 * m)),
+        ]
+    }
+
+    #[test]
+    fn synthetic_day_stores_a_fraction_of_the_rows_and_fills_back_within_the_deadband() {
+        let mut c = Compactor::default();
+        let mut stored = 0;
+        let mut total = 0;
+        for (key, series) in synthetic_day() {
+            let kept: Vec<_> = series.iter().copied().filter(|&(ts, v)| c.keep(1, key, v, ts)).collect();
+            c.commit();
+            stored += kept.len();
+            total += series.len();
+
+            let filled = forward_fill(&kept, MIN, series.last().unwrap().0);
+            assert_eq!(filled.len(), series.len(), "{key}: one row per window after filling");
+            let db = deadband(key).unwrap();
+            for ((ts, got, _), &(want_ts, want)) in filled.iter().zip(&series) {
+                assert_eq!(*ts, want_ts);
+                assert!((got.unwrap() - want.unwrap()).abs() < db + 1e-9, "{key} at {ts}: {got:?} vs {want:?}");
+            }
+        }
+        assert_eq!(c.skipped_total() as usize, total - stored);
+        assert!(stored * 2 < total, "{stored} of {total} rows stored");
+    }
+
+    #[test]
+    fn keyframe_every_fifteen_minutes_of_a_flat_signal() {
+        let mut c = Compactor::default();
+        let kept = (0..60).filter(|&m| c.keep(1, "air_temp_c", Some(20.0), m * MIN)).count();
+        assert_eq!(kept, 4);
+        assert!(c.keep(1, "battery_v", Some(3.7), 0) && c.keep(1, "battery_v", Some(3.7), MIN), "no deadband, always kept");
+    }
+
+    #[test]
+    fn rolled_back_flush_does_not_suppress_the_next_write() {
+        let mut c = Compactor::default();
+        assert!(c.keep(1, "air_temp_c", Some(20.0), 0));
+        c.rollback();
+        assert!(c.keep(1, "air_temp_c", Some(20.01), MIN), "nothing was stored, so this row is needed");
+        assert!(!c.keep(1, "air_temp_c", Some(20.02), 2 * MIN), "staged rows count within the same flush");
+        c.commit();
+        assert!(!c.keep(1, "air_temp_c", Some(20.03), 3 * MIN));
+        assert_eq!(c.skipped_total(), 1, "uncommitted skips are not counted yet");
+        c.rollback();
+        assert_eq!(c.skipped_total(), 1);
+    }
+
+    #[test]
+    fn fill_stops_at_long_gaps_and_the_end() {
+        let rows = [(0, Some(1.0)), (3 * MIN, Some(2.0)), (60 * MIN, None), (61 * MIN + 2_000, Some(3.0))];
+        let filled = forward_fill(&rows, MIN, 62 * MIN);
+        let at = |t: i64| filled.iter().find(|r| r.0 == t).copied();
+        assert_eq!(at(2 * MIN), Some((2 * MIN, Some(1.0), true)));
+        assert_eq!(at(17 * MIN), Some((17 * MIN, Some(2.0), true)));
+        assert_eq!(at(18 * MIN), None, "15 minutes after the last row is a real gap");
+        assert_eq!(filled.iter().filter(|r| r.0 > 60 * MIN && r.0 < 61 * MIN + 2_000).count(), 0, "jittered next row");
+        assert_eq!(filled.last().copied(), Some((61 * MIN + 2_000, Some(3.0), false)), "nothing past `until_ms`");
+    }
+}
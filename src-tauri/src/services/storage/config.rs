@@ -0,0 +1,17 @@
+#[derive(Clone, Copy)]
+pub struct StorageConfig {
+    /// Opt-in: skip node rows whose value moved less than the sensor's deadband (see compact.rs).
+    pub compact: bool,
+    /// Count would-be inserts instead of writing (also `--dry-run` / APPTEST_DRY_RUN=1, see dry_run.rs).
+    pub dry_run: bool,
+    /// Opt-in, one-way: store values as scaled integers (see scaled.rs). Older rows need convert_scaled_values.
+    pub scaled_values: bool,
+}
+
+pub const fn storage_config() -> StorageConfig {
+    StorageConfig {
+        compact: false,
+        dry_run: false,
+        scaled_values: false,
+    }
+}
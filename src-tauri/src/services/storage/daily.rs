@@ -0,0 +1,92 @@
+//! Daily summary per greenhouse (`greenhouse_daily`, one row per local day).
+//! - Today's row is rewritten as the day goes on; closed days are final.
+//! - Holds the VPD KPI for now: minutes in / out of the target band during daylight, plus
+//!   daylight minutes without a VPD value, and the band and light threshold the day was judged by.
+
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+
+use super::sqlite::open_db;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct VpdDay {
+    pub greenhouse_id: u16,
+    pub day_start_ms: i64, // local midnight
+    pub in_band_min: f64,
+    pub out_band_min: f64,
+    pub unknown_min: f64,  // daylight, but no greenhouse VPD
+    pub vpd_min_kpa: f32,
+    pub vpd_max_kpa: f32,
+    pub daylight_par_min: f32,
+}
+
+impl VpdDay {
+    /// Share of judged daylight minutes inside the band (None before any daylight).
+    pub fn pct_in_band(&self) -> Option<f64> {
+        let judged = self.in_band_min + self.out_band_min;
+        (judged > 0.0).then(|| self.in_band_min * 100.0 / judged)
+    }
+}
+
+/// Blocking: write (or overwrite) the given day rows in one transaction.
+pub fn upsert_vpd_days(db_path: &str, days: &[VpdDay], updated_ms: i64) -> rusqlite::Result<()> {
+    let conn = open_db(db_path)?;
+    let tx = conn.unchecked_transaction()?;
+    for d in days {
+        tx.execute("INSERT OR IGNORE INTO greenhouse_id(id) VALUES (?1)", params![d.greenhouse_id])?;
+        tx.execute(
+            "INSERT INTO greenhouse_daily
+               (greenhouse_id, day_start_ms, vpd_in_band_min, vpd_out_band_min, vpd_unknown_min,
+                vpd_min_kpa, vpd_max_kpa, daylight_par_min, updated_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+             ON CONFLICT(greenhouse_id, day_start_ms) DO UPDATE SET
+               vpd_in_band_min = excluded.vpd_in_band_min,
+               vpd_out_band_min = excluded.vpd_out_band_min,
+               vpd_unknown_min = excluded.vpd_unknown_min,
+               vpd_min_kpa = excluded.vpd_min_kpa,
+               vpd_max_kpa = excluded.vpd_max_kpa,
+               daylight_par_min = excluded.daylight_par_min,
+               updated_ms = excluded.updated_ms",
+            params![d.greenhouse_id, d.day_start_ms, d.in_band_min, d.out_band_min, d.unknown_min,
+                    d.vpd_min_kpa as f64, d.vpd_max_kpa as f64, d.daylight_par_min as f64, updated_ms],
+        )?;
+    }
+    tx.commit()
+}
+
+fn row_to_day(r: &rusqlite::Row) -> rusqlite::Result<VpdDay> {
+    Ok(VpdDay {
+        greenhouse_id: r.get(0)?,
+        day_start_ms: r.get(1)?,
+        in_band_min: r.get(2)?,
+        out_band_min: r.get(3)?,
+        unknown_min: r.get(4)?,
+        vpd_min_kpa: r.get::<_, f64>(5)? as f32,
+        vpd_max_kpa: r.get::<_, f64>(6)? as f32,
+        daylight_par_min: r.get::<_, f64>(7)? as f32,
+    })
+}
+
+const DAY_COLUMNS: &str = "greenhouse_id, day_start_ms, vpd_in_band_min, vpd_out_band_min, vpd_unknown_min,
+                           vpd_min_kpa, vpd_max_kpa, daylight_par_min";
+
+/// Blocking: days of `gh_id` starting in `[from_ms, to_ms)`, oldest first.
+pub fn load_vpd_days(db_path: &str, gh_id: u16, from_ms: i64, to_ms: i64) -> rusqlite::Result<Vec<VpdDay>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {DAY_COLUMNS} FROM greenhouse_daily
+         WHERE greenhouse_id = ?1 AND day_start_ms >= ?2 AND day_start_ms < ?3
+         ORDER BY day_start_ms"
+    ))?;
+    let rows = stmt.query_map(params![gh_id, from_ms, to_ms], row_to_day)?;
+    rows.collect()
+}
+
+/// Blocking: one stored day, used to resume today's counts after a restart.
+pub fn load_vpd_day(db_path: &str, gh_id: u16, day_start_ms: i64) -> rusqlite::Result<Option<VpdDay>> {
+    open_db(db_path)?.query_row(
+        &format!("SELECT {DAY_COLUMNS} FROM greenhouse_daily WHERE greenhouse_id = ?1 AND day_start_ms = ?2"),
+        params![gh_id, day_start_ms],
+        row_to_day,
+    ).optional()
+}
@@ -0,0 +1,93 @@
+//! Stored drainage analyses (`drainage_event`, one row per node per irrigation event).
+//! - Keyed by (greenhouse, node, first shot): rerunning the analysis over the same hours changes nothing.
+//! - Weight traces are read from the nodes' stored 60s rows (merged nodes read as the node they were merged into).
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::scaled::NV_VALUE_SQL;
+use super::sqlite::open_db;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DrainageEvent {
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+    pub shot_ms: i64,
+    /// Shots merged into this event (a new shot inside the follow window restarts it).
+    pub shots: u32,
+    pub peak_ms: i64,
+    pub pre_g: f64,
+    pub peak_g: f64,
+    pub end_g: f64,
+    /// (peak - end) / (peak - pre): share of the added water gone after the follow window.
+    pub drained_frac: f64,
+    pub coverage: f64,
+    pub baseline_frac: Option<f64>,
+    pub baseline_events: usize,
+    pub verdict: String,
+    pub analyzed_ms: i64,
+}
+
+/// (greenhouse, node) -> weight samples (ts_ms, g), oldest first, for indoor nodes in `[from_ms, to_ms)`.
+pub type WeightTraces = BTreeMap<(u16, u16), Vec<(i64, f64)>>;
+
+/// Blocking: weight traces of every indoor node in `[from_ms, to_ms)`.
+pub fn load_weight_traces(conn: &Connection, from_ms: i64, to_ms: i64) -> rusqlite::Result<WeightTraces> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT n.greenhouse_id, n.node_id, nv.ts_ms, {NV_VALUE_SQL}
+         FROM node_values nv JOIN sensor_type st ON st.id = nv.sensor_type_id JOIN node_name n ON n.id = nv.node_id
+         WHERE st.key = 'weight_g' AND nv.window_sec = 60 AND nv.agg LIKE 'rolling_%' AND nv.ts_ms >= ?1 AND nv.ts_ms < ?2
+         ORDER BY nv.ts_ms",
+    ))?;
+    let rows = stmt.query_map(params![from_ms, to_ms], |r| Ok((
+        r.get::<_, u16>(0)?, r.get::<_, u16>(1)?, r.get::<_, i64>(2)?, r.get::<_, Option<f64>>(3)?,
+    )))?;
+    let mut out = WeightTraces::new();
+    for row in rows {
+        let (gh, node, ts, v) = row?;
+        if let Some(v) = v { out.entry((gh, node)).or_default().push((ts, v)); }
+    }
+    Ok(out)
+}
+
+/// Drained fractions of the node's events judged normal (or without baseline) in `[from_ms, to_ms)`.
+pub fn load_baseline(conn: &Connection, gh_id: u16, node_id: u16, from_ms: i64, to_ms: i64) -> rusqlite::Result<Vec<f64>> {
+    let mut stmt = conn.prepare(
+        "SELECT drained_frac FROM drainage_event
+         WHERE greenhouse_id = ?1 AND node_id = ?2 AND shot_ms >= ?3 AND shot_ms < ?4
+           AND verdict IN ('normal', 'no_baseline')",
+    )?;
+    let rows = stmt.query_map(params![gh_id, node_id, from_ms, to_ms], |r| r.get(0))?;
+    rows.collect()
+}
+
+/// Store `ev` unless an event with the same first shot is already stored; true when inserted.
+pub fn insert_event(conn: &Connection, ev: &DrainageEvent) -> rusqlite::Result<bool> {
+    Ok(conn.execute(
+        "INSERT OR IGNORE INTO drainage_event
+           (greenhouse_id, node_id, shot_ms, shots, peak_ms, pre_g, peak_g, end_g, drained_frac, coverage,
+            baseline_frac, baseline_events, verdict, analyzed_ms)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![ev.greenhouse_id, ev.node_id, ev.shot_ms, ev.shots, ev.peak_ms, ev.pre_g, ev.peak_g, ev.end_g,
+                ev.drained_frac, ev.coverage, ev.baseline_frac, ev.baseline_events as i64, ev.verdict, ev.analyzed_ms],
+    )? > 0)
+}
+
+/// Blocking: events of `node_id` (any greenhouse) whose first shot is in `[from_ms, to_ms)`.
+pub fn load_events(db_path: &str, node_id: u16, from_ms: i64, to_ms: i64) -> rusqlite::Result<Vec<DrainageEvent>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(
+        "SELECT greenhouse_id, node_id, shot_ms, shots, peak_ms, pre_g, peak_g, end_g, drained_frac, coverage,
+                baseline_frac, baseline_events, verdict, analyzed_ms
+         FROM drainage_event WHERE node_id = ?1 AND shot_ms >= ?2 AND shot_ms < ?3
+         ORDER BY greenhouse_id, shot_ms",
+    )?;
+    let rows = stmt.query_map(params![node_id, from_ms, to_ms], |r| Ok(DrainageEvent {
+        greenhouse_id: r.get(0)?, node_id: r.get(1)?, shot_ms: r.get(2)?, shots: r.get(3)?, peak_ms: r.get(4)?,
+        pre_g: r.get(5)?, peak_g: r.get(6)?, end_g: r.get(7)?, drained_frac: r.get(8)?, coverage: r.get(9)?,
+        baseline_frac: r.get(10)?, baseline_events: r.get::<_, i64>(11)? as usize, verdict: r.get(12)?,
+        analyzed_ms: r.get(13)?,
+    }))?;
+    rows.collect()
+}
@@ -0,0 +1,75 @@
+//! Dry-run storage: the full pipeline runs, `run_storage` only counts what it would insert.
+//! - Enabled at startup by `--dry-run`, `APPTEST_DRY_RUN=1` or `storage_config().dry_run`.
+//! - Fixed for the lifetime of the process: leaving dry-run needs a restart, so no window is
+//!   ever half counted and half written.
+//! - Nothing opens the DB in this mode (no init, no migrations, no flushes): `open_db` refuses, and
+//!   the startup loads, audit and watchdogs skip their DB work; commands that need the DB say why.
+
+use serde::Serialize;
+use std::{sync::{Arc, OnceLock, RwLock}, time::{SystemTime, UNIX_EPOCH}};
+use tracing::info;
+
+use crate::services::mqtt::greenhouse_sensor::{
+    aggregator::NodeAvg, greenhouse_aggregator::GhAvg, hourly_aggregator::GhHourly,
+};
+use super::config::storage_config;
+
+/// Would-be writes since startup (all zero and `enabled: false` in normal mode).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DryRunReport {
+    pub enabled: bool,
+    pub since_ms: i64,
+    pub flushes: u64,
+    pub node_values_rows: u64,
+    pub greenhouse_average_rows: u64,
+    pub hourly_rows: u64,
+    pub node_windows: u64,
+    pub gh_windows: u64,
+}
+
+pub type DryRunShared = Arc<RwLock<DryRunReport>>;
+
+/// Decided once, at the first call (startup), and fixed from then on.
+pub fn dry_run_requested() -> bool {
+    static REQUESTED: OnceLock<bool> = OnceLock::new();
+    *REQUESTED.get_or_init(|| {
+        storage_config().dry_run
+            || std::env::args().any(|a| a == "--dry-run")
+            || std::env::var("APPTEST_DRY_RUN").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+    })
+}
+
+/// What `open_db` returns in dry-run instead of a connection.
+pub(crate) fn db_refused() -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
+        Some("dry run: the database is not opened".into()),
+    )
+}
+
+pub fn new_report(enabled: bool) -> DryRunShared {
+    let since_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0);
+    Arc::new(RwLock::new(DryRunReport { enabled, since_ms, ..Default::default() }))
+}
+
+/// Count one flush worth of batches (same row shapes as `flush_batch`) and log the totals.
+pub fn record_flush(report: &DryRunShared, nodes: &[NodeAvg], gh: &[GhAvg], hourly: &[GhHourly]) {
+    if nodes.is_empty() && gh.is_empty() && hourly.is_empty() { return; }
+    let node_rows: u64 = nodes.iter().map(|na| (na.fields().len() + na.extremes.rows().len() + na.spread.rows().len()) as u64).sum();
+    let gh_rows: u64 = gh.iter().map(|ga| (ga.fields().len() + ga.extremes.rows().len()) as u64).sum();
+    let hourly_rows: u64 = hourly.iter().map(|h| h.fields.len() as u64 * 3).sum(); // mean/min/max
+
+    info!(
+        target: "DB",
+        "DRY RUN flush: would insert node_values={} greenhouse_average={} (hourly {})",
+        node_rows, gh_rows + hourly_rows, hourly_rows
+    );
+    if let Ok(mut r) = report.write() {
+        r.flushes += 1;
+        r.node_values_rows += node_rows;
+        r.greenhouse_average_rows += gh_rows + hourly_rows;
+        r.hourly_rows += hourly_rows;
+        r.node_windows += nodes.len() as u64;
+        r.gh_windows += gh.len() as u64;
+    }
+}
@@ -0,0 +1,156 @@
+//! CSV export of stored greenhouse rows (the export engine behind scheduled exports).
+//! - One row per stored value: local time, ts_ms, sensor, unit, agg, value, nodes; optionally
+//!   followed by the row's provenance (source, revision, revised_ms; see history.rs).
+//! - With `nodes` the greenhouse's node rows are exported instead (local time, ts_ms, node, sensor,
+//!   unit, agg, value, filled, then the optional source): a regular window grid, with windows skipped
+//!   by compact mode forward-filled (history.rs `load_node_history`).
+//! - Written to `<file>.tmp` and renamed, so a consumer polling the share never sees half a file.
+//! - Outcomes of scheduled exports go to `export_history`.
+
+use chrono::{Local, TimeZone};
+use rusqlite::params;
+use serde::Serialize;
+use std::{fs, io::{BufWriter, Write}, path::{Path, PathBuf}};
+
+use super::history::{load_node_history, HistoryQuery, RevisionFilter};
+use super::scaled::GH_VALUE_SQL;
+use super::sqlite::open_db;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportRecord {
+    pub ts_ms: i64,
+    pub greenhouse_id: u16,
+    pub from_ms: i64,
+    pub to_ms: i64,
+    pub path: String,
+    pub rows: i64,
+    pub ok: bool,
+    pub attempts: u32,
+    pub detail: String,
+}
+
+/// Row selection and optional columns of one export. Empty `sensors` / `aggs` mean all.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CsvOptions<'a> {
+    pub sensors: &'a [&'a str],
+    pub aggs: &'a [&'a str],
+    /// Append source, revision and revised_ms columns.
+    pub provenance: bool,
+    pub revisions: RevisionFilter,
+    /// Node rows on their window grid instead of greenhouse rows.
+    pub nodes: bool,
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) { format!("\"{}\"", s.replace('"', "\"\"")) } else { s.to_string() }
+}
+
+fn local_time(ts_ms: i64) -> String {
+    Local.timestamp_millis_opt(ts_ms).single().map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_default()
+}
+
+fn opt(v: Option<impl ToString>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Write `header` and `lines` to `<out>.tmp`, then rename it over `out`; returns the line count.
+fn write_csv(out: &Path, header: &str, lines: impl Iterator<Item = Result<String, String>>) -> Result<usize, String> {
+    if let Some(dir) = out.parent() { fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?; }
+    let tmp = PathBuf::from(format!("{}.tmp", out.display()));
+    let file = fs::File::create(&tmp).map_err(|e| format!("{}: {e}", tmp.display()))?;
+    let mut w = BufWriter::new(file);
+    let mut n = 0;
+    let io = |e: std::io::Error| format!("{}: {e}", tmp.display());
+    writeln!(w, "{header}").map_err(io)?;
+    for line in lines {
+        writeln!(w, "{}", line?).map_err(io)?;
+        n += 1;
+    }
+    w.into_inner().map_err(|e| e.to_string())?.sync_all().map_err(io)?;
+    fs::rename(&tmp, out).map_err(|e| format!("{}: {e}", out.display()))?;
+    Ok(n)
+}
+
+/// Blocking: write `gh_id`'s rows in `[from_ms, to_ms)` selected by `opts` to `out`.
+pub fn export_greenhouse_csv(
+    db_path: &str, gh_id: u16, from_ms: i64, to_ms: i64, opts: CsvOptions, out: &Path,
+) -> Result<usize, String> {
+    if opts.nodes { return export_node_csv(db_path, gh_id, from_ms, to_ms, opts, out); }
+    let conn = open_db(db_path).map_err(|e| e.to_string())?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT ga.ts_ms, st.key, st.unit, ga.agg, {GH_VALUE_SQL}, ga.nodes, ga.source, ga.revision, ga.revised_ms
+         FROM greenhouse_average ga JOIN sensor_type st ON st.id = ga.sensor_type_id
+         WHERE ga.greenhouse_id = ?1 AND ga.ts_ms >= ?2 AND ga.ts_ms < ?3 AND {}
+         ORDER BY ga.ts_ms, st.key, ga.agg",
+        opts.revisions.sql(),
+    )).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![gh_id, from_ms, to_ms], |r| Ok((
+        r.get::<_, i64>(0)?, r.get::<_, String>(1)?, r.get::<_, String>(2)?,
+        r.get::<_, String>(3)?, r.get::<_, Option<f64>>(4)?, r.get::<_, i64>(5)?,
+        (r.get::<_, String>(6)?, r.get::<_, i64>(7)?, r.get::<_, Option<i64>>(8)?),
+    ))).map_err(|e| e.to_string())?;
+
+    let extra = if opts.provenance { ",source,revision,revised_ms" } else { "" };
+    let lines = rows.filter_map(|row| {
+        let (ts, key, unit, agg, value, nodes, (source, revision, revised_ms)) = match row {
+            Ok(row) => row,
+            Err(e) => return Some(Err(e.to_string())),
+        };
+        if !opts.sensors.is_empty() && !opts.sensors.contains(&key.as_str()) { return None; }
+        if !opts.aggs.is_empty() && !opts.aggs.contains(&agg.as_str()) { return None; }
+        let mut line = format!("{},{ts},{},{},{},{},{nodes}",
+            local_time(ts), csv_field(&key), csv_field(&unit), csv_field(&agg), opt(value));
+        if opts.provenance {
+            line += &format!(",{},{revision},{}", csv_field(&source), opt(revised_ms));
+        }
+        Some(Ok(line))
+    });
+    write_csv(out, &format!("local_time,ts_ms,sensor,unit,agg,value,nodes{extra}"), lines)
+}
+
+/// Node rows of `gh_id` on their window grid, compacted windows forward-filled (`filled` = 1).
+fn export_node_csv(
+    db_path: &str, gh_id: u16, from_ms: i64, to_ms: i64, opts: CsvOptions, out: &Path,
+) -> Result<usize, String> {
+    let q = HistoryQuery {
+        sensors: opts.sensors.iter().map(|s| s.to_string()).collect(),
+        aggs: opts.aggs.iter().map(|s| s.to_string()).collect(),
+        limit: Some(u32::MAX),
+        ..Default::default()
+    };
+    let rows = load_node_history(db_path, gh_id, None, from_ms, to_ms, &q).map_err(|e| e.to_string())?;
+    let extra = if opts.provenance { ",source" } else { "" };
+    let lines = rows.into_iter().map(|r| {
+        let mut line = format!("{},{},{},{},{},{},{},{}",
+            local_time(r.ts_ms), r.ts_ms, r.node_id, csv_field(&r.sensor), csv_field(&r.unit), csv_field(&r.agg),
+            opt(r.value), r.filled as u8);
+        if opts.provenance { line += &format!(",{}", csv_field(&r.source)); }
+        Ok(line)
+    });
+    write_csv(out, &format!("local_time,ts_ms,node,sensor,unit,agg,value,filled{extra}"), lines)
+}
+
+/// Blocking: append one export outcome.
+pub fn record_export(db_path: &str, rec: &ExportRecord) -> rusqlite::Result<()> {
+    open_db(db_path)?.execute(
+        "INSERT INTO export_history(ts_ms, greenhouse_id, from_ms, to_ms, path, rows, ok, attempts, detail)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![rec.ts_ms, rec.greenhouse_id, rec.from_ms, rec.to_ms, rec.path, rec.rows,
+                rec.ok as i64, rec.attempts, rec.detail],
+    )?;
+    Ok(())
+}
+
+/// Blocking: the latest `limit` export outcomes, newest first.
+pub fn load_export_history(db_path: &str, limit: u32) -> rusqlite::Result<Vec<ExportRecord>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(
+        "SELECT ts_ms, greenhouse_id, from_ms, to_ms, path, rows, ok, attempts, detail
+         FROM export_history ORDER BY ts_ms DESC, id DESC LIMIT ?1",
+    )?;
+    let rows = stmt.query_map(params![limit], |r| Ok(ExportRecord {
+        ts_ms: r.get(0)?, greenhouse_id: r.get(1)?, from_ms: r.get(2)?, to_ms: r.get(3)?,
+        path: r.get(4)?, rows: r.get(5)?, ok: r.get::<_, i64>(6)? != 0, attempts: r.get(7)?, detail: r.get(8)?,
+    }))?;
+    rows.collect()
+}
@@ -0,0 +1,64 @@
+//! Daily node health snapshots (`node_health_daily`, one row per node per local day).
+//! - Each scoring run folds its scores into the day's row: running sum, minimum and last score.
+//! - Unscored periods (no component measured) are not stored.
+
+use rusqlite::params;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::sqlite::open_db;
+use crate::services::node_health::NodeHealth;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthDay {
+    pub day_start_ms: i64,
+    pub score_avg: f64,
+    pub score_min: f64,
+    pub last_score: f64,
+    pub samples: i64,
+}
+
+/// Blocking: `expected_interval_secs` of every roster node that has one, by (greenhouse_id, node_id).
+pub fn load_expected_intervals(db_path: &str) -> rusqlite::Result<HashMap<(u16, u16), u64>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(
+        "SELECT greenhouse_id, node_id, expected_interval_secs FROM node_name
+         WHERE expected_interval_secs IS NOT NULL AND expected_interval_secs > 0",
+    )?;
+    let rows = stmt.query_map([], |r| Ok(((r.get::<_, u16>(0)?, r.get::<_, u16>(1)?), r.get::<_, i64>(2)? as u64)))?;
+    rows.collect()
+}
+
+/// Blocking: fold one scoring run into the rows of local day `day_start_ms`.
+pub fn upsert_health_days(db_path: &str, day_start_ms: i64, scores: &[NodeHealth]) -> rusqlite::Result<()> {
+    let conn = open_db(db_path)?;
+    let tx = conn.unchecked_transaction()?;
+    for h in scores {
+        let Some(score) = h.score else { continue };
+        tx.execute(
+            "INSERT INTO node_health_daily
+               (greenhouse_id, node_id, day_start_ms, samples, score_sum, score_min, last_score, updated_ms)
+             VALUES (?1, ?2, ?3, 1, ?4, ?4, ?4, ?5)
+             ON CONFLICT(greenhouse_id, node_id, day_start_ms) DO UPDATE SET
+               samples = samples + 1, score_sum = score_sum + excluded.score_sum,
+               score_min = MIN(score_min, excluded.score_min), last_score = excluded.last_score,
+               updated_ms = excluded.updated_ms",
+            params![h.greenhouse_id, h.node_id, day_start_ms, score as f64, h.ts_ms],
+        )?;
+    }
+    tx.commit()
+}
+
+/// Blocking: daily snapshots of one node for days starting in `[from_ms, to_ms)`, oldest first.
+pub fn load_health_days(db_path: &str, gh_id: u16, node_id: u16, from_ms: i64, to_ms: i64) -> rusqlite::Result<Vec<HealthDay>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(
+        "SELECT day_start_ms, score_sum / samples, score_min, last_score, samples FROM node_health_daily
+         WHERE greenhouse_id = ?1 AND node_id = ?2 AND day_start_ms >= ?3 AND day_start_ms < ?4
+         ORDER BY day_start_ms",
+    )?;
+    let rows = stmt.query_map(params![gh_id, node_id, from_ms, to_ms], |r| Ok(HealthDay {
+        day_start_ms: r.get(0)?, score_avg: r.get(1)?, score_min: r.get(2)?, last_score: r.get(3)?, samples: r.get(4)?,
+    }))?;
+    rows.collect()
+}
@@ -0,0 +1,190 @@
+//! Stored greenhouse history with row provenance, for charts that must tell original from revised points.
+//! - `source` records the write path that produced a row: 'live' (the storage writer), 'rebuild'
+//!   (rebuild.rs), 'catchup' (windows back-filled from late samples, aggregator.rs) and 'import'
+//!   reserved for backfill paths. Rows written before
+//!   migration 14 read as 'live'.
+//! - `revision` counts rewrites of a row and `revised_ms` is the last one; revised rows are updated
+//!   in place, so the value before a rewrite is not kept.
+//! - `RevisionFilter` selects the latest data (everything, default), only original rows (never
+//!   revised) or only revised rows. CSV export (export.rs) can add the same columns.
+//! - Node history reads `node_values` and puts windows skipped by compact mode back (compact.rs
+//!   `forward_fill`), flagged `filled`, so a compacted series reads as a regular one.
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::compact::{compacted, forward_fill};
+use super::scaled::{GH_VALUE_SQL, NV_VALUE_SQL};
+use super::sqlite::open_db;
+
+const DEFAULT_LIMIT: u32 = 50_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provenance {
+    Live,
+    Catchup,
+    Import,
+    Rebuild,
+}
+
+impl Provenance {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Provenance::Live => "live",
+            Provenance::Catchup => "catchup",
+            Provenance::Import => "import",
+            Provenance::Rebuild => "rebuild",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionFilter {
+    /// Every row with its current value.
+    #[default]
+    Latest,
+    /// Rows never rewritten since they were first stored.
+    Original,
+    /// Rows rewritten at least once.
+    Revised,
+}
+
+impl RevisionFilter {
+    /// WHERE condition on `greenhouse_average ga`.
+    pub(crate) fn sql(self) -> &'static str {
+        match self {
+            RevisionFilter::Latest => "1",
+            RevisionFilter::Original => "ga.revision = 0",
+            RevisionFilter::Revised => "ga.revision > 0",
+        }
+    }
+}
+
+/// Optional filters of `get_gh_history`; empty `sensors` / `aggs` mean all.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryQuery {
+    pub sensors: Vec<String>,
+    pub aggs: Vec<String>,
+    pub revisions: RevisionFilter,
+    pub source: Option<Provenance>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryRow {
+    pub ts_ms: i64,
+    pub sensor: String,
+    pub unit: String,
+    pub agg: String,
+    pub value: Option<f64>,
+    pub nodes: i64,
+    pub source: String,
+    pub revision: i64,
+    pub revised_ms: Option<i64>,
+    /// Stamped inside a clock adjustment's quarantined span (clock.rs); its ts_ms is approximate.
+    pub quarantined: bool,
+}
+
+/// Blocking: `gh_id`'s rows in `[from_ms, to_ms)`, oldest first, at most `limit` (default 50 000).
+pub fn load_gh_history(db_path: &str, gh_id: u16, from_ms: i64, to_ms: i64, q: &HistoryQuery) -> rusqlite::Result<Vec<HistoryRow>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT ga.ts_ms, st.key, st.unit, ga.agg, {GH_VALUE_SQL}, ga.nodes, ga.source, ga.revision, ga.revised_ms, ga.quarantined
+         FROM greenhouse_average ga JOIN sensor_type st ON st.id = ga.sensor_type_id
+         WHERE ga.greenhouse_id = ?1 AND ga.ts_ms >= ?2 AND ga.ts_ms < ?3 AND {} AND (?4 IS NULL OR ga.source = ?4)
+         ORDER BY ga.ts_ms, st.key, ga.agg",
+        q.revisions.sql(),
+    ))?;
+    let rows = stmt.query_map(params![gh_id, from_ms, to_ms, q.source.map(Provenance::as_str)], |r| Ok(HistoryRow {
+        ts_ms: r.get(0)?, sensor: r.get(1)?, unit: r.get(2)?, agg: r.get(3)?, value: r.get(4)?, nodes: r.get(5)?,
+        source: r.get(6)?, revision: r.get(7)?, revised_ms: r.get(8)?, quarantined: r.get::<_, i64>(9)? != 0,
+    }))?;
+    let limit = q.limit.unwrap_or(DEFAULT_LIMIT) as usize;
+    let mut out = Vec::new();
+    for row in rows {
+        let row = row?;
+        if !q.sensors.is_empty() && !q.sensors.contains(&row.sensor) { continue; }
+        if !q.aggs.is_empty() && !q.aggs.contains(&row.agg) { continue; }
+        out.push(row);
+        if out.len() >= limit { break; }
+    }
+    Ok(out)
+}
+
+/// One stored or forward-filled node value.
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeHistoryRow {
+    pub ts_ms: i64,
+    pub node_id: u16,
+    pub sensor: String,
+    pub unit: String,
+    pub agg: String,
+    pub value: Option<f64>,
+    pub source: String,
+    pub quarantined: bool,
+    /// Not stored: a window compact mode skipped, carrying the stored value before it.
+    pub filled: bool,
+}
+
+/// Blocking: node rows of `gh_id` (one node, or all) in `[from_ms, to_ms)`, oldest first, at most `limit`,
+/// with compacted windows filled in up to each node's newest stored window. Node rows are never revised,
+/// so `q.revisions` does not apply.
+pub fn load_node_history(
+    db_path: &str, gh_id: u16, node_id: Option<u16>, from_ms: i64, to_ms: i64, q: &HistoryQuery,
+) -> rusqlite::Result<Vec<NodeHistoryRow>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT n.node_id, nv.ts_ms, st.key, st.unit, nv.agg, {NV_VALUE_SQL}, nv.window_sec, nv.source, nv.quarantined
+         FROM node_values nv JOIN sensor_type st ON st.id = nv.sensor_type_id JOIN node_name n ON n.id = nv.node_id
+         WHERE n.greenhouse_id = ?1 AND (?2 IS NULL OR n.node_id = ?2) AND nv.ts_ms >= ?3 AND nv.ts_ms < ?4
+           AND (?5 IS NULL OR nv.source = ?5)
+         ORDER BY n.node_id, st.key, nv.agg, nv.ts_ms",
+    ))?;
+    let rows = stmt.query_map(params![gh_id, node_id, from_ms, to_ms, q.source.map(Provenance::as_str)], |r| Ok((
+        r.get::<_, i64>(6)? * 1000,
+        NodeHistoryRow {
+            node_id: r.get(0)?, ts_ms: r.get(1)?, sensor: r.get(2)?, unit: r.get(3)?, agg: r.get(4)?, value: r.get(5)?,
+            source: r.get(7)?, quarantined: r.get::<_, i64>(8)? != 0, filled: false,
+        },
+    )))?;
+
+    // one series per (node, sensor, agg); fill runs to the node's newest window, not into the future
+    let mut series: Vec<(i64, Vec<NodeHistoryRow>)> = Vec::new();
+    let mut newest: HashMap<u16, i64> = HashMap::new();
+    for row in rows {
+        let (step_ms, row) = row?;
+        let n = newest.entry(row.node_id).or_insert(row.ts_ms);
+        *n = (*n).max(row.ts_ms);
+        if !q.sensors.is_empty() && !q.sensors.contains(&row.sensor) { continue; }
+        if !q.aggs.is_empty() && !q.aggs.contains(&row.agg) { continue; }
+        match series.last_mut() {
+            Some((_, s)) if s[0].node_id == row.node_id && s[0].sensor == row.sensor && s[0].agg == row.agg => s.push(row),
+            _ => series.push((step_ms, vec![row])),
+        }
+    }
+
+    let mut out = Vec::new();
+    for (step_ms, rows) in series {
+        if !compacted(&rows[0].sensor, &rows[0].agg) {
+            out.extend(rows);
+            continue;
+        }
+        let points: Vec<_> = rows.iter().map(|r| (r.ts_ms, r.value)).collect();
+        let until = newest[&rows[0].node_id].min(to_ms - 1);
+        let mut stored = rows.into_iter();
+        let mut last = None;
+        for (ts_ms, value, filled) in forward_fill(&points, step_ms, until) {
+            if !filled { last = stored.next(); }
+            if let Some(from) = &last {
+                out.push(NodeHistoryRow { ts_ms, value, filled, ..from.clone() });
+            }
+        }
+    }
+    out.sort_by(|a, b| (a.ts_ms, a.node_id, &a.sensor, &a.agg).cmp(&(b.ts_ms, b.node_id, &b.sensor, &b.agg)));
+    out.truncate(q.limit.unwrap_or(DEFAULT_LIMIT) as usize);
+    Ok(out)
+}
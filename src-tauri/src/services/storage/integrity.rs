@@ -0,0 +1,241 @@
+//! Database sanity audit (`audit_database`): the checks we used to run by hand before support sessions.
+//! - Each finding has a severity and a suggested remediation; `fixable` ones can be repaired in place.
+//! - Fix mode only touches the safe subset: empty sensor units backfilled from the registry, missing
+//!   indexes recreated. Everything else is reported, never changed.
+//! - Quick mode (run at startup) skips the table scans: schema version, units and indexes only.
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::sqlite::{open_db, schema_version};
+use crate::services::mqtt::greenhouse_sensor::derived::sensor_units;
+
+/// Indexes the code expects, with the statement that creates each.
+const EXPECTED_INDEXES: &[(&str, &str)] = &[
+    ("idx_node_values_ts", "CREATE INDEX IF NOT EXISTS idx_node_values_ts ON node_values(ts_ms)"),
+    ("idx_ghavg_ts", "CREATE INDEX IF NOT EXISTS idx_ghavg_ts ON greenhouse_average(ts_ms)"),
+    ("idx_clock_adjustment_range", "CREATE INDEX IF NOT EXISTS idx_clock_adjustment_range ON clock_adjustment(from_ms, to_ms)"),
+    ("idx_command_audit_ts", "CREATE INDEX IF NOT EXISTS idx_command_audit_ts ON command_audit(ts_ms)"),
+    ("idx_export_history_ts", "CREATE INDEX IF NOT EXISTS idx_export_history_ts ON export_history(ts_ms)"),
+    ("idx_maintenance_window_node", "CREATE INDEX IF NOT EXISTS idx_maintenance_window_node ON maintenance_window(greenhouse_id, node_id, started_ms)"),
+];
+
+const EARLIEST_PLAUSIBLE_MS: i64 = 1_577_836_800_000; // 2020-01-01, before the first install
+const FUTURE_SLACK_MS: i64 = 10 * 60_000;
+const RATE_SPAN_MS: i64 = 3_600_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub severity: Severity,
+    pub detail: String,
+    pub remediation: String,
+    pub fixable: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditReport {
+    pub ts_ms: i64,
+    pub quick: bool,
+    pub schema_version: i64,
+    pub code_schema_version: usize,
+    pub findings: Vec<Finding>, // still present after any fixes
+    pub fixed: Vec<String>,
+}
+
+impl AuditReport {
+    /// Worst severity among the findings, if any.
+    pub fn worst(&self) -> Option<Severity> {
+        self.findings.iter().map(|f| f.severity).max()
+    }
+}
+
+struct Audit<'a> {
+    conn: &'a Connection,
+    fix: bool,
+    findings: Vec<Finding>,
+    fixed: Vec<String>,
+}
+
+impl Audit<'_> {
+    fn push(&mut self, check: &'static str, severity: Severity, detail: String, remediation: &str) {
+        self.findings.push(Finding { check, severity, detail, remediation: remediation.into(), fixable: false });
+    }
+
+    /// Apply `sql` in fix mode, otherwise report it as a fixable finding.
+    fn fixable(&mut self, check: &'static str, severity: Severity, detail: String, remediation: &str,
+               sql: &str, p: impl rusqlite::Params) -> rusqlite::Result<()> {
+        if self.fix {
+            self.conn.execute(sql, p)?;
+            self.fixed.push(detail);
+        } else {
+            self.findings.push(Finding { check, severity, detail, remediation: remediation.into(), fixable: true });
+        }
+        Ok(())
+    }
+
+    fn count(&self, sql: &str, p: impl rusqlite::Params) -> rusqlite::Result<i64> {
+        self.conn.query_row(sql, p, |r| r.get(0))
+    }
+
+    fn schema(&mut self, version: i64) {
+        let code = schema_version() as i64;
+        if version > code {
+            self.push("schema_version", Severity::Error,
+                format!("database is at schema {version}, this build knows {code}"),
+                "a newer build wrote this database; run that build or restore a matching backup");
+        } else if version < code {
+            self.push("schema_version", Severity::Error,
+                format!("database is at schema {version}, expected {code} after migrations"),
+                "a migration failed; check the [DB] log lines from startup");
+        }
+    }
+
+    fn units(&mut self) -> rusqlite::Result<()> {
+        let registry: HashMap<&str, &str> = sensor_units().into_iter().collect();
+        let rows: Vec<(String, String)> = {
+            let mut stmt = self.conn.prepare("SELECT key, unit FROM sensor_type ORDER BY key")?;
+            let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        for (key, unit) in rows {
+            match registry.get(key.as_str()) {
+                // unitless sensors (PAR, weight) are stored with an empty unit on purpose
+                Some(&want) if unit.trim().is_empty() && !want.is_empty() => {
+                    self.fixable("sensor_units", Severity::Warning,
+                        format!("sensor '{key}' has no unit (registry: '{want}')"),
+                        "backfill the unit from the registry (fix mode)",
+                        "UPDATE sensor_type SET unit = ?2 WHERE key = ?1", params![key, want])?;
+                }
+                Some(&want) if unit != want => self.push("sensor_units", Severity::Warning,
+                    format!("sensor '{key}' is stored as '{unit}', registry says '{want}'"),
+                    "check which build wrote it; values may be in a different unit"),
+                Some(_) => {}
+                None => self.push("sensor_units", Severity::Info,
+                    format!("sensor '{key}' is not produced by this build"),
+                    "expected for removed derived metrics; history stays readable"),
+            }
+        }
+        Ok(())
+    }
+
+    fn indexes(&mut self) -> rusqlite::Result<()> {
+        for (name, sql) in EXPECTED_INDEXES {
+            let present = self.count("SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name = ?1", params![name])?;
+            if present == 0 {
+                self.fixable("indexes", Severity::Warning, format!("index {name} is missing"),
+                    "recreate the index (fix mode); history queries are slow without it", sql, [])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn references(&mut self) -> rusqlite::Result<()> {
+        let missing_nodes = self.count(
+            "SELECT COUNT(*) FROM node_values nv LEFT JOIN node_name n ON n.id = nv.node_id WHERE n.id IS NULL", [])?;
+        if missing_nodes > 0 {
+            self.push("references", Severity::Error, format!("{missing_nodes} node_values row(s) reference missing nodes"),
+                "restore the node_name rows from a backup, or delete the rows after exporting them");
+        }
+        let missing_sensors = self.count(
+            "SELECT (SELECT COUNT(*) FROM node_values v LEFT JOIN sensor_type s ON s.id = v.sensor_type_id WHERE s.id IS NULL)
+                  + (SELECT COUNT(*) FROM greenhouse_average v LEFT JOIN sensor_type s ON s.id = v.sensor_type_id WHERE s.id IS NULL)", [])?;
+        if missing_sensors > 0 {
+            self.push("references", Severity::Error, format!("{missing_sensors} row(s) reference missing sensor types"),
+                "restore sensor_type from a backup; these rows cannot be labelled");
+        }
+        let orphans = self.count(
+            "SELECT COUNT(*) FROM greenhouse_id g
+             WHERE NOT EXISTS (SELECT 1 FROM node_name n WHERE n.greenhouse_id = g.id)
+               AND NOT EXISTS (SELECT 1 FROM greenhouse_average a WHERE a.greenhouse_id = g.id)", [])?;
+        if orphans > 0 {
+            self.push("orphaned_greenhouses", Severity::Info, format!("{orphans} greenhouse id(s) have no nodes and no data"),
+                "usually a test frame with a wrong greenhouse id; safe to ignore or delete");
+        }
+        Ok(())
+    }
+
+    fn timestamps(&mut self, now: i64) -> rusqlite::Result<()> {
+        for table in ["node_values", "greenhouse_average"] {
+            let bad = self.count(
+                &format!("SELECT COUNT(*) FROM {table} WHERE ts_ms < ?1 OR ts_ms > ?2"),
+                params![EARLIEST_PLAUSIBLE_MS, now + FUTURE_SLACK_MS],
+            )?;
+            if bad > 0 {
+                self.push("timestamps", Severity::Warning, format!("{bad} {table} row(s) are stamped before 2020 or in the future"),
+                    "the PC clock was wrong when they were written; compare with get_clock_adjustments");
+            }
+        }
+        let inverted = self.count("SELECT COUNT(*) FROM node_values WHERE window_start_ms > ts_ms", [])?;
+        if inverted > 0 {
+            self.push("timestamps", Severity::Warning, format!("{inverted} node_values row(s) start their window after their timestamp"),
+                "the clock stepped backwards mid-window; treat those windows as approximate");
+        }
+        Ok(())
+    }
+
+    /// Windows stored per active node over the last hour vs what its window length allows.
+    fn rates(&mut self, now: i64) -> rusqlite::Result<()> {
+        let since = now - RATE_SPAN_MS;
+        if self.count("SELECT COUNT(*) FROM node_values WHERE ts_ms >= ?1", params![since])? == 0 {
+            self.push("rates", Severity::Info, "no node rows in the last hour".into(),
+                "nothing to compare; the pipeline was not running or no node is reporting");
+            return Ok(());
+        }
+        let rows: Vec<(u16, u16, i64, i64)> = {
+            let mut stmt = self.conn.prepare(
+                "SELECT n.greenhouse_id, n.node_id, COUNT(DISTINCT nv.window_start_ms), COALESCE(MAX(nv.window_sec), 60)
+                 FROM node_name n
+                 LEFT JOIN node_values nv ON nv.node_id = n.id AND nv.agg LIKE 'rolling_%' AND nv.ts_ms >= ?1
+                 WHERE n.active = 1 AND n.merged_into IS NULL
+                 GROUP BY n.id ORDER BY n.greenhouse_id, n.node_id",
+            )?;
+            let rows = stmt.query_map(params![since], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        for (gh, node, windows, window_sec) in rows {
+            let expected = RATE_SPAN_MS / 1000 / window_sec.max(1);
+            if windows == 0 {
+                self.push("rates", Severity::Info, format!("GH {gh} node {node} stored nothing in the last hour"),
+                    "check the node, or deactivate_node if it was removed");
+            } else if windows * 2 > expected * 3 {
+                self.push("rates", Severity::Warning,
+                    format!("GH {gh} node {node} stored {windows} windows in the last hour, expected about {expected}"),
+                    "two nodes may share this id, or two app instances write the same database");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+/// Blocking: run the checks (`quick` skips table scans); with `fix`, repair the safe subset first.
+pub fn audit_database(db_path: &str, quick: bool, fix: bool) -> rusqlite::Result<AuditReport> {
+    let conn = open_db(db_path)?;
+    let now = now_ms();
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+    let mut a = Audit { conn: &conn, fix, findings: Vec::new(), fixed: Vec::new() };
+    a.schema(version);
+    a.units()?;
+    a.indexes()?;
+    if !quick {
+        a.references()?;
+        a.timestamps(now)?;
+        a.rates(now)?;
+    }
+    let (findings, fixed) = (a.findings, a.fixed);
+    Ok(AuditReport { ts_ms: now, quick, schema_version: version, code_schema_version: schema_version(), findings, fixed })
+}
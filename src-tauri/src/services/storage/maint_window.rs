@@ -0,0 +1,111 @@
+//! Per-node maintenance windows (`maintenance_window`).
+//! - At most one open window per node; opening twice keeps the first window (and its note).
+//! - Readings inside a window are stored as usual; the windows tell history views which ones to distrust.
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+
+use super::sqlite::open_db;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceWindow {
+    pub id: i64,
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+    pub started_ms: i64,
+    pub ended_ms: Option<i64>, // None while open
+    pub note: String,
+    pub auto_closed: bool,     // closed by the max-duration watchdog, not by a person
+    pub reminded_ms: Option<i64>,
+}
+
+impl MaintenanceWindow {
+    /// True when the window overlaps an aggregation window starting at `from_ms`.
+    pub fn covers(&self, from_ms: i64) -> bool {
+        self.ended_ms.is_none_or(|end| end > from_ms)
+    }
+}
+
+const COLUMNS: &str = "id, greenhouse_id, node_id, started_ms, ended_ms, note, auto_closed, reminded_ms";
+
+fn read_window(r: &Row) -> rusqlite::Result<MaintenanceWindow> {
+    Ok(MaintenanceWindow {
+        id: r.get(0)?,
+        greenhouse_id: r.get(1)?,
+        node_id: r.get(2)?,
+        started_ms: r.get(3)?,
+        ended_ms: r.get(4)?,
+        note: r.get(5)?,
+        auto_closed: r.get::<_, i64>(6)? != 0,
+        reminded_ms: r.get(7)?,
+    })
+}
+
+fn open_window_for(conn: &Connection, gh_id: u16, node_id: u16) -> rusqlite::Result<Option<MaintenanceWindow>> {
+    conn.query_row(
+        &format!("SELECT {COLUMNS} FROM maintenance_window WHERE greenhouse_id=?1 AND node_id=?2 AND ended_ms IS NULL"),
+        params![gh_id, node_id],
+        read_window,
+    ).optional()
+}
+
+/// Blocking: open a window for the node (or return the one already open).
+pub fn start_maintenance(db_path: &str, gh_id: u16, node_id: u16, note: &str, now_ms: i64) -> rusqlite::Result<MaintenanceWindow> {
+    let conn = open_db(db_path)?;
+    if let Some(w) = open_window_for(&conn, gh_id, node_id)? { return Ok(w); }
+    conn.execute(
+        "INSERT INTO maintenance_window(greenhouse_id, node_id, started_ms, note) VALUES (?1, ?2, ?3, ?4)",
+        params![gh_id, node_id, now_ms, note],
+    )?;
+    Ok(MaintenanceWindow {
+        id: conn.last_insert_rowid(),
+        greenhouse_id: gh_id,
+        node_id,
+        started_ms: now_ms,
+        ended_ms: None,
+        note: note.to_string(),
+        auto_closed: false,
+        reminded_ms: None,
+    })
+}
+
+/// Blocking: close the node's open window; None when there was none. A non-empty `note` is appended.
+pub fn end_maintenance(db_path: &str, gh_id: u16, node_id: u16, note: &str, now_ms: i64, auto: bool) -> rusqlite::Result<Option<MaintenanceWindow>> {
+    let conn = open_db(db_path)?;
+    let Some(mut w) = open_window_for(&conn, gh_id, node_id)? else { return Ok(None) };
+    if !note.is_empty() {
+        w.note = if w.note.is_empty() { note.to_string() } else { format!("{}; {note}", w.note) };
+    }
+    conn.execute(
+        "UPDATE maintenance_window SET ended_ms=?2, note=?3, auto_closed=?4 WHERE id=?1",
+        params![w.id, now_ms, w.note, auto as i64],
+    )?;
+    w.ended_ms = Some(now_ms);
+    w.auto_closed = auto;
+    Ok(Some(w))
+}
+
+/// Blocking: remember that the "still in maintenance" reminder went out.
+pub fn mark_reminded(db_path: &str, id: i64, now_ms: i64) -> rusqlite::Result<()> {
+    open_db(db_path)?.execute("UPDATE maintenance_window SET reminded_ms=?2 WHERE id=?1", params![id, now_ms])?;
+    Ok(())
+}
+
+/// Blocking: every window still open (reloaded at startup).
+pub fn load_open_windows(db_path: &str) -> rusqlite::Result<Vec<MaintenanceWindow>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(&format!("SELECT {COLUMNS} FROM maintenance_window WHERE ended_ms IS NULL"))?;
+    let rows = stmt.query_map([], read_window)?;
+    rows.collect()
+}
+
+/// Blocking: windows of one greenhouse overlapping `[from_ms, to_ms)`, oldest first.
+pub fn load_windows(conn: &Connection, gh_id: u16, from_ms: i64, to_ms: i64) -> rusqlite::Result<Vec<MaintenanceWindow>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {COLUMNS} FROM maintenance_window
+         WHERE greenhouse_id=?1 AND started_ms < ?3 AND (ended_ms IS NULL OR ended_ms > ?2)
+         ORDER BY started_ms"
+    ))?;
+    let rows = stmt.query_map(params![gh_id, from_ms, to_ms], read_window)?;
+    rows.collect()
+}
@@ -0,0 +1,134 @@
+//! Node maintenance operations on the stored history.
+//! - `merge_nodes`: re-point a duplicated node's `node_values` rows to the surviving node.
+//! - `deactivate_node`: hide a node without deleting its history.
+//! - Both run in one transaction; `dry_run` executes the same statements and rolls back,
+//!   so the preview counts are exactly what a real run would do.
+//! - `backup_db`: consistent online copy (`VACUUM INTO`) under `<db dir>/backups/`.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use super::sqlite::{absolute_path, open_db};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeReport {
+    pub dry_run: bool,
+    pub greenhouse_id: u16,
+    pub from_node_id: u16,
+    pub into_node_id: u16,
+    pub moved_rows: usize,
+    /// Source rows left in place because the target already had a value for that (sensor, agg, window).
+    pub skipped_conflicts: usize,
+    pub skipped_first_ts_ms: Option<i64>,
+    pub skipped_last_ts_ms: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeactivateReport {
+    pub dry_run: bool,
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+    pub was_active: bool,
+    pub kept_rows: usize,
+}
+
+/// (rowid, active, merged_into) of a node_name row.
+fn node_row(conn: &Connection, gh_id: u16, node_id: u16) -> Result<(i64, bool, Option<i64>), String> {
+    conn.query_row(
+        "SELECT id, active, merged_into FROM node_name WHERE greenhouse_id=?1 AND node_id=?2",
+        params![gh_id, node_id],
+        |r| Ok((r.get::<_, i64>(0)?, r.get::<_, i64>(1)? != 0, r.get::<_, Option<i64>>(2)?)),
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("node {node_id} not found in greenhouse {gh_id}"))
+}
+
+/// Blocking: move `from_node_id`'s history onto `into_node_id` and mark the source as merged.
+/// On overlapping timestamps the target's rows win; the source rows stay behind and are reported.
+pub fn merge_nodes(
+    db_path: &str,
+    gh_id: u16,
+    from_node_id: u16,
+    into_node_id: u16,
+    dry_run: bool,
+) -> Result<MergeReport, String> {
+    if from_node_id == into_node_id {
+        return Err("cannot merge a node into itself".into());
+    }
+    let conn = open_db(db_path).map_err(|e| e.to_string())?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+
+    let (from_row, _, from_merged) = node_row(&tx, gh_id, from_node_id)?;
+    let (into_row, _, into_merged) = node_row(&tx, gh_id, into_node_id)?;
+    if from_merged.is_some() {
+        return Err(format!("node {from_node_id} is already merged"));
+    }
+    if into_merged.is_some() {
+        return Err(format!("node {into_node_id} is merged itself; merge into its target instead"));
+    }
+
+    let moved_rows = tx.execute(
+        "UPDATE OR IGNORE node_values SET node_id=?2 WHERE node_id=?1",
+        params![from_row, into_row],
+    ).map_err(|e| e.to_string())?;
+    let (skipped_conflicts, skipped_first_ts_ms, skipped_last_ts_ms) = tx.query_row(
+        "SELECT COUNT(*), MIN(ts_ms), MAX(ts_ms) FROM node_values WHERE node_id=?1",
+        params![from_row],
+        |r| Ok((r.get::<_, i64>(0)? as usize, r.get::<_, Option<i64>>(1)?, r.get::<_, Option<i64>>(2)?)),
+    ).map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE node_name SET merged_into=?2, active=0 WHERE id=?1",
+        params![from_row, into_row],
+    ).map_err(|e| e.to_string())?;
+
+    if dry_run {
+        tx.rollback().map_err(|e| e.to_string())?;
+    } else {
+        tx.commit().map_err(|e| e.to_string())?;
+        warn!(target: "DB", "merged GH:{gh_id} node {from_node_id} -> {into_node_id} (moved {moved_rows}, skipped {skipped_conflicts})");
+    }
+
+    Ok(MergeReport {
+        dry_run, greenhouse_id: gh_id, from_node_id, into_node_id,
+        moved_rows, skipped_conflicts, skipped_first_ts_ms, skipped_last_ts_ms,
+    })
+}
+
+/// Blocking: mark a node inactive. Its rows stay untouched and new data is still stored.
+pub fn deactivate_node(db_path: &str, gh_id: u16, node_id: u16, dry_run: bool) -> Result<DeactivateReport, String> {
+    let conn = open_db(db_path).map_err(|e| e.to_string())?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+
+    let (row, was_active, _) = node_row(&tx, gh_id, node_id)?;
+    let kept_rows = tx.query_row(
+        "SELECT COUNT(*) FROM node_values WHERE node_id=?1",
+        params![row],
+        |r| r.get::<_, i64>(0),
+    ).map_err(|e| e.to_string())? as usize;
+    tx.execute("UPDATE node_name SET active=0 WHERE id=?1", params![row]).map_err(|e| e.to_string())?;
+
+    if dry_run {
+        tx.rollback().map_err(|e| e.to_string())?;
+    } else {
+        tx.commit().map_err(|e| e.to_string())?;
+        info!(target: "DB", "deactivated GH:{gh_id} node {node_id} ({kept_rows} rows kept)");
+    }
+
+    Ok(DeactivateReport { dry_run, greenhouse_id: gh_id, node_id, was_active, kept_rows })
+}
+
+/// Blocking: write a consistent copy of the DB to `<db dir>/backups/<stem>-<ts_ms>.db`; returns its path.
+pub fn backup_db(db_path: &str, ts_ms: i64) -> Result<String, String> {
+    let abs = absolute_path(db_path);
+    let dir = abs.parent().map(|d| d.join("backups")).ok_or("database path has no parent directory")?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("could not create {}: {e}", dir.display()))?;
+    let stem = abs.file_stem().and_then(|s| s.to_str()).unwrap_or("app");
+    let target = dir.join(format!("{stem}-{ts_ms}.db"));
+    let target = target.to_str().ok_or("backup path is not valid UTF-8")?.to_string();
+    open_db(db_path)
+        .and_then(|conn| conn.execute("VACUUM INTO ?1", params![target]))
+        .map_err(|e| format!("backup failed: {e}"))?;
+    Ok(target)
+}
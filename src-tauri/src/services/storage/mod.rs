@@ -1 +1,20 @@
 pub mod sqlite;
+pub mod maintenance;
+pub mod node_meta;
+pub mod compact;
+pub mod config;
+pub mod dry_run;
+pub mod audit;
+pub mod daily;
+pub mod export;
+pub mod maint_window;
+pub mod integrity;
+pub mod commissioning;
+pub mod scaled;
+pub mod origin_map;
+pub mod rebuild;
+pub mod history;
+pub mod drainage;
+pub mod health;
+pub mod node_schema;
+pub mod node_status;
@@ -0,0 +1,104 @@
+//! Node metadata stored alongside `node_name` (display preferences, roster view).
+//! - Display prefs live server-side so they survive reinstalls and are shared by all windows.
+//! - Hidden nodes are still stored and processed; the flag only affects default charting.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use super::sqlite::open_db;
+use crate::services::node_health::NodeHealth;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeDisplayPrefs {
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+    pub color: Option<String>, // "#rrggbb"
+    pub sort_order: Option<i64>,
+    pub icon: Option<String>,
+    pub hidden: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeInfo {
+    pub greenhouse_id: u16,
+    pub node_id: u16,
+    pub label: String,
+    pub active: bool,
+    pub merged_into_node_id: Option<u16>,
+    pub display: NodeDisplayPrefs,
+    /// Latest health score; filled in by the `list_nodes` command (None until the node was scored).
+    pub health: Option<NodeHealth>,
+}
+
+fn valid_color(c: &str) -> bool {
+    c.len() == 7 && c.starts_with('#') && c[1..].chars().all(|ch| ch.is_ascii_hexdigit())
+}
+
+fn read_prefs(conn: &Connection) -> rusqlite::Result<Vec<NodeDisplayPrefs>> {
+    let mut stmt = conn.prepare(
+        "SELECT greenhouse_id, node_id, color, sort_order, icon, hidden FROM node_name
+         ORDER BY greenhouse_id, COALESCE(sort_order, node_id)",
+    )?;
+    let rows = stmt.query_map([], |r| Ok(NodeDisplayPrefs {
+        greenhouse_id: r.get(0)?,
+        node_id: r.get(1)?,
+        color: r.get(2)?,
+        sort_order: r.get(3)?,
+        icon: r.get(4)?,
+        hidden: r.get::<_, i64>(5)? != 0,
+    }))?;
+    rows.collect()
+}
+
+/// Blocking: display prefs for every known node, in display order.
+pub fn get_display_prefs(db_path: &str) -> rusqlite::Result<Vec<NodeDisplayPrefs>> {
+    read_prefs(&open_db(db_path)?)
+}
+
+/// Blocking: replace one node's display prefs; returns the full updated list.
+pub fn set_display_prefs(db_path: &str, p: &NodeDisplayPrefs) -> Result<Vec<NodeDisplayPrefs>, String> {
+    if let Some(c) = &p.color {
+        if !valid_color(c) { return Err(format!("invalid color '{c}', expected #rrggbb")); }
+    }
+    let conn = open_db(db_path).map_err(|e| e.to_string())?;
+    let changed = conn.execute(
+        "UPDATE node_name SET color=?3, sort_order=?4, icon=?5, hidden=?6
+         WHERE greenhouse_id=?1 AND node_id=?2",
+        params![p.greenhouse_id, p.node_id, p.color, p.sort_order, p.icon, p.hidden as i64],
+    ).map_err(|e| e.to_string())?;
+    if changed == 0 {
+        return Err(format!("node {} not found in greenhouse {}", p.node_id, p.greenhouse_id));
+    }
+    read_prefs(&conn).map_err(|e| e.to_string())
+}
+
+/// Blocking: every node row with its status and display prefs.
+pub fn list_nodes(db_path: &str) -> rusqlite::Result<Vec<NodeInfo>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(
+        "SELECT n.greenhouse_id, n.node_id, n.label, n.active, m.node_id,
+                n.color, n.sort_order, n.icon, n.hidden
+         FROM node_name n LEFT JOIN node_name m ON m.id = n.merged_into
+         ORDER BY n.greenhouse_id, COALESCE(n.sort_order, n.node_id)",
+    )?;
+    let rows = stmt.query_map([], |r| {
+        let (greenhouse_id, node_id): (u16, u16) = (r.get(0)?, r.get(1)?);
+        Ok(NodeInfo {
+            greenhouse_id,
+            node_id,
+            label: r.get(2)?,
+            active: r.get::<_, i64>(3)? != 0,
+            merged_into_node_id: r.get(4)?,
+            display: NodeDisplayPrefs {
+                greenhouse_id,
+                node_id,
+                color: r.get(5)?,
+                sort_order: r.get(6)?,
+                icon: r.get(7)?,
+                hidden: r.get::<_, i64>(8)? != 0,
+            },
+            health: None,
+        })
+    })?;
+    rows.collect()
+}
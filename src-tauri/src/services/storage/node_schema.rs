@@ -0,0 +1,65 @@
+//! Declared frame layouts per node id range (`node_schema`), read into the schema registry (schema.rs).
+//! - `greenhouse_id` NULL applies the range in every greenhouse; ranges are inclusive.
+//! - One row per (greenhouse_id, node_from, node_to); setting the same range again replaces its layout.
+
+use rusqlite::params;
+use serde::Serialize;
+use tracing::warn;
+
+use super::sqlite::open_db;
+use crate::services::mqtt::greenhouse_sensor::decoder::FrameLayout;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeSchemaEntry {
+    pub greenhouse_id: Option<u16>,
+    pub node_from: u16,
+    pub node_to: u16,
+    pub layout: FrameLayout,
+    pub updated_ms: i64,
+}
+
+/// Blocking: every declared range, by greenhouse (any first) then range start.
+pub fn load_node_schema(db_path: &str) -> rusqlite::Result<Vec<NodeSchemaEntry>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(
+        "SELECT greenhouse_id, node_from, node_to, layout, updated_ms FROM node_schema ORDER BY greenhouse_id, node_from, node_to",
+    )?;
+    let rows = stmt.query_map([], |r| Ok((
+        r.get::<_, Option<u16>>(0)?, r.get::<_, u16>(1)?, r.get::<_, u16>(2)?, r.get::<_, String>(3)?, r.get::<_, i64>(4)?,
+    )))?;
+    let mut out = Vec::new();
+    for row in rows {
+        let (greenhouse_id, node_from, node_to, layout, updated_ms) = row?;
+        match FrameLayout::parse(&layout) {
+            Some(layout) => out.push(NodeSchemaEntry { greenhouse_id, node_from, node_to, layout, updated_ms }),
+            None => warn!(target: "DB", "node_schema {node_from}..={node_to}: unknown layout '{layout}', ignored"),
+        }
+    }
+    Ok(out)
+}
+
+/// Blocking: declare (`Some`) or remove (`None`) the layout of one node id range; returns the full updated list.
+pub fn set_node_schema(
+    db_path: &str,
+    gh_id: Option<u16>,
+    node_from: u16,
+    node_to: u16,
+    layout: Option<FrameLayout>,
+    now_ms: i64,
+) -> rusqlite::Result<Vec<NodeSchemaEntry>> {
+    let conn = open_db(db_path)?;
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "DELETE FROM node_schema WHERE greenhouse_id IS ?1 AND node_from = ?2 AND node_to = ?3",
+        params![gh_id, node_from, node_to],
+    )?;
+    if let Some(l) = layout {
+        tx.execute(
+            "INSERT INTO node_schema(greenhouse_id, node_from, node_to, layout, updated_ms) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![gh_id, node_from, node_to, l.as_str(), now_ms],
+        )?;
+    }
+    tx.commit()?;
+    drop(conn);
+    load_node_schema(db_path)
+}
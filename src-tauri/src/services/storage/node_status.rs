@@ -0,0 +1,31 @@
+//! Node availability changes (`node_status_log`), one row per change reported by availability.rs or
+//! liveness.rs.
+//! - `retained` rows were learnt from a retained status on connect: the node changed state earlier than `ts_ms`.
+//! - Rows with `last_seen_ms` come from the samples (`online` / `late` / `offline`), the others from the
+//!   nodes' status topics.
+
+use rusqlite::params;
+
+use super::sqlite::open_db;
+use crate::services::mqtt::greenhouse_sensor::availability::NodeAvailability;
+use crate::services::mqtt::greenhouse_sensor::liveness::NodeStatus;
+
+/// Blocking: append one availability change.
+pub fn insert_node_status(db_path: &str, a: &NodeAvailability) -> rusqlite::Result<()> {
+    let conn = open_db(db_path)?;
+    conn.execute(
+        "INSERT INTO node_status_log(ts_ms, greenhouse_id, node_id, status, retained) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![a.since_ms, a.greenhouse_id, a.node_id, a.status.as_str(), a.retained],
+    )?;
+    Ok(())
+}
+
+/// Blocking: append one change seen from the samples.
+pub fn insert_node_liveness(db_path: &str, s: &NodeStatus) -> rusqlite::Result<()> {
+    let conn = open_db(db_path)?;
+    conn.execute(
+        "INSERT INTO node_status_log(ts_ms, greenhouse_id, node_id, status, last_seen_ms) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![s.ts_ms, s.greenhouse_id, s.node_id, s.state.as_str(), s.last_seen_ms],
+    )?;
+    Ok(())
+}
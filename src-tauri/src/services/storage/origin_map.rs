@@ -0,0 +1,45 @@
+//! Origin mappings (`gh_origin_map`): which greenhouse id frames from one topic origin are stored under.
+//! - Origin "" is the local broker; bridged sites are named by their topic prefix (see origin.rs).
+
+use rusqlite::params;
+use serde::Serialize;
+
+use super::sqlite::open_db;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OriginMapping {
+    pub origin: String,
+    pub greenhouse_id: u16,
+    pub effective_gh_id: u16,
+    pub updated_ms: i64,
+}
+
+/// Blocking: every mapping, by origin then greenhouse id.
+pub fn load_origin_map(db_path: &str) -> rusqlite::Result<Vec<OriginMapping>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(
+        "SELECT origin, greenhouse_id, effective_gh_id, updated_ms FROM gh_origin_map ORDER BY origin, greenhouse_id",
+    )?;
+    let rows = stmt.query_map([], |r| Ok(OriginMapping {
+        origin: r.get(0)?,
+        greenhouse_id: r.get(1)?,
+        effective_gh_id: r.get(2)?,
+        updated_ms: r.get(3)?,
+    }))?;
+    rows.collect()
+}
+
+/// Blocking: set (`Some`) or remove (`None`) one mapping; returns the full updated list.
+pub fn set_origin_mapping(db_path: &str, origin: &str, gh_id: u16, effective: Option<u16>, now_ms: i64) -> rusqlite::Result<Vec<OriginMapping>> {
+    let conn = open_db(db_path)?;
+    match effective {
+        Some(eff) => conn.execute(
+            "INSERT INTO gh_origin_map(origin, greenhouse_id, effective_gh_id, updated_ms) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(origin, greenhouse_id) DO UPDATE SET effective_gh_id = excluded.effective_gh_id, updated_ms = excluded.updated_ms",
+            params![origin, gh_id, eff, now_ms],
+        )?,
+        None => conn.execute("DELETE FROM gh_origin_map WHERE origin = ?1 AND greenhouse_id = ?2", params![origin, gh_id])?,
+    };
+    drop(conn);
+    load_origin_map(db_path)
+}
@@ -0,0 +1,422 @@
+//! Recompute stored greenhouse averages from stored node rows (`rebuild_gh_averages`).
+//! - For every existing rolling greenhouse row of the current window (`rolling_60s` by default) in the range, the node windows that were fresh at
+//!   its timestamp are averaged again under today's rules: outdoor stations, soil nodes (no greenhouse
+//!   field of their own) and nodes in maintenance are left out, derived metrics are evaluated on the new means.
+//! - Only existing rows are rewritten (missing windows are not invented); a changed row gets
+//!   `revision + 1`, `revised_ms` and source 'rebuild'. Hourly rows of the touched hours are
+//!   recomputed from the result under the same rule: only those whose value or nodes moved are rewritten.
+//! - One transaction per chunk: cancelling (or a crash) leaves whole chunks rebuilt and the rest as
+//!   they were, so a rerun over the same range finishes the job and changes nothing twice.
+//! - Roster coverage keeps the roster implied by the stored row (nodes / coverage).
+
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+
+use super::history::Provenance;
+use super::maint_window::load_windows;
+use super::scaled::{scaled_from, GH_VALUE_SQL, NV_VALUE_SQL};
+use super::sqlite::{open_db, stored};
+use crate::services::math::{acc_opt, mean, r2};
+use crate::services::mqtt::config::outdoor;
+use crate::services::mqtt::greenhouse_sensor::derived::{evaluate, BASE_SENSORS};
+use crate::services::mqtt::greenhouse_sensor::aggregator::{current_window, rolling_agg};
+use crate::services::mqtt::greenhouse_sensor::greenhouse_aggregator::{stale_grace, Confidence};
+use crate::services::mqtt::greenhouse_sensor::hourly_aggregator::local_hour_start;
+use crate::services::node_maintenance::now_ms;
+
+const HOUR_MS: i64 = 3_600_000;
+const CHANGE_EPS: f64 = 0.005;     // below storage precision
+
+/// Set by `cancel_gh_rebuild`; the running rebuild stops before its next chunk.
+pub type RebuildCancel = Arc<AtomicBool>;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RebuildOptions {
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Hours of greenhouse rows per transaction (default 6).
+    pub chunk_hours: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RebuildProgress {
+    pub greenhouse_id: u16,
+    pub done_to_ms: i64,
+    pub windows_done: usize,
+    pub windows_total: usize,
+    pub rows_changed: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SensorChange {
+    pub rows_changed: usize,
+    pub mean_abs_change: Option<f64>, // over rows that had a value before and after
+    #[serde(skip)]
+    abs_sum: f64,
+    #[serde(skip)]
+    abs_n: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RebuildReport {
+    pub greenhouse_id: u16,
+    pub from_ms: i64,
+    pub to_ms: i64,
+    pub dry_run: bool,
+    pub cancelled: bool,
+    pub done_to_ms: i64,       // rows before this are rebuilt (== to_ms unless cancelled)
+    pub windows: usize,
+    pub rows_examined: usize,
+    pub rows_changed: usize,
+    pub rows_emptied: usize,   // no indoor node left for that window: value set to NULL
+    pub mean_abs_change: Option<f64>,
+    pub by_sensor: BTreeMap<String, SensorChange>,
+    pub hourly_rows_updated: usize,
+}
+
+/// One stored node window: node id, window length and its values by sensor key.
+struct NodeWindow {
+    node_id: u16,
+    ts_ms: i64,
+    window_sec: i64,
+    values: HashMap<String, f64>,
+}
+
+struct GhRow {
+    id: i64,
+    key: String,
+    value: Option<f64>,
+    nodes: i64,
+    coverage: Option<f64>,
+    scale: i64,
+}
+
+/// The aggregation window whose greenhouse rows are rebuilt, with the live aggregator's grace.
+struct Window {
+    agg: String,
+    ms: i64,
+    grace_ms: i64,
+}
+
+impl Window {
+    fn current() -> Self {
+        let w = current_window();
+        Window { agg: rolling_agg(w.as_secs()), ms: w.as_millis() as i64, grace_ms: stale_grace(w).as_millis() as i64 }
+    }
+}
+
+fn is_outdoor(w: &NodeWindow, win: &Window) -> bool {
+    w.window_sec * 1000 > win.ms || outdoor().station_priority.contains(&w.node_id)
+}
+
+fn load_node_windows(conn: &Connection, gh_id: u16, from_ms: i64, to_ms: i64) -> rusqlite::Result<Vec<NodeWindow>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT n.node_id, nv.ts_ms, nv.window_sec, st.key, {NV_VALUE_SQL}
+         FROM node_values nv
+         JOIN node_name n ON n.id = nv.node_id
+         JOIN sensor_type st ON st.id = nv.sensor_type_id
+         WHERE n.greenhouse_id = ?1 AND nv.ts_ms >= ?2 AND nv.ts_ms < ?3 AND nv.agg LIKE 'rolling_%'
+         ORDER BY n.node_id, nv.ts_ms"
+    ))?;
+    let mut rows = stmt.query(params![gh_id, from_ms, to_ms])?;
+    let mut out: Vec<NodeWindow> = Vec::new();
+    while let Some(r) = rows.next()? {
+        let (node_id, ts_ms, window_sec): (u16, i64, i64) = (r.get(0)?, r.get(1)?, r.get(2)?);
+        if out.last().is_none_or(|w| w.node_id != node_id || w.ts_ms != ts_ms) {
+            out.push(NodeWindow { node_id, ts_ms, window_sec, values: HashMap::new() });
+        }
+        if let (Some(w), Some(v)) = (out.last_mut(), r.get::<_, Option<f64>>(4)?) {
+            if v.is_finite() { w.values.insert(r.get(3)?, v); }
+        }
+    }
+    Ok(out)
+}
+
+fn load_gh_rows(conn: &Connection, gh_id: u16, from_ms: i64, to_ms: i64, agg: &str) -> rusqlite::Result<BTreeMap<i64, Vec<GhRow>>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT ga.ts_ms, ga.id, st.key, {GH_VALUE_SQL}, ga.nodes, ga.coverage, st.scale
+         FROM greenhouse_average ga JOIN sensor_type st ON st.id = ga.sensor_type_id
+         WHERE ga.greenhouse_id = ?1 AND ga.agg = ?4 AND ga.ts_ms >= ?2 AND ga.ts_ms < ?3"
+    ))?;
+    let mut rows = stmt.query(params![gh_id, from_ms, to_ms, agg])?;
+    let mut out: BTreeMap<i64, Vec<GhRow>> = BTreeMap::new();
+    while let Some(r) = rows.next()? {
+        out.entry(r.get(0)?).or_default().push(GhRow {
+            id: r.get(1)?, key: r.get(2)?, value: r.get(3)?, nodes: r.get(4)?, coverage: r.get(5)?, scale: r.get(6)?,
+        });
+    }
+    Ok(out)
+}
+
+/// Greenhouse means at `ts` from the freshest indoor, non-maintenance window of each node that has a
+/// greenhouse field (soil windows only carry substrate series and count neither as nodes nor for coverage).
+fn recompute(windows: &[NodeWindow], ts: i64, win: &Window, in_maintenance: &dyn Fn(u16, i64, i64) -> bool) -> (usize, HashMap<&'static str, f32>) {
+    let mut latest: BTreeMap<u16, &NodeWindow> = BTreeMap::new();
+    // rows on the window grid share the timestamp of the node windows they average; older rows were
+    // stamped at flush time, when a node window of the same millisecond had reached the aggregator after it
+    let same_tick = ts % win.ms == 0;
+    for w in windows.iter().filter(|w| (w.ts_ms < ts || same_tick && w.ts_ms == ts) && ts - w.ts_ms <= w.window_sec * 1000 + win.grace_ms) {
+        latest.insert(w.node_id, w); // sorted by node, then ts: the last one wins
+    }
+    let used: Vec<&NodeWindow> = latest.into_values()
+        .filter(|w| !is_outdoor(w, win) && !in_maintenance(w.node_id, w.ts_ms - w.window_sec * 1000, w.ts_ms))
+        .filter(|w| BASE_SENSORS.iter().any(|(key, _)| w.values.contains_key(*key)))
+        .collect();
+    let base: Vec<(&'static str, &'static str, Option<f32>)> = BASE_SENSORS.iter().map(|&(key, unit)| {
+        let (mut sum, mut cnt) = (0.0, 0);
+        for w in &used { acc_opt(w.values.get(key).map(|v| *v as f32), &mut sum, &mut cnt); }
+        (key, unit, mean(sum, cnt))
+    }).collect();
+    let derived = evaluate(&base);
+    let means = base.into_iter().chain(derived.fields()).filter_map(|(k, _, v)| v.map(|v| (k, v))).collect();
+    (used.len(), means)
+}
+
+fn bind(id: i64, scaled_from_id: i64, scale: i64, v: Option<f32>) -> Option<f64> {
+    stored(v, if id >= scaled_from_id { scale } else { 1 })
+}
+
+/// Values that differ by less than storage precision are the same.
+fn changed(old: Option<f64>, new: Option<f64>) -> bool {
+    match (old, new) {
+        (Some(old), Some(new)) => (old - new).abs() > CHANGE_EPS,
+        (old, new) => old.is_some() != new.is_some(),
+    }
+}
+
+/// Recompute hourly / hourly_min / hourly_max for one hour from the rolling rows now stored; rows
+/// whose value and nodes stay as they are keep their revision.
+fn refresh_hour(tx: &Transaction, gh_id: u16, hour: i64, scaled_from_id: i64, agg: &str) -> rusqlite::Result<usize> {
+    // (sensor_type id, scale, [mean, min, max], max nodes)
+    let stats: Vec<(i64, i64, [Option<f64>; 3], i64)> = {
+        let mut stmt = tx.prepare(&format!(
+            "SELECT st.id, st.scale, AVG({GH_VALUE_SQL}), MIN({GH_VALUE_SQL}), MAX({GH_VALUE_SQL}), MAX(ga.nodes)
+             FROM greenhouse_average ga JOIN sensor_type st ON st.id = ga.sensor_type_id
+             WHERE ga.greenhouse_id = ?1 AND ga.agg = ?4 AND ga.ts_ms >= ?2 AND ga.ts_ms < ?3
+             GROUP BY st.id"
+        ))?;
+        let rows = stmt.query_map(params![gh_id, hour, hour + HOUR_MS, agg], |r| Ok((r.get(0)?, r.get(1)?, [r.get(2)?, r.get(3)?, r.get(4)?], r.get(5)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    let mut updated = 0;
+    for (st_id, scale, values, nodes) in stats {
+        for (agg, v) in ["hourly", "hourly_min", "hourly_max"].into_iter().zip(values) {
+            let existing: Option<(i64, Option<f64>, i64)> = tx.query_row(
+                &format!("SELECT ga.id, {GH_VALUE_SQL}, ga.nodes FROM greenhouse_average ga JOIN sensor_type st ON st.id = ga.sensor_type_id
+                          WHERE ga.greenhouse_id = ?1 AND ga.ts_ms = ?2 AND ga.sensor_type_id = ?3 AND ga.agg = ?4"),
+                params![gh_id, hour, st_id, agg], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            ).optional()?;
+            let Some((id, old, old_nodes)) = existing else { continue };
+            let new = r2(v.map(|x| x as f32));
+            if !changed(old, new) && old_nodes == nodes { continue; }
+            updated += tx.execute(
+                "UPDATE greenhouse_average SET value = ?2, nodes = ?3, revision = revision + 1, revised_ms = ?4, source = ?5 WHERE id = ?1",
+                params![id, bind(id, scaled_from_id, scale, new.map(|x| x as f32)), nodes, now_ms(), Provenance::Rebuild.as_str()],
+            )?;
+        }
+    }
+    Ok(updated)
+}
+
+/// Blocking: rebuild `gh_id`'s rows in `[from_ms, to_ms)`; `progress` is called after every chunk,
+/// `cancel` is checked before each one.
+pub fn rebuild_gh_averages(
+    db_path: &str, gh_id: u16, from_ms: i64, to_ms: i64, opts: RebuildOptions,
+    cancel: &AtomicBool, progress: &dyn Fn(&RebuildProgress),
+) -> Result<RebuildReport, String> {
+    if to_ms <= from_ms { return Err("to_ms must be after from_ms".into()); }
+    let mut conn = open_db(db_path).map_err(|e| e.to_string())?;
+    conn.busy_timeout(std::time::Duration::from_secs(5)).map_err(|e| e.to_string())?; // the live writer shares the file
+    let db = |e: rusqlite::Error| e.to_string();
+    let scaled_from_id = scaled_from(&conn, "greenhouse_average").map_err(db)?;
+    let maint = load_windows(&conn, gh_id, from_ms - HOUR_MS, to_ms).map_err(db)?;
+    let in_maintenance = |node_id: u16, start: i64, end: i64| {
+        maint.iter().any(|m| m.node_id == node_id && m.started_ms < end && m.covers(start))
+    };
+    let win = Window::current();
+    let windows_total: usize = conn.query_row(
+        "SELECT COUNT(DISTINCT ts_ms) FROM greenhouse_average WHERE greenhouse_id = ?1 AND agg = ?4 AND ts_ms >= ?2 AND ts_ms < ?3",
+        params![gh_id, from_ms, to_ms, win.agg], |r| r.get::<_, i64>(0),
+    ).map_err(db)? as usize;
+
+    let chunk_ms = opts.chunk_hours.unwrap_or(6).max(1) as i64 * HOUR_MS;
+    let mut report = RebuildReport {
+        greenhouse_id: gh_id, from_ms, to_ms, dry_run: opts.dry_run, cancelled: false, done_to_ms: from_ms,
+        windows: 0, rows_examined: 0, rows_changed: 0, rows_emptied: 0, mean_abs_change: None,
+        by_sensor: BTreeMap::new(), hourly_rows_updated: 0,
+    };
+    let (mut abs_sum, mut abs_n) = (0.0, 0usize);
+    let mut a = from_ms;
+    while a < to_ms {
+        if cancel.load(Ordering::Relaxed) { report.cancelled = true; break; }
+        let b = (a + chunk_ms).min(to_ms);
+        let node_windows = load_node_windows(&conn, gh_id, a - win.ms - win.grace_ms, b).map_err(db)?;
+        let gh_rows = load_gh_rows(&conn, gh_id, a, b, &win.agg).map_err(db)?;
+        let tx = conn.transaction().map_err(db)?;
+        let mut hours = BTreeSet::new();
+        for (ts, rows) in &gh_rows {
+            report.windows += 1;
+            let (n, means) = recompute(&node_windows, *ts, &win, &in_maintenance);
+            for row in rows {
+                report.rows_examined += 1;
+                let new = r2(means.get(row.key.as_str()).copied());
+                if !changed(row.value, new) && row.nodes == n as i64 { continue; }
+                let roster = row.coverage.filter(|c| *c > 0.0).map_or(n, |c| ((row.nodes as f64 / c).round() as usize).max(n)).max(1);
+                let coverage = n as f32 / roster as f32;
+                tx.execute(
+                    "UPDATE greenhouse_average SET value = ?2, nodes = ?3, coverage = ?4, confidence = ?5,
+                            revision = revision + 1, revised_ms = ?6, source = ?7
+                     WHERE id = ?1",
+                    params![row.id, bind(row.id, scaled_from_id, row.scale, new.map(|v| v as f32)), n as i64,
+                            r2(Some(coverage)), Confidence::from_coverage(coverage).as_str(), now_ms(),
+                            Provenance::Rebuild.as_str()],
+                ).map_err(db)?;
+                report.rows_changed += 1;
+                if new.is_none() { report.rows_emptied += 1; }
+                let s = report.by_sensor.entry(row.key.clone()).or_default();
+                s.rows_changed += 1;
+                if let (Some(old), Some(new)) = (row.value, new) {
+                    s.abs_sum += (old - new).abs();
+                    s.abs_n += 1;
+                    abs_sum += (old - new).abs();
+                    abs_n += 1;
+                }
+                hours.insert(local_hour_start(*ts));
+            }
+        }
+        for hour in hours {
+            report.hourly_rows_updated += refresh_hour(&tx, gh_id, hour, scaled_from_id, &win.agg).map_err(db)?;
+        }
+        if opts.dry_run { tx.rollback().map_err(db)?; } else { tx.commit().map_err(db)?; }
+        report.done_to_ms = b;
+        progress(&RebuildProgress {
+            greenhouse_id: gh_id, done_to_ms: b, windows_done: report.windows, windows_total, rows_changed: report.rows_changed,
+        });
+        a = b;
+    }
+    for s in report.by_sensor.values_mut() {
+        s.mean_abs_change = (s.abs_n > 0).then(|| s.abs_sum / s.abs_n as f64);
+    }
+    report.mean_abs_change = (abs_n > 0).then(|| abs_sum / abs_n as f64);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mqtt::greenhouse_sensor::aggregator::NodeAvg;
+    use crate::services::mqtt::greenhouse_sensor::greenhouse_aggregator::GhAvg;
+    use crate::services::mqtt::greenhouse_sensor::hourly_aggregator::{GhHourly, HourStat};
+    use crate::services::storage::sqlite::flush_batch;
+
+    const T0: i64 = 1_760_000_400_000; // on the hour (UTC)
+
+    /// Two hours with one stored window each: greenhouse rows from three nodes at 24 °C, node rows
+    /// of two nodes at 20 / 22 °C, and the hourly rows of both hours.
+    fn seeded(dir: &tempfile::TempDir) -> String {
+        let db = dir.path().join("app.db").to_str().unwrap().to_string();
+        for ts in [T0 + 1_800_000, T0 + 5_400_000] {
+            let hour = GhHourly {
+                hour_start_ms: local_hour_start(ts), greenhouse_id: 1, nodes: 3,
+                fields: [("air_temp_c".to_string(), HourStat { unit: "C".into(), mean: Some(24.0), min: Some(24.0), max: Some(24.0) })].into(),
+            };
+            flush_batch(&db, vec![NodeAvg::sample(1, 1, ts, 20.0), NodeAvg::sample(1, 2, ts, 22.0)],
+                        vec![GhAvg::sample(1, ts)], vec![hour], None, Provenance::Live);
+        }
+        db
+    }
+
+    /// (ts_ms, agg, value, nodes, revision, source) of the greenhouse air temperature rows.
+    fn air_rows(db: &str) -> Vec<(i64, String, Option<f64>, i64, i64, String)> {
+        open_db(db).unwrap()
+            .prepare(&format!("SELECT ga.ts_ms, ga.agg, {GH_VALUE_SQL}, ga.nodes, ga.revision, ga.source
+                               FROM greenhouse_average ga JOIN sensor_type st ON st.id = ga.sensor_type_id
+                               WHERE st.key = 'air_temp_c' ORDER BY ga.ts_ms, ga.agg")).unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?))).unwrap()
+            .map(Result::unwrap).collect()
+    }
+
+    fn rebuild(db: &str, opts: RebuildOptions, cancel: &AtomicBool, progress: &dyn Fn(&RebuildProgress)) -> RebuildReport {
+        rebuild_gh_averages(db, 1, T0, T0 + 2 * HOUR_MS, RebuildOptions { chunk_hours: Some(1), ..opts }, cancel, progress).unwrap()
+    }
+
+    #[test]
+    fn dry_run_reports_the_changes_and_leaves_the_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = seeded(&dir);
+        let before = air_rows(&db);
+        let r = rebuild(&db, RebuildOptions { dry_run: true, ..Default::default() }, &AtomicBool::new(false), &|_| {});
+        assert!(r.dry_run && !r.cancelled);
+        assert_eq!(r.windows, 2);
+        assert!(r.rows_changed > 0 && r.hourly_rows_updated > 0);
+        assert_eq!(r.by_sensor["air_temp_c"].mean_abs_change, Some(3.0));
+        assert_eq!(air_rows(&db), before);
+    }
+
+    #[test]
+    fn cancelling_keeps_the_finished_chunks_and_leaves_the_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = seeded(&dir);
+        let before = air_rows(&db);
+        let cancel = AtomicBool::new(false);
+        let r = rebuild(&db, RebuildOptions::default(), &cancel, &|_| cancel.store(true, Ordering::Relaxed));
+        assert!(r.cancelled);
+        assert_eq!((r.done_to_ms, r.windows), (T0 + HOUR_MS, 1));
+
+        let after = air_rows(&db);
+        let (first, rest): (Vec<_>, Vec<_>) = after.iter().partition(|row| row.0 < T0 + HOUR_MS);
+        let rolling = first.iter().find(|row| row.1 == "rolling_60s").unwrap();
+        assert_eq!((rolling.2, rolling.3, rolling.4, rolling.5.as_str()), (Some(21.0), 2, 1, "rebuild"));
+        let hourly = first.iter().find(|row| row.1 == "hourly").unwrap();
+        assert_eq!((hourly.2, hourly.3, hourly.4), (Some(21.0), 2, 1));
+        let untouched: Vec<_> = before.iter().filter(|row| row.0 >= T0 + HOUR_MS).collect();
+        assert_eq!(rest, untouched);
+    }
+
+    #[test]
+    fn a_rerun_changes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = seeded(&dir);
+        let first = rebuild(&db, RebuildOptions::default(), &AtomicBool::new(false), &|_| {});
+        assert!(first.rows_changed > 0);
+        let rebuilt = air_rows(&db);
+        assert!(rebuilt.iter().all(|row| row.4 == 1), "each row revised once: {rebuilt:?}");
+
+        let again = rebuild(&db, RebuildOptions::default(), &AtomicBool::new(false), &|_| {});
+        assert_eq!((again.windows, again.rows_changed, again.hourly_rows_updated), (2, 0, 0));
+        // hours recomputed without a change keep their revision too
+        let mut conn = open_db(&db).unwrap();
+        let scaled_from_id = scaled_from(&conn, "greenhouse_average").unwrap();
+        let tx = conn.transaction().unwrap();
+        assert_eq!(refresh_hour(&tx, 1, local_hour_start(T0 + 1_800_000), scaled_from_id, "rolling_60s").unwrap(), 0);
+        tx.commit().unwrap();
+        assert_eq!(air_rows(&db), rebuilt);
+    }
+
+    fn win() -> Window {
+        Window { agg: "rolling_60s".into(), ms: 60_000, grace_ms: 2_500 }
+    }
+
+    fn node(node_id: u16, ts_ms: i64, values: &[(&str, f64)]) -> NodeWindow {
+        NodeWindow { node_id, ts_ms, window_sec: 60, values: values.iter().map(|(k, v)| (k.to_string(), *v)).collect() }
+    }
+
+    #[test]
+    fn soil_windows_count_neither_as_nodes_nor_for_the_means() {
+        let windows = [
+            node(1, 120_000, &[("air_temp_c", 20.0), ("air_rh_pct", 60.0)]),
+            node(2, 120_000, &[("air_temp_c", 22.0), ("air_rh_pct", 70.0)]),
+            node(9, 120_000, &[("vwc1_pct", 41.0), ("ec_ms_cm", 2.1), ("battery_v", 3.9)]),
+        ];
+        let (n, means) = recompute(&windows, 120_000, &win(), &|_, _, _| false);
+        assert_eq!(n, 2, "the soil node would inflate nodes and coverage");
+        assert_eq!(means.get("air_temp_c"), Some(&21.0));
+        assert!(!means.contains_key("vwc1_pct"));
+        // a greenhouse with soil nodes only has nothing to average
+        let (n, means) = recompute(&windows[2..], 120_000, &win(), &|_, _, _| false);
+        assert_eq!((n, means.get("air_temp_c")), (0, None));
+    }
+}
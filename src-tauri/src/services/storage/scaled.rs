@@ -0,0 +1,126 @@
+//! Opt-in scaled-integer values (`storage_config().scaled_values`, or `convert_scaled_values`).
+//! - A scaled row stores round(value × `sensor_type.scale`) where scale is 10^precision from the sensor
+//!   registry (2 decimals, the same precision r2 always kept). SQLite writes whole numbers in a REAL
+//!   column as 1–4 byte integers, so no table rebuild is needed; 8-byte floats simply stop appearing.
+//! - `value_scale.scaled_from_id` marks, per table, the first scaled row id: rows at or above it are
+//!   scaled, rows below are plain REAL. New rows always get higher ids, so a half-converted database
+//!   reads correctly and conversion can stop and resume anywhere.
+//! - One-way: once a database is scaled it stays scaled, whatever the config says (writers follow
+//!   `sensor_type.scale`, not the config). Readers go through `GH_VALUE_SQL` and never see the difference.
+//! - `convert_scaled_values` walks each boundary down in chunks, one transaction per chunk.
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::time::Instant;
+use tracing::info;
+
+use super::sqlite::{db_size_bytes, open_db};
+use crate::services::mqtt::greenhouse_sensor::derived::storage_scale;
+
+const TABLES: [&str; 2] = ["node_values", "greenhouse_average"];
+const DEFAULT_CHUNK_ROWS: i64 = 20_000;
+
+/// SQL for a greenhouse_average value as stored before scaling (aliases: `ga`, sensor_type `st`).
+pub(crate) const GH_VALUE_SQL: &str =
+    "(CASE WHEN ga.id >= (SELECT scaled_from_id FROM value_scale WHERE tbl = 'greenhouse_average') \
+     THEN ga.value / st.scale ELSE ga.value END)";
+
+/// Same for a node_values value (aliases: `nv`, sensor_type `st`).
+pub(crate) const NV_VALUE_SQL: &str =
+    "(CASE WHEN nv.id >= (SELECT scaled_from_id FROM value_scale WHERE tbl = 'node_values') \
+     THEN nv.value / st.scale ELSE nv.value END)";
+
+/// First scaled row id of `table` (i64::MAX when the database is not scaled).
+pub(crate) fn scaled_from(conn: &Connection, table: &str) -> rusqlite::Result<i64> {
+    conn.query_row("SELECT COALESCE((SELECT scaled_from_id FROM value_scale WHERE tbl = ?1), ?2)",
+        params![table, i64::MAX], |r| r.get(0))
+}
+
+/// True once the database stores scaled values.
+pub(crate) fn is_scaled(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row("SELECT EXISTS (SELECT 1 FROM value_scale)", [], |r| r.get(0))
+}
+
+/// Switch the database to scaled values (no-op when already scaled). Existing rows stay as they are.
+pub(crate) fn enable(conn: &Connection) -> rusqlite::Result<bool> {
+    if is_scaled(conn)? { return Ok(false); }
+    let tx = conn.unchecked_transaction()?;
+    for table in TABLES {
+        tx.execute(
+            &format!("INSERT INTO value_scale(tbl, scaled_from_id) SELECT ?1, COALESCE(MAX(id), 0) + 1 FROM {table}"),
+            params![table],
+        )?;
+    }
+    let keys: Vec<(i64, String)> = {
+        let mut stmt = tx.prepare("SELECT id, key FROM sensor_type")?;
+        let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    for (id, key) in keys {
+        tx.execute("UPDATE sensor_type SET scale = ?2 WHERE id = ?1", params![id, storage_scale(&key)])?;
+    }
+    tx.commit()?;
+    info!(target: "DB", "scaled integer values enabled (older rows stay REAL until convert_scaled_values)");
+    Ok(true)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScaledTableProgress {
+    pub table: &'static str,
+    pub converted: i64,
+    pub remaining: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScaledReport {
+    pub newly_enabled: bool,
+    pub complete: bool,
+    pub chunks: usize,
+    pub elapsed_ms: u64,
+    pub tables: Vec<ScaledTableProgress>,
+    pub db_bytes: u64, // file only shrinks after backup_db / VACUUM
+}
+
+fn boundary(conn: &Connection, table: &str) -> rusqlite::Result<i64> {
+    conn.query_row("SELECT scaled_from_id FROM value_scale WHERE tbl = ?1", params![table], |r| r.get(0))
+}
+
+fn remaining(conn: &Connection, table: &str, below: i64) -> rusqlite::Result<i64> {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {table} WHERE id < ?1"), params![below], |r| r.get(0))
+}
+
+/// Blocking: enable scaling if needed, then convert older rows newest-first, `chunk_rows` ids per
+/// transaction, for at most `max_chunks` chunks (None = until done). Safe to interrupt and rerun.
+pub fn convert_scaled_values(db_path: &str, chunk_rows: Option<i64>, max_chunks: Option<usize>) -> rusqlite::Result<ScaledReport> {
+    let started = Instant::now();
+    let conn = open_db(db_path)?;
+    conn.busy_timeout(std::time::Duration::from_secs(5))?; // the live writer shares the file
+    let newly_enabled = enable(&conn)?;
+    let chunk = chunk_rows.unwrap_or(DEFAULT_CHUNK_ROWS).max(1);
+    let mut chunks = 0;
+    let mut tables = Vec::new();
+    for table in TABLES {
+        let first: Option<i64> = conn.query_row(&format!("SELECT MIN(id) FROM {table}"), [], |r| r.get(0))?;
+        let mut b = boundary(&conn, table)?;
+        let mut converted = 0;
+        while let Some(first) = first.filter(|f| b > *f) {
+            if max_chunks.is_some_and(|m| chunks >= m) { break; }
+            let lo = (b - chunk).max(first);
+            let tx = conn.unchecked_transaction()?;
+            converted += tx.execute(
+                &format!("UPDATE {table} SET value = ROUND(value * (SELECT s.scale FROM sensor_type s WHERE s.id = {table}.sensor_type_id))
+                          WHERE id >= ?1 AND id < ?2 AND value IS NOT NULL"),
+                params![lo, b],
+            )? as i64;
+            tx.execute("UPDATE value_scale SET scaled_from_id = ?2 WHERE tbl = ?1", params![table, lo])?;
+            tx.commit()?;
+            b = lo;
+            chunks += 1;
+        }
+        tables.push(ScaledTableProgress { table, converted, remaining: remaining(&conn, table, b)? });
+    }
+    let complete = tables.iter().all(|t| t.remaining == 0);
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    info!(target: "DB", "scaled conversion: {chunks} chunk(s) in {elapsed_ms} ms, {}", if complete { "complete" } else { "partial, rerun to resume" });
+    Ok(ScaledReport { newly_enabled, complete, chunks, elapsed_ms, tables, db_bytes: db_size_bytes(db_path) })
+}
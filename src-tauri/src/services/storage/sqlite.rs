@@ -1,38 +1,83 @@
 //! Async, durable SSD storage using rusqlite.
 //! - 5-table schema (greenhouse_id, sensor_type, greenhouse_average, node_name, node_values).
 //! - FK ON, WAL, NORMAL sync.
+//! - Schema changes after the initial layout are ordered migrations tracked in `PRAGMA user_version`.
 //! - Per-insert error handling: bad rows are logged and skipped (no crash).
-//! - 2-decimal rounding on floats for consistent storage.
+//! - Rows carry their true window: agg 'rolling_{window_sec}s' (the aggregation window, 60s by default;
+//!   longer for outdoor node rows), taken from each NodeAvg / GhAvg.
+//! - Node row ts_ms is the flush time, or the newest node-clock measurement time when the frames
+//!   carried one (`NodeAvg::measured_ms`, v4 frames).
+//! - Rows are unique per computed window (window_start_ms, window_seq); a duplicate window is
+//!   logged, never dropped silently, even when two windows flush in the same millisecond.
+//! - 2-decimal rounding on floats for consistent storage; optionally stored as scaled integers (scaled.rs).
 //! - Prints the absolute DB path on init so you can open it in a viewer.
+//! - Optional compact mode (storage_config().compact) skips node rows inside a per-sensor deadband.
+//! - Hourly greenhouse aggregates go into greenhouse_average with agg='hourly'/'hourly_min'/'hourly_max'.
+//! - Detected wall-clock jumps are kept in clock_adjustment as quarantined ts ranges, and the rows
+//!   stamped inside them get `quarantined = 1` (again once the range is over, for the rows written since).
+//! - Dry-run mode (dry_run.rs) counts would-be rows per flush; no connection is opened at all.
+//! - On app shutdown (shutdown.rs) the writer drains the aggregators' last windows and writes its
+//!   pending batch before returning.
+//! - Every row records the write path that produced it (`source`, see history.rs); this writer is 'live',
+//!   except for the node aggregator's back-filled windows of late samples (`NodeAvg::catchup`), which
+//!   arrive on their own critical input, are stored as 'catchup' and skip compaction.
 
-use std::{fs, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
-use tokio::{sync::mpsc, time::{interval, Duration}};
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}, sync::{Arc, Mutex, RwLock}, time::{Instant, SystemTime, UNIX_EPOCH}};
+use tokio::{sync::mpsc, task::JoinHandle, time::{interval, sleep_until, Duration}};
+use tokio_util::sync::CancellationToken;
 use rusqlite::{Connection, params};
+use tracing::{debug, error, info, warn};
 
-use crate::services::mqtt::greenhouse_sensor::aggregator::NodeAvg;
-use crate::services::mqtt::greenhouse_sensor::greenhouse_aggregator::GhAvg;
+use crate::services::clock::ClockAdjustment;
+use crate::services::math::r2;
+use crate::services::mqtt::greenhouse_sensor::aggregator::{node_agg, rolling_agg, stat_agg, NodeAvg};
+use crate::services::mqtt::greenhouse_sensor::derived::storage_scale;
+use crate::services::mqtt::greenhouse_sensor::greenhouse_aggregator::{Confidence, GhAvg};
+use crate::services::mqtt::greenhouse_sensor::hourly_aggregator::{GhHourly, HourStat};
+use crate::services::mqtt::greenhouse_sensor::nodes::label_for;
+use crate::services::shutdown::STORAGE_DRAIN;
+use crate::services::supervisor::Lease;
+use super::compact::Compactor;
+use super::config::storage_config;
+use super::scaled;
+use super::dry_run::{db_refused, dry_run_requested, record_flush, DryRunShared};
+use super::history::Provenance;
 
-#[inline]
-fn now_ms() -> i64 {
-    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+pub const BATCH_SIZE: usize = 512;
+pub const FLUSH_EVERY: Duration = Duration::from_secs(1);
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
 }
+
+/// Value as bound for a sensor with `scale` (1 = plain REAL).
 #[inline]
-fn r2(v: Option<f32>) -> Option<f64> { v.map(|x| ((x as f64) * 100.0).round() / 100.0) }
+pub(crate) fn stored(v: Option<f32>, scale: i64) -> Option<f64> {
+    if scale > 1 { v.map(|x| ((x as f64) * scale as f64).round()) } else { r2(v) }
+}
 
-fn absolute_path(db_path: &str) -> PathBuf {
+pub fn absolute_path(db_path: &str) -> PathBuf {
     let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
     cwd.join(db_path)
 }
 
+/// On-disk size of the DB including its WAL file (0 if missing).
+pub fn db_size_bytes(db_path: &str) -> u64 {
+    let abs = absolute_path(db_path);
+    let wal = PathBuf::from(format!("{}-wal", abs.display()));
+    [abs, wal].iter().filter_map(|p| fs::metadata(p).ok()).map(|m| m.len()).sum()
+}
+
 fn open_and_init(path: &str) -> rusqlite::Result<Connection> {
+    if dry_run_requested() { return Err(db_refused()); }
     // ensure directory exists
     if let Some(dir) = std::path::Path::new(path).parent() {
         if !dir.as_os_str().is_empty() { let _ = fs::create_dir_all(dir); }
     }
     let conn = Connection::open(path)?;
-    conn.pragma_update(None, "foreign_keys", &"ON")?;
-    conn.pragma_update(None, "journal_mode", &"WAL")?;
-    conn.pragma_update(None, "synchronous", &"NORMAL")?;
+    conn.pragma_update(None, "foreign_keys", "ON")?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
 
     // NOTE: renamed "values" -> "node_values" (avoid SQL keyword)
     conn.execute_batch(r#"
@@ -82,9 +127,283 @@ fn open_and_init(path: &str) -> rusqlite::Result<Connection> {
       CREATE INDEX IF NOT EXISTS idx_ghavg_ts ON greenhouse_average(ts_ms);
     "#)?;
 
+    migrate(&conn)?;
+    if storage_config().scaled_values { scaled::enable(&conn)?; }
     Ok(conn)
 }
 
+/// Ordered schema migrations; entry N brings `user_version` from N to N+1.
+/// Append only — never edit an entry that has shipped.
+const MIGRATIONS: &[&str] = &[
+    // 1: node merge / deactivate tooling
+    r#"
+      ALTER TABLE node_name ADD COLUMN active INTEGER NOT NULL DEFAULT 1;
+      ALTER TABLE node_name ADD COLUMN merged_into INTEGER REFERENCES node_name(id);
+    "#,
+    // 2: per-node display preferences
+    r#"
+      ALTER TABLE node_name ADD COLUMN color TEXT;
+      ALTER TABLE node_name ADD COLUMN sort_order INTEGER;
+      ALTER TABLE node_name ADD COLUMN icon TEXT;
+      ALTER TABLE node_name ADD COLUMN hidden INTEGER NOT NULL DEFAULT 0;
+    "#,
+    // 3: wall-clock jumps and the ts ranges whose aggregates are not trustworthy
+    r#"
+      CREATE TABLE IF NOT EXISTS clock_adjustment (
+        id          INTEGER PRIMARY KEY,
+        detected_ms INTEGER NOT NULL,
+        delta_ms    INTEGER NOT NULL,
+        from_ms     INTEGER NOT NULL,
+        to_ms       INTEGER NOT NULL
+      );
+      CREATE INDEX IF NOT EXISTS idx_clock_adjustment_range ON clock_adjustment(from_ms, to_ms);
+    "#,
+    // 4: rows are unique per computed window (start + sequence), not per flush timestamp
+    r#"
+      CREATE TABLE node_values_v4 (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        ts_ms INTEGER NOT NULL,
+        node_id INTEGER NOT NULL,
+        sensor_type_id INTEGER NOT NULL,
+        value REAL,
+        agg TEXT NOT NULL,
+        window_sec INTEGER NOT NULL,
+        window_start_ms INTEGER NOT NULL,
+        window_seq INTEGER NOT NULL DEFAULT 0,
+        UNIQUE(node_id, sensor_type_id, agg, window_start_ms, window_seq),
+        FOREIGN KEY (node_id) REFERENCES node_name(id) ON DELETE CASCADE,
+        FOREIGN KEY (sensor_type_id) REFERENCES sensor_type(id) ON DELETE RESTRICT
+      );
+      INSERT INTO node_values_v4
+        (id, ts_ms, node_id, sensor_type_id, value, agg, window_sec, window_start_ms, window_seq)
+        SELECT id, ts_ms, node_id, sensor_type_id, value, agg, window_sec, ts_ms - window_sec * 1000, 0
+        FROM node_values;
+      DROP TABLE node_values;
+      ALTER TABLE node_values_v4 RENAME TO node_values;
+      CREATE INDEX IF NOT EXISTS idx_node_values_ts ON node_values(ts_ms);
+
+      CREATE TABLE greenhouse_average_v4 (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        ts_ms INTEGER NOT NULL,
+        greenhouse_id INTEGER NOT NULL,
+        sensor_type_id INTEGER NOT NULL,
+        value REAL,
+        nodes INTEGER NOT NULL,
+        agg TEXT NOT NULL,
+        window_sec INTEGER NOT NULL,
+        window_start_ms INTEGER NOT NULL,
+        window_seq INTEGER NOT NULL DEFAULT 0,
+        UNIQUE(greenhouse_id, sensor_type_id, agg, window_start_ms, window_seq),
+        FOREIGN KEY (greenhouse_id) REFERENCES greenhouse_id(id) ON DELETE CASCADE,
+        FOREIGN KEY (sensor_type_id) REFERENCES sensor_type(id) ON DELETE RESTRICT
+      );
+      INSERT INTO greenhouse_average_v4
+        (id, ts_ms, greenhouse_id, sensor_type_id, value, nodes, agg, window_sec, window_start_ms, window_seq)
+        SELECT id, ts_ms, greenhouse_id, sensor_type_id, value, nodes, agg, window_sec,
+               CASE WHEN agg LIKE 'hourly%' THEN ts_ms ELSE ts_ms - window_sec * 1000 END, 0
+        FROM greenhouse_average;
+      DROP TABLE greenhouse_average;
+      ALTER TABLE greenhouse_average_v4 RENAME TO greenhouse_average;
+      CREATE INDEX IF NOT EXISTS idx_ghavg_ts ON greenhouse_average(ts_ms);
+    "#,
+    // 5: audit log of remote commands
+    r#"
+      CREATE TABLE IF NOT EXISTS command_audit (
+        id      INTEGER PRIMARY KEY,
+        ts_ms   INTEGER NOT NULL,
+        source  TEXT NOT NULL,
+        cmd_id  TEXT NOT NULL,
+        op      TEXT NOT NULL,
+        args    TEXT NOT NULL,
+        ok      INTEGER NOT NULL,
+        detail  TEXT NOT NULL
+      );
+      CREATE INDEX IF NOT EXISTS idx_command_audit_ts ON command_audit(ts_ms);
+    "#,
+    // 6: daily summary per greenhouse (VPD KPI)
+    r#"
+      CREATE TABLE IF NOT EXISTS greenhouse_daily (
+        greenhouse_id    INTEGER NOT NULL,
+        day_start_ms     INTEGER NOT NULL,
+        vpd_in_band_min  REAL NOT NULL DEFAULT 0,
+        vpd_out_band_min REAL NOT NULL DEFAULT 0,
+        vpd_unknown_min  REAL NOT NULL DEFAULT 0,
+        vpd_min_kpa      REAL NOT NULL,
+        vpd_max_kpa      REAL NOT NULL,
+        daylight_par_min REAL NOT NULL,
+        updated_ms       INTEGER NOT NULL,
+        PRIMARY KEY (greenhouse_id, day_start_ms),
+        FOREIGN KEY (greenhouse_id) REFERENCES greenhouse_id(id) ON DELETE CASCADE
+      );
+    "#,
+    // 7: outcomes of scheduled CSV exports
+    r#"
+      CREATE TABLE IF NOT EXISTS export_history (
+        id            INTEGER PRIMARY KEY,
+        ts_ms         INTEGER NOT NULL,
+        greenhouse_id INTEGER NOT NULL,
+        from_ms       INTEGER NOT NULL,
+        to_ms         INTEGER NOT NULL,
+        path          TEXT NOT NULL,
+        rows          INTEGER NOT NULL,
+        ok            INTEGER NOT NULL,
+        attempts      INTEGER NOT NULL,
+        detail        TEXT NOT NULL
+      );
+      CREATE INDEX IF NOT EXISTS idx_export_history_ts ON export_history(ts_ms);
+    "#,
+    // 8: per-node maintenance windows (readings kept, excluded from greenhouse averages)
+    r#"
+      CREATE TABLE IF NOT EXISTS maintenance_window (
+        id            INTEGER PRIMARY KEY,
+        greenhouse_id INTEGER NOT NULL,
+        node_id       INTEGER NOT NULL,
+        started_ms    INTEGER NOT NULL,
+        ended_ms      INTEGER,
+        note          TEXT NOT NULL DEFAULT '',
+        auto_closed   INTEGER NOT NULL DEFAULT 0,
+        reminded_ms   INTEGER
+      );
+      CREATE INDEX IF NOT EXISTS idx_maintenance_window_node ON maintenance_window(greenhouse_id, node_id, started_ms);
+    "#,
+    // 9: roster coverage and confidence level of each greenhouse row (NULL before this version)
+    r#"
+      ALTER TABLE greenhouse_average ADD COLUMN coverage REAL;
+      ALTER TABLE greenhouse_average ADD COLUMN confidence TEXT;
+    "#,
+    // 10: commissioning metadata per node (imported from the commissioning sheet)
+    r#"
+      ALTER TABLE node_name ADD COLUMN zone TEXT;
+      ALTER TABLE node_name ADD COLUMN air_temp_offset_c REAL;
+      ALTER TABLE node_name ADD COLUMN air_rh_offset_pct REAL;
+      ALTER TABLE node_name ADD COLUMN plant_area_m2 REAL;
+      ALTER TABLE node_name ADD COLUMN expected_interval_secs INTEGER;
+    "#,
+    // 11: opt-in scaled-integer values (scaled.rs); scale 1 = plain REAL, no value_scale rows = never enabled
+    r#"
+      ALTER TABLE sensor_type ADD COLUMN scale INTEGER NOT NULL DEFAULT 1;
+      CREATE TABLE IF NOT EXISTS value_scale (
+        tbl            TEXT PRIMARY KEY,
+        scaled_from_id INTEGER NOT NULL
+      );
+    "#,
+    // 12: greenhouse id per (topic origin, greenhouse id in the frame), for sites sharing a broker
+    r#"
+      CREATE TABLE IF NOT EXISTS gh_origin_map (
+        origin          TEXT NOT NULL,
+        greenhouse_id   INTEGER NOT NULL,
+        effective_gh_id INTEGER NOT NULL,
+        updated_ms      INTEGER NOT NULL,
+        PRIMARY KEY (origin, greenhouse_id)
+      );
+    "#,
+    // 13: greenhouse rows rewritten after the fact (rebuild.rs) count their revisions
+    r#"
+      ALTER TABLE greenhouse_average ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+      ALTER TABLE greenhouse_average ADD COLUMN revised_ms INTEGER;
+    "#,
+    // 14: which write path produced each row (history.rs::Provenance); everything before this was live
+    r#"
+      ALTER TABLE greenhouse_average ADD COLUMN source TEXT NOT NULL DEFAULT 'live';
+      ALTER TABLE node_values ADD COLUMN source TEXT NOT NULL DEFAULT 'live';
+    "#,
+    // 15: slab drainage after irrigation shots (report/drainage.rs)
+    r#"
+      CREATE TABLE IF NOT EXISTS drainage_event (
+        greenhouse_id   INTEGER NOT NULL,
+        node_id         INTEGER NOT NULL,
+        shot_ms         INTEGER NOT NULL,
+        shots           INTEGER NOT NULL,
+        peak_ms         INTEGER NOT NULL,
+        pre_g           REAL NOT NULL,
+        peak_g          REAL NOT NULL,
+        end_g           REAL NOT NULL,
+        drained_frac    REAL NOT NULL,
+        coverage        REAL NOT NULL,
+        baseline_frac   REAL,
+        baseline_events INTEGER NOT NULL,
+        verdict         TEXT NOT NULL,
+        analyzed_ms     INTEGER NOT NULL,
+        PRIMARY KEY (greenhouse_id, node_id, shot_ms)
+      );
+    "#,
+    // 16: daily node health score snapshots for trend charts (node_health.rs)
+    r#"
+      CREATE TABLE IF NOT EXISTS node_health_daily (
+        greenhouse_id INTEGER NOT NULL,
+        node_id       INTEGER NOT NULL,
+        day_start_ms  INTEGER NOT NULL,
+        samples       INTEGER NOT NULL,
+        score_sum     REAL NOT NULL,
+        score_min     REAL NOT NULL,
+        last_score    REAL NOT NULL,
+        updated_ms    INTEGER NOT NULL,
+        PRIMARY KEY (greenhouse_id, node_id, day_start_ms)
+      );
+    "#,
+    // 17: declared frame layout per node id range (schema.rs); greenhouse_id NULL = every greenhouse
+    r#"
+      CREATE TABLE IF NOT EXISTS node_schema (
+        greenhouse_id INTEGER,
+        node_from     INTEGER NOT NULL,
+        node_to       INTEGER NOT NULL,
+        layout        TEXT NOT NULL,
+        updated_ms    INTEGER NOT NULL,
+        CHECK (node_from <= node_to)
+      );
+    "#,
+    // 18: node online / offline changes from the nodes' status topics (availability.rs)
+    r#"
+      CREATE TABLE IF NOT EXISTS node_status_log (
+        ts_ms         INTEGER NOT NULL,
+        greenhouse_id INTEGER NOT NULL,
+        node_id       INTEGER NOT NULL,
+        status        TEXT NOT NULL,
+        retained      INTEGER NOT NULL DEFAULT 0
+      );
+      CREATE INDEX IF NOT EXISTS idx_node_status_log_node_ts ON node_status_log(greenhouse_id, node_id, ts_ms);
+    "#,
+    // 19: finite samples behind each node reading (aggregator.rs::SampleCounts); NULL for derived,
+    //     telemetry and wind rows and for everything written before this
+    r#"
+      ALTER TABLE node_values ADD COLUMN samples INTEGER;
+    "#,
+    // 20: online / late / offline changes seen from the samples (liveness.rs) carry the node's last
+    //     sample time; NULL for status-topic rows
+    r#"
+      ALTER TABLE node_status_log ADD COLUMN last_seen_ms INTEGER;
+    "#,
+    // 21: clock jumps also quarantine the span before the jump (old clock), and rows stamped inside
+    //     either span are flagged (clock.rs)
+    r#"
+      ALTER TABLE clock_adjustment ADD COLUMN before_from_ms INTEGER;
+      ALTER TABLE clock_adjustment ADD COLUMN before_to_ms INTEGER;
+      ALTER TABLE node_values ADD COLUMN quarantined INTEGER NOT NULL DEFAULT 0;
+      ALTER TABLE greenhouse_average ADD COLUMN quarantined INTEGER NOT NULL DEFAULT 0;
+    "#,
+];
+
+/// Schema version this build migrates to.
+pub(crate) fn schema_version() -> usize { MIGRATIONS.len() }
+
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |r| r.get::<_, i64>(0))? as usize;
+    for (i, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", (i + 1) as i64)?;
+        tx.commit()?;
+        info!(target: "DB", "migrated schema to version {}", i + 1);
+    }
+    Ok(())
+}
+
+/// Opens (creating/migrating if needed) the DB at `db_path` resolved against the CWD.
+pub fn open_db(db_path: &str) -> rusqlite::Result<Connection> {
+    let abs = absolute_path(db_path);
+    open_and_init(abs.to_str().unwrap_or(db_path))
+}
+
 fn ensure_greenhouse(conn: &Connection, gh_id: u16) -> rusqlite::Result<()> {
     conn.execute("INSERT OR IGNORE INTO greenhouse_id(id) VALUES (?1)", params![gh_id])?;
     Ok(())
@@ -95,194 +414,853 @@ fn ensure_node(conn: &Connection, gh_id: u16, node_id: u16, label: &str) -> rusq
         "INSERT OR IGNORE INTO node_name(greenhouse_id,node_id,label) VALUES (?1,?2,?3)",
         params![gh_id, node_id, label],
     )?;
+    // merged nodes keep writing into the row they were merged into
     conn.query_row(
-        "SELECT id FROM node_name WHERE greenhouse_id=?1 AND node_id=?2",
+        "SELECT COALESCE(merged_into, id) FROM node_name WHERE greenhouse_id=?1 AND node_id=?2",
         params![gh_id, node_id],
         |r| r.get::<_, i64>(0),
     )
 }
-fn ensure_sensor(conn: &Connection, key: &str, unit: &str) -> rusqlite::Result<i64> {
+/// (sensor_type id, scale); new sensors get their registry scale once the DB is scaled.
+fn ensure_sensor(conn: &Connection, key: &str, unit: &str) -> rusqlite::Result<(i64, i64)> {
     conn.execute(
-        "INSERT OR IGNORE INTO sensor_type(key,unit) VALUES (?1,?2)",
-        params![key, unit],
+        "INSERT OR IGNORE INTO sensor_type(key,unit,scale)
+         VALUES (?1,?2, CASE WHEN EXISTS (SELECT 1 FROM value_scale) THEN ?3 ELSE 1 END)",
+        params![key, unit, storage_scale(key)],
     )?;
     conn.query_row(
-        "SELECT id FROM sensor_type WHERE key=?1",
+        "SELECT id, scale FROM sensor_type WHERE key=?1",
         params![key],
-        |r| r.get::<_, i64>(0),
+        |r| Ok((r.get::<_, i64>(0)?, r.get::<_, i64>(1)?)),
     )
 }
 
-fn label_for(node_id: u16) -> &'static str {
-    match node_id {
-        65001 => "Outdoor_Node",
-        1 => "node01", 2 => "node02", 3 => "node03", 4 => "node04",
-        5 => "node05", 6 => "node06", 7 => "node07", 8 => "node08",
-        9 => "node09", 10 => "node10", 11 => "node11", 12 => "node12",
-        _ => "nodeXX",
-    }
-}
-
-fn insert_node_field(conn: &Connection, ts: i64, node_rowid: i64,
-                     key: &str, unit: &str, val: Option<f32>) {
-    if let Ok(st_id) = ensure_sensor(conn, key, unit) {
-        if let Err(e) = conn.execute(
-            "INSERT OR IGNORE INTO node_values(ts_ms,node_id,sensor_type_id,value,agg,window_sec)
-             VALUES (?1,?2,?3,?4,'rolling_60s',60)",
-            params![ts, node_rowid, st_id, r2(val)],
+/// One node row, stamped with its window close (or newest node-clock time); `agg` is the field's
+/// `node_agg` or a `stat_agg`.
+fn insert_node_field(conn: &Connection, source: Provenance, node_rowid: i64, na: &NodeAvg, agg: &str,
+                     (key, unit, val): (&str, &str, Option<f32>)) {
+    let (win_start, win_seq) = (na.window_start_ms, na.window_seq);
+    if let Ok((st_id, scale)) = ensure_sensor(conn, key, unit) {
+        match conn.execute(
+            "INSERT OR IGNORE INTO node_values
+             (ts_ms,node_id,sensor_type_id,value,agg,window_sec,window_start_ms,window_seq,source,samples)
+             VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10)",
+            params![na.measured_ms.unwrap_or(na.ts_ms), node_rowid, st_id, stored(val, scale), agg, na.window_sec,
+                    win_start, win_seq as i64, source.as_str(), na.samples.get(key)],
         ) {
-            eprintln!("[DB] skip node field {key}: {e}");
+            Ok(0) => warn!(target: "DB", "duplicate node window {key} ({agg}) node_row={node_rowid} start={win_start} seq={win_seq} (kept existing)"),
+            Ok(_) => (),
+            Err(e) => warn!(target: "DB", "skip node field {key}: {e}"),
         }
     } else {
-        eprintln!("[DB] skip sensor ensure for key={key}");
+        warn!(target: "DB", "skip sensor ensure for key={key}");
     }
 }
 
-fn insert_gh_field(conn: &Connection, ts: i64, gh_id: u16,
-                   key: &str, unit: &str, val: Option<f32>, nodes: usize) {
+/// One greenhouse row, stamped with its window close like the node rows it averages.
+fn insert_gh_field(conn: &Connection, source: Provenance, ga: &GhAvg, agg: &str, (key, unit, val): (&str, &str, Option<f32>), coverage: f32) {
+    let (gh_id, win) = (ga.greenhouse_id, (ga.window_start_ms, ga.window_seq));
     if ensure_greenhouse(conn, gh_id).is_err() {
-        eprintln!("[DB] skip greenhouse ensure gh_id={gh_id}");
+        warn!(target: "DB", "skip greenhouse ensure gh_id={gh_id}");
         return;
     }
-    if let Ok(st_id) = ensure_sensor(conn, key, unit) {
-        if let Err(e) = conn.execute(
+    if let Ok((st_id, scale)) = ensure_sensor(conn, key, unit) {
+        match conn.execute(
             "INSERT OR IGNORE INTO greenhouse_average
-             (ts_ms,greenhouse_id,sensor_type_id,value,nodes,agg,window_sec)
-             VALUES (?1,?2,?3,?4,?5,'rolling_60s',60)",
-            params![ts, gh_id, st_id, r2(val), nodes as i64],
+             (ts_ms,greenhouse_id,sensor_type_id,value,nodes,agg,window_sec,window_start_ms,window_seq,coverage,confidence,source)
+             VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10,?11,?12)",
+            params![ga.ts_ms, gh_id, st_id, stored(val, scale), ga.nodes as i64, agg, ga.window_sec, win.0, win.1 as i64,
+                    r2(Some(coverage)), Confidence::from_coverage(coverage).as_str(), source.as_str()],
         ) {
-            eprintln!("[DB] skip gh field {key}: {e}");
+            Ok(0) => warn!(target: "DB", "duplicate gh window {key} ({agg}) gh={gh_id} start={} seq={} (kept existing)", win.0, win.1),
+            Ok(_) => (),
+            Err(e) => warn!(target: "DB", "skip gh field {key}: {e}"),
         }
     } else {
-        eprintln!("[DB] skip gh sensor ensure for key={key}");
+        warn!(target: "DB", "skip gh sensor ensure for key={key}");
     }
 }
 
+fn insert_gh_hourly(conn: &Connection, h: &GhHourly, source: Provenance) {
+    if ensure_greenhouse(conn, h.greenhouse_id).is_err() {
+        warn!(target: "DB", "skip greenhouse ensure gh_id={}", h.greenhouse_id);
+        return;
+    }
+    for (key, st) in &h.fields {
+        let Ok((st_id, scale)) = ensure_sensor(conn, key, &st.unit) else {
+            warn!(target: "DB", "skip hourly sensor ensure for key={key}");
+            continue;
+        };
+        for (agg, val) in [("hourly", st.mean), ("hourly_min", st.min), ("hourly_max", st.max)] {
+            if let Err(e) = conn.execute(
+                "INSERT OR IGNORE INTO greenhouse_average
+                 (ts_ms,greenhouse_id,sensor_type_id,value,nodes,agg,window_sec,window_start_ms,window_seq,source)
+                 VALUES (?1,?2,?3,?4,?5,?6,3600,?1,0,?7)",
+                params![h.hour_start_ms, h.greenhouse_id, st_id, stored(val, scale), h.nodes as i64, agg, source.as_str()],
+            ) {
+                warn!(target: "DB", "skip hourly field {key} ({agg}): {e}");
+            }
+        }
+    }
+}
+
+/// Blocking read of closed hours since `since_ms` (used to refill the hourly cache on startup).
+pub fn load_recent_hourly(db_path: &str, since_ms: i64) -> rusqlite::Result<Vec<GhHourly>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT ga.ts_ms, ga.greenhouse_id, ga.nodes, ga.agg, {}, st.key, st.unit
+         FROM greenhouse_average ga JOIN sensor_type st ON st.id = ga.sensor_type_id
+         WHERE ga.agg IN ('hourly','hourly_min','hourly_max') AND ga.ts_ms >= ?1
+         ORDER BY ga.ts_ms",
+        scaled::GH_VALUE_SQL,
+    ))?;
+    let rows = stmt.query_map(params![since_ms], |r| Ok((
+        r.get::<_, i64>(0)?, r.get::<_, u16>(1)?, r.get::<_, i64>(2)?, r.get::<_, String>(3)?,
+        r.get::<_, Option<f64>>(4)?, r.get::<_, String>(5)?, r.get::<_, String>(6)?,
+    )))?;
+
+    let mut by_hour: BTreeMap<(i64, u16), GhHourly> = BTreeMap::new();
+    for row in rows {
+        let (ts, gh_id, nodes, agg, value, key, unit) = row?;
+        let h = by_hour.entry((ts, gh_id)).or_insert_with(|| GhHourly {
+            hour_start_ms: ts, greenhouse_id: gh_id, nodes: nodes as usize, fields: BTreeMap::new(),
+        });
+        let st = h.fields.entry(key).or_insert_with(|| HourStat { unit, mean: None, min: None, max: None });
+        let v = value.map(|x| x as f32);
+        match agg.as_str() {
+            "hourly" => st.mean = v,
+            "hourly_min" => st.min = v,
+            _ => st.max = v,
+        }
+    }
+    Ok(by_hour.into_values().collect())
+}
+
+/// Blocking: record one clock jump (rare, written immediately rather than batched) and flag the rows
+/// already stamped inside its spans.
+fn insert_clock_adjustment(db_path: &str, adj: &ClockAdjustment) -> rusqlite::Result<usize> {
+    let conn = open_db(db_path)?;
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO clock_adjustment(detected_ms, delta_ms, from_ms, to_ms, before_from_ms, before_to_ms)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![adj.detected_ms, adj.delta_ms, adj.from_ms, adj.to_ms, adj.before_from_ms, adj.before_to_ms],
+    )?;
+    let n = quarantine_rows(&tx, adj)?;
+    tx.commit()?;
+    Ok(n)
+}
+
+/// Set `quarantined` on node and greenhouse rows stamped inside either span of `adj`; returns the rows changed.
+fn quarantine_rows(conn: &Connection, adj: &ClockAdjustment) -> rusqlite::Result<usize> {
+    let mut n = 0;
+    for table in ["node_values", "greenhouse_average"] {
+        n += conn.execute(
+            &format!("UPDATE {table} SET quarantined = 1
+                      WHERE quarantined = 0 AND (ts_ms BETWEEN ?1 AND ?2 OR ts_ms BETWEEN ?3 AND ?4)"),
+            params![adj.from_ms, adj.to_ms, adj.before_from_ms, adj.before_to_ms],
+        )?;
+    }
+    Ok(n)
+}
+
+/// Blocking: flag the rows written inside `adj`'s spans since it was recorded.
+fn requarantine(db_path: &str, adj: &ClockAdjustment) -> rusqlite::Result<usize> {
+    quarantine_rows(&open_db(db_path)?, adj)
+}
+
+/// Blocking: clock jumps whose quarantined range ends after `since_ms`, oldest first.
+pub fn load_clock_adjustments(db_path: &str, since_ms: i64) -> rusqlite::Result<Vec<ClockAdjustment>> {
+    let conn = open_db(db_path)?;
+    let mut stmt = conn.prepare(
+        "SELECT detected_ms, delta_ms, from_ms, to_ms, before_from_ms, before_to_ms FROM clock_adjustment
+         WHERE to_ms >= ?1 OR before_to_ms >= ?1 ORDER BY detected_ms",
+    )?;
+    let rows = stmt.query_map(params![since_ms], |r| {
+        let (from_ms, to_ms) = (r.get(2)?, r.get(3)?);
+        Ok(ClockAdjustment {
+            detected_ms: r.get(0)?, delta_ms: r.get(1)?, from_ms, to_ms,
+            // jumps recorded before migration 21 have no pre-jump span
+            before_from_ms: r.get::<_, Option<i64>>(4)?.unwrap_or(from_ms),
+            before_to_ms: r.get::<_, Option<i64>>(5)?.unwrap_or(from_ms),
+        })
+    })?;
+    rows.collect()
+}
+
 /// Blocking batch flush inside a transaction (spawn_blocking caller).
 /// Bad rows are logged and skipped; commit still happens.
-fn flush_batch(db_path: &str, batch_nodes: Vec<NodeAvg>, batch_gh: Vec<GhAvg>) {
-    if batch_nodes.is_empty() && batch_gh.is_empty() { return; }
+/// `compactor` is Some only in compact mode and persists across flushes; every row is tagged `source`.
+pub(crate) fn flush_batch(
+    db_path: &str,
+    batch_nodes: Vec<NodeAvg>,
+    batch_gh: Vec<GhAvg>,
+    batch_hourly: Vec<GhHourly>,
+    compactor: Option<Arc<Mutex<Compactor>>>,
+    source: Provenance,
+) {
+    if batch_nodes.is_empty() && batch_gh.is_empty() && batch_hourly.is_empty() { return; }
     let abs = absolute_path(db_path);
     let Ok(conn) = open_and_init(abs.to_str().unwrap_or(db_path)) else {
-        eprintln!("[DB] open/init failed at {}", abs.display());
+        warn!(target: "DB", "open/init failed at {}", abs.display());
         return;
     };
     let Ok(tx) = conn.unchecked_transaction() else {
-        eprintln!("[DB] begin tx failed at {}", abs.display());
+        warn!(target: "DB", "begin tx failed at {}", abs.display());
         return;
     };
-    let ts = now_ms();
+    let mut compact = compactor.as_ref().and_then(|c| c.lock().ok());
 
     for na in batch_nodes {
-        match ensure_node(&tx, na.greenhouse_id, na.node_id, label_for(na.node_id)) {
+        // back-filled windows are older than the deadband's last kept value
+        let (source, mut compact) = if na.catchup { (Provenance::Catchup, None) } else { (source, compact.as_mut()) };
+        match ensure_node(&tx, na.greenhouse_id, na.node_id, label_for(na.node_id, na.outdoor)) {
             Ok(node_rowid) => {
-                insert_node_field(&tx, ts, node_rowid, "air_temp_c", "C",   na.air_temp_c);
-                insert_node_field(&tx, ts, node_rowid, "leaf_temp_c","C",   na.leaf_temp_c);
-                insert_node_field(&tx, ts, node_rowid, "bag_temp_c", "C",   na.bag_temp_c);
-                insert_node_field(&tx, ts, node_rowid, "air_rh_pct", "%",   na.air_rh_pct);
-                insert_node_field(&tx, ts, node_rowid, "bag_rh1_pct","%",   na.bag_rh1_pct);
-                insert_node_field(&tx, ts, node_rowid, "bag_rh2_pct","%",   na.bag_rh2_pct);
-                insert_node_field(&tx, ts, node_rowid, "bag_rh3_pct","%",   na.bag_rh3_pct);
-                insert_node_field(&tx, ts, node_rowid, "bag_rh4_pct","%",   na.bag_rh4_pct);
-                insert_node_field(&tx, ts, node_rowid, "bag_rh_avg_pct","%",na.bag_rh_avg_pct);
-                insert_node_field(&tx, ts, node_rowid, "par_value",  "",    na.par_value);
-                insert_node_field(&tx, ts, node_rowid, "weight_g",   "",    na.weight_g);
-                insert_node_field(&tx, ts, node_rowid, "ea_air_kpa", "kPa", na.ea_air_kpa);
-                insert_node_field(&tx, ts, node_rowid, "ea_leaf_kpa","kPa", na.ea_leaf_kpa);
-                insert_node_field(&tx, ts, node_rowid, "es_kpa",     "kPa", na.es_kpa);
-                insert_node_field(&tx, ts, node_rowid, "vpd_kpa",    "kPa", na.vpd_kpa);
+                for (key, unit, val) in na.fields() {
+                    if let Some(c) = compact.as_deref_mut() {
+                        if !c.keep(node_rowid, key, r2(val), na.ts_ms) { continue; }
+                    }
+                    insert_node_field(&tx, source, node_rowid, &na, &node_agg(key, na.window_sec as u64), (key, unit, val));
+                }
+                // extremes and deviations are not compacted: a deadband on the mean says nothing about the range
+                for (kind, key, unit, v) in na.extremes.rows().into_iter().chain(na.spread.rows()) {
+                    insert_node_field(&tx, source, node_rowid, &na, &stat_agg(kind, na.window_sec as u64), (key, unit, Some(v)));
+                }
             }
-            Err(e) => eprintln!("[DB] skip node ensure gh={} node={}: {e}", na.greenhouse_id, na.node_id),
+            Err(e) => warn!(target: "DB", "skip node ensure gh={} node={}: {e}", na.greenhouse_id, na.node_id),
         }
     }
 
     for ga in batch_gh {
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "air_temp_c","C",   ga.air_temp_c,   ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "leaf_temp_c","C",  ga.leaf_temp_c,  ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "bag_temp_c","C",   ga.bag_temp_c,   ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "air_rh_pct","%",   ga.air_rh_pct,   ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "bag_rh1_pct","%",  ga.bag_rh1_pct,  ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "bag_rh2_pct","%",  ga.bag_rh2_pct,  ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "bag_rh3_pct","%",  ga.bag_rh3_pct,  ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "bag_rh4_pct","%",  ga.bag_rh4_pct,  ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "bag_rh_avg_pct","%",ga.bag_rh_avg_pct,ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "par_value","",      ga.par_value,    ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "weight_g","",       ga.weight_g,     ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "ea_air_kpa","kPa",  ga.ea_air_kpa,   ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "ea_leaf_kpa","kPa", ga.ea_leaf_kpa,  ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "es_kpa","kPa",      ga.es_kpa,       ga.nodes);
-        insert_gh_field(&tx, ts, ga.greenhouse_id, "vpd_kpa","kPa",     ga.vpd_kpa,      ga.nodes);
-    }
-
-    if let Err(e) = tx.commit() {
-        eprintln!("[DB] commit failed (batch skipped): {e}");
+        let rolling = rolling_agg(ga.window_sec as u64);
+        let rows: Vec<_> = ga.fields().into_iter().zip(ga.field_coverage()).collect();
+        for &(field, coverage) in &rows {
+            insert_gh_field(&tx, source, &ga, &rolling, field, coverage);
+        }
+        for (kind, key, unit, v) in ga.extremes.rows() {
+            let coverage = rows.iter().find(|((k, ..), _)| *k == key).map_or(ga.coverage, |r| r.1);
+            insert_gh_field(&tx, source, &ga, &stat_agg(kind, ga.window_sec as u64), (key, unit, Some(v)), coverage);
+        }
+    }
+
+    for h in &batch_hourly {
+        insert_gh_hourly(&tx, h, source);
+    }
+
+    match tx.commit() {
+        Ok(()) => if let Some(c) = compact.as_mut() {
+            c.commit();
+            debug!(target: "DB", "compact mode: {} node rows skipped within deadband so far", c.skipped_total());
+        },
+        Err(e) => {
+            warn!(target: "DB", "commit failed (batch skipped): {e}");
+            if let Some(c) = compact.as_mut() { c.rollback(); }
+        }
+    }
+}
+
+/// Flush timing since startup, read through `get_pipeline_stats`.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct StorageStats {
+    pub flushes: u64,
+    /// Total time with a flush running on the blocking pool (messages keep being received meanwhile).
+    pub flush_in_flight_ms: u64,
+    pub last_flush_ms: u64,
+    pub max_flush_ms: u64,
+    /// Times receiving paused because a full batch was already waiting behind a running flush.
+    pub backpressure_pauses: u64,
+}
+
+pub type StorageStatsShared = Arc<RwLock<StorageStats>>;
+
+/// Hand the current batches to `flush_batch` on the blocking pool (or to the dry-run counter),
+/// leaving them empty. Returns the running flush, if one was started.
+fn start_flush(
+    abs: &Path,
+    batch_nodes: &mut Vec<NodeAvg>,
+    batch_gh: &mut Vec<GhAvg>,
+    batch_hourly: &mut Vec<GhHourly>,
+    compactor: &Option<Arc<Mutex<Compactor>>>,
+    dry_run: &Option<DryRunShared>,
+) -> Option<JoinHandle<()>> {
+    let bn = std::mem::take(batch_nodes);
+    let bg = std::mem::take(batch_gh);
+    let bh = std::mem::take(batch_hourly);
+    if let Some(report) = dry_run {
+        record_flush(report, &bn, &bg, &bh);
+        return None;
     }
+    let path = abs.to_path_buf();
+    let c = compactor.clone();
+    Some(tokio::task::spawn_blocking(move || {
+        #[cfg(test)]
+        if let Some(&delay) = SLOW_FLUSH.lock().unwrap().get(&path) { std::thread::sleep(delay); }
+        flush_batch(path.to_str().unwrap(), bn, bg, bh, c, Provenance::Live)
+    }))
+}
+
+/// Extra time every flush into a database takes (tests of a slow disk).
+#[cfg(test)]
+static SLOW_FLUSH: Mutex<BTreeMap<PathBuf, Duration>> = Mutex::new(BTreeMap::new());
+
+/// Wait for the running flush, if any (pending forever otherwise, for use in `select!`).
+async fn flush_done(in_flight: &mut Option<(JoinHandle<()>, Instant)>) -> Instant {
+    match in_flight {
+        Some((handle, started)) => {
+            if let Err(e) = handle.await { error!(target: "DB", "flush join error: {e}"); }
+            *started
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Streams the DB writer consumes.
+pub struct StorageInputs {
+    /// NodeAvg stream (per-node windows) from aggregator
+    pub nodeavg: mpsc::Receiver<NodeAvg>,
+    /// Back-filled NodeAvg windows (late samples) from aggregator
+    pub catchup: mpsc::Receiver<NodeAvg>,
+    /// GhAvg stream (per-greenhouse windows) from greenhouse aggregator
+    pub ghavg: mpsc::Receiver<GhAvg>,
+    /// GhHourly stream (per-greenhouse closed local hours) from hourly aggregator
+    pub hourly: mpsc::Receiver<GhHourly>,
+    /// Detected wall-clock jumps (quarantine intervals) from the clock watch
+    pub clock: mpsc::Receiver<ClockAdjustment>,
 }
 
 /// Public async task:
-/// - `rx_nodeavg`: NodeAvg stream (per-node 60s) from aggregator
-/// - `rx_ghavg`: GhAvg stream (per-greenhouse 60s) from greenhouse aggregator
+/// - `inputs`: the NodeAvg (live and back-filled), GhAvg, hourly and clock streams (`StorageInputs`)
+/// - `dry_run`: Some to count would-be rows instead of writing (fixed until restart)
 /// - Batches and flushes every 1s or 512 msgs via spawn_blocking (keeps hot path non-blocking)
+/// - A flush runs while the next batch keeps filling; at most one flush is in flight and one
+///   batch waits behind it. Only when that waiting batch is full does receiving pause.
+/// - After `shutdown` it keeps receiving until both aggregators have finished (their final windows
+///   included), at most `STORAGE_DRAIN`, then writes the pending batch and whatever is still queued
+///   and returns.
 pub async fn run_storage(
     db_path: &'static str,
-    mut rx_nodeavg: mpsc::Receiver<NodeAvg>,
-    mut rx_ghavg: mpsc::Receiver<GhAvg>,
+    mut inputs: Lease<StorageInputs>,
+    dry_run: Option<DryRunShared>,
+    stats: StorageStatsShared,
+    shutdown: CancellationToken,
 ) {
+    let StorageInputs { nodeavg: rx_nodeavg, catchup: rx_catchup, ghavg: rx_ghavg, hourly: rx_hourly, clock: rx_clock } = &mut *inputs;
     let abs = absolute_path(db_path);
-    println!("[DB] Using database at: {}", abs.display());
-
-    // Ensure DB exists (blocking once)
-    match tokio::task::spawn_blocking({
-        let path = abs.clone();
-        move || open_and_init(path.to_str().unwrap_or(db_path))
-    }).await {
-        Ok(Ok(_)) => (),
-        Ok(Err(e)) => {
-            eprintln!("[DB] init error at {}: {}", abs.display(), e);
-            return;
-        }
-        Err(e) => {
-            eprintln!("[DB] init join error: {}", e);
-            return;
-        }
+    if dry_run.is_some() {
+        info!(target: "DB", "DRY RUN: nothing will be written to {}", abs.display());
+    } else {
+        info!(target: "DB", "Using database at: {}", abs.display());
     }
 
-    const BATCH_SIZE: usize = 512;
-    const FLUSH_EVERY: Duration = Duration::from_secs(1);
+    // Ensure DB exists (blocking once); skipped in dry-run so the file is never touched
+    if dry_run.is_none() {
+        match tokio::task::spawn_blocking({
+            let path = abs.clone();
+            move || open_and_init(path.to_str().unwrap_or(db_path))
+        }).await {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => {
+                error!(target: "DB", "init error at {}: {}", abs.display(), e);
+                return;
+            }
+            Err(e) => {
+                error!(target: "DB", "init join error: {}", e);
+                return;
+            }
+        }
+    }
 
     let mut batch_nodes: Vec<NodeAvg> = Vec::with_capacity(256);
     let mut batch_gh: Vec<GhAvg> = Vec::with_capacity(128);
+    let mut batch_hourly: Vec<GhHourly> = Vec::new();
+    let compactor = storage_config().compact.then(|| Arc::new(Mutex::new(Compactor::default())));
+    if compactor.is_some() { info!(target: "DB", "compact storage mode ON (deadband per sensor)"); }
     let mut tick = interval(FLUSH_EVERY);
+    let mut in_flight: Option<(JoinHandle<()>, Instant)> = None;
+    let mut flush_due = false; // tick fired while a flush was running
+    let mut open_quarantines: Vec<ClockAdjustment> = Vec::new(); // rows inside them may still arrive
+    let mut paused = false;
+    let (mut nodes_open, mut catchup_open, mut gh_open, mut stopping) = (true, true, true, false);
+    let mut drain_until = tokio::time::Instant::now(); // set on shutdown
 
     loop {
+        let full = batch_nodes.len() + batch_gh.len() >= BATCH_SIZE;
+        let accepting = !(full && in_flight.is_some());
+        if !accepting && !paused {
+            if let Ok(mut s) = stats.write() { s.backpressure_pauses += 1; }
+        }
+        paused = !accepting;
+
         tokio::select! {
-            Some(na) = rx_nodeavg.recv() => {
-                batch_nodes.push(na);
-                if batch_nodes.len() + batch_gh.len() >= BATCH_SIZE {
-                    let bn = std::mem::take(&mut batch_nodes);
-                    let bg = std::mem::take(&mut batch_gh);
+            na = rx_nodeavg.recv(), if accepting && nodes_open => match na {
+                Some(na) => batch_nodes.push(na),
+                None => nodes_open = false,
+            },
+            na = rx_catchup.recv(), if accepting && catchup_open => match na {
+                Some(na) => batch_nodes.push(na),
+                None => catchup_open = false,
+            },
+            ga = rx_ghavg.recv(), if accepting && gh_open => match ga {
+                Some(ga) => batch_gh.push(ga),
+                None => gh_open = false,
+            },
+            Some(h) = rx_hourly.recv() => batch_hourly.push(h),
+            Some(adj) = rx_clock.recv() => {
+                if dry_run.is_some() {
+                    info!(target: "DB", "DRY RUN: would record clock adjustment {:+}ms", adj.delta_ms);
+                } else {
                     let path = abs.clone();
-                    let _ = tokio::task::spawn_blocking(move || flush_batch(path.to_str().unwrap(), bn, bg)).await;
+                    match tokio::task::spawn_blocking(move || insert_clock_adjustment(path.to_str().unwrap(), &adj)).await {
+                        Ok(Ok(n)) => {
+                            info!(target: "DB", "clock adjustment recorded, {n} row(s) quarantined");
+                            open_quarantines.push(adj);
+                        }
+                        Ok(Err(e)) => warn!(target: "DB", "clock adjustment insert failed: {e}"),
+                        Err(e) => error!(target: "DB", "clock adjustment join error: {e}"),
+                    }
                 }
             }
-            Some(ga) = rx_ghavg.recv() => {
-                batch_gh.push(ga);
-                if batch_nodes.len() + batch_gh.len() >= BATCH_SIZE {
-                    let bn = std::mem::take(&mut batch_nodes);
-                    let bg = std::mem::take(&mut batch_gh);
-                    let path = abs.clone();
-                    let _ = tokio::task::spawn_blocking(move || flush_batch(path.to_str().unwrap(), bn, bg)).await;
+            started = flush_done(&mut in_flight) => {
+                in_flight = None;
+                let ms = started.elapsed().as_millis() as u64;
+                if let Ok(mut s) = stats.write() {
+                    s.flushes += 1;
+                    s.flush_in_flight_ms += ms;
+                    s.last_flush_ms = ms;
+                    s.max_flush_ms = s.max_flush_ms.max(ms);
                 }
             }
             _ = tick.tick() => {
-                if !(batch_nodes.is_empty() && batch_gh.is_empty()) {
-                    let bn = std::mem::take(&mut batch_nodes);
-                    let bg = std::mem::take(&mut batch_gh);
+                flush_due = true;
+                // a quarantine span is over once its rows are flushed: flag the ones written since
+                let now = now_ms();
+                let (over, open): (Vec<_>, Vec<_>) = open_quarantines.drain(..)
+                    .partition(|a| a.to_ms + 2 * FLUSH_EVERY.as_millis() as i64 <= now);
+                open_quarantines = open;
+                for adj in over {
                     let path = abs.clone();
-                    let _ = tokio::task::spawn_blocking(move || flush_batch(path.to_str().unwrap(), bn, bg)).await;
+                    match tokio::task::spawn_blocking(move || requarantine(path.to_str().unwrap(), &adj)).await {
+                        Ok(Ok(n)) => info!(target: "DB", "{n} more row(s) quarantined after the clock adjustment"),
+                        Ok(Err(e)) => warn!(target: "DB", "quarantine update failed: {e}"),
+                        Err(e) => error!(target: "DB", "quarantine join error: {e}"),
+                    }
                 }
             }
+            _ = shutdown.cancelled(), if !stopping => {
+                stopping = true;
+                drain_until = tokio::time::Instant::now() + STORAGE_DRAIN;
+            }
+            _ = sleep_until(drain_until), if stopping => {
+                warn!(target: "DB", "inputs still open {}s after shutdown; writing what has arrived", STORAGE_DRAIN.as_secs());
+                break;
+            }
             else => break,
         }
+        if stopping && !nodes_open && !catchup_open && !gh_open { break; }
+
+        let pending = !(batch_nodes.is_empty() && batch_gh.is_empty() && batch_hourly.is_empty());
+        let full = batch_nodes.len() + batch_gh.len() >= BATCH_SIZE;
+        if in_flight.is_none() && pending && (flush_due || full) {
+            flush_due = false;
+            in_flight = start_flush(&abs, &mut batch_nodes, &mut batch_gh, &mut batch_hourly, &compactor, &dry_run)
+                .map(|h| (h, Instant::now()));
+        }
+    }
+
+    if let Some((handle, _)) = in_flight { let _ = handle.await; }
+    // shutdown: the batch still filling, and anything queued behind it, is written before returning
+    while let Ok(na) = rx_nodeavg.try_recv() { batch_nodes.push(na); }
+    while let Ok(na) = rx_catchup.try_recv() { batch_nodes.push(na); }
+    while let Ok(ga) = rx_ghavg.try_recv() { batch_gh.push(ga); }
+    while let Ok(h) = rx_hourly.try_recv() { batch_hourly.push(h); }
+    if !(batch_nodes.is_empty() && batch_gh.is_empty() && batch_hourly.is_empty()) {
+        let rows = batch_nodes.len() + batch_gh.len() + batch_hourly.len();
+        if let Some(handle) = start_flush(&abs, &mut batch_nodes, &mut batch_gh, &mut batch_hourly, &compactor, &dry_run) {
+            if let Err(e) = handle.await { error!(target: "DB", "final flush join error: {e}"); }
+        }
+        info!(target: "DB", "final flush of {rows} pending averages on shutdown");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mqtt::greenhouse_sensor::aggregator::{SoilAvg, Spread};
+    use crate::services::storage::history::{load_node_history, HistoryQuery};
+
+    fn gh_rows(db: &str, quarantined: bool) -> i64 {
+        open_db(db).unwrap().query_row(
+            "SELECT COUNT(DISTINCT ts_ms) FROM greenhouse_average WHERE quarantined = ?1", params![quarantined as i64], |r| r.get(0),
+        ).unwrap()
+    }
+
+    #[test]
+    fn clock_adjustment_flags_rows_on_both_sides_of_the_jump() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let db = db.to_str().unwrap();
+        let t = 1_760_000_040_000;
+        let adj = ClockAdjustment {
+            detected_ms: t + 3_600_000, delta_ms: 3_600_000 - 5_000,
+            from_ms: t + 3_600_000 - 5_000, to_ms: t + 3_600_000 + 62_500,
+            before_from_ms: t - 1_000, before_to_ms: t + 4_000,
+        };
+        // before the last good check, just before the jump (old clock), and after it (new clock)
+        let batch = vec![GhAvg::sample(1, t - 60_000), GhAvg::sample(1, t), GhAvg::sample(1, t + 3_600_000)];
+        flush_batch(db, Vec::new(), batch, Vec::new(), None, Provenance::Live);
+        assert!(insert_clock_adjustment(db, &adj).unwrap() > 0);
+        assert_eq!((gh_rows(db, true), gh_rows(db, false)), (2, 1));
+
+        // a window closing inside the new-clock span after the jump was recorded
+        flush_batch(db, Vec::new(), vec![GhAvg::sample(1, t + 3_660_000)], Vec::new(), None, Provenance::Live);
+        assert_eq!(gh_rows(db, true), 2);
+        assert!(requarantine(db, &adj).unwrap() > 0);
+        assert_eq!((gh_rows(db, true), gh_rows(db, false)), (3, 1));
+
+        let loaded = load_clock_adjustments(db, t).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!((loaded[0].before_from_ms, loaded[0].before_to_ms), (adj.before_from_ms, adj.before_to_ms));
+    }
+
+    #[test]
+    fn greenhouse_rows_store_the_coverage_and_confidence_of_their_field() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let db = db.to_str().unwrap();
+        let t = 1_760_000_040_000;
+        let mut field_nodes = [4; 16];
+        (field_nodes[0], field_nodes[1]) = (2, 1); // air from 2 of 4 nodes, leaf from 1
+        let ga = GhAvg { nodes: 2, roster: 4, coverage: 0.5, confidence: Confidence::Medium, field_nodes, ..GhAvg::sample(1, t) };
+        flush_batch(db, Vec::new(), vec![ga], Vec::new(), None, Provenance::Live);
+
+        let stored = |key: &str| -> (i64, f64, String) {
+            open_db(db).unwrap().query_row(
+                "SELECT ga.nodes, ga.coverage, ga.confidence FROM greenhouse_average ga JOIN sensor_type st ON st.id = ga.sensor_type_id
+                 WHERE st.key = ?1 AND ga.agg = 'rolling_60s'", params![key], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            ).unwrap()
+        };
+        assert_eq!(stored("air_temp_c"), (2, 0.5, "medium".into()));
+        assert_eq!(stored("leaf_temp_c"), (2, 0.25, "low".into()));
+        assert_eq!(stored("par_value"), (2, 1.0, "high".into()));
+    }
+
+    #[test]
+    fn back_to_back_flushes_with_one_ts_keep_both_windows() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let db = db.to_str().unwrap();
+        let t = 1_760_000_040_000;
+        // after a stall two windows close on the same tick: same ts_ms, consecutive windows
+        let (mut a, mut b) = (NodeAvg::sample(1, 2, t, 21.0), NodeAvg::sample(1, 2, t, 22.0));
+        (a.window_start_ms, a.window_seq) = (t - 120_000, 7);
+        (b.window_start_ms, b.window_seq) = (t - 60_000, 8);
+        let (mut ga, mut gb) = (GhAvg::sample(1, t), GhAvg::sample(1, t));
+        (ga.window_start_ms, ga.window_seq) = (t - 120_000, 7);
+        (gb.window_start_ms, gb.window_seq) = (t - 60_000, 8);
+        flush_batch(db, vec![a], vec![ga], Vec::new(), None, Provenance::Live);
+        flush_batch(db, vec![b], vec![gb], Vec::new(), None, Provenance::Live);
+
+        let conn = open_db(db).unwrap();
+        let temps: Vec<(i64, i64, f64)> = conn
+            .prepare("SELECT nv.ts_ms, nv.window_seq, nv.value FROM node_values nv JOIN sensor_type st ON st.id = nv.sensor_type_id
+                      WHERE st.key = 'air_temp_c' AND nv.agg = 'rolling_60s' ORDER BY nv.window_seq").unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?))).unwrap().map(Result::unwrap).collect();
+        assert_eq!(temps, [(t, 7, 21.0), (t, 8, 22.0)]);
+        let gh_windows = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT COUNT(DISTINCT window_seq) FROM greenhouse_average WHERE ts_ms = ?1", params![t], |r| r.get(0)).unwrap()
+        };
+        assert_eq!(gh_windows(&conn), 2);
+
+        // the same window flushed again (a retried batch) is the one real duplicate
+        let rows = |conn: &Connection| -> i64 { conn.query_row("SELECT COUNT(*) FROM node_values", [], |r| r.get(0)).unwrap() };
+        let before = rows(&conn);
+        flush_batch(db, vec![a], vec![ga], Vec::new(), None, Provenance::Live);
+        assert_eq!((rows(&conn), gh_windows(&conn)), (before, 2));
+    }
+
+    #[test]
+    fn rows_carry_the_window_they_were_aggregated_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let db = db.to_str().unwrap();
+        let t = 1_760_000_040_000;
+        let (mut na, mut ga) = (NodeAvg::sample(1, 2, t, 21.0), GhAvg::sample(1, t));
+        (na.window_sec, na.window_start_ms) = (10, t - 10_000);
+        (ga.window_sec, ga.window_start_ms) = (10, t - 10_000);
+        flush_batch(db, vec![na], vec![ga], Vec::new(), None, Provenance::Live);
+
+        let conn = open_db(db).unwrap();
+        let windows = |table: &str| -> Vec<(String, i64, i64)> {
+            conn.prepare(&format!(
+                "SELECT DISTINCT t.agg, t.window_sec, t.window_start_ms FROM {table} t JOIN sensor_type st ON st.id = t.sensor_type_id
+                 WHERE st.key = 'air_temp_c' AND t.agg LIKE 'rolling%'"
+            )).unwrap().query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?))).unwrap().map(Result::unwrap).collect()
+        };
+        let ten = vec![("rolling_10s".to_string(), 10, t - 10_000)];
+        assert_eq!(windows("node_values"), ten);
+        assert_eq!(windows("greenhouse_average"), ten);
+    }
+
+    #[test]
+    fn spread_is_stored_as_sd_rows_under_the_field_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let db = db.to_str().unwrap();
+        let mut na = NodeAvg::sample(1, 2, 1_760_000_040_000, 21.0);
+        na.spread = Spread { air_temp_sd: Some(0.42), air_rh_sd: None, vpd_sd: Some(0.05) };
+        flush_batch(db, vec![na], Vec::new(), Vec::new(), None, Provenance::Live);
+
+        let rows: Vec<(String, f64)> = open_db(db).unwrap()
+            .prepare("SELECT st.key, nv.value FROM node_values nv JOIN sensor_type st ON st.id = nv.sensor_type_id
+                      WHERE nv.agg = 'sd_60s' ORDER BY st.key").unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?))).unwrap().map(Result::unwrap).collect();
+        assert_eq!(rows, [("air_temp_c".to_string(), 0.42), ("vpd_kpa".to_string(), 0.05)]);
+    }
+
+    #[tokio::test]
+    async fn sample_counts_are_stored_with_each_reading() {
+        use crate::services::mqtt::greenhouse_sensor::{aggregator::last_windows, decoder::Decoded};
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let db = db.to_str().unwrap();
+        // six frames, RH missing from two of them
+        let frames = (0..6).map(|i| Decoded::Standard {
+            greenhouse_id: 1, node_id: 5,
+            air_temp_c: 22.0, leaf_temp_c: 23.0, bag_temp_c: 21.5, air_rh_pct: if i % 3 == 1 { f32::NAN } else { 68.0 },
+            bag_rh1_pct: 80.0, bag_rh2_pct: 81.0, bag_rh3_pct: 82.0, bag_rh4_pct: 83.0, bag_rh_avg_pct: 81.5,
+            par_value: 420, weight_g: 1200.0, ea_air_kpa: 2.1, ea_leaf_kpa: 2.4, es_kpa: 3.1, vpd_kpa: 1.0,
+            battery_v: Some(3.9), rssi_dbm: None, seq: None, device_ts: None,
+        }).collect();
+        let na = last_windows(frames).await[&(1, 5)];
+        flush_batch(db, vec![na], Vec::new(), Vec::new(), None, Provenance::Live);
+
+        let samples = |key: &str| -> Vec<(String, Option<i64>)> {
+            open_db(db).unwrap()
+                .prepare("SELECT nv.agg, nv.samples FROM node_values nv JOIN sensor_type st ON st.id = nv.sensor_type_id
+                          WHERE st.key = ?1 ORDER BY nv.agg").unwrap()
+                .query_map(params![key], |r| Ok((r.get(0)?, r.get(1)?))).unwrap().map(Result::unwrap).collect()
+        };
+        let rh = samples("air_rh_pct");
+        assert!(rh.iter().any(|(agg, _)| agg == "rolling_60s"), "{rh:?}");
+        // the mean and its min / max / sd rows all say four
+        assert!(rh.iter().all(|(_, n)| *n == Some(4)), "{rh:?}");
+        assert!(samples("air_temp_c").iter().all(|(_, n)| *n == Some(6)));
+        // telemetry has no count
+        assert_eq!(samples("battery_v"), [("rolling_60s".to_string(), None)]);
+    }
+
+    #[tokio::test]
+    async fn receiving_continues_while_a_slow_flush_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.db");
+        let db: &'static str = Box::leak(path.to_str().unwrap().to_string().into_boxed_str());
+        SLOW_FLUSH.lock().unwrap().insert(absolute_path(db), Duration::from_millis(1500));
+        let (tx, nodeavg) = mpsc::channel(8);
+        let (gh_tx, ghavg) = mpsc::channel::<GhAvg>(8);
+        let (_hourly_tx, hourly) = mpsc::channel(8);
+        let (_clock_tx, clock) = mpsc::channel(8);
+        let (catchup_tx, catchup) = mpsc::channel(8);
+        let inputs = crate::services::supervisor::Slot::new(StorageInputs { nodeavg, catchup, ghavg, hourly, clock });
+        let stats = StorageStatsShared::default();
+        let stop = CancellationToken::new();
+        let writer = tokio::spawn(run_storage(db, inputs.lease().unwrap(), None, stats.clone(), stop.clone()));
+
+        let t = 1_760_000_040_000;
+        let window = |i: i64| NodeAvg::sample(1, 2, t + i * 60_000, 20.0);
+        tx.send(window(0)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        // the first flush is now on the blocking pool for 1.5 s; 8x the channel capacity still goes through
+        for i in 1..=64 {
+            let sent = tokio::time::timeout(Duration::from_millis(100), tx.send(window(i))).await;
+            assert!(matches!(sent, Ok(Ok(()))), "window {i} not received during the flush");
+        }
+        assert_eq!(stats.read().unwrap().flushes, 0, "the flush ended before the windows were sent");
+        while stats.read().unwrap().flushes == 0 { tokio::time::sleep(Duration::from_millis(50)).await; }
+        let s = stats.read().unwrap().clone();
+        assert!(s.flush_in_flight_ms >= 1500 && s.max_flush_ms >= 1500, "{s:?}");
+
+        // shutdown ends the writer once both aggregators are gone
+        drop((tx, gh_tx, catchup_tx));
+        stop.cancel();
+        writer.await.unwrap();
+        let stored: i64 = open_db(db).unwrap().query_row(
+            "SELECT COUNT(*) FROM node_values nv JOIN sensor_type st ON st.id = nv.sensor_type_id WHERE st.key = 'air_temp_c'", [], |r| r.get(0),
+        ).unwrap();
+        assert_eq!(stored, 65);
+    }
+
+    #[tokio::test]
+    async fn cancellation_flushes_the_pending_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.db");
+        let db: &'static str = Box::leak(path.to_str().unwrap().to_string().into_boxed_str());
+        let (tx, nodeavg) = mpsc::channel(64);
+        let (gh_tx, ghavg) = mpsc::channel(64);
+        let (_hourly_tx, hourly) = mpsc::channel(8);
+        let (_clock_tx, clock) = mpsc::channel(8);
+        let (catchup_tx, catchup) = mpsc::channel(8);
+        let inputs = crate::services::supervisor::Slot::new(StorageInputs { nodeavg, catchup, ghavg, hourly, clock });
+        let stop = CancellationToken::new();
+        let writer = tokio::spawn(run_storage(db, inputs.lease().unwrap(), None, StorageStatsShared::default(), stop.clone()));
+
+        // the first tick is due at once, so the first window is written right away; the rest wait for the next tick
+        let t = 1_760_000_040_000;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        tx.send(NodeAvg::sample(1, 2, t, 20.0)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        for i in 1..=10 {
+            tx.send(NodeAvg::sample(1, 2, t + i * 60_000, 20.0)).await.unwrap();
+            gh_tx.send(GhAvg::sample(1, t + i * 60_000)).await.unwrap();
+        }
+
+        // well before that tick: only the shutdown flush can have written them
+        stop.cancel();
+        drop((tx, gh_tx, catchup_tx));
+        tokio::time::timeout(FLUSH_EVERY / 2, writer).await.expect("storage did not stop").unwrap();
+        let conn = open_db(db).unwrap();
+        let temps: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM node_values nv JOIN sensor_type st ON st.id = nv.sensor_type_id WHERE st.key = 'air_temp_c'", [], |r| r.get(0),
+        ).unwrap();
+        assert_eq!((temps, gh_rows(db, false)), (11, 10));
+    }
+
+    #[test]
+    fn compact_mode_stores_fewer_node_rows_and_history_fills_them_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let db = db.to_str().unwrap();
+        let t0 = 1_760_000_040_000;
+        let compactor = Some(Arc::new(Mutex::new(Compactor::default())));
+        // six hours of slowly warming air, flushed every 10 windows like the writer does
+        let windows: Vec<_> = (0..360).map(|m| NodeAvg::sample(1, 2, t0 + m * 60_000, 20.0 + m as f32 * 0.01)).collect();
+        for chunk in windows.chunks(10) {
+            flush_batch(db, chunk.to_vec(), Vec::new(), Vec::new(), compactor.clone(), Provenance::Live);
+        }
+        let stored = |key: &str| -> i64 {
+            open_db(db).unwrap().query_row(
+                "SELECT COUNT(*) FROM node_values nv JOIN sensor_type st ON st.id = nv.sensor_type_id WHERE st.key = ?1",
+                params![key], |r| r.get(0),
+            ).unwrap()
+        };
+        let temps = stored("air_temp_c");
+        assert!((60..=72).contains(&temps), "a row per 0.05 C, got {temps}");
+        assert_eq!(stored("air_rh_pct"), 24, "flat: a keyframe every 15 min");
+
+        let q = HistoryQuery { sensors: vec!["air_temp_c".into()], ..Default::default() };
+        let rows = load_node_history(db, 1, Some(2), t0, t0 + 360 * 60_000, &q).unwrap();
+        assert_eq!(rows.len(), 360);
+        assert_eq!(rows.iter().filter(|r| !r.filled).count() as i64, temps);
+        for (r, w) in rows.iter().zip(&windows) {
+            assert_eq!(r.ts_ms, w.ts_ms);
+            assert!((r.value.unwrap() - w.air_temp_c.unwrap() as f64).abs() < 0.05 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn rebuilt_row_reports_rebuild_and_its_neighbours_stay_live() {
+        use crate::services::storage::history::{load_gh_history, RevisionFilter};
+        use crate::services::storage::rebuild::{rebuild_gh_averages, RebuildOptions};
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let db = db.to_str().unwrap();
+        let t = 1_760_000_040_000;
+        // three nodes at 24 C for three windows; the middle greenhouse row was stored at 27 C
+        let ts = [t, t + 60_000, t + 120_000];
+        let nodes = ts.iter().flat_map(|&ts| (2..=4).map(move |n| NodeAvg::sample(1, n, ts, 24.0))).collect();
+        let mut gh: Vec<GhAvg> = ts.iter().map(|&ts| GhAvg::sample(1, ts)).collect();
+        gh[1].air_temp_c = Some(27.0);
+        flush_batch(db, nodes, gh, Vec::new(), None, Provenance::Live);
+
+        let report = rebuild_gh_averages(db, 1, t, t + 180_000, RebuildOptions::default(), &Default::default(), &|_| {}).unwrap();
+        assert!(report.rows_changed > 0);
+        let temps = |revisions| {
+            let q = HistoryQuery { sensors: vec!["air_temp_c".into()], aggs: vec!["rolling_60s".into()], revisions, ..Default::default() };
+            load_gh_history(db, 1, t, t + 180_000, &q).unwrap()
+        };
+        let rows = temps(RevisionFilter::Latest);
+        let got: Vec<_> = rows.iter().map(|r| (r.ts_ms, r.value, r.source.as_str(), r.revision, r.revised_ms.is_some())).collect();
+        assert_eq!(got, [
+            (ts[0], Some(24.0), "live", 0, false),
+            (ts[1], Some(24.0), "rebuild", 1, true),
+            (ts[2], Some(24.0), "live", 0, false),
+        ]);
+        let originals: Vec<i64> = temps(RevisionFilter::Original).iter().map(|r| r.ts_ms).collect();
+        assert_eq!(originals, [ts[0], ts[2]]);
+        let revised: Vec<i64> = temps(RevisionFilter::Revised).iter().map(|r| r.ts_ms).collect();
+        assert_eq!(revised, [ts[1]]);
+    }
+
+    #[test]
+    fn node_rows_carry_the_measurement_time_when_frames_had_a_clock() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let db = db.to_str().unwrap();
+        let t = 1_760_000_040_000;
+        let mut clocked = NodeAvg::sample(1, 2, t, 21.0);
+        clocked.measured_ms = Some(t - 25_000);
+        flush_batch(db, vec![NodeAvg::sample(1, 3, t, 21.0), clocked], Vec::new(), Vec::new(), None, Provenance::Live);
+        let ts: Vec<(i64, i64)> = open_db(db).unwrap()
+            .prepare("SELECT DISTINCT nn.node_id, nv.ts_ms FROM node_values nv JOIN node_name nn ON nn.id = nv.node_id ORDER BY nn.node_id").unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?))).unwrap().map(Result::unwrap).collect();
+        assert_eq!(ts, [(2, t - 25_000), (3, t)]);
+    }
+
+    #[test]
+    fn soil_windows_store_their_substrate_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let db = db.to_str().unwrap();
+        let mut soil = NodeAvg::sample(1, 9, 1_760_000_040_000, 0.0);
+        soil.air_temp_c = None;
+        soil.air_rh_pct = None;
+        soil.soil = Some(SoilAvg { vwc1_pct: Some(35.0), vwc2_pct: Some(36.0), vwc3_pct: None, vwc4_pct: Some(33.0), ec_ms_cm: Some(2.1) });
+        flush_batch(db, vec![soil], Vec::new(), Vec::new(), None, Provenance::Live);
+        let rows: Vec<(String, Option<f64>)> = open_db(db).unwrap()
+            .prepare("SELECT st.key, nv.value FROM node_values nv JOIN sensor_type st ON st.id = nv.sensor_type_id WHERE nv.agg = 'rolling_60s' ORDER BY st.key").unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?))).unwrap().map(Result::unwrap).collect();
+        let keys: Vec<&str> = rows.iter().map(|r| r.0.as_str()).collect();
+        assert_eq!(keys, ["ec_ms_cm", "vwc1_pct", "vwc2_pct", "vwc3_pct", "vwc4_pct"]);
+        assert_eq!(rows[0].1, Some(2.1));
+        assert_eq!(rows[3].1, None);
+    }
+
+    #[tokio::test]
+    async fn telemetry_frames_reach_node_values() {
+        use crate::services::mqtt::greenhouse_sensor::aggregator::last_windows;
+        use crate::services::mqtt::greenhouse_sensor::decoder::decode_payload;
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("app.db");
+        let db = db.to_str().unwrap().to_string();
+        // v2 frames of node 3 (battery 3.65 V / 3.75 V, -72 / -68 dBm), a v1 frame of node 4 and a v5 frame
+        // of node 5 (3610 mV, -80 dBm), as published
+        let frame = |node: u8, tail: Option<(f32, f32)>| {
+            let mut f = vec![0u8; 60];
+            f[0] = 1;
+            f[2] = node;
+            f[4..8].copy_from_slice(&21.0f32.to_le_bytes());
+            let Some((batt, rssi)) = tail else { return f };
+            let mut v2 = vec![0x02];
+            v2.extend(f);
+            v2.extend(batt.to_le_bytes());
+            v2.extend(rssi.to_le_bytes());
+            v2
+        };
+        let mut v5 = vec![0x05];
+        v5.extend(frame(5, None));
+        v5.extend(3610u16.to_le_bytes());
+        v5.push(-80i8 as u8);
+        let frames = [frame(3, Some((3.65, -72.0))), frame(3, Some((3.75, -68.0))), frame(4, None), v5];
+        let decoded = frames.iter().map(|f| decode_payload(f).unwrap()).collect();
+        let windows: Vec<NodeAvg> = last_windows(decoded).await.into_values().collect();
+        let path = db.clone();
+        tokio::task::spawn_blocking(move || flush_batch(&path, windows, Vec::new(), Vec::new(), None, Provenance::Live)).await.unwrap();
+        let rows: Vec<(i64, String, f64)> = open_db(&db).unwrap()
+            .prepare("SELECT nn.node_id, st.key, nv.value FROM node_values nv JOIN node_name nn ON nn.id = nv.node_id
+                      JOIN sensor_type st ON st.id = nv.sensor_type_id WHERE st.key IN ('battery_v', 'rssi_dbm') ORDER BY st.key, nn.node_id").unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?))).unwrap().map(Result::unwrap).collect();
+        assert_eq!(rows, [
+            (3, "battery_v".to_string(), 3.7), (5, "battery_v".to_string(), 3.61),
+            (3, "rssi_dbm".to_string(), -70.0), (5, "rssi_dbm".to_string(), -80.0),
+        ]);
     }
 }
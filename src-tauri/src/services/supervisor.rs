@@ -0,0 +1,253 @@
+//! Restart of pipeline stages that die while the app keeps running.
+//! - main.rs starts the hourly stage, storage and the UI emitter, and pipeline.rs the subscriber and
+//!   both aggregators, through `supervise`. A run that panics, or returns before shutdown, is logged
+//!   with its cause and reported as a `task_failed` event, then started again.
+//! - Restarts wait `RESTART_DELAY`, doubling per failure in a row up to `MAX_RESTART_DELAY`; a run that
+//!   lasted `HEALTHY_AFTER` starts the count over. A stage failing at startup thus retries once a
+//!   minute instead of spinning.
+//! - Channel halves live in a `Slot` that outlives each run. A run `lease`s them and the lease puts
+//!   them back when the run ends, unwinding included, so the next run talks to the same neighbours.
+//!   Senders that clone (lanes, mpsc) are simply cloned per run.
+//! - Once `shutdown` is cancelled a run ending is the normal stop and nothing restarts; dropping the
+//!   slot then closes the channel, as before.
+
+use serde::Serialize;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+use crate::services::presenter::emitter::EventSink;
+
+pub const RESTART_DELAY: Duration = Duration::from_secs(1);
+pub const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+pub const HEALTHY_AFTER: Duration = Duration::from_secs(60);
+
+#[inline] fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+/// Payload of `task_failed`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskFailed {
+    pub task: &'static str,
+    /// Panic message, or "returned" for a run that ended on its own.
+    pub reason: String,
+    /// Failures in a row, this one included.
+    pub restarts: u32,
+    pub restart_in_ms: u64,
+    pub ts_ms: i64,
+}
+
+/// A channel half (or bundle of them) kept across runs of a stage.
+pub struct Slot<T>(Arc<Mutex<Option<T>>>);
+
+impl<T> Clone for Slot<T> {
+    fn clone(&self) -> Self { Slot(self.0.clone()) }
+}
+
+impl<T> Slot<T> {
+    pub fn new(value: T) -> Self {
+        Slot(Arc::new(Mutex::new(Some(value))))
+    }
+
+    /// The value for the next run; None while a run still holds it.
+    pub fn lease(&self) -> Option<Lease<T>> {
+        let value = self.0.lock().unwrap_or_else(PoisonError::into_inner).take()?;
+        Some(Lease { value: Some(value), slot: self.0.clone() })
+    }
+}
+
+/// A run's hold on a `Slot`; derefs to the value and returns it on drop.
+pub struct Lease<T> {
+    value: Option<T>,
+    slot: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> Deref for Lease<T> {
+    type Target = T;
+    fn deref(&self) -> &T { self.value.as_ref().expect("lease holds its value until dropped") }
+}
+
+impl<T> DerefMut for Lease<T> {
+    fn deref_mut(&mut self) -> &mut T { self.value.as_mut().expect("lease holds its value until dropped") }
+}
+
+impl<T> Drop for Lease<T> {
+    fn drop(&mut self) {
+        if let Some(v) = self.value.take() {
+            *self.slot.lock().unwrap_or_else(PoisonError::into_inner) = Some(v);
+        }
+    }
+}
+
+/// Wait before restart number `restarts` (1 = first).
+fn restart_delay(restarts: u32) -> Duration {
+    RESTART_DELAY.saturating_mul(1 << restarts.saturating_sub(1).min(16)).min(MAX_RESTART_DELAY)
+}
+
+/// Runs `start()` until `shutdown`, starting it again whenever a run panics or returns early.
+pub async fn supervise<S, F, Fut>(name: &'static str, sink: S, shutdown: CancellationToken, mut start: F)
+where
+    S: EventSink,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut restarts: u32 = 0;
+    loop {
+        let started = Instant::now();
+        let reason = match tokio::spawn(start()).await {
+            Ok(()) => "returned".to_string(),
+            Err(e) if e.is_panic() => {
+                let panic = e.into_panic();
+                panic.downcast_ref::<&str>().map(|s| s.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "panicked".to_string())
+            }
+            Err(e) => e.to_string(),
+        };
+        if shutdown.is_cancelled() { return; }
+
+        if started.elapsed() >= HEALTHY_AFTER { restarts = 0; }
+        restarts += 1;
+        let delay = restart_delay(restarts);
+        error!(target: "PIPE", "task '{name}' stopped ({reason}); restart #{restarts} in {}ms", delay.as_millis());
+        let ev = TaskFailed { task: name, reason, restarts, restart_in_ms: delay.as_millis() as u64, ts_ms: now_ms() };
+        if let Ok(v) = serde_json::to_value(&ev) { sink.emit_json("task_failed", v); }
+
+        tokio::select! {
+            _ = sleep(delay) => info!(target: "PIPE", "restarting task '{name}'"),
+            _ = shutdown.cancelled() => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tokio::sync::mpsc;
+    use crate::services::mqtt::greenhouse_sensor::{aggregator::NodeAvg, greenhouse_aggregator::GhAvg};
+    use crate::services::presenter::emitter::RecordingSink;
+    use crate::services::shutdown::STORAGE_DRAIN;
+    use crate::services::storage::sqlite::{open_db, run_storage, StorageInputs, StorageStatsShared};
+
+    fn failures(sink: &RecordingSink) -> Vec<(String, u64, u64)> {
+        sink.0.lock().unwrap().iter()
+            .filter(|(e, _)| e == "task_failed")
+            .map(|(_, v)| (v["reason"].as_str().unwrap().to_string(), v["restarts"].as_u64().unwrap(), v["restart_in_ms"].as_u64().unwrap()))
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_stage_returning_early_is_restarted_with_growing_delays() {
+        let (sink, stop) = (RecordingSink::default(), CancellationToken::new());
+        let runs = Arc::new(AtomicU32::new(0));
+        let task = tokio::spawn(supervise("stage", sink.clone(), stop.clone(), {
+            let runs = runs.clone();
+            move || {
+                runs.fetch_add(1, Ordering::SeqCst);
+                async {}
+            }
+        }));
+        // runs at 0 s, then after 1 + 2 + 4 s
+        sleep(Duration::from_millis(7_500)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+        let returned = |n, ms| ("returned".to_string(), n, ms);
+        assert_eq!(failures(&sink)[..3], [returned(1, 1_000), returned(2, 2_000), returned(3, 4_000)]);
+
+        // shutdown during the wait: no further run
+        stop.cancel();
+        task.await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_panic_is_reported_with_its_message() {
+        let (sink, stop) = (RecordingSink::default(), CancellationToken::new());
+        let runs = Arc::new(AtomicU32::new(0));
+        let task = tokio::spawn(supervise("stage", sink.clone(), stop.clone(), {
+            let (runs, stop) = (runs.clone(), stop.clone());
+            move || {
+                let (n, stop) = (runs.fetch_add(1, Ordering::SeqCst), stop.clone());
+                async move {
+                    if n == 0 { panic!("decoder bug"); }
+                    stop.cancelled().await;
+                }
+            }
+        }));
+        sleep(Duration::from_secs(2)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2, "restarted once");
+        stop.cancel();
+        task.await.unwrap();
+        // the second run ended on shutdown, which is not a failure
+        assert_eq!(failures(&sink), [("decoder bug".to_string(), 1, 1_000)]);
+    }
+
+    #[test]
+    fn restart_delay_doubles_up_to_its_cap() {
+        let secs: Vec<u64> = (1..=9).map(|n| restart_delay(n).as_secs()).collect();
+        assert_eq!(secs, [1, 2, 4, 8, 16, 32, 60, 60, 60]);
+        assert_eq!(restart_delay(u32::MAX), MAX_RESTART_DELAY);
+    }
+
+    #[test]
+    fn a_lease_returns_its_value_to_the_slot_even_on_panic() {
+        let slot = Slot::new(vec![1]);
+        let mut lease = slot.lease().unwrap();
+        assert!(slot.lease().is_none(), "held by the running stage");
+        lease.push(2);
+        drop(lease);
+
+        let held = slot.clone();
+        let unwound = std::panic::catch_unwind(move || {
+            let _lease = held.lease().unwrap();
+            panic!("stage died");
+        });
+        assert!(unwound.is_err());
+        assert_eq!(*slot.lease().unwrap(), [1, 2], "the next run gets the same value");
+    }
+
+    #[tokio::test]
+    async fn storage_drains_and_stops_while_a_lease_still_holds_its_senders() {
+        let dir = tempfile::tempdir().unwrap();
+        let db: &'static str = Box::leak(dir.path().join("app.db").to_str().unwrap().to_string().into_boxed_str());
+        let (tx_nodes, nodeavg) = mpsc::channel(64);
+        let (tx_gh, ghavg) = mpsc::channel(64);
+        let (tx_hourly, hourly) = mpsc::channel(8);
+        let (tx_clock, clock) = mpsc::channel(8);
+        let (tx_catchup, catchup) = mpsc::channel(8);
+        // an aggregator run that never lets go of its outputs
+        let senders = Slot::new((tx_nodes, tx_gh, tx_hourly, tx_clock, tx_catchup));
+        let held = senders.lease().unwrap();
+
+        let (sink, stop) = (RecordingSink::default(), CancellationToken::new());
+        let inputs = Slot::new(StorageInputs { nodeavg, catchup, ghavg, hourly, clock });
+        let storage = tokio::spawn(supervise("storage", sink.clone(), stop.clone(), {
+            let stop = stop.clone();
+            move || run_storage(db, inputs.lease().expect("storage inputs"), None, StorageStatsShared::default(), stop.clone())
+        }));
+
+        let t = 1_760_000_040_000;
+        for i in 0..5 {
+            held.0.send(NodeAvg::sample(1, 2, t + i * 60_000, 20.0)).await.unwrap();
+            held.1.send(GhAvg::sample(1, t + i * 60_000)).await.unwrap();
+        }
+        sleep(Duration::from_millis(200)).await;
+        stop.cancel();
+        // queued after the cancel, before storage gives up waiting for its inputs to close
+        held.0.send(NodeAvg::sample(1, 2, t + 5 * 60_000, 20.0)).await.unwrap();
+
+        tokio::time::timeout(STORAGE_DRAIN + Duration::from_secs(1), storage).await.expect("storage kept waiting").unwrap();
+        assert!(senders.lease().is_none(), "the senders are still leased");
+        assert!(failures(&sink).is_empty(), "a stop on shutdown is not restarted");
+        let conn = open_db(db).unwrap();
+        let count = |sql: &str| -> i64 { conn.query_row(sql, [], |r| r.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(DISTINCT ts_ms) FROM node_values"), 6);
+        assert_eq!(count("SELECT COUNT(DISTINCT ts_ms) FROM greenhouse_average"), 5);
+        drop(held);
+    }
+}